@@ -0,0 +1,111 @@
+//! Benchmarks for mesh generation, terrain noise and scene graph updates.
+//! Run with `cargo bench`, results are written to target/criterion.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nalgebra_glm as glm;
+
+use procedural_planets::mesh::Mesh;
+use procedural_planets::procedural_planet::Planet;
+use procedural_planets::scene_graph::{Node, SceneNode, SceneNodeType};
+
+fn cubesphere_side(subdivisions: usize) -> Mesh {
+    Mesh::cs_plane(
+        glm::vec3(1.0, 1.0, 1.0),
+        glm::vec3(0.0, 0.0, 0.0),
+        glm::vec3(0.0, 1.0, 0.0),
+        subdivisions,
+        None,
+        true,
+    )
+}
+
+fn cubesphere(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cubesphere");
+    for subdivisions in [16, 32, 64, 128, 256] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subdivisions),
+            &subdivisions,
+            |b, &subdivisions| b.iter(|| cubesphere_side(black_box(subdivisions))),
+        );
+    }
+    group.finish();
+}
+
+fn noise(c: &mut Criterion) {
+    let planet = Planet::with_seed(1234);
+    let points: Vec<glm::Vec3> = (0..1024)
+        .map(|i| {
+            let a = i as f32 * 0.1;
+            glm::normalize(&glm::vec3(a.sin(), (a * 0.7).cos(), a.cos()))
+        })
+        .collect();
+    c.bench_function("noise 1024 points", |b| {
+        b.iter(|| {
+            points
+                .iter()
+                .map(|p| planet.get_height(black_box(p)))
+                .sum::<f32>()
+        })
+    });
+}
+
+fn merge(c: &mut Criterion) {
+    let sides: Vec<Mesh> = (0..6).map(|_| cubesphere_side(64)).collect();
+    c.bench_function("merge 6 sides", |b| {
+        b.iter(|| {
+            let mut mesh = Mesh::default();
+            for side in &sides {
+                mesh.merge(black_box(side));
+            }
+            mesh
+        })
+    });
+}
+
+fn optimize(c: &mut Criterion) {
+    c.bench_function("optimize side 64", |b| {
+        b.iter_batched(
+            || cubesphere_side(64),
+            |mut mesh| mesh.optimize(),
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+/// Complete quadtree like the planet terrain, `nodes` keeps them alive
+fn quadtree(parent: &mut SceneNode, depth: usize, nodes: &mut Vec<Node>) {
+    if depth == 0 {
+        return;
+    }
+    for i in 0..4 {
+        let mut child = SceneNode::with_type(SceneNodeType::Planet);
+        child.set_position(glm::vec3(i as f32, 0.0, 0.0));
+        child.set_scale(glm::vec3(0.5, 0.5, 0.5));
+        quadtree(&mut child, depth - 1, nodes);
+        parent.add_child(&child);
+        nodes.push(child);
+    }
+}
+
+fn transforms(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_node_transformations");
+    for depth in [4, 6, 8] {
+        let mut nodes = Vec::new();
+        let mut root = SceneNode::new();
+        quadtree(&mut root, depth, &mut nodes);
+        let player_position = glm::vec3(10.0, 0.0, 0.0);
+        group.bench_with_input(BenchmarkId::new("nodes", nodes.len()), &depth, |b, _| {
+            b.iter(|| unsafe {
+                root.update_node_transformations(&glm::identity(), &player_position)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("nodes_f64", nodes.len()), &depth, |b, _| {
+            b.iter(|| unsafe {
+                root.update_node_transformations_f64(&glm::identity(), &player_position)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cubesphere, noise, merge, optimize, transforms);
+criterion_main!(benches);
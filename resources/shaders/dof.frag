@@ -0,0 +1,50 @@
+#version 460 core
+
+#define SAMPLES 48
+
+out vec4 color;
+
+uniform sampler2D u_scene;      // Copy of the frame
+uniform sampler2D u_distance;   // Distance of each pixel along the view axis
+uniform vec4 u_viewport;        // x, y, width and height of the view, in pixels
+uniform float u_focus;          // Distance in focus
+uniform float u_aperture;       // Blur radius, in pixels, of what is infinitely far
+uniform float u_max_radius;     // Of the blur, in pixels
+
+const float GOLDEN_ANGLE = 2.39996323;
+
+// Radius of the circle of confusion at distance d, thin lens
+float coc(float d)
+{
+    return min(u_aperture * abs(d - u_focus) / max(d, 1e-6), u_max_radius);
+}
+
+void main()
+{
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec4 center = texelFetch(u_scene, pixel, 0);
+    float radius = coc(texelFetch(u_distance, pixel, 0).r);
+    if (radius < 0.5) {
+        color = center;
+        return;
+    }
+
+    // Gather over a disc, points spread evenly by the golden angle. Samples
+    // count as much as their own blur reaches this pixel, so sharp things
+    // behind don't smear over what is in front of them.
+    vec2 low = u_viewport.xy + 0.5;
+    vec2 high = u_viewport.xy + u_viewport.zw - 0.5;
+    vec2 size = vec2(textureSize(u_scene, 0));
+    vec3 sum = center.rgb;
+    float weight = 1.0;
+    for (int i = 0; i < SAMPLES; i++) {
+        float r = sqrt((float(i) + 0.5) / float(SAMPLES)) * radius;
+        float a = float(i) * GOLDEN_ANGLE;
+        vec2 p = clamp(gl_FragCoord.xy + r * vec2(cos(a), sin(a)), low, high);
+        float reach = coc(texture(u_distance, p / size).r);
+        float w = smoothstep(r - 1.0, r + 1.0, reach);
+        sum += texture(u_scene, p / size).rgb * w;
+        weight += w;
+    }
+    color = vec4(sum / weight, 1.0);
+}
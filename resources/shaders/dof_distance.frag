@@ -0,0 +1,24 @@
+#version 460 core
+
+out float distance;
+
+uniform sampler2D u_depth;      // Copy of the depth buffer after a pass
+uniform vec2 u_projection;      // Depth terms of the projection of the pass, [2][2] and [3][2]
+uniform float u_log_depth;      // Logarithmic depth when above 0, see scene.vert
+
+void main()
+{
+    float depth = texelFetch(u_depth, ivec2(gl_FragCoord.xy), 0).r;
+    // Nothing drawn in this pass, keep what passes further out left
+    if (depth >= 1.0) {
+        discard;
+    }
+    if (u_log_depth > 0.0) {
+        distance = exp2(depth * 2.0 / u_log_depth);
+    }
+    else {
+        // Distance along the view axis, from clip z = -p22 w + p32
+        float ndc = depth * 2.0 - 1.0;
+        distance = u_projection.y / (ndc + u_projection.x);
+    }
+}
@@ -0,0 +1,40 @@
+#version 460 core
+
+#define SHAPE_GLOW      0u
+#define SHAPE_DISC      1u
+#define SHAPE_RING      2u
+#define SHAPE_STREAKS   3u
+
+in vec2 v_corner;
+
+out vec4 color;
+
+uniform uint u_shape;
+uniform vec4 u_color;
+
+void main()
+{
+    float r = length(v_corner);
+    float a;
+    switch (u_shape) {
+    case SHAPE_GLOW:
+        a = exp(-r * r * 6.0);
+        break;
+    case SHAPE_DISC:
+        a = 1.0 - smoothstep(0.7, 1.0, r);
+        break;
+    case SHAPE_RING:
+        a = 1.0 - smoothstep(0.0, 0.12, abs(r - 0.85));
+        break;
+    case SHAPE_STREAKS:
+        // Thin spikes along the axes and diagonals, fading outwards
+        float angle = atan(v_corner.y, v_corner.x);
+        float spikes = pow(abs(cos(angle * 4.0)), 60.0) + 0.4 * pow(abs(cos(angle * 4.0 + 0.79)), 60.0);
+        a = spikes * (1.0 - smoothstep(0.0, 1.0, r));
+        break;
+    default:
+        a = 0.0;
+        break;
+    }
+    color = vec4(u_color.rgb, u_color.a * a);
+}
@@ -0,0 +1,30 @@
+#version 460 core
+
+layout (location = 0) in vec2 corner;   // Of the quad, -1 to 1
+
+out vec2 v_corner;
+
+uniform bool u_probe;       // Occlusion probe at u_position, else a sprite at u_center
+uniform vec2 u_size;        // Half the width and height, in normalized device coordinates
+// Sprites
+uniform vec2 u_center;      // Normalized device coordinates
+// Probes
+uniform vec3 u_position;    // Relative to the origin
+uniform mat4 u_view;
+uniform mat4 u_perspective;
+uniform float u_log_depth;  // 2 / log2(far + 1) for logarithmic depth, 0 for the usual depth
+
+void main()
+{
+    v_corner = corner;
+    if (u_probe) {
+        // Same size on screen at any distance
+        gl_Position = u_perspective * u_view * vec4(u_position, 1.0);
+        gl_Position.xy += corner * u_size * gl_Position.w;
+        if (u_log_depth > 0.0) {
+            gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w)) * u_log_depth - 1.0) * gl_Position.w;
+        }
+    } else {
+        gl_Position = vec4(u_center + corner * u_size, 0.0, 1.0);
+    }
+}
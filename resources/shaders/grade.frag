@@ -0,0 +1,17 @@
+#version 460 core
+
+in vec2 v_uv;
+
+out vec4 color;
+
+uniform sampler2D u_scene;  // Copy of the frame, in display colours
+uniform sampler3D u_lut;    // Graded colour by display colour
+uniform float u_lut_size;   // Texels along each side of the LUT
+
+void main()
+{
+    vec3 c = clamp(texture(u_scene, v_uv).rgb, 0.0, 1.0);
+    // Between the centers of the first and last texels, so the ends map exactly
+    vec3 uvw = c * (u_lut_size - 1.0) / u_lut_size + 0.5 / u_lut_size;
+    color = vec4(texture(u_lut, uvw).rgb, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 460 core
+
+in vec2 v_uv;
+
+uniform sampler2D u_texture;    // Bound to unit 0, the default
+uniform vec4 u_tint;            // Multiplies the colour, see animation.rs
+
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 velocity;   // Still on screen, see motion_blur.rs
+
+void main()
+{
+    color = texture(u_texture, v_uv) * u_tint;
+    velocity = vec4(0.0, 0.0, 0.0, 1.0);
+}
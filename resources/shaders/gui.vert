@@ -0,0 +1,17 @@
+#version 460 core
+
+// GUI elements and text, drawn flat over the frame, see text.rs. Takes the
+// vertices and uniforms of scene.vert it needs.
+
+layout (location = 0) in vec3 position;
+layout (location = 3) in vec2 uv;
+
+out vec2 v_uv;
+
+uniform mat4 u_mvp;         // On screen, without perspective
+
+void main()
+{
+    v_uv = uv;
+    gl_Position = u_mvp * vec4(position, 1.0);
+}
@@ -0,0 +1,116 @@
+#version 460 core
+
+in vec2 v_uv;
+
+out vec4 color;
+
+uniform samplerCube u_sky;      // Captured sky, with mipmaps
+uniform int u_face;             // Cubemap face drawn, in the order of GL_TEXTURE_CUBE_MAP_POSITIVE_X
+uniform bool u_irradiance;      // Cosine weighted over the hemisphere, instead of a specular lobe
+uniform float u_roughness;      // Of the specular lobe
+uniform uint u_samples;         // Of the specular lobe
+uniform float u_sky_size;       // Texels across a face of the sky at level 0
+
+const float PI = 3.14159265359;
+
+// Direction through a texel of a cubemap face, as sampled by texture()
+vec3 face_direction(int face, vec2 uv)
+{
+    vec2 st = uv * 2.0 - 1.0;
+    switch (face) {
+    case 0: return vec3(1.0, -st.y, -st.x);
+    case 1: return vec3(-1.0, -st.y, st.x);
+    case 2: return vec3(st.x, 1.0, st.y);
+    case 3: return vec3(st.x, -1.0, -st.y);
+    case 4: return vec3(st.x, -st.y, 1.0);
+    default: return vec3(-st.x, -st.y, -1.0);
+    }
+}
+
+// Basis with z along n
+mat3 tangent_frame(vec3 n)
+{
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+    vec3 t = normalize(cross(up, n));
+    return mat3(t, cross(n, t), n);
+}
+
+// Van der Corput sequence in base 2, without bit operations on floats
+float radical_inverse(uint i)
+{
+    float result = 0.0;
+    float f = 0.5;
+    while (i > 0u) {
+        result += f * float(i & 1u);
+        i >>= 1u;
+        f *= 0.5;
+    }
+    return result;
+}
+
+float ggx(float n_dot_h, float roughness)
+{
+    float a2 = roughness * roughness * roughness * roughness;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+vec3 irradiance(vec3 n)
+{
+    // A coarse level of the sky is enough, and keeps stars from aliasing
+    mat3 frame = tangent_frame(n);
+    vec3 sum = vec3(0.0);
+    float count = 0.0;
+    const float STEP = 0.05;
+    for (float phi = 0.0; phi < 2.0 * PI; phi += STEP) {
+        for (float theta = 0.0; theta < 0.5 * PI; theta += STEP) {
+            vec3 d = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            sum += textureLod(u_sky, frame * d, 3.0).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    return PI * sum / count;
+}
+
+// Split-sum prefilter, taking the view along the normal. Samples come from
+// coarser levels the less likely their direction, to hide the noise.
+vec3 prefiltered(vec3 n)
+{
+    mat3 frame = tangent_frame(n);
+    float a = u_roughness * u_roughness;
+    float texel_angle = 4.0 * PI / (6.0 * u_sky_size * u_sky_size);
+    vec3 sum = vec3(0.0);
+    float weight = 0.0;
+    for (uint i = 0u; i < u_samples; i++) {
+        vec2 xi = vec2(float(i) / float(u_samples), radical_inverse(i));
+        float phi = 2.0 * PI * xi.x;
+        float cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+        float sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        vec3 h = frame * vec3(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+        vec3 l = reflect(-n, h);
+        float n_dot_l = dot(n, l);
+        if (n_dot_l > 0.0) {
+            // pdf of l, with the view along n: D * (n.h) / (4 (h.v)) = D / 4
+            float pdf = ggx(cos_theta, u_roughness) / 4.0 + 1e-4;
+            float sample_angle = 1.0 / (float(u_samples) * pdf);
+            float level = 0.5 * log2(sample_angle / texel_angle) + 1.0;
+            sum += textureLod(u_sky, l, max(level, 0.0)).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return sum / max(weight, 1e-4);
+}
+
+void main()
+{
+    vec3 n = normalize(face_direction(u_face, v_uv));
+    if (u_irradiance) {
+        color = vec4(irradiance(n), 1.0);
+    }
+    else if (u_roughness == 0.0) {
+        color = vec4(textureLod(u_sky, n, 0.0).rgb, 1.0);
+    }
+    else {
+        color = vec4(prefiltered(n), 1.0);
+    }
+}
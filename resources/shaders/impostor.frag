@@ -0,0 +1,16 @@
+#version 460 core
+
+in vec2 v_uv;
+
+out vec4 color;
+
+uniform sampler2D u_texture;    // The planet rendered from about here, see impostor.rs
+
+void main()
+{
+    color = texture(u_texture, v_uv);
+    // Space around the planet
+    if (color.a < 0.01) {
+        discard;
+    }
+}
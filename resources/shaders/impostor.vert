@@ -0,0 +1,22 @@
+#version 460 core
+
+out vec2 v_uv;
+
+uniform vec3 u_center;      // Of the planet, relative to the origin
+uniform vec3 u_right;       // Half the width of the quad, facing the eye
+uniform vec3 u_up;          // Half the height
+uniform mat4 u_view;
+uniform mat4 u_perspective;
+uniform float u_log_depth;  // 2 / log2(far + 1) for logarithmic depth, 0 for the usual depth
+
+void main()
+{
+    // Corners of a triangle strip, -1 to 1
+    vec2 corner = vec2(gl_VertexID & 1, gl_VertexID >> 1) * 2.0 - 1.0;
+    v_uv = corner * 0.5 + 0.5;
+    vec3 position = u_center + corner.x * u_right + corner.y * u_up;
+    gl_Position = u_perspective * u_view * vec4(position, 1.0);
+    if (u_log_depth > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w)) * u_log_depth - 1.0) * gl_Position.w;
+    }
+}
@@ -0,0 +1,14 @@
+#version 460 core
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec4 color;
+
+out vec4 v_color;
+
+uniform mat4 u_mvp;         // View-perspective matrix, lines are in world space
+
+void main()
+{
+    v_color = color;
+    gl_Position = u_mvp * vec4(position, 1.0f);
+}
@@ -0,0 +1,16 @@
+#version 460 core
+
+in vec4 v_color;
+
+out vec4 color;
+
+uniform bool u_points;
+
+void main()
+{
+    // Bodies are round dots
+    if (u_points && length(gl_PointCoord * 2.0 - 1.0) > 1.0) {
+        discard;
+    }
+    color = v_color;
+}
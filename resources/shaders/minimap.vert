@@ -0,0 +1,15 @@
+#version 460 core
+
+layout (location = 0) in vec3 position;     // On the map, and the size of points in pixels
+layout (location = 1) in vec4 color;
+
+out vec4 v_color;
+
+uniform mat4 u_projection;  // Orthographic, the map seen from above
+
+void main()
+{
+    v_color = color;
+    gl_Position = u_projection * vec4(position.xy, 0.0f, 1.0f);
+    gl_PointSize = position.z;
+}
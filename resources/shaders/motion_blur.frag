@@ -0,0 +1,31 @@
+#version 460 core
+
+#define SAMPLES 12
+
+out vec4 color;
+
+uniform sampler2D u_scene;      // The frame drawn
+uniform sampler2D u_velocity;   // Motion on screen since the last frame, in pixels
+uniform float u_shutter;        // Share of the frame the shutter is open
+uniform float u_max_length;     // Of the blur, in pixels
+
+void main()
+{
+    vec2 size = vec2(textureSize(u_scene, 0));
+    vec2 uv = gl_FragCoord.xy / size;
+    vec2 motion = texture(u_velocity, uv).xy * u_shutter;
+    float length_px = length(motion);
+    if (length_px < 0.5) {
+        color = vec4(texture(u_scene, uv).rgb, 1.0);
+        return;
+    }
+    motion *= min(length_px, u_max_length) / length_px;
+
+    // Centered on the pixel, back and forth along where it moved
+    vec3 sum = vec3(0.0);
+    for (int i = 0; i < SAMPLES; i++) {
+        float t = (float(i) + 0.5) / float(SAMPLES) - 0.5;
+        sum += texture(u_scene, uv + motion * t / size).rgb;
+    }
+    color = vec4(sum / float(SAMPLES), 1.0);
+}
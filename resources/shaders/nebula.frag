@@ -0,0 +1,53 @@
+#version 460 core
+
+in vec2 v_uv;
+
+out vec4 color;
+
+uniform int u_face;         // Cubemap face drawn, in the order of GL_TEXTURE_CUBE_MAP_POSITIVE_X
+uniform vec3 u_offset;      // Into the noise, from the seed
+uniform vec3 u_band;        // Normal of the plane the galaxy is brightest along
+uniform vec3 u_color_dust;  // Of thin clouds
+uniform vec3 u_color_glow;  // Of the dense cores
+
+//-noise.glsl header-----------------------------------------------------------/
+float noise3d(vec3 p);
+float fractal_noise3d(vec3 pos, float size, float height);
+//-noise.glsl header end-------------------------------------------------------/
+
+// Direction through a texel of a cubemap face, as sampled by texture()
+vec3 face_direction(int face, vec2 uv)
+{
+    vec2 st = uv * 2.0 - 1.0;
+    switch (face) {
+    case 0: return vec3(1.0, -st.y, -st.x);
+    case 1: return vec3(-1.0, -st.y, st.x);
+    case 2: return vec3(st.x, 1.0, st.y);
+    case 3: return vec3(st.x, -1.0, -st.y);
+    case 4: return vec3(st.x, -st.y, 1.0);
+    default: return vec3(-st.x, -st.y, -1.0);
+    }
+}
+
+void main()
+{
+    vec3 dir = normalize(face_direction(u_face, v_uv));
+    vec3 p = dir + u_offset;
+
+    // Fractal noise, warped by itself for wispy clouds, sums to just below 2
+    vec3 warp = vec3(
+        fractal_noise3d(p, 2.0, 1.0),
+        fractal_noise3d(p + 5.2, 2.0, 1.0),
+        fractal_noise3d(p + 9.7, 2.0, 1.0)
+    );
+    float dust = fractal_noise3d(p + warp * 0.6, 3.0, 1.0) / 1.94;
+    float cores = fractal_noise3d(p * 1.7 - warp * 0.4, 4.0, 1.0) / 1.94;
+
+    // Densest along a band across the sky
+    float band = exp(-pow(dot(dir, u_band) * 3.0, 2.0));
+    dust = smoothstep(0.35, 0.8, dust) * (0.3 + 0.7 * band);
+    cores = smoothstep(0.55, 0.9, cores) * band;
+
+    vec3 c = u_color_dust * dust + u_color_glow * cores * cores;
+    color = vec4(c, 1.0);
+}
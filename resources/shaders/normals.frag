@@ -0,0 +1,12 @@
+#version 460 core
+
+in vec4 g_color;
+
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 velocity;   // Still on screen, see motion_blur.rs
+
+void main()
+{
+    color = g_color;
+    velocity = vec4(0.0, 0.0, 0.0, 1.0);
+}
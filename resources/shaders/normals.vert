@@ -0,0 +1,16 @@
+#version 460 core
+
+// Face normals and broken triangles of a mesh, see normal_debug.rs. Takes the
+// vertices of scene.vert it needs, in model space.
+
+layout (location = 0) in vec3 position;
+layout (location = 2) in vec3 normal;   // Zero for meshes without normals
+
+out vec3 v_position;
+out vec3 v_normal;
+
+void main()
+{
+    v_position = position;
+    v_normal = normal;
+}
@@ -0,0 +1,9 @@
+#version 460 core
+
+out vec4 color;
+
+void main()
+{
+    // Only counted, with colour writes off
+    color = vec4(1.0);
+}
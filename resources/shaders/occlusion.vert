@@ -0,0 +1,21 @@
+#version 460 core
+
+uniform vec3 u_center;      // Of the body, relative to the origin
+uniform float u_radius;     // Half the width of the box around it
+uniform mat4 u_view;
+uniform mat4 u_perspective;
+uniform float u_log_depth;  // 2 / log2(far + 1) for logarithmic depth, 0 for the usual depth
+
+void main()
+{
+    // Corners of a cube as a triangle strip, -1 to 1
+    vec3 corner = vec3(
+        (0x287a >> gl_VertexID) & 1,
+        (0x02af >> gl_VertexID) & 1,
+        (0x31e3 >> gl_VertexID) & 1
+    ) * 2.0 - 1.0;
+    gl_Position = u_perspective * u_view * vec4(u_center + corner * u_radius, 1.0);
+    if (u_log_depth > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w)) * u_log_depth - 1.0) * gl_Position.w;
+    }
+}
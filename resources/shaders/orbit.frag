@@ -0,0 +1,17 @@
+#version 460 core
+
+in float g_edge;
+
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 velocity;   // Still on screen, see motion_blur.rs
+
+uniform vec4 u_color;
+uniform float u_width;  // Pixels
+
+void main()
+{
+    // Covered share of the pixel, fading over the pixel at each edge
+    float coverage = clamp(u_width / 2.0 + 0.5 - abs(g_edge), 0.0, 1.0);
+    color = vec4(u_color.rgb, u_color.a * coverage);
+    velocity = vec4(0.0, 0.0, 0.0, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 460 core
+
+// Orbit paths, see orbit_paths.rs. Points relative to the parent body, in the
+// units of the scene.
+
+layout (location = 0) in vec3 position;
+
+uniform mat4 u_mvp;
+
+void main()
+{
+    gl_Position = u_mvp * vec4(position, 1.0);
+}
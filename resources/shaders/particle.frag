@@ -0,0 +1,13 @@
+#version 460 core
+
+in vec2 v_corner;
+in vec4 v_color;
+
+out vec4 color;
+
+void main()
+{
+    // Round, with soft edges
+    float falloff = 1.0 - smoothstep(0.3, 1.0, length(v_corner));
+    color = vec4(v_color.rgb, v_color.a * falloff);
+}
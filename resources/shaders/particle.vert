@@ -0,0 +1,61 @@
+#version 460 core
+
+layout (location = 0) in vec2 corner;       // Of the quad, -1 to 1
+#ifdef GPU_PARTICLES
+// Simulated on the GPU, see particles.comp
+struct Particle {
+    vec4 position;      // In world space, age in w
+    vec4 previous;      // Position before the last step, lifetime in w
+    vec4 velocity;      // Size when spawned in w
+    vec4 acceleration;  // Size when dying in w
+    vec4 color_start;
+    vec4 color_end;
+};
+
+layout (std430, binding = 3) readonly buffer Particles {
+    Particle particles[];
+};
+
+uniform vec3 u_origin;      // World position particles are drawn relative to
+uniform vec3 u_eye;         // Relative to the origin
+uniform float u_alpha;      // Of the way from the step before the last to the last
+uniform vec2 u_range;       // Distances from the eye drawn in this depth pass
+#else
+layout (location = 1) in vec4 center_size;  // Relative to the origin, and half the width
+layout (location = 2) in vec4 color;
+#endif
+
+out vec2 v_corner;
+out vec4 v_color;
+
+uniform mat4 u_view;
+uniform mat4 u_perspective;
+uniform float u_log_depth;  // 2 / log2(far + 1) for logarithmic depth, 0 for the usual depth
+
+void main()
+{
+    v_corner = corner;
+#ifdef GPU_PARTICLES
+    Particle p = particles[gl_InstanceID];
+    float t = p.position.w / p.previous.w;  // Age over lifetime, not below 1 if never spawned
+    vec3 center = mix(p.previous.xyz, p.position.xyz, u_alpha) - u_origin;
+    float distance = length(center - u_eye);
+    if (!(t < 1.0) || distance < u_range.x || distance >= u_range.y) {
+        // Dead, or in another pass, collapsed outside the view
+        v_color = vec4(0.0);
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+    vec4 center_size = vec4(center, mix(p.velocity.w, p.acceleration.w, t));
+    v_color = mix(p.color_start, p.color_end, t);
+#else
+    v_color = color;
+#endif
+    // Offset in view space, facing the camera
+    vec4 position = u_view * vec4(center_size.xyz, 1.0);
+    position.xy += corner * center_size.w;
+    gl_Position = u_perspective * position;
+    if (u_log_depth > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w)) * u_log_depth - 1.0) * gl_Position.w;
+    }
+}
@@ -1,31 +1,131 @@
 #version 460 core
 
+#if __VERSION__ >= 460
+#define BATCHED_DRAWS       // Terrain patches drawn together, see terrain_batch.rs
+#endif
+
+#ifndef SHADOWS_ON
+#ifdef REDUCED_PIPELINE
+#define SHADOWS_ON 0        // No shadows on minimal hardware
+#else
+#define SHADOWS_ON 1
+#endif
+#endif
+
+#ifndef LOG_DEPTH
+#define LOG_DEPTH 1         // Set by u_log_depth, unless fixed in a variant
+#endif
+
+#ifdef REDUCED_PIPELINE
+uniform sampler2D u_texture;        // GLSL 3.30 has no binding layout, bound to unit 0 by default
+#else
 layout (binding = 0) uniform sampler2D u_texture;
-
-#define NODE_TYPE_GEOMETRY      0
-#define NODE_TYPE_SKYBOX        1
-#define NODE_TYPE_GEOMETRY2D    2
-#define NODE_TYPE_PLANET        3
-#define NODE_TYPE_OCEAN         4
-float specular_multiplier[] = {0.0, 0.0, 0.0, 0.15, 0.25};
-float specular_scale[] = {0.0, 0.0, 0.0, 16.0, 4.0};
+#endif
+
+#define NODE_TYPE_GEOMETRY      0u
+#define NODE_TYPE_SKYBOX        1u
+#define NODE_TYPE_GEOMETRY2D    2u
+#define NODE_TYPE_PLANET        3u
+#define NODE_TYPE_OCEAN         4u
+#define NODE_TYPE_CLOUD         5u
+#define DEBUG_NONE      0u
+#define DEBUG_WIREFRAME 1u
+#define DEBUG_NORMALS   2u
+#define DEBUG_UV        3u
+#define DEBUG_LOD       4u
+#define DEBUG_OVERDRAW  5u
+float specular_multiplier[] = float[](0.0, 0.0, 0.0, 0.15, 0.25, 0.0);
+float specular_scale[] = float[](0.0, 0.0, 0.0, 16.0, 4.0, 1.0);
+const float ICE_ROUGHNESS = 0.3;    // Of the top layer of planets, reflecting the sky
+const vec3 CITY_COLOR = vec3(1.0, 0.7, 0.35); // Sodium lamps, see city_lights.rs
+const float M_PI = 3.14159265;
 
 in vec3 v_position;
 in vec4 v_color;
 in vec3 v_normal;
 in vec2 v_uv;
+in vec4 v_tangent;
 in vec3 v_model_position;
+in float v_depth_w;
+#ifdef BATCHED_DRAWS
+flat in uint v_draw;                // Index into u_draws
+#endif
 
 uniform float u_time;
 uniform vec3 u_player_position;
 uniform mat4 u_model;
 uniform mat4 u_mvp;
+uniform mat4 u_previous_mvp;        // u_mvp of the last frame, for velocity, see motion_blur.rs
+uniform vec2 u_viewport_size;
 uniform mat4 u_perspective;
 uniform mat4 u_view;
 
+// Fixed in variants of the shader, see ShaderVariants in shader.rs
+#ifdef NODE_TYPE
+const uint u_node_type = NODE_TYPE;
+#else
 uniform uint u_node_type;
+#endif
 uniform uint u_current_planet_id;   // Just in case multiple planets should be rendered
+#ifdef HAS_TEXTURE
+const bool u_has_texture = HAS_TEXTURE;
+#else
 uniform bool u_has_texture;
+#endif
+uniform vec4 u_tint;                // Multiplies the colour of geometry, see animation.rs
+uniform float u_fade;               // Share of pixels drawn, the rest if negative, see lod.rs
+#ifdef DEBUG_MODE
+const uint u_debug_mode = DEBUG_MODE;
+#else
+uniform uint u_debug_mode;
+#endif
+uniform uint u_lod_level;           // LoD level of planet patch
+uniform bool u_outline;             // Selection outline pass, flat colour
+uniform vec3 u_outline_color;
+uniform bool u_selected;            // Selected node, tinted towards the outline colour at the rim
+uniform float u_log_depth;          // Logarithmic depth when above 0, see scene.vert
+uniform samplerCube u_nebula;       // Baked nebula behind the stars, see nebula.rs
+uniform bool u_has_nebula;
+uniform sampler2D u_normal_map;     // Of the node, see render_queue.rs
+uniform bool u_has_normal_map;
+uniform sampler2D u_detail_map;     // Craters and rock grain tiled over planets, see texture.rs
+uniform sampler2D u_terrain_map;    // Rock, grass and snow patterns in r, g and b, see texture.rs
+uniform sampler2D u_city_lights;    // Of the planet, by longitude and latitude, see city_lights.rs
+uniform bool u_has_city_lights;
+uniform bool u_normal_maps;         // Normal mapping enabled
+uniform float u_light_intensity;    // Scales the light from lightsources, see settings.conf
+uniform float u_ambient_light;      // Scales the ambient light, but not the glow of lightsources
+uniform samplerCube u_reflection;   // Surroundings of the planet, reflected by the ocean, see reflections.rs
+uniform bool u_has_reflection;
+uniform samplerCube u_irradiance;   // Light from the whole sky around a normal, see ibl.rs
+uniform samplerCube u_environment;  // Sky reflected, blurred more at higher levels
+uniform bool u_has_environment;
+uniform float u_environment_levels; // Highest level of u_environment, for roughness 1
+uniform float u_metallic;           // Of the node
+uniform float u_roughness;
+uniform bool u_batched;             // Drawn with others, the values of each in u_draws
+
+#ifdef BATCHED_DRAWS
+struct Draw {
+    mat4 mvp;
+    mat4 previous_mvp;
+    mat4 model;
+    uint planet_id;
+    uint lod_level;
+    float fade;
+};
+layout(std430, binding = 2) readonly buffer Draws {
+    Draw u_draws[];
+};
+#endif
+
+// Of the node drawn, from the uniforms above or from u_draws, set first in main
+mat4 node_model;
+mat4 node_mvp;
+mat4 node_previous_mvp;
+uint node_planet_id;
+uint node_lod_level;
+float node_fade;
 
 #define N_LAYERS 5
 
@@ -46,15 +146,29 @@ uniform struct Planet {
     vec3 color_scheme[N_LAYERS];        // Colours of height map
     float color_thresholds[N_LAYERS-1]; // Levels for changing colour
     float color_blending;               // Level of blending between colours
+    float detail_strength;  // Bumpiness of the detail map
+    float detail_scale;     // Repeats of the detail map across a side
+    uint max_lod;           // Finest LoD level of terrain patches
+    float texture_strength; // Contrast of the terrain patterns
+    vec3 texture_scale;     // Repeats of the rock, grass and snow patterns across the planet
+    float bands;            // Bands of colour by latitude, 0 for none
+    float glow;             // Brightness of the bottom layer glowing in the dark
     vec3 ocean_dark_color;  // Colour of the ocean
     vec3 ocean_light_color; // Colour of the ocean
+    // Cloud shell
+    vec3 cloud_color;
+    float cloud_coverage;   // Share of the sky covered, 0 to 1
+    float cloud_density;    // Opacity of the thickest clouds
+    float cloud_size;       // Noise frequency, higher for smaller clouds
+    float cloud_wind;       // Drift around the planet's axis, radians per second
 } u_planets[MAX_PLANETS];
 
 uniform uint u_lightsources_len;
 uniform uint u_lightsources[MAX_PLANETS];
 uniform uint u_planet_ids_sorted[MAX_PLANETS];
 
-out vec4 color;
+layout (location = 0) out vec4 color;
+layout (location = 1) out vec4 velocity;   // Of the surface on screen, in pixels since the last frame
 
   const uint k = 1103515245U;  // GLIB C
 //const uint k = 134775813U;   // Delphi and Turbo Pascal
@@ -115,27 +229,78 @@ vec4 phong_light(
 );
 vec4 planet_shader(vec3 position, vec3 normal, uint planet_id);
 vec4 skybox_shader();
+vec4 debug_shader(vec4 color);
 vec4 ocean_shader(
     vec3 v_position, 
     vec3 v_normal, 
     vec3 ocean_dark_color, 
     vec3 ocean_light_color
 );
+vec4 cloud_shader(vec3 position, vec3 normal, uint planet_id);
+vec3 surface_normal();
+vec3 terrain_texture(vec3 position, float h, uint planet_id);
+vec3 city_lights(vec3 position, uint planet_id);
+vec3 gas_giant_color(vec3 position, uint planet_id);
+vec3 environment_light(
+    vec3 diffuse_color,
+    vec3 position,
+    vec3 normal,
+    float metallic,
+    float roughness
+);
+
+const float BAYER[16] = float[](0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
 
 void main()
 {
+    node_model = u_model;
+    node_mvp = u_mvp;
+    node_previous_mvp = u_previous_mvp;
+    node_planet_id = u_current_planet_id;
+    node_lod_level = u_lod_level;
+    node_fade = u_fade;
+#ifdef BATCHED_DRAWS
+    if (u_batched) {
+        Draw draw = u_draws[v_draw];
+        node_model = draw.model;
+        node_mvp = draw.mvp;
+        node_previous_mvp = draw.previous_mvp;
+        node_planet_id = draw.planet_id;
+        node_lod_level = draw.lod_level;
+        node_fade = draw.fade;
+    }
+#endif
+    // Cross-fading levels of detail draw complementary dither patterns
+    if (node_fade < 1.0) {
+        ivec2 pixel = ivec2(gl_FragCoord.xy) & 3;
+        float threshold = (BAYER[pixel.y * 4 + pixel.x] + 0.5) / 16.0;
+        if (node_fade >= 0.0 ? threshold >= node_fade : threshold < -node_fade) {
+            discard;
+        }
+    }
+#if LOG_DEPTH
+    // Per fragment, as depth interpolated between vertices is wrong for large
+    // triangles. Writing it turns off early depth testing, so variants
+    // without logarithmic depth leave it out.
+    gl_FragDepth = (u_log_depth > 0.0 && u_node_type != NODE_TYPE_SKYBOX)
+        ? log2(v_depth_w) * u_log_depth * 0.5
+        : gl_FragCoord.z;
+#endif
     switch (u_node_type) {
     case NODE_TYPE_GEOMETRY:
     case NODE_TYPE_PLANET:
-        color = planet_shader(v_position, v_normal, u_current_planet_id);
+        color = planet_shader(v_position, surface_normal(), node_planet_id);
         break;
     case NODE_TYPE_OCEAN:
         color = ocean_shader(
             v_position, v_normal,
-            u_planets[u_current_planet_id].ocean_dark_color, 
-            u_planets[u_current_planet_id].ocean_dark_color
+            u_planets[node_planet_id].ocean_dark_color, 
+            u_planets[node_planet_id].ocean_dark_color
         );
         break;
+    case NODE_TYPE_CLOUD:
+        color = cloud_shader(v_position, v_normal, node_planet_id);
+        break;
     case NODE_TYPE_SKYBOX:
         color = skybox_shader();
         break;
@@ -147,6 +312,88 @@ void main()
         color = vec4(0.0, 1.0, 0.0, 1.0);
         break;
     }
+    if (u_node_type == NODE_TYPE_GEOMETRY || u_node_type == NODE_TYPE_GEOMETRY2D) {
+        color *= u_tint;
+    }
+    if (u_selected && u_node_type != NODE_TYPE_SKYBOX && u_node_type != NODE_TYPE_GEOMETRY2D) {
+        vec3 normal = normalize(mat3(node_model) * v_normal);
+        vec3 position = (node_model * vec4(v_position, 1.0)).xyz;
+        float rim = 1.0 - abs(dot(normal, normalize(u_player_position - position)));
+        color.rgb = mix(color.rgb, u_outline_color, 0.5 * pow(rim, 3.0));
+    }
+    if (u_debug_mode != DEBUG_NONE) {
+        color = debug_shader(color);
+    }
+    // Where the surface was on screen the last frame, written where enabled
+    vec4 current = node_mvp * vec4(v_position, 1.0);
+    vec4 previous = node_previous_mvp * vec4(v_position, 1.0);
+    velocity = vec4(0.0, 0.0, 0.0, 1.0);
+    if (previous.w > 0.0 && current.w > 0.0) {
+        velocity.xy = (current.xy / current.w - previous.xy / previous.w) * 0.5 * u_viewport_size;
+    }
+    if (u_outline) {
+        color = vec4(u_outline_color, 1.0);
+    }
+}
+
+//-----------------------------------------------------------------------------/
+// Debug visualizations, replacing the colour of 3D geometry
+//-----------------------------------------------------------------------------/
+vec4 debug_shader(vec4 color)
+{
+    if (u_debug_mode == DEBUG_OVERDRAW) {
+        return vec4(0.08, 0.04, 0.02, 1.0);
+    }
+    if (u_node_type == NODE_TYPE_SKYBOX || u_node_type == NODE_TYPE_GEOMETRY2D) {
+        return color;
+    }
+    switch (u_debug_mode) {
+    case DEBUG_NORMALS: {
+        vec3 normal = normalize(transpose(inverse(mat3(node_model))) * surface_normal());
+        return vec4(normal * 0.5 + 0.5, 1.0);
+    }
+    case DEBUG_UV: {
+        vec2 checker = floor(v_uv * 16.0);
+        float c = mod(checker.x + checker.y, 2.0);
+        return vec4(mix(vec3(0.2), vec3(0.9), c) * vec3(v_uv, 1.0), 1.0);
+    }
+    case DEBUG_LOD: {
+        // Blue at lowest detail to red at the highest of the planet
+        float max_lod = float(max(u_planets[node_planet_id].max_lod, 1u));
+        float t = clamp(float(node_lod_level) / max_lod, 0.0, 1.0);
+        return vec4(mix(vec3(0.1, 0.2, 1.0), vec3(1.0, 0.1, 0.1), t), 1.0);
+    }
+    default:
+        return color;
+    }
+}
+
+//-----------------------------------------------------------------------------/
+// Normal in model space, perturbed by the normal map of the node, or the
+// detail map on planets
+//-----------------------------------------------------------------------------/
+vec3 surface_normal()
+{
+    vec3 normal = normalize(v_normal);
+    if (!u_normal_maps || dot(v_tangent.xyz, v_tangent.xyz) < 1e-6) {
+        return normal;
+    }
+    vec3 mapped;
+    if (u_node_type == NODE_TYPE_PLANET) {
+        Planet planet = u_planets[node_planet_id];
+        mapped = texture(u_detail_map, v_uv * planet.detail_scale).xyz * 2.0 - 1.0;
+        mapped.xy *= planet.detail_strength;
+    }
+    else if (u_node_type == NODE_TYPE_GEOMETRY && u_has_normal_map) {
+        mapped = texture(u_normal_map, v_uv).xyz * 2.0 - 1.0;
+    }
+    else {
+        return normal;
+    }
+    // Tangent space, re-orthogonalized after interpolation
+    vec3 t = normalize(v_tangent.xyz - normal * dot(normal, v_tangent.xyz));
+    vec3 b = cross(normal, t) * v_tangent.w;
+    return normalize(mat3(t, b, normal) * mapped);
 }
 
 //-----------------------------------------------------------------------------/
@@ -180,23 +427,180 @@ vec4 planet_shader(vec3 position, vec3 normal, uint planet_id)
         diffuse_color = u_planets[planet_id].color_scheme[4];
         //diffuse_color = vec3(1.0, 1.0, 1.0);
     }
+    // Or bands of colour by latitude, on gas giants
+    if (u_planets[planet_id].bands > 0.0) {
+        diffuse_color = gas_giant_color(position, planet_id);
+    }
 
     //-------------------------------------------------------------------------/
     // Lighting
     //  Ambient colour is full diffuse colour for lightsources
     //  Specular colour is the lightsource's emission colour
     //-------------------------------------------------------------------------/
+    if (u_node_type == NODE_TYPE_PLANET && !u_planets[planet_id].lightsource) {
+        // Tinted by craters, volcanoes and canyons, see surface_features.rs
+        diffuse_color *= terrain_texture(position, h, planet_id) * v_color.rgb;
+    }
+
     vec3 ambient_color = diffuse_color.rgb * (
         u_planets[planet_id].lightsource ? 1.0 : 0.23
         );
 
-    return phong_light(
-        diffuse_color, 
-        ambient_color, 
+    //-------------------------------------------------------------------------/
+    // Sky reflected by metallic props, and glossy on the ice above the top
+    // threshold of planets
+    //-------------------------------------------------------------------------/
+    float metallic = 0.0;
+    float roughness = 1.0;
+    if (u_node_type == NODE_TYPE_GEOMETRY) {
+        metallic = u_metallic;
+        roughness = u_roughness;
+    }
+    else if (h >= u_planets[planet_id].color_thresholds[3] && !u_planets[planet_id].lightsource) {
+        roughness = ICE_ROUGHNESS;
+    }
+
+    vec4 color = phong_light(
+        diffuse_color * (1.0 - metallic), 
+        ambient_color * (1.0 - metallic), 
         position,
         normal, 
         1.0
     );
+    // Props are drawn with planet 0, lit whether or not it is a lightsource
+    if (u_has_environment && (u_node_type == NODE_TYPE_GEOMETRY || !u_planets[planet_id].lightsource)) {
+        color.rgb += environment_light(diffuse_color, position, normal, metallic, roughness);
+    }
+    if (u_node_type == NODE_TYPE_PLANET && u_has_city_lights) {
+        color.rgb += city_lights(position, planet_id);
+    }
+    // Molten lowlands, fading out up the edge of the bottom layer
+    if (u_planets[planet_id].glow > 0.0 && !u_planets[planet_id].lightsource) {
+        float threshold = u_planets[planet_id].color_thresholds[0];
+        float molten = 1.0 - smoothstep(threshold - 0.002, threshold, h);
+        color.rgb += u_planets[planet_id].color_scheme[0] * u_planets[planet_id].glow * molten;
+    }
+    return color;
+}
+
+//-----------------------------------------------------------------------------/
+// Gas giants, turbulent bands of colour by latitude with oval storms, the
+// bands streaming around the planet's axis in jets of alternating speed
+//-----------------------------------------------------------------------------/
+vec3 gas_giant_color(vec3 position, uint planet_id)
+{
+    Planet planet = u_planets[planet_id];
+    vec3 d = normalize(position);
+    float jet = sin(d.y * planet.bands * M_PI);
+    float angle = u_time * planet.cloud_wind * (1.0 + jet);
+    d.xz = mat2(cos(angle), -sin(angle), sin(angle), cos(angle)) * d.xz;
+
+    // Band edges warped by turbulence, churning slowly
+    vec3 churn = vec3(0.0, u_time * 0.02, 0.0);
+    float t = (d.y * 0.5 + 0.5) * planet.bands
+        + (noise3d(d * 6.0 + churn) - 0.5) * 0.8
+        + (noise3d(d * 23.0 - churn) - 0.5) * 0.2;
+    int band = int(floor(max(t, 0.0)));
+    vec3 color = mix(
+        planet.color_scheme[band % N_LAYERS],
+        planet.color_scheme[(band + 1) % N_LAYERS],
+        smoothstep(0.7, 1.0, fract(t))
+    );
+
+    // Storms wide along the bands, in swirling rings of the second colour
+    vec3 cell = d * vec3(2.5, 10.0, 2.5) + vec3(u_time * 0.005, 0.0, 0.0);
+    float storm = smoothstep(0.68, 0.85, noise3d(cell));
+    float swirl = sin(noise3d(cell * 3.0) * 12.0 + u_time * 0.3);
+    return mix(color, planet.color_scheme[1] * (1.0 + 0.25 * swirl), storm);
+}
+
+//-----------------------------------------------------------------------------/
+// Lights of cities, coming on as the sun sets at the ground
+//-----------------------------------------------------------------------------/
+vec3 city_lights(vec3 position, uint planet_id)
+{
+    vec3 d = normalize(position);
+    vec3 up = normalize(mat3(node_model) * d);
+    float daylight = 0.0;
+    for (uint i = 0u; i < u_lightsources_len; i++) {
+        vec3 light_dir = normalize(u_planets[u_lightsources[i]].position - u_planets[planet_id].position);
+        daylight = max(daylight, dot(up, light_dir));
+    }
+    float night = 1.0 - smoothstep(-0.15, 0.05, daylight);
+    // Longitude wraps around, taken from whichever side has no seam at the
+    // fragment, so the mipmap isn't picked across it (Tarini)
+    float longitude = atan(d.z, d.x) / (2.0 * M_PI);
+    float u0 = fract(longitude + 0.5);
+    float u1 = fract(longitude) - 0.5;
+    float u = fwidth(u0) <= fwidth(u1) + 1e-6 ? u0 : u1;
+    vec2 uv = vec2(u, acos(clamp(d.y, -1.0, 1.0)) / M_PI);
+    return texture(u_city_lights, uv).r * CITY_COLOR * night;
+}
+
+//-----------------------------------------------------------------------------/
+// Rock on steep slopes, snow up high and grass elsewhere, each a pattern
+// brightening and darkening the terrain colour. Mapped triplanar, projected
+// along the three axes and blended by the normal, so cliffs aren't stretched.
+//-----------------------------------------------------------------------------/
+vec4 triplanar(vec3 position, vec3 weights, float scale)
+{
+    return texture(u_terrain_map, position.yz * scale) * weights.x
+        + texture(u_terrain_map, position.zx * scale) * weights.y
+        + texture(u_terrain_map, position.xy * scale) * weights.z;
+}
+
+vec3 terrain_texture(vec3 position, float h, uint planet_id)
+{
+    Planet planet = u_planets[planet_id];
+    if (planet.texture_strength <= 0.0) {
+        return vec3(1.0);
+    }
+    vec3 normal = normalize(v_normal);
+    float flatness = dot(normal, normalize(position));
+    float rock = 1.0 - smoothstep(0.75, 0.9, flatness);
+    float snow = smoothstep(planet.color_thresholds[2], planet.color_thresholds[3], h) * (1.0 - rock);
+    float grass = 1.0 - rock - snow;
+
+    vec3 weights = pow(abs(normal), vec3(4.0));
+    weights /= weights.x + weights.y + weights.z;
+    // Sampled outside of branches, which would leave mipmapping undefined
+    float pattern = triplanar(position, weights, planet.texture_scale.x).r * rock
+        + triplanar(position, weights, planet.texture_scale.y).g * grass
+        + triplanar(position, weights, planet.texture_scale.z).b * snow;
+    return vec3(1.0 + (pattern - 0.5) * 2.0 * planet.texture_strength);
+}
+
+//-----------------------------------------------------------------------------/
+// Image-based lighting: diffuse light from the sky and its reflection, with
+// an analytic fit of the split-sum BRDF term (Karis, "Physically Based
+// Shading on Mobile")
+//-----------------------------------------------------------------------------/
+vec3 environment_light(
+    vec3 diffuse_color,
+    vec3 position,      // vertex position from model, in model scale
+    vec3 normal,
+    float metallic,
+    float roughness
+) {
+    vec3 world_position = (node_model * vec4(position, 1.0)).xyz;
+    vec3 n = normalize(transpose(inverse(mat3(node_model))) * normal);
+    vec3 view_dir = normalize(u_player_position - world_position);
+    float n_dot_v = max(dot(n, view_dir), 0.0);
+
+    vec3 f0 = mix(vec3(0.04), diffuse_color, metallic);
+    const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+    vec4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    vec2 ab = vec2(-1.04, 1.04) * a004 + r.zw;
+
+    vec3 specular = textureLod(
+        u_environment,
+        reflect(-view_dir, n),
+        roughness * u_environment_levels
+    ).rgb * (f0 * ab.x + ab.y);
+    vec3 diffuse = texture(u_irradiance, n).rgb * diffuse_color * (1.0 - metallic);
+    return diffuse + specular;
 }
 
 //-----------------------------------------------------------------------------/
@@ -210,10 +614,11 @@ vec4 phong_light(
     vec3 normal,
     float alpha
 ) {
-    vec3 color = ambient_color;
+    bool glowing = u_node_type == NODE_TYPE_PLANET && u_planets[node_planet_id].lightsource;
+    vec3 color = ambient_color * (glowing ? 1.0 : u_ambient_light);
 
-    vec3 planet_center = u_planets[u_current_planet_id].position;
-    mat3 normal_matrix = transpose(inverse(mat3(u_model)));
+    vec3 planet_center = u_planets[node_planet_id].position;
+    mat3 normal_matrix = transpose(inverse(mat3(node_model)));
     position += planet_center;
     normal = normal_matrix * normal;
 
@@ -225,7 +630,7 @@ vec4 phong_light(
     vec3 specular_color;
     float specular = 0.0;// = pow(max(dot(half_direction, normalize(normal)), 0.0), 32.0);
 
-    for (int i = 0; i < u_lightsources_len; i++) {
+    for (uint i = 0u; i < u_lightsources_len; i++) {
         uint light_id = u_lightsources[i];
         light = u_planets[light_id].position;
         vec3 light_dir = light - position;
@@ -248,8 +653,9 @@ vec4 phong_light(
         vec3 occluder;
         float occluder_radius;
         float light_radius=u_planets[light_id].radius;
-        for (int i = 0; i < u_planets_len+1; i++) {
-            if (i == u_current_planet_id) continue;
+#if SHADOWS_ON
+        for (uint i = 0u; i < u_planets_len+1u; i++) {
+            if (i == node_planet_id) continue;
             
             if (i < u_planets_len) {
                 occluder=u_planets[i].position;
@@ -281,7 +687,8 @@ vec4 phong_light(
                 a -= shadow;
             }
         }
-        color += a * vec3(diffuse * diffuse_color + specular * specular_color);
+#endif
+        color += a * u_light_intensity * vec3(diffuse * diffuse_color + specular * specular_color);
     }
     return vec4(color, alpha);
 }
@@ -310,9 +717,43 @@ vec4 ocean_shader(
         0.96
     );
 
+    if (u_has_reflection) {
+        vec3 position = (node_model * vec4(v_position, 1.0)).xyz;
+        vec3 n = normalize(transpose(inverse(mat3(node_model))) * normal);
+        vec3 view_dir = normalize(position - u_player_position);
+        vec3 sky = texture(u_reflection, reflect(view_dir, n)).rgb;
+        // Schlick's approximation: little is reflected head on, all of it at grazing angles
+        float fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(-view_dir, n), 0.0), 5.0);
+        color.rgb = mix(color.rgb, sky, fresnel);
+    }
+
     return color;
 }
 
+//-----------------------------------------------------------------------------/
+// Translucent cloud shell, noise drifting around the planet's axis with time
+//-----------------------------------------------------------------------------/
+vec4 cloud_shader(vec3 position, vec3 normal, uint planet_id)
+{
+    Planet planet = u_planets[planet_id];
+    float angle = u_time * planet.cloud_wind;
+    vec3 p = normalize(position);
+    p.xz = mat2(cos(angle), -sin(angle), sin(angle), cos(angle)) * p.xz;
+
+    // Fractal noise sums to just below 2
+    float n = fractal_noise3d(p, planet.cloud_size, 1.0) / 1.94;
+    float threshold = 1.0 - planet.cloud_coverage;
+    float alpha = smoothstep(threshold, threshold + 0.2, n) * planet.cloud_density;
+
+    return phong_light(
+        planet.cloud_color,
+        planet.cloud_color * 0.3,
+        position,
+        normal,
+        alpha
+    );
+}
+
 //-----------------------------------------------------------------------------/
 // SKYBOX
 // Renders a starry sky, and a texture of distant planets
@@ -325,7 +766,7 @@ vec4 skybox_shader()
     vec3 rd = normalize(v_position);    // Texture position on skybox -> ray direction
     vec3 ro = u_player_position;        // Ray origin
 
-    for (int i = 0; i < u_planets_len; i++) {
+    for (uint i = 0u; i < u_planets_len; i++) {
         //---------------------------------------------------------------------/
         // Draw coloured, faded dots on planet positions, doubles as lowest LoD
         // and atmosphere (latter has some issues).
@@ -374,5 +815,10 @@ vec4 skybox_shader()
     
     float v = rand2(vec2(rand2(st.xy), st.z));
 
-    return vec4(mix(vec3(radgrad), c.rgb, c.a), 1.0);
+    vec3 sky = vec3(radgrad);
+    if (u_has_nebula) {
+        sky += texture(u_nebula, rd).rgb;
+    }
+
+    return vec4(mix(sky, c.rgb, c.a), 1.0);
 }
\ No newline at end of file
@@ -1,28 +1,90 @@
 #version 460 core
 
-in vec3 position;
-in vec4 color;
-in vec3 normal;
-in vec2 uv;
+#if __VERSION__ >= 460
+#define BATCHED_DRAWS       // Terrain patches drawn together, see terrain_batch.rs
+#endif
+
+// Indices of mesh::VertexLayout, see Mesh::mkvao_with
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+layout(location = 2) in vec3 normal;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in vec4 tangent;   // Zero for meshes without tangents
+layout(location = 5) in vec4 joints;    // Skinned meshes only, indices into u_bones
+layout(location = 6) in vec4 weights;   // Summing to one, or zero where not skinned
 
 out vec3 v_position;
 out vec4 v_color;
 out vec3 v_normal;
 out vec2 v_uv;
+out vec4 v_tangent;
 out vec3 v_model_position;
+out float v_depth_w;        // 1 + clip space w, for logarithmic depth
 
+#ifdef NODE_TYPE
+const uint u_node_type = NODE_TYPE; // Fixed in a variant, see ShaderVariants in shader.rs
+#else
 uniform uint u_node_type;
+#endif
 uniform mat4 u_model;       // Transforms model into world coordinates
 uniform mat4 u_mvp;         // Model-view-perspective matrix
+uniform float u_outline_width; // Push vertices out along the normal in the outline pass
+uniform float u_log_depth;  // 2 / log2(far + 1) for logarithmic depth, 0 for the usual depth
+uniform bool u_skinned;
+uniform bool u_batched;     // Drawn with others, the matrices of each in u_draws
+
+#ifdef BATCHED_DRAWS
+struct Draw {
+    mat4 mvp;
+    mat4 previous_mvp;
+    mat4 model;
+    uint planet_id;
+    uint lod_level;
+    float fade;
+};
+layout(std430, binding = 2) readonly buffer Draws {
+    Draw u_draws[];         // By gl_DrawID, bound at BATCH_DRAWS_BINDING
+};
+flat out uint v_draw;
+#endif
+
+#if __VERSION__ >= 420
+layout(std140, binding = 0) uniform Bones {
+#else
+layout(std140) uniform Bones {  // GLSL before 4.20 has no binding layout, bound to 0 by default
+#endif
+    mat4 u_bones[128];      // MAX_JOINTS in globals.rs, bound at BONES_BINDING
+};
 
 void main()
 {
     v_position = position;
     v_normal = normal;
-    v_model_position = position;
+    v_tangent = tangent;
+    if (u_skinned && dot(weights, vec4(1.0)) > 0.0) {
+        mat4 skin = weights.x * u_bones[int(joints.x)]
+                  + weights.y * u_bones[int(joints.y)]
+                  + weights.z * u_bones[int(joints.z)]
+                  + weights.w * u_bones[int(joints.w)];
+        v_position = (skin * vec4(position, 1.0)).xyz;
+        v_normal = mat3(skin) * normal;
+        v_tangent.xyz = mat3(skin) * tangent.xyz;
+    }
+    v_model_position = v_position;
     v_color = color;
     v_uv = uv;
-    vec4 pos = u_mvp * vec4(v_position, 1.0f);
-    gl_Position = (u_node_type == 1) ? pos.xyww : pos;
+    mat4 mvp = u_mvp;
+#ifdef BATCHED_DRAWS
+    v_draw = uint(gl_DrawID);
+    if (u_batched) {
+        mvp = u_draws[gl_DrawID].mvp;
+    }
+#endif
+    vec4 pos = mvp * vec4(v_position + v_normal * u_outline_width, 1.0f);
+    gl_Position = (u_node_type == 1u) ? pos.xyww : pos;
+    v_depth_w = 1.0 + gl_Position.w;
+    if (u_log_depth > 0.0 && u_node_type != 1u) {
+        gl_Position.z = (log2(max(1e-6, v_depth_w)) * u_log_depth - 1.0) * gl_Position.w;
+    }
 
 }
\ No newline at end of file
@@ -0,0 +1,41 @@
+#version 460 core
+
+#define SAMPLES 64
+
+in vec2 v_uv;
+
+out vec4 color;
+
+uniform sampler2D u_scene;  // Downscaled copy of the frame
+uniform vec2 u_light;       // Screen position of the light, 0 to 1
+uniform float u_radius;     // Of the light on screen, relative to the height
+uniform float u_aspect;
+uniform vec3 u_color;
+uniform float u_threshold;  // Brightness of unoccluded light
+uniform float u_density;    // Share of the way to the light sampled
+uniform float u_decay;      // Weight kept per sample
+uniform float u_intensity;
+
+// Light where the light source is drawn and not covered by something darker
+float occlusion_mask(vec2 uv)
+{
+    vec3 scene = texture(u_scene, uv).rgb;
+    float brightness = dot(scene, vec3(0.2126, 0.7152, 0.0722));
+    float disc = 1.0 - smoothstep(u_radius, u_radius * 1.5, length((uv - u_light) * vec2(u_aspect, 1.0)));
+    return disc * smoothstep(u_threshold, u_threshold + 0.2, brightness);
+}
+
+void main()
+{
+    // March towards the light, with the light further away weighing less
+    vec2 delta = (v_uv - u_light) * u_density / float(SAMPLES);
+    vec2 uv = v_uv;
+    float weight = 1.0;
+    float light = 0.0;
+    for (int i = 0; i < SAMPLES; i++) {
+        uv -= delta;
+        light += occlusion_mask(uv) * weight;
+        weight *= u_decay;
+    }
+    color = vec4(u_color * light * u_intensity / float(SAMPLES), 1.0);
+}
@@ -0,0 +1,12 @@
+#version 460 core
+
+in vec4 v_color;
+
+out vec4 color;
+
+void main()
+{
+    // Round, with soft edges
+    float r = length(gl_PointCoord * 2.0 - 1.0);
+    color = vec4(v_color.rgb, v_color.a * (1.0 - smoothstep(0.4, 1.0, r)));
+}
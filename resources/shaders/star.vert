@@ -0,0 +1,20 @@
+#version 460 core
+
+layout (location = 0) in vec3 direction;    // Unit vector towards the star
+layout (location = 1) in vec4 color_size;   // Colour, and flux relative to the reference magnitude
+
+out vec4 v_color;
+
+uniform mat4 u_view;
+uniform mat4 u_perspective;
+uniform float u_brightness;
+
+void main()
+{
+    // Direction only, the stars don't move with the camera
+    gl_Position = u_perspective * u_view * vec4(direction, 0.0);
+    float flux = color_size.a * u_brightness;
+    // Faint stars dim, bright stars grow
+    gl_PointSize = clamp(1.0 + sqrt(flux), 1.0, 6.0);
+    v_color = vec4(color_size.rgb, min(sqrt(flux), 1.0));
+}
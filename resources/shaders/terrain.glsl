@@ -0,0 +1,148 @@
+// Terrain height of planets, the fractal noise of Planet::noise on the GPU.
+// Shaders including this declare the `permutation` table of the planet's
+// noise::Perlin seed in a storage buffer, `u_noise`, `u_max_height` and the
+// stamped features in `u_features` and `u_features_len`, see planets.comp.
+
+uint corner_hash(ivec3 c)
+{
+    uint h = permutation[c.x & 255];
+    h = permutation[h ^ uint(c.y & 255)];
+    return permutation[h ^ uint(c.z & 255)];
+}
+
+float gradient_dot(uint h, vec3 p)
+{
+    switch (h & 15u) {
+    case 0u: case 12u: return  p.x + p.y;
+    case 1u: case 13u: return -p.x + p.y;
+    case 2u:           return  p.x - p.y;
+    case 3u:           return -p.x - p.y;
+    case 4u:           return  p.x + p.z;
+    case 5u:           return -p.x + p.z;
+    case 6u:           return  p.x - p.z;
+    case 7u:           return -p.x - p.z;
+    case 8u:           return  p.y + p.z;
+    case 9u: case 14u: return -p.y + p.z;
+    case 10u:          return  p.y - p.z;
+    default:           return -p.y - p.z;
+    }
+}
+
+vec3 s_curve5(vec3 x)
+{
+    return x * x * x * (x * (x * 6.0 - 15.0) + 10.0);
+}
+
+// 3D Perlin noise in -1 to 1, as in noise 0.7
+float perlin(vec3 p)
+{
+    vec3 floored = floor(p);
+    ivec3 c = ivec3(floored);
+    vec3 d = p - floored;
+    float g000 = gradient_dot(corner_hash(c),                d);
+    float g100 = gradient_dot(corner_hash(c + ivec3(1, 0, 0)), d - vec3(1.0, 0.0, 0.0));
+    float g010 = gradient_dot(corner_hash(c + ivec3(0, 1, 0)), d - vec3(0.0, 1.0, 0.0));
+    float g110 = gradient_dot(corner_hash(c + ivec3(1, 1, 0)), d - vec3(1.0, 1.0, 0.0));
+    float g001 = gradient_dot(corner_hash(c + ivec3(0, 0, 1)), d - vec3(0.0, 0.0, 1.0));
+    float g101 = gradient_dot(corner_hash(c + ivec3(1, 0, 1)), d - vec3(1.0, 0.0, 1.0));
+    float g011 = gradient_dot(corner_hash(c + ivec3(0, 1, 1)), d - vec3(0.0, 1.0, 1.0));
+    float g111 = gradient_dot(corner_hash(c + ivec3(1, 1, 1)), d - vec3(1.0, 1.0, 1.0));
+
+    vec3 s = s_curve5(d);
+    float k1 = g100 - g000;
+    float k2 = g010 - g000;
+    float k3 = g001 - g000;
+    float k4 = g000 + g110 - g100 - g010;
+    float k5 = g000 + g101 - g100 - g001;
+    float k6 = g000 + g011 - g010 - g001;
+    float k7 = g100 + g010 + g001 + g111 - g000 - g110 - g101 - g011;
+    float result = g000 + k1 * s.x + k2 * s.y + k3 * s.z
+        + k4 * s.x * s.y + k5 * s.x * s.z + k6 * s.y * s.z + k7 * s.x * s.y * s.z;
+    return clamp(result * 2.0 / sqrt(3.0), -1.0, 1.0);
+}
+
+// Height in max heights and tint of the ground from the craters, volcanoes
+// and canyons stamped on the planet, as surface_features::stamp
+float stamped(vec3 direction, out vec3 tint)
+{
+    float height = 0.0;
+    tint = vec3(1.0);
+    for (uint i = 0u; i < u_features_len; i++) {
+        vec4 center_radius = u_features[3u * i];
+        vec4 axis_length = u_features[3u * i + 1u];
+        vec4 depth_kind = u_features[3u * i + 2u];
+        vec3 center = center_radius.xyz;
+        float depth = depth_kind.x;
+        float angle = acos(clamp(dot(direction, center), -1.0, 1.0));
+        int kind = int(depth_kind.y + 0.5);
+        if (kind == 0) {
+            // Crater, with a dark floor and bright ejecta fading out around it
+            float t = angle / center_radius.w;
+            if (t > 2.5) {
+                continue;
+            }
+            float bowl = t < 1.0 ? t * t - 1.0 : 0.0;
+            float rim = exp(-pow((t - 1.0) / 0.25, 2.0)) * 0.3;
+            height += (bowl + rim) * depth;
+            tint *= t < 1.0 ? 0.85 : 1.0 + 0.2 * (1.0 - (t - 1.0) / 1.5);
+        }
+        else if (kind == 1) {
+            // Volcano, a cone of dark basalt with a caldera
+            float t = angle / center_radius.w;
+            if (t > 1.0) {
+                continue;
+            }
+            float cone = (1.0 - t) * (1.0 - t);
+            float caldera = max(1.0 - t / 0.15, 0.0) * 0.6;
+            height -= (cone - caldera) * depth;
+            tint = mix(tint, vec3(0.45, 0.4, 0.4), cone);
+        }
+        else {
+            // Canyon along a great circle, narrowing towards the ends
+            vec3 axis = axis_length.xyz;
+            vec3 tangent = cross(axis, center);
+            float across = asin(clamp(dot(direction, axis), -1.0, 1.0)) / center_radius.w;
+            float along = atan(dot(direction, tangent), dot(direction, center)) / axis_length.w;
+            if (abs(across) > 1.0 || abs(along) > 1.0) {
+                continue;
+            }
+            float u = abs(across) / max(sqrt(1.0 - along * along), 1e-3);
+            float cut = 1.0 - smoothstep(0.6, 1.0, u);
+            height -= cut * depth;
+            tint = mix(tint, vec3(0.8, 0.62, 0.5), cut);
+        }
+    }
+    return height;
+}
+
+// Tint of the ground from the stamped features, the vertex colour of patches
+vec3 terrain_tint(vec3 direction)
+{
+    vec3 tint;
+    stamped(direction, tint);
+    return tint;
+}
+
+// Planet::noise, of a direction from the planet center
+float terrain(vec3 direction)
+{
+    float gain = u_noise.gain + u_noise.gain_amplitude
+        * (perlin(direction * u_noise.gain_frequency) + u_noise.gain_offset);
+    float lacunarity = u_noise.lacunarity + u_noise.lac_amplitude
+        * (perlin(direction * u_noise.lac_frequency) + u_noise.lac_offset);
+    float sum = 0.0;
+    float amplitude = u_noise.amplitude;
+    float frequency = u_noise.frequency;
+    for (int i = 0; i < u_noise.octaves; i++) {
+        sum += perlin(direction * frequency * u_noise.size) * amplitude * u_max_height;
+        frequency *= lacunarity;
+        amplitude *= gain;
+    }
+    vec3 tint;
+    return sum + stamped(direction, tint) * u_max_height;
+}
+
+vec3 displaced(vec3 position)
+{
+    return position * (1.0 + terrain(normalize(position)));
+}
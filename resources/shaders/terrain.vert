@@ -0,0 +1,16 @@
+#version 460 core
+
+// Corners of the terrain patches on the sides of the cube, see
+// terrain_tessellation.rs. Moved onto the sphere after tessellation.
+
+layout(location = 0) in vec3 position;
+layout(location = 3) in vec2 uv;
+
+out vec3 tc_position;
+out vec2 tc_uv;
+
+void main()
+{
+    tc_position = position;
+    tc_uv = uv;
+}
@@ -0,0 +1,68 @@
+#version 460 core
+
+in vec2 v_corner;
+in vec3 v_color;
+flat in float v_kind;
+in float v_light;
+in float v_distance;
+in float v_fade;
+
+out vec4 color;
+
+uniform vec2 u_range;  // Distances drawn in this depth pass
+
+float hash(vec2 p)
+{
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// Coverage and shade of a tuft of blades
+float grass(vec2 p, out float shade)
+{
+    float cover = 0.0;
+    shade = 0.0;
+    for (int i = 0; i < 5; i++) {
+        float offset = (float(i) - 2.0) * 0.35;
+        float lean = (float(i) - 2.0) * 0.25;
+        float height = 0.6 + 0.4 * fract(float(i) * 0.618);
+        float half_width = 0.12 * (1.0 - p.y / height);
+        if (p.y < height && abs(p.x - offset - lean * p.y) < half_width) {
+            cover = 1.0;
+            shade = 0.6 + 0.4 * p.y / height;
+        }
+    }
+    return cover;
+}
+
+// Coverage and shade of a tree, a trunk under a pointed crown
+float tree(vec2 p, out float shade)
+{
+    shade = 0.0;
+    if (abs(p.x) < 0.08 && p.y < 0.3) {
+        shade = -1.0;  // Bark
+        return 1.0;
+    }
+    float t = (p.y - 0.2) / 0.8;
+    // Layers of branches, widest at the bottom
+    float outline = (1.0 - t) * (0.85 + 0.15 * sin(t * 28.0));
+    if (t > 0.0 && t < 1.0 && abs(p.x) < outline) {
+        shade = 0.55 + 0.45 * (1.0 - abs(p.x) / max(outline, 1e-3)) * (0.5 + 0.5 * t);
+        return 1.0;
+    }
+    return 0.0;
+}
+
+void main()
+{
+    // Split between the depth passes, and thinned out near the edge
+    if (v_distance < u_range.x || v_distance >= u_range.y || hash(gl_FragCoord.xy) > v_fade) {
+        discard;
+    }
+    float shade;
+    float cover = v_kind > 0.5 ? tree(v_corner, shade) : grass(v_corner, shade);
+    if (cover < 0.5) {
+        discard;
+    }
+    vec3 albedo = shade < 0.0 ? vec3(0.25, 0.17, 0.1) : v_color * shade;
+    color = vec4(albedo * v_light, 1.0);
+}
@@ -0,0 +1,47 @@
+#version 460 core
+
+layout (location = 0) in vec2 corner;         // Of the quad, -1 to 1 across and 0 to 1 up
+layout (location = 1) in vec4 position_size;  // On the planet of radius 1, and height in radii
+layout (location = 2) in vec4 color_kind;     // Colour, and 0 for grass or 1 for trees
+
+out vec2 v_corner;
+out vec3 v_color;
+flat out float v_kind;
+out float v_light;
+out float v_distance;  // From the eye
+out float v_fade;      // Towards the edge of the reach, 1 to 0
+
+uniform mat4 u_view;
+uniform mat4 u_perspective;
+uniform float u_log_depth;  // 2 / log2(far + 1) for logarithmic depth, 0 for the usual depth
+uniform vec3 u_planet_position;  // Relative to the origin
+uniform float u_planet_radius;
+uniform vec3 u_eye;              // Relative to the origin
+uniform vec3 u_sun_direction;    // From the planet, zero without a sun
+uniform float u_reach;
+
+void main()
+{
+    v_corner = corner;
+    v_color = color_kind.rgb;
+    v_kind = color_kind.a;
+    vec3 up = normalize(position_size.xyz);
+    vec3 base = u_planet_position + position_size.xyz * u_planet_radius;
+    float size = position_size.w * u_planet_radius;
+    // Standing up from the ground, turned towards the eye
+    vec3 to_eye = u_eye - base;
+    vec3 right = cross(up, to_eye);
+    right = length(right) > 1e-6 ? normalize(right) : vec3(1.0, 0.0, 0.0);
+    float width = v_kind > 0.5 ? 0.45 : 0.6;
+    vec3 world = base + right * corner.x * width * size + up * corner.y * size;
+
+    // Lit where the sun is up, dimly at night
+    v_light = length(u_sun_direction) > 0.0 ? 0.08 + 0.92 * smoothstep(-0.1, 0.3, dot(up, u_sun_direction)) : 1.0;
+    v_distance = length(world - u_eye);
+    v_fade = 1.0 - smoothstep(0.7, 1.0, length(base - u_eye) / u_reach);
+
+    gl_Position = u_perspective * u_view * vec4(world, 1.0);
+    if (u_log_depth > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w)) * u_log_depth - 1.0) * gl_Position.w;
+    }
+}
@@ -0,0 +1,128 @@
+//! Keyframe animation of node properties, like spinning station rings or
+//! pulsing beacons
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera_path::Ease;
+use crate::scene_graph::SceneNode;
+
+/// Value of a property at a time in seconds, eased towards the next key
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Key<T> {
+    pub time: f32,
+    pub value: T,
+    #[serde(default)]
+    pub ease: Ease,
+}
+
+/// Keys of one property, in order of time
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Track {
+    Position(Vec<Key<[f32; 3]>>),
+    Rotation(Vec<Key<[f32; 3]>>), // Euler angles, replacing any orientation
+    Scale(Vec<Key<[f32; 3]>>),
+    Tint(Vec<Key<[f32; 4]>>), // Multiplying the colour of geometry
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Clip {
+    pub tracks: Vec<Track>,
+    pub looping: bool, // Or held at the last keys
+}
+
+impl Default for Clip {
+    fn default() -> Self {
+        Clip {
+            tracks: vec![],
+            looping: true,
+        }
+    }
+}
+
+/// Value at `time` between the keys around it, held before the first and
+/// after the last
+fn sample<const N: usize>(keys: &[Key<[f32; N]>], time: f32) -> [f32; N] {
+    let next = keys.partition_point(|k| k.time <= time);
+    if next == 0 || next == keys.len() {
+        return keys.get(next.max(1) - 1).map_or([0.0; N], |k| k.value);
+    }
+    let (a, b) = (&keys[next - 1], &keys[next]);
+    let t = a.ease.apply((time - a.time) / (b.time - a.time));
+    std::array::from_fn(|i| a.value[i] + (b.value[i] - a.value[i]) * t)
+}
+
+impl Track {
+    fn duration(&self) -> f32 {
+        let last = match self {
+            Track::Position(keys) | Track::Rotation(keys) | Track::Scale(keys) => {
+                keys.last().map(|k| k.time)
+            }
+            Track::Tint(keys) => keys.last().map(|k| k.time),
+        };
+        last.unwrap_or(0.0)
+    }
+
+    fn apply(&self, node: &mut SceneNode, time: f32) {
+        match self {
+            Track::Position(keys) => node.set_position(sample(keys, time).into()),
+            Track::Rotation(keys) => {
+                node.set_orientation(None);
+                node.set_rotation(sample(keys, time).into());
+            }
+            Track::Scale(keys) => node.set_scale(sample(keys, time).into()),
+            Track::Tint(keys) => node.tint = sample(keys, time).into(),
+        }
+    }
+}
+
+impl Clip {
+    /// Time of the last key
+    pub fn duration(&self) -> f32 {
+        self.tracks.iter().map(Track::duration).fold(0.0, f32::max)
+    }
+}
+
+/// Clip played by a node
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub clip: Clip,
+    pub time: f32, // Seconds into the clip
+    pub playing: bool,
+}
+
+impl Animation {
+    pub fn new(clip: Clip) -> Self {
+        Animation {
+            clip,
+            time: 0.0,
+            playing: true,
+        }
+    }
+}
+
+/// Advance the animations of `node` and the nodes below it by `delta_time`
+/// seconds, and set the properties they animate
+pub fn animate(node: &mut SceneNode, delta_time: f32) {
+    if let Some(mut animation) = node.animation.take() {
+        if animation.playing {
+            let duration = animation.clip.duration();
+            animation.time += delta_time;
+            if animation.time >= duration {
+                if animation.clip.looping && duration > 0.0 {
+                    animation.time %= duration;
+                } else {
+                    animation.time = duration;
+                    animation.playing = false;
+                }
+            }
+            for track in &animation.clip.tracks {
+                track.apply(node, animation.time);
+            }
+        }
+        node.animation = Some(animation);
+    }
+    for &child in &node.children {
+        animate(unsafe { &mut *child }, delta_time);
+    }
+}
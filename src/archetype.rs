@@ -0,0 +1,159 @@
+//! Presets for kinds of planets, picked per body in scene files
+
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+use crate::globals::N_LAYERS;
+use crate::procedural_planet::{NoiseParams, Planet};
+
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+pub enum Archetype {
+    #[default]
+    Terrestrial, // Continents and oceans, clouds and plants
+    OceanWorld,  // Low sandy islands between wide seas, under heavy clouds
+    GasGiant,    // Smooth, in bands of colour by latitude
+    IceWorld,    // Frozen plains and glossy glaciers
+    LavaWorld,   // Dark basalt over glowing molten lowlands
+    BarrenMoon,  // Grey and airless, covered in craters
+}
+
+fn palette(colors: [[f32; 3]; N_LAYERS]) -> [glm::Vec3; N_LAYERS] {
+    colors.map(|c| glm::vec3(c[0], c[1], c[2]))
+}
+
+impl Archetype {
+    /// Name for showing on screen
+    pub fn label(self) -> &'static str {
+        match self {
+            Archetype::Terrestrial => "terrestrial",
+            Archetype::OceanWorld => "ocean world",
+            Archetype::GasGiant => "gas giant",
+            Archetype::IceWorld => "ice world",
+            Archetype::LavaWorld => "lava world",
+            Archetype::BarrenMoon => "barren moon",
+        }
+    }
+
+    /// Set up `planet` as this kind of planet
+    pub fn apply(self, planet: &mut Planet) {
+        planet.archetype = self;
+        planet.bands = 0.0;
+        planet.glow = 0.0;
+        planet.oblateness = 0.0;
+        planet.has_clouds = false;
+        planet.vegetation = 0.0;
+        planet.surface_features = true;
+        // Thresholds in mountain heights
+        let thresholds: [f32; N_LAYERS - 1];
+        match self {
+            Archetype::Terrestrial => {
+                planet.max_height = 0.03;
+                planet.noise = NoiseParams { size: 25.0, ..Default::default() };
+                planet.color_scheme = palette([
+                    [0.4, 0.4, 0.3],
+                    [0.7, 0.55, 0.0],
+                    [0.2, 0.6, 0.4],
+                    [0.5, 0.4, 0.4],
+                    [0.91, 1.0, 1.0],
+                ]);
+                thresholds = [-0.02, 0.03, 0.6, 0.75];
+                planet.has_ocean = true;
+                planet.ocean_dark_color = glm::vec3(0.001, 0.03, 0.01);
+                planet.ocean_light_color = glm::vec3(0.04, 0.37, 0.33);
+                planet.has_clouds = true;
+                planet.cloud_coverage = 0.5;
+                planet.vegetation = 1.0;
+            }
+            Archetype::OceanWorld => {
+                planet.max_height = 0.015;
+                planet.noise = NoiseParams { size: 12.0, octaves: 5, ..Default::default() };
+                planet.color_scheme = palette([
+                    [0.76, 0.7, 0.5],
+                    [0.85, 0.8, 0.6],
+                    [0.25, 0.55, 0.3],
+                    [0.4, 0.45, 0.35],
+                    [0.95, 0.97, 1.0],
+                ]);
+                thresholds = [0.2, 0.3, 0.5, 0.9];
+                planet.has_ocean = true;
+                planet.ocean_dark_color = glm::vec3(0.0, 0.05, 0.2);
+                planet.ocean_light_color = glm::vec3(0.02, 0.25, 0.5);
+                planet.has_clouds = true;
+                planet.cloud_coverage = 0.65;
+                planet.cloud_density = 0.9;
+                planet.vegetation = 0.5;
+            }
+            Archetype::GasGiant => {
+                planet.max_height = 0.0005;
+                planet.noise = NoiseParams { size: 3.0, octaves: 2, ..Default::default() };
+                planet.color_scheme = palette([
+                    [0.85, 0.75, 0.6],
+                    [0.7, 0.5, 0.35],
+                    [0.9, 0.85, 0.75],
+                    [0.6, 0.4, 0.3],
+                    [0.8, 0.65, 0.5],
+                ]);
+                thresholds = [-0.5, 0.0, 0.5, 2.0];
+                planet.has_ocean = false;
+                planet.surface_features = false;
+                planet.detail_strength = 0.0;
+                planet.texture_strength = 0.0;
+                planet.bands = 12.0;
+                planet.oblateness = 0.06;
+                planet.cloud_wind = 0.02;
+            }
+            Archetype::IceWorld => {
+                planet.max_height = 0.02;
+                planet.noise = NoiseParams {
+                    size: 8.0,
+                    gain: 0.45,
+                    lac_amplitude: 0.2,
+                    ..Default::default()
+                };
+                planet.color_scheme = palette([
+                    [0.55, 0.65, 0.75],
+                    [0.7, 0.8, 0.88],
+                    [0.82, 0.88, 0.93],
+                    [0.6, 0.62, 0.66],
+                    [0.95, 0.98, 1.0],
+                ]);
+                thresholds = [-0.3, 0.0, 0.4, 0.55];
+                planet.has_ocean = false;
+                planet.texture_strength = 0.15;
+            }
+            Archetype::LavaWorld => {
+                planet.max_height = 0.04;
+                planet.noise = NoiseParams {
+                    size: 15.0,
+                    gain: 0.55,
+                    lacunarity: 2.2,
+                    ..Default::default()
+                };
+                planet.color_scheme = palette([
+                    [1.0, 0.35, 0.05],
+                    [0.25, 0.1, 0.08],
+                    [0.15, 0.13, 0.12],
+                    [0.3, 0.25, 0.22],
+                    [0.45, 0.4, 0.38],
+                ]);
+                thresholds = [-0.1, 0.0, 0.5, 0.8];
+                planet.has_ocean = false;
+                planet.glow = 2.0;
+            }
+            Archetype::BarrenMoon => {
+                planet.max_height = 0.09;
+                planet.noise = NoiseParams { size: 5.4, ..Default::default() };
+                planet.color_scheme = palette([
+                    [0.35, 0.35, 0.35],
+                    [0.45, 0.44, 0.43],
+                    [0.55, 0.54, 0.52],
+                    [0.62, 0.6, 0.58],
+                    [0.75, 0.74, 0.72],
+                ]);
+                thresholds = [-0.3, -0.1, 0.1, 0.4];
+                planet.has_ocean = false;
+            }
+        }
+        planet.color_thresholds = thresholds.map(|t| t * planet.max_height);
+    }
+}
@@ -0,0 +1,150 @@
+//! Panel in the upper left corner with facts about the selected body
+
+use nalgebra_glm as glm;
+
+use crate::globals::{BODY_INFO_SAMPLES, N_LAYERS, WORLD_SPEED};
+use crate::procedural_planet::Planet;
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+use crate::simulation;
+use crate::text::{self, Font, Text};
+
+const TEXT_SCALE: f32 = 0.6;
+const N_LINES: usize = 6;
+const UPDATE_INTERVAL: f32 = 0.25; // Seconds between text updates
+
+/// Facts about a planet that its `Planet` doesn't keep, on its scene node
+#[derive(Clone, Debug, Default)]
+pub struct BodyMetadata {
+    pub name: String,
+    pub surface: Option<[f32; N_LAYERS + 1]>, // Shares under the ocean and in each colour layer, None until sampled
+}
+
+impl BodyMetadata {
+    pub fn named(name: &str) -> Self {
+        BodyMetadata {
+            name: name.to_string(),
+            surface: None,
+        }
+    }
+}
+
+/// Name of planet `i`, from the metadata on its node if it has one
+pub fn name(planet_nodes: &[Node], i: usize) -> String {
+    match planet_nodes[i].body.as_ref() {
+        Some(body) if !body.name.is_empty() => body.name.clone(),
+        _ => format!("Planet {}", i),
+    }
+}
+
+/// Terrain patches with a mesh on the GPU below `node`, and their vertices
+fn count_patches(node: &SceneNode) -> (usize, usize) {
+    let mut counts = match node.node_type {
+        SceneNodeType::Planet | SceneNodeType::PlanetSkip if node.index_count > 0 => (1, node.vao.vertices),
+        _ => (0, 0),
+    };
+    for &child in &node.children {
+        let (patches, vertices) = count_patches(unsafe { &*child });
+        counts.0 += patches;
+        counts.1 += vertices;
+    }
+    counts
+}
+
+pub struct BodyInfo {
+    pub root: Node,
+    lines: Vec<Text>,
+    shown: Option<usize>, // Planet the text is about
+    time: f32,            // Since the text was updated
+}
+
+impl BodyInfo {
+    pub fn new(font: &Font) -> Self {
+        let mut root = SceneNode::new();
+        let lines = (0..N_LINES)
+            .map(|i| {
+                let anchor = glm::vec2(-1.0, 1.0 - TEXT_SCALE * text::LINE_HEIGHT * (i + 1) as f32);
+                let line = Text::new(font, "", anchor, TEXT_SCALE);
+                root.add_child(&line.node);
+                line
+            })
+            .collect();
+        BodyInfo {
+            root,
+            lines,
+            shown: None,
+            time: 0.0,
+        }
+    }
+
+    /// Whether there is anything to draw
+    pub fn visible(&self) -> bool {
+        self.shown.is_some()
+    }
+
+    /// Show the facts about planet `selected`, or nothing if None. Samples
+    /// the surface of the planet if it hasn't been yet.
+    pub fn update(&mut self, selected: Option<usize>, planets: &[Planet], planet_nodes: &mut [Node], delta_time: f32) {
+        self.time += delta_time;
+        if selected == self.shown && self.time < UPDATE_INTERVAL {
+            return;
+        }
+        self.time = 0.0;
+        self.shown = selected;
+        let Some(i) = selected else {
+            for line in &mut self.lines {
+                line.set_text("");
+            }
+            return;
+        };
+        let planet = &planets[i];
+        let body = planet_nodes[i].body.get_or_insert_with(BodyMetadata::default);
+        let surface = *body.surface.get_or_insert_with(|| planet.surface_shares(BODY_INFO_SAMPLES));
+
+        let orbit = if planet.parent_id == planet.planet_id {
+            String::from("Orbits nothing")
+        } else {
+            let speed = (planet.traj_speed * WORLD_SPEED).abs();
+            let period = if speed > 0.0 {
+                simulation::format_duration(std::f64::consts::TAU / speed as f64)
+            } else {
+                String::from("none")
+            };
+            format!(
+                "Orbits {} at {:.1}, period {}",
+                name(planet_nodes, planet.parent_id),
+                planet.trajectory,
+                period
+            )
+        };
+        let mut surface_text = String::from("Surface:");
+        if planet.has_ocean {
+            surface_text += &format!(" ocean {:.0}%,", surface[0] * 100.0);
+        }
+        surface_text += " layers";
+        for share in &surface[1..] {
+            surface_text += &format!(" {:.0}%", share * 100.0);
+        }
+        let (patches, vertices) = count_patches(&planet_nodes[i]);
+
+        let text = [
+            format!(
+                "{} ({})",
+                name(planet_nodes, i),
+                if planet.lightsource { "light source" } else { planet.archetype.label() }
+            ),
+            format!(
+                "Radius {:.2}, mountains {:.1}%, gravity {:.2}",
+                planet.radius,
+                planet.max_height * 100.0,
+                planet.gravity
+            ),
+            orbit,
+            format!("Seed {}", planet.seed),
+            surface_text,
+            format!("Terrain: {} patches, {} vertices, max LoD {}", patches, vertices, planet.max_lod),
+        ];
+        for (line, s) in self.lines.iter_mut().zip(text.iter()) {
+            line.set_text(s);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Camera poses saved to numbered slots, and flown back to
+
+use std::path::Path;
+
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{Cameras, View};
+use crate::camera_path::Ease;
+use crate::globals::BOOKMARK_SLOTS;
+use crate::player::Player;
+use crate::procedural_planet::Planet;
+use crate::util::CameraPosition;
+
+/// Camera pose relative to a planet
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Bookmark {
+    pub planet: usize,       // Closest planet when saved
+    pub offset: [f32; 3],    // Of the camera from its center, in world units
+    pub direction: [f32; 3], // Looked in
+}
+
+/// Flying from where the camera was to a bookmark
+#[derive(Debug, Copy, Clone)]
+struct Flight {
+    to: Bookmark,
+    from: Option<(glm::Vec3, glm::Vec3)>, // Position and direction, once flying freely
+    elapsed: f32,
+    duration: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Bookmarks {
+    pub slots: [Option<Bookmark>; BOOKMARK_SLOTS],
+    #[serde(skip)]
+    path: String,
+    #[serde(skip)]
+    flight: Option<Flight>,
+}
+
+fn vec3(v: [f32; 3]) -> glm::Vec3 {
+    glm::vec3(v[0], v[1], v[2])
+}
+
+impl Bookmark {
+    /// Looking at `planet` from the side `eye` is on, as close as it fills
+    /// `fill` of the height of a view `fov` radians high. The distance only
+    /// follows from its bounding sphere, mountains included. Like positions,
+    /// its radius is `unit_scale` times larger while anchored to a planet.
+    pub fn facing(planet: &Planet, eye: &glm::Vec3, fill: f32, fov: f32, unit_scale: f32) -> Self {
+        let bounds = planet.radius * (1.0 + planet.max_height);
+        let half_angle = (fill.clamp(0.01, 1.0) * fov / 2.0).min(std::f32::consts::FRAC_PI_2);
+        let distance = bounds / half_angle.sin() / unit_scale;
+        let from = eye - planet.position;
+        let outward = if glm::length2(&from) > 0.0 {
+            glm::normalize(&from)
+        } else {
+            glm::vec3(0.0, 0.0, 1.0)
+        };
+        let offset = outward * distance;
+        Bookmark {
+            planet: planet.planet_id,
+            offset: [offset.x, offset.y, offset.z],
+            direction: [-outward.x, -outward.y, -outward.z],
+        }
+    }
+}
+
+impl Bookmarks {
+    /// Bookmarks saved to the RON file at `path`, none if there is no such
+    /// file yet
+    pub fn load(path: &str) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(_) => return Bookmarks { path: path.to_string(), ..Default::default() },
+        };
+        let mut bookmarks: Bookmarks = ron::from_str(&text).unwrap_or_else(|e| {
+            eprintln!("Ignoring bookmarks in {}: {}", path, e);
+            Bookmarks::default()
+        });
+        bookmarks.path = path.to_string();
+        bookmarks
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())?;
+        if let Some(dir) = Path::new(&self.path).parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", self.path, e))?;
+        }
+        std::fs::write(&self.path, text).map_err(|e| format!("{}: {}", self.path, e))
+    }
+
+    /// Save `view` to `slot`, relative to the player's closest planet.
+    /// Distances are `unit_scale` times larger while anchored to a planet.
+    pub fn store(&mut self, slot: usize, view: &View, player: &Player, planets: &[Planet], unit_scale: f32) {
+        let Some(planet) = planets.get(player.closest_planet_id) else {
+            return;
+        };
+        let offset = (view.eye - planet.position) / unit_scale;
+        self.slots[slot] = Some(Bookmark {
+            planet: player.closest_planet_id,
+            offset: [offset.x, offset.y, offset.z],
+            direction: [view.direction.x, view.direction.y, view.direction.z],
+        });
+        match self.save() {
+            Ok(()) => eprintln!("Saved bookmark {} to {}", slot + 1, self.path),
+            Err(e) => eprintln!("Failed to save bookmark {}", e),
+        }
+    }
+
+    /// Start flying to the bookmark in `slot`, taking `duration` seconds
+    pub fn go_to(&mut self, slot: usize, duration: f32) {
+        match self.slots[slot] {
+            Some(to) => self.fly_to(to, duration),
+            None => eprintln!("No bookmark {}, save one with Ctrl+{}", slot + 1, slot + 1),
+        }
+    }
+
+    /// Start flying to `to`, taking `duration` seconds
+    pub fn fly_to(&mut self, to: Bookmark, duration: f32) {
+        self.flight = Some(Flight { to, from: None, elapsed: 0.0, duration });
+    }
+
+    /// Move the player towards the bookmark being flown to, in free fly mode.
+    /// Positions are only in world units when `scaled`, so coming from a
+    /// planet the flight starts on the next frame, once out of its space.
+    pub fn update(
+        &mut self,
+        player: &mut Player,
+        cameras: &mut Cameras,
+        planets: &[Planet],
+        scaled: bool,
+        delta_time: f32,
+    ) {
+        let Some(flight) = self.flight.as_mut() else {
+            return;
+        };
+        let Some(planet) = planets.get(flight.to.planet) else {
+            // Gone with a reloaded scene
+            self.flight = None;
+            return;
+        };
+        if cameras.active().mode() != CameraPosition::FreeFly {
+            let closest = &planets[player.closest_planet_id.min(planets.len() - 1)];
+            cameras.select(CameraPosition::FreeFly, player, closest);
+        }
+        if !scaled {
+            return;
+        }
+        let (position, direction) = *flight.from.get_or_insert((player.position, player.direction));
+        flight.elapsed += delta_time;
+        let t = if flight.duration > 0.0 { (flight.elapsed / flight.duration).min(1.0) } else { 1.0 };
+        let eased = Ease::InOut.apply(t);
+
+        let target = planet.position + vec3(flight.to.offset);
+        let look = glm::normalize(&vec3(flight.to.direction));
+        player.position = glm::lerp(&position, &target, eased);
+        let turned = glm::lerp(&direction, &look, eased);
+        // Through nothing halfway, when turning right round
+        player.direction = if glm::length2(&turned) > 1e-6 { glm::normalize(&turned) } else { look };
+        player.velocity = glm::zero();
+        if t >= 1.0 {
+            self.flight = None;
+        }
+    }
+}
@@ -0,0 +1,678 @@
+use nalgebra_glm as glm;
+// Camera controllers
+// - Turn mouse and keyboard input into player movement, and place the camera
+
+use crate::globals::*;
+use crate::player::{Player, PlayerState};
+use crate::procedural_planet::Planet;
+use crate::ship::Ship;
+use crate::util::{damp, damp_quat, CameraPosition, Config};
+
+/// Where the camera is and where it looks
+#[derive(Debug, Copy, Clone)]
+pub struct View {
+    pub eye: glm::Vec3,
+    pub direction: glm::Vec3,
+    pub up: glm::Vec3,
+}
+
+impl View {
+    /// Looking from the player's eyes
+    pub fn first_person(player: &mut Player) -> Self {
+        View {
+            eye: player.position,
+            direction: player.direction,
+            up: player.up(),
+        }
+    }
+
+    /// Looking down at `planet` from `distance` radii above where the player
+    /// is over it, with the way the player looks up on screen. The player is
+    /// not moved, so it can be drawn next to their own view.
+    pub fn locked_to(planet: &Planet, player: &Player, distance: f32) -> Self {
+        let offset = player.position - planet.position;
+        let outward = if glm::length2(&offset) > 0.0 {
+            glm::normalize(&offset)
+        } else {
+            glm::vec3(0.0, 0.0, 1.0)
+        };
+        let heading = player.direction - outward * glm::dot(&player.direction, &outward);
+        let up = if glm::length2(&heading) > 1e-6 {
+            glm::normalize(&heading)
+        } else {
+            // Looking straight down as well
+            glm::cross(&player.right, &player.direction)
+        };
+        View {
+            eye: planet.position + outward * planet.radius * distance,
+            direction: -outward,
+            up,
+        }
+    }
+
+    /// The same view, with `origin` moved to the world origin
+    pub fn relative_to(&self, origin: &glm::Vec3) -> Self {
+        View { eye: self.eye - origin, ..*self }
+    }
+
+    pub fn matrix(&self) -> glm::Mat4 {
+        glm::look_at(&self.eye, &(self.eye + self.direction), &self.up)
+    }
+}
+
+/// Side by side rectangles splitting a window of `window_size` between
+/// `count` views, each as x, y, width and height in pixels
+pub fn split(window_size: (u32, u32), count: usize) -> Vec<(i32, i32, u32, u32)> {
+    let width = (window_size.0 / count.max(1) as u32).max(1);
+    (0..count)
+        .map(|i| ((i as u32 * width) as i32, 0, width, window_size.1.max(1)))
+        .collect()
+}
+
+/// Movement held this frame along the player's axes, each from -1 to 1
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Movement {
+    pub right: f32,
+    pub forward: f32,
+    pub up: f32,
+    pub roll: f32, // Right wing down, when flying the ship
+}
+
+/// Moves the player and places the camera in one camera mode
+pub trait CameraController {
+    fn mode(&self) -> CameraPosition;
+
+    /// Set the player state this mode moves in, when switching to it
+    fn enter(&mut self, player: &mut Player, planet: &Planet);
+
+    /// Turn by mouse movement, in radians. Vertical first, so looking up and
+    /// down stays relative to the planet's surface.
+    fn look(&mut self, player: &mut Player, delta_h: f32, delta_v: f32) {
+        let up = player.up();
+        player.direction = glm::rotate_vec3(&player.direction, -delta_v, &player.right);
+        player.direction = glm::rotate_vec3(&player.direction, -delta_h, &up);
+    }
+
+    /// Move the player, `planet` being the closest one
+    fn step(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        planet: &Planet,
+        conf: &Config,
+        delta_time: f32,
+    );
+
+    fn view(&self, player: &mut Player) -> View {
+        View::first_person(player)
+    }
+
+    /// Only turn while the right mouse button is held
+    fn drag_to_look(&self) -> bool {
+        false
+    }
+
+    /// Zoom by mouse wheel movement
+    fn zoom(&mut self, _scroll: f32) {}
+
+    /// Follow the planets as they move, and the one selected by clicking
+    fn track(&mut self, _planets: &[Planet], _selected: Option<usize>) {}
+
+    /// The ship flown in this mode, once launched
+    fn ship(&self) -> Option<&Ship> {
+        None
+    }
+
+    /// Move what is kept in scene coordinates along with the scene, when its
+    /// root moves from `from` to `to` and distances are scaled by `scale`
+    fn rebase(&mut self, _from: &glm::Vec3, _to: &glm::Vec3, _scale: f32) {}
+}
+
+/// Speed up towards moving along `wish`, at full speed for a length of 1 or
+/// more, or slow down when nothing is held. Returns how far to move this frame.
+fn accelerate(player: &mut Player, wish: glm::Vec3, conf: &Config, delta_time: f32) -> glm::Vec3 {
+    let (target, rate) = if glm::length2(&wish) > 0.0 {
+        (wish / glm::length(&wish).max(1.0) * player.speed, conf.acceleration)
+    } else {
+        (glm::zero(), conf.damping)
+    };
+    player.velocity = damp(player.velocity, target, rate, delta_time);
+    player.velocity * delta_time
+}
+
+/// Anchor to the closest planet, moving the origin to its center
+fn anchor(player: &mut Player, planet: &Planet) -> glm::Vec3 {
+    if let PlayerState::FreeFloat = player.state {
+        eprintln!("Player position is {:?}", player.position);
+        eprintln!("New origin is {:?}", planet.position);
+        player.position -= planet.position;
+        eprintln!("New player position is {:?}", player.position);
+    }
+    glm::vec3(0.0, 0.0, 0.0)
+}
+
+/// Move the player's feet by `offset`, without going below the terrain of
+/// the closest planet, and stick to the ground when anchored to it
+fn walk(player: &mut Player, offset: glm::Vec3, planet: &Planet, delta_time: f32) {
+    let up = player.up();
+    let mut feet = player.feet();
+    let mut position = feet + offset;
+    if matches!(player.state, PlayerState::Landed(_)) {
+        // Apply gravitational pull
+        position += up * player.hspeed;
+        if player.hspeed > -MAX_H_SPEED {
+            player.hspeed -= delta_time * planet.gravity;
+        }
+    }
+    let height = (100.0 * planet.get_height(&position)).round() / 100.0;
+    let go_to = (100.0 * glm::length(&(position - planet.position))).round() / 100.0;
+    if go_to >= height {
+        feet = position;
+    } else if !matches!(player.state, PlayerState::FreeFloat) {
+        // Stick to the ground
+        feet = glm::normalize(&position) * height;
+    }
+    player.position = feet + up * player.height;
+}
+
+/// Walking on the surface, up and forward follow the curve of the planet
+fn surface_direction(player: &mut Player, movement: &Movement) -> glm::Vec3 {
+    let up = player.up();
+    let flat_direction = glm::cross(&up, &player.right);
+    player.right * movement.right + flat_direction * movement.forward + up * movement.up
+}
+
+/// Jump if standing on the ground
+fn jump(player: &mut Player, planet: &Planet, jump_speed: f32) {
+    let planet_h = planet.get_height(&player.feet());
+    let player_h = glm::length(&(player.feet() - planet.position));
+    // Not quite right, but jetpack physics is alright as well
+    if planet_h - player_h < H_ERROR {
+        player.hspeed = jump_speed;
+    }
+}
+
+/// Flying freely in the direction looked at, with the world's y axis up
+pub struct FreeFly;
+
+impl CameraController for FreeFly {
+    fn mode(&self) -> CameraPosition {
+        CameraPosition::FreeFly
+    }
+
+    fn enter(&mut self, player: &mut Player, _planet: &Planet) {
+        player.state = PlayerState::FreeFloat;
+    }
+
+    /// Horizontal first, turning around the world's y axis
+    fn look(&mut self, player: &mut Player, delta_h: f32, delta_v: f32) {
+        let up = player.up();
+        player.direction = glm::rotate_vec3(&player.direction, -delta_h, &up);
+        player.direction = glm::rotate_vec3(&player.direction, -delta_v, &player.right);
+    }
+
+    fn step(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        planet: &Planet,
+        conf: &Config,
+        delta_time: f32,
+    ) {
+        let up = player.up();
+        let wish = player.right * movement.right
+            + player.direction * movement.forward
+            + up * movement.up;
+        let offset = accelerate(player, wish, conf, delta_time);
+        walk(player, offset, planet, delta_time);
+    }
+}
+
+/// Flying around the closest planet, with up pointing away from its center
+pub struct Orbital;
+
+impl CameraController for Orbital {
+    fn mode(&self) -> CameraPosition {
+        CameraPosition::Orbital
+    }
+
+    fn enter(&mut self, player: &mut Player, planet: &Planet) {
+        player.state = PlayerState::Anchored(anchor(player, planet));
+    }
+
+    fn step(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        planet: &Planet,
+        conf: &Config,
+        delta_time: f32,
+    ) {
+        let wish = surface_direction(player, movement);
+        let offset = accelerate(player, wish, conf, delta_time);
+        walk(player, offset, planet, delta_time);
+    }
+}
+
+/// Walking on the surface of the closest planet, pulled down by its gravity
+pub struct FirstPerson;
+
+impl CameraController for FirstPerson {
+    fn mode(&self) -> CameraPosition {
+        CameraPosition::FirstPerson
+    }
+
+    fn enter(&mut self, player: &mut Player, planet: &Planet) {
+        player.state = PlayerState::Landed(anchor(player, planet));
+    }
+
+    fn step(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        planet: &Planet,
+        conf: &Config,
+        delta_time: f32,
+    ) {
+        // Up jumps instead of flying
+        if movement.up > 0.0 {
+            jump(player, planet, conf.jump_speed);
+        }
+        let movement = Movement { up: movement.up.min(0.0), ..*movement };
+        let wish = surface_direction(player, &movement);
+        let offset = accelerate(player, wish, conf, delta_time);
+        walk(player, offset, planet, delta_time);
+    }
+}
+
+/// Walking like first person, seen from behind and above
+pub struct ThirdPerson;
+
+impl CameraController for ThirdPerson {
+    fn mode(&self) -> CameraPosition {
+        CameraPosition::ThirdPerson
+    }
+
+    fn enter(&mut self, player: &mut Player, planet: &Planet) {
+        FirstPerson.enter(player, planet);
+    }
+
+    fn step(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        planet: &Planet,
+        conf: &Config,
+        delta_time: f32,
+    ) {
+        FirstPerson.step(player, movement, planet, conf, delta_time);
+    }
+
+    fn view(&self, player: &mut Player) -> View {
+        let up = player.up();
+        let eye = player.position
+            + (up * THIRD_PERSON_HEIGHT - player.direction * THIRD_PERSON_DISTANCE)
+                * player.height;
+        View {
+            eye,
+            direction: glm::normalize(&(player.position - eye)),
+            up,
+        }
+    }
+}
+
+/// Orbiting a body, the selected one or the closest when switching to it.
+/// Turned by dragging with the right mouse button or with A/D and
+/// space/shift, zoomed with the wheel or W/S, easing towards where it's
+/// dragged to.
+#[derive(Default)]
+pub struct OrbitSelected {
+    target: usize,
+    center: glm::Vec3,       // Position of the target
+    target_radius: f32,
+    radius: f32,             // Eased towards the target's radius
+    focus_offset: glm::Vec3, // From the target, eased to zero after switching targets
+    current: glm::Vec3,      // Yaw, pitch and distance in radii
+    goal: glm::Vec3,         // Where the camera is eased towards
+}
+
+impl OrbitSelected {
+    fn clamp(&mut self) {
+        self.goal.y = self.goal.y.clamp(-ORBIT_MAX_PITCH, ORBIT_MAX_PITCH);
+        self.goal.z = self.goal.z.clamp(ORBIT_MIN_DISTANCE, ORBIT_MAX_DISTANCE);
+    }
+}
+
+impl CameraController for OrbitSelected {
+    fn mode(&self) -> CameraPosition {
+        CameraPosition::OrbitSelected
+    }
+
+    /// Start from where the player is, around the closest planet
+    fn enter(&mut self, player: &mut Player, planet: &Planet) {
+        self.target = planet.planet_id;
+        self.center = planet.position;
+        self.target_radius = planet.radius;
+        self.radius = planet.radius;
+        self.focus_offset = glm::zero();
+        let offset = player.position - planet.position;
+        let distance = glm::length(&offset).max(f32::EPSILON);
+        self.goal = glm::vec3(
+            offset.x.atan2(offset.z),
+            (offset.y / distance).asin(),
+            distance / planet.radius,
+        );
+        self.clamp();
+        self.current = self.goal;
+        player.state = PlayerState::FreeFloat;
+    }
+
+    fn look(&mut self, _player: &mut Player, delta_h: f32, delta_v: f32) {
+        self.goal.x -= delta_h;
+        self.goal.y += delta_v;
+        self.clamp();
+    }
+
+    fn step(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        _planet: &Planet,
+        _conf: &Config,
+        delta_time: f32,
+    ) {
+        self.goal.x -= movement.right * delta_time;
+        self.goal.y += movement.up * delta_time;
+        self.goal.z *= (-movement.forward * delta_time).exp();
+        self.clamp();
+
+        self.current = damp(self.current, self.goal, ORBIT_DAMPING, delta_time);
+        self.radius = damp(self.radius, self.target_radius, ORBIT_DAMPING, delta_time);
+        self.focus_offset = damp(self.focus_offset, glm::zero(), ORBIT_DAMPING, delta_time);
+
+        let (yaw, pitch, distance) = (self.current.x, self.current.y, self.current.z);
+        let outward = glm::vec3(yaw.sin() * pitch.cos(), pitch.sin(), yaw.cos() * pitch.cos());
+        player.position = self.center + self.focus_offset + outward * distance * self.radius;
+        player.direction = -outward;
+    }
+
+    fn drag_to_look(&self) -> bool {
+        true
+    }
+
+    fn zoom(&mut self, scroll: f32) {
+        self.goal.z *= (-scroll * ORBIT_ZOOM_SPEED).exp();
+        self.clamp();
+    }
+
+    fn track(&mut self, planets: &[Planet], selected: Option<usize>) {
+        if let Some(id) = selected.filter(|&id| id != self.target) {
+            // Glide over from the previous target
+            let focus = self.center + self.focus_offset;
+            self.target = id;
+            self.focus_offset = focus - planets[id].position;
+        }
+        self.center = planets[self.target].position;
+        self.target_radius = planets[self.target].radius;
+    }
+}
+
+/// Flying the ship, seen from behind and above it. The ship is launched
+/// where the player is when first switching to it, and is parked where it
+/// was left when switching away, moving along with the scene.
+#[derive(Default)]
+pub struct ShipChase {
+    ship: Option<Ship>,
+    eye: glm::Vec3, // Eased towards behind the ship
+    up: glm::Vec3,
+    steps: usize, // Since switching to it
+}
+
+impl CameraController for ShipChase {
+    fn mode(&self) -> CameraPosition {
+        CameraPosition::Ship
+    }
+
+    fn enter(&mut self, player: &mut Player, _planet: &Planet) {
+        player.state = PlayerState::FreeFloat;
+        self.steps = 0;
+    }
+
+    fn look(&mut self, _player: &mut Player, delta_h: f32, delta_v: f32) {
+        if let Some(ship) = self.ship.as_mut() {
+            ship.look(delta_h, delta_v);
+        }
+    }
+
+    fn step(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        planet: &Planet,
+        _conf: &Config,
+        delta_time: f32,
+    ) {
+        self.steps += 1;
+        if self.steps == 1 {
+            // Coming from a planet, the player is moved back out of its
+            // scaled space before the next step
+            return;
+        }
+        let ship = self
+            .ship
+            .get_or_insert_with(|| Ship::new(player.position, player.direction));
+        ship.fly(movement, planet, player.speed, delta_time);
+
+        let behind = ship.position
+            + (ship.up() * SHIP_CHASE_HEIGHT - ship.forward() * SHIP_CHASE_DISTANCE) * SHIP_LENGTH;
+        if self.steps == 2 {
+            // Right behind it at once
+            (self.eye, self.up) = (behind, ship.up());
+        }
+        self.eye = damp(self.eye, behind, SHIP_CHASE_DAMPING, delta_time);
+        self.up = glm::normalize(&damp(self.up, ship.up(), SHIP_CHASE_DAMPING, delta_time));
+        // The player rides along, for the closest planet and terrain detail
+        player.position = ship.position;
+        player.direction = ship.forward();
+        player.velocity = ship.velocity;
+    }
+
+    /// Looking past the ship, a little ahead of its nose
+    fn view(&self, player: &mut Player) -> View {
+        match &self.ship {
+            Some(ship) => View {
+                eye: self.eye,
+                direction: glm::normalize(&(ship.position + ship.forward() * SHIP_LENGTH - self.eye)),
+                up: self.up,
+            },
+            None => View::first_person(player),
+        }
+    }
+
+    fn ship(&self) -> Option<&Ship> {
+        self.ship.as_ref()
+    }
+
+    fn rebase(&mut self, from: &glm::Vec3, to: &glm::Vec3, scale: f32) {
+        if let Some(ship) = self.ship.as_mut() {
+            ship.rebase(from, to, scale);
+        }
+        self.eye = to + (self.eye - from) * scale;
+    }
+}
+
+/// The camera controllers, cycled through at runtime
+pub struct Cameras {
+    controllers: Vec<Box<dyn CameraController>>,
+    active: usize,
+    look: glm::Vec2, // Mouse look not yet applied, when smoothing it
+    bank: glm::Quat, // Roll into turns while flying, around the view direction
+    landed_from: Option<usize>, // Flying camera landed from, to take off into
+    armed: bool,                // Lands when flying down, once high enough
+    climb: f32,                 // Seconds jump has been held, while landed
+}
+
+impl Default for Cameras {
+    fn default() -> Self {
+        Cameras {
+            controllers: vec![
+                Box::new(FreeFly),
+                Box::new(Orbital),
+                Box::new(FirstPerson),
+                Box::new(ThirdPerson),
+                Box::new(OrbitSelected::default()),
+                Box::new(ShipChase::default()),
+            ],
+            active: 0,
+            look: glm::zero(),
+            bank: glm::quat_identity(),
+            landed_from: None,
+            armed: true,
+            climb: 0.0,
+        }
+    }
+}
+
+impl Cameras {
+    pub fn active(&mut self) -> &mut dyn CameraController {
+        self.controllers[self.active].as_mut()
+    }
+
+    pub fn select(&mut self, mode: CameraPosition, player: &mut Player, planet: &Planet) {
+        if let Some(i) = self.controllers.iter().position(|c| c.mode() == mode) {
+            self.active = i;
+            self.controllers[i].enter(player, planet);
+        }
+        self.landed_from = None;
+    }
+
+    /// Flying rather than walking, so the speed follows the altitude
+    pub fn flying(&mut self) -> bool {
+        matches!(
+            self.active().mode(),
+            CameraPosition::FreeFly | CameraPosition::Orbital | CameraPosition::Ship
+        )
+    }
+
+    /// Speed to move at this frame, `movement_speed` unless flying with
+    /// `auto_speed`. Then it is the height over the ground of `planet`, the
+    /// closest one, times `auto_speed_rate` per second, within
+    /// `auto_speed_min` and `auto_speed_max`, slowing down near a surface and
+    /// speeding up out between the planets.
+    pub fn speed(&mut self, player: &Player, planet: &Planet, conf: &Config) -> f32 {
+        if !conf.auto_speed || !self.flying() {
+            return conf.movement_speed;
+        }
+        let altitude = glm::distance(&player.position, &planet.position) - planet.get_height(&player.position);
+        (altitude * conf.auto_speed_rate).clamp(conf.auto_speed_min, conf.auto_speed_max.max(conf.auto_speed_min))
+    }
+
+    /// Land on `planet`, the closest one, when flying down within
+    /// `landing_altitude` radii of its ground, walking on it in first person.
+    /// Holding jump for `TAKEOFF_HOLD` seconds takes off into the camera
+    /// landed from, which only lands again after climbing out of that
+    /// altitude.
+    pub fn auto_land(
+        &mut self,
+        player: &mut Player,
+        movement: &Movement,
+        planet: &Planet,
+        conf: &Config,
+        delta_time: f32,
+    ) {
+        if !conf.auto_land {
+            self.landed_from = None;
+            return;
+        }
+        let feet = player.feet();
+        let offset = feet - planet.position;
+        let distance = glm::length(&offset);
+        if distance <= 0.0 {
+            return;
+        }
+        let outward = offset / distance;
+        let altitude = (distance - planet.get_height(&feet)) / planet.radius;
+
+        match (self.active().mode(), self.landed_from) {
+            (CameraPosition::FreeFly | CameraPosition::Orbital, _) => {
+                let descending = glm::dot(&player.velocity, &outward) < 0.0;
+                if altitude > conf.landing_altitude {
+                    self.armed = true;
+                } else if self.armed && descending {
+                    let flying = self.active;
+                    self.select(CameraPosition::FirstPerson, player, planet);
+                    self.landed_from = Some(flying);
+                    self.climb = 0.0;
+                    eprintln!("Landed, hold jump to take off");
+                }
+            }
+            (CameraPosition::FirstPerson, Some(flying)) => {
+                self.climb = if movement.up > 0.0 { self.climb + delta_time } else { 0.0 };
+                if self.climb >= TAKEOFF_HOLD {
+                    self.active = flying;
+                    self.controllers[flying].enter(player, planet);
+                    self.landed_from = None;
+                    self.armed = false;
+                    // Carry on upwards
+                    player.velocity = outward * player.speed;
+                    eprintln!("Took off, camera mode: {:?}", self.controllers[flying].mode());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Turn the active camera by mouse movement in radians, spread over the
+    /// next frames with `look_smoothing`. Flying cameras bank into turns by
+    /// up to `tilt`, fully when turning `tilt_speed` radians per second.
+    pub fn look(&mut self, player: &mut Player, delta: glm::Vec2, conf: &Config, delta_time: f32) {
+        self.look += delta;
+        let rate = if conf.look_smoothing > 0.0 { 1.0 / conf.look_smoothing } else { 0.0 };
+        let left = damp(self.look, glm::zero(), rate, delta_time);
+        let turn = self.look - left;
+        self.look = left;
+        self.active().look(player, turn.x, turn.y);
+
+        let flying = matches!(self.active().mode(), CameraPosition::FreeFly | CameraPosition::Orbital);
+        let roll = if flying && conf.tilt_speed > 0.0 && delta_time > 0.0 {
+            // Turning right rolls right, around the view direction
+            -conf.tilt * (turn.x / delta_time / conf.tilt_speed).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        let target = glm::quat_angle_axis(roll, &glm::vec3(0.0, 0.0, 1.0));
+        self.bank = damp_quat(&self.bank, &target, TILT_DAMPING, delta_time);
+    }
+
+    /// View of the active camera, banked into turns
+    pub fn view(&mut self, player: &mut Player) -> View {
+        let mut view = self.active().view(player);
+        // The bank is in view space, x right, y up and z back
+        let right = glm::normalize(&glm::cross(&view.direction, &view.up));
+        let up = glm::cross(&right, &view.direction);
+        let banked = glm::quat_rotate_vec3(&self.bank, &glm::vec3(0.0, 1.0, 0.0));
+        view.up = right * banked.x + up * banked.y - view.direction * banked.z;
+        view
+    }
+
+    /// The ship, once launched by flying it
+    pub fn ship(&self) -> Option<&Ship> {
+        self.controllers.iter().find_map(|c| c.ship())
+    }
+
+    /// Keep every controller's positions in the scene's coordinates, see
+    /// `CameraController::rebase`
+    pub fn rebase(&mut self, from: &glm::Vec3, to: &glm::Vec3, scale: f32) {
+        for controller in self.controllers.iter_mut() {
+            controller.rebase(from, to, scale);
+        }
+    }
+
+    /// Switch to the next camera mode
+    pub fn next(&mut self, player: &mut Player, planet: &Planet) {
+        self.active = (self.active + 1) % self.controllers.len();
+        self.controllers[self.active].enter(player, planet);
+        self.landed_from = None;
+        eprintln!("Camera mode: {:?}", self.controllers[self.active].mode());
+    }
+}
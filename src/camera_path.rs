@@ -0,0 +1,214 @@
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+/// Speed along the segment from a keyframe to the next
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+pub enum Ease {
+    #[default]
+    Linear,
+    In,    // Speeding up
+    Out,   // Slowing down
+    InOut, // Both
+}
+impl Ease {
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Ease::Linear => t,
+            Ease::In => t * t,
+            Ease::Out => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::InOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// Camera position and point looked at, at a time in seconds
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Keyframe {
+    pub time: f32,
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+    #[serde(default)]
+    pub ease: Ease,
+}
+
+/// Camera moving smoothly through keyframes, read from a RON file, e.g.
+///
+/// ```ron
+/// (keyframes: [
+///     (time: 0.0, position: (0.0, 100.0, 1500.0), look_at: (0.0, 0.0, 0.0), ease: In),
+///     (time: 10.0, position: (800.0, 40.0, 900.0), look_at: (0.0, 0.0, 0.0)),
+///     (time: 20.0, position: (900.0, 0.0, 0.0), look_at: (0.0, 0.0, 0.0)),
+/// ])
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+fn vec3(v: [f32; 3]) -> glm::Vec3 {
+    glm::vec3(v[0], v[1], v[2])
+}
+
+/// Point at `t` between `p1` and `p2` on a Catmull-Rom spline
+fn catmull_rom(p0: &glm::Vec3, p1: &glm::Vec3, p2: &glm::Vec3, p3: &glm::Vec3, t: f32) -> glm::Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+impl CameraPath {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut camera_path: CameraPath =
+            ron::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        if camera_path.keyframes.is_empty() {
+            return Err(format!("{}: no keyframes", path));
+        }
+        camera_path
+            .keyframes
+            .sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(camera_path)
+    }
+
+    /// Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    /// Camera position and direction at `time`, on a Catmull-Rom spline
+    /// through the keyframes, and held before the first and after the last
+    pub fn sample(&self, time: f32) -> (glm::Vec3, glm::Vec3) {
+        let last = self.keyframes.len() - 1;
+        let next = self
+            .keyframes
+            .iter()
+            .position(|k| k.time > time)
+            .unwrap_or(last);
+        let prev = next.saturating_sub(1);
+        let (a, b) = (&self.keyframes[prev], &self.keyframes[next]);
+        let t = if b.time > a.time {
+            a.ease.apply(((time - a.time) / (b.time - a.time)).clamp(0.0, 1.0))
+        } else {
+            0.0
+        };
+        // Neighbours on either side shape the curve, repeated at the ends
+        let (before, after) = (prev.saturating_sub(1), (next + 1).min(last));
+        let spline = |field: fn(&Keyframe) -> [f32; 3]| {
+            catmull_rom(
+                &vec3(field(&self.keyframes[before])),
+                &vec3(field(a)),
+                &vec3(field(b)),
+                &vec3(field(&self.keyframes[after])),
+                t,
+            )
+        };
+        let position = spline(|k| k.position);
+        let look_at = spline(|k| k.look_at);
+        (position, glm::normalize(&(look_at - position)))
+    }
+}
+
+/// Playing a camera path back, paused with P and scrubbed with [ and ]
+#[derive(Debug, Copy, Clone)]
+pub struct Playback {
+    pub time: f32,
+    pub playing: bool,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback { time: 0.0, playing: true }
+    }
+}
+
+impl Playback {
+    /// Move time forward while playing, stopping at the end of the path
+    pub fn advance(&mut self, delta_time: f32, path: &CameraPath) {
+        if self.playing {
+            self.time += delta_time;
+        }
+        if self.time >= path.duration() {
+            self.time = path.duration();
+            self.playing = false;
+        }
+    }
+
+    /// Jump forward or back in time
+    pub fn scrub(&mut self, seconds: f32, path: &CameraPath) {
+        self.time = (self.time + seconds).clamp(0.0, path.duration());
+    }
+
+    /// Pause, or play, starting over when at the end
+    pub fn toggle(&mut self, path: &CameraPath) {
+        if !self.playing && self.time >= path.duration() {
+            self.time = 0.0;
+        }
+        self.playing = !self.playing;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path() -> CameraPath {
+        ron::from_str(
+            "(keyframes: [
+                (time: 0.0, position: (0.0, 100.0, 1500.0), look_at: (0.0, 0.0, 0.0), ease: In),
+                (time: 10.0, position: (800.0, 40.0, 900.0), look_at: (0.0, 0.0, 0.0)),
+                (time: 20.0, position: (900.0, 0.0, 0.0), look_at: (0.0, 0.0, 10.0), ease: InOut),
+            ])",
+        )
+        .unwrap()
+    }
+
+    fn assert_at(sample: (glm::Vec3, glm::Vec3), key: &Keyframe) {
+        let (position, direction) = sample;
+        let expected = vec3(key.position);
+        assert!(glm::distance(&position, &expected) < 1e-3, "{:?} isn't {:?}", position, expected);
+        let towards = glm::normalize(&(vec3(key.look_at) - expected));
+        assert!(glm::distance(&direction, &towards) < 1e-5);
+    }
+
+    #[test]
+    fn samples_pass_through_keyframes() {
+        let path = path();
+        for key in &path.keyframes {
+            assert_at(path.sample(key.time), key);
+        }
+        assert_eq!(path.duration(), 20.0);
+        // Between keys, on the way
+        let (position, direction) = path.sample(15.0);
+        assert!(position.x > 800.0 && position.z < 900.0);
+        assert!((glm::length(&direction) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn samples_hold_past_the_ends() {
+        let path = path();
+        assert_at(path.sample(-5.0), &path.keyframes[0]);
+        assert_at(path.sample(20.001), &path.keyframes[2]);
+        assert_at(path.sample(1000.0), &path.keyframes[2]);
+
+        let single = CameraPath { keyframes: vec![path.keyframes[1]] };
+        assert_at(single.sample(0.0), &path.keyframes[1]);
+        assert_at(single.sample(50.0), &path.keyframes[1]);
+    }
+
+    #[test]
+    fn playback_stops_at_the_end() {
+        let path = path();
+        let mut playback = Playback::default();
+        playback.advance(25.0, &path);
+        assert_eq!(playback.time, 20.0);
+        assert!(!playback.playing);
+        playback.toggle(&path);
+        assert_eq!(playback.time, 0.0);
+        playback.scrub(-3.0, &path);
+        assert_eq!(playback.time, 0.0);
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::OnceLock;
+
+/// OpenGL version of the current context, and the render path chosen from it.
+/// Probed once on the render thread, after the context is made current.
+#[derive(Copy, Clone, Debug)]
+pub struct Capabilities {
+    pub major: i32,
+    pub minor: i32,
+    /// GL 3.3-class hardware: GLSL 3.30, a single light and no shadows
+    pub reduced: bool,
+}
+
+static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+
+/// Probe the current context. `force_reduced` selects the reduced pipeline
+/// even on capable hardware, for testing it. Does nothing if already probed.
+///
+/// # Safety
+/// Needs a current OpenGL context on the calling thread.
+pub unsafe fn probe(force_reduced: bool) -> Capabilities {
+    *CAPABILITIES.get_or_init(|| Capabilities::query(force_reduced))
+}
+
+/// Capabilities of the current context, probed on first use
+pub fn global() -> Capabilities {
+    *CAPABILITIES.get_or_init(|| unsafe { Capabilities::query(false) })
+}
+
+impl Capabilities {
+    unsafe fn query(force_reduced: bool) -> Self {
+        let (mut major, mut minor) = (0, 0);
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        let mut caps = Capabilities { major, minor, reduced: force_reduced };
+        // Explicit sampler bindings in the shaders need 4.2
+        caps.reduced |= !caps.at_least(4, 2);
+        if caps.reduced {
+            eprintln!("OpenGL {}.{}: using the reduced render pipeline", major, minor);
+        }
+        caps
+    }
+
+    pub fn at_least(&self, major: i32, minor: i32) -> bool {
+        (self.major, self.minor) >= (major, minor)
+    }
+
+    /// Debug output callbacks are core since 4.3
+    pub fn debug_output(&self) -> bool {
+        self.at_least(4, 3)
+    }
+
+    /// Compute shaders and shader storage buffers, core since 4.3
+    pub fn compute_shaders(&self) -> bool {
+        self.at_least(4, 3) && !self.reduced
+    }
+
+    /// Multi-draw indirect with `gl_DrawID` in shaders, core since 4.6
+    pub fn multi_draw(&self) -> bool {
+        self.at_least(4, 6) && !self.reduced
+    }
+
+    /// Tessellation shaders, core since 4.0, sampling terrain from a shader
+    /// storage buffer like compute shaders
+    pub fn tessellation(&self) -> bool {
+        self.compute_shaders()
+    }
+
+    /// Lightsources passed to the scene shader
+    pub fn max_lights(&self) -> usize {
+        if self.reduced {
+            1
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// `#version` line for shaders written against GLSL 4.60. The reduced
+    /// pipeline also defines `REDUCED_PIPELINE` for the shaders to check.
+    pub fn glsl_header(&self) -> String {
+        if self.reduced {
+            "#version 330 core\n#define REDUCED_PIPELINE".to_string()
+        } else if self.at_least(4, 6) {
+            "#version 460 core".to_string()
+        } else {
+            format!("#version {}{}0 core", self.major, self.minor)
+        }
+    }
+}
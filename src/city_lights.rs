@@ -0,0 +1,90 @@
+//! City lights on the night side of inhabited planets
+
+use nalgebra_glm as glm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::globals::{CITY_CLUSTERS, CITY_LIGHTS_PER_CLUSTER, CITY_LIGHTS_SIZE};
+use crate::procedural_planet::Planet;
+use crate::texture;
+
+/// Texture unit the city lights of a planet are bound to
+pub const CITY_LIGHTS_UNIT: u32 = 8;
+
+/// Texel of the map at `direction` from the planet center, as in
+/// `city_lights` in scene.frag
+fn texel(direction: &glm::Vec3, width: usize, height: usize) -> (usize, usize) {
+    let u = direction.z.atan2(direction.x) / std::f32::consts::TAU + 0.5;
+    let v = direction.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+    (
+        ((u * width as f32) as usize).min(width - 1),
+        ((v * height as f32) as usize).min(height - 1),
+    )
+}
+
+/// Random direction, uniform over the sphere
+fn direction(rng: &mut StdRng) -> glm::Vec3 {
+    let y: f32 = rng.gen_range(-1.0..1.0);
+    let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+    let r = (1.0 - y * y).sqrt();
+    glm::vec3(r * angle.cos(), y, r * angle.sin())
+}
+
+/// Bake the lights of `planet`, the texture, or 0 for planets without
+pub fn bake(planet: &Planet) -> u32 {
+    if planet.city_density <= 0.0 || planet.lightsource {
+        return 0;
+    }
+    let timer = std::time::SystemTime::now();
+    eprint!("Baking city lights of planet {} . . . ", planet.planet_id);
+    let (width, height) = (CITY_LIGHTS_SIZE as usize, CITY_LIGHTS_SIZE as usize / 2);
+    let mut rng = StdRng::seed_from_u64(planet.seed as u64 ^ 0xc171e5);
+    // Dry land below the mountains
+    let habitable = |d: &glm::Vec3| {
+        let h = planet.surface_height(d) - 1.0;
+        (!planet.has_ocean || h > planet.ocean_lvl) && h < planet.color_thresholds[2]
+    };
+
+    let mut light = vec![0.0f32; width * height];
+    let mut splat = |d: &glm::Vec3, brightness: f32| {
+        let (x, y) = texel(d, width, height);
+        for (dx, dy, weight) in [(0, 0, 1.0), (1, 0, 0.3), (width - 1, 0, 0.3), (0, 1, 0.3), (0, height - 1, 0.3)] {
+            let (x, y) = ((x + dx) % width, (y + dy) % height);
+            light[y * width + x] += brightness * weight;
+        }
+    };
+
+    let clusters = (CITY_CLUSTERS as f32 * planet.city_density).round() as usize;
+    for _ in 0..clusters {
+        let center = match (0..20).map(|_| direction(&mut rng)).find(|d| habitable(d)) {
+            Some(center) => center,
+            None => continue,
+        };
+        // Many towns and a few sprawling cities, in radians
+        let size = 0.01 + 0.04 * rng.gen::<f32>().powi(3);
+        for _ in 0..CITY_LIGHTS_PER_CLUSTER {
+            // Dense in the core
+            let r = size * rng.gen::<f32>().powi(2);
+            let offset = glm::normalize(&glm::cross(&center, &direction(&mut rng))) * r;
+            let d = glm::normalize(&(center + offset));
+            if habitable(&d) {
+                splat(&d, rng.gen_range(0.3..1.0) * (1.0 - r / size));
+            }
+        }
+    }
+    // Villages strewn in between
+    for _ in 0..clusters * CITY_LIGHTS_PER_CLUSTER / 4 {
+        let d = direction(&mut rng);
+        if habitable(&d) {
+            splat(&d, rng.gen_range(0.1..0.4));
+        }
+    }
+
+    let img = image::ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+        let c = (light[y as usize * width + x as usize].min(1.0) * 255.0).round() as u8;
+        image::Rgba([c, c, c, 255])
+    });
+    let t_id = unsafe { texture::get_texture_id(&img, gl::RGBA8, "city lights") };
+    eprintln!("took {:?}", timer.elapsed().unwrap());
+    t_id
+}
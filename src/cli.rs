@@ -0,0 +1,102 @@
+use clap::{Parser, ValueEnum};
+
+use crate::util::{CameraPosition, Config};
+
+/// Command line options, overriding values from settings.conf
+#[derive(Parser, Debug, Clone, Default)]
+#[command(name = "procedural-planets", about = "Procedurally generated planets")]
+pub struct Args {
+    /// Window width in pixels
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// Window height in pixels
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// Borderless fullscreen
+    #[arg(long, conflicts_with = "windowed")]
+    pub fullscreen: bool,
+    /// Normal window, even if settings.conf says fullscreen
+    #[arg(long)]
+    pub windowed: bool,
+    /// World seed, which the planets, their craters and textures and the
+    /// nebula are all generated from. 0 gives the default scene
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Camera mode at startup
+    #[arg(long, value_enum)]
+    pub camera_mode: Option<CameraMode>,
+    /// Load the scene from a RON or JSON file instead of the built-in one
+    #[arg(long, conflicts_with = "kitchen_sink")]
+    pub scene: Option<String>,
+    /// Small test scene covering all render paths
+    #[arg(long)]
+    pub kitchen_sink: bool,
+    /// Record frames at a fixed timestep, to a directory of PNG files or a
+    /// video file encoded with ffmpeg (.mp4, .mkv, .webm or .mov)
+    #[arg(long, value_name = "PATH")]
+    pub record: Option<String>,
+    /// Frame rate of the recording
+    #[arg(long, default_value_t = 60, requires = "record")]
+    pub record_fps: u32,
+    /// Stop recording and exit after this many frames
+    #[arg(long, requires = "record")]
+    pub record_frames: Option<u64>,
+    /// Render offscreen with a hidden window, writing frames to --record.
+    /// Renders one frame unless --record-frames or --camera-path say otherwise
+    #[arg(long, requires = "record")]
+    pub headless: bool,
+    /// Move the camera along keyframes from a RON file
+    #[arg(long, value_name = "PATH")]
+    pub camera_path: Option<String>,
+    /// Use the reduced GL 3.3 render pipeline, even on capable hardware
+    #[arg(long)]
+    pub reduced: bool,
+    /// Write the GPU time of each part of every frame to a CSV file
+    #[arg(long, value_name = "PATH")]
+    pub profile_csv: Option<String>,
+    /// Write the CPU time of named scopes to a chrome tracing JSON file
+    #[arg(long, value_name = "PATH")]
+    pub profile_trace: Option<String>,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum CameraMode {
+    First,
+    Third,
+    Free,
+    Orbital,
+    OrbitSelected,
+    Ship,
+}
+
+impl Args {
+    /// Override config values given on the command line. Also used after
+    /// reloading the config, so the overrides stick.
+    pub fn apply(&self, conf: &mut Config) {
+        if let Some(width) = self.width {
+            conf.screen_width = width;
+        }
+        if let Some(height) = self.height {
+            conf.screen_height = height;
+        }
+        if self.fullscreen {
+            conf.fullscreen = true;
+        }
+        if self.windowed {
+            conf.fullscreen = false;
+        }
+        if let Some(seed) = self.seed {
+            conf.seed = seed;
+        }
+        if let Some(mode) = self.camera_mode {
+            conf.camera_position = match mode {
+                CameraMode::First => CameraPosition::FirstPerson,
+                CameraMode::Third => CameraPosition::ThirdPerson,
+                CameraMode::Free => CameraPosition::FreeFly,
+                CameraMode::Orbital => CameraPosition::Orbital,
+                CameraMode::OrbitSelected => CameraPosition::OrbitSelected,
+                CameraMode::Ship => CameraPosition::Ship,
+            };
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Gamma-correct colour pipeline
+
+use nalgebra_glm as glm;
+use std::cell::Cell;
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Light in linear space and encode the scene to sRGB. Read when textures
+/// are loaded, so set before loading the scene.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+}
+
+/// Whether colours are lit in linear space
+pub fn enabled() -> bool {
+    ENABLED.with(|e| e.get())
+}
+
+/// Whether the default framebuffer, bound now, encodes to sRGB when
+/// `FRAMEBUFFER_SRGB` is enabled
+pub unsafe fn window_is_srgb() -> bool {
+    let mut encoding = 0;
+    gl::GetFramebufferAttachmentParameteriv(
+        gl::FRAMEBUFFER,
+        gl::BACK_LEFT,
+        gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING,
+        &mut encoding,
+    );
+    encoding as u32 == gl::SRGB
+}
+
+/// Colour as picked, in sRGB, converted to linear when lighting in linear space
+pub fn linear(c: &glm::Vec3) -> glm::Vec3 {
+    if !enabled() {
+        return *c;
+    }
+    c.map(|c| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+/// Internal format for colour textures and render targets shown as they are,
+/// decoded to linear when sampled
+pub fn texture_format() -> u32 {
+    if enabled() {
+        gl::SRGB8_ALPHA8
+    } else {
+        gl::RGBA8
+    }
+}
+
+/// Encode what is drawn from now on, the 3D scene
+pub unsafe fn begin_scene() {
+    if enabled() {
+        gl::Enable(gl::FRAMEBUFFER_SRGB);
+    }
+}
+
+/// Stop encoding, for overlays already in display colours
+pub unsafe fn end_scene() {
+    gl::Disable(gl::FRAMEBUFFER_SRGB);
+}
@@ -0,0 +1,166 @@
+//! Colour grading of the frame through a 3D lookup table
+
+use image::io::Reader as ImageReader;
+
+use crate::error::{Error, Result};
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::shader;
+
+pub struct ColorGrading {
+    shader: shader::Shader,
+    vao: GpuHandle, // Empty, vertices are made in the vertex shader
+    lut: GpuHandle,
+    lut_size: u32,
+    fbo: GpuHandle,
+    texture: GpuHandle, // Copy of the frame
+    size: (u32, u32),   // Of the copy
+}
+
+impl ColorGrading {
+    /// Load the LUT at `path`
+    pub unsafe fn load(path: &str) -> Result<Self> {
+        let img = ImageReader::open(path)
+            .map_err(|source| Error::Io {
+                path: path.to_string(),
+                source,
+            })?
+            .decode()
+            .map_err(|source| Error::Image {
+                path: path.to_string(),
+                source,
+            })?
+            .into_rgba8();
+        let (width, height) = img.dimensions();
+        if height < 2 || width != height * height {
+            return Err(Error::Lut {
+                path: path.to_string(),
+                message: format!(
+                    "{}x{} is not a strip of square slices, expected {}x{}",
+                    width,
+                    height,
+                    height * height,
+                    height
+                ),
+            });
+        }
+        let n = height as usize;
+
+        // Slices side by side to a texture of n layers, blue by layer
+        let mut texels = Vec::with_capacity(n * n * n * 4);
+        for b in 0..n {
+            for g in 0..n {
+                for r in 0..n {
+                    let pixel = img.get_pixel((b * n + r) as u32, g as u32);
+                    texels.extend_from_slice(&pixel.0);
+                }
+            }
+        }
+        let mut lut = 0;
+        gl::GenTextures(1, &mut lut);
+        gl::BindTexture(gl::TEXTURE_3D, lut);
+        for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl::TexParameteri(gl::TEXTURE_3D, wrap, gl::CLAMP_TO_EDGE as i32);
+        }
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexImage3D(
+            gl::TEXTURE_3D,
+            0,
+            gl::RGBA8 as i32,
+            n as i32,
+            n as i32,
+            n as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            texels.as_ptr() as *const _,
+        );
+        gl::BindTexture(gl::TEXTURE_3D, 0);
+
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/fullscreen.vert", None)
+            .attach_file("./resources/shaders/grade.frag", None)
+            .link_or_error_shader();
+        let (mut vao, mut fbo, mut texture) = (0, 0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+        Ok(ColorGrading {
+            shader,
+            vao: GpuHandle::new(Kind::VertexArray, vao, 0, "color grading"),
+            lut: GpuHandle::new(Kind::Texture, lut, texels.len() as u64, "color grading LUT"),
+            lut_size: n as u32,
+            fbo: GpuHandle::new(Kind::Framebuffer, fbo, 0, "color grading"),
+            texture: GpuHandle::new(Kind::Texture, texture, 0, "color grading"),
+            size: (0, 0),
+        })
+    }
+
+    /// Reallocate the copy when the window changes size
+    unsafe fn resize(&mut self, size: (u32, u32)) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        self.texture.resize(size.0 as u64 * size.1 as u64 * 4);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture.id());
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as i32,
+            size.0 as i32,
+            size.1 as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo.id());
+        gl::FramebufferTexture2D(
+            gl::DRAW_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            self.texture.id(),
+            0,
+        );
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target as u32);
+    }
+
+    /// Grade the frame drawn so far in the framebuffer currently bound, of
+    /// `window_size`, with the viewport covering it. Leaves the grading
+    /// shader active.
+    pub unsafe fn draw(&mut self, window_size: (u32, u32)) {
+        let (width, height) = (window_size.0 as i32, window_size.1 as i32);
+        self.resize(window_size);
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target as u32);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo.id());
+        gl::BlitFramebuffer(0, 0, width, height, 0, 0, width, height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+
+        self.shader.activate();
+        gl::BindVertexArray(self.vao.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture.id());
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_3D, self.lut.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Uniform1i(self.shader.get_uniform_location("u_scene"), 0);
+        gl::Uniform1i(self.shader.get_uniform_location("u_lut"), 1);
+        gl::Uniform1f(self.shader.get_uniform_location("u_lut_size"), self.lut_size as f32);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
@@ -0,0 +1,102 @@
+//! Comets, lumpy icy nuclei trailing a tail of particles away from the sun
+
+use nalgebra_glm as glm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::globals::{COMET_ACTIVE_RADII, COMET_LOBES};
+use crate::mesh::Mesh;
+use crate::particles::{Emitter, Preset};
+use crate::procedural_planet::Planet;
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+
+/// Name of the emitter node below a nucleus
+pub const TAIL_NAME: &str = "comet tail";
+
+/// Irregular nucleus of about `radius`, shaped by `seed`
+pub fn nucleus(radius: f32, seed: u32, color: glm::Vec4) -> Mesh {
+    let mut rng = StdRng::seed_from_u64(seed as u64 ^ 0xc0_3e7);
+    let lobes: Vec<(glm::Vec3, f32)> = (0..COMET_LOBES)
+        .map(|_| {
+            let direction = glm::normalize(&glm::vec3(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0f32),
+            ));
+            (direction, rng.gen_range(0.1..0.45))
+        })
+        .collect();
+    let stretch = glm::vec3(rng.gen_range(1.2..1.8), 1.0, rng.gen_range(0.8..1.1));
+
+    let mut mesh = Mesh::icosphere(1.0, 3, color);
+    for v in mesh.vertices.chunks_exact_mut(3) {
+        let direction = glm::vec3(v[0], v[1], v[2]);
+        let bulge: f32 = lobes
+            .iter()
+            .map(|(center, height)| height * glm::dot(&direction, center).max(0.0).powi(4))
+            .sum();
+        let p = direction.component_mul(&stretch) * radius * (0.8 + bulge);
+        v.copy_from_slice(&[p.x, p.y, p.z]);
+    }
+    mesh.recompute_normals(None);
+    mesh.compute_tangents();
+    mesh
+}
+
+/// Emitter of the tail, to add below a nucleus
+pub fn tail(gpu: bool) -> Node {
+    let mut node = SceneNode::with_type(SceneNodeType::Empty);
+    node.name = TAIL_NAME.to_string();
+    let mut emitter = Emitter::preset(Preset::CometTail);
+    emitter.gpu = gpu;
+    node.emitter = Some(emitter);
+    node
+}
+
+/// Turn the tails below `node` away from the nearest light source among
+/// `planets`, as placed when last drawn, and set how strongly they blow
+pub fn update(node: &mut SceneNode, planets: &[Planet]) {
+    let world: glm::Mat4 = node.world_transformation.cast();
+    for &child in &node.children {
+        let child = unsafe { &mut *child };
+        if child.name == TAIL_NAME && child.emitter.is_some() {
+            blow(child, &world, planets);
+        }
+        update(child, planets);
+    }
+}
+
+/// Point `tail` of the nucleus with transformation `parent` straight away
+/// from the nearest sun
+fn blow(tail: &mut SceneNode, parent: &glm::Mat4, planets: &[Planet]) {
+    let position = (parent * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+    let sun = planets
+        .iter()
+        .filter(|planet| planet.lightsource)
+        .min_by(|a, b| {
+            let (da, db) = (glm::distance2(&a.position, &position), glm::distance2(&b.position, &position));
+            da.total_cmp(&db)
+        });
+    let (away, intensity) = match sun {
+        Some(sun) => {
+            let to_comet = position - sun.position;
+            let distance = glm::length(&to_comet).max(f32::EPSILON);
+            // Falls off with the sunlight, by the square of the distance
+            let radii = distance / sun.radius.max(f32::EPSILON);
+            (to_comet / distance, (COMET_ACTIVE_RADII / radii).powi(2).min(1.0))
+        }
+        None => (glm::vec3(0.0, 1.0, 0.0), 0.0),
+    };
+
+    // In the nucleus' own space, which may tumble
+    let local = glm::inverse(&glm::mat4_to_mat3(parent)) * away;
+    if glm::length2(&local) > 0.0 {
+        let up = glm::vec3(0.0, 1.0, 0.0);
+        tail.set_orientation(Some(glm::quat_rotation(&up, &glm::normalize(&local))));
+    }
+    let preset = Emitter::preset(Preset::CometTail);
+    let emitter = tail.emitter.as_mut().unwrap();
+    emitter.rate = preset.rate * intensity;
+    emitter.speed = preset.speed * (0.5 + intensity);
+    emitter.color.0.w = preset.color.0.w * intensity.sqrt();
+}
@@ -0,0 +1,179 @@
+//! Immediate mode debug line rendering
+
+use nalgebra_glm as glm;
+use std::cell::RefCell;
+
+use crate::{shader, util};
+
+pub const RED: glm::Vec4 = glm::Vec4::new(1.0, 0.0, 0.0, 1.0);
+pub const GREEN: glm::Vec4 = glm::Vec4::new(0.0, 1.0, 0.0, 1.0);
+pub const BLUE: glm::Vec4 = glm::Vec4::new(0.0, 0.0, 1.0, 1.0);
+pub const YELLOW: glm::Vec4 = glm::Vec4::new(1.0, 1.0, 0.0, 1.0);
+
+// Interleaved position (3) and colour (4)
+const VERTEX_SIZE: usize = 7;
+const CIRCLE_SEGMENTS: usize = 32;
+
+thread_local! {
+    static LINES: RefCell<Vec<f32>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Add a line segment from `p0` to `p1`
+pub fn line(p0: &glm::Vec3, p1: &glm::Vec3, color: glm::Vec4) {
+    LINES.with(|lines| {
+        let mut lines = lines.borrow_mut();
+        for p in [p0, p1] {
+            lines.extend_from_slice(&[p.x, p.y, p.z]);
+            lines.extend_from_slice(&[color.x, color.y, color.z, color.w]);
+        }
+    });
+}
+
+/// Axis aligned bounding box
+#[allow(dead_code)]
+pub fn aabb(min: &glm::Vec3, max: &glm::Vec3, color: glm::Vec4) {
+    let corner = |i: usize| {
+        glm::vec3(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        )
+    };
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                line(&corner(i), &corner(i | bit), color);
+            }
+        }
+    }
+}
+
+/// Bounding sphere, drawn as three great circles
+pub fn sphere(center: &glm::Vec3, radius: f32, color: glm::Vec4) {
+    let axes = [
+        (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0)),
+        (glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+        (glm::vec3(0.0, 0.0, 1.0), glm::vec3(1.0, 0.0, 0.0)),
+    ];
+    for (u, v) in axes.iter() {
+        let point = |i: usize| {
+            let a = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::PI * 2.0;
+            center + (u * a.cos() + v * a.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            line(&point(i), &point(i + 1), color);
+        }
+    }
+}
+
+/// Coordinate axes gizmo of a transformation, x red, y green, z blue
+pub fn axes(transform: &glm::Mat4, size: f32) {
+    let origin = glm::vec4_to_vec3(&(transform * glm::vec4(0.0, 0.0, 0.0, 1.0)));
+    for (axis, color) in [(0, RED), (1, GREEN), (2, BLUE)] {
+        let mut dir = glm::vec4(0.0, 0.0, 0.0, 0.0);
+        dir[axis] = 1.0;
+        let dir = glm::normalize(&glm::vec4_to_vec3(&(transform * dir)));
+        line(&origin, &(origin + dir * size), color);
+    }
+}
+
+/// Frustum of a view-projection matrix
+#[allow(dead_code)]
+pub fn frustum(view_projection: &glm::Mat4, color: glm::Vec4) {
+    let inv = glm::inverse(view_projection);
+    let corner = |i: usize| {
+        let ndc = glm::vec4(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 },
+            1.0,
+        );
+        let p = inv * ndc;
+        glm::vec3(p.x, p.y, p.z) / p.w
+    };
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                line(&corner(i), &corner(i | bit), color);
+            }
+        }
+    }
+}
+
+/// Per-vertex normals of a mesh with a model transformation
+#[allow(dead_code)]
+pub fn normals(mesh: &crate::mesh::Mesh, model: &glm::Mat4, length: f32, color: glm::Vec4) {
+    let normal_matrix = glm::transpose(&glm::inverse(&glm::mat4_to_mat3(model)));
+    for (v, n) in mesh.vertices.chunks(3).zip(mesh.normals.chunks(3)) {
+        let p = glm::vec4_to_vec3(&(model * glm::vec4(v[0], v[1], v[2], 1.0)));
+        let n = glm::normalize(&(normal_matrix * glm::vec3(n[0], n[1], n[2])));
+        line(&p, &(p + n * length), color);
+    }
+}
+
+/// GPU side of the debug lines
+pub struct DebugDraw {
+    shader: shader::Shader,
+    vao: u32,
+    vbo: u32,
+    capacity: isize, // Bytes allocated for vbo
+    pub line_count: usize, // Lines drawn in the last flush
+}
+
+impl DebugDraw {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/lines.vert", None)
+            .attach_file("./resources/shaders/lines.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+        let stride = (VERTEX_SIZE * std::mem::size_of::<f32>()) as i32;
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, util::offset::<f32>(3));
+
+        DebugDraw {
+            shader,
+            vao,
+            vbo,
+            capacity: 0,
+            line_count: 0,
+        }
+    }
+
+    /// Draw and clear all lines added since last flush. Leaves the debug line
+    /// shader active, so reactivate the scene shader afterwards.
+    pub unsafe fn flush(&mut self, view_projection: &glm::Mat4) {
+        let lines = LINES.with(|lines| std::mem::take(&mut *lines.borrow_mut()));
+        self.line_count = lines.len() / VERTEX_SIZE / 2;
+        if lines.is_empty() {
+            return;
+        }
+
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        let size = util::byte_size_of_array(&lines);
+        if size > self.capacity {
+            // Grow, and orphan the old storage
+            self.capacity = size.max(2 * self.capacity);
+            gl::BufferData(gl::ARRAY_BUFFER, self.capacity, std::ptr::null(), gl::DYNAMIC_DRAW);
+        }
+        gl::BufferSubData(gl::ARRAY_BUFFER, 0, size, util::pointer_to_array(&lines));
+
+        self.shader.activate();
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_mvp"),
+            1,
+            gl::FALSE,
+            view_projection.as_ptr(),
+        );
+        gl::DrawArrays(gl::LINES, 0, (lines.len() / VERTEX_SIZE) as i32);
+    }
+}
@@ -0,0 +1,292 @@
+use std::sync::{mpsc, Arc};
+
+use glutin::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+use crate::procedural_planet::Planet;
+use crate::{gpu_resources, util};
+
+/// Pointer events sent from the event loop to the render thread
+pub type GuiEvents = mpsc::Receiver<egui::Event>;
+
+/// Translate a window event to egui input. `pointer` keeps the last cursor
+/// position, as button events don't carry one.
+pub fn translate_event(event: &WindowEvent, pointer: &mut egui::Pos2) -> Option<egui::Event> {
+    match event {
+        WindowEvent::CursorMoved { position, .. } => {
+            *pointer = egui::pos2(position.x as f32, position.y as f32);
+            Some(egui::Event::PointerMoved(*pointer))
+        }
+        WindowEvent::CursorLeft { .. } => Some(egui::Event::PointerGone),
+        WindowEvent::MouseInput { state, button, .. } => {
+            let button = match button {
+                MouseButton::Left => egui::PointerButton::Primary,
+                MouseButton::Right => egui::PointerButton::Secondary,
+                MouseButton::Middle => egui::PointerButton::Middle,
+                _ => return None,
+            };
+            Some(egui::Event::PointerButton {
+                pos: *pointer,
+                button,
+                pressed: *state == ElementState::Pressed,
+                modifiers: egui::Modifiers::default(),
+            })
+        }
+        WindowEvent::MouseWheel { delta, .. } => Some(egui::Event::Scroll(match delta {
+            MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * 50.0,
+            MouseScrollDelta::PixelDelta(p) => egui::vec2(p.x as f32, p.y as f32),
+        })),
+        _ => None,
+    }
+}
+
+/// In-app panel for tweaking camera, lights and terrain live, toggled by F1
+pub struct DebugPanel {
+    ctx: egui::Context,
+    painter: egui_glow::Painter,
+    events: GuiEvents,
+    pub visible: bool,
+    pub wants_pointer: bool, // Pointer is used by the panel, don't move the camera
+}
+
+impl DebugPanel {
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn new(
+        context: &glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>,
+        events: GuiEvents,
+    ) -> Self {
+        let gl = egui_glow::glow::Context::from_loader_function(|symbol| {
+            context.get_proc_address(symbol) as *const _
+        });
+        let painter = egui_glow::Painter::new(Arc::new(gl), "", None)
+            .expect("Failed to create egui painter");
+        DebugPanel {
+            ctx: egui::Context::default(),
+            painter,
+            events,
+            visible: false,
+            wants_pointer: false,
+        }
+    }
+
+    /// Draw the panel for a frame, editing `conf` and `planets` directly.
+    /// Returns true when terrain parameters of planet `selected` changed, so
+    /// its terrain should be regenerated. Changes blending, depth test, face
+    /// culling and the active program, so restore those afterwards.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn run(
+        &mut self,
+        size: (u32, u32),
+        elapsed: f32,
+        conf: &mut util::Config,
+        planets: &mut [Planet],
+        selected: usize,
+    ) -> bool {
+        let events = self.events.try_iter().collect();
+        if !self.visible {
+            self.wants_pointer = false;
+            return false;
+        }
+        let raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(size.0 as f32, size.1 as f32),
+            )),
+            pixels_per_point: Some(1.0),
+            time: Some(elapsed as f64),
+            events,
+            ..Default::default()
+        };
+
+        let mut regenerate = false;
+        let output = self.ctx.run(raw_input, |ctx| {
+            egui::Window::new("Tweaks").show(ctx, |ui| {
+                ui.collapsing("Camera", |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut conf.movement_speed, 0.001..=1000.0)
+                            .logarithmic(true)
+                            .text("Speed"),
+                    );
+                    ui.checkbox(&mut conf.auto_speed, "Speed by altitude");
+                    ui.add(
+                        egui::Slider::new(&mut conf.auto_speed_rate, 0.001..=10.0)
+                            .logarithmic(true)
+                            .text("Altitudes per second"),
+                    );
+                    ui.add(egui::Slider::new(&mut conf.acceleration, 0.0..=20.0).text("Acceleration"));
+                    ui.add(egui::Slider::new(&mut conf.damping, 0.0..=20.0).text("Damping"));
+                    ui.add(
+                        egui::Slider::new(&mut conf.look_smoothing, 0.0..=0.2).text("Look smoothing"),
+                    );
+                    ui.checkbox(&mut conf.auto_land, "Land automatically");
+                    ui.add(
+                        egui::Slider::new(&mut conf.landing_altitude, 0.0..=0.2)
+                            .text("Landing altitude (radii)"),
+                    );
+                    ui.add(egui::Slider::new(&mut conf.fov, 0.2..=2.5).text("Field of view"));
+                    ui.add(egui::Slider::new(&mut conf.focus_fill, 0.05..=1.0).text("Focus fills (F)"));
+                    ui.add(
+                        egui::Slider::new(&mut conf.render_limit, 0.0..=0.1).text("Render limit"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut conf.impostor_pixels, 0.0..=256.0)
+                            .text("Impostors below (px)"),
+                    );
+                    ui.checkbox(&mut conf.occlusion_culling, "Occlusion culling");
+                    ui.checkbox(&mut conf.minimap, "Minimap");
+                    ui.checkbox(&mut conf.crosshair, "Crosshair");
+                    ui.horizontal(|ui| {
+                        ui.label("Labels:");
+                        ui.checkbox(&mut conf.labels_planets, "Planets");
+                        ui.checkbox(&mut conf.labels_props, "Props");
+                    });
+                    ui.checkbox(&mut conf.split_screen, "Split screen");
+                    ui.add(
+                        egui::Slider::new(&mut conf.split_distance, 1.1..=20.0)
+                            .text("Planet camera distance"),
+                    );
+                    ui.checkbox(&mut conf.depth_of_field, "Depth of field");
+                    ui.add(
+                        egui::Slider::new(&mut conf.focus_distance, 0.1..=100000.0)
+                            .logarithmic(true)
+                            .text("Focus distance"),
+                    );
+                    ui.add(egui::Slider::new(&mut conf.aperture, 0.0..=32.0).text("Aperture (px)"));
+                    ui.checkbox(&mut conf.auto_focus, "Focus on selected");
+                    ui.checkbox(&mut conf.motion_blur, "Motion blur");
+                    ui.add(egui::Slider::new(&mut conf.motion_blur_shutter, 0.0..=1.0).text("Shutter"));
+                    ui.checkbox(&mut conf.log_depth, "Logarithmic depth");
+                    ui.checkbox(&mut conf.camera_relative, "Camera-relative rendering");
+                    ui.checkbox(&mut conf.double_precision, "Double precision transforms");
+                    ui.checkbox(&mut conf.debug_normals, "Face normals");
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgba_unmultiplied(&mut conf.bg_color);
+                        ui.label("Background");
+                    });
+                });
+                ui.collapsing("Lights", |ui| {
+                    ui.add(egui::Slider::new(&mut conf.light_intensity, 0.0..=4.0).text("Intensity"));
+                    ui.add(egui::Slider::new(&mut conf.ambient_light, 0.0..=4.0).text("Ambient"));
+                    ui.checkbox(&mut conf.lens_flare, "Lens flare");
+                    ui.checkbox(&mut conf.light_shafts, "Light shafts");
+                    ui.checkbox(&mut conf.ocean_reflections, "Ocean reflections");
+                    ui.checkbox(&mut conf.image_based_lighting, "Image-based lighting");
+                    ui.checkbox(&mut conf.shader_variants, "Shader variants");
+                    for planet in planets.iter_mut().filter(|p| p.lightsource) {
+                        let e = planet.emission;
+                        let mut color = [e.x, e.y, e.z];
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_rgb(&mut color);
+                            ui.label(format!("Planet {}", planet.planet_id));
+                        });
+                        planet.emission = glm::vec3(color[0], color[1], color[2]);
+                    }
+                });
+                ui.collapsing("Orbits", |ui| {
+                    ui.checkbox(&mut conf.nbody, "N-body gravity");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut conf.nbody_integrator, util::Integrator::SemiImplicitEuler, "Euler");
+                        ui.radio_value(&mut conf.nbody_integrator, util::Integrator::RungeKutta4, "RK4");
+                    });
+                    ui.checkbox(&mut conf.orbit_paths, "Orbit paths");
+                    ui.add(egui::Slider::new(&mut conf.orbit_path_width, 0.5..=8.0).text("Path width (px)"));
+                });
+                let planet = &mut planets[selected];
+                ui.collapsing(format!("Terrain (planet {})", planet.planet_id), |ui| {
+                    let noise = &mut planet.noise;
+                    let sliders = [
+                        ui.add(egui::Slider::new(&mut planet.max_height, 0.0..=0.2).text("Max height")),
+                        ui.add(egui::Slider::new(&mut noise.size, 0.1..=500.0).logarithmic(true).text("Size")),
+                        ui.add(egui::Slider::new(&mut noise.amplitude, 0.0..=2.0).text("Amplitude")),
+                        ui.add(egui::Slider::new(&mut noise.octaves, 1..=12).text("Octaves")),
+                        ui.add(egui::Slider::new(&mut noise.gain_amplitude, 0.0..=2.0).text("Gain amplitude")),
+                        ui.add(egui::Slider::new(&mut noise.gain_frequency, 0.0..=30.0).text("Gain frequency")),
+                        ui.add(egui::Slider::new(&mut noise.gain_offset, -2.0..=2.0).text("Gain offset")),
+                        ui.add(egui::Slider::new(&mut planet.max_lod, 0..=6).text("Max LoD")),
+                    ];
+                    // Only regenerate when a slider is let go, not while dragging
+                    regenerate = sliders
+                        .iter()
+                        .any(|r| r.drag_released() || (r.changed() && !r.dragged()));
+                    ui.add(egui::Slider::new(&mut planet.lod_bias, 0.25..=4.0).text("LoD distance"));
+                    ui.checkbox(&mut conf.normal_maps, "Normal maps");
+                    ui.checkbox(&mut conf.compute_terrain, "Displace on the GPU");
+                    ui.checkbox(&mut conf.batch_terrain, "Batch patch draws");
+                    ui.checkbox(&mut conf.tessellated_terrain, "Tessellate on the GPU");
+                    ui.add(egui::Slider::new(&mut conf.erosion_iterations, 0..=8).text("Erosion passes"));
+                    ui.checkbox(&mut conf.vegetation, "Grass and trees");
+                    ui.add(
+                        egui::Slider::new(&mut conf.tess_edge_pixels, 2.0..=32.0)
+                            .text("Triangle size (px)"),
+                    );
+                    ui.add(egui::Slider::new(&mut planet.detail_strength, 0.0..=2.0).text("Detail strength"));
+                    ui.add(
+                        egui::Slider::new(&mut planet.detail_scale, 1.0..=512.0)
+                            .logarithmic(true)
+                            .text("Detail scale"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut planet.texture_strength, 0.0..=1.0)
+                            .text("Terrain texture strength"),
+                    );
+                    for (scale, name) in planet.texture_scale.iter_mut().zip(["Rock", "Grass", "Snow"]) {
+                        ui.add(
+                            egui::Slider::new(scale, 1.0..=512.0)
+                                .logarithmic(true)
+                                .text(format!("{} scale", name)),
+                        );
+                    }
+                });
+                ui.collapsing(format!("Clouds (planet {})", planet.planet_id), |ui| {
+                    ui.checkbox(&mut planet.has_clouds, "Enabled");
+                    ui.add(egui::Slider::new(&mut planet.cloud_height, 0.0..=0.2).text("Height"));
+                    ui.add(egui::Slider::new(&mut planet.cloud_coverage, 0.0..=1.0).text("Coverage"));
+                    ui.add(egui::Slider::new(&mut planet.cloud_density, 0.0..=1.0).text("Density"));
+                    ui.add(egui::Slider::new(&mut planet.cloud_size, 0.5..=32.0).logarithmic(true).text("Size"));
+                    ui.add(egui::Slider::new(&mut planet.cloud_wind, -0.1..=0.1).text("Wind"));
+                    let c = planet.cloud_color;
+                    let mut color = [c.x, c.y, c.z];
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut color);
+                        ui.label("Colour");
+                    });
+                    planet.cloud_color = glm::vec3(color[0], color[1], color[2]);
+                });
+                ui.collapsing(format!("Gas giant (planet {})", planet.planet_id), |ui| {
+                    // Turning bands on or off flattens the terrain or raises it again
+                    let bands = ui.add(egui::Slider::new(&mut planet.bands, 0.0..=32.0).text("Bands"));
+                    regenerate |= bands.drag_released() || (bands.changed() && !bands.dragged());
+                    ui.add(egui::Slider::new(&mut planet.oblateness, 0.0..=0.3).text("Oblateness"));
+                    ui.add(egui::Slider::new(&mut planet.cloud_wind, -0.1..=0.1).text("Wind"));
+                });
+                ui.collapsing("GPU memory", |ui| {
+                    egui::Grid::new("gpu_memory").show(ui, |ui| {
+                        for (kind, (count, bytes)) in gpu_resources::usage() {
+                            ui.label(format!("{:?}", kind));
+                            ui.label(format!("{}", count));
+                            ui.label(format!("{:.2} MiB", gpu_resources::mib(bytes)));
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button("Print by label").clicked() {
+                        eprint!("{}", gpu_resources::dump());
+                    }
+                });
+            });
+        });
+        self.wants_pointer = self.ctx.wants_pointer_input() || self.ctx.is_pointer_over_area();
+
+        let primitives = self.ctx.tessellate(output.shapes);
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        self.painter.paint_and_update_textures(
+            [size.0, size.1],
+            1.0,
+            &primitives,
+            &output.textures_delta,
+        );
+        gl::Disable(gl::SCISSOR_TEST);
+        regenerate
+    }
+}
@@ -0,0 +1,197 @@
+//! Depth of field, blurring what is out of focus like a camera lens
+
+use nalgebra_glm as glm;
+
+use crate::globals::DOF_MAX_RADIUS;
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::shader;
+
+pub struct DepthOfField {
+    distance_shader: shader::Shader,
+    blur_shader: shader::Shader,
+    vao: GpuHandle, // Empty, vertices are made in the vertex shader
+    depth_fbo: GpuHandle,
+    depth: GpuHandle, // Copy of the depth buffer
+    distance_fbo: GpuHandle,
+    distance: GpuHandle,
+    scene_fbo: GpuHandle,
+    scene: GpuHandle, // Copy of the frame
+    size: (u32, u32), // Of the textures, the window
+}
+
+unsafe fn new_texture(filter: u32) -> u32 {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+    texture
+}
+
+impl DepthOfField {
+    pub unsafe fn new() -> Self {
+        let distance_shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/fullscreen.vert", None)
+            .attach_file("./resources/shaders/dof_distance.frag", None)
+            .link_or_error_shader();
+        let blur_shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/fullscreen.vert", None)
+            .attach_file("./resources/shaders/dof.frag", None)
+            .link_or_error_shader();
+
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        let mut fbos = [0; 3];
+        gl::GenFramebuffers(3, fbos.as_mut_ptr());
+        let [depth_fbo, distance_fbo, scene_fbo] = fbos;
+        let depth = new_texture(gl::NEAREST);
+        let distance = new_texture(gl::NEAREST);
+        let scene = new_texture(gl::LINEAR);
+
+        DepthOfField {
+            distance_shader,
+            blur_shader,
+            vao: GpuHandle::new(Kind::VertexArray, vao, 0, "depth of field"),
+            depth_fbo: GpuHandle::new(Kind::Framebuffer, depth_fbo, 0, "depth of field"),
+            depth: GpuHandle::new(Kind::Texture, depth, 0, "depth of field"),
+            distance_fbo: GpuHandle::new(Kind::Framebuffer, distance_fbo, 0, "depth of field"),
+            distance: GpuHandle::new(Kind::Texture, distance, 0, "depth of field"),
+            scene_fbo: GpuHandle::new(Kind::Framebuffer, scene_fbo, 0, "depth of field"),
+            scene: GpuHandle::new(Kind::Texture, scene, 0, "depth of field"),
+            size: (0, 0),
+        }
+    }
+
+    /// Reallocate the textures when the window changes size
+    unsafe fn resize(&mut self, size: (u32, u32)) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        let (width, height) = (size.0 as i32, size.1 as i32);
+        let texels = size.0 as u64 * size.1 as u64;
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        let textures = [
+            (&mut self.depth, &self.depth_fbo, gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8),
+            (&mut self.distance, &self.distance_fbo, gl::R32F, gl::RED, gl::FLOAT),
+            (&mut self.scene, &self.scene_fbo, gl::RGBA16F, gl::RGBA, gl::HALF_FLOAT),
+        ];
+        for (texture, fbo, internal, format, kind) in textures {
+            let bytes = if internal == gl::RGBA16F { 8 } else { 4 };
+            texture.resize(texels * bytes);
+            gl::BindTexture(gl::TEXTURE_2D, texture.id());
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal as i32,
+                width,
+                height,
+                0,
+                format,
+                kind,
+                std::ptr::null(),
+            );
+            let attachment = if internal == gl::DEPTH24_STENCIL8 {
+                gl::DEPTH_STENCIL_ATTACHMENT
+            } else {
+                gl::COLOR_ATTACHMENT0
+            };
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, fbo.id());
+            gl::FramebufferTexture2D(gl::DRAW_FRAMEBUFFER, attachment, gl::TEXTURE_2D, texture.id(), 0);
+        }
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target as u32);
+    }
+
+    /// Start a view, in a window of `window_size`, within the `viewport` set
+    /// as x, y, width and height. Everything in it is infinitely far away
+    /// until captured.
+    pub unsafe fn begin(&mut self, window_size: (u32, u32), viewport: (i32, i32, u32, u32)) {
+        self.resize(window_size);
+        let (x, y, width, height) = viewport;
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.distance_fbo.id());
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::Scissor(x, y, width as i32, height as i32);
+        gl::ClearBufferfv(gl::COLOR, 0, [f32::INFINITY; 4].as_ptr());
+        gl::Disable(gl::SCISSOR_TEST);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target as u32);
+    }
+
+    /// Write the distance of what the pass just drawn with `perspective` and
+    /// `log_depth` covers, within the viewport set. Leaves the distance
+    /// shader active.
+    pub unsafe fn capture(&mut self, perspective: &glm::Mat4, log_depth: f32) {
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let [x, y, width, height] = viewport;
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target as u32);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.depth_fbo.id());
+        let (x1, y1) = (x + width, y + height);
+        gl::BlitFramebuffer(x, y, x1, y1, x, y, x1, y1, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.distance_fbo.id());
+        self.distance_shader.activate();
+        gl::BindVertexArray(self.vao.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth.id());
+        gl::Uniform1i(self.distance_shader.get_uniform_location("u_depth"), 0);
+        gl::Uniform2f(
+            self.distance_shader.get_uniform_location("u_projection"),
+            perspective[(2, 2)],
+            perspective[(2, 3)],
+        );
+        gl::Uniform1f(self.distance_shader.get_uniform_location("u_log_depth"), log_depth);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+    }
+
+    /// Blur the view in the framebuffer currently bound, within `viewport`,
+    /// with `focus` the distance in focus and `aperture` the blur radius in
+    /// pixels of what is infinitely far. Leaves the blur shader active.
+    pub unsafe fn draw(&mut self, focus: f32, aperture: f32, viewport: (i32, i32, u32, u32)) {
+        let (x, y, width, height) = viewport;
+        let (x1, y1) = (x + width as i32, y + height as i32);
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target as u32);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.scene_fbo.id());
+        gl::BlitFramebuffer(x, y, x1, y1, x, y, x1, y1, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+
+        let sh = &self.blur_shader;
+        sh.activate();
+        gl::BindVertexArray(self.vao.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.scene.id());
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, self.distance.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Uniform1i(sh.get_uniform_location("u_scene"), 0);
+        gl::Uniform1i(sh.get_uniform_location("u_distance"), 1);
+        gl::Uniform4f(
+            sh.get_uniform_location("u_viewport"),
+            x as f32,
+            y as f32,
+            width as f32,
+            height as f32,
+        );
+        gl::Uniform1f(sh.get_uniform_location("u_focus"), focus);
+        gl::Uniform1f(sh.get_uniform_location("u_aperture"), aperture);
+        gl::Uniform1f(sh.get_uniform_location("u_max_radius"), DOF_MAX_RADIUS);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
@@ -0,0 +1,139 @@
+//! Hydraulic and thermal erosion of terrain patches
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::globals::{
+    EROSION_BORDER, EROSION_CAPACITY, EROSION_DEPOSITION, EROSION_DROPLET_STEPS, EROSION_EVAPORATION,
+    EROSION_INERTIA, EROSION_RATE, EROSION_TALUS,
+};
+
+/// Passes over new patches, shared with the worker threads
+static ITERATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Erode new patches with `iterations` passes, 0 for none
+pub fn set_iterations(iterations: usize) {
+    ITERATIONS.store(iterations, Ordering::Relaxed);
+}
+
+/// Passes new patches are eroded with
+pub fn iterations() -> usize {
+    ITERATIONS.load(Ordering::Relaxed)
+}
+
+/// Height and slope at `(x, z)` in the grid, interpolated between vertices
+fn sample(heights: &[f32], res: usize, x: f32, z: f32) -> (f32, f32, f32) {
+    let (cx, cz) = (x.floor() as usize, z.floor() as usize);
+    let (fx, fz) = (x - cx as f32, z - cz as f32);
+    let i = cz * res + cx;
+    let (h00, h10, h01, h11) = (heights[i], heights[i + 1], heights[i + res], heights[i + res + 1]);
+    let height = h00 * (1.0 - fx) * (1.0 - fz) + h10 * fx * (1.0 - fz) + h01 * (1.0 - fx) * fz + h11 * fx * fz;
+    let gx = (h10 - h00) * (1.0 - fz) + (h11 - h01) * fz;
+    let gz = (h01 - h00) * (1.0 - fx) + (h11 - h10) * fx;
+    (height, gx, gz)
+}
+
+/// Add `amount` at `(x, z)`, spread over the vertices around it
+fn deposit(heights: &mut [f32], res: usize, x: f32, z: f32, amount: f32) {
+    let (cx, cz) = (x.floor() as usize, z.floor() as usize);
+    let (fx, fz) = (x - cx as f32, z - cz as f32);
+    let i = cz * res + cx;
+    heights[i] += amount * (1.0 - fx) * (1.0 - fz);
+    heights[i + 1] += amount * fx * (1.0 - fz);
+    heights[i + res] += amount * (1.0 - fx) * fz;
+    heights[i + res + 1] += amount * fx * fz;
+}
+
+/// Erode `heights`, a `res` by `res` grid of vertices `spacing` apart, in
+/// radii, with the current number of passes. Below `sea_level`, if any, drops
+/// stop. `seed` places the raindrops.
+pub fn erode(heights: &mut [f32], res: usize, spacing: f32, max_height: f32, sea_level: Option<f32>, seed: u64) {
+    let iterations = iterations();
+    if iterations == 0 || res < 3 || max_height <= 0.0 {
+        return;
+    }
+    // In max heights, so the rates hold for any planet
+    let original: Vec<f32> = heights.iter().map(|h| h / max_height).collect();
+    let mut h = original.clone();
+    let sea_level = sea_level.map(|level| level / max_height);
+    let talus = EROSION_TALUS * spacing / max_height;
+    let limit = (res - 1) as f32 - 1e-3;
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    for _ in 0..iterations {
+        //-Hydraulic-----------------------------------------------------------/
+        for _ in 0..res * res {
+            let (mut x, mut z) = (rng.gen_range(0.0..limit), rng.gen_range(0.0..limit));
+            let (mut dx, mut dz) = (0.0f32, 0.0f32);
+            let (mut speed, mut water, mut sediment) = (1.0f32, 1.0f32, 0.0f32);
+            for _ in 0..EROSION_DROPLET_STEPS {
+                let (height, gx, gz) = sample(&h, res, x, z);
+                if sea_level.is_some_and(|level| height < level) {
+                    deposit(&mut h, res, x, z, sediment);
+                    break;
+                }
+                // Downhill, keeping some of the way it was going
+                dx = dx * EROSION_INERTIA - gx * (1.0 - EROSION_INERTIA);
+                dz = dz * EROSION_INERTIA - gz * (1.0 - EROSION_INERTIA);
+                let length = (dx * dx + dz * dz).sqrt();
+                if length < 1e-6 {
+                    deposit(&mut h, res, x, z, sediment);
+                    break;
+                }
+                dx /= length;
+                dz /= length;
+                let (nx, nz) = (x + dx, z + dz);
+                if !(0.0..limit).contains(&nx) || !(0.0..limit).contains(&nz) {
+                    break;
+                }
+                let (new_height, _, _) = sample(&h, res, nx, nz);
+                let drop = height - new_height;
+                let capacity = (drop * speed * water * EROSION_CAPACITY).max(0.0);
+                if drop < 0.0 || sediment > capacity {
+                    // Fill the pit climbed out of, or drop what it can't carry
+                    let amount = if drop < 0.0 {
+                        sediment.min(-drop)
+                    } else {
+                        (sediment - capacity) * EROSION_DEPOSITION
+                    };
+                    sediment -= amount;
+                    deposit(&mut h, res, x, z, amount);
+                } else {
+                    let amount = ((capacity - sediment) * EROSION_RATE).min(drop);
+                    sediment += amount;
+                    deposit(&mut h, res, x, z, -amount);
+                }
+                speed = (speed * speed + drop).max(0.0).sqrt();
+                water *= 1.0 - EROSION_EVAPORATION;
+                x = nx;
+                z = nz;
+            }
+        }
+        //-Thermal-------------------------------------------------------------/
+        for z in 1..res - 1 {
+            for x in 1..res - 1 {
+                let i = z * res + x;
+                for j in [i - 1, i + 1, i - res, i + res] {
+                    let excess = h[i] - h[j] - talus;
+                    if excess > 0.0 {
+                        h[i] -= excess * 0.25;
+                        h[j] += excess * 0.25;
+                    }
+                }
+            }
+        }
+    }
+
+    // Fading in from the edges, which stay as they were
+    let border = EROSION_BORDER * (res - 1) as f32;
+    for z in 0..res {
+        for x in 0..res {
+            let edge = x.min(z).min(res - 1 - x).min(res - 1 - z) as f32;
+            let t = (edge / border).min(1.0);
+            let fade = t * t * (3.0 - 2.0 * t);
+            let i = z * res + x;
+            heights[i] = (original[i] + (h[i] - original[i]) * fade) * max_height;
+        }
+    }
+}
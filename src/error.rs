@@ -0,0 +1,70 @@
+//! Errors loading resources and setting up rendering
+
+use std::fmt;
+use std::sync::Mutex;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io { path: String, source: std::io::Error },
+    Shader { path: String, log: String }, // Failed to compile
+    Link { log: String },
+    Image { path: String, source: image::ImageError },
+    Model { path: String, source: tobj::LoadError },
+    Gltf { path: String, message: String }, // Malformed, or using what gltf.rs doesn't support
+    Lut { path: String, message: String },  // Not a colour grading LUT, see color_grading.rs
+    Context(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::Shader { path, log } => write!(f, "{} failed to compile: {}", path, log.trim()),
+            Error::Link { log } => write!(f, "Shader program failed to link: {}", log.trim()),
+            Error::Image { path, source } => write!(f, "{}: {}", path, source),
+            Error::Model { path, source } => write!(f, "{}: {}", path, source),
+            Error::Gltf { path, message } => write!(f, "{}: {}", path, message),
+            Error::Lut { path, message } => write!(f, "{}: {}", path, message),
+            Error::Context(message) => write!(f, "OpenGL context: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Image { source, .. } => Some(source),
+            Error::Model { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+static REPORTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Log an error recovered from, and keep it for the warning on screen
+pub fn report(error: &Error) {
+    eprintln!("Error: {}", error);
+    if let Ok(mut reported) = REPORTED.lock() {
+        reported.push(error.to_string());
+    }
+}
+
+/// One line warning about the errors reported, empty if there were none
+pub fn warning() -> String {
+    let reported = match REPORTED.lock() {
+        Ok(reported) => reported,
+        Err(_) => return String::new(),
+    };
+    let Some(first) = reported.first() else {
+        return String::new();
+    };
+    let first = first.lines().next().unwrap_or_default();
+    match reported.len() {
+        1 => format!("Warning: {}", first),
+        n => format!("Warning: {} (and {} more, see the log)", first, n - 1),
+    }
+}
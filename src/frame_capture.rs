@@ -0,0 +1,87 @@
+//! Frame capture trigger through the RenderDoc in-application API, with
+//! `--features renderdoc` and the application launched from RenderDoc
+
+#[cfg(feature = "renderdoc")]
+use renderdoc::{RenderDoc, V110};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CaptureState {
+    Idle,
+    Requested, // Start capturing at beginning of next frame
+    Capturing, // Currently capturing, end after swap_buffers
+}
+
+pub struct FrameCapture {
+    #[cfg(feature = "renderdoc")]
+    rd: Option<RenderDoc<V110>>,
+    state: CaptureState,
+    pub captures: usize, // Number of completed captures
+}
+
+impl FrameCapture {
+    /// Try to connect to RenderDoc. Falls back to a no-op if it's unavailable
+    pub fn new() -> Self {
+        #[cfg(feature = "renderdoc")]
+        let rd = match RenderDoc::<V110>::new() {
+            Ok(rd) => {
+                eprintln!("RenderDoc API loaded, frame capture available");
+                Some(rd)
+            }
+            Err(e) => {
+                eprintln!("RenderDoc not available, frame capture disabled ({})", e);
+                None
+            }
+        };
+        FrameCapture {
+            #[cfg(feature = "renderdoc")]
+            rd,
+            state: CaptureState::Idle,
+            captures: 0,
+        }
+    }
+
+    /// True if captures can actually be made
+    pub fn available(&self) -> bool {
+        #[cfg(feature = "renderdoc")]
+        return self.rd.is_some();
+        #[cfg(not(feature = "renderdoc"))]
+        return false;
+    }
+
+    /// Request a capture of the next frame
+    pub fn request(&mut self) {
+        if !self.available() {
+            eprintln!("Frame capture requested, but RenderDoc is not available");
+            return;
+        }
+        if self.state == CaptureState::Idle {
+            self.state = CaptureState::Requested;
+        }
+    }
+
+    /// Call at the very beginning of a frame, before any GL work
+    pub fn begin_frame(&mut self) {
+        if self.state != CaptureState::Requested {
+            return;
+        }
+        #[cfg(feature = "renderdoc")]
+        if let Some(rd) = self.rd.as_mut() {
+            rd.start_frame_capture(std::ptr::null(), std::ptr::null());
+        }
+        self.state = CaptureState::Capturing;
+    }
+
+    /// Call right after `swap_buffers`
+    pub fn end_frame(&mut self) {
+        if self.state != CaptureState::Capturing {
+            return;
+        }
+        #[cfg(feature = "renderdoc")]
+        if let Some(rd) = self.rd.as_mut() {
+            rd.end_frame_capture(std::ptr::null(), std::ptr::null());
+        }
+        self.captures += 1;
+        eprintln!("Captured frame ({} captures so far)", self.captures);
+        self.state = CaptureState::Idle;
+    }
+}
@@ -1,37 +1,43 @@
 #[allow(unused_imports)]
 use std::thread;
-use std::sync::{Mutex, Arc};
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use nalgebra_glm as glm;
-use glutin::event::{
-    VirtualKeyCode
-};
 
 use crate::*;
 use crate::player::PlayerState;
 use crate::procedural_planet as planet;
-use crate::texture::load_texture;
-use crate::scene_graph::{SceneNode, SceneNodeType};
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
 use crate::globals::*;
 
 const POLYMODES: [u32;3] = [gl::FILL, gl::POINT, gl::LINE];
+// Depth partitions (near, far), drawn back to front with cleared depth. The
+// furthest pass has no far plane, so distant bodies are never clipped.
+const CLIPPING_PASSES: [(f32, f32); 4] = [
+    (125.0, f32::INFINITY),
+    (2.5, 1250.0),
+    (0.005, 25.0),
+    (0.0005, 2.5),
+];
+// A logarithmic depth buffer spans everything in one pass, limited to the
+// clipping planes
+const LOG_DEPTH_PASSES: [(f32, f32); 1] = [(0.0, f32::MAX)];
 
 
 /// Initializes game ad runs main game loop
 pub fn game(
-    mouse_delta: Arc<Mutex<(f32, f32)>>,
-    pressed_keys: Arc<Mutex<Vec<VirtualKeyCode>>>,
+    mut conf: util::Config,
+    args: cli::Args,
+    input: Arc<input::InputState>,
+    gui_events: debug_panel::GuiEvents,
     context: glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>
 ) {
 
     let setup_timer = std::time::SystemTime::now();
 
 
-    //-------------------------------------------------------------------------/
-    // Read config
-    //-------------------------------------------------------------------------/
-    let mut conf = util::Config::load();
+    // Apply changes to the settings file while running
+    let mut conf_watcher = util::ConfigWatcher::new();
 
     let mut player = player::Player {
         height: conf.player_height,
@@ -53,17 +59,47 @@ pub fn game(
                     "./resources/shaders/noise.glsl",
                 ])
             )
-            .link();
+            .link_or_error_shader();
 
         sh.activate();
+        gl::Uniform1i(sh.get_uniform_location("u_nebula"), nebula::NEBULA_UNIT as i32);
+        gl::Uniform1i(
+            sh.get_uniform_location("u_normal_map"),
+            render_queue::NORMAL_MAP_UNIT as i32,
+        );
+        gl::Uniform1i(sh.get_uniform_location("u_detail_map"), texture::DETAIL_MAP_UNIT as i32);
+        gl::Uniform1i(sh.get_uniform_location("u_terrain_map"), texture::TERRAIN_MAP_UNIT as i32);
+        gl::Uniform1i(sh.get_uniform_location("u_city_lights"), city_lights::CITY_LIGHTS_UNIT as i32);
+        gl::Uniform1i(
+            sh.get_uniform_location("u_reflection"),
+            render_queue::REFLECTION_UNIT as i32,
+        );
+        gl::Uniform1i(sh.get_uniform_location("u_irradiance"), ibl::IRRADIANCE_UNIT as i32);
+        gl::Uniform1i(sh.get_uniform_location("u_environment"), ibl::SPECULAR_UNIT as i32);
         sh
     };
+    // GUI and text are drawn flat, without the lighting of the scene shader
+    let gui_program = unsafe {
+        shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/gui.vert", None)
+            .attach_file("./resources/shaders/gui.frag", None)
+            .link()
+            .map(|gui| gui.program_id)
+            .map_err(|e| error::report(&e))
+            .ok()
+    };
+    shader::set_program_for(SceneNodeType::Geometry2d, gui_program);
+    // The scene shader without branching on the node type, built when first drawn
+    let mut scene_variants = shader::ShaderVariants::new(&[
+        ("./resources/shaders/scene.vert", &[]),
+        ("./resources/shaders/scene.frag", &["./resources/shaders/noise.glsl"]),
+    ]);
     eprintln!("took {:?}", timer.elapsed());
 
     //-------------------------------------------------------------------------/
-    // Load charmap texture
+    // Load font
     //-------------------------------------------------------------------------/
-    let charmap_id = load_texture("resources/textures/charmap.png");
+    let font = text::Font::charmap();
 
 
     //-------------------------------------------------------------------------/
@@ -84,83 +120,67 @@ pub fn game(
     // GUI meshes
     //-------------------------------------------------------------------------/
     let text_scale = 0.6;
-    let text_title = mesh::Mesh::text_buffer("PROCEDURAL PLANETS", 49.0 / 29.0, 1.0);
-    let mut text_title_node = SceneNode::from_vao(unsafe { text_title.mkvao() });
-    text_title_node.node_type = SceneNodeType::Geometry2d;
-    text_title_node.texture_id = Some(charmap_id);
-    text_title_node.position = glm::vec3(-0.5, 0.7, 0.0);
-    text_title_node.scale = glm::vec3(1.0, 1.0, 1.0);
-
-    let mut text_pos_mesh = mesh::Mesh::text_buffer("N/A", 49.0 / 29.0, 1.0);
-    let mut text_pos_node = SceneNode::from_vao(unsafe { text_pos_mesh.mkvao() });
-    text_pos_node.node_type = SceneNodeType::Geometry2d;
-    text_pos_node.texture_id = Some(charmap_id);
-    text_pos_node.position = glm::vec3(-1.0, -1.0 + text_scale * 0.05 * 0.0, 0.0);
-    text_pos_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-
-    #[allow(unused_assignments)]
-    let mut text_pstate_mesh = mesh::Mesh::text_buffer("N/A", 49.0 / 29.0, 1.0);
-    let mut text_pstate_node = SceneNode::from_vao(unsafe { text_pos_mesh.mkvao() });
-    text_pstate_node.node_type = SceneNodeType::Geometry2d;
-    text_pstate_node.texture_id = Some(charmap_id);
-    text_pstate_node.position = glm::vec3(-1.0, -1.0 + text_scale * 0.05 * 1.0, 0.0);
-    text_pstate_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-
-    #[allow(unused_assignments)]
-    let mut text_mspeed_mesh = mesh::Mesh::text_buffer("N/A", 49.0 / 29.0, 1.0);
-    let mut text_mspeed_node = SceneNode::from_vao(unsafe { text_mspeed_mesh.mkvao() });
-    text_mspeed_node.node_type = SceneNodeType::Geometry2d;
-    text_mspeed_node.texture_id = Some(charmap_id);
-    text_mspeed_node.position = glm::vec3(-1.0, -1.0 + text_scale * 0.05 * 2.0, 0.0);
-    text_mspeed_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-
-    #[allow(unused_assignments)]
-    let mut text_closest_mesh = mesh::Mesh::text_buffer("N/A", 49.0 / 29.0, 1.0);
-    let mut text_closest_node = SceneNode::from_vao(unsafe { text_closest_mesh.mkvao() });
-    text_closest_node.node_type = SceneNodeType::Geometry2d;
-    text_closest_node.texture_id = Some(charmap_id);
-    text_closest_node.position = glm::vec3(-1.0, -1.0 + text_scale * 0.05 * 3.0, 0.0);
-    text_closest_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-
-    #[allow(unused_assignments)]
-    let mut text_height_mesh = mesh::Mesh::text_buffer("N/A", 49.0 / 29.0, 1.0);
-    let mut text_height_node = SceneNode::from_vao(unsafe { text_height_mesh.mkvao() });
-    text_height_node.node_type = SceneNodeType::Geometry2d;
-    text_height_node.texture_id = Some(charmap_id);
-    text_height_node.position = glm::vec3(-1.0, -1.0 + text_scale * 0.05 * 4.0, 0.0);
-    text_height_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-
-    #[allow(unused_assignments)]
-    let mut text_mouse_mesh = mesh::Mesh::text_buffer("N/A", 49.0 / 29.0, 1.0);
-    let mut text_mouse_node = SceneNode::from_vao(unsafe { text_mouse_mesh.mkvao() });
-    text_mouse_node.node_type = SceneNodeType::Geometry2d;
-    text_mouse_node.texture_id = Some(charmap_id);
-    text_mouse_node.position = glm::vec3(-1.0, -1.0 + text_scale * 0.05 * 5.0, 0.0);
-    text_mouse_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-
-    #[allow(unused_assignments)]
-    let mut text_gfxmem_mesh = mesh::Mesh::text_buffer("N/A", 49.0 / 29.0, 1.0);
-    let mut text_gfxmem_node = SceneNode::from_vao(unsafe { text_gfxmem_mesh.mkvao() });
-    text_gfxmem_node.node_type = SceneNodeType::Geometry2d;
-    text_gfxmem_node.texture_id = Some(charmap_id);
-    text_gfxmem_node.position = glm::vec3(-1.0, -1.0 + text_scale * 0.05 * 6.0, 0.0);
-    text_gfxmem_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-
-    let controls_text = [
+    let hud_line = |i: usize| glm::vec2(-1.0, -1.0 + text_scale * text::LINE_HEIGHT * i as f32);
+    let mut text_pos = text::Text::new(&font, "N/A", hud_line(0), text_scale);
+    let mut text_pstate = text::Text::new(&font, "N/A", hud_line(1), text_scale);
+    let mut text_mspeed = text::Text::new(&font, "N/A", hud_line(2), text_scale);
+    let mut text_closest = text::Text::new(&font, "N/A", hud_line(3), text_scale);
+    let mut text_height = text::Text::new(&font, "N/A", hud_line(4), text_scale);
+    let mut text_mouse = text::Text::new(&font, "N/A", hud_line(5), text_scale);
+    let mut text_gfxmem = text::Text::new(&font, "N/A", hud_line(6), text_scale);
+    // Resources that failed to load, replaced by placeholders
+    let mut text_warning = text::Text::new(&font, "", hud_line(7), text_scale);
+    // Simulated date and time scale
+    let mut text_time = text::Text::new(&font, "N/A", hud_line(8), text_scale);
+
+    // Frame statistics, toggled with F3
+    let mut stats_overlay = stats_overlay::StatsOverlay::new(&font);
+    let mut frame_stats = stats_overlay::FrameStats::default();
+    // Names over planets and props
+    let mut labels = labels::Labels::new(&font);
+    // Crosshair, lit up over bodies
+    let mut reticle = reticle::Reticle::new(&font);
+    // Facts about the selected planet
+    let mut body_info = body_info::BodyInfo::new(&font);
+    // GPU time of each part of the frame, shown with the statistics or
+    // written to --profile-csv
+    let mut gpu_profiler = match &args.profile_csv {
+        Some(path) => profiler::GpuProfiler::new()
+            .with_csv(path)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {}", path, e)),
+        None => profiler::GpuProfiler::new(),
+    };
+    // CPU time of named scopes, shown with the statistics or written to
+    // --profile-trace
+    if let Some(path) = &args.profile_trace {
+        profiler::cpu()
+            .trace_to(path)
+            .unwrap_or_else(|e| panic!("Failed to create {}: {}", path, e));
+    }
+
+    let controls_text: Vec<text::Text> = [
         "WSAD/SHIFT/SPACE : movement",
         "UP/DOWN : increase and decrease movement speed",
-        "F : cycle player state (free/anchored/landed)",
+        "TAB : cycle camera mode (free fly/orbital/first person/third person/ship)",
+        "F : fly to the selected body",
+        "Z/C : roll the ship",
         "I : toggle text interface",
         "M : cycle polygon modes",
+        "V : cycle debug render modes",
+        "B : toggle bounding volume lines",
+        "F3 : toggle frame statistics",
+        "F1 : toggle tweak panel",
+        "F5 : save scene",
+        "F9 : reload scene",
+        "P : pause time, and the camera path",
+        "N : step paused time",
+        ", / . : slow down and speed up time",
+        "[, ] : scrub the camera path",
+        "1-9 : fly to a bookmark, CTRL+1-9 : save one",
     ].iter().enumerate().map(|(i, s)| {
-        let text_mesh = mesh::Mesh::text_buffer(s, 49.0 / 29.0, 1.0 * s.len() as f32 / 28.0);
-        let mut text_node = SceneNode::from_vao(unsafe { text_mesh.mkvao() });
-        text_node.node_type = SceneNodeType::Geometry2d;
-        text_node.texture_id = Some(charmap_id);
-        text_node.position = glm::vec3(-1.0, 1.0 - text_scale * 0.05 * (i+1) as f32, 0.0);
-        text_node.scale = glm::vec3(1.0, 1.0, 1.0) * text_scale;
-        text_node
-    });
+        let anchor = glm::vec2(-1.0, 1.0 - text_scale * text::LINE_HEIGHT * (i+1) as f32);
+        text::Text::new(&font, s, anchor, text_scale)
+    }).collect();
 
 
     //-------------------------------------------------------------------------/
@@ -177,16 +197,70 @@ pub fn game(
     let mut skybox_node = SceneNode::from_vao(unsafe { skybox_mesh.mkvao() });
     skybox_node.node_type = SceneNodeType::Skybox;
 
+    // Real stars over the skybox, if a catalog is given
+    let starfield = if conf.star_catalog.is_empty() {
+        None
+    } else {
+        match unsafe { starfield::Starfield::load(&conf.star_catalog) } {
+            Ok(starfield) => {
+                eprintln!("Loaded {} stars from {}", starfield.count(), conf.star_catalog);
+                Some(starfield)
+            }
+            Err(e) => {
+                eprintln!("Failed to load star catalog {}", e);
+                None
+            }
+        }
+    };
+    unsafe { sh.activate() };
+    // Baked when first shown
+    // Gamma-correct where the window can encode sRGB, before loading textures
+    color::set_enabled(conf.srgb && (args.headless || unsafe { color::window_is_srgb() }));
+    if conf.srgb && !color::enabled() {
+        eprintln!("No sRGB-capable framebuffer, colours are lit as picked");
+    }
+    // Film look, if a LUT is given
+    let mut color_grading = if conf.color_lut.is_empty() {
+        None
+    } else {
+        unsafe { color_grading::ColorGrading::load(&conf.color_lut) }
+            .map_err(|e| error::report(&e))
+            .ok()
+    };
+    let mut nebula: Option<nebula::Nebula> = None;
+    let mut environment: Option<ibl::EnvironmentLight> = None;
+    // Every part of the world from one seed, each drawing its own numbers
+    eprintln!("World seed {}", conf.seed);
+    let planet_seed = util::derive_seed(conf.seed, "planets") as u32;
+    let detail_map = texture::detail_normal_map(util::derive_seed(conf.seed, "detail") as u32, DETAIL_MAP_SIZE);
+    let terrain_map = texture::terrain_detail_map(util::derive_seed(conf.seed, "terrain") as u32, TERRAIN_MAP_SIZE);
+    // Terrain displaced in a compute shader where supported
+    unsafe { terrain_compute::init() };
+    // Patches drawn in batches where supported
+    unsafe { terrain_batch::init() };
+    // And tessellated, when turned on
+    unsafe { terrain_tessellation::init() };
+    // Normals drawn over marked nodes, for debugging meshes
+    unsafe { normal_debug::init() };
+
 
     //-------------------------------------------------------------------------/
     // Scene setup, build planets
     //-------------------------------------------------------------------------/
-    let (mut planets, mut planet_nodes, lightsources) = scene::create_scene();
+    // GPU objects made for the scene are freed together when reloading it with F9
+    let mut scene_scope = gpu_resources::begin_scope();
+    let (mut scene_description, (mut planets, mut planet_nodes, mut lightsources, mut props)) =
+        build_scene(&args, planet_seed).unwrap_or_else(|e| panic!("{}", e));
+    gpu_resources::end_scope();
     //-------------------------------------------------------------------------/
     // Organize planets and nodes
     //-------------------------------------------------------------------------/
 
     player.closest_planet_id  = 0;
+    // Camera mode from settings.conf or --camera-mode, cycled with Tab
+    let mut cameras = camera::Cameras::default();
+    cameras.select(conf.camera_position, &mut player, &planets[0]);
+    let mut bookmarks = bookmarks::Bookmarks::load(&conf.bookmarks);
 
 
     //-------------------------------------------------------------------------/
@@ -197,21 +271,32 @@ pub fn game(
     for planet in &planet_nodes {
         scene_root.add_child(planet);
     }
+    for prop in props.iter().filter(|prop| prop.node_type != SceneNodeType::Geometry2d) {
+        scene_root.add_child(prop);
+    }
+    // Made when the ship is first flown, and left in the scene after
+    let mut ship_node: Option<Node> = None;
+    // Only moved nodes get new transformations, all of them when this changes
+    let mut double_precision = conf.double_precision;
 
 
     //-------------------------------------------------------------------------/
     // Build GUI
     //-------------------------------------------------------------------------/
     let mut gui_root = SceneNode::new();
-    //gui_root.add_child(&text_title_node);
-    gui_root.add_child(&text_pos_node);
-    gui_root.add_child(&text_pstate_node);
-    gui_root.add_child(&text_mspeed_node);
-    gui_root.add_child(&text_closest_node);
-    gui_root.add_child(&text_height_node);
-    gui_root.add_child(&text_mouse_node);
-    gui_root.add_child(&text_gfxmem_node);
-    controls_text.for_each(|nd| gui_root.add_child(&nd));
+    for label in [
+        &text_pos, &text_pstate, &text_mspeed, &text_closest,
+        &text_height, &text_mouse, &text_gfxmem, &text_warning, &text_time,
+    ] {
+        gui_root.add_child(&label.node);
+    }
+    for label in &controls_text {
+        gui_root.add_child(&label.node);
+    }
+    let gui_labels = gui_root.get_n_children();
+    for prop in props.iter().filter(|prop| prop.node_type == SceneNodeType::Geometry2d) {
+        gui_root.add_child(prop);
+    }
 
 
     //-------------------------------------------------------------------------/
@@ -219,10 +304,129 @@ pub fn game(
     //-------------------------------------------------------------------------/
     let first_frame_time = std::time::Instant::now();
     let mut last_frame_time = first_frame_time;
+    // Time of orbits, ocean waves, particles and props, which can be paused
+    // and scaled
+    let mut clock = simulation::SimulationClock::new();
+    // Planets pulling on each other, while turned on, see nbody.rs
+    let mut nbody: Option<nbody::NBody> = None;
 
-    let mut key_debounce: HashMap<VirtualKeyCode, u32> = HashMap::new();
     let mut frame_counter: u64 = 0;
 
+    // Evict least recently visible terrain when over budget
+    let mut memory_budget = memory_budget::MemoryBudget::new(
+        conf.budget_terrain_mb,
+        conf.budget_impostor_mb,
+    );
+    // Spread uploads of generated terrain over frames
+    let mut terrain_uploads = terrain_uploads::TerrainUploads::new();
+
+    // Throttle simulation updates of far away bodies
+    let mut roi = simulation::RegionOfInterest::new(conf.roi_radius, conf.roi_max_interval);
+    // Props tagged dynamic fall onto the nearest planet
+    let mut physics = physics::Physics::new();
+
+    // RenderDoc frame capture, no-op unless built with the renderdoc feature
+    let mut frame_capture = frame_capture::FrameCapture::new();
+
+    // Sticks and triggers, no-op unless built with the gamepad feature
+    let mut gamepad = gamepad::Gamepad::new();
+
+    // Lines for visualizing bounding volumes and such
+    let mut debug_lines = unsafe {
+        let debug_lines = debug_draw::DebugDraw::new();
+        sh.activate();
+        debug_lines
+    };
+
+    // Exhaust, impact dust and snow
+    let mut particles = unsafe {
+        let particles = particles::ParticleSystem::new();
+        sh.activate();
+        particles
+    };
+    let impact = particles::Emitter::preset(particles::Preset::Impact);
+
+    // Grass and trees streamed in around the camera
+    let mut vegetation = unsafe {
+        let vegetation = vegetation::Vegetation::new();
+        sh.activate();
+        vegetation
+    };
+
+    // Flare when looking towards a light source, probed in each view
+    let mut lens_flares: Vec<_> = (0..MAX_VIEWS)
+        .map(|_| unsafe {
+            let lens_flare = lens_flare::LensFlare::new();
+            sh.activate();
+            lens_flare
+        })
+        .collect();
+    // Rays past planets covering a light source
+    let mut light_shafts = unsafe {
+        let light_shafts = light_shafts::LightShafts::new();
+        sh.activate();
+        light_shafts
+    };
+    // Blur out of focus
+    let mut depth_of_field = unsafe {
+        let depth_of_field = depth_of_field::DepthOfField::new();
+        sh.activate();
+        depth_of_field
+    };
+    // Blur along the motion on screen
+    let mut motion_blur = unsafe {
+        let motion_blur = motion_blur::MotionBlur::new();
+        sh.activate();
+        motion_blur
+    };
+    // Solar system from above, in a corner
+    let mut minimap = unsafe {
+        let minimap = minimap::Minimap::new();
+        sh.activate();
+        minimap
+    };
+    // Orbits drawn through the scene
+    let mut orbit_paths = unsafe {
+        let orbit_paths = orbit_paths::OrbitPaths::new();
+        sh.activate();
+        orbit_paths
+    };
+    // Bodies hidden behind planets, in each view
+    let mut occlusions: Vec<_> = (0..MAX_VIEWS)
+        .map(|_| unsafe {
+            let occlusion = occlusion::OcclusionCulling::new();
+            sh.activate();
+            occlusion
+        })
+        .collect();
+    // Billboards of planets too small on screen to be worth drawing in full
+    let mut impostors: Vec<_> = (0..MAX_VIEWS)
+        .map(|view| unsafe {
+            let impostors = impostor::Impostors::new(view);
+            sh.activate();
+            impostors
+        })
+        .collect();
+
+    // Surroundings of planets, reflected by their oceans
+    let mut reflections = unsafe {
+        let reflections = reflections::OceanReflections::new();
+        sh.activate();
+        reflections
+    };
+
+    // Sliders for tweaking settings live, toggled with F1
+    let mut debug_panel = unsafe {
+        let debug_panel = debug_panel::DebugPanel::new(&context, gui_events);
+        sh.activate();
+        debug_panel
+    };
+
+    // Draw commands collected from the scene graph, sorted before submission
+    let mut render_queue = render_queue::RenderQueue::new();
+    // Planet selected by clicking on it, outlined and edited in the tweak panel
+    let mut selected: Option<usize> = None;
+
 
     //-------------------------------------------------------------------------/
     //-------------------------------------------------------------------------/
@@ -236,21 +440,115 @@ pub fn game(
     );
     let mut scaled = true;
 
+    // Render to a framebuffer object instead of the hidden window with --headless
+    let offscreen = args.headless.then(|| unsafe {
+        offscreen::Framebuffer::new((conf.screen_width, conf.screen_height))
+            .unwrap_or_else(|e| panic!("Failed to create offscreen framebuffer: {}", e))
+    });
+    let window_size = || match &offscreen {
+        Some(framebuffer) => framebuffer.size,
+        None => context.window().inner_size().into(),
+    };
+
+    // Camera moved along keyframes with --camera-path
+    let camera_path = args.camera_path.as_ref().map(|path| {
+        camera_path::CameraPath::load(path)
+            .unwrap_or_else(|e| panic!("Failed to load camera path {}", e))
+    });
+    let mut playback = camera_path::Playback::default();
+
+    // Offline recording with a fixed timestep, started with --record
+    let mut recorder = args.record.as_ref().map(|path| {
+        unsafe { recording::Recorder::new(path, args.record_fps, window_size()) }
+            .unwrap_or_else(|e| panic!("Failed to start recording: {}", e))
+    });
+    let mut unsettled_frames = 0;
+    // Headless rendering stops by itself, after one frame or the camera path
+    let record_frames = args.record_frames.or_else(|| {
+        args.headless.then(|| {
+            camera_path.as_ref().map_or(1, |path| {
+                (path.duration() * args.record_fps as f32).ceil() as u64 + 1
+            })
+        })
+    });
+
     loop {
+        frame_capture.begin_frame();
+        memory_budget.begin_frame(frame_counter);
+        render_queue.reset_stats();
+        if let Some(changed) = conf_watcher.poll() {
+            if let Err(e) = conf.update_from(&changed) {
+                eprintln!("Failed to apply {}: {}", util::CONFIG_PATH, e);
+            }
+            args.apply(&mut conf);
+            player.height = conf.player_height;
+            // In place, so throttled orbits carry on as they were unless these changed
+            roi.radius = conf.roi_radius;
+            roi.max_interval = conf.roi_max_interval.max(1);
+        }
+        // Finish terrain and other jobs needing the GL context
+        let mut terrain_settled = true;
+        if recorder.is_some() {
+            // No realtime constraint, let terrain catch up instead of popping in
+            while jobs::global().pending() > 0 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            terrain_settled =
+                jobs::global().drain_completions(std::time::Duration::from_secs(60)) == 0;
+            unsafe { texture::stream_uploads(0) };
+        } else {
+            jobs::global().drain_completions(std::time::Duration::from_millis(JOB_DRAIN_BUDGET_MS));
+            unsafe { texture::stream_uploads(conf.budget_texture_upload_kb * 1024) };
+        }
         let now = std::time::Instant::now();
-        let elapsed = now.duration_since(first_frame_time).as_secs_f32();
-        let delta_time = now.duration_since(last_frame_time).as_secs_f32();
+        let (elapsed, delta_time) = match &recorder {
+            Some(recorder) => recorder.frame_time(recorder.frames()),
+            None => (
+                now.duration_since(first_frame_time).as_secs_f32(),
+                now.duration_since(last_frame_time).as_secs_f32(),
+            ),
+        };
         last_frame_time = now;
+        let steps = clock.tick(delta_time);
+        // Drawn between the last two updates, so it moves smoothly
+        let sim_time = clock.render_time();
+        let orbit_time = clock.render_seconds();
+        let stepped_delta = clock.stepped_delta();
+
+        // Simulated orbits start from the fixed ones when turned on
+        let trajectory_scale = if scaled { 1.0 } else { SCALING_FACTOR };
+        match (conf.nbody, nbody.as_mut()) {
+            (false, _) => nbody = None,
+            (true, None) => {
+                nbody = Some(nbody::NBody::new(&planets, orbit_time, trajectory_scale, conf.nbody_integrator))
+            }
+            (true, Some(nbody)) => {
+                nbody.integrator = conf.nbody_integrator;
+                for _ in 0..steps {
+                    nbody.step(SIMULATION_STEP);
+                }
+            }
+        }
+        // Position of a planet relative to its parent
+        let orbit_offset = |planet: &planet::Planet, scaled: bool| match nbody.as_ref() {
+            Some(nbody) => {
+                let scale = if scaled { 1.0 } else { SCALING_FACTOR };
+                nbody.offset(planet.planet_id, planet.parent_id, clock.alpha()) * scale
+            }
+            None => nbody::kepler_offset(planet, orbit_time),
+        };
 
-        key_debounce.iter_mut().for_each(|(_, v)| if *v > 0 { *v -= 1; });
-
+        // Where the scene's root is, to move the parked ship along with it
+        let root_before = (planet_nodes[0].position(), if scaled { 1.0 } else { SCALING_FACTOR });
         let mut computed = vec![];
+        let rescaling = matches!(player.state, PlayerState::Anchored(_) | PlayerState::Landed(_)) == scaled;
         if matches!(player.state, PlayerState::Anchored(_) | PlayerState::Landed(_)) {
             if scaled {
                 // Scale up
                 player.position *= SCALING_FACTOR;
                 for i in 0..planets.len() {
-                    planet_nodes[i].scale *= SCALING_FACTOR;
+                    let scale = planet_nodes[i].scale() * SCALING_FACTOR;
+                    planet_nodes[i].set_scale(scale);
                     planets[i].trajectory *= SCALING_FACTOR;
                 }
                 scaled = false;
@@ -258,28 +556,24 @@ pub fn game(
             // Reverse origin if player is anchored, origin of scene at center of closest planet
             let mut idx = player.closest_planet_id as usize;
             computed.push(idx);
-            planet_nodes[idx].position = glm::zero();
+            planet_nodes[idx].set_position(glm::zero());
             planets[idx].position = glm::zero();
-            planet_nodes[idx].rotation = glm::zero();
+            planet_nodes[idx].set_rotation(glm::zero());
 
             while planets[idx].planet_id != planets[idx].parent_id {
                 let idx_next = planets[idx].parent_id;
                 computed.push(idx_next);
-                let angle = planets[idx].traj_speed * WORLD_SPEED * elapsed + planets[idx].traj_init_angle.x;
-                let traj_position = glm::vec3(
-                    (angle).sin() * planets[idx].trajectory,
-                    planets[idx].traj_init_angle.y,
-                    (angle).cos() * planets[idx].trajectory,
-                );
+                let traj_position = orbit_offset(&planets[idx], scaled);
                 // let rotation = planets[idx].rot_speed * WORLD_SPEED * elapsed + planets[idx].rot_init_angle;
                 // let rotation_vec = planet_nodes[idx].rotation + rotation * planets[idx].rot_axis;
-                planet_nodes[idx_next].position = planets[idx].position - traj_position;
+                let position = planet_nodes[idx].position_f64() - traj_position.cast();
+                planet_nodes[idx_next].set_position_f64(position);
                     // - glm::rotate_vec3(
                     //     &traj_position,
                     //     -rotation,
                     //     &planets[idx].rot_axis
                     // );
-                planets[idx_next].position = planet_nodes[idx_next].position;
+                planets[idx_next].position = planet_nodes[idx_next].position();
                 // planet_nodes[idx_next].rotation = -rotation_vec;
                 idx = idx_next;
             }
@@ -287,10 +581,12 @@ pub fn game(
         else if !scaled {
             // Scale down
             player.position /= SCALING_FACTOR;
-            planet_nodes[0].position /= SCALING_FACTOR;
+            let position = planet_nodes[0].position() / SCALING_FACTOR;
+            planet_nodes[0].set_position(position);
 
             for i in 0..planets.len() {
-                planet_nodes[i].scale /= SCALING_FACTOR;
+                let scale = planet_nodes[i].scale() / SCALING_FACTOR;
+                planet_nodes[i].set_scale(scale);
                 planets[i].trajectory /= SCALING_FACTOR;
             }
             scaled = true;
@@ -298,33 +594,40 @@ pub fn game(
 
         // Planet trajectories, skip any that have already been computed
         // Skip 0 because sun is either origin or computed beforehand
+        // Far away planets only recompute their trajectory every few frames
+        roi.reset_stats();
         for i in (1..planets.len()).filter(|i| !computed.contains(i)) {
-            // Origin of trajectory
-            let origin = planet_nodes[planets[i].parent_id].position;
-            // Angle of position to inital position
-            let angle = planets[i].traj_speed * WORLD_SPEED * elapsed
-                + planets[i].traj_init_angle.x;
-            // planet_nodes[i].rotation: angle rotation around each axis
-            // // Parent's rotation
-            // let parent_rotation = planets[planets[i].parent_id].rot_speed * WORLD_SPEED * elapsed
-            //     + planets[planets[i].parent_id].rot_init_angle;
-
-            // Trajectory position relative to parent
-            let traj_position = glm::vec3(
-                (angle).sin() * planets[i].trajectory,
-                planets[i].traj_init_angle.y,
-                (angle).cos() * planets[i].trajectory,
-            );
+            // Origin of trajectory, in double precision down chains of moons
+            let origin = planet_nodes[planets[i].parent_id].position_f64();
+            let distance = glm::length(&(planets[i].position - player.position));
+            // Trajectories change with scaling, so recompute everything then
+            // Simulated orbits aren't recomputed later, so they can't be skipped
+            // Nor can the first, or the planet would sit at its parent's center
+            if nbody.is_some()
+                || rescaling
+                || planets[i].traj_offset.is_none()
+                || roi.should_update(distance, frame_counter, i)
+            {
+                // planet_nodes[i].rotation: angle rotation around each axis
+                // // Parent's rotation
+                // let parent_rotation = planets[planets[i].parent_id].rot_speed * WORLD_SPEED * elapsed
+                //     + planets[planets[i].parent_id].rot_init_angle;
+
+                // Trajectory position relative to parent
+                planets[i].traj_offset = Some(orbit_offset(&planets[i], scaled));
+            }
+            // Keep following the parent even when not updated
+            let traj_position = planets[i].traj_offset.unwrap_or_default();
 
             // Rotate back and add origin to get global position
             // - or keep relative rotation as a feature?
-            planet_nodes[i].position = origin + traj_position;
+            planet_nodes[i].set_position_f64(origin + traj_position.cast());
                 // + glm::rotate_vec3(
                 //     &traj_position,
                 //     parent_rotation,
                 //     &-planets[planets[i].parent_id].rot_axis
                 // );
-            planets[i].position = planet_nodes[i].position;
+            planets[i].position = planet_nodes[i].position();
 
             // // Planet rotation
             // let rotation = planets[i].rot_speed * WORLD_SPEED * elapsed
@@ -332,41 +635,201 @@ pub fn game(
             // let rotation_vec = planet_nodes[planets[i].parent_id].rotation + rotation * planets[i].rot_axis;
             // planet_nodes[i].rotation = rotation_vec;
         }
+        let root_after = (planet_nodes[0].position(), if scaled { 1.0 } else { SCALING_FACTOR });
+        if root_after != root_before {
+            cameras.rebase(&root_before.0, &root_after.0, root_after.1 / root_before.1);
+        }
+        if conf.orbit_paths {
+            let scale = if scaled { 1.0 } else { SCALING_FACTOR };
+            unsafe { orbit_paths.update(&planets, nbody.as_ref(), scale) };
+        }
+
+        //---------------------------------------------------------------------/
+        // Fixed timestep update, drawn between the last two steps
+        //---------------------------------------------------------------------/
+        // Particles are in world space, which moves when rescaling
+        if rescaling {
+            particles.clear();
+        }
+        for _ in 0..steps {
+            update(
+                SIMULATION_STEP,
+                &mut physics,
+                &mut particles,
+                &mut props,
+                &planets,
+                &planet_nodes,
+                &mut scene_root,
+                &impact,
+            );
+        }
+        physics.place(&mut props, &planet_nodes, clock.alpha());
 
         //---------------------------------------------------------------------/
         // Handle keyboard and mouse input
         //---------------------------------------------------------------------/
         // Mouse input modifies direction
         //---------------------------------------------------------------------/
-        // Handle mouse movement. delta contains the x and y movement of
-        // the mouse since last frame in pixels
-        if let Ok(mut delta) = mouse_delta.lock() {
-            let cpid = player.closest_planet_id;
-            mouse_input(
-                &delta,
-                &mut player,
-                &planets[cpid],
-                &mut conf,
-                delta_time,
-            );
-            *delta = (0.0, 0.0);
+        let (pad_movement, pad_look) = gamepad.poll(&conf);
+        let mut frame_input = input.frame();
+        // Mouse is used by the tweak panel
+        if debug_panel.wants_pointer {
+            frame_input.release_pointer();
         }
+        // Handle mouse movement, unless not dragging the orbit camera
+        let dragging = frame_input.held(input::Action::Orbit);
+        let mouse_delta = if cameras.active().drag_to_look() && !dragging {
+            glm::vec2(0.0, 0.0)
+        } else {
+            frame_input.mouse_delta
+        };
+        mouse_input(
+            &mouse_delta,
+            pad_look * delta_time,
+            &mut player,
+            &mut cameras,
+            &mut conf,
+            delta_time,
+        );
 
-        // Add active movement
-        if let Ok(keys) = pressed_keys.lock() {
-            let cpid = player.closest_planet_id;
-            keyboard_input(
-                keys,
-                &mut key_debounce,
-                &mut player,
-                &planets[cpid],
-                &mut conf,
-                delta_time,
-            );
+        // Follow the selected body, zoom with the wheel
+        cameras.active().track(&planets, selected);
+        cameras.active().zoom(frame_input.scroll);
+
+        // Add active movement, starting from the gamepad
+        let (mut save_scene, mut reload_scene) = (false, false);
+        let cpid = player.closest_planet_id;
+        keyboard_input(
+            &frame_input,
+            pad_movement,
+            &mut player,
+            &mut cameras,
+            &planets[cpid],
+            &mut conf,
+            &mut frame_capture,
+            &mut stats_overlay,
+            &mut debug_panel,
+            camera_path.as_ref(),
+            &mut playback,
+            &mut clock,
+            &mut save_scene,
+            &mut reload_scene,
+            delta_time,
+        );
+        if save_scene {
+            // Distances are scaled up while anchored to a planet
+            let unit_scale = if scaled { 1.0 } else { SCALING_FACTOR };
+            let result = scene_description
+                .capture(&planets, &planet_nodes, &props, nbody.as_ref(), planet_seed, orbit_time, unit_scale)
+                .save(SAVED_SCENE_PATH);
+            match result {
+                Ok(()) => eprintln!("Saved scene to {}", SAVED_SCENE_PATH),
+                Err(e) => eprintln!("Failed to save scene {}", e),
+            }
+        }
+        if reload_scene {
+            // Build the new scene before freeing the old, which is kept if
+            // the scene file is broken
+            let new_scope = gpu_resources::begin_scope();
+            let scene = build_scene(&args, planet_seed);
+            gpu_resources::end_scope();
+            match scene {
+                Err(e) => {
+                    eprintln!("{}", e);
+                    unsafe { gpu_resources::release(new_scope) };
+                }
+                Ok(scene) => unsafe {
+                    // Nothing may point into the old nodes once they're destroyed
+                    scene_root.children.clear();
+                    gui_root.children.truncate(gui_labels);
+                    for impostors in impostors.iter_mut() {
+                        impostors.clear(&mut memory_budget);
+                    }
+                    reflections.clear();
+                    vegetation.clear();
+                    memory_budget.clear();
+                    nbody = None;
+                    for node in planet_nodes.drain(..).chain(props.drain(..)) {
+                        SceneNode::destroy(node);
+                    }
+                    let released = gpu_resources::release(scene_scope);
+                    eprintln!("Reloaded scene, freed {} more GPU objects", released);
+
+                    scene_scope = new_scope;
+                    (scene_description, (planets, planet_nodes, lightsources, props)) = scene;
+                    for planet in &planet_nodes {
+                        scene_root.add_child(planet);
+                    }
+                    for prop in &props {
+                        match prop.node_type {
+                            SceneNodeType::Geometry2d => gui_root.add_child(prop),
+                            _ => scene_root.add_child(prop),
+                        }
+                    }
+                    if let Some(node) = &ship_node {
+                        scene_root.add_child(node);
+                    }
+                    // Start over from where a new scene starts
+                    if !scaled {
+                        player.position /= SCALING_FACTOR;
+                        scaled = true;
+                    }
+                    player.closest_planet_id = 0;
+                    cameras.select(conf.camera_position, &mut player, &planets[0]);
+                    physics = physics::Physics::new();
+                    selected = None;
+                },
+            }
+        }
+
+        // Save the camera to a bookmark with Ctrl, or fly back to one
+        let unit_scale = if scaled { 1.0 } else { SCALING_FACTOR };
+        if let Some(slot) = frame_input.bookmark() {
+            if frame_input.held(input::Action::Control) {
+                let view = cameras.view(&mut player);
+                bookmarks.store(slot, &view, &player, &planets, unit_scale);
+            } else {
+                bookmarks.go_to(slot, conf.bookmark_transition);
+            }
+        }
+        // Fly until the selected body fills focus_fill of the view
+        if frame_input.pressed(input::Action::Focus) {
+            match selected {
+                Some(id) => {
+                    let view = cameras.view(&mut player);
+                    let to = bookmarks::Bookmark::facing(&planets[id], &view.eye, conf.focus_fill, conf.fov, unit_scale);
+                    bookmarks.fly_to(to, conf.bookmark_transition);
+                }
+                None => eprintln!("Select a body to focus on first"),
+            }
+        }
+        bookmarks.update(&mut player, &mut cameras, &planets, scaled, delta_time);
+
+        // Move the ship to where it was flown
+        if let Some(ship) = cameras.ship() {
+            let node = ship_node.get_or_insert_with(|| {
+                let node = ship::node(&conf.ship_model);
+                scene_root.add_child(&node);
+                node
+            });
+            ship.place(node, unit_scale);
         }
 
-        // Lastly, center skybox around player
-        skybox_node.position = player.position;
+        // Follow the camera path, overriding input
+        if let Some(path) = &camera_path {
+            // Recordings follow the fixed timestep, so frames land evenly on the path
+            if recorder.is_some() {
+                playback.time = elapsed.min(path.duration());
+            } else {
+                playback.advance(stepped_delta, path);
+            }
+            let (position, direction) = path.sample(playback.time);
+            let cpid = player.closest_planet_id;
+            cameras.select(util::CameraPosition::FreeFly, &mut player, &planets[cpid]);
+            player.position = position;
+            player.direction = direction;
+        }
+        let view = cameras.view(&mut player);
 
 
         //---------------------------------------------------------------------/
@@ -375,34 +838,30 @@ pub fn game(
         // Log position
         let s = format!("global position: {:.3},{:.3},{:.3}",
             player.position.x, player.position.y, player.position.z);
-        text_pos_mesh = mesh::Mesh::text_buffer(
-            &s,
-            49.0 / 29.0, 1.0 * s.len() as f32 / 28.0
-        );
-        text_pos_node.update_buffers(&text_pos_mesh);
+        text_pos.set_text(&s);
         // Log gpu memory
         let buf_mem = util::MEMORY_USAGE.load(std::sync::atomic::Ordering::Relaxed);
-        let s = format!("GPU mem {}KiB used for planet buffers",
-            buf_mem / 1024);
-        text_gfxmem_mesh = mesh::Mesh::text_buffer(
-            &s,
-            49.0 / 29.0, 1.0 * s.len() as f32 / 28.0
-        );
-        text_gfxmem_node.update_buffers(&text_gfxmem_mesh);
+        let s = format!("GPU mem {}KiB used for mesh buffers, {}KiB terrain ({} evicted)",
+            buf_mem / 1024,
+            memory_budget.used(memory_budget::ResourceKind::Terrain) / 1024,
+            memory_budget.evicted);
+        text_gfxmem.set_text(&s);
+        text_warning.set_text(&error::warning());
         // Log movement speed
-        let s = format!("Speed: {:.3}", conf.movement_speed);
-        text_mspeed_mesh = mesh::Mesh::text_buffer(
-            &s,
-            49.0 / 29.0, 1.0 * s.len() as f32 / 28.0
-        );
-        text_mspeed_node.update_buffers(&text_mspeed_mesh);
+        let s = format!("Speed: {:.3}", player.speed);
+        text_mspeed.set_text(&s);
         // Log fps
         let s = format!("FPS: {:}", 1.0 / delta_time);
-        text_closest_mesh = mesh::Mesh::text_buffer(
-            &s,
-            49.0 / 29.0, 1.0 * s.len() as f32 / 28.0
-        );
-        text_closest_node.update_buffers(&text_closest_mesh);
+        text_closest.set_text(&s);
+        // Display simulated date, time since the start and time scale
+        let mut s = simulation::format_elapsed(orbit_time);
+        if let Some(start) = conf.start_date {
+            s = format!("{}, {}", simulation::format_date(start, orbit_time), s);
+        }
+        s += &format!(", time {}{}",
+            simulation::format_scale(clock.scale),
+            if clock.paused { " (paused)" } else { "" });
+        text_time.set_text(&s);
         // Log mouse directional vectors
         let up = player.up();
         let s = format!("dir: {:.3},{:.3},{:.3} right: {:.3},{:.3},{:.3}, up: {:.3},{:.3},{:.3}",
@@ -410,12 +869,8 @@ pub fn game(
             player.right.x, player.right.y, player.right.z,
             up.x, up.y, up.z,
         );
-        text_mouse_mesh = mesh::Mesh::text_buffer(
-            &s,
-            49.0 / 29.0, 1.0 * s.len() as f32 / 28.0
-        );
-        text_mouse_node.update_buffers(&text_mouse_mesh);
-        // Display player state
+        text_mouse.set_text(&s);
+        // Display camera mode and player state
         let s = match player.state {
             player::PlayerState::FreeFloat => String::from("Free floating"),
             player::PlayerState::Anchored(a) => String::from(
@@ -425,11 +880,13 @@ pub fn game(
                 &format!("Landed on: {:.3},{:.3},{:.3}", a.x, a.y, a.z)
             ),
         };
-        text_pstate_mesh = mesh::Mesh::text_buffer(
-            &s,
-            49.0 / 29.0, 1.0 * s.len() as f32 / 28.0
-        );
-        text_pstate_node.update_buffers(&text_pstate_mesh);
+        let s = match &camera_path {
+            Some(path) => format!("Camera path {:.1}/{:.1}s{}",
+                playback.time, path.duration(),
+                if playback.playing && !clock.paused { "" } else { " (paused)" }),
+            None => format!("{:?} camera, {}", cameras.active().mode(), s),
+        };
+        text_pstate.set_text(&s);
         // Display height over planet and planet's terrain heights
         let s = match player.state {
             player::PlayerState::FreeFloat => String::from("Free floating"),
@@ -444,398 +901,903 @@ pub fn game(
                 )
             ),
         };
-        text_height_mesh = mesh::Mesh::text_buffer(
-            &s,
-            49.0 / 29.0, 1.0 * s.len() as f32 / 28.0
-        );
-        text_height_node.update_buffers(&text_height_mesh);
+        text_height.set_text(&s);
 
 
 
-        //---------------------------------------------------------------------/
-        // Update perspective
-        //---------------------------------------------------------------------/
-        let wsize = context.window().inner_size();
-        let aspect = wsize.width as f32 / wsize.height as f32;
-        let perspective_mat: glm::Mat4 = glm::perspective(
-            aspect,
-            conf.fov,       // field of view
-            conf.clip_near, // near
-            conf.clip_far   // far
-        );
-
-        //---------------------------------------------------------------------/
-        // First person view
-        //---------------------------------------------------------------------/
-        let up = player.up();
-        // let cam = glm::look_at(&player.position, &(player.position+player.direction), &up);
-        let cam = glm::look_at(&player.position, &(player.position+player.direction), &up);
-        let perspective_view = perspective_mat * cam;
+        let wsize = window_size();
 
 
         //---------------------------------------------------------------------/
-        // Draw section
+        // Draw section, at the simulation state interpolated by clock.alpha()
         //---------------------------------------------------------------------/
         unsafe {
             // Fixed both viewport and movement direction??
-            gl::Viewport(0, 0, wsize.width as i32, wsize.height as i32);
+            if let Some(framebuffer) = &offscreen {
+                framebuffer.bind();
+            }
+            gl::Viewport(0, 0, wsize.0 as i32, wsize.1 as i32);
             //-----------------------------------------------------------------/
             // Global uniforms
             //-----------------------------------------------------------------/
             let u_time = sh.get_uniform_location("u_time");
-            gl::Uniform1f(u_time, elapsed);
+            gl::Uniform1f(u_time, sim_time);
             let u_view = sh.get_uniform_location("u_view");
             let u_perspective = sh.get_uniform_location("u_perspective");
-            gl::UniformMatrix4fv(
-                u_view,
-                1,
-                gl::FALSE,
-                cam.as_ptr(),
-            );
-            gl::UniformMatrix4fv(
-                u_perspective,
-                1,
-                gl::FALSE,
-                perspective_mat.as_ptr(),
-            );
 
 
             //-----------------------------------------------------------------/
             // Clear background, set polygon mode
             //-----------------------------------------------------------------/
-            gl::ClearColor(
-                conf.bg_color[0], conf.bg_color[1],
-                conf.bg_color[2], conf.bg_color[3]
+            color::begin_scene();
+            let bg = color::linear(&glm::vec3(conf.bg_color[0], conf.bg_color[1], conf.bg_color[2]));
+            gl::ClearColor(bg.x, bg.y, bg.z, conf.bg_color[3]);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+            gl::PolygonMode(gl::FRONT_AND_BACK, match conf.debug_mode {
+                util::DebugMode::Wireframe => gl::LINE,
+                _ => POLYMODES[conf.polymode],
+            });
+            gl::Uniform1ui(
+                sh.get_uniform_location("u_debug_mode"),
+                conf.debug_mode as u32
             );
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl::PolygonMode(gl::FRONT_AND_BACK, POLYMODES[conf.polymode]);
+            render_queue.overdraw = conf.debug_mode == util::DebugMode::Overdraw;
 
 
             //-----------------------------------------------------------------/
             // Planet transforms and update uniforms
             // Compute closest planet
             //-----------------------------------------------------------------/
-            scene_root.update_node_transformations(&glm::identity(), &player.position);
+            {
+                profile_scope!("update_transforms");
+                animation::animate(&mut scene_root, stepped_delta);
+                skinning::animate(&mut scene_root, stepped_delta);
+                if conf.double_precision != double_precision {
+                    double_precision = conf.double_precision;
+                    scene_root.mark_dirty();
+                }
+                frame_stats.skipped_transforms = if conf.double_precision {
+                    scene_root.update_node_transformations_f64(&glm::identity(), &view.eye)
+                } else {
+                    scene_root.update_node_transformations(&glm::identity(), &view.eye)
+                };
+                skinning::upload(&scene_root);
+            }
 
             let mut planets_sorted = vec![];
-            for (node, mut planet) in planet_nodes.iter().zip(&mut planets) {
-                planet.position = node.position;
-                planet.rotation = node.rotation;
-                planet.radius = node.scale.x / 2.0;
-                planet.update_uniforms(&sh);
+            for (node, planet) in planet_nodes.iter().zip(&mut planets) {
+                planet.position = node.position();
+                planet.rotation = node.rotation();
+                planet.radius = node.scale().x / 2.0;
                 let dist = glm::length(&(planet.position - player.position)) - planet.radius;
                 planets_sorted.push((dist, planet.planet_id));
             }
-            planets_sorted.sort_by(|&a,&b| a.0.partial_cmp(&b.0).unwrap());
+            planets_sorted.sort_by(|&a,&b| a.0.total_cmp(&b.0));
             planets_sorted.iter().enumerate().for_each(|(i, &(_dist, id))| {
                 gl::Uniform1ui(
                     sh.get_uniform_location(&format!("u_planet_ids_sorted[{}]", i)),
                     id as u32,
                 )
             });
+            shader::arrays_changed(&sh);
             if let PlayerState::FreeFloat = player.state {
                 // Only update closest planet if position is not depending on it
                 player.closest_planet_id = planets_sorted[0].1;
             }
-            // Stop rendering passed render_limit
-            (0..planets.len()).for_each(|i| {
-                planets[i].lod(&mut (*planet_nodes[i]), player.position);
-                let depth_test = planets[i].radius / glm::length(&(planets[i].position - player.position));
-                planet_nodes[i].node_type = if depth_test.atan() < conf.render_limit {
-                    SceneNodeType::PlanetSkip
+            // Terrain detail follows the player, also when seen from other views
+            planet::CULLED_PATCHES.store(0, std::sync::atomic::Ordering::Relaxed);
+            terrain_compute::set_enabled(conf.compute_terrain);
+            terrain_batch::set_enabled(conf.batch_terrain);
+            normal_debug::set_enabled(conf.debug_normals);
+            let specialize = conf.shader_variants
+                && conf.debug_mode == util::DebugMode::None
+                && !capabilities::global().reduced;
+            for (node_type, defines) in [
+                (SceneNodeType::Skybox, &[("NODE_TYPE", "1u")][..]),
+                (SceneNodeType::Planet, &[("NODE_TYPE", "3u"), ("HAS_TEXTURE", "false")]),
+                (SceneNodeType::Ocean, &[("NODE_TYPE", "4u")]),
+                (SceneNodeType::Cloud, &[("NODE_TYPE", "5u")]),
+            ] {
+                let program = if specialize {
+                    let log_depth = if conf.log_depth { "1" } else { "0" };
+                    let defines = [defines, &[("DEBUG_MODE", "0u"), ("LOG_DEPTH", log_depth)]].concat();
+                    scene_variants.get(&defines).map(|variant| variant.program_id)
                 } else {
-                    SceneNodeType::Empty
+                    None
                 };
+                shader::set_program_for(node_type, program);
+            }
+            erosion::set_iterations(conf.erosion_iterations);
+            terrain_tessellation::set_enabled(conf.tessellated_terrain);
+            terrain_tessellation::set_edge_pixels(conf.tess_edge_pixels);
+            if recorder.is_some() {
+                // No realtime constraint, upload everything ready
+                terrain_uploads.set_budget(0, 0);
+            } else {
+                terrain_uploads.set_budget(conf.budget_upload_kb * 1024, conf.budget_upload_patches);
+            }
+            (0..planets.len()).for_each(|i| {
+                planets[i].lod(&mut (*planet_nodes[i]), player.position, &mut memory_budget, &mut terrain_uploads);
             });
+            terrain_uploads.flush(&mut memory_budget);
+            if conf.vegetation {
+                vegetation.update(&planets, &player.position);
+            }
+            // New patches may have been displaced in the compute shader
+            sh.activate();
+
+            memory_budget.enforce();
 
             gl::Uniform1ui(
                 sh.get_uniform_location("u_planets_len"),
                 planets.len() as u32
             );
-            lightsources.iter().enumerate().for_each(|(i, &id)| {
+            let max_lights = capabilities::global().max_lights();
+            lightsources.iter().take(max_lights).enumerate().for_each(|(i, &id)| {
                 gl::Uniform1ui(
                     sh.get_uniform_location(&format!("u_lightsources[{}]", i)),
                     id as u32,
                 )
             });
+            shader::arrays_changed(&sh);
             gl::Uniform1ui(
                 sh.get_uniform_location("u_lightsources_len"),
-                lightsources.len() as u32
+                lightsources.len().min(max_lights) as u32
             );
-            gl::Uniform3fv(
-                sh.get_uniform_location("u_player_position"),
-                1,
-                player.position.as_ptr()
+            if conf.nebula && nebula.is_none() {
+                nebula = Some(nebula::Nebula::bake(util::derive_seed(conf.seed, "nebula"), NEBULA_SIZE));
+                sh.activate();
+            }
+            if let Some(nebula) = &nebula {
+                nebula.bind();
+            }
+            gl::Uniform1i(sh.get_uniform_location("u_has_nebula"), conf.nebula as i32);
+            // Baked again with the sky as it is now, the nebula having been toggled
+            if conf.image_based_lighting
+                && environment.as_ref().is_none_or(|environment| environment.nebula != conf.nebula)
+            {
+                if let Some(environment) = environment.take() {
+                    environment.delete();
+                }
+                environment = Some(ibl::EnvironmentLight::bake(&sh, &mut skybox_node, conf.nebula));
+                sh.activate();
+            }
+            if let Some(environment) = &environment {
+                environment.bind(&sh);
+            }
+            gl::Uniform1i(
+                sh.get_uniform_location("u_has_environment"),
+                (conf.image_based_lighting && environment.is_some()) as i32,
             );
+            gl::ActiveTexture(gl::TEXTURE0 + texture::DETAIL_MAP_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, detail_map);
+            gl::ActiveTexture(gl::TEXTURE0 + texture::TERRAIN_MAP_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, terrain_map);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::Uniform1i(sh.get_uniform_location("u_normal_maps"), conf.normal_maps as i32);
+            gl::Uniform1f(sh.get_uniform_location("u_light_intensity"), conf.light_intensity);
+            gl::Uniform1f(sh.get_uniform_location("u_ambient_light"), conf.ambient_light);
+
+            gpu_profiler.enabled = stats_overlay.visible || args.profile_csv.is_some();
+            gpu_profiler.begin_frame();
+            gpu_profiler.begin("reflections");
+            reflections.update(
+                &sh,
+                &planets,
+                &mut planet_nodes,
+                &scene_root,
+                &mut skybox_node,
+                conf.ocean_reflections,
+            );
+            render_queue.reflections = if conf.ocean_reflections {
+                reflections.cubemaps()
+            } else {
+                vec![]
+            };
+            render_queue.city_lights = planets.iter().map(|planet| planet.city_lights).collect();
 
             //-----------------------------------------------------------------/
-            // Draw skybox
+            // Views side by side: the active camera, and with split_screen the
+            // one looking down at the planet the player is at, or the selected
             //-----------------------------------------------------------------/
-            gl::DepthFunc(gl::LEQUAL);
-            skybox_node.update_node_transformations(&glm::identity(), &player.position);
-            skybox_node.draw_scene(&perspective_view, &sh, (0.1, 10.0));
-            gl::DepthFunc(gl::LESS);
+            let mut views = vec![view];
+            if conf.split_screen {
+                let pid = selected.unwrap_or(player.closest_planet_id);
+                views.push(camera::View::locked_to(&planets[pid], &player, conf.split_distance));
+            }
+            let viewports = camera::split(wsize, views.len());
+            frame_stats.skipped_planets = 0;
+            frame_stats.impostors = 0;
+            frame_stats.occluded = 0;
+            // The last pass of the last view, for the overlays
+            let mut clipping = clip_planes(&conf, &player, &planets, &views[0].eye);
+            let mut perspective_view = glm::identity();
+            // Views are drawn into the blur's framebuffer, which has the
+            // window's clear colour and is cleared by it
+            let blur = conf.motion_blur && !render_queue.overdraw;
+            if blur {
+                motion_blur.begin(wsize);
+            }
+            for (v, (view, &viewport)) in views.iter().zip(&viewports).enumerate() {
+                let (x, y, width, height) = viewport;
+                let (clip_near, clip_far) = clip_planes(&conf, &player, &planets, &view.eye);
+                gl::Viewport(x, y, width as i32, height as i32);
+                gl::Uniform2f(sh.get_uniform_location("u_viewport_size"), width as f32, height as f32);
+                render_queue.view = v;
+                let aspect = width as f32 / height as f32;
+                let pixel_scale = height as f32 / (2.0 * (conf.fov / 2.0).tan());
+                // Draw relative to the camera, so draws far from the world
+                // origin keep their precision
+                let origin = if conf.camera_relative { view.eye } else { glm::zero() };
+                render_queue.origin = origin;
+                let cam = view.relative_to(&origin).matrix();
+                let perspective_infinite = util::perspective_infinite(aspect, conf.fov, clip_near);
+                gl::UniformMatrix4fv(
+                    u_view,
+                    1,
+                    gl::FALSE,
+                    cam.as_ptr(),
+                );
+                if v > 0 {
+                    // Only the distances to this eye are left to update
+                    scene_root.update_node_transformations(&glm::identity(), &view.eye);
+                }
+                particles.prepare(&view.eye, &origin, clock.alpha());
+                for planet in &planets {
+                    planet.update_uniforms(&sh, &origin);
+                }
+                // Stop rendering passed render_limit
+                (0..planets.len()).for_each(|i| {
+                    let depth_test = planets[i].radius / glm::length(&(planets[i].position - view.eye));
+                    planet_nodes[i].node_type = if depth_test.atan() < conf.render_limit {
+                        frame_stats.skipped_planets += 1;
+                        SceneNodeType::PlanetSkip
+                    } else {
+                        SceneNodeType::Empty
+                    };
+                });
+                gl::Uniform3fv(
+                    sh.get_uniform_location("u_player_position"),
+                    1,
+                    (view.eye - origin).as_ptr()
+                );
+                let occlusion = &mut occlusions[v];
+                occlusion.begin(
+                    &planets,
+                    &mut planet_nodes,
+                    &mut props,
+                    &view.eye,
+                    &origin,
+                    conf.occlusion_culling,
+                );
+                // Planets with all uniforms set, before any is drawn
+                let impostors = &mut impostors[v];
+                impostors.update(
+                    &sh,
+                    &planets,
+                    &mut planet_nodes,
+                    view,
+                    &origin,
+                    conf.impostor_pixels,
+                    pixel_scale,
+                    &mut memory_budget,
+                );
+                frame_stats.impostors += impostors.count;
+                frame_stats.occluded += occlusion.count;
+                sh.activate();
+                // Flares are probed just in front of the surface, facing the eye
+                let lights: Vec<lens_flare::Light> = lightsources
+                    .iter()
+                    .map(|&id| {
+                        let planet = &planets[id];
+                        let radius = planet.radius * (1.0 + planet.max_height);
+                        let towards_eye = glm::normalize(&(view.eye - planet.position));
+                        lens_flare::Light {
+                            position: planet.position + towards_eye * radius * 1.05 - origin,
+                            radius,
+                            color: planet.emission,
+                        }
+                    })
+                    .collect();
+                let lens_flare = &mut lens_flares[v];
+                if conf.lens_flare {
+                    lens_flare.begin(&lights, (width, height), delta_time);
+                }
+
+                //-------------------------------------------------------------/
+                // Draw skybox, centered around the camera
+                //-------------------------------------------------------------/
+                gpu_profiler.begin("skybox");
+                gl::DepthFunc(gl::LEQUAL);
+                skybox_node.set_position(view.eye);
+                skybox_node.update_node_transformations(&glm::identity(), &view.eye);
+                let skybox_view = perspective_infinite * cam;
+                // Through the queue, keeping how it turned with the camera
+                if blur {
+                    motion_blur.velocity(true);
+                }
+                skybox_node.collect_draws(&skybox_view, sh.program_id, (0.1, 10.0), &mut render_queue);
+                render_queue.sort();
+                render_queue.submit(&sh);
+                gl::DepthFunc(gl::LESS);
+                if blur {
+                    motion_blur.velocity(false);
+                }
+                if let Some(starfield) = &starfield {
+                    starfield.draw(&cam, &perspective_infinite, conf.star_brightness);
+                    sh.activate();
+                }
+
+                //-------------------------------------------------------------/
+                // Draw elements in multiple passes using different clipping
+                // planes
+                //-------------------------------------------------------------/
+                // Draw objects very far away, pretty far away, far away (close
+                // planets) and close (landed on planet). Passes are limited to
+                // the active clipping planes, and skipped if entirely outside.
+                // Depth is cleared in the whole window, the views before are
+                // done with it.
+                if render_queue.overdraw {
+                    // Count every fragment, not only the visible ones
+                    gl::Disable(gl::DEPTH_TEST);
+                }
+                let dof = conf.depth_of_field && !render_queue.overdraw;
+                if dof {
+                    depth_of_field.begin(wsize, viewport);
+                }
+                let passes: &[(f32, f32)] = if conf.log_depth {
+                    &LOG_DEPTH_PASSES
+                } else {
+                    &CLIPPING_PASSES
+                };
+                for (i, pass) in passes.iter().enumerate() {
+                    let infinite = pass.1.is_infinite();
+                    let pass_clipping = (
+                        pass.0.max(clip_near),
+                        if infinite { pass.1 } else { pass.1.min(clip_far) },
+                    );
+                    if pass_clipping.0 >= pass_clipping.1 {
+                        continue;
+                    }
+                    clipping = pass_clipping;
+                    gl::Clear(gl::DEPTH_BUFFER_BIT);
+                    let perspective_mat: glm::Mat4 = if infinite {
+                        util::perspective_infinite(aspect, conf.fov, clipping.0)
+                    } else {
+                        glm::perspective(
+                            aspect,
+                            conf.fov,   // field of view
+                            clipping.0, // near
+                            clipping.1  // far
+                        )
+                    };
+                    gl::UniformMatrix4fv(
+                        u_perspective,
+                        1,
+                        gl::FALSE,
+                        perspective_mat.as_ptr(),
+                    );
+                    let log_depth = if conf.log_depth { 2.0 / (clipping.1 + 1.0).log2() } else { 0.0 };
+                    gl::Uniform1f(sh.get_uniform_location("u_log_depth"), log_depth);
+                    perspective_view = perspective_mat * cam;
+                    gpu_profiler.begin("geometry");
+                    if blur {
+                        motion_blur.velocity(true);
+                    }
+                    if terrain_tessellation::enabled() {
+                        terrain_tessellation::draw(
+                            &sh,
+                            planets.iter().zip(planet_nodes.iter().map(|node| &***node)),
+                            &perspective_view,
+                            &view.eye,
+                            clipping,
+                            pixel_scale,
+                            &mut render_queue,
+                        );
+                    }
+                    scene_root.collect_draws(&perspective_view, sh.program_id, clipping, &mut render_queue);
+                    render_queue.sort();
+                    render_queue.submit(&sh);
+                    if blur {
+                        motion_blur.velocity(false);
+                    }
+                    // Particles and impostors nearer than the pass before
+                    let until = if i > 0 { passes[i - 1].0 } else { f32::INFINITY };
+                    impostors.draw(&cam, &perspective_mat, log_depth, (pass.0, until));
+                    gpu_profiler.begin("particles");
+                    particles.draw(&cam, &perspective_mat, log_depth, (pass.0, until));
+                    if conf.orbit_paths {
+                        gpu_profiler.begin("orbit paths");
+                        orbit_paths.draw(
+                            &planets,
+                            &cam,
+                            &perspective_mat,
+                            &origin,
+                            log_depth,
+                            (width, height),
+                            conf.orbit_path_width,
+                        );
+                    }
+                    if conf.vegetation {
+                        gpu_profiler.begin("vegetation");
+                        let sun = lightsources.first().map(|&id| planets[id].position);
+                        vegetation.draw(
+                            &planets,
+                            &cam,
+                            &perspective_mat,
+                            &origin,
+                            &view.eye,
+                            sun,
+                            log_depth,
+                            (pass.0, until),
+                        );
+                    }
+                    if conf.lens_flare {
+                        gpu_profiler.begin("lens flare");
+                        lens_flare.probe(i, &cam, &perspective_mat, log_depth);
+                    }
+                    gpu_profiler.begin("occlusion");
+                    occlusion.probe(i, &cam, &perspective_mat, log_depth);
+                    if dof {
+                        gpu_profiler.begin("depth of field");
+                        depth_of_field.capture(&perspective_mat, log_depth);
+                    }
+                    sh.activate();
+                }
+                gpu_profiler.end();
+                occlusion.end(&cam, &perspective_infinite);
+                if dof {
+                    // On the surface of the selected body, facing the eye
+                    let focus = match selected.filter(|_| conf.auto_focus) {
+                        Some(pid) => {
+                            let planet = &planets[pid];
+                            (glm::distance(&planet.position, &view.eye) - planet.radius).max(clip_near)
+                        }
+                        None => conf.focus_distance,
+                    };
+                    gpu_profiler.begin("depth of field");
+                    depth_of_field.draw(focus, conf.aperture, viewport);
+                    sh.activate();
+                }
+                if conf.light_shafts && !render_queue.overdraw {
+                    gpu_profiler.begin("light shafts");
+                    light_shafts.draw(&lights, &cam, &perspective_infinite, viewport);
+                    sh.activate();
+                }
+                if conf.lens_flare && !render_queue.overdraw {
+                    gpu_profiler.begin("lens flare");
+                    lens_flare.draw(&cam, &perspective_infinite, aspect);
+                    sh.activate();
+                }
+                gl::Enable(gl::DEPTH_TEST);
+
+                //-------------------------------------------------------------/
+                // Draw debug lines on top of everything, in the first view
+                //-------------------------------------------------------------/
+                if v > 0 {
+                    continue;
+                }
+                gpu_profiler.begin("overlays");
+                if conf.debug_lines {
+                    for (node, planet) in planet_nodes.iter().zip(&planets) {
+                        debug_draw::sphere(
+                            &planet.position,
+                            planet.radius * (1.0 + planet.max_height),
+                            if planet.planet_id == player.closest_planet_id {
+                                debug_draw::RED
+                            } else {
+                                debug_draw::GREEN
+                            },
+                        );
+                        debug_draw::axes(&node.current_transformation_matrix, planet.radius * 1.5);
+                    }
+                }
+                if cameras.active().mode() == util::CameraPosition::ThirdPerson {
+                    // Mark the player followed by the camera
+                    debug_draw::line(&player.feet(), &player.position, debug_draw::YELLOW);
+                }
+                gl::Disable(gl::DEPTH_TEST);
+                debug_lines.flush(&(perspective_infinite * cam * glm::translation(&-origin)));
+                if conf.draw_gui {
+                    // In this view, as the labels are placed in it
+                    let kinds = labels::LabelKinds {
+                        planets: conf.labels_planets,
+                        props: conf.labels_props,
+                    };
+                    labels.update(
+                        &planets,
+                        &planet_nodes,
+                        &props,
+                        view,
+                        &(perspective_infinite * cam * glm::translation(&-origin)),
+                        kinds,
+                        delta_time,
+                    );
+                    sh.activate();
+                    labels.root.update_node_transformations(&glm::identity(), &view.eye);
+                    labels.root.draw_scene(&perspective_view, &sh, clipping);
+                }
+                gl::Enable(gl::DEPTH_TEST);
+                sh.activate();
+            }
+            gl::Viewport(0, 0, wsize.0 as i32, wsize.1 as i32);
+            render_queue.overdraw = false;
+            render_queue.end_frame();
+            if blur {
+                gpu_profiler.begin("motion blur");
+                motion_blur.draw(conf.motion_blur_shutter);
+                sh.activate();
+            }
+            color::end_scene();
+            if let Some(color_grading) = &mut color_grading {
+                gpu_profiler.begin("color grading");
+                color_grading.draw(wsize);
+                sh.activate();
+            }
 
             //-----------------------------------------------------------------/
-            // Draw elements in multiple passes using different clipping planes
+            // Draw minimap if enabled, with the frustum of the active camera
             //-----------------------------------------------------------------/
-            // Draw objects very far away
-            gl::Clear(gl::DEPTH_BUFFER_BIT);
-            let clipping = (125.0, 162500.0);
-            let perspective_mat: glm::Mat4 = glm::perspective(
-                aspect,
-                conf.fov,       // field of view
-                clipping.0, // near
-                clipping.1   // far
-            );
-            gl::UniformMatrix4fv(
-                u_perspective,
-                1,
-                gl::FALSE,
-                perspective_mat.as_ptr(),
-            );
-            let perspective_view = perspective_mat * cam;
-            scene_root.draw_scene(&perspective_view, &sh, clipping);
-            // Draw objects pretty far away
-            gl::Clear(gl::DEPTH_BUFFER_BIT);
-            let clipping = (2.5, 1250.0);
-            let perspective_mat: glm::Mat4 = glm::perspective(
-                aspect,
-                conf.fov,       // field of view
-                clipping.0, // near
-                clipping.1   // far
-            );
-            gl::UniformMatrix4fv(
-                u_perspective,
-                1,
-                gl::FALSE,
-                perspective_mat.as_ptr(),
-            );
-            let perspective_view = perspective_mat * cam;
-            scene_root.draw_scene(&perspective_view, &sh, clipping);
-            // Draw objects far away (close planets)
-            gl::Clear(gl::DEPTH_BUFFER_BIT);
-            let clipping = (0.005, 25.0);
-            let perspective_mat: glm::Mat4 = glm::perspective(
-                aspect,
-                conf.fov,       // field of view
-                clipping.0, // near
-                clipping.1   // far
-            );
-            gl::UniformMatrix4fv(
-                u_perspective,
-                1,
-                gl::FALSE,
-                perspective_mat.as_ptr(),
-            );
-            let perspective_view = perspective_mat * cam;
-            scene_root.draw_scene(&perspective_view, &sh, clipping);
-            // Draw objects that are close (landed on planet)
-            gl::Clear(gl::DEPTH_BUFFER_BIT);
-            let clipping = (0.0005, 2.5);
-            let perspective_mat: glm::Mat4 = glm::perspective(
-                aspect,
-                conf.fov,       // field of view
-                clipping.0, // near
-                clipping.1   // far
-            );
-            let perspective_view = perspective_mat * cam;
-            scene_root.draw_scene(&perspective_view, &sh, clipping);
-
+            if conf.minimap {
+                gpu_profiler.begin("minimap");
+                let (_, _, width, height) = viewports[0];
+                minimap.draw(
+                    &planets,
+                    &view,
+                    width as f32 / height as f32,
+                    conf.fov,
+                    selected,
+                    wsize,
+                );
+                sh.activate();
+                gpu_profiler.begin("overlays");
+            }
 
             //-----------------------------------------------------------------/
             // Draw GUI if enabled
             //-----------------------------------------------------------------/
             if conf.draw_gui {
                 gl::Disable(gl::DEPTH_TEST);
-                gui_root.update_node_transformations(&glm::identity(), &player.position);
+                gui_root.update_node_transformations(&glm::identity(), &view.eye);
                 gui_root.draw_scene(&perspective_view, &sh, clipping);
                 gl::Enable(gl::DEPTH_TEST);
             }
+
+            //-----------------------------------------------------------------/
+            // Draw frame statistics if enabled
+            //-----------------------------------------------------------------/
+            frame_stats.frame_time = delta_time;
+            frame_stats.draw_calls = render_queue.draw_calls;
+            frame_stats.triangles = render_queue.triangles;
+            frame_stats.state_changes = render_queue.state_changes;
+            frame_stats.avoided_calls = render_queue.avoided_calls();
+            frame_stats.batched_patches = render_queue.batched;
+            frame_stats.culled_patches =
+                planet::CULLED_PATCHES.load(std::sync::atomic::Ordering::Relaxed) as usize;
+            frame_stats.skipped_updates = roi.skipped;
+            frame_stats.particles = particles.count();
+            frame_stats.plants = if conf.vegetation { vegetation.count() } else { 0 };
+            let cpu_times = profiler::cpu().averages();
+            stats_overlay.update(&frame_stats, &gpu_profiler.averages(), &cpu_times);
+            if stats_overlay.visible {
+                gl::Disable(gl::DEPTH_TEST);
+                stats_overlay.root.update_node_transformations(&glm::identity(), &view.eye);
+                stats_overlay.root.draw_scene(&perspective_view, &sh, clipping);
+                gl::Enable(gl::DEPTH_TEST);
+            }
+            // In the middle of the active camera's view, to the left when split
+            let (x, _, width, _) = viewports[0];
+            let center = glm::vec2(2.0 * (x as f32 + width as f32 / 2.0) / wsize.0 as f32 - 1.0, 0.0);
+            reticle.update(&view, &planets, &planet_nodes, center);
+            let crosshair = conf.crosshair && conf.draw_gui;
+            if crosshair {
+                gl::Disable(gl::DEPTH_TEST);
+                reticle.root.update_node_transformations(&glm::identity(), &view.eye);
+                reticle.root.draw_scene(&perspective_view, &sh, clipping);
+                gl::Enable(gl::DEPTH_TEST);
+            }
+            body_info.update(selected.filter(|_| conf.draw_gui), &planets, &mut planet_nodes, delta_time);
+            if body_info.visible() {
+                gl::Disable(gl::DEPTH_TEST);
+                body_info.root.update_node_transformations(&glm::identity(), &view.eye);
+                body_info.root.draw_scene(&perspective_view, &sh, clipping);
+                gl::Enable(gl::DEPTH_TEST);
+            }
+
+            //-----------------------------------------------------------------/
+            // Draw tweak panel if enabled
+            //-----------------------------------------------------------------/
+            // Edits the selected planet, or the closest one
+            let pid = selected.unwrap_or(player.closest_planet_id);
+            gpu_profiler.begin("panel");
+            let regenerate = debug_panel.run(
+                wsize,
+                elapsed,
+                &mut conf,
+                &mut planets,
+                pid,
+            );
+            if regenerate {
+                planets[pid].regenerate(&mut planet_nodes[pid], &mut memory_budget);
+            }
+
+            //-----------------------------------------------------------------/
+            // Select the planet clicked on, outlined from the next frame
+            //-----------------------------------------------------------------/
+            // In the active camera's view, to the left when split, or under
+            // the crosshair
+            let mut picked = None;
+            if frame_input.pressed(input::Action::Select) {
+                let pos = frame_input.cursor;
+                let dir = picking::cursor_ray(
+                    (pos.x, pos.y),
+                    (viewports[0].2, viewports[0].3),
+                    conf.fov,
+                    &view.direction,
+                    &view.up,
+                );
+                picked = Some(picking::pick_planet(&view.eye, &dir, &planets));
+            }
+            // Only with the crosshair showing what's ahead
+            if crosshair && frame_input.pressed(input::Action::SelectAhead) {
+                picked = Some(reticle.hovered);
+            }
+            if let Some(picked) = picked {
+                if let Some(pid) = selected {
+                    planet_nodes[pid].selected = false;
+                }
+                if let Some(pid) = picked {
+                    planet_nodes[pid].selected = true;
+                }
+                selected = picked;
+            }
+            if debug_panel.visible {
+                // Restore the state changed by egui
+                gl::Enable(gl::DEPTH_TEST);
+                gl::Enable(gl::CULL_FACE);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                sh.activate();
+            }
+            gpu_profiler.end_frame();
         }
 
+        if let Some(recorder) = recorder.as_mut() {
+            // Redraw the same moment until no more terrain is generated for it
+            terrain_settled &= jobs::global().pending() == 0 && terrain_uploads.waiting() == 0;
+            if terrain_settled || unsettled_frames >= RECORD_MAX_SETTLE_FRAMES {
+                unsafe { recorder.capture() };
+                unsettled_frames = 0;
+            } else {
+                unsettled_frames += 1;
+            }
+        }
         context.swap_buffers().unwrap();
+        frame_capture.end_frame();
+        let mut cpu_profiler = profiler::cpu();
+        cpu_profiler.end_frame();
+        cpu_profiler.set_enabled(stats_overlay.visible);
+        drop(cpu_profiler);
         frame_counter += 1;
+
+        let recorded = recorder.as_ref().map_or(0, |recorder| recorder.frames());
+        // Closing the window finishes the recording too, so the file is whole
+        if record_frames.is_some_and(|frames| recorded >= frames) || input.quit_requested() {
+            if let Some(recorder) = recorder.take() {
+                unsafe { recorder.finish() };
+            }
+            return;
+        }
+    }
+}
+
+
+/// Load the scene file given with --scene, or build the test scene with
+/// --kitchen-sink or the default one. The description is kept for saving the
+/// scene again with F5.
+fn build_scene(args: &cli::Args, seed: u32) -> Result<(scene_file::SceneDescription, scene_file::Scene), String> {
+    let mut description = match &args.scene {
+        Some(path) => scene_file::SceneDescription::load(path)
+            .map_err(|e| format!("Failed to load scene {}", e))?,
+        None => Default::default(),
+    };
+    let (mut planets, planet_nodes, lightsources, props) = if args.scene.is_some() {
+        description
+            .build(seed)
+            .map_err(|e| format!("Failed to build scene {}", e))?
+    } else if args.kitchen_sink {
+        // Props described as in scene files, so they're saved with the scene
+        let (planets, planet_nodes, lightsources, nodes) = scene::create_kitchen_sink_scene(seed);
+        description.nodes = nodes;
+        (planets, planet_nodes, lightsources, description.build_props())
+    } else {
+        let (planets, planet_nodes, lightsources) = scene::create_scene(seed);
+        (planets, planet_nodes, lightsources, vec![])
+    };
+    for planet in &mut planets {
+        planet.stamp_features();
+        planet.city_lights = city_lights::bake(planet);
+    }
+    Ok((description, (planets, planet_nodes, lightsources, props)))
+}
+
+
+/// Choose clipping planes from player state, and optionally pull the far
+/// plane in to the bounding spheres of the planets seen from `eye`. The near
+/// plane is left alone, as props, particles and the ship can be right in
+/// front of the camera.
+fn clip_planes(
+    conf: &util::Config,
+    player: &player::Player,
+    planets: &[planet::Planet],
+    eye: &glm::Vec3,
+) -> (f32, f32) {
+    let (near, far) = match player.state {
+        PlayerState::FreeFloat => (conf.clip_near, conf.clip_far),
+        PlayerState::Anchored(_) |
+        PlayerState::Landed(_) => (conf.clip_near_surface, conf.clip_far_surface),
+    };
+    if !conf.auto_clip {
+        return (near, far);
     }
+    // Furthest planet surface, including terrain
+    let furthest = planets.iter().fold(0.0f32, |f, p| {
+        let r = p.radius * (1.0 + p.max_height);
+        (glm::distance(&p.position, eye) + r).max(f)
+    });
+    (near, (1.1 * furthest).min(far).max(near))
 }
 
 
+/// Advance the simulation by a fixed step of `delta_time` seconds. Props fall
+/// and bounce, throwing up dust where they land hard, comet tails turn away
+/// from the sun, and particles move.
+#[allow(clippy::too_many_arguments)]
+fn update(
+    delta_time: f32,
+    physics: &mut physics::Physics,
+    particles: &mut particles::ParticleSystem,
+    props: &mut [Node],
+    planets: &[planet::Planet],
+    planet_nodes: &[Node],
+    scene_root: &mut SceneNode,
+    impact: &particles::Emitter,
+) {
+    physics.step(props, planets, planet_nodes, delta_time);
+    for (position, up) in &physics.impacts {
+        particles.burst(position, up, impact, IMPACT_PARTICLES);
+    }
+    for prop in props.iter_mut() {
+        comet::update(prop, planets);
+    }
+    particles.update(scene_root, delta_time);
+}
+
 fn mouse_input(
-    delta: &std::sync::MutexGuard<'_, (f32, f32)>,
+    delta: &glm::Vec2, // Pixels the mouse moved this frame
+    pad_look: glm::Vec2, // Radians turned by the gamepad this frame
     player: &mut player::Player,
-    closest_planet: &planet::Planet,
+    cameras: &mut camera::Cameras,
     conf: &mut util::Config,
     delta_time: f32
 ) {
+    profile_scope!("input");
     /* Look left/right (horizontal angle), rotate around y axis */
-    let delta_h = (*delta).0 * delta_time * conf.mouse_speed;
+    let delta_h = delta.x * conf.mouse_speed * MOUSE_RADIANS_PER_PIXEL;
     /* Look up/down (vertical angle), rotate around x axis */
-    let delta_v = (*delta).1 * delta_time * conf.mouse_speed;
-    let up = player.up();
-    match player.state {
-        player::PlayerState::Landed(_) |
-        player::PlayerState::Anchored(_) => {
-            // vertical angle rotates around right -> modifies only direction
-            player.direction = glm::rotate_vec3(
-                &player.direction, -delta_v, &player.right
-            );
-            // horizontal angle rotates around up -> modifies right and direction
-            player.direction = glm::rotate_vec3(
-                &player.direction, -delta_h, &up
-            );
-        },
-        player::PlayerState::FreeFloat => {
-            // horizontal angle rotates around up -> modifies right and direction
-            player.direction = glm::rotate_vec3(
-                &player.direction, -delta_h, &up
-            );
-            // vertical angle rotates around right -> modifies up and direction
-            player.direction = glm::rotate_vec3(
-                &player.direction, -delta_v, &player.right
-            );
-
-        }
-    }
+    let delta_v = delta.y * conf.mouse_speed * MOUSE_RADIANS_PER_PIXEL;
+    cameras.look(player, glm::vec2(delta_h, delta_v) + pad_look, conf, delta_time);
 }
 
 
 /// Handle keyboard input
+#[allow(clippy::too_many_arguments)]
 fn keyboard_input(
-    keys: std::sync::MutexGuard<'_, std::vec::Vec<glutin::event::VirtualKeyCode>>,
-    key_debounce: &mut std::collections::HashMap<glutin::event::VirtualKeyCode, u32>,
+    frame_input: &input::InputFrame,
+    mut movement: camera::Movement, // From the gamepad, keys add to it
     player: &mut player::Player,
+    cameras: &mut camera::Cameras,
     closest_planet: &planet::Planet,
     conf: &mut util::Config,
+    frame_capture: &mut frame_capture::FrameCapture,
+    stats_overlay: &mut stats_overlay::StatsOverlay,
+    debug_panel: &mut debug_panel::DebugPanel,
+    camera_path: Option<&camera_path::CameraPath>,
+    playback: &mut camera_path::Playback,
+    clock: &mut simulation::SimulationClock,
+    save_scene: &mut bool,
+    reload_scene: &mut bool,
     delta_time: f32
 ) {
-    use player::PlayerState::*;
-    let up = player.up();
-    let _flat_direction = glm::cross(&up, &player.right);
-
-    // Transform from camera position to movement
-    let mut player_position = player.position - up * player.height;
-    let mut position = player_position;
-    let movement_speed = conf.movement_speed;
-    for key in keys.iter() {
-        match key {
-            /* Move left/right */
-            VirtualKeyCode::A => {
-                position -= match player.state {
-                    FreeFloat => player.right * delta_time * movement_speed,
-                    Anchored(_) |
-                    Landed(_) => player.right * delta_time * movement_speed,
-                }
-            },
-            VirtualKeyCode::D => {
-                position += match player.state {
-                    FreeFloat => player.right * delta_time * movement_speed,
-                    Anchored(_) |
-                    Landed(_) => player.right * delta_time * movement_speed,
-                }
-            },
-            /* Move forward (inward)/backward, in camera direction */
-            VirtualKeyCode::W => {
-                position += match player.state {
-                    FreeFloat => player.direction * delta_time * movement_speed,
-                    Anchored(_) |
-                    Landed(_) => _flat_direction * delta_time * movement_speed,
-                }
-            },
-            VirtualKeyCode::S => {
-                position -= match player.state {
-                    FreeFloat => player.direction * delta_time * movement_speed,
-                    Anchored(_) |
-                    Landed(_) => _flat_direction * delta_time * movement_speed,
-                }
-            },
-            /* Move up/down */
-            VirtualKeyCode::Space => {
-                match player.state {
-                    Landed(_) => {
-                        // Jump, set horizontal speed
-                        let planet_h = closest_planet.get_height(&position);
-                        let player_h = glm::length(&(
-                            player.feet() - closest_planet.position
-                        )); // closest_planet.position == a
-                        // Not quite right, but jetpack physics is alright as well
-                        if planet_h - player_h < H_ERROR {
-                            player.hspeed = conf.jump_speed;
-                        }
-                    },
-                    _ => position += up * delta_time * movement_speed,
-                }
-            },
-            VirtualKeyCode::LShift => {
-                position -= up * delta_time * movement_speed
-            },
-            VirtualKeyCode::M => {
-                let v = key_debounce.entry(VirtualKeyCode::M).or_insert(0);
-                if *v == 0 {
-                    conf.polymode = (conf.polymode + 1) % 3;
-                    *v = 10;
-                }
-            },
-            VirtualKeyCode::Up => {
-                let v = key_debounce.entry(VirtualKeyCode::Up).or_insert(0);
-                if *v == 0 {
-                    conf.movement_speed = conf.movement_speed * 1.6;
-                    *v = 10;
-                }
-            },
-            VirtualKeyCode::Down => {
-                let v = key_debounce.entry(VirtualKeyCode::Down).or_insert(0);
-                if *v == 0 {
-                    conf.movement_speed = conf.movement_speed / 1.6;
-                    *v = 10;
-                }
-            },
-            VirtualKeyCode::I => {
-                let v = key_debounce.entry(VirtualKeyCode::I).or_insert(0);
-                if *v == 0 {
-                    conf.draw_gui = !conf.draw_gui;
-                    *v = 10;
-                }
-            },
-            VirtualKeyCode::F => {
-                let v = key_debounce.entry(VirtualKeyCode::F).or_insert(0);
-                if *v == 0 {
-                    use player::PlayerState::*;
-                    player.state = match player.state {
-                        FreeFloat => {
-                            let a = closest_planet.position;
-                            eprintln!("Player position is {:?}", position);
-                            eprintln!("New origin is {:?}", a);
-                            position -= a;
-                            eprintln!("New player position is {:?}", position);
-                            Anchored(glm::vec3(0.0, 0.0, 0.0))
-                        },
-                        Anchored(_) => Landed(glm::vec3(0.0, 0.0, 0.0)),
-                        Landed(_) => FreeFloat,
-                    };
-                    *v = 10;
-                }
-            }
-            _ => { }
-        }
-
+    profile_scope!("input");
+    use input::Action;
+    /* Move left/right */
+    movement.right += frame_input.axis(Action::MoveLeft, Action::MoveRight);
+    /* Move forward (inward)/backward */
+    movement.forward += frame_input.axis(Action::MoveBack, Action::MoveForward);
+    /* Move up/down, or jump when walking */
+    movement.up += frame_input.axis(Action::MoveDown, Action::MoveUp);
+    /* Roll, when flying the ship */
+    movement.roll += frame_input.axis(Action::RollLeft, Action::RollRight);
+
+    if frame_input.pressed(Action::CyclePolygonMode) {
+        conf.polymode = (conf.polymode + 1) % 3;
     }
-
-    // Apply movement
-    if matches!(player.state, Landed(_)) {
-        // Apply gravitational pull
-        position += up * player.hspeed;
-        if player.hspeed > -MAX_H_SPEED {
-            player.hspeed -= delta_time * closest_planet.gravity;
+    if frame_input.pressed(Action::CycleDebugMode) {
+        conf.debug_mode = conf.debug_mode.next();
+        eprintln!("Debug mode: {:?}", conf.debug_mode);
+    }
+    if frame_input.pressed(Action::ToggleDebugLines) {
+        conf.debug_lines = !conf.debug_lines;
+    }
+    // Flying with auto_speed, the keys and wheel scale the speed per altitude
+    let speed = if conf.auto_speed && cameras.flying() {
+        if frame_input.scroll != 0.0 {
+            conf.auto_speed_rate *= 2f32.powf(frame_input.scroll / AUTO_SPEED_SCROLL);
         }
+        &mut conf.auto_speed_rate
+    } else {
+        &mut conf.movement_speed
+    };
+    if frame_input.pressed(Action::Faster) {
+        *speed *= 1.6;
     }
-    let height = (100.0 * closest_planet.get_height(&position)).round() / 100.0;
-    let go_to = (100.0 * glm::length(&(position - closest_planet.position))).round() / 100.0;
-    if go_to >= height {
-        player_position = position;
+    if frame_input.pressed(Action::Slower) {
+        *speed /= 1.6;
     }
-    else if matches!(player.state, Landed(_) | Anchored(_)) {
-        // Stick to the ground
-        player_position = glm::normalize(&position) * height;
+    if frame_input.pressed(Action::ToggleHud) {
+        conf.draw_gui = !conf.draw_gui;
     }
-    player.position = player_position + up * player.height;
-}
\ No newline at end of file
+    if frame_input.pressed(Action::ToggleStats) {
+        stats_overlay.visible = !stats_overlay.visible;
+    }
+    if frame_input.pressed(Action::ToggleMinimap) {
+        conf.minimap = !conf.minimap;
+    }
+    if frame_input.pressed(Action::ToggleOrbits) {
+        conf.orbit_paths = !conf.orbit_paths;
+    }
+    if frame_input.pressed(Action::TogglePanel) {
+        debug_panel.visible = !debug_panel.visible;
+    }
+    if frame_input.pressed(Action::SaveScene) {
+        *save_scene = true;
+    }
+    if frame_input.pressed(Action::ReloadScene) {
+        *reload_scene = true;
+    }
+    if frame_input.pressed(Action::CaptureFrame) {
+        frame_capture.request();
+    }
+    if frame_input.pressed(Action::PlayPause) {
+        clock.toggle_pause();
+    }
+    if frame_input.pressed(Action::Step) {
+        clock.step();
+    }
+    if frame_input.pressed(Action::TimeFaster) {
+        clock.faster();
+    }
+    if frame_input.pressed(Action::TimeSlower) {
+        clock.slower();
+    }
+    if let Some(path) = camera_path {
+        // The path follows simulation time, and starts over once finished
+        if frame_input.pressed(Action::PlayPause) && !playback.playing {
+            playback.toggle(path);
+            clock.paused = false;
+        }
+        /* Scrub through the camera path */
+        let scrub = frame_input.axis(Action::ScrubBack, Action::ScrubForward);
+        if scrub != 0.0 {
+            playback.scrub(scrub * CAMERA_PATH_SCRUB_SPEED * delta_time, path);
+        }
+    }
+    if frame_input.pressed(Action::CycleCamera) {
+        cameras.next(player, closest_planet);
+    }
+
+    // Apply movement
+    player.speed = cameras.speed(player, closest_planet, conf);
+    cameras.active().step(player, &movement, closest_planet, conf, delta_time);
+    cameras.auto_land(player, &movement, closest_planet, conf, delta_time);
+}
@@ -0,0 +1,77 @@
+//! Gamepad input through gilrs with `--features gamepad`, feeding the same
+//! movement and look as the keyboard and mouse
+
+#[cfg(feature = "gamepad")]
+use gilrs::{Axis, Button, GamepadId, Gilrs};
+use nalgebra_glm as glm;
+
+use crate::camera::Movement;
+use crate::util::Config;
+
+pub struct Gamepad {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<Gilrs>,
+    #[cfg(feature = "gamepad")]
+    active: Option<GamepadId>,
+}
+
+impl Gamepad {
+    /// Start listening for gamepads. Falls back to a no-op if unavailable
+    pub fn new() -> Self {
+        #[cfg(feature = "gamepad")]
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => {
+                for (_id, gamepad) in gilrs.gamepads() {
+                    eprintln!("Gamepad connected: {}", gamepad.name());
+                }
+                Some(gilrs)
+            }
+            Err(e) => {
+                eprintln!("Gamepad input not available ({})", e);
+                None
+            }
+        };
+        Gamepad {
+            #[cfg(feature = "gamepad")]
+            gilrs,
+            #[cfg(feature = "gamepad")]
+            active: None,
+        }
+    }
+
+    /// Ignore small stick values around the center, scaling the rest to the
+    /// full range so movement still starts smoothly
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn dead_zone(value: f32, dead_zone: f32) -> f32 {
+        if value.abs() <= dead_zone {
+            0.0
+        } else {
+            value.signum() * (value.abs() - dead_zone) / (1.0 - dead_zone)
+        }
+    }
+
+    /// Movement, and look rate in radians per second, from the sticks and
+    /// triggers. Nothing without a gamepad.
+    #[cfg_attr(not(feature = "gamepad"), allow(unused_variables, unused_mut))]
+    pub fn poll(&mut self, conf: &Config) -> (Movement, glm::Vec2) {
+        let (mut movement, mut look) = (Movement::default(), glm::vec2(0.0, 0.0));
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = self.gilrs.as_mut() {
+            while let Some(event) = gilrs.next_event() {
+                self.active = Some(event.id);
+            }
+            if let Some(gamepad) = self.active.and_then(|id| gilrs.connected_gamepad(id)) {
+                let axis = |axis| Self::dead_zone(gamepad.value(axis), conf.gamepad_dead_zone);
+                let trigger = |button| gamepad.button_data(button).map_or(0.0, |d| d.value());
+                movement.right = axis(Axis::LeftStickX);
+                movement.forward = axis(Axis::LeftStickY);
+                movement.up = trigger(Button::RightTrigger2) - trigger(Button::LeftTrigger2);
+                movement.roll = trigger(Button::RightTrigger) - trigger(Button::LeftTrigger);
+                // Stick up looks up, like moving the mouse away
+                look = glm::vec2(axis(Axis::RightStickX), -axis(Axis::RightStickY))
+                    * conf.gamepad_sensitivity;
+            }
+        }
+        (movement, look)
+    }
+}
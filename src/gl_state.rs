@@ -0,0 +1,207 @@
+//! Cache of the GL state set by the render queue, skipping calls that
+//! wouldn't change anything
+
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct GlState {
+    program: Option<u32>,
+    vao: Option<u32>,
+    active_unit: Option<u32>,
+    textures: HashMap<u32, (u32, u32)>, // Target and texture by unit
+    buffers: HashMap<(u32, u32), u32>,  // Buffers by target and binding point
+    capabilities: HashMap<u32, bool>,
+    depth_mask: Option<bool>,
+    stencil_mask: Option<u32>,
+    blend_func: Option<(u32, u32)>,
+    uniforms: HashMap<(u32, i32), [u32; 4]>, // Bits of the value by program and location
+    // Statistics, accumulated until reset
+    pub issued: usize,
+    pub avoided: usize,
+}
+
+impl GlState {
+    /// Forget bindings and capabilities, after other code may have changed
+    /// them
+    pub fn invalidate(&mut self) {
+        self.program = None;
+        self.vao = None;
+        self.active_unit = None;
+        self.textures.clear();
+        self.buffers.clear();
+        self.capabilities.clear();
+        self.depth_mask = None;
+        self.stencil_mask = None;
+        self.blend_func = None;
+    }
+
+    /// Forget everything, also uniforms, as programs may be replaced
+    pub fn end_frame(&mut self) {
+        self.invalidate();
+        self.uniforms.clear();
+    }
+
+    /// Forget the uniforms of `program`, after they were set elsewhere
+    pub fn forget_uniforms(&mut self, program: u32) {
+        self.uniforms.retain(|&(p, _), _| p != program);
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.issued = 0;
+        self.avoided = 0;
+    }
+
+    /// Remember `value` as the state, returning whether it changed
+    fn update<T: PartialEq>(cached: &mut Option<T>, value: T, issued: &mut usize, avoided: &mut usize) -> bool {
+        if cached.as_ref() == Some(&value) {
+            *avoided += 1;
+            false
+        } else {
+            *cached = Some(value);
+            *issued += 1;
+            true
+        }
+    }
+
+    /// Returns whether a call was made, as for the other setters
+    pub unsafe fn use_program(&mut self, program: u32) -> bool {
+        let changed = Self::update(&mut self.program, program, &mut self.issued, &mut self.avoided);
+        if changed {
+            gl::UseProgram(program);
+        }
+        changed
+    }
+
+    pub unsafe fn bind_vertex_array(&mut self, vao: u32) -> bool {
+        let changed = Self::update(&mut self.vao, vao, &mut self.issued, &mut self.avoided);
+        if changed {
+            gl::BindVertexArray(vao);
+        }
+        changed
+    }
+
+    /// Make texture `unit` active, counting from 0
+    pub unsafe fn active_texture(&mut self, unit: u32) -> bool {
+        let changed = Self::update(&mut self.active_unit, unit, &mut self.issued, &mut self.avoided);
+        if changed {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+        }
+        changed
+    }
+
+    /// Bind `texture` to `target` of texture `unit`, which is left active
+    pub unsafe fn bind_texture(&mut self, unit: u32, target: u32, texture: u32) -> bool {
+        let mut cached = self.textures.get(&unit).copied();
+        let changed = Self::update(&mut cached, (target, texture), &mut self.issued, &mut self.avoided);
+        if changed {
+            self.active_texture(unit);
+            gl::BindTexture(target, texture);
+            self.textures.insert(unit, (target, texture));
+        }
+        changed
+    }
+
+    /// Bind `buffer` to binding point `index` of `target`
+    pub unsafe fn bind_buffer_base(&mut self, target: u32, index: u32, buffer: u32) -> bool {
+        let mut cached = self.buffers.get(&(target, index)).copied();
+        let changed = Self::update(&mut cached, buffer, &mut self.issued, &mut self.avoided);
+        if changed {
+            gl::BindBufferBase(target, index, buffer);
+            self.buffers.insert((target, index), buffer);
+        }
+        changed
+    }
+
+    /// Enable or disable `capability`
+    pub unsafe fn set(&mut self, capability: u32, enabled: bool) -> bool {
+        let mut cached = self.capabilities.get(&capability).copied();
+        let changed = Self::update(&mut cached, enabled, &mut self.issued, &mut self.avoided);
+        if changed {
+            if enabled {
+                gl::Enable(capability);
+            } else {
+                gl::Disable(capability);
+            }
+            self.capabilities.insert(capability, enabled);
+        }
+        changed
+    }
+
+    pub unsafe fn depth_mask(&mut self, write: bool) -> bool {
+        let changed = Self::update(&mut self.depth_mask, write, &mut self.issued, &mut self.avoided);
+        if changed {
+            gl::DepthMask(if write { gl::TRUE } else { gl::FALSE });
+        }
+        changed
+    }
+
+    pub unsafe fn stencil_mask(&mut self, mask: u32) -> bool {
+        let changed = Self::update(&mut self.stencil_mask, mask, &mut self.issued, &mut self.avoided);
+        if changed {
+            gl::StencilMask(mask);
+        }
+        changed
+    }
+
+    pub unsafe fn blend_func(&mut self, source: u32, destination: u32) -> bool {
+        let changed = Self::update(&mut self.blend_func, (source, destination), &mut self.issued, &mut self.avoided);
+        if changed {
+            gl::BlendFunc(source, destination);
+        }
+        changed
+    }
+
+    /// Whether the uniform at `location` of the program in use needs setting
+    /// to `bits`. Always, when the program isn't known, and never for
+    /// uniforms the program doesn't have.
+    fn uniform_changed(&mut self, location: i32, bits: [u32; 4]) -> bool {
+        if location < 0 {
+            return false;
+        }
+        let program = match self.program {
+            Some(program) => program,
+            None => {
+                self.issued += 1;
+                return true;
+            }
+        };
+        let mut cached = self.uniforms.get(&(program, location)).copied();
+        let changed = Self::update(&mut cached, bits, &mut self.issued, &mut self.avoided);
+        if changed {
+            self.uniforms.insert((program, location), bits);
+        }
+        changed
+    }
+
+    pub unsafe fn uniform_1i(&mut self, location: i32, value: i32) -> bool {
+        let changed = self.uniform_changed(location, [value as u32, 0, 0, 0]);
+        if changed {
+            gl::Uniform1i(location, value);
+        }
+        changed
+    }
+
+    pub unsafe fn uniform_1ui(&mut self, location: i32, value: u32) -> bool {
+        let changed = self.uniform_changed(location, [value, 0, 0, 0]);
+        if changed {
+            gl::Uniform1ui(location, value);
+        }
+        changed
+    }
+
+    pub unsafe fn uniform_1f(&mut self, location: i32, value: f32) -> bool {
+        let changed = self.uniform_changed(location, [value.to_bits(), 0, 0, 0]);
+        if changed {
+            gl::Uniform1f(location, value);
+        }
+        changed
+    }
+
+    pub unsafe fn uniform_4f(&mut self, location: i32, value: &[f32; 4]) -> bool {
+        let changed = self.uniform_changed(location, value.map(f32::to_bits));
+        if changed {
+            gl::Uniform4fv(location, 1, value.as_ptr());
+        }
+        changed
+    }
+}
@@ -4,22 +4,146 @@
 
 pub const SCALING_FACTOR: f32 = 10.0;
 pub const WORLD_SPEED: f32 = 0.5;
+pub const SAVED_SCENE_PATH: &str = "resources/scenes/saved.ron"; // Written with F5
+pub const RECORD_MAX_SETTLE_FRAMES: u32 = 120; // Redraws waiting for terrain before recording a frame anyway
+pub const MAX_VIEWS: usize = 2; // Drawn side by side in the window, with split_screen
 
-//-main.rs---------------------------------------------------------------------/
+//-render_queue.rs-------------------------------------------------------------/
 
-pub const SCREEN_W: u32 = 1920;
-pub const SCREEN_H: u32 = 1080;
+pub const OUTLINE_WIDTH: f32 = 0.02; // Relative to the radius of the selected planet
+pub const OUTLINE_COLOR: [f32; 3] = [1.0, 0.75, 0.1];
+
+//-normal_debug.rs-------------------------------------------------------------/
+
+pub const DEBUG_NORMAL_LENGTH: f32 = 0.5; // Of face normals, in mean edges of the triangle
 
 //-mesh.rs---------------------------------------------------------------------/
 
 // Iterations of fractal noise
 pub const FRACTAL_ITERATIONS: usize = 8;
+// Angle (rad) between faces beyond which models without normals get a hard edge
+pub const SMOOTHING_ANGLE: f32 = 0.8;
+
+//-mesh_optimize.rs------------------------------------------------------------/
+
+pub const FORSYTH_CACHE_SIZE: usize = 32; // Vertex cache modelled when reordering triangles
+pub const SIMULATED_CACHE_SIZE: usize = 16; // FIFO cache for measuring misses per triangle
+
+//-camera.rs-------------------------------------------------------------------/
+
+// Third person camera offset from the player's eyes, in player heights
+pub const THIRD_PERSON_DISTANCE: f32 = 6.0;
+pub const THIRD_PERSON_HEIGHT: f32 = 2.0;
+pub const MOUSE_RADIANS_PER_PIXEL: f32 = 1.0 / 60.0; // Turned with mouse_speed=1.0
+pub const ORBIT_DAMPING: f32 = 8.0; // Rate of easing towards the dragged orbit, per second
+pub const TILT_DAMPING: f32 = 4.0; // Rate of banking into and out of turns, per second
+pub const ORBIT_ZOOM_SPEED: f32 = 0.002; // Per scrolled pixel
+pub const ORBIT_MAX_PITCH: f32 = 1.5;
+// Orbit distance limits, in radii of the body
+pub const ORBIT_MIN_DISTANCE: f32 = 1.3;
+pub const ORBIT_MAX_DISTANCE: f32 = 200.0;
+pub const AUTO_SPEED_SCROLL: f32 = 200.0; // Scrolled pixels doubling the speed, with auto_speed
+pub const TAKEOFF_HOLD: f32 = 0.4; // Seconds of holding jump to take off after landing
+
+//-camera_path.rs--------------------------------------------------------------/
+
+pub const CAMERA_PATH_SCRUB_SPEED: f32 = 5.0; // Seconds of the path per second held
+
+//-physics.rs------------------------------------------------------------------/
+
+pub const GRAVITY_SCALE: f32 = 1.0; // Surface gravity of props, in radii/s² per unit of planet gravity
+pub const PHYSICS_RESTITUTION: f32 = 0.3; // Speed kept bouncing off the ground
+pub const PHYSICS_FRICTION: f32 = 4.0; // Rate of slowing down sliding on the ground, per second
+pub const PHYSICS_MAX_STEP: f32 = 0.05; // Longer frames are simulated slower, so nothing falls through
+
+//-lens_flare.rs---------------------------------------------------------------/
+
+pub const FLARE_PROBE_SIZE: f32 = 8.0; // Pixels across tested for occlusion of each light
+pub const FLARE_FADE: f32 = 12.0; // Rate of fading in and out with visibility, per second
+pub const FLARE_INTENSITY: f32 = 0.6;
+
+//-light_shafts.rs-------------------------------------------------------------/
+
+pub const SHAFT_DOWNSCALE: u32 = 2; // Resolution of the occlusion mask, relative to the window
+pub const SHAFT_THRESHOLD: f32 = 0.3; // Brightness of the light source that isn't covered
+pub const SHAFT_DENSITY: f32 = 0.9; // Share of the way to the light sampled
+pub const SHAFT_DECAY: f32 = 0.97; // Weight kept per sample, lower for shorter rays
+pub const SHAFT_INTENSITY: f32 = 1.5;
+
+//-depth_of_field.rs-----------------------------------------------------------/
+
+pub const DOF_MAX_RADIUS: f32 = 16.0; // Pixels blurred over at most, however large the aperture
+
+//-motion_blur.rs--------------------------------------------------------------/
+
+pub const MOTION_BLUR_MAX_LENGTH: f32 = 32.0; // Pixels blurred over at most, however fast the motion
+
+//-starfield.rs----------------------------------------------------------------/
+
+pub const STAR_MAGNITUDE_LIMIT: f32 = 6.5; // Fainter catalog stars are skipped, about what the eye sees
+pub const STAR_REFERENCE_MAGNITUDE: f32 = 2.5; // Drawn fully opaque at a brightness of 1
+
+//-nebula.rs-------------------------------------------------------------------/
+
+pub const NEBULA_SIZE: u32 = 512; // Texels across each face of the baked cubemap
+
+//-texture.rs------------------------------------------------------------------/
+
+pub const DETAIL_MAP_SIZE: u32 = 512; // Texels across the planet detail normal map
+pub const DETAIL_CRATERS: usize = 60; // Craters in each tile of the detail map
+pub const DETAIL_OCTAVES: usize = 5; // Octaves of rock grain in the detail map
+pub const TERRAIN_MAP_SIZE: u32 = 256; // Texels across the rock, grass and snow patterns
+pub const TERRAIN_OCTAVES: usize = 5; // Octaves of the rock pattern, the others use two fewer
+pub const TEXTURE_PREVIEW_SIZE: u32 = 16; // Texels across the stand-in of a texture still uploading
+
+//-city_lights.rs--------------------------------------------------------------/
+
+pub const CITY_LIGHTS_SIZE: u32 = 1024; // Texels around the equator of the baked lights
+pub const CITY_CLUSTERS: usize = 80; // Cities on a planet of city density 1
+pub const CITY_LIGHTS_PER_CLUSTER: usize = 60;
+
+//-surface_features.rs---------------------------------------------------------/
+
+pub const MAX_SURFACE_FEATURES: usize = 32; // Per planet, as MAX_FEATURES in planets.comp and terrain.tes
+
+//-erosion.rs------------------------------------------------------------------/
+
+pub const EROSION_DROPLET_STEPS: usize = 48; // Vertices a raindrop runs over at most
+pub const EROSION_INERTIA: f32 = 0.3; // Share of its direction a raindrop keeps each step
+pub const EROSION_CAPACITY: f32 = 8.0; // Sediment carried per drop in height, speed and water
+pub const EROSION_RATE: f32 = 0.3; // Share of the spare capacity picked up each step
+pub const EROSION_DEPOSITION: f32 = 0.3; // Share of the excess sediment dropped each step
+pub const EROSION_EVAPORATION: f32 = 0.02; // Share of the water evaporating each step
+pub const EROSION_TALUS: f32 = 0.8; // Steepest slope that holds, as height over distance
+pub const EROSION_BORDER: f32 = 0.15; // Share of a patch from its edges where erosion fades in
+
+//-vegetation.rs---------------------------------------------------------------/
+
+pub const VEGETATION_CELLS: usize = 4096; // Cells across each face of the cube around a planet
+pub const VEGETATION_PER_CELL: usize = 48; // Plants tried in each cell, for vegetation 1
+pub const VEGETATION_CELLS_PER_FRAME: usize = 16; // Cells started growing on the workers per frame
+pub const VEGETATION_REACH: f32 = 0.004; // Radii from the camera plants are grown within
+pub const VEGETATION_MAX_SLOPE: f32 = 0.6; // Steepest ground plants grow on, as height over distance
+pub const VEGETATION_TREE_SIZE: f32 = 3e-4; // Height of trees, in radii
+pub const VEGETATION_GRASS_SIZE: f32 = 4e-5; // Height of grass, in radii
+
+//-particles.rs----------------------------------------------------------------/
+
+pub const MAX_PARTICLES: usize = 20000;
+pub const MAX_GPU_PARTICLES: usize = 1 << 18; // Slots in the storage buffer of emitters simulated on the GPU
+pub const GPU_PARTICLES_BINDING: u32 = 3; // Storage buffer binding of the particles on the GPU
+pub const IMPACT_SPEED: f32 = 0.05; // Landing faster than this, in radii per second, kicks up dust
+pub const IMPACT_PARTICLES: usize = 60;
 
 //-player.rs-------------------------------------------------------------------/
 
 pub const MAX_H_SPEED: f32 = 1.0; // Maximum horizontal speed from gravity
 pub const H_ERROR: f32 = 0.001; // Margin of error for height computation
 
+//-jobs.rs---------------------------------------------------------------------/
+
+pub const JOB_DRAIN_BUDGET_MS: u64 = 2; // Time per frame for finishing jobs on the render thread
+
 //-procedural_planets.rs-------------------------------------------------------/
 
 /// Thresholds for level of detail
@@ -28,3 +152,114 @@ pub const MAX_IN_FLIGHT: u64 = 4;
 //const THRESHOLD: [f32; MAX_LOD] = [128.0, 32.0, 16.0, 8.0, 4.0, 2.0];
 pub const SUBDIVS_PER_LEVEL: usize = 16; // 256: 480+380=860ms, 128: 127+98=225ms
 pub const N_LAYERS: usize = 5; // Must match with scene.frag:22
+pub const HORIZON_MARGIN: f32 = 0.05; // Extra angle (rad) before culling patches behind the horizon
+
+//-terrain_tessellation.rs-----------------------------------------------------/
+
+pub const TESS_PATCHES_PER_SIDE: usize = 32; // Split up to 64 times more on the GPU
+
+//-terrain_batch.rs------------------------------------------------------------/
+
+pub const BATCH_VERTEX_MB: u64 = 64; // Shared vertex buffer terrain patches are copied into
+pub const BATCH_INDEX_MB: u64 = 24; // And their indices
+pub const BATCH_MIN_DRAWS: usize = 4; // Fewer patches in a row are drawn one by one
+pub const BATCH_DRAWS_BINDING: u32 = 2; // Storage buffer binding of the per-draw transformations
+
+//-profiler.rs-----------------------------------------------------------------/
+
+pub const PROFILER_WINDOW: usize = 60; // Frames averaged over
+pub const PROFILER_MAX_PENDING: usize = 4; // Frames waiting for results before timing is skipped
+
+//-simulation.rs---------------------------------------------------------------/
+
+pub const SIMULATION_STEP: f32 = 1.0 / 60.0; // Seconds simulated by each fixed update
+pub const MAX_SIMULATION_STEPS: usize = 8; // Updates run per frame, the rest due are skipped
+pub const SHADER_TIME_WRAP: f64 = 86400.0; // Seconds the time in shaders wraps at, keeping it precise in single precision
+pub const TIME_SCALE_MAX: f32 = 64.0; // Fastest time scale run in fixed updates, and the inverse of the slowest
+// Time scales warped through past TIME_SCALE_MAX, from 10 minutes to a Julian year per second
+pub const TIME_WARP_STOPS: [f32; 7] = [600.0, 3600.0, 21600.0, 86400.0, 604800.0, 2629800.0, 31557600.0];
+
+//-nbody.rs--------------------------------------------------------------------/
+
+pub const NBODY_SUBSTEPS: usize = 4; // Integration steps per fixed update
+pub const NBODY_SOFTENING: f32 = 0.5; // Distance added to every pull, so close passes stay finite
+
+//-skinning.rs-----------------------------------------------------------------/
+
+pub const MAX_JOINTS: usize = 128; // Must match with scene.vert
+pub const BONES_BINDING: u32 = 0; // Uniform buffer binding of the bone matrices
+
+//-impostor.rs-----------------------------------------------------------------/
+
+pub const IMPOSTOR_SIZE: u32 = 128; // Texels across the texture of each impostor
+pub const IMPOSTOR_ANGLE: f32 = 0.05; // Angle (rad) the eye moves around a planet before a new texture
+pub const IMPOSTOR_MAX_AGE: u64 = 120; // Frames before rendering again anyway, for spin and lighting
+pub const IMPOSTOR_UPDATES: usize = 1; // Textures rendered per frame at most
+
+//-occlusion.rs----------------------------------------------------------------/
+
+pub const OCCLUSION_MARGIN: f32 = 1.2; // Boxes tested are this much larger than the bodies
+pub const OCCLUSION_CONFIRM: usize = 2; // Results in a row with a body covered before it's hidden
+
+//-minimap.rs------------------------------------------------------------------/
+
+pub const MINIMAP_SIZE: u32 = 256; // Pixels across the inset, at most half the window height
+pub const MINIMAP_MARGIN: u32 = 16; // Pixels from the corner of the window
+pub const MINIMAP_ORBIT_SEGMENTS: usize = 64;
+pub const MINIMAP_DOT_PIXELS: f32 = 4.0; // Smallest size of bodies, however far out the map reaches
+pub const MINIMAP_FRUSTUM: f32 = 0.15; // Length of the camera frustum, relative to the map's reach
+
+//-body_info.rs----------------------------------------------------------------/
+
+pub const BODY_INFO_SAMPLES: usize = 4096; // Terrain heights sampled for the share of each colour layer
+
+//-labels.rs-------------------------------------------------------------------/
+
+pub const LABEL_MIN_SCALE: f32 = 0.4; // Text scale of labels on bodies far away
+pub const LABEL_MAX_SCALE: f32 = 0.8; // Text scale of labels on bodies up close
+pub const LABEL_FULL_SIZE: f32 = 0.05; // Apparent radius over distance where labels reach full size
+pub const LABEL_FADE_RATE: f32 = 8.0; // Per second, of fading in and out behind planets
+
+//-reticle.rs------------------------------------------------------------------/
+
+pub const RETICLE_SCALE: f32 = 0.8; // Text scale of the crosshair
+
+//-comet.rs--------------------------------------------------------------------/
+
+pub const COMET_LOBES: usize = 6; // Bulges pushed out of the nucleus
+pub const COMET_ACTIVE_RADII: f32 = 12.0; // Sun radii within which the tail blows in full
+
+//-orbit_paths.rs--------------------------------------------------------------/
+
+pub const ORBIT_PATH_SEGMENTS: usize = 256; // Points along each path
+pub const ORBIT_PATH_INTERVAL: usize = 30; // Frames between sampling the paths again
+pub const ORBIT_PATH_ALPHA: f32 = 0.6; // Opacity of the paths, to see the planets through them
+
+//-reflections.rs--------------------------------------------------------------/
+
+pub const REFLECTION_SIZE: u32 = 128; // Texels across each face of an ocean's cubemap
+pub const REFLECTION_FACES: usize = 2; // Cubemap faces rendered per frame, round the oceans in turn
+
+//-ibl.rs----------------------------------------------------------------------/
+
+pub const ENVIRONMENT_SIZE: u32 = 128; // Texels across each face of the sky and its sharpest reflection
+pub const ENVIRONMENT_LEVELS: u32 = 5; // Mip levels of reflections, from mirror to fully rough
+pub const IRRADIANCE_SIZE: u32 = 16; // Texels across each face of the diffuse light
+pub const ENVIRONMENT_SAMPLES: u32 = 256; // Directions averaged per texel of rough reflections
+
+//-ship.rs---------------------------------------------------------------------/
+
+pub const SHIP_LENGTH: f32 = 4.0; // Of the built-in hull, nose to engine
+pub const SHIP_THRUST: f32 = 0.5; // Acceleration in movement speeds per second
+pub const SHIP_DRAG: f32 = 0.25; // Rate of losing speed while drifting, per second
+pub const SHIP_TURN_DAMPING: f32 = 4.0; // Rate of turns easing out, per second
+pub const SHIP_ROLL_ACCELERATION: f32 = 6.0; // Radians per second squared, while rolling
+pub const SHIP_IDLE: f32 = 0.15; // Exhaust while not thrusting, of full burn
+pub const SHIP_CLEARANCE: f32 = 1.0; // Closest the ship gets to the ground
+pub const SHIP_CHASE_DISTANCE: f32 = 3.0; // Chase camera behind the ship, in ship lengths
+pub const SHIP_CHASE_HEIGHT: f32 = 0.8; // Chase camera above the ship, in ship lengths
+pub const SHIP_CHASE_DAMPING: f32 = 6.0; // Rate of the chase camera catching up, per second
+
+//-bookmarks.rs----------------------------------------------------------------/
+
+pub const BOOKMARK_SLOTS: usize = 9; // Saved with Ctrl and the number keys 1 to 9
@@ -0,0 +1,478 @@
+//! Loader for glTF 2.0 models, with their skin and animations
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{self, Error};
+use crate::globals::{MAX_JOINTS, SMOOTHING_ANGLE};
+use crate::mesh::{generate_color_vec, Mesh};
+use crate::skinning::{AnimationClip, Channel, Joint, Property, Skeleton};
+use crate::util;
+
+const GLB_JSON: u32 = 0x4E4F534A;
+const GLB_BINARY: u32 = 0x004E4942;
+
+pub struct Model {
+    pub mesh: Mesh,
+    pub skeleton: Option<Skeleton>, // Joints and clips of a skinned model
+}
+
+//-----------------------------------------------------------------------------/
+// The parts of the file format used
+//-----------------------------------------------------------------------------/
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct Document {
+    buffers: Vec<Buffer>,
+    buffer_views: Vec<BufferView>,
+    accessors: Vec<Accessor>,
+    meshes: Vec<MeshDoc>,
+    nodes: Vec<NodeDoc>,
+    skins: Vec<SkinDoc>,
+    animations: Vec<AnimationDoc>,
+}
+
+#[derive(Deserialize)]
+struct Buffer {
+    uri: Option<String>, // Left out for the binary chunk of a .glb
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_stride: Option<usize>, // Tightly packed when left out
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Accessor {
+    buffer_view: Option<usize>, // All zero when left out
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct MeshDoc {
+    primitives: Vec<Primitive>,
+}
+
+#[derive(Deserialize)]
+struct Primitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    mode: Option<u32>, // Triangles when left out
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct NodeDoc {
+    name: Option<String>,
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    skin: Option<usize>,
+    matrix: Option<[f32; 16]>, // Column major, instead of the rest
+    translation: Option<[f32; 3]>,
+    rotation: Option<[f32; 4]>,
+    scale: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SkinDoc {
+    joints: Vec<usize>,
+    inverse_bind_matrices: Option<usize>, // Identity when left out
+}
+
+#[derive(Deserialize)]
+struct AnimationDoc {
+    name: Option<String>,
+    channels: Vec<ChannelDoc>,
+    samplers: Vec<SamplerDoc>,
+}
+
+#[derive(Deserialize)]
+struct ChannelDoc {
+    sampler: usize,
+    target: TargetDoc,
+}
+
+#[derive(Deserialize)]
+struct TargetDoc {
+    node: Option<usize>,
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct SamplerDoc {
+    input: usize,
+    output: usize,
+    interpolation: Option<String>, // Linear when left out
+}
+
+//-----------------------------------------------------------------------------/
+// Loading
+//-----------------------------------------------------------------------------/
+
+/// Read a `.gltf` or `.glb` file, coloured `color`
+pub fn load(path: &str, color: glm::Vec4) -> error::Result<Model> {
+    let bytes = std::fs::read(path).map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    Gltf::parse(&bytes, dir)
+        .and_then(|gltf| gltf.model(color))
+        .map_err(|message| Error::Gltf {
+            path: path.to_string(),
+            message,
+        })
+}
+
+struct Gltf {
+    doc: Document,
+    buffers: Vec<Vec<u8>>,
+    parents: Vec<Option<usize>>, // Of each node
+}
+
+impl Gltf {
+    fn parse(bytes: &[u8], dir: &Path) -> Result<Self, String> {
+        let (json, binary) = if bytes.starts_with(b"glTF") {
+            split_glb(bytes).ok_or("truncated binary file")?
+        } else {
+            (bytes, None)
+        };
+        let doc: Document = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+        let buffers = doc
+            .buffers
+            .iter()
+            .map(|buffer| match buffer.uri.as_deref() {
+                None => binary.map(<[u8]>::to_vec).ok_or_else(|| "buffer without data".to_string()),
+                Some(uri) if uri.starts_with("data:") => uri
+                    .split_once(";base64,")
+                    .and_then(|(_, data)| decode_base64(data))
+                    .ok_or_else(|| "buffer with an unreadable data URI".to_string()),
+                Some(uri) => std::fs::read(dir.join(uri)).map_err(|e| format!("{}: {}", uri, e)),
+            })
+            .collect::<Result<_, _>>()?;
+        let mut parents = vec![None; doc.nodes.len()];
+        for (i, node) in doc.nodes.iter().enumerate() {
+            for &child in &node.children {
+                *parents.get_mut(child).ok_or("missing node")? = Some(i);
+            }
+        }
+        Ok(Gltf {
+            doc,
+            buffers,
+            parents,
+        })
+    }
+
+    fn model(&self, color: glm::Vec4) -> Result<Model, String> {
+        let skin = (!self.doc.skins.is_empty()).then_some(0);
+        let mut mesh = Mesh::default();
+        for (i, node) in self.doc.nodes.iter().enumerate() {
+            let Some(index) = node.mesh else { continue };
+            // Skinned meshes are in the bind pose, wherever their node is
+            let transform = match skin {
+                Some(_) if node.skin == skin => glm::identity(),
+                Some(_) => continue,
+                None => self.global(i),
+            };
+            let primitives = &self.doc.meshes.get(index).ok_or("missing mesh")?.primitives;
+            for primitive in primitives {
+                if let Some(part) = self.primitive(primitive, &transform, color, skin.is_some())? {
+                    mesh.merge(&part);
+                }
+            }
+        }
+        if mesh.indices.is_empty() {
+            return Err("no triangles".to_string());
+        }
+        mesh.compute_tangents();
+        let skeleton = skin.map(|skin| self.skeleton(skin)).transpose()?;
+        Ok(Model { mesh, skeleton })
+    }
+
+    /// Triangles of a primitive, moved by `transform`. Skinned primitives
+    /// always get joints and weights, zero where the file has none.
+    fn primitive(
+        &self,
+        primitive: &Primitive,
+        transform: &glm::Mat4,
+        color: glm::Vec4,
+        skinned: bool,
+    ) -> Result<Option<Mesh>, String> {
+        if primitive.mode.unwrap_or(4) != 4 {
+            return Ok(None);
+        }
+        let attribute = |name: &str| {
+            primitive
+                .attributes
+                .get(name)
+                .map(|&accessor| self.read(accessor).map(|(values, _)| values))
+                .transpose()
+        };
+        let Some(positions) = attribute("POSITION")? else {
+            return Ok(None);
+        };
+        let count = positions.len() / 3;
+        let normal_matrix = glm::inverse_transpose(glm::mat4_to_mat3(transform));
+
+        let mut mesh = Mesh {
+            vertices: positions
+                .chunks_exact(3)
+                .flat_map(|p| {
+                    let p = transform * glm::vec4(p[0], p[1], p[2], 1.0);
+                    [p.x, p.y, p.z]
+                })
+                .collect(),
+            // Flipped, glTF starts at the top of the texture
+            texture_coordinates: attribute("TEXCOORD_0")?
+                .map(|uvs| uvs.chunks_exact(2).flat_map(|uv| [uv[0], 1.0 - uv[1]]).collect())
+                .unwrap_or_else(|| vec![0.0; count * 2]),
+            colors: generate_color_vec(color, count),
+            ..Default::default()
+        };
+        if skinned {
+            let weights = attribute("WEIGHTS_0")?.unwrap_or_else(|| vec![0.0; count * 4]);
+            mesh.weights = weights
+                .chunks_exact(4)
+                .flat_map(|w| {
+                    let total: f32 = w.iter().sum();
+                    w.iter().map(move |x| if total > 0.0 { x / total } else { 0.0 })
+                })
+                .collect();
+            mesh.joints = attribute("JOINTS_0")?.unwrap_or_else(|| vec![0.0; count * 4]);
+        }
+        // Read as floats, exact for far more vertices than a model has
+        mesh.indices = match primitive.indices {
+            Some(accessor) => self.read(accessor)?.0.iter().map(|&i| i as u32).collect(),
+            None => (0..count as u32).collect(),
+        };
+        mesh.index_count = mesh.indices.len() as i32;
+        if mesh.indices.iter().any(|&i| i as usize >= count) {
+            return Err("index out of range".to_string());
+        }
+        match attribute("NORMAL")? {
+            Some(normals) => {
+                mesh.normals = normals
+                    .chunks_exact(3)
+                    .flat_map(|n| {
+                        let n = glm::normalize(&(normal_matrix * glm::vec3(n[0], n[1], n[2])));
+                        [n.x, n.y, n.z]
+                    })
+                    .collect()
+            }
+            None => mesh.recompute_normals(Some(SMOOTHING_ANGLE)),
+        }
+        Ok(Some(mesh))
+    }
+
+    /// Joints of a skin, and the animations moving them
+    fn skeleton(&self, skin: usize) -> Result<Skeleton, String> {
+        let skin = &self.doc.skins[skin];
+        if skin.joints.len() > MAX_JOINTS {
+            return Err(format!("{} joints, at most {} are supported", skin.joints.len(), MAX_JOINTS));
+        }
+        let inverse_bind: Vec<glm::Mat4> = match skin.inverse_bind_matrices {
+            Some(accessor) => self.read(accessor)?.0.chunks_exact(16).map(glm::make_mat4).collect(),
+            None => vec![glm::identity(); skin.joints.len()],
+        };
+        if inverse_bind.len() != skin.joints.len() {
+            return Err("an inverse bind matrix missing for a joint".to_string());
+        }
+        let joint_of = |node: usize| skin.joints.iter().position(|&j| j == node);
+
+        let mut joints = Vec::new();
+        for (&node, inverse_bind) in skin.joints.iter().zip(inverse_bind) {
+            let doc = self.doc.nodes.get(node).ok_or("missing node")?;
+            // Nodes between joints are left out
+            let mut above = self.parents[node];
+            while let Some(p) = above.filter(|&p| joint_of(p).is_none()) {
+                above = self.parents[p];
+            }
+            let parent = above.and_then(joint_of);
+            let (translation, rotation, scale) = trs(doc);
+            joints.push(Joint {
+                name: doc.name.clone().unwrap_or_default(),
+                parent,
+                armature: match (parent, self.parents[node]) {
+                    (None, Some(p)) => self.global(p),
+                    _ => glm::identity(),
+                },
+                translation,
+                rotation,
+                scale,
+                inverse_bind,
+            });
+        }
+
+        let mut clips = Vec::new();
+        for (i, animation) in self.doc.animations.iter().enumerate() {
+            let mut channels = Vec::new();
+            for channel in &animation.channels {
+                let Some(joint) = channel.target.node.and_then(joint_of) else {
+                    continue;
+                };
+                let property = match channel.target.path.as_str() {
+                    "translation" => Property::Translation,
+                    "rotation" => Property::Rotation,
+                    "scale" => Property::Scale,
+                    _ => continue,
+                };
+                let sampler = animation.samplers.get(channel.sampler).ok_or("missing sampler")?;
+                let times = self.read(sampler.input)?.0;
+                let (values, components) = self.read(sampler.output)?;
+                let mut values: Vec<glm::Vec4> = values
+                    .chunks_exact(components)
+                    .map(|v| glm::vec4(v[0], v[1], v[2], v.get(3).copied().unwrap_or(0.0)))
+                    .collect();
+                let interpolation = sampler.interpolation.as_deref().unwrap_or("LINEAR");
+                if interpolation == "CUBICSPLINE" {
+                    // Keys between their tangents, interpolated linearly instead
+                    values = values.chunks_exact(3).map(|key| key[1]).collect();
+                }
+                if times.is_empty() || times.len() != values.len() {
+                    return Err("animation keys without values".to_string());
+                }
+                channels.push(Channel {
+                    joint,
+                    property,
+                    step: interpolation == "STEP",
+                    times,
+                    values,
+                });
+            }
+            let name = animation.name.clone().unwrap_or_else(|| format!("animation {}", i));
+            clips.push(AnimationClip::new(name, channels));
+        }
+        Ok(Skeleton { joints, clips })
+    }
+
+    /// Transformation of a node in the scene
+    fn global(&self, node: usize) -> glm::Mat4 {
+        let above = self.parents[node].map_or(glm::identity(), |p| self.global(p));
+        above * local(&self.doc.nodes[node])
+    }
+
+    /// Values of an accessor as floats, and the number of them per element
+    fn read(&self, index: usize) -> Result<(Vec<f32>, usize), String> {
+        let accessor = self.doc.accessors.get(index).ok_or("missing accessor")?;
+        let components = match accessor.kind.as_str() {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            "MAT4" => 16,
+            kind => return Err(format!("unsupported accessor type {}", kind)),
+        };
+        let size = match accessor.component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            kind => return Err(format!("unknown component type {}", kind)),
+        };
+        let Some(view) = accessor.buffer_view else {
+            return Ok((vec![0.0; accessor.count * components], components));
+        };
+        let view = self.doc.buffer_views.get(view).ok_or("missing buffer view")?;
+        let buffer = self.buffers.get(view.buffer).ok_or("missing buffer")?;
+        let stride = view.byte_stride.unwrap_or(components * size);
+        let start = view.byte_offset + accessor.byte_offset;
+
+        let mut values = Vec::with_capacity(accessor.count * components);
+        for element in 0..accessor.count {
+            for component in 0..components {
+                let at = start + element * stride + component * size;
+                let b = buffer.get(at..at + size).ok_or("accessor outside its buffer")?;
+                let normalized = accessor.normalized;
+                values.push(match accessor.component_type {
+                    5120 if normalized => (b[0] as i8 as f32 / 127.0).max(-1.0),
+                    5120 => b[0] as i8 as f32,
+                    5121 if normalized => b[0] as f32 / 255.0,
+                    5121 => b[0] as f32,
+                    5122 if normalized => (i16::from_le_bytes([b[0], b[1]]) as f32 / 32767.0).max(-1.0),
+                    5122 => i16::from_le_bytes([b[0], b[1]]) as f32,
+                    5123 if normalized => u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0,
+                    5123 => u16::from_le_bytes([b[0], b[1]]) as f32,
+                    5125 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+                    _ => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                });
+            }
+        }
+        Ok((values, components))
+    }
+}
+
+/// Translation, rotation and scale of a node
+fn trs(node: &NodeDoc) -> (glm::Vec3, glm::Quat, glm::Vec3) {
+    if let Some(matrix) = node.matrix {
+        return util::decompose(&glm::make_mat4(&matrix));
+    }
+    (
+        node.translation.map_or(glm::zero(), glm::Vec3::from),
+        node.rotation.map_or(glm::quat_identity(), |[x, y, z, w]| glm::quat(x, y, z, w)),
+        node.scale.map_or(glm::vec3(1.0, 1.0, 1.0), glm::Vec3::from),
+    )
+}
+
+/// Transformation of a node relative to its parent
+fn local(node: &NodeDoc) -> glm::Mat4 {
+    if let Some(matrix) = node.matrix {
+        return glm::make_mat4(&matrix);
+    }
+    let (translation, rotation, scale) = trs(node);
+    glm::translation(&translation) * glm::quat_to_mat4(&rotation) * glm::scaling(&scale)
+}
+
+/// JSON and binary chunks of a .glb file
+fn split_glb(bytes: &[u8]) -> Option<(&[u8], Option<&[u8]>)> {
+    let word = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let (mut json, mut binary) = (None, None);
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let (length, kind) = (word(at)? as usize, word(at + 4)?);
+        let chunk = bytes.get(at + 8..at + 8 + length)?;
+        match kind {
+            GLB_JSON => json = Some(chunk),
+            GLB_BINARY => binary = Some(chunk),
+            _ => {}
+        }
+        at += 8 + length;
+    }
+    Some((json?, binary))
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut bits, mut n) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=' && !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6 | value as u32) & 0xFFFF;
+        n += 6;
+        if n >= 8 {
+            n -= 8;
+            bytes.push((bits >> n) as u8);
+        }
+    }
+    Some(bytes)
+}
@@ -0,0 +1,198 @@
+//! Registry of the GL objects in use, for freeing them together and seeing
+//! where GPU memory goes
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    VertexArray,
+    Buffer,
+    Texture,
+    Framebuffer,
+    Renderbuffer,
+}
+
+/// Objects made between `begin_scope` and `end_scope`, deleted together
+/// with `release`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scope(u32);
+
+/// Objects made outside of any scope, only deleted one by one
+pub const PERMANENT: Scope = Scope(0);
+
+struct Entry {
+    bytes: u64,
+    label: &'static str,
+    scope: Scope,
+    generation: u64, // Tells apart objects the GL gave the same name
+}
+
+#[derive(Default)]
+struct Registry {
+    live: HashMap<(Kind, u32), Entry>,
+    scopes: Vec<Scope>, // Nested, the current one last
+    next_scope: u32,
+    next_generation: u64,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+unsafe fn gl_delete(kind: Kind, id: u32) {
+    match kind {
+        Kind::VertexArray => gl::DeleteVertexArrays(1, &id),
+        Kind::Buffer => gl::DeleteBuffers(1, &id),
+        Kind::Texture => gl::DeleteTextures(1, &id),
+        Kind::Framebuffer => gl::DeleteFramebuffers(1, &id),
+        Kind::Renderbuffer => gl::DeleteRenderbuffers(1, &id),
+    }
+}
+
+/// Start tracking an object, in the current scope. Name 0 is ignored.
+pub fn track(kind: Kind, id: u32, bytes: u64, label: &'static str) {
+    if id == 0 {
+        return;
+    }
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let scope = registry.scopes.last().copied().unwrap_or(PERMANENT);
+        registry.next_generation += 1;
+        let generation = registry.next_generation;
+        registry.live.insert((kind, id), Entry { bytes, label, scope, generation });
+    });
+}
+
+/// Generation of the live object named `id`, different for every object
+/// tracked, so work queued for one isn't done on a later one given the same
+/// name. None if it isn't tracked.
+pub fn generation(kind: Kind, id: u32) -> Option<u64> {
+    REGISTRY.with(|registry| registry.borrow().live.get(&(kind, id)).map(|entry| entry.generation))
+}
+
+/// Set the size of an object after reallocating its storage
+pub fn resize(kind: Kind, id: u32, bytes: u64) {
+    REGISTRY.with(|registry| {
+        if let Some(entry) = registry.borrow_mut().live.get_mut(&(kind, id)) {
+            entry.bytes = bytes;
+        }
+    });
+}
+
+/// Delete an object and stop tracking it. Untracked objects are deleted all
+/// the same, name 0 is ignored.
+pub unsafe fn delete(kind: Kind, id: u32) {
+    if id == 0 {
+        return;
+    }
+    REGISTRY.with(|registry| registry.borrow_mut().live.remove(&(kind, id)));
+    gl_delete(kind, id);
+}
+
+/// Track objects made from now on in a new scope, until `end_scope`
+pub fn begin_scope() -> Scope {
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        registry.next_scope += 1;
+        let scope = Scope(registry.next_scope);
+        registry.scopes.push(scope);
+        scope
+    })
+}
+
+/// Go back to the scope current before the last `begin_scope`
+pub fn end_scope() {
+    REGISTRY.with(|registry| registry.borrow_mut().scopes.pop());
+}
+
+/// Delete every object still alive in `scope`. Returns how many there were.
+pub unsafe fn release(scope: Scope) -> usize {
+    let doomed: Vec<(Kind, u32)> = REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let keys: Vec<(Kind, u32)> = registry
+            .live
+            .iter()
+            .filter(|(_, entry)| entry.scope == scope)
+            .map(|(&key, _)| key)
+            .collect();
+        keys.iter().for_each(|key| {
+            registry.live.remove(key);
+        });
+        keys
+    });
+    for &(kind, id) in &doomed {
+        gl_delete(kind, id);
+    }
+    doomed.len()
+}
+
+/// Number of live objects and their bytes, by kind
+pub fn usage() -> BTreeMap<Kind, (usize, u64)> {
+    REGISTRY.with(|registry| {
+        let mut usage = BTreeMap::new();
+        for (&(kind, _), entry) in &registry.borrow().live {
+            let (count, bytes) = usage.entry(kind).or_insert((0, 0));
+            *count += 1;
+            *bytes += entry.bytes;
+        }
+        usage
+    })
+}
+
+/// Live objects and their memory by kind and label, largest first
+pub fn dump() -> String {
+    let mut by_label: BTreeMap<(Kind, &str), (usize, u64)> = BTreeMap::new();
+    REGISTRY.with(|registry| {
+        for (&(kind, _), entry) in &registry.borrow().live {
+            let (count, bytes) = by_label.entry((kind, entry.label)).or_insert((0, 0));
+            *count += 1;
+            *bytes += entry.bytes;
+        }
+    });
+    let mut rows: Vec<_> = by_label.into_iter().collect();
+    rows.sort_by_key(|&(_, (_, bytes))| std::cmp::Reverse(bytes));
+
+    let mut out = String::from("Live GPU resources:\n");
+    for (kind, (count, bytes)) in usage() {
+        let _ = writeln!(out, "  {:<12?} {:>6} {:>10.2} MiB", kind, count, mib(bytes));
+    }
+    for ((kind, label), (count, bytes)) in rows {
+        let _ = writeln!(out, "    {:<16} {:<12?} {:>6} {:>10.2} MiB", label, kind, count, mib(bytes));
+    }
+    out
+}
+
+pub fn mib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Owns a tracked GL object, deleting it when dropped
+#[derive(Debug)]
+pub struct GpuHandle {
+    kind: Kind,
+    id: u32,
+}
+
+impl GpuHandle {
+    pub fn new(kind: Kind, id: u32, bytes: u64, label: &'static str) -> Self {
+        track(kind, id, bytes, label);
+        GpuHandle { kind, id }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Set the size after reallocating storage
+    pub fn resize(&self, bytes: u64) {
+        resize(self.kind, self.id, bytes);
+    }
+}
+
+impl Drop for GpuHandle {
+    fn drop(&mut self) {
+        unsafe { delete(self.kind, self.id) };
+    }
+}
@@ -0,0 +1,182 @@
+//! Image-based lighting from the sky
+
+use nalgebra_glm as glm;
+
+use crate::globals::{ENVIRONMENT_LEVELS, ENVIRONMENT_SAMPLES, ENVIRONMENT_SIZE, IRRADIANCE_SIZE};
+use crate::gpu_resources::{self, Kind};
+use crate::reflections::FACES;
+use crate::scene_graph::Node;
+use crate::shader::{self, Shader};
+use crate::util;
+
+/// Texture unit the irradiance cubemap is bound to
+pub const IRRADIANCE_UNIT: u32 = 5;
+/// Texture unit the prefiltered specular cubemap is bound to
+pub const SPECULAR_UNIT: u32 = 6;
+
+pub struct EnvironmentLight {
+    irradiance: u32,
+    specular: u32,
+    pub nebula: bool, // Whether the sky had the nebula when baked
+}
+
+/// Cubemap of half floats with `levels` mip levels from `size` texels across
+unsafe fn new_cubemap(size: u32, levels: u32, label: &'static str) -> u32 {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+    for level in 0..levels {
+        let texels = (size >> level).max(1) as i32;
+        for face in 0..FACES.len() as u32 {
+            gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                level as i32,
+                gl::RGBA16F as i32,
+                texels,
+                texels,
+                0,
+                gl::RGBA,
+                gl::HALF_FLOAT,
+                std::ptr::null(),
+            );
+        }
+    }
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAX_LEVEL, levels as i32 - 1);
+    let min_filter = if levels > 1 { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as i32);
+    }
+    let texels: u64 = (0..levels).map(|level| ((size >> level).max(1) as u64).pow(2)).sum();
+    gpu_resources::track(Kind::Texture, texture, texels * 8 * 6, label);
+    texture
+}
+
+impl EnvironmentLight {
+    /// Capture the sky drawn by `skybox` with `sh`, and prefilter it. Leaves
+    /// `u_view`, `u_perspective` and `u_player_position` to be set again for
+    /// drawing, and no shader active.
+    pub unsafe fn bake(sh: &Shader, skybox: &mut Node, nebula: bool) -> Self {
+        let timer = std::time::SystemTime::now();
+        eprint!("Prefiltering sky . . . ");
+
+        // Keeping the framebuffer and viewport of the caller
+        let (mut target, mut viewport) = (0, [0; 4]);
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+
+        // The sky without the planets, which move and are lit on their own
+        let sky_levels = ENVIRONMENT_SIZE.ilog2() + 1;
+        let sky = new_cubemap(ENVIRONMENT_SIZE, sky_levels, "environment sky");
+        sh.activate();
+        let u_planets_len = sh.get_uniform_location("u_planets_len");
+        let mut planets_len = 0;
+        gl::GetUniformuiv(sh.program_id, u_planets_len, &mut planets_len);
+        gl::Uniform1ui(u_planets_len, 0);
+        let perspective = util::perspective_infinite(1.0, std::f32::consts::FRAC_PI_2, 0.1);
+        gl::UniformMatrix4fv(sh.get_uniform_location("u_perspective"), 1, gl::FALSE, perspective.as_ptr());
+        gl::Uniform3fv(sh.get_uniform_location("u_player_position"), 1, glm::Vec3::zeros().as_ptr());
+        gl::Uniform1f(sh.get_uniform_location("u_log_depth"), 0.0);
+        skybox.set_position(glm::zero());
+        skybox.update_node_transformations(&glm::identity(), &glm::zero());
+        gl::Viewport(0, 0, ENVIRONMENT_SIZE as i32, ENVIRONMENT_SIZE as i32);
+        for (face, (direction, up)) in FACES.iter().enumerate() {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                sky,
+                0,
+            );
+            let look = glm::look_at(&glm::zero(), &glm::Vec3::from(*direction), &glm::Vec3::from(*up));
+            gl::UniformMatrix4fv(sh.get_uniform_location("u_view"), 1, gl::FALSE, look.as_ptr());
+            skybox.draw_scene(&(perspective * look), sh, (0.1, 10.0));
+        }
+        gl::Uniform1ui(u_planets_len, planets_len);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, sky);
+        gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+
+        let prefilter = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/fullscreen.vert", None)
+            .attach_file("./resources/shaders/ibl.frag", None)
+            .link_or_error_shader();
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        prefilter.activate();
+        gl::Uniform1i(prefilter.get_uniform_location("u_sky"), 0);
+        gl::Uniform1f(prefilter.get_uniform_location("u_sky_size"), ENVIRONMENT_SIZE as f32);
+        gl::Uniform1ui(prefilter.get_uniform_location("u_samples"), ENVIRONMENT_SAMPLES);
+
+        let irradiance = new_cubemap(IRRADIANCE_SIZE, 1, "environment irradiance");
+        let specular = new_cubemap(ENVIRONMENT_SIZE, ENVIRONMENT_LEVELS, "environment specular");
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, sky);
+        let u_face = prefilter.get_uniform_location("u_face");
+        let u_irradiance = prefilter.get_uniform_location("u_irradiance");
+        let u_roughness = prefilter.get_uniform_location("u_roughness");
+        let draw = |texture: u32, size: u32, level: u32| {
+            gl::Viewport(0, 0, size as i32, size as i32);
+            for face in 0..FACES.len() as u32 {
+                gl::FramebufferTexture2D(
+                    gl::FRAMEBUFFER,
+                    gl::COLOR_ATTACHMENT0,
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                    texture,
+                    level as i32,
+                );
+                gl::Uniform1i(u_face, face as i32);
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+        };
+        gl::Uniform1i(u_irradiance, 1);
+        draw(irradiance, IRRADIANCE_SIZE, 0);
+        gl::Uniform1i(u_irradiance, 0);
+        for level in 0..ENVIRONMENT_LEVELS {
+            let roughness = level as f32 / (ENVIRONMENT_LEVELS - 1) as f32;
+            gl::Uniform1f(u_roughness, roughness);
+            draw(specular, (ENVIRONMENT_SIZE >> level).max(1), level);
+        }
+
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+        gl::UseProgram(0);
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+        gl::DeleteFramebuffers(1, &fbo);
+        gl::DeleteVertexArrays(1, &vao);
+        gl::DeleteProgram(prefilter.program_id);
+        gpu_resources::delete(Kind::Texture, sky);
+        eprintln!("took {:?}", timer.elapsed().unwrap());
+
+        EnvironmentLight {
+            irradiance,
+            specular,
+            nebula,
+        }
+    }
+
+    /// Bind the cubemaps for the scene shader, which is active
+    pub unsafe fn bind(&self, sh: &Shader) {
+        gl::ActiveTexture(gl::TEXTURE0 + IRRADIANCE_UNIT);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.irradiance);
+        gl::ActiveTexture(gl::TEXTURE0 + SPECULAR_UNIT);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.specular);
+        gl::ActiveTexture(gl::TEXTURE0);
+        let levels = sh.get_uniform_location("u_environment_levels");
+        gl::Uniform1f(levels, (ENVIRONMENT_LEVELS - 1) as f32);
+    }
+
+    /// Delete the cubemaps, before baking again
+    pub unsafe fn delete(self) {
+        gpu_resources::delete(Kind::Texture, self.irradiance);
+        gpu_resources::delete(Kind::Texture, self.specular);
+    }
+}
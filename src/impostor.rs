@@ -0,0 +1,332 @@
+//! Distant planets drawn as camera-facing billboards of themselves
+
+use nalgebra_glm as glm;
+
+use crate::camera::View;
+use crate::globals::{IMPOSTOR_ANGLE, IMPOSTOR_MAX_AGE, IMPOSTOR_SIZE, IMPOSTOR_UPDATES, MAX_VIEWS};
+use crate::gpu_resources::{self, Kind};
+use crate::memory_budget::{Handle, MemoryBudget, ResourceKind};
+use crate::procedural_planet::Planet;
+use crate::render_queue::RenderQueue;
+use crate::scene_graph::{Node, SceneNodeType};
+use crate::shader::{self, Shader};
+use crate::terrain_tessellation;
+
+/// Key of a planet's texture for `view` in the memory budget, counting down
+/// from the top to stay clear of the node ids used for terrain
+fn budget_key(view: usize, planet_id: usize) -> usize {
+    usize::MAX - planet_id * MAX_VIEWS - view
+}
+
+struct Impostor {
+    texture: u32,
+    direction: glm::Vec3, // From the planet to the eye, when rendered
+    up: glm::Vec3,        // Of the view it was rendered with
+    rendered: u64,        // Frame
+    // Quad this frame, relative to the origin
+    center: glm::Vec3,
+    right: glm::Vec3, // Half the width, facing the eye
+    top: glm::Vec3,   // Half the height
+    distance: f32,    // Of the center to the eye
+    visible: bool,    // Drawn instead of the planet
+}
+
+pub struct Impostors {
+    shader: Shader,
+    vao: u32, // Without buffers, corners come from the vertex id
+    fbo: u32,
+    impostors: Vec<Option<Impostor>>, // By planet id
+    view: usize,                      // Index of the view drawn with these
+    frame: u64,
+    pub count: usize, // Planets drawn as impostors this frame
+}
+
+impl Impostors {
+    /// Impostors for view `view`, below `MAX_VIEWS`, each view seeing
+    /// the planets from its own eye
+    pub unsafe fn new(view: usize) -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/impostor.vert", None)
+            .attach_file("./resources/shaders/impostor.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut fbo, mut depth_stencil) = (0, 0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenRenderbuffers(1, &mut depth_stencil);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, depth_stencil);
+        let size = IMPOSTOR_SIZE as i32;
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, size, size);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_STENCIL_ATTACHMENT,
+            gl::RENDERBUFFER,
+            depth_stencil,
+        );
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+        gpu_resources::track(Kind::VertexArray, vao, 0, "impostor");
+        gpu_resources::track(Kind::Framebuffer, fbo, 0, "impostor");
+        let texels = IMPOSTOR_SIZE as u64 * IMPOSTOR_SIZE as u64;
+        gpu_resources::track(Kind::Renderbuffer, depth_stencil, texels * 4, "impostor");
+
+        Impostors {
+            shader,
+            vao,
+            fbo,
+            impostors: Vec::new(),
+            view,
+            frame: 0,
+            count: 0,
+        }
+    }
+
+    /// Choose the planets drawn as impostors, skipping them in the scene
+    /// graph, and render the textures due. Planets without a texture yet are
+    /// drawn as usual until theirs is rendered.
+    /// * `max_pixels` - Size on screen below which planets become impostors,
+    ///   0 for none
+    /// * `pixel_scale` - Pixels per unit at unit distance,
+    ///   `height / (2 tan(fov / 2))`
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn update(
+        &mut self,
+        sh: &Shader,
+        planets: &[Planet],
+        nodes: &mut [Node],
+        view: &View,
+        origin: &glm::Vec3,
+        max_pixels: f32,
+        pixel_scale: f32,
+        budget: &mut MemoryBudget,
+    ) {
+        self.frame += 1;
+        self.count = 0;
+        self.impostors.resize_with(planets.len(), || None);
+        let mut wanted = vec![false; planets.len()];
+        let mut due = vec![];
+        for (planet, node) in planets.iter().zip(nodes.iter()) {
+            let key = budget_key(self.view, planet.planet_id);
+            let slot = &mut self.impostors[planet.planet_id];
+            if let Some(impostor) = slot.as_mut() {
+                impostor.visible = false;
+                if !budget.contains(key) {
+                    // Evicted, and the texture deleted
+                    *slot = None;
+                }
+            }
+            let offset = view.eye - planet.position;
+            let distance = glm::length(&offset);
+            let reach = planet.reach();
+            let pixels = 2.0 * reach / distance * pixel_scale;
+            if node.node_type == SceneNodeType::PlanetSkip
+                || node.occluded
+                || max_pixels <= 0.0
+                || pixels >= max_pixels
+                || distance <= 2.0 * reach
+            {
+                continue;
+            }
+            wanted[planet.planet_id] = true;
+            let direction = offset / distance;
+            let stale = slot.as_ref().is_none_or(|impostor| {
+                glm::angle(&impostor.direction, &direction) > IMPOSTOR_ANGLE
+                    || self.frame - impostor.rendered > IMPOSTOR_MAX_AGE
+            });
+            if stale {
+                let rendered = slot.as_ref().map_or(0, |impostor| impostor.rendered);
+                due.push((rendered, planet.planet_id));
+            }
+        }
+        // Oldest first, those without a texture before any
+        due.sort();
+        for &(_, planet_id) in due.iter().take(IMPOSTOR_UPDATES) {
+            self.render(sh, &planets[planet_id], &mut nodes[planet_id], view, origin, budget);
+        }
+
+        for (planet, node) in planets.iter().zip(nodes.iter_mut()) {
+            let impostor = match self.impostors[planet.planet_id].as_mut() {
+                Some(impostor) if wanted[planet.planet_id] => impostor,
+                _ => continue,
+            };
+            let offset = view.eye - planet.position;
+            let distance = glm::length(&offset);
+            let reach = planet.reach();
+            // Covering what the texture does, seen from here
+            let forward = -offset / distance;
+            let right = glm::normalize(&glm::cross(&forward, &impostor.up));
+            let half = reach * distance / (distance * distance - reach * reach).sqrt();
+            impostor.center = planet.position - origin;
+            impostor.right = right * half;
+            impostor.top = glm::cross(&right, &forward) * half;
+            impostor.distance = distance;
+            impostor.visible = true;
+            node.node_type = SceneNodeType::PlanetSkip;
+            budget.touch(budget_key(self.view, planet.planet_id));
+            self.count += 1;
+        }
+    }
+
+    /// Render the texture of `planet`, looking at it from the eye
+    unsafe fn render(
+        &mut self,
+        sh: &Shader,
+        planet: &Planet,
+        node: &mut Node,
+        view: &View,
+        origin: &glm::Vec3,
+        budget: &mut MemoryBudget,
+    ) {
+        let texture = match &self.impostors[planet.planet_id] {
+            Some(impostor) => impostor.texture,
+            None => {
+                let bytes = IMPOSTOR_SIZE as u64 * IMPOSTOR_SIZE as u64 * 4;
+                let texture = new_texture();
+                gpu_resources::track(Kind::Texture, texture, bytes, "impostor");
+                budget.register(
+                    budget_key(self.view, planet.planet_id),
+                    Handle::Texture(texture),
+                    ResourceKind::Impostor,
+                    bytes,
+                );
+                texture
+            }
+        };
+
+        // Keeping the framebuffer, viewport and clear colour of the caller
+        let (mut target, mut viewport, mut clear_color) = (0, [0; 4], [0.0; 4]);
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            texture,
+            0,
+        );
+        gl::Viewport(0, 0, IMPOSTOR_SIZE as i32, IMPOSTOR_SIZE as i32);
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+
+        // Frustum just around the planet, and its atmosphere and clouds
+        let distance = glm::distance(&view.eye, &planet.position);
+        let reach = planet.reach();
+        let half_angle = (reach / distance).asin();
+        let clipping = (distance - reach, distance + reach);
+        let perspective = glm::perspective(1.0, 2.0 * half_angle, clipping.0, clipping.1);
+        let look = glm::look_at(&(view.eye - origin), &(planet.position - origin), &view.up);
+        let perspective_view = perspective * look;
+        sh.activate();
+        gl::UniformMatrix4fv(sh.get_uniform_location("u_perspective"), 1, gl::FALSE, perspective.as_ptr());
+        gl::Uniform1f(sh.get_uniform_location("u_log_depth"), 0.0);
+
+        let mut queue = RenderQueue::new();
+        queue.origin = *origin;
+        node.node_type = SceneNodeType::Empty;
+        if terrain_tessellation::enabled() {
+            let pixel_scale = IMPOSTOR_SIZE as f32 / (2.0 * half_angle.tan());
+            terrain_tessellation::draw(
+                sh,
+                std::iter::once((planet, &***node)),
+                &perspective_view,
+                &view.eye,
+                clipping,
+                pixel_scale,
+                &mut queue,
+            );
+            sh.activate();
+        }
+        node.collect_draws(&perspective_view, sh.program_id, clipping, &mut queue);
+        queue.sort();
+        queue.submit(sh);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        gl::ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+
+        self.impostors[planet.planet_id] = Some(Impostor {
+            texture,
+            direction: glm::normalize(&(view.eye - planet.position)),
+            up: view.up,
+            rendered: self.frame,
+            center: glm::zero(),
+            right: glm::zero(),
+            top: glm::zero(),
+            distance: 0.0,
+            visible: false,
+        });
+    }
+
+    /// Draw the impostors between `range.0` and `range.1` from the eye
+    pub unsafe fn draw(&self, view: &glm::Mat4, perspective: &glm::Mat4, log_depth: f32, range: (f32, f32)) {
+        let mut drawn = self
+            .impostors
+            .iter()
+            .flatten()
+            .filter(|i| i.visible && i.distance >= range.0 && i.distance < range.1)
+            .peekable();
+        if drawn.peek().is_none() {
+            return;
+        }
+
+        self.shader.activate();
+        gl::UniformMatrix4fv(self.shader.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_perspective"),
+            1,
+            gl::FALSE,
+            perspective.as_ptr(),
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_log_depth"), log_depth);
+        gl::BindVertexArray(self.vao);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        for impostor in drawn {
+            gl::BindTexture(gl::TEXTURE_2D, impostor.texture);
+            gl::Uniform3fv(self.shader.get_uniform_location("u_center"), 1, impostor.center.as_ptr());
+            gl::Uniform3fv(self.shader.get_uniform_location("u_right"), 1, impostor.right.as_ptr());
+            gl::Uniform3fv(self.shader.get_uniform_location("u_up"), 1, impostor.top.as_ptr());
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+        }
+    }
+
+    /// Delete the textures, when the planets are replaced
+    pub unsafe fn clear(&mut self, budget: &mut MemoryBudget) {
+        for (planet_id, impostor) in self.impostors.drain(..).enumerate() {
+            let key = budget_key(self.view, planet_id);
+            if let (Some(impostor), true) = (impostor, budget.contains(key)) {
+                budget.remove(key);
+                gpu_resources::delete(Kind::Texture, impostor.texture);
+            }
+        }
+    }
+}
+
+unsafe fn new_texture() -> u32 {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        gl::RGBA8 as i32,
+        IMPOSTOR_SIZE as i32,
+        IMPOSTOR_SIZE as i32,
+        0,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        std::ptr::null(),
+    );
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::BindTexture(gl::TEXTURE_2D, 0);
+    texture
+}
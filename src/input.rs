@@ -0,0 +1,279 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+
+use glutin::event::{
+    DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+use nalgebra_glm as glm;
+
+/// Something the user can do, bound to a key or mouse button
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveForward,
+    MoveBack,
+    MoveUp, // Or jump when walking
+    MoveDown,
+    RollLeft,
+    RollRight,
+    Faster,
+    Slower,
+    CycleCamera,
+    Focus, // Fly to the selected body
+    CyclePolygonMode,
+    CycleDebugMode,
+    ToggleDebugLines,
+    ToggleHud,
+    ToggleStats,
+    ToggleMinimap,
+    ToggleOrbits,
+    TogglePanel,
+    SaveScene,
+    ReloadScene,
+    CaptureFrame,
+    PlayPause, // Pause time, and the camera path
+    Step,      // Advance paused time by a step
+    TimeFaster,
+    TimeSlower,
+    ScrubBack,
+    ScrubForward,
+    Select, // Pick the body under the cursor
+    Orbit,  // Drag to orbit the selected body
+    SelectAhead, // Pick the body under the crosshair
+    Control,     // Held to save bookmarks
+    Bookmark1,   // Fly to a bookmark, or save it with Control
+    Bookmark2,
+    Bookmark3,
+    Bookmark4,
+    Bookmark5,
+    Bookmark6,
+    Bookmark7,
+    Bookmark8,
+    Bookmark9,
+}
+
+/// Bookmark actions, in the order of their slots
+const BOOKMARKS: [Action; 9] = [
+    Action::Bookmark1,
+    Action::Bookmark2,
+    Action::Bookmark3,
+    Action::Bookmark4,
+    Action::Bookmark5,
+    Action::Bookmark6,
+    Action::Bookmark7,
+    Action::Bookmark8,
+    Action::Bookmark9,
+];
+
+impl Action {
+    fn bit(self) -> u64 {
+        1 << self as u64
+    }
+
+    /// Action bound to a key
+    pub fn from_key(key: VirtualKeyCode) -> Option<Self> {
+        use VirtualKeyCode::*;
+        Some(match key {
+            A => Action::MoveLeft,
+            D => Action::MoveRight,
+            W => Action::MoveForward,
+            S => Action::MoveBack,
+            Space => Action::MoveUp,
+            LShift => Action::MoveDown,
+            Z => Action::RollLeft,
+            C => Action::RollRight,
+            Up => Action::Faster,
+            Down => Action::Slower,
+            Tab => Action::CycleCamera,
+            F => Action::Focus,
+            M => Action::CyclePolygonMode,
+            V => Action::CycleDebugMode,
+            B => Action::ToggleDebugLines,
+            I => Action::ToggleHud,
+            F3 => Action::ToggleStats,
+            O => Action::ToggleMinimap,
+            T => Action::ToggleOrbits,
+            E => Action::SelectAhead,
+            F1 => Action::TogglePanel,
+            F5 => Action::SaveScene,
+            F9 => Action::ReloadScene,
+            F12 => Action::CaptureFrame,
+            P => Action::PlayPause,
+            N => Action::Step,
+            Period => Action::TimeFaster,
+            Comma => Action::TimeSlower,
+            LBracket => Action::ScrubBack,
+            RBracket => Action::ScrubForward,
+            LControl | RControl => Action::Control,
+            Key1 => Action::Bookmark1,
+            Key2 => Action::Bookmark2,
+            Key3 => Action::Bookmark3,
+            Key4 => Action::Bookmark4,
+            Key5 => Action::Bookmark5,
+            Key6 => Action::Bookmark6,
+            Key7 => Action::Bookmark7,
+            Key8 => Action::Bookmark8,
+            Key9 => Action::Bookmark9,
+            _ => return None,
+        })
+    }
+
+    /// Action bound to a mouse button
+    pub fn from_button(button: MouseButton) -> Option<Self> {
+        match button {
+            MouseButton::Left => Some(Action::Select),
+            MouseButton::Right => Some(Action::Orbit),
+            MouseButton::Middle => Some(Action::SelectAhead),
+            _ => None,
+        }
+    }
+}
+
+/// Add to an f32 stored as bits
+fn add_f32(value: &AtomicU32, delta: f32) {
+    let _ = value.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some((f32::from_bits(bits) + delta).to_bits())
+    });
+}
+
+/// Take an f32 stored as bits, leaving zero
+fn take_f32(value: &AtomicU32) -> f32 {
+    f32::from_bits(value.swap(0f32.to_bits(), Ordering::Relaxed))
+}
+
+/// Input written by the event loop and read by the render thread, without
+/// locking. Actions held and pressed are bit sets, mouse movement and scroll
+/// accumulate until taken with `frame`.
+#[derive(Debug, Default)]
+pub struct InputState {
+    held: AtomicU64,
+    pressed: AtomicU64, // Since the last frame, so short taps aren't missed
+    mouse_delta: [AtomicU32; 2],
+    scroll: AtomicU32,
+    cursor: [AtomicU32; 2],
+    quit: AtomicBool, // Asked to stop rendering, see `request_quit`
+}
+
+impl InputState {
+    fn set(&self, action: Action, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                // Key repeat sends presses while held, only the first counts
+                let held = self.held.fetch_or(action.bit(), Ordering::AcqRel);
+                if held & action.bit() == 0 {
+                    self.pressed.fetch_or(action.bit(), Ordering::AcqRel);
+                }
+            }
+            ElementState::Released => {
+                self.held.fetch_and(!action.bit(), Ordering::AcqRel);
+            }
+        }
+    }
+
+    /// Translate keys, mouse buttons, cursor and wheel movement
+    pub fn handle_window_event(&self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { state, virtual_keycode: Some(key), .. },
+                ..
+            } => {
+                if let Some(action) = Action::from_key(*key) {
+                    self.set(action, *state);
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                if let Some(action) = Action::from_button(*button) {
+                    self.set(action, *state);
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor[0].store((position.x as f32).to_bits(), Ordering::Relaxed);
+                self.cursor[1].store((position.y as f32).to_bits(), Ordering::Relaxed);
+            }
+            WindowEvent::MouseWheel { delta, .. } => add_f32(
+                &self.scroll,
+                match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y * 50.0,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32,
+                },
+            ),
+            // Nothing stays held once the window loses focus
+            WindowEvent::Focused(false) => self.held.store(0, Ordering::Release),
+            _ => {}
+        }
+    }
+
+    /// Accumulate raw mouse movement
+    pub fn handle_device_event(&self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            add_f32(&self.mouse_delta[0], delta.0 as f32);
+            add_f32(&self.mouse_delta[1], delta.1 as f32);
+        }
+    }
+
+    /// Ask the render thread to wrap up and end, like finishing a recording,
+    /// after which the event loop exits
+    pub fn request_quit(&self) {
+        self.quit.store(true, Ordering::Release);
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit.load(Ordering::Acquire)
+    }
+
+    /// Input since the last frame, starting over for the next
+    pub fn frame(&self) -> InputFrame {
+        InputFrame {
+            held: self.held.load(Ordering::Acquire),
+            pressed: self.pressed.swap(0, Ordering::AcqRel),
+            mouse_delta: glm::vec2(take_f32(&self.mouse_delta[0]), take_f32(&self.mouse_delta[1])),
+            scroll: take_f32(&self.scroll),
+            cursor: glm::vec2(
+                f32::from_bits(self.cursor[0].load(Ordering::Relaxed)),
+                f32::from_bits(self.cursor[1].load(Ordering::Relaxed)),
+            ),
+        }
+    }
+}
+
+/// Snapshot of the input for one frame
+#[derive(Debug, Default, Copy, Clone)]
+pub struct InputFrame {
+    held: u64,
+    pressed: u64,
+    pub mouse_delta: glm::Vec2, // Pixels
+    pub scroll: f32,            // Pixels, positive away from the user
+    pub cursor: glm::Vec2,      // Window pixels from the top left
+}
+
+impl InputFrame {
+    /// Action is held down
+    pub fn held(&self, action: Action) -> bool {
+        self.held & action.bit() != 0
+    }
+
+    /// Action was pressed since the last frame, for toggles
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed & action.bit() != 0
+    }
+
+    /// -1, 0 or 1 from a pair of opposite actions
+    pub fn axis(&self, negative: Action, positive: Action) -> f32 {
+        self.held(positive) as i32 as f32 - self.held(negative) as i32 as f32
+    }
+
+    /// Slot of the bookmark key pressed, counting from 0
+    pub fn bookmark(&self) -> Option<usize> {
+        BOOKMARKS.iter().position(|&action| self.pressed(action))
+    }
+
+    /// Drop the mouse input, when the pointer is used by the tweak panel
+    pub fn release_pointer(&mut self) {
+        let buttons = Action::Select.bit() | Action::Orbit.bit();
+        self.held &= !buttons;
+        self.pressed &= !buttons;
+        self.mouse_delta = glm::vec2(0.0, 0.0);
+        self.scroll = 0.0;
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{BinaryHeap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::profile_scope;
+
+type Job = Box<dyn FnOnce() + Send>;
+type Completion = Box<dyn FnOnce() + Send>;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low = 0,
+    Normal = 1,
+    High = 2,
+}
+
+struct QueuedJob {
+    priority: Priority,
+    sequence: u64, // Keeps jobs of equal priority in FIFO order
+    job: Job,
+}
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.sequence == other.sequence
+    }
+}
+impl Eq for QueuedJob {}
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Max heap: highest priority first, then lowest sequence number
+        self.priority
+            .cmp(&other.priority)
+            .then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<BinaryHeap<QueuedJob>>,
+    available: Condvar,
+    completions: Mutex<VecDeque<Completion>>,
+    sequence: AtomicU64,
+    running: AtomicUsize,
+}
+
+/// Small job system: a pool of worker threads taking jobs by priority, and a
+/// completion queue drained on the render thread, for work that needs the GL
+/// context (like uploading a generated mesh).
+pub struct JobSystem {
+    shared: Arc<Shared>,
+}
+
+static JOBS: OnceLock<JobSystem> = OnceLock::new();
+
+/// Number of workers to use, leaving a core for the render and event threads
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get().saturating_sub(2))
+        .unwrap_or(1)
+        .max(1)
+}
+
+/// Start the global job system. Does nothing if it's already running.
+pub fn init(workers: usize) {
+    JOBS.get_or_init(|| JobSystem::new(workers));
+}
+
+/// The global job system, started with default settings if not initialized
+pub fn global() -> &'static JobSystem {
+    JOBS.get_or_init(|| JobSystem::new(default_workers()))
+}
+
+/// Run `job` on a worker thread
+pub fn spawn<F: FnOnce() + Send + 'static>(priority: Priority, job: F) {
+    global().spawn(priority, job);
+}
+
+/// Run `work` on a worker thread, then `complete` with the result on the
+/// render thread when completions are drained. If `work` panics, `complete`
+/// is dropped without being run.
+pub fn spawn_with_completion<T, W, C>(priority: Priority, work: W, complete: C)
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+    C: FnOnce(T) + Send + 'static,
+{
+    global().spawn_with_completion(priority, work, complete);
+}
+
+impl JobSystem {
+    pub fn new(workers: usize) -> Self {
+        let shared = Arc::new(Shared::default());
+        for i in 0..workers {
+            let shared = Arc::clone(&shared);
+            std::thread::Builder::new()
+                .name(format!("worker-{}", i))
+                .spawn(move || Self::worker(shared))
+                .expect("Failed to spawn worker thread");
+        }
+        eprintln!("Started job system with {} workers", workers);
+        JobSystem { shared }
+    }
+
+    fn worker(shared: Arc<Shared>) {
+        loop {
+            let job = {
+                let mut queue = shared.queue.lock().unwrap();
+                loop {
+                    if let Some(job) = queue.pop() {
+                        break job;
+                    }
+                    queue = shared.available.wait(queue).unwrap();
+                }
+            };
+            shared.running.fetch_add(1, Ordering::Relaxed);
+            // Keep the worker (and the running count) alive through a
+            // panicking job, the panic message is printed by the hook
+            let result = {
+                profile_scope!("job");
+                panic::catch_unwind(AssertUnwindSafe(job.job))
+            };
+            if result.is_err() {
+                eprintln!("Job panicked, its completion is dropped");
+            }
+            shared.running.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, priority: Priority, job: F) {
+        let sequence = self.shared.sequence.fetch_add(1, Ordering::Relaxed);
+        self.shared.queue.lock().unwrap().push(QueuedJob {
+            priority,
+            sequence,
+            job: Box::new(job),
+        });
+        self.shared.available.notify_one();
+    }
+
+    pub fn spawn_with_completion<T, W, C>(&self, priority: Priority, work: W, complete: C)
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static,
+        C: FnOnce(T) + Send + 'static,
+    {
+        let shared = Arc::clone(&self.shared);
+        self.spawn(priority, move || {
+            let result = work();
+            shared
+                .completions
+                .lock()
+                .unwrap()
+                .push_back(Box::new(move || complete(result)));
+        });
+    }
+
+    /// Run completions on the calling (render) thread until the queue is
+    /// empty or `budget` is spent. Returns the number of completions run.
+    pub fn drain_completions(&self, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut count = 0;
+        while start.elapsed() < budget {
+            // Don't hold the lock while running the completion
+            let completion = self.shared.completions.lock().unwrap().pop_front();
+            match completion {
+                Some(completion) => completion(),
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Jobs waiting for or being processed by a worker
+    pub fn pending(&self) -> usize {
+        self.shared.queue.lock().unwrap().len() + self.shared.running.load(Ordering::Relaxed)
+    }
+}
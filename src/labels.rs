@@ -0,0 +1,117 @@
+//! Names drawn over planets and props, following them on screen
+
+use nalgebra_glm as glm;
+
+use crate::body_info;
+use crate::camera::View;
+use crate::globals::{LABEL_FADE_RATE, LABEL_FULL_SIZE, LABEL_MAX_SCALE, LABEL_MIN_SCALE};
+use crate::picking;
+use crate::procedural_planet::Planet;
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+use crate::text::{Align, Font, Text};
+
+/// Which kinds of nodes are labelled
+#[derive(Debug, Copy, Clone)]
+pub struct LabelKinds {
+    pub planets: bool,
+    pub props: bool,
+}
+
+struct Label {
+    text: Text,
+    alpha: f32,
+}
+
+pub struct Labels {
+    pub root: Node,
+    font: Font,
+    labels: Vec<Label>, // Planets first, then props
+}
+
+/// Whether a planet other than `except` is between `eye` and `point`
+fn hidden(planets: &[Planet], except: Option<usize>, eye: &glm::Vec3, point: &glm::Vec3) -> bool {
+    let to_point = point - eye;
+    let distance = glm::length(&to_point);
+    if distance <= 0.0 {
+        return false;
+    }
+    let direction = to_point / distance;
+    planets.iter().filter(|planet| Some(planet.planet_id) != except).any(|planet| {
+        // Nearest hit of the flattened sphere between the eye and the point
+        picking::ray_ellipsoid(eye, &direction, &planet.position, planet.radius, &planet.flattening())
+            .is_some_and(|near| near > 0.0 && near < distance)
+    })
+}
+
+impl Labels {
+    pub fn new(font: &Font) -> Self {
+        Labels {
+            root: SceneNode::new(),
+            font: *font,
+            labels: vec![],
+        }
+    }
+
+    /// Place, scale and fade the labels of `planets` and `props` as seen
+    /// from `view` through `view_projection`, in world space
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        planets: &[Planet],
+        planet_nodes: &[Node],
+        props: &[Node],
+        view: &View,
+        view_projection: &glm::Mat4,
+        kinds: LabelKinds,
+        delta_time: f32,
+    ) {
+        let count = planets.len() + props.len();
+        if self.labels.len() != count {
+            self.root.children.clear();
+            self.labels.truncate(count);
+            while self.labels.len() < count {
+                let text = Text::new(&self.font, "", glm::zero(), LABEL_MAX_SCALE).align(Align::Center);
+                self.labels.push(Label { text, alpha: 0.0 });
+            }
+            for label in &self.labels {
+                self.root.add_child(&label.text.node);
+            }
+        }
+        let fade = (-LABEL_FADE_RATE * delta_time).exp();
+
+        for (i, label) in self.labels.iter_mut().enumerate() {
+            // Name, position, radius in world space, and the planet itself
+            let (name, position, radius, planet, wanted) = if i < planets.len() {
+                let planet = &planets[i];
+                let name = body_info::name(planet_nodes, i);
+                (name, planet.position, planet.reach(), Some(i), kinds.planets)
+            } else {
+                let node = &props[i - planets.len()];
+                let m = &node.current_transformation_matrix;
+                let position = glm::vec3(m[(0, 3)], m[(1, 3)], m[(2, 3)]);
+                let size = node.scale().max() * if node.bounds > 0.0 { node.bounds } else { 1.0 };
+                let wanted = kinds.props && node.node_type != SceneNodeType::Geometry2d && !node.name.is_empty();
+                (node.name.clone(), position, size, None, wanted)
+            };
+            // Just above what it names, as seen from the eye
+            let anchor = position + view.up * radius * 1.1;
+            let distance = glm::distance(&position, &view.eye).max(radius);
+            let visible = wanted && !hidden(planets, planet, &view.eye, &anchor);
+            let target = if visible { 1.0 } else { 0.0 };
+            label.alpha = target + (label.alpha - target) * fade;
+
+            let text = &mut label.text;
+            if label.alpha < 0.01 {
+                text.node.node_type = SceneNodeType::Empty;
+                continue;
+            }
+            text.set_text(&name);
+            let apparent = radius / distance;
+            let scale = LABEL_MIN_SCALE
+                + (LABEL_MAX_SCALE - LABEL_MIN_SCALE) * (apparent / LABEL_FULL_SIZE).min(1.0);
+            text.set_scale(scale);
+            text.set_world_anchor(&anchor, view_projection);
+            text.node.tint.w = label.alpha;
+        }
+    }
+}
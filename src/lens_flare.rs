@@ -0,0 +1,249 @@
+//! Lens flare and glare around light sources
+
+use nalgebra_glm as glm;
+
+use crate::globals::{FLARE_FADE, FLARE_INTENSITY, FLARE_PROBE_SIZE};
+use crate::{shader, util};
+
+// Sprite shapes, matching flare.frag
+const GLOW: u32 = 0;
+const DISC: u32 = 1;
+const RING: u32 = 2;
+const STREAKS: u32 = 3;
+
+/// Flare sprites as (position from the light (0) through the screen center
+/// (0.5) to the opposite side (1), size, shape, opacity)
+const SPRITES: [(f32, f32, u32, f32); 8] = [
+    (0.0, 0.5, STREAKS, 0.6),
+    (0.25, 0.06, DISC, 0.25),
+    (0.4, 0.03, DISC, 0.35),
+    (0.55, 0.1, RING, 0.15),
+    (0.7, 0.05, DISC, 0.3),
+    (0.8, 0.14, DISC, 0.1),
+    (0.9, 0.2, RING, 0.1),
+    (1.1, 0.08, DISC, 0.2),
+];
+
+/// Light source flaring or casting shafts, relative to the rendering origin
+#[derive(Debug, Copy, Clone)]
+pub struct Light {
+    pub position: glm::Vec3, // In front of its surface, facing the eye
+    pub radius: f32,
+    pub color: glm::Vec3,
+}
+
+#[derive(Debug, Default)]
+struct Probe {
+    queries: Vec<u32>, // Per depth pass
+    issued: Vec<bool>,
+    reference: u32, // Without depth testing, for the pixels the probe covers
+    pending: bool,  // Queries are issued, waiting for results
+    visible: f32,   // 0 to 1, from the latest results
+    visibility: f32, // Easing towards `visible`
+}
+
+pub struct LensFlare {
+    shader: shader::Shader,
+    vao: u32,
+    lights: Vec<Light>,
+    probes: Vec<Probe>, // Per light
+    probe_size: glm::Vec2, // Normalized device coordinates
+}
+
+impl LensFlare {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/flare.vert", None)
+            .attach_file("./resources/shaders/flare.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(1, &mut vbo);
+        // Corners of a quad, as a triangle strip
+        let quad: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            util::byte_size_of_array(&quad),
+            util::pointer_to_array(&quad),
+            gl::STATIC_DRAW,
+        );
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+
+        LensFlare {
+            shader,
+            vao,
+            lights: Vec::new(),
+            probes: Vec::new(),
+            probe_size: glm::vec2(0.0, 0.0),
+        }
+    }
+
+    /// Start a frame with the lights to flare, reading back the occlusion of
+    /// the frames before
+    pub unsafe fn begin(&mut self, lights: &[Light], window_size: (u32, u32), delta_time: f32) {
+        self.lights = lights.to_vec();
+        self.probe_size = glm::vec2(
+            FLARE_PROBE_SIZE / window_size.0.max(1) as f32,
+            FLARE_PROBE_SIZE / window_size.1.max(1) as f32,
+        );
+        self.probes.resize_with(lights.len(), Default::default);
+        for probe in self.probes.iter_mut() {
+            if probe.pending && Self::available(probe.reference) {
+                let reference = Self::result(probe.reference);
+                probe.visible = if reference == 0 {
+                    0.0 // Outside the screen
+                } else {
+                    probe
+                        .queries
+                        .iter()
+                        .zip(&probe.issued)
+                        .filter(|(_, &issued)| issued)
+                        .map(|(&q, _)| Self::result(q) as f32 / reference as f32)
+                        .fold(1.0, f32::min)
+                };
+                probe.issued.iter_mut().for_each(|i| *i = false);
+                probe.pending = false;
+            }
+            probe.visibility = util::damp(probe.visibility, probe.visible, FLARE_FADE, delta_time);
+        }
+    }
+
+    unsafe fn available(query: u32) -> bool {
+        let mut available = 0;
+        gl::GetQueryObjectuiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+        available != 0
+    }
+
+    unsafe fn result(query: u32) -> u32 {
+        let mut samples = 0;
+        gl::GetQueryObjectuiv(query, gl::QUERY_RESULT, &mut samples);
+        samples
+    }
+
+    /// Count the pixels of each light not hidden by depth pass `pass`, with
+    /// the same `view` and `perspective` as the pass. Depth is clamped, so
+    /// probes beyond the pass are tested against everything in it. Leaves the
+    /// flare shader active.
+    pub unsafe fn probe(&mut self, pass: usize, view: &glm::Mat4, perspective: &glm::Mat4, log_depth: f32) {
+        if self.probes.iter().all(|p| p.pending) {
+            return;
+        }
+        self.shader.activate();
+        gl::BindVertexArray(self.vao);
+        gl::Uniform1i(self.shader.get_uniform_location("u_probe"), 1);
+        gl::Uniform2fv(self.shader.get_uniform_location("u_size"), 1, self.probe_size.as_ptr());
+        gl::UniformMatrix4fv(self.shader.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_perspective"),
+            1,
+            gl::FALSE,
+            perspective.as_ptr(),
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_log_depth"), log_depth);
+        gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+        gl::DepthMask(gl::FALSE);
+        gl::Enable(gl::DEPTH_CLAMP);
+
+        for (light, probe) in self.lights.iter().zip(self.probes.iter_mut()) {
+            if probe.pending {
+                continue;
+            }
+            if probe.queries.len() <= pass {
+                let mut queries = vec![0; pass + 1 - probe.queries.len()];
+                gl::GenQueries(queries.len() as i32, queries.as_mut_ptr());
+                probe.queries.extend(queries);
+                probe.issued.resize(pass + 1, false);
+            }
+            gl::Uniform3fv(self.shader.get_uniform_location("u_position"), 1, light.position.as_ptr());
+            gl::BeginQuery(gl::SAMPLES_PASSED, probe.queries[pass]);
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+            gl::EndQuery(gl::SAMPLES_PASSED);
+            probe.issued[pass] = true;
+        }
+
+        gl::Disable(gl::DEPTH_CLAMP);
+        gl::DepthMask(gl::TRUE);
+        gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+    }
+
+    /// Draw the flares on top of the scene, after all depth passes, with an
+    /// infinite `perspective` like the skybox. Leaves the flare shader active.
+    pub unsafe fn draw(&mut self, view: &glm::Mat4, perspective: &glm::Mat4, aspect: f32) {
+        self.shader.activate();
+        gl::BindVertexArray(self.vao);
+        gl::Disable(gl::DEPTH_TEST);
+
+        // Reference pixel counts, without depth testing
+        gl::Uniform1i(self.shader.get_uniform_location("u_probe"), 1);
+        gl::Uniform2fv(self.shader.get_uniform_location("u_size"), 1, self.probe_size.as_ptr());
+        gl::UniformMatrix4fv(self.shader.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_perspective"),
+            1,
+            gl::FALSE,
+            perspective.as_ptr(),
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_log_depth"), 0.0);
+        gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+        for (light, probe) in self.lights.iter().zip(self.probes.iter_mut()) {
+            if probe.pending || !probe.issued.iter().any(|&i| i) {
+                continue;
+            }
+            if probe.reference == 0 {
+                gl::GenQueries(1, &mut probe.reference);
+            }
+            gl::Uniform3fv(self.shader.get_uniform_location("u_position"), 1, light.position.as_ptr());
+            gl::BeginQuery(gl::SAMPLES_PASSED, probe.reference);
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+            gl::EndQuery(gl::SAMPLES_PASSED);
+            probe.pending = true;
+        }
+        gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+
+        // Sprites, brightest with the light close to the center of the screen
+        gl::Uniform1i(self.shader.get_uniform_location("u_probe"), 0);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE);
+        let view_projection = perspective * view;
+        for (light, probe) in self.lights.iter().zip(&self.probes) {
+            let clip = view_projection * glm::vec4(light.position.x, light.position.y, light.position.z, 1.0);
+            if probe.visibility <= 0.001 || clip.w <= 0.0 {
+                continue;
+            }
+            let center = clip.xy() / clip.w;
+            let fade = 1.0 - (glm::length(&center) / 1.5).min(1.0);
+            let color = light.color / light.color.max().max(f32::EPSILON);
+            // Glare grows with the light's size on screen
+            let apparent = light.radius * perspective[(1, 1)] / clip.w;
+            for &(t, size, shape, opacity) in SPRITES.iter() {
+                let size = if shape == STREAKS { size + 4.0 * apparent } else { size };
+                let alpha = opacity * probe.visibility * fade * FLARE_INTENSITY;
+                let position = center * (1.0 - 2.0 * t);
+                gl::Uniform2f(self.shader.get_uniform_location("u_center"), position.x, position.y);
+                gl::Uniform2f(self.shader.get_uniform_location("u_size"), size / aspect, size);
+                gl::Uniform1ui(self.shader.get_uniform_location("u_shape"), shape);
+                gl::Uniform4f(self.shader.get_uniform_location("u_color"), color.x, color.y, color.z, alpha);
+                gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+            }
+            // Soft glow around the light itself
+            let size = 0.25 + 6.0 * apparent;
+            gl::Uniform2f(self.shader.get_uniform_location("u_center"), center.x, center.y);
+            gl::Uniform2f(self.shader.get_uniform_location("u_size"), size / aspect, size);
+            gl::Uniform1ui(self.shader.get_uniform_location("u_shape"), GLOW);
+            gl::Uniform4f(
+                self.shader.get_uniform_location("u_color"),
+                color.x,
+                color.y,
+                color.z,
+                0.5 * probe.visibility * FLARE_INTENSITY,
+            );
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+        }
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
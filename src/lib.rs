@@ -0,0 +1,79 @@
+//! Procedural planets renderer
+//!
+//! The scene graph, mesh generation, shaders, render queue and camera are
+//! usable on their own, `gamelogic::game` runs the full demo on an OpenGL
+//! context made current on the calling thread.
+extern crate nalgebra_glm as glm;
+
+mod animation;
+mod archetype;
+mod body_info;
+mod bookmarks;
+pub mod camera;
+mod camera_path;
+pub mod capabilities;
+mod city_lights;
+pub mod cli;
+mod color;
+mod color_grading;
+mod comet;
+mod debug_draw;
+pub mod debug_panel;
+mod depth_of_field;
+mod erosion;
+pub mod error;
+mod frame_capture;
+pub mod gamelogic;
+mod gamepad;
+mod gl_state;
+mod globals;
+mod gltf;
+mod gpu_resources;
+mod ibl;
+mod impostor;
+pub mod input;
+pub mod jobs;
+mod labels;
+mod lens_flare;
+mod light_shafts;
+mod lod;
+mod memory_budget;
+pub mod mesh;
+mod mesh_optimize;
+mod minimap;
+mod motion_blur;
+mod nbody;
+mod nebula;
+mod normal_debug;
+mod occlusion;
+mod offscreen;
+mod orbit_paths;
+mod particles;
+mod picking;
+mod physics;
+mod player;
+mod primitives;
+pub mod procedural_planet;
+mod profiler;
+mod recording;
+mod reflections;
+pub mod render_queue;
+mod reticle;
+mod scene;
+mod scene_file;
+pub mod scene_graph;
+pub mod shader;
+mod ship;
+mod simulation;
+mod skinning;
+mod starfield;
+mod stats_overlay;
+mod surface_features;
+mod terrain_batch;
+mod terrain_compute;
+mod terrain_tessellation;
+mod terrain_uploads;
+mod text;
+mod texture;
+pub mod util;
+mod vegetation;
@@ -0,0 +1,158 @@
+//! Light shafts (god rays) streaming from light sources past what covers them
+
+use nalgebra_glm as glm;
+
+use crate::globals::{
+    SHAFT_DECAY, SHAFT_DENSITY, SHAFT_DOWNSCALE, SHAFT_INTENSITY, SHAFT_THRESHOLD,
+};
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::lens_flare::Light;
+use crate::shader;
+
+pub struct LightShafts {
+    shader: shader::Shader,
+    vao: GpuHandle, // Empty, vertices are made in the vertex shader
+    fbo: GpuHandle,
+    texture: GpuHandle,
+    size: (u32, u32), // Of the texture
+}
+
+impl LightShafts {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/fullscreen.vert", None)
+            .attach_file("./resources/shaders/shafts.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut fbo, mut texture) = (0, 0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        // Nothing shines in from outside the screen
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_BORDER as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_BORDER as i32);
+        gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, [0.0f32; 4].as_ptr());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+        LightShafts {
+            shader,
+            vao: GpuHandle::new(Kind::VertexArray, vao, 0, "light shafts"),
+            fbo: GpuHandle::new(Kind::Framebuffer, fbo, 0, "light shafts"),
+            texture: GpuHandle::new(Kind::Texture, texture, 0, "light shafts"),
+            size: (0, 0),
+        }
+    }
+
+    /// Reallocate the texture when the view changes size
+    unsafe fn resize(&mut self, view_size: (u32, u32)) {
+        let size = (
+            (view_size.0 / SHAFT_DOWNSCALE).max(1),
+            (view_size.1 / SHAFT_DOWNSCALE).max(1),
+        );
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        self.texture.resize(size.0 as u64 * size.1 as u64 * 4);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture.id());
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as i32,
+            size.0 as i32,
+            size.1 as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo.id());
+        gl::FramebufferTexture2D(
+            gl::DRAW_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            self.texture.id(),
+            0,
+        );
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target as u32);
+    }
+
+    /// Add the shafts of `lights` on top of the frame drawn so far, in the
+    /// framebuffer currently bound, within the `viewport` set as x, y, width
+    /// and height. Takes an infinite `perspective` like the skybox. Leaves the
+    /// shaft shader active.
+    pub unsafe fn draw(
+        &mut self,
+        lights: &[Light],
+        view: &glm::Mat4,
+        perspective: &glm::Mat4,
+        viewport: (i32, i32, u32, u32),
+    ) {
+        let (x, y, width, height) = viewport;
+        let view_projection = perspective * view;
+        let aspect = width as f32 / height as f32;
+        // Light sources in front of the camera, on screen from 0 to 1
+        let on_screen: Vec<(glm::Vec2, f32, &Light)> = lights
+            .iter()
+            .filter_map(|light| {
+                let p = &light.position;
+                let clip = view_projection * glm::vec4(p.x, p.y, p.z, 1.0);
+                if clip.w <= 0.0 {
+                    return None;
+                }
+                let position = clip.xy() / clip.w * 0.5 + glm::vec2(0.5, 0.5);
+                let radius = light.radius * perspective[(1, 1)] / clip.w * 0.5;
+                Some((position, radius, light))
+            })
+            .collect();
+        if on_screen.is_empty() {
+            return;
+        }
+
+        // Copy the frame, the mask is made from it
+        self.resize((width, height));
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target as u32);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo.id());
+        gl::BlitFramebuffer(
+            x,
+            y,
+            x + width as i32,
+            y + height as i32,
+            0,
+            0,
+            self.size.0 as i32,
+            self.size.1 as i32,
+            gl::COLOR_BUFFER_BIT,
+            gl::LINEAR,
+        );
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+
+        self.shader.activate();
+        gl::BindVertexArray(self.vao.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture.id());
+        gl::Uniform1i(self.shader.get_uniform_location("u_scene"), 0);
+        gl::Uniform1f(self.shader.get_uniform_location("u_aspect"), aspect);
+        gl::Uniform1f(self.shader.get_uniform_location("u_threshold"), SHAFT_THRESHOLD);
+        gl::Uniform1f(self.shader.get_uniform_location("u_density"), SHAFT_DENSITY);
+        gl::Uniform1f(self.shader.get_uniform_location("u_decay"), SHAFT_DECAY);
+        gl::Uniform1f(self.shader.get_uniform_location("u_intensity"), SHAFT_INTENSITY);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+        for (position, radius, light) in on_screen {
+            gl::Uniform2f(self.shader.get_uniform_location("u_light"), position.x, position.y);
+            gl::Uniform1f(self.shader.get_uniform_location("u_radius"), radius);
+            gl::Uniform3fv(self.shader.get_uniform_location("u_color"), 1, light.color.as_ptr());
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
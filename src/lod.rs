@@ -0,0 +1,48 @@
+//! Meshes of less detail for nodes far away, like props and small moons
+
+use crate::mesh::VAOobj;
+
+pub struct LodLevel {
+    pub vao: VAOobj,
+    pub index_count: i32,
+    pub distance: f32, // Drawn from here on, until the next level
+}
+
+impl LodLevel {
+    pub fn new(vao: VAOobj, distance: f32) -> Self {
+        LodLevel {
+            vao,
+            index_count: vao.n,
+            distance,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LodGroup {
+    pub levels: Vec<LodLevel>, // Beyond the mesh of the node, by increasing distance
+    pub fade: f32,             // Width of the band levels are cross-faded in, 0 to switch
+}
+
+impl LodGroup {
+    /// Level to draw at `distance`, 0 being the mesh of the node, and the next
+    /// one when fading into it. Each with the fade to draw it with, see
+    /// `DrawCommand::fade`.
+    pub fn select(&self, distance: f32) -> ((usize, f32), Option<(usize, f32)>) {
+        let level = self.levels.partition_point(|l| l.distance <= distance);
+        let Some(next) = self.levels.get(level) else {
+            return ((level, 1.0), None);
+        };
+        let start = next.distance - self.fade;
+        if self.fade <= 0.0 || distance < start {
+            return ((level, 1.0), None);
+        }
+        let shown = 1.0 - (distance - start) / self.fade;
+        ((level, shown), Some((level + 1, -shown)))
+    }
+
+    /// Level `level` as selected, `None` for the mesh of the node
+    pub fn level(&self, level: usize) -> Option<&LodLevel> {
+        level.checked_sub(1).map(|l| &self.levels[l])
+    }
+}
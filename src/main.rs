@@ -1,75 +1,113 @@
-extern crate nalgebra_glm as glm;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{mpsc, Arc, RwLock};
 use std::thread;
 #[allow(unused_imports)]
 use std::{mem, os::raw::c_void, ptr};
 
-mod gamelogic;
-mod globals;
-mod mesh;
-mod player;
-mod procedural_planet;
-mod scene;
-mod scene_graph;
-mod shader;
-mod texture;
-mod util;
-
-use glutin::event::{
-    DeviceEvent,
-    ElementState::{Pressed, Released},
-    Event, KeyboardInput,
-    VirtualKeyCode::{self, *},
-    WindowEvent,
-};
+use clap::Parser;
+use procedural_planets::error::Error;
+use procedural_planets::{capabilities, cli, debug_panel, gamelogic, input, jobs, util};
 
-use glutin::event_loop::ControlFlow;
+use glutin::event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 
-use crate::globals::{SCREEN_H, SCREEN_W};
+use glutin::event_loop::ControlFlow;
 
 fn main() {
+    //-------------------------------------------------------------------------/
+    // Read config, with overrides from the command line
+    //-------------------------------------------------------------------------/
+    let args = cli::Args::parse();
+    let mut conf = util::Config::load().unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+    args.apply(&mut conf);
+
     //-------------------------------------------------------------------------/
     // Set up the necessary objects to deal with windows and event handling
     //-------------------------------------------------------------------------/
     let el = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new()
         .with_title("Procedural planets")
-        .with_fullscreen(Some(glutin::window::Fullscreen::Borderless(None)))
+        .with_fullscreen(if conf.fullscreen {
+            Some(glutin::window::Fullscreen::Borderless(None))
+        } else {
+            None
+        })
+        .with_visible(!args.headless)
         .with_resizable(true)
-        .with_maximized(true)
-        .with_inner_size(glutin::dpi::LogicalSize::new(SCREEN_W, SCREEN_H));
-    let cb = glutin::ContextBuilder::new()
-        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (4, 6)))
-        .with_vsync(true);
-    let windowed_context = cb.build_windowed(wb, &el).unwrap();
+        .with_maximized(conf.fullscreen)
+        .with_inner_size(glutin::dpi::LogicalSize::new(conf.screen_width, conf.screen_height));
+    // Ask for OpenGL 4.6, and fall back to 3.3 core on older hardware. The
+    // capability probe picks the render pipeline for whichever we get.
+    let windowed_context = match [(4, 6), (3, 3)]
+        .iter()
+        .find_map(|&version| {
+            glutin::ContextBuilder::new()
+                .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, version))
+                .with_gl_profile(glutin::GlProfile::Core)
+                .with_stencil_buffer(8)
+                .with_srgb(conf.srgb)
+                .with_vsync(true)
+                .build_windowed(wb.clone(), &el)
+                .map_err(|e| eprintln!("No OpenGL {}.{} context: {}", version.0, version.1, e))
+                .ok()
+        }) {
+        Some(windowed_context) => windowed_context,
+        None => {
+            eprintln!("Error: {}", Error::Context("No OpenGL 3.3 or newer context".to_string()));
+            std::process::exit(1);
+        }
+    };
     // Uncomment these if you want to use the mouse for controls, but want it
     // to be confined to the screen and/or invisible.
     // windowed_context.window().set_cursor_grab(true).expect("failed to grab cursor");
     // windowed_context.window().set_cursor_visible(false);
 
-    // Set up a shared vector for keeping track of currently pressed keys
-    let arc_pressed_keys = Arc::new(Mutex::new(Vec::<VirtualKeyCode>::with_capacity(10)));
-    // Make a reference of this vector to send to the render thread
-    let pressed_keys = Arc::clone(&arc_pressed_keys);
+    // Set up shared input state, translated from window events here and read
+    // by the render thread once per frame
+    let arc_input = Arc::new(input::InputState::default());
+    // Make a reference of this state to send to the render thread
+    let input = Arc::clone(&arc_input);
+
+    // Pointer events for the tweak panel, received on the render thread
+    let (gui_sender, gui_events) = mpsc::channel();
+    let mut gui_pointer = egui::Pos2::ZERO;
 
-    // Set up shared tuple for tracking mouse movement between frames
-    let arc_mouse_delta = Arc::new(Mutex::new((0f32, 0f32)));
-    // Make a reference of this tuple to send to the render thread
-    let mouse_delta = Arc::clone(&arc_mouse_delta);
+    // Start worker threads for terrain generation and other background work
+    jobs::init(jobs::default_workers());
+
+    // A recording is finished by the render thread before exiting
+    let recording = args.record.is_some();
+
+    // Keep track of the health of the rendering thread
+    let render_thread_healthy = Arc::new(RwLock::new(true));
+    let render_thread_health = Arc::clone(&render_thread_healthy);
+    // Wakes the event loop to notice when the render thread is done
+    let event_loop_proxy = el.create_proxy();
 
     //-------------------------------------------------------------------------/
     // Spawn a separate thread for rendering, so event handling doesn't
-    // block rendering
+    // block rendering. This is the only long-lived thread outside the job
+    // system, as it owns the OpenGL context.
     //-------------------------------------------------------------------------/
-    let render_thread = thread::spawn(move || {
+    thread::Builder::new().name("render".into()).spawn(move || {
         // Acquire the OpenGL Context and load the function pointers. This has
         // to be done inside of the rendering thread, because an active OpenGL
         // context cannot safely traverse a thread boundary.
 
-        let context = unsafe {
-            let c = windowed_context.make_current().unwrap();
-            gl::load_with(|symbol| c.get_proc_address(symbol) as *const _);
-            c
+        let context = match unsafe { windowed_context.make_current() } {
+            Ok(c) => {
+                gl::load_with(|symbol| c.get_proc_address(symbol) as *const _);
+                c
+            }
+            Err((_, e)) => {
+                eprintln!("Error: {}", Error::Context(e.to_string()));
+                if let Ok(mut health) = render_thread_health.write() {
+                    *health = false;
+                }
+                let _ = event_loop_proxy.send_event(());
+                return;
+            }
         };
 
         //---------------------------------------------------------------------/
@@ -82,8 +120,10 @@ fn main() {
             gl::Disable(gl::MULTISAMPLE);
             gl::Enable(gl::BLEND); // Enable transparency
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA); //
-            gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
-            gl::DebugMessageCallback(Some(util::debug_callback), ptr::null());
+            if capabilities::probe(args.reduced).debug_output() {
+                gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+                gl::DebugMessageCallback(Some(util::debug_callback), ptr::null());
+            }
 
             // Print some diagnostics
             println!(
@@ -97,22 +137,23 @@ fn main() {
                 util::get_gl_string(gl::SHADING_LANGUAGE_VERSION)
             );
         }
-        gamelogic::game(mouse_delta, pressed_keys, context);
-    });
-
-    //-------------------------------------------------------------------------/
-    // Keep track of the health of the rendering thread
-    //-------------------------------------------------------------------------/
-    let render_thread_healthy = Arc::new(RwLock::new(true));
-    let render_thread_watchdog = Arc::clone(&render_thread_healthy);
-    thread::spawn(move || {
-        if !render_thread.join().is_ok() {
-            if let Ok(mut health) = render_thread_watchdog.write() {
-                println!("Render thread panicked!");
-                *health = false;
-            }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            gamelogic::game(conf, args, input, gui_events, context)
+        }));
+        if let Err(panic) = result {
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            eprintln!("Render thread panicked: {}", message);
         }
-    });
+        // Close the window when rendering ends, e.g. after a recording
+        if let Ok(mut health) = render_thread_health.write() {
+            *health = false;
+        }
+        let _ = event_loop_proxy.send_event(());
+    }).expect("Failed to spawn render thread");
 
     //-------------------------------------------------------------------------/
     // Start the event loop -- This is where window events get handled
@@ -120,7 +161,7 @@ fn main() {
     el.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
 
-        // Terminate program if render thread panics
+        // Terminate program if render thread panics or ends
         if let Ok(health) = render_thread_healthy.read() {
             if *health == false {
                 *control_flow = ControlFlow::Exit;
@@ -132,7 +173,11 @@ fn main() {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
-                *control_flow = ControlFlow::Exit;
+                if recording {
+                    arc_input.request_quit();
+                } else {
+                    *control_flow = ControlFlow::Exit;
+                }
             }
             Event::WindowEvent {
                 event: WindowEvent::Resized(size),
@@ -143,55 +188,35 @@ fn main() {
                 //     arc_context.window().inner_size(),
                 //     arc_context.window().outer_size());
             }
-            // Keep track of currently pressed keys to send to the rendering thread
+            // Exit on escape
             Event::WindowEvent {
                 event:
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
-                                state: key_state,
-                                virtual_keycode: Some(keycode),
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
                                 ..
                             },
                         ..
                     },
                 ..
             } => {
-                if let Ok(mut keys) = arc_pressed_keys.lock() {
-                    match key_state {
-                        Released => {
-                            if keys.contains(&keycode) {
-                                let i = keys.iter().position(|&k| k == keycode).unwrap();
-                                keys.remove(i);
-                            }
-                        }
-                        Pressed => {
-                            if !keys.contains(&keycode) {
-                                keys.push(keycode);
-                            }
-                        }
-                    }
-                }
-                // Handle escape separately
-                match keycode {
-                    Escape => {
-                        *control_flow = ControlFlow::Exit;
-                    }
-                    Q => {
-                        /////*control_flow = ControlFlow::Exit;
-                    }
-                    _ => {}
+                if recording {
+                    arc_input.request_quit();
+                } else {
+                    *control_flow = ControlFlow::Exit;
                 }
             }
-            Event::DeviceEvent {
-                event: DeviceEvent::MouseMotion { delta },
-                ..
-            } => {
-                // Accumulate mouse movement
-                if let Ok(mut position) = arc_mouse_delta.lock() {
-                    *position = (position.0 + delta.0 as f32, position.1 + delta.1 as f32);
+            // Translate input for the rendering thread, and forward cursor
+            // and mouse buttons to the tweak panel
+            Event::WindowEvent { event, .. } => {
+                arc_input.handle_window_event(&event);
+                if let Some(gui_event) = debug_panel::translate_event(&event, &mut gui_pointer) {
+                    // Only fails once the render thread has stopped
+                    let _ = gui_sender.send(gui_event);
                 }
             }
+            Event::DeviceEvent { event, .. } => arc_input.handle_device_event(&event),
             _ => {}
         }
     });
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::gpu_resources::{self, Kind};
+use crate::scene_graph::SceneNode;
+
+/// Categories of streamed content, each with its own budget
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ResourceKind {
+    Terrain = 0,  // Planet terrain patches
+    Impostor = 1, // Impostor render targets
+}
+
+/// What to free when a resource is evicted
+#[derive(Copy, Clone, Debug)]
+pub enum Handle {
+    Node(*mut SceneNode), // Node owning a VAO, freed with `free_buffers`
+    Texture(u32),         // GL texture name
+}
+
+#[derive(Debug)]
+struct Entry {
+    handle: Handle,
+    kind: ResourceKind,
+    bytes: u64,
+    last_used: u64, // Frame number in which the resource was last visible
+}
+
+/// Memory budget manager for streamed content
+///
+/// Resources are registered with their size when uploaded, and touched every
+/// frame they are visible. When a category is over budget, the least recently
+/// visible resources are freed until it fits again. Resources touched in the
+/// current frame are never evicted.
+pub struct MemoryBudget {
+    limits: [u64; 2], // Bytes
+    used: [u64; 2],   // Bytes
+    entries: HashMap<usize, Entry>,
+    frame: u64,
+    pub evicted: usize, // Total number of evicted resources
+}
+
+impl MemoryBudget {
+    /// Create budgets given in MiB, 0 means unlimited
+    pub fn new(terrain_mb: u64, impostor_mb: u64) -> Self {
+        let mb = |v: u64| if v == 0 { u64::MAX } else { v * 1024 * 1024 };
+        MemoryBudget {
+            limits: [mb(terrain_mb), mb(impostor_mb)],
+            used: [0; 2],
+            entries: HashMap::new(),
+            frame: 0,
+            evicted: 0,
+        }
+    }
+
+    pub fn begin_frame(&mut self, frame: u64) {
+        self.frame = frame;
+    }
+
+    /// Start tracking a resource. `key` must be unique, e.g. a node id.
+    pub fn register(&mut self, key: usize, handle: Handle, kind: ResourceKind, bytes: u64) {
+        if let Some(old) = self.entries.insert(
+            key,
+            Entry {
+                handle,
+                kind,
+                bytes,
+                last_used: self.frame,
+            },
+        ) {
+            self.used[old.kind as usize] -= old.bytes;
+        }
+        self.used[kind as usize] += bytes;
+    }
+
+    /// Mark resource as visible this frame
+    pub fn touch(&mut self, key: usize) {
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.last_used = self.frame;
+        }
+    }
+
+    /// Whether a resource is tracked, and so not evicted
+    pub fn contains(&self, key: usize) -> bool {
+        self.entries.contains_key(&key)
+    }
+
+    /// Stop tracking a resource that was freed elsewhere
+    pub fn remove(&mut self, key: usize) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.used[entry.kind as usize] -= entry.bytes;
+        }
+    }
+
+    /// Stop tracking everything, when the resources were freed together
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = [0; 2];
+    }
+
+    /// Bytes in use for a category
+    pub fn used(&self, kind: ResourceKind) -> u64 {
+        self.used[kind as usize]
+    }
+
+    /// Evict least recently visible resources from categories over budget
+    pub unsafe fn enforce(&mut self) {
+        for kind in [ResourceKind::Terrain, ResourceKind::Impostor] {
+            let k = kind as usize;
+            if self.used[k] <= self.limits[k] {
+                continue;
+            }
+            let mut candidates: Vec<(u64, usize)> = self
+                .entries
+                .iter()
+                .filter(|(_, e)| e.kind == kind && e.last_used < self.frame)
+                .map(|(&key, e)| (e.last_used, key))
+                .collect();
+            candidates.sort();
+            for (_, key) in candidates {
+                if self.used[k] <= self.limits[k] {
+                    break;
+                }
+                let entry = self.entries.remove(&key).unwrap();
+                match entry.handle {
+                    Handle::Node(node) => (*node).free_buffers(),
+                    Handle::Texture(id) => gpu_resources::delete(Kind::Texture, id),
+                }
+                self.used[k] -= entry.bytes;
+                self.evicted += 1;
+            }
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use crate::globals::FRACTAL_ITERATIONS;
+use crate::globals::{FRACTAL_ITERATIONS, SMOOTHING_ANGLE};
+use crate::gpu_resources::{self, Kind};
 use crate::util;
+use std::borrow::Cow;
 use tobj;
 
 // internal helper
-fn generate_color_vec(color: glm::TVec4<f32>, num: usize) -> Vec<f32> {
+pub(crate) fn generate_color_vec(color: glm::TVec4<f32>, num: usize) -> Vec<f32> {
     glm::value_ptr(&color)
         .iter()
         .cloned()
@@ -27,7 +29,175 @@ pub struct VAOobj {
     pub cbo: u32,   // Color Buffer Object
     pub nbo: u32,   // Normal Buffer Object
     pub texbo: u32, // Texture Buffer Object
+    pub tbo: u32,   // Tangent Buffer Object
+    pub jbo: u32,   // Joint Buffer Object
+    pub wbo: u32,   // Weight Buffer Object
     pub n: i32,     // Index Count
+    pub vertices: usize, // Vertex Count
+    pub bytes: u64, // Size of all buffers
+    pub layout: VertexLayout,
+}
+
+/// Vertex attributes uploaded by `Mesh::mkvao_with`, at the attribute
+/// indices of scene.vert. The position (0) is always included.
+///
+/// Separate buffers can be updated attribute by attribute, like text is. An
+/// interleaved layout puts all in the vertex buffer, one vertex after the
+/// other, which is faster to draw but only updated as a whole.
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct VertexLayout {
+    pub color: bool,   // 1, vec4
+    pub normal: bool,  // 2, vec3
+    pub uv: bool,      // 3, vec2
+    pub tangent: bool, // 4, vec4
+    pub skin: bool,    // 5 and 6, joint indices and weights as vec4
+    pub interleaved: bool,
+}
+
+impl VertexLayout {
+    /// Everything the scene shader lights with, without normal mapping
+    pub const LIT: Self = VertexLayout {
+        color: true,
+        normal: true,
+        uv: true,
+        tangent: false,
+        skin: false,
+        interleaved: false,
+    };
+    /// Lit and normal mapped
+    pub const NORMAL_MAPPED: Self = VertexLayout {
+        tangent: true,
+        ..Self::LIT
+    };
+    /// Normal mapped and posed by joints, see skinning.rs
+    pub const SKINNED: Self = VertexLayout {
+        skin: true,
+        ..Self::NORMAL_MAPPED
+    };
+
+    /// Attributes `mesh` has data for, in separate buffers
+    pub fn of(mesh: &Mesh) -> Self {
+        let n = mesh.vertices.len() / 3;
+        VertexLayout {
+            color: n > 0 && mesh.colors.len() == n * 4,
+            normal: n > 0 && mesh.normals.len() == n * 3,
+            uv: n > 0 && mesh.texture_coordinates.len() == n * 2,
+            tangent: n > 0 && mesh.tangents.len() == n * 4,
+            skin: n > 0 && mesh.joints.len() == n * 4 && mesh.weights.len() == n * 4,
+            interleaved: false,
+        }
+    }
+
+    /// The same attributes in a single buffer
+    pub fn interleaved(self) -> Self {
+        VertexLayout {
+            interleaved: true,
+            ..self
+        }
+    }
+
+    /// Attribute indices included and their components, in buffer order
+    fn attributes(&self) -> impl Iterator<Item = (u32, usize)> {
+        let enabled = [true, self.color, self.normal, self.uv, self.tangent, self.skin, self.skin];
+        [3, 4, 3, 2, 4, 4, 4]
+            .into_iter()
+            .enumerate()
+            .filter(move |&(i, _)| enabled[i])
+            .map(|(i, components)| (i as u32, components))
+    }
+
+    /// Floats per vertex in an interleaved buffer
+    pub fn stride(&self) -> usize {
+        self.attributes().map(|(_, components)| components).sum()
+    }
+
+    /// Floats before attribute `index` in each vertex of an interleaved buffer
+    pub fn offset(&self, index: u32) -> usize {
+        self.attributes()
+            .take_while(|&(i, _)| i != index)
+            .map(|(_, components)| components)
+            .sum()
+    }
+
+    /// Enable the attributes of the bound vertex array, reading them from
+    /// the interleaved vertex buffer bound to `ARRAY_BUFFER`
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn point_interleaved(&self) {
+        let stride = (self.stride() * std::mem::size_of::<f32>()) as i32;
+        for (index, components) in self.attributes() {
+            gl::EnableVertexAttribArray(index);
+            gl::VertexAttribPointer(
+                index,
+                components as i32,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                util::offset::<f32>(self.offset(index) as u32),
+            );
+        }
+    }
+}
+
+/// Replace the contents of a buffer, bound to `target`. The old storage is
+/// orphaned, so draws still reading it don't make the update wait.
+unsafe fn orphan<T>(target: u32, buffer: u32, data: &[T]) {
+    gl::BindBuffer(target, buffer);
+    let size = util::byte_size_of_array(data);
+    gl::BufferData(target, size, std::ptr::null(), gl::DYNAMIC_DRAW);
+    if size > 0 {
+        gl::BufferSubData(target, 0, size, data.as_ptr() as *const _);
+    }
+}
+
+impl VAOobj {
+    /// Replace the mesh with one of any size, with the same layout, for
+    /// meshes changing often. Buffers are orphaned and refilled as
+    /// `DYNAMIC_DRAW`, which doesn't stall on draws of the old mesh.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn update_dynamic(&mut self, mesh: &Mesh) {
+        let vertex_count = mesh.vertices.len() / 3;
+        let attributes = mesh.attribute_data(&self.layout);
+        gl::BindVertexArray(self.vao);
+        orphan(gl::ELEMENT_ARRAY_BUFFER, self.ibo, &mesh.indices);
+        gpu_resources::resize(Kind::Buffer, self.ibo, util::byte_size_of_array(&mesh.indices) as u64);
+        let mut bytes = util::byte_size_of_array(&mesh.indices);
+        if self.layout.interleaved {
+            let (data, _) = Mesh::interleave(&attributes, vertex_count);
+            orphan(gl::ARRAY_BUFFER, self.vbo, &data);
+            gpu_resources::resize(Kind::Buffer, self.vbo, util::byte_size_of_array(&data) as u64);
+            bytes += util::byte_size_of_array(&data);
+        } else {
+            for (index, _, values) in &attributes {
+                let buffer = [self.vbo, self.cbo, self.nbo, self.texbo, self.tbo, self.jbo, self.wbo][*index as usize];
+                orphan(gl::ARRAY_BUFFER, buffer, values);
+                gpu_resources::resize(Kind::Buffer, buffer, util::byte_size_of_array(values) as u64);
+                bytes += util::byte_size_of_array(values);
+            }
+        }
+        self.n = mesh.index_count;
+        self.vertices = vertex_count;
+        util::MEMORY_USAGE.fetch_sub(self.bytes, std::sync::atomic::Ordering::Relaxed);
+        self.bytes = bytes as u64;
+        util::MEMORY_USAGE.fetch_add(self.bytes, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Delete the VAO and all its buffers
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn delete(&mut self) {
+        crate::terrain_batch::forget(self.vbo);
+        for buffer in [self.vbo, self.ibo, self.cbo, self.nbo, self.texbo, self.tbo, self.jbo, self.wbo] {
+            gpu_resources::delete(Kind::Buffer, buffer);
+        }
+        gpu_resources::delete(Kind::VertexArray, self.vao);
+        util::MEMORY_USAGE.fetch_sub(self.bytes, std::sync::atomic::Ordering::Relaxed);
+        *self = Default::default();
+    }
 }
 
 //-----------------------------------------------------------------------------/
@@ -38,7 +208,10 @@ pub struct Mesh {
     pub vertices: Vec<f32>,
     pub normals: Vec<f32>,
     pub texture_coordinates: Vec<f32>,
+    pub tangents: Vec<f32>, // Along u, with the sign of the bitangent in w
     pub colors: Vec<f32>,
+    pub joints: Vec<f32>,  // Indices of the four joints moving each vertex, see skinning.rs
+    pub weights: Vec<f32>, // How much each of those joints moves it, summing to one
     pub indices: Vec<u32>,
     pub index_count: i32,
 }
@@ -48,7 +221,8 @@ impl Mesh {
     pub fn from(mesh: tobj::Mesh, color: glm::TVec4<f32>) -> Self {
         let num_verts = mesh.positions.len() / 3;
         let index_count = mesh.indices.len() as i32;
-        Mesh {
+        let has_normals = !mesh.normals.is_empty();
+        let mut m = Mesh {
             vertices: mesh.positions,
             normals: mesh.normals,
             texture_coordinates: if mesh.texcoords.len() > 0 {
@@ -57,113 +231,305 @@ impl Mesh {
                 vec![0.0; num_verts * 2]
             },
             indices: mesh.indices,
+            tangents: Vec::new(),
             colors: generate_color_vec(color, num_verts),
             index_count,
-        }
-    }
-
-    /// Extended mkvao_simple_color to associate colors to vertices
-    pub unsafe fn mkvao(&self) -> VAOobj {
-        let mut id = VAOobj {
-            n: self.index_count,
             ..Default::default()
         };
+        if !has_normals {
+            m.recompute_normals(Some(SMOOTHING_ANGLE));
+        }
+        m.compute_tangents();
+        m
+    }
 
-        /* Create and bind vertex array */
-        gl::GenVertexArrays(1, &mut id.vao);
-        gl::BindVertexArray(id.vao);
-
-        /* Create and bind index buffer, add data */
-        //let mut ibo = 0;
-        gl::GenBuffers(1, &mut id.ibo);
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, id.ibo);
-
-        let ibuf_size = util::byte_size_of_array(&self.indices);
-        let ibuf_data = util::pointer_to_array(&self.indices);
-
-        gl::BufferData(
-            gl::ELEMENT_ARRAY_BUFFER,
-            ibuf_size,
-            ibuf_data as *const _,
-            gl::STATIC_DRAW,
-        );
+    /// Size in bytes of all the mesh's data, as uploaded by mkvao
+    pub fn byte_size(&self) -> u64 {
+        (util::byte_size_of_array(&self.vertices)
+            + util::byte_size_of_array(&self.normals)
+            + util::byte_size_of_array(&self.texture_coordinates)
+            + util::byte_size_of_array(&self.tangents)
+            + util::byte_size_of_array(&self.colors)
+            + util::byte_size_of_array(&self.joints)
+            + util::byte_size_of_array(&self.weights)
+            + util::byte_size_of_array(&self.indices)) as u64
+    }
 
-        // Next sections are vertex attributes
+    /// Distance of the furthest vertex from the origin
+    pub fn bounding_radius(&self) -> f32 {
+        self.vertices
+            .chunks_exact(3)
+            .map(|v| glm::length(&glm::vec3(v[0], v[1], v[2])))
+            .fold(0.0, f32::max)
+    }
 
-        /* Create and bind vertex buffer, add data */
-        gl::GenBuffers(1, &mut id.vbo);
-        gl::BindBuffer(gl::ARRAY_BUFFER, id.vbo);
+    /// Append the vertices and indices of another mesh, so both can be drawn
+    /// with a single VAO
+    pub fn merge(&mut self, other: &Mesh) {
+        let offset = (self.vertices.len() / 3) as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.normals.extend_from_slice(&other.normals);
+        self.texture_coordinates.extend_from_slice(&other.texture_coordinates);
+        self.tangents.extend_from_slice(&other.tangents);
+        self.colors.extend_from_slice(&other.colors);
+        self.joints.extend_from_slice(&other.joints);
+        self.weights.extend_from_slice(&other.weights);
+        self.indices.extend(other.indices.iter().map(|i| i + offset));
+        self.index_count += other.index_count;
+    }
 
-        let vbuf_size = util::byte_size_of_array(&self.vertices);
-        let vbuf_data = util::pointer_to_array(&self.vertices);
+    /// Replace the normals with the average of the surrounding faces, weighted
+    /// by their area. With `smoothing_angle` (rad), faces meeting at a sharper
+    /// angle don't share normals, and vertices on such edges are split so each
+    /// side gets its own.
+    pub fn recompute_normals(&mut self, smoothing_angle: Option<f32>) {
+        let vertices = util::to_array_of_vec3(self.vertices.clone());
+        // Not normalized, so larger faces weigh more
+        let face_normals: Vec<glm::Vec3> = self
+            .indices
+            .chunks_exact(3)
+            .map(|t| {
+                let (a, b, c) = (vertices[t[0] as usize], vertices[t[1] as usize], vertices[t[2] as usize]);
+                glm::cross(&(b - a), &(c - a))
+            })
+            .collect();
+        let mut normals = vec![glm::vec3(0.0, 0.0, 0.0); vertices.len()];
+        for (t, n) in self.indices.chunks_exact(3).zip(&face_normals) {
+            for &i in t {
+                normals[i as usize] += n;
+            }
+        }
 
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            vbuf_size,
-            vbuf_data as *const _,
-            gl::STATIC_DRAW,
-        );
+        if let Some(angle) = smoothing_angle {
+            let cos_angle = angle.cos();
+            let mut faces_of: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+            for (f, t) in self.indices.chunks_exact(3).enumerate() {
+                for &i in t {
+                    faces_of[i as usize].push(f);
+                }
+            }
+            let unit = |n: &glm::Vec3| glm::normalize(n).map(|c| if c.is_nan() { 0.0 } else { c });
+            // Each corner is smoothed with the faces around it within the
+            // angle. Corners of a vertex ending up with different normals
+            // get a copy of the vertex each.
+            let mut splits: Vec<Vec<(glm::Vec3, u32)>> = vec![Vec::new(); vertices.len()];
+            for c in 0..self.indices.len() {
+                let (f, v) = (c / 3, self.indices[c] as usize);
+                let n_f = unit(&face_normals[f]);
+                let n = faces_of[v]
+                    .iter()
+                    .filter(|&&g| glm::dot(&n_f, &unit(&face_normals[g])) >= cos_angle)
+                    .fold(glm::vec3(0.0, 0.0, 0.0), |sum, &g| sum + face_normals[g]);
+                let index = match splits[v].iter().find(|(m, _)| glm::distance2(m, &n) < 1e-12) {
+                    Some(&(_, index)) => index,
+                    None if splits[v].is_empty() => {
+                        normals[v] = n;
+                        v as u32
+                    }
+                    None => {
+                        let index = normals.len() as u32;
+                        normals.push(n);
+                        self.vertices.extend_from_slice(&[vertices[v].x, vertices[v].y, vertices[v].z]);
+                        let uv = self.texture_coordinates[2 * v..2 * v + 2].to_vec();
+                        self.texture_coordinates.extend(uv);
+                        let color = self.colors[4 * v..4 * v + 4].to_vec();
+                        self.colors.extend(color);
+                        if !self.tangents.is_empty() {
+                            let tangent = self.tangents[4 * v..4 * v + 4].to_vec();
+                            self.tangents.extend(tangent);
+                        }
+                        if !self.joints.is_empty() {
+                            let joints = self.joints[4 * v..4 * v + 4].to_vec();
+                            self.joints.extend(joints);
+                            let weights = self.weights[4 * v..4 * v + 4].to_vec();
+                            self.weights.extend(weights);
+                        }
+                        index
+                    }
+                };
+                splits[v].push((n, index));
+                self.indices[c] = index;
+            }
+        }
 
-        let mut attrib_idx = 0;
-        /* Define attrib ptr for vertex buffer */
-        gl::EnableVertexAttribArray(attrib_idx);
-        gl::VertexAttribPointer(attrib_idx, 3, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+        self.normals = normals
+            .iter()
+            .flat_map(|n| {
+                let n = glm::normalize(n);
+                // Vertices outside any face, or of degenerate ones only
+                if n.x.is_nan() { [0.0, 1.0, 0.0] } else { [n.x, n.y, n.z] }
+            })
+            .collect();
+    }
 
-        /* Create and bind color buffer, add data */
-        gl::GenBuffers(1, &mut id.cbo);
-        gl::BindBuffer(gl::ARRAY_BUFFER, id.cbo);
+    /// Compute tangents from the texture coordinates, for normal mapping.
+    /// Call after the normals are final. Where the texture coordinates don't
+    /// give a direction, any direction perpendicular to the normal is used.
+    pub fn compute_tangents(&mut self) {
+        let vertices = util::to_array_of_vec3(self.vertices.clone());
+        let normals = util::to_array_of_vec3(self.normals.clone());
+        let uvs = util::to_array_of_vec2(self.texture_coordinates.clone());
+        // Directions of increasing u and v, summed over the faces around
+        let mut along_u = vec![glm::vec3(0.0, 0.0, 0.0); vertices.len()];
+        let mut along_v = vec![glm::vec3(0.0, 0.0, 0.0); vertices.len()];
+        for t in self.indices.chunks_exact(3) {
+            let (a, b, c) = (t[0] as usize, t[1] as usize, t[2] as usize);
+            let (e1, e2) = (vertices[b] - vertices[a], vertices[c] - vertices[a]);
+            let (d1, d2) = (uvs[b] - uvs[a], uvs[c] - uvs[a]);
+            let det = d1.x * d2.y - d2.x * d1.y;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            let u = (e1 * d2.y - e2 * d1.y) / det;
+            let v = (e2 * d1.x - e1 * d2.x) / det;
+            for i in [a, b, c] {
+                along_u[i] += u;
+                along_v[i] += v;
+            }
+        }
 
-        let cbuf_size = util::byte_size_of_array(&self.colors);
-        let cbuf_data = util::pointer_to_array(&self.colors);
+        self.tangents = Vec::with_capacity(vertices.len() * 4);
+        for i in 0..vertices.len() {
+            let n = normals[i];
+            // Gram-Schmidt, so the tangent is perpendicular to the normal
+            let mut t = along_u[i] - n * glm::dot(&n, &along_u[i]);
+            if glm::length2(&t) < 1e-12 {
+                let axis = if n.x.abs() < 0.9 { glm::vec3(1.0, 0.0, 0.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+                t = glm::cross(&axis, &n);
+            }
+            let t = glm::normalize(&t);
+            let w = if glm::dot(&glm::cross(&n, &t), &along_v[i]) < 0.0 { -1.0 } else { 1.0 };
+            self.tangents.extend_from_slice(&[t.x, t.y, t.z, w]);
+        }
+    }
 
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            cbuf_size,
-            cbuf_data as *const _,
-            gl::STATIC_DRAW,
-        );
+    /// Upload to the GPU with the attributes the mesh has data for, in
+    /// separate buffers so they can be updated one by one
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn mkvao(&self) -> VAOobj {
+        self.mkvao_with(VertexLayout::of(self))
+    }
 
-        attrib_idx += 1;
-        /* Define attrib ptr for color buffer */
-        gl::EnableVertexAttribArray(attrib_idx);
-        gl::VertexAttribPointer(attrib_idx, 4, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+    /// Data of each attribute in `layout`, as (index, components, values)
+    fn attribute_data(&self, layout: &VertexLayout) -> Vec<(u32, usize, Cow<'_, [f32]>)> {
+        let vertex_count = self.vertices.len() / 3;
+        let mut attributes: Vec<(u32, usize, Cow<[f32]>)> = Vec::new();
+        attributes.push((0, 3, self.vertices.as_slice().into()));
+        let enabled = [
+            (layout.color, 4, &self.colors, 1.0),
+            (layout.normal, 3, &self.normals, 0.0),
+            (layout.uv, 2, &self.texture_coordinates, 0.0),
+            (layout.tangent, 4, &self.tangents, 0.0),
+            (layout.skin, 4, &self.joints, 0.0),
+            (layout.skin, 4, &self.weights, 0.0),
+        ];
+        for (i, &(enabled, components, data, default)) in enabled.iter().enumerate() {
+            if !enabled {
+                continue;
+            }
+            let values = if data.len() == vertex_count * components {
+                data.as_slice().into()
+            } else {
+                vec![default; vertex_count * components].into()
+            };
+            attributes.push((i as u32 + 1, components, values));
+        }
+        attributes
+    }
 
-        /* Add normals */
-        gl::GenBuffers(1, &mut id.nbo);
-        gl::BindBuffer(gl::ARRAY_BUFFER, id.nbo);
-        let nbo_size = util::byte_size_of_array(&self.normals);
-        let nbo_data = util::pointer_to_array(&self.normals);
+    /// All attributes of each vertex after each other, and the number of
+    /// floats per vertex
+    fn interleave(attributes: &[(u32, usize, Cow<[f32]>)], vertex_count: usize) -> (Vec<f32>, usize) {
+        let stride: usize = attributes.iter().map(|(_, components, _)| components).sum();
+        let mut data = Vec::with_capacity(vertex_count * stride);
+        for v in 0..vertex_count {
+            for (_, components, values) in attributes {
+                data.extend_from_slice(&values[v * components..(v + 1) * components]);
+            }
+        }
+        (data, stride)
+    }
 
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            nbo_size,
-            nbo_data as *const _,
-            gl::STATIC_DRAW,
-        );
+    /// Upload to the GPU with the attributes of `layout`. Attributes the mesh
+    /// has no data for are filled with defaults: white, and zero otherwise.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn mkvao_with(&self, layout: VertexLayout) -> VAOobj {
+        let vertex_count = self.vertices.len() / 3;
+        let attributes = self.attribute_data(&layout);
 
-        attrib_idx += 1;
-        /* Define attrib ptr for normals buffer */
-        gl::EnableVertexAttribArray(attrib_idx);
-        gl::VertexAttribPointer(attrib_idx, 3, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+        let mut id = VAOobj {
+            n: self.index_count,
+            vertices: vertex_count,
+            layout,
+            ..Default::default()
+        };
 
-        /* Add texture coordinates */
-        gl::GenBuffers(1, &mut id.texbo);
-        gl::BindBuffer(gl::ARRAY_BUFFER, id.texbo);
-        let texbo_size = util::byte_size_of_array(&self.texture_coordinates);
-        let texbo_data = util::pointer_to_array(&self.texture_coordinates);
+        /* Create and bind vertex array */
+        gl::GenVertexArrays(1, &mut id.vao);
+        gl::BindVertexArray(id.vao);
+        gpu_resources::track(Kind::VertexArray, id.vao, 0, "mesh");
 
+        /* Create and bind index buffer, add data */
+        gl::GenBuffers(1, &mut id.ibo);
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, id.ibo);
         gl::BufferData(
-            gl::ARRAY_BUFFER,
-            texbo_size,
-            texbo_data as *const _,
+            gl::ELEMENT_ARRAY_BUFFER,
+            util::byte_size_of_array(&self.indices),
+            self.indices.as_ptr() as *const _,
             gl::STATIC_DRAW,
         );
+        let mut bytes = util::byte_size_of_array(&self.indices);
+        gpu_resources::track(Kind::Buffer, id.ibo, bytes as u64, "mesh");
+
+        if layout.interleaved {
+            /* One buffer, each vertex with all its attributes after each other */
+            let (data, _) = Self::interleave(&attributes, vertex_count);
+            gl::GenBuffers(1, &mut id.vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, id.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                util::byte_size_of_array(&data),
+                data.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            gpu_resources::track(Kind::Buffer, id.vbo, util::byte_size_of_array(&data) as u64, "mesh");
+            bytes += util::byte_size_of_array(&data);
+            layout.point_interleaved();
+        } else {
+            /* A buffer per attribute */
+            for (index, components, values) in &attributes {
+                let buffer = match index {
+                    0 => &mut id.vbo,
+                    1 => &mut id.cbo,
+                    2 => &mut id.nbo,
+                    3 => &mut id.texbo,
+                    4 => &mut id.tbo,
+                    5 => &mut id.jbo,
+                    _ => &mut id.wbo,
+                };
+                gl::GenBuffers(1, buffer);
+                gl::BindBuffer(gl::ARRAY_BUFFER, *buffer);
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    util::byte_size_of_array(values),
+                    values.as_ptr() as *const _,
+                    gl::STATIC_DRAW,
+                );
+                gpu_resources::track(Kind::Buffer, *buffer, util::byte_size_of_array(values) as u64, "mesh");
+                bytes += util::byte_size_of_array(values);
+                gl::EnableVertexAttribArray(*index);
+                gl::VertexAttribPointer(*index, *components as i32, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+            }
+        }
+        // Attributes left out are disabled, the shader reads zero. For the
+        // tangent that means no normal mapping.
 
-        attrib_idx += 1;
-        /* Define attrib ptr for normals buffer */
-        gl::EnableVertexAttribArray(attrib_idx);
-        gl::VertexAttribPointer(attrib_idx, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
-
+        id.bytes = bytes as u64;
+        util::MEMORY_USAGE.fetch_add(id.bytes, std::sync::atomic::Ordering::Relaxed);
         id
     }
 
@@ -259,25 +625,30 @@ impl Mesh {
                 glm::vec2(1.0, 1.0)
             };
 
+            // Corners of the face at uvs 0, 2, 1 and 3, in the order of the
+            // triangles above, so both triangles agree and tangents follow u
             if inverted {
-                for &i in [1, 2, 3, 1, 0, 2].iter() {
+                for &i in [0, 2, 3, 0, 3, 1].iter() {
                     texture_coordinates.push(uvs[i].component_mul(&texture_scale_factor));
                 }
             } else {
-                for &i in [3, 1, 0, 3, 0, 2].iter() {
+                for &i in [0, 3, 2, 0, 1, 3].iter() {
                     texture_coordinates.push(uvs[i].component_mul(&texture_scale_factor));
                 }
             }
         }
         let vertex_count = vertices.len();
-        Mesh {
+        let mut mesh = Mesh {
             vertices: util::from_array_of_vec3(vertices),
             indices: mindices,
             normals: util::from_array_of_vec3(mnormals),
             texture_coordinates: util::from_array_of_vec2(texture_coordinates),
             colors: generate_color_vec(color, vertex_count),
             index_count: 36,
-        }
+            ..Default::default()
+        };
+        mesh.compute_tangents();
+        mesh
     }
 
     pub fn text_buffer(text: &str, char_height_over_width: f32, total_text_width: f32) -> Self {
@@ -325,6 +696,7 @@ impl Mesh {
             colors: generate_color_vec(glm::vec4(1.0, 1.0, 1.0, 1.0), vertex_count),
             indices,
             index_count,
+            ..Default::default()
         }
     }
 
@@ -340,10 +712,6 @@ impl Mesh {
         let vertex_count = res * res;
         let index_count = 6 * (res - 1) * (res - 1);
         let step = scale / subdivisions as f32 * 2.0;
-        util::MEMORY_USAGE.fetch_add(
-            vertex_count as u64 * 4 * 8 + index_count as u64 * 4,
-            std::sync::atomic::Ordering::Relaxed,
-        );
         // let timer = std::time::SystemTime::now();
         // eprint!("Constructing CS plane with {} vertices . . . ", vertex_count);
         let mut vertices = vec![glm::vec3(0.0, 0.0, 0.0); vertex_count];
@@ -361,6 +729,9 @@ impl Mesh {
                     position.z - scale.z + step.z * z as f32,
                     //2.0 * z as f32 / subdivisions as f32 - 1.0,
                 );
+                // Position on the side of the cube, continuous over patches
+                // of the same side
+                texture[z * res + x] = glm::vec2(pos.x, pos.z) * 0.5 + glm::vec2(0.5, 0.5);
                 // Convert to side of cubesphere
                 if cubesphere {
                     pos = glm::vec3(
@@ -383,10 +754,6 @@ impl Mesh {
                 pos = glm::rotate_z_vec3(&pos, rotation.z);
                 vertices[z * res + x] = pos;
 
-                texture[z * res + x] = glm::vec2(
-                    ((pos.x + pos.z).atan() + 1.0) / 2.0,
-                    ((pos.y / pos.x).atan() + 1.0) / 2.0,
-                );
                 // Normal is just the position normalized for now
                 normals[z * res + x] = glm::normalize(&vertices[z * res + x]);
 
@@ -414,6 +781,7 @@ impl Mesh {
             ),
             indices,
             index_count: index_count as i32,
+            ..Default::default()
         }
     }
 }
@@ -0,0 +1,324 @@
+//! Post-processing of meshes before upload: merging duplicate vertices, and
+//! ordering triangles and vertices for the GPU's caches
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::globals::{FORSYTH_CACHE_SIZE, SIMULATED_CACHE_SIZE};
+use crate::mesh::Mesh;
+
+/// Vertex and cache statistics before and after `Mesh::optimize`
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Report {
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    pub acmr_before: f32, // Average cache misses per triangle, 0.5 is the best possible
+    pub acmr_after: f32,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let removed = self.vertices_before - self.vertices_after;
+        write!(
+            f,
+            "{} -> {} vertices ({:.1}% fewer), ACMR {:.2} -> {:.2}",
+            self.vertices_before,
+            self.vertices_after,
+            100.0 * removed as f32 / self.vertices_before.max(1) as f32,
+            self.acmr_before,
+            self.acmr_after,
+        )
+    }
+}
+
+/// Score of a vertex by its position in the cache and the triangles left
+/// using it, as in Forsyth's article
+fn vertex_score(cache_position: Option<usize>, live_triangles: u32) -> f32 {
+    if live_triangles == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        // The last triangle's vertices, kept low so it isn't drawn again
+        Some(p) if p < 3 => 0.75,
+        Some(p) => (1.0 - (p - 3) as f32 / (FORSYTH_CACHE_SIZE - 3) as f32).powf(1.5),
+    };
+    // Vertices with few triangles left are finished off first
+    cache_score + 2.0 * (live_triangles as f32).powf(-0.5)
+}
+
+impl Mesh {
+    fn vertex_count(&self) -> usize {
+        self.vertices.len() / 3
+    }
+
+    /// Every attribute of the mesh as (values, components), skipping those
+    /// without data for every vertex
+    fn attributes_mut(&mut self) -> Vec<(&mut Vec<f32>, usize)> {
+        let n = self.vertices.len() / 3;
+        [
+            (&mut self.vertices, 3),
+            (&mut self.normals, 3),
+            (&mut self.texture_coordinates, 2),
+            (&mut self.tangents, 4),
+            (&mut self.colors, 4),
+            (&mut self.joints, 4),
+            (&mut self.weights, 4),
+        ]
+        .into_iter()
+        .filter(|(values, components)| values.len() == n * components)
+        .collect()
+    }
+
+    /// Keep vertex `order[i]` as vertex `i`, dropping vertices not in `order`
+    fn reorder_vertices(&mut self, order: &[u32]) {
+        for (values, components) in self.attributes_mut() {
+            *values = order
+                .iter()
+                .flat_map(|&v| values[v as usize * components..(v as usize + 1) * components].to_vec())
+                .collect();
+        }
+    }
+
+    /// Merge vertices with the same position, normal, texture coordinates,
+    /// tangent, colour and joint weights. Returns the number of vertices removed.
+    pub fn deduplicate(&mut self) -> usize {
+        let count = self.vertex_count();
+        let mut unique: HashMap<Vec<u32>, u32> = HashMap::new();
+        let mut order = Vec::new();
+        let mut remap = vec![0; count];
+        {
+            let attributes = self.attributes_mut();
+            for v in 0..count {
+                // Bit patterns, with -0.0 and 0.0 made the same
+                let key: Vec<u32> = attributes
+                    .iter()
+                    .flat_map(|(values, c)| values[v * c..(v + 1) * c].iter().map(|x| (x + 0.0).to_bits()))
+                    .collect();
+                remap[v] = *unique.entry(key).or_insert_with(|| {
+                    order.push(v as u32);
+                    order.len() as u32 - 1
+                });
+            }
+        }
+        if order.len() < count {
+            self.reorder_vertices(&order);
+            self.indices.iter_mut().for_each(|i| *i = remap[*i as usize]);
+        }
+        count - order.len()
+    }
+
+    /// Average cache misses per triangle, drawing with a FIFO vertex cache of
+    /// `SIMULATED_CACHE_SIZE`
+    pub fn acmr(&self) -> f32 {
+        let mut cache = std::collections::VecDeque::with_capacity(SIMULATED_CACHE_SIZE);
+        let mut misses = 0;
+        for &i in &self.indices {
+            if !cache.contains(&i) {
+                misses += 1;
+                if cache.len() == SIMULATED_CACHE_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(i);
+            }
+        }
+        misses as f32 / (self.indices.len() / 3).max(1) as f32
+    }
+
+    /// Reorder triangles so vertices are reused while still in the vertex
+    /// cache (Forsyth)
+    pub fn optimize_vertex_cache(&mut self) {
+        let vertex_count = self.vertex_count();
+        let triangle_count = self.indices.len() / 3;
+        if triangle_count == 0 {
+            return;
+        }
+
+        // Triangles around each vertex, those drawn are moved to the end
+        let mut live = vec![0u32; vertex_count];
+        for &i in &self.indices {
+            live[i as usize] += 1;
+        }
+        let mut start = vec![0usize; vertex_count + 1];
+        for v in 0..vertex_count {
+            start[v + 1] = start[v] + live[v] as usize;
+        }
+        let mut adjacency = vec![0u32; self.indices.len()];
+        let mut filled = start.clone();
+        for (t, triangle) in self.indices.chunks_exact(3).enumerate() {
+            for &i in triangle {
+                adjacency[filled[i as usize]] = t as u32;
+                filled[i as usize] += 1;
+            }
+        }
+
+        let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+        let mut score: Vec<f32> = (0..vertex_count).map(|v| vertex_score(None, live[v])).collect();
+        let mut triangle_score: Vec<f32> = self
+            .indices
+            .chunks_exact(3)
+            .map(|t| t.iter().map(|&i| score[i as usize]).sum())
+            .collect();
+        let mut drawn = vec![false; triangle_count];
+        let mut cache: Vec<u32> = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+        let mut output = Vec::with_capacity(self.indices.len());
+        let mut best = None;
+        let mut scan = 0; // Triangles before are all drawn
+
+        for _ in 0..triangle_count {
+            // Nothing in the cache to continue from, take the best of the rest
+            let t = match best {
+                Some(t) => t,
+                None => {
+                    while drawn[scan] {
+                        scan += 1;
+                    }
+                    (scan..triangle_count)
+                        .filter(|&t| !drawn[t])
+                        .max_by(|&a, &b| triangle_score[a].total_cmp(&triangle_score[b]))
+                        .unwrap()
+                }
+            };
+            drawn[t] = true;
+            let triangle = [self.indices[3 * t], self.indices[3 * t + 1], self.indices[3 * t + 2]];
+            output.extend_from_slice(&triangle);
+
+            for &v in &triangle {
+                // Move the drawn triangle past the live ones of the vertex
+                let v = v as usize;
+                let around = &mut adjacency[start[v]..start[v] + live[v] as usize];
+                let k = around.iter().position(|&a| a as usize == t).unwrap();
+                around.swap(k, live[v] as usize - 1);
+                live[v] -= 1;
+                // To the front of the cache
+                cache.retain(|&c| c as usize != v);
+                cache.insert(0, v as u32);
+            }
+
+            // Rescore what's in the cache, and what fell out of it
+            for (p, &v) in cache.iter().enumerate() {
+                let v = v as usize;
+                cache_position[v] = if p < FORSYTH_CACHE_SIZE { Some(p) } else { None };
+                let new_score = vertex_score(cache_position[v], live[v]);
+                let delta = new_score - score[v];
+                score[v] = new_score;
+                for &a in &adjacency[start[v]..start[v] + live[v] as usize] {
+                    triangle_score[a as usize] += delta;
+                }
+            }
+            cache.truncate(FORSYTH_CACHE_SIZE);
+
+            best = cache
+                .iter()
+                .flat_map(|&v| &adjacency[start[v as usize]..start[v as usize] + live[v as usize] as usize])
+                .map(|&a| a as usize)
+                .max_by(|&a, &b| triangle_score[a].total_cmp(&triangle_score[b]));
+        }
+        self.indices = output;
+    }
+
+    /// Number vertices in the order they're first used, so they're fetched
+    /// in order. Unused vertices are dropped.
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut remap = vec![u32::MAX; self.vertex_count()];
+        let mut order = Vec::with_capacity(self.vertex_count());
+        for i in self.indices.iter_mut() {
+            if remap[*i as usize] == u32::MAX {
+                remap[*i as usize] = order.len() as u32;
+                order.push(*i);
+            }
+            *i = remap[*i as usize];
+        }
+        self.reorder_vertices(&order);
+    }
+
+    /// Deduplicate vertices, then optimise for the vertex cache and fetching
+    pub fn optimize(&mut self) -> Report {
+        let vertices_before = self.vertex_count();
+        let acmr_before = self.acmr();
+        self.deduplicate();
+        self.optimize_vertex_cache();
+        self.optimize_vertex_fetch();
+        Report {
+            vertices_before,
+            vertices_after: self.vertex_count(),
+            acmr_before,
+            acmr_after: self.acmr(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Positions of the corners of each triangle, rotated to start at the
+    /// least so the winding is kept, sorted
+    fn triangles(mesh: &Mesh) -> Vec<[[u32; 3]; 3]> {
+        let position = |i: u32| {
+            let v = &mesh.vertices[i as usize * 3..i as usize * 3 + 3];
+            [v[0].to_bits(), v[1].to_bits(), v[2].to_bits()]
+        };
+        let mut triangles: Vec<[[u32; 3]; 3]> = mesh
+            .indices
+            .chunks(3)
+            .map(|t| {
+                let mut corners = [position(t[0]), position(t[1]), position(t[2])];
+                let first = (0..3).min_by_key(|&c| corners[c]).unwrap();
+                corners.rotate_left(first);
+                corners
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    /// Grid of `n` by `n` quads, every triangle with vertices of its own
+    fn triangle_soup(n: usize) -> Mesh {
+        let mut mesh = Mesh::default();
+        for z in 0..n {
+            for x in 0..n {
+                for (dx, dz) in [(0, 0), (0, 1), (1, 1), (0, 0), (1, 1), (1, 0)] {
+                    let (px, pz) = ((x + dx) as f32, (z + dz) as f32);
+                    mesh.vertices.extend([px, 0.0, pz]);
+                    mesh.colors.extend([px / n as f32, 0.0, pz / n as f32, 1.0]);
+                    mesh.indices.push(mesh.indices.len() as u32);
+                }
+            }
+        }
+        mesh.index_count = mesh.indices.len() as i32;
+        mesh
+    }
+
+    #[test]
+    fn reordering_keeps_the_triangles() {
+        let mut mesh = Mesh::icosphere(1.0, 3, glm::vec4(1.0, 1.0, 1.0, 1.0));
+        let before = triangles(&mesh);
+        mesh.optimize_vertex_cache();
+        assert_eq!(triangles(&mesh), before);
+        mesh.optimize_vertex_fetch();
+        assert_eq!(triangles(&mesh), before);
+
+        let mut mesh = triangle_soup(8);
+        let before = triangles(&mesh);
+        let report = mesh.optimize();
+        assert_eq!(triangles(&mesh), before);
+        assert!(report.acmr_after < report.acmr_before);
+    }
+
+    #[test]
+    fn deduplication_keeps_what_indices_point_at() {
+        let mut mesh = triangle_soup(4);
+        let corner = |mesh: &Mesh, i: u32| {
+            let i = i as usize;
+            (mesh.vertices[i * 3..i * 3 + 3].to_vec(), mesh.colors[i * 4..i * 4 + 4].to_vec())
+        };
+        let before: Vec<_> = mesh.indices.iter().map(|&i| corner(&mesh, i)).collect();
+        let removed = mesh.deduplicate();
+        assert_eq!(removed, 4 * 4 * 6 - 5 * 5);
+        assert_eq!(mesh.vertices.len(), 5 * 5 * 3);
+        assert_eq!(mesh.colors.len(), 5 * 5 * 4);
+        let after: Vec<_> = mesh.indices.iter().map(|&i| corner(&mesh, i)).collect();
+        assert_eq!(after, before);
+    }
+}
@@ -0,0 +1,191 @@
+//! Overview of the solar system in a corner of the window
+
+use nalgebra_glm as glm;
+
+use crate::camera::View;
+use crate::globals::{
+    MINIMAP_DOT_PIXELS, MINIMAP_FRUSTUM, MINIMAP_MARGIN, MINIMAP_ORBIT_SEGMENTS, MINIMAP_SIZE,
+    N_LAYERS,
+};
+use crate::procedural_planet::Planet;
+use crate::{shader, util};
+
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.05, 1.0];
+const ORBIT: glm::Vec4 = glm::Vec4::new(0.3, 0.3, 0.4, 1.0);
+const FRUSTUM: glm::Vec4 = glm::Vec4::new(1.0, 1.0, 0.0, 1.0);
+const SELECTED: glm::Vec4 = glm::Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+// Interleaved position on the map (2), point size (1) and colour (4)
+const VERTEX_SIZE: usize = 7;
+
+pub struct Minimap {
+    shader: shader::Shader,
+    vao: u32,
+    vbo: u32,
+    capacity: isize, // Bytes allocated for vbo
+}
+
+/// Position of `p` on the map, seen from above with -z up
+fn on_map(p: &glm::Vec3, center: &glm::Vec3) -> glm::Vec2 {
+    glm::vec2(p.x - center.x, center.z - p.z)
+}
+
+fn vertex(vertices: &mut Vec<f32>, p: &glm::Vec2, size: f32, color: &glm::Vec4) {
+    vertices.extend_from_slice(&[p.x, p.y, size, color.x, color.y, color.z, color.w]);
+}
+
+/// Colour of a body, its light if it shines, otherwise the middle of its terrain
+pub fn body_color(planet: &Planet) -> glm::Vec4 {
+    let color = if planet.lightsource {
+        planet.emission / planet.emission.max().max(1.0)
+    } else {
+        planet.color_scheme[N_LAYERS / 2]
+    };
+    glm::vec4(color.x, color.y, color.z, 1.0)
+}
+
+impl Minimap {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/minimap.vert", None)
+            .attach_file("./resources/shaders/minimap.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+        let stride = (VERTEX_SIZE * std::mem::size_of::<f32>()) as i32;
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, util::offset::<f32>(3));
+
+        Minimap {
+            shader,
+            vao,
+            vbo,
+            capacity: 0,
+        }
+    }
+
+    /// Draw the map in the lower right corner of a window of `window_size`,
+    /// with the frustum of `view` as drawn with `aspect` and `fov`, and the
+    /// `selected` body marked. Restores the viewport to the whole window and
+    /// the clear colour, and leaves the minimap shader active.
+    pub unsafe fn draw(
+        &mut self,
+        planets: &[Planet],
+        view: &View,
+        aspect: f32,
+        fov: f32,
+        selected: Option<usize>,
+        window_size: (u32, u32),
+    ) {
+        let Some(sun) = planets.first() else {
+            return;
+        };
+        let size = MINIMAP_SIZE.min(window_size.1 / 2).min(window_size.0 / 2);
+        if size == 0 {
+            return;
+        }
+        let center = sun.position;
+        let eye = on_map(&view.eye, &center);
+        let reach = planets
+            .iter()
+            .map(|planet| glm::length(&on_map(&planet.position, &center)) + planet.radius)
+            .fold(glm::length(&eye), f32::max)
+            * 1.1;
+        let pixels_per_unit = size as f32 / (2.0 * reach);
+
+        let mut lines = vec![];
+        for planet in planets.iter().skip(1) {
+            let parent = on_map(&planets[planet.parent_id].position, &center);
+            let point = |i: usize| {
+                let a = i as f32 / MINIMAP_ORBIT_SEGMENTS as f32 * std::f32::consts::PI * 2.0;
+                parent + glm::vec2(a.cos(), a.sin()) * planet.trajectory
+            };
+            for i in 0..MINIMAP_ORBIT_SEGMENTS {
+                vertex(&mut lines, &point(i), 1.0, &ORBIT);
+                vertex(&mut lines, &point(i + 1), 1.0, &ORBIT);
+            }
+        }
+        // Edges of the frustum out to a fixed length, flattened onto the map
+        let right = glm::normalize(&glm::cross(&view.direction, &view.up));
+        let up = glm::cross(&right, &view.direction);
+        let tan_v = (fov / 2.0).tan();
+        let corners: Vec<glm::Vec2> = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|&(x, y)| {
+                let edge = view.direction + right * x * tan_v * aspect + up * y * tan_v;
+                eye + on_map(&glm::normalize(&edge), &glm::zero()) * reach * MINIMAP_FRUSTUM
+            })
+            .collect();
+        for (i, corner) in corners.iter().enumerate() {
+            vertex(&mut lines, &eye, 1.0, &FRUSTUM);
+            vertex(&mut lines, corner, 1.0, &FRUSTUM);
+            vertex(&mut lines, corner, 1.0, &FRUSTUM);
+            vertex(&mut lines, &corners[(i + 1) % corners.len()], 1.0, &FRUSTUM);
+        }
+
+        let mut points = vec![];
+        for planet in planets {
+            let p = on_map(&planet.position, &center);
+            let dot = (2.0 * planet.radius * pixels_per_unit).max(MINIMAP_DOT_PIXELS);
+            if selected == Some(planet.planet_id) {
+                // Outlined by a larger dot beneath
+                vertex(&mut points, &p, dot + 4.0, &SELECTED);
+            }
+            vertex(&mut points, &p, dot, &body_color(planet));
+        }
+
+        let mut vertices = lines;
+        let line_count = vertices.len() / VERTEX_SIZE;
+        vertices.extend(points);
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        let bytes = util::byte_size_of_array(&vertices);
+        if bytes > self.capacity {
+            // Grow, and orphan the old storage
+            self.capacity = bytes.max(2 * self.capacity);
+            gl::BufferData(gl::ARRAY_BUFFER, self.capacity, std::ptr::null(), gl::DYNAMIC_DRAW);
+        }
+        gl::BufferSubData(gl::ARRAY_BUFFER, 0, bytes, util::pointer_to_array(&vertices));
+
+        let x = window_size.0.saturating_sub(size + MINIMAP_MARGIN) as i32;
+        let y = MINIMAP_MARGIN.min(window_size.1 - size) as i32;
+        gl::Viewport(x, y, size as i32, size as i32);
+        let mut clear_color = [0.0f32; 4];
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::Scissor(x, y, size as i32, size as i32);
+        gl::ClearColor(BACKGROUND[0], BACKGROUND[1], BACKGROUND[2], BACKGROUND[3]);
+        gl::Clear(gl::COLOR_BUFFER_BIT);
+        gl::Disable(gl::SCISSOR_TEST);
+        gl::ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+
+        let projection = glm::ortho(-reach, reach, -reach, reach, -1.0, 1.0);
+        self.shader.activate();
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_projection"),
+            1,
+            gl::FALSE,
+            projection.as_ptr(),
+        );
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Uniform1i(self.shader.get_uniform_location("u_points"), 0);
+        gl::DrawArrays(gl::LINES, 0, line_count as i32);
+        gl::Enable(gl::PROGRAM_POINT_SIZE);
+        gl::Uniform1i(self.shader.get_uniform_location("u_points"), 1);
+        gl::DrawArrays(
+            gl::POINTS,
+            line_count as i32,
+            (vertices.len() / VERTEX_SIZE - line_count) as i32,
+        );
+        gl::Disable(gl::PROGRAM_POINT_SIZE);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Viewport(0, 0, window_size.0 as i32, window_size.1 as i32);
+    }
+}
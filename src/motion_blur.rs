@@ -0,0 +1,147 @@
+//! Motion blur from a velocity buffer, for fast flybys and spinning moons
+
+use crate::globals::MOTION_BLUR_MAX_LENGTH;
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::shader;
+
+pub struct MotionBlur {
+    shader: shader::Shader,
+    vao: GpuHandle, // Empty, vertices are made in the vertex shader
+    fbo: GpuHandle,
+    color: GpuHandle,
+    velocity: GpuHandle,
+    depth_stencil: GpuHandle,
+    size: (u32, u32), // Of the attachments, the window
+    target: u32,      // Framebuffer drawn to before `begin`, and by `draw`
+}
+
+unsafe fn new_texture() -> u32 {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    texture
+}
+
+impl MotionBlur {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/fullscreen.vert", None)
+            .attach_file("./resources/shaders/motion_blur.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut fbo, mut depth_stencil) = (0, 0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenRenderbuffers(1, &mut depth_stencil);
+        let color = new_texture();
+        let velocity = new_texture();
+
+        MotionBlur {
+            shader,
+            vao: GpuHandle::new(Kind::VertexArray, vao, 0, "motion blur"),
+            fbo: GpuHandle::new(Kind::Framebuffer, fbo, 0, "motion blur"),
+            color: GpuHandle::new(Kind::Texture, color, 0, "motion blur"),
+            velocity: GpuHandle::new(Kind::Texture, velocity, 0, "motion blur"),
+            depth_stencil: GpuHandle::new(Kind::Renderbuffer, depth_stencil, 0, "motion blur"),
+            size: (0, 0),
+            target: 0,
+        }
+    }
+
+    /// Reallocate the attachments when the window changes size
+    unsafe fn resize(&mut self, size: (u32, u32)) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        let (width, height) = (size.0 as i32, size.1 as i32);
+        let texels = size.0 as u64 * size.1 as u64;
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo.id());
+        let attachments = [
+            (&mut self.color, gl::RGBA16F, gl::RGBA, 8),
+            (&mut self.velocity, gl::RG16F, gl::RG, 4),
+        ];
+        for (i, (texture, internal, format, bytes)) in attachments.into_iter().enumerate() {
+            texture.resize(texels * bytes);
+            gl::BindTexture(gl::TEXTURE_2D, texture.id());
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal as i32,
+                width,
+                height,
+                0,
+                format,
+                gl::HALF_FLOAT,
+                std::ptr::null(),
+            );
+            gl::FramebufferTexture2D(
+                gl::DRAW_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0 + i as u32,
+                gl::TEXTURE_2D,
+                texture.id(),
+                0,
+            );
+        }
+        self.depth_stencil.resize(texels * 4);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_stencil.id());
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        gl::FramebufferRenderbuffer(
+            gl::DRAW_FRAMEBUFFER,
+            gl::DEPTH_STENCIL_ATTACHMENT,
+            gl::RENDERBUFFER,
+            self.depth_stencil.id(),
+        );
+    }
+
+    /// Draw into the framebuffer of the blur from now on, in a window of
+    /// `window_size`, cleared to the clear colour set and standing still
+    pub unsafe fn begin(&mut self, window_size: (u32, u32)) {
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        self.target = target as u32;
+        self.resize(window_size);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo.id());
+        gl::DrawBuffer(gl::COLOR_ATTACHMENT0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+        self.velocity(true);
+        gl::ClearBufferfv(gl::COLOR, 1, [0.0f32; 4].as_ptr());
+        self.velocity(false);
+    }
+
+    /// Whether draws write their velocity, only the scene shader does
+    pub unsafe fn velocity(&self, write: bool) {
+        let buffers = [gl::COLOR_ATTACHMENT0, if write { gl::COLOR_ATTACHMENT1 } else { gl::NONE }];
+        gl::DrawBuffers(buffers.len() as i32, buffers.as_ptr());
+    }
+
+    /// Draw the frame to the framebuffer bound before `begin`, over the whole
+    /// window, blurred for `shutter`, the share of the frame the shutter is
+    /// open. Leaves the blur shader active and the viewport covering the
+    /// window.
+    pub unsafe fn draw(&mut self, shutter: f32) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.target);
+        gl::Viewport(0, 0, self.size.0 as i32, self.size.1 as i32);
+        self.shader.activate();
+        gl::BindVertexArray(self.vao.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.color.id());
+        gl::ActiveTexture(gl::TEXTURE1);
+        gl::BindTexture(gl::TEXTURE_2D, self.velocity.id());
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Uniform1i(self.shader.get_uniform_location("u_scene"), 0);
+        gl::Uniform1i(self.shader.get_uniform_location("u_velocity"), 1);
+        gl::Uniform1f(self.shader.get_uniform_location("u_shutter"), shutter);
+        gl::Uniform1f(self.shader.get_uniform_location("u_max_length"), MOTION_BLUR_MAX_LENGTH);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
@@ -0,0 +1,223 @@
+//! Planets pulling on each other with Newtonian gravity, instead of moving
+//! along fixed orbits
+
+use nalgebra_glm as glm;
+
+use crate::globals::{NBODY_SOFTENING, NBODY_SUBSTEPS, WORLD_SPEED};
+use crate::procedural_planet::Planet;
+use crate::util::Integrator;
+
+/// Angle of `planet` on its fixed orbit at `time`, taken in double precision
+/// so orbits don't stutter after simulated years
+pub fn orbit_angle(planet: &Planet, time: f64) -> f32 {
+    let turned = (planet.traj_speed as f64 * WORLD_SPEED as f64 * time) % std::f64::consts::TAU;
+    turned as f32 + planet.traj_init_angle.x
+}
+
+/// Position of `planet` relative to its parent on its fixed orbit at `time`,
+/// in the units of its `trajectory`
+pub fn kepler_offset(planet: &Planet, time: f64) -> glm::Vec3 {
+    let angle = orbit_angle(planet, time);
+    glm::vec3(
+        angle.sin() * planet.trajectory,
+        planet.traj_init_angle.y,
+        angle.cos() * planet.trajectory,
+    )
+}
+
+#[derive(Clone)]
+pub struct NBody {
+    pub integrator: Integrator,
+    masses: Vec<f32>,
+    positions: Vec<glm::Vec3>,
+    velocities: Vec<glm::Vec3>,
+    previous: Vec<glm::Vec3>, // Positions before the last step
+}
+
+impl NBody {
+    /// Start from the orbits of `planets` at `time`, with trajectories
+    /// currently `scale` times those of the scene
+    pub fn new(planets: &[Planet], time: f64, scale: f32, integrator: Integrator) -> Self {
+        let n = planets.len();
+        let is_root = |i: usize| planets[i].parent_id == planets[i].planet_id;
+        // Circular orbits around the parent, in the units of the scene
+        let offsets: Vec<glm::Vec3> = planets.iter().map(|p| kepler_offset(p, time) / scale).collect();
+        let radii: Vec<f32> = planets.iter().map(|p| p.trajectory / scale).collect();
+
+        let masses: Vec<f32> = (0..n)
+            .map(|i| {
+                let planet = &planets[i];
+                planet.mass.unwrap_or_else(|| {
+                    // Enough to keep the nearest moon on its orbit, G = 1
+                    let moon = (0..n)
+                        .filter(|&j| !is_root(j) && planets[j].parent_id == planet.planet_id && radii[j] > 0.0)
+                        .min_by(|&a, &b| radii[a].total_cmp(&radii[b]));
+                    match moon {
+                        Some(j) => (planets[j].traj_speed * WORLD_SPEED).powi(2) * radii[j].powi(3),
+                        None => planet.gravity * (planet.radius / scale).powi(2),
+                    }
+                })
+            })
+            .collect();
+
+        // Parents first, as their motion is added to that of their moons
+        let mut positions = vec![glm::zero(); n];
+        let mut velocities = vec![glm::zero(); n];
+        let mut done = vec![false; n];
+        while done.iter().any(|&d| !d) {
+            let before = done.iter().filter(|&&d| d).count();
+            for i in 0..n {
+                let parent = planets[i].parent_id;
+                if done[i] || (!is_root(i) && !done[parent]) {
+                    continue;
+                }
+                done[i] = true;
+                if is_root(i) {
+                    positions[i] = planets[i].position / scale;
+                    continue;
+                }
+                positions[i] = positions[parent] + offsets[i];
+                let velocity = planets[i].velocity.unwrap_or_else(|| {
+                    let angle = orbit_angle(&planets[i], time);
+                    let speed = (masses[parent] / radii[i].max(NBODY_SOFTENING)).sqrt();
+                    glm::vec3(angle.cos(), 0.0, -angle.sin()) * speed * planets[i].traj_speed.signum()
+                });
+                velocities[i] = velocities[parent] + velocity;
+            }
+            // Parents orbiting each other, left at the origin
+            if done.iter().filter(|&&d| d).count() == before {
+                break;
+            }
+        }
+
+        NBody {
+            integrator,
+            masses,
+            previous: positions.clone(),
+            positions,
+            velocities,
+        }
+    }
+
+    /// Acceleration of each body at `positions`, softened so close passes
+    /// don't fling bodies away
+    fn accelerations(&self, positions: &[glm::Vec3]) -> Vec<glm::Vec3> {
+        let mut accelerations = vec![glm::zero::<glm::Vec3>(); positions.len()];
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                let d = positions[j] - positions[i];
+                let r2 = glm::length2(&d) + NBODY_SOFTENING * NBODY_SOFTENING;
+                let pull = d / (r2 * r2.sqrt());
+                accelerations[i] += pull * self.masses[j];
+                accelerations[j] -= pull * self.masses[i];
+            }
+        }
+        accelerations
+    }
+
+    /// Advance by `delta_time` simulated seconds
+    pub fn step(&mut self, delta_time: f32) {
+        self.previous.clone_from(&self.positions);
+        let dt = delta_time / NBODY_SUBSTEPS as f32;
+        for _ in 0..NBODY_SUBSTEPS {
+            match self.integrator {
+                Integrator::SemiImplicitEuler => {
+                    let a = self.accelerations(&self.positions);
+                    for ((v, x), a) in self.velocities.iter_mut().zip(&mut self.positions).zip(&a) {
+                        *v += a * dt;
+                        *x += *v * dt;
+                    }
+                }
+                Integrator::RungeKutta4 => self.step_rk4(dt),
+            }
+        }
+    }
+
+    fn step_rk4(&mut self, dt: f32) {
+        let (x, v) = (&self.positions, &self.velocities);
+        let moved = |dx: &[glm::Vec3], h: f32| -> Vec<glm::Vec3> {
+            x.iter().zip(dx).map(|(x, dx)| x + dx * h).collect()
+        };
+        let k1v = self.accelerations(x);
+        let k2x: Vec<glm::Vec3> = v.iter().zip(&k1v).map(|(v, a)| v + a * dt / 2.0).collect();
+        let k2v = self.accelerations(&moved(v, dt / 2.0));
+        let k3x: Vec<glm::Vec3> = v.iter().zip(&k2v).map(|(v, a)| v + a * dt / 2.0).collect();
+        let k3v = self.accelerations(&moved(&k2x, dt / 2.0));
+        let k4x: Vec<glm::Vec3> = v.iter().zip(&k3v).map(|(v, a)| v + a * dt).collect();
+        let k4v = self.accelerations(&moved(&k3x, dt));
+        for i in 0..self.positions.len() {
+            self.positions[i] += (self.velocities[i] + (k2x[i] + k3x[i]) * 2.0 + k4x[i]) * dt / 6.0;
+            self.velocities[i] += (k1v[i] + (k2v[i] + k3v[i]) * 2.0 + k4v[i]) * dt / 6.0;
+        }
+    }
+
+    /// Position of body `i` relative to body `parent`, `alpha` of the way
+    /// from the step before the last to the last, in the units of the scene
+    pub fn offset(&self, i: usize, parent: usize, alpha: f32) -> glm::Vec3 {
+        let at = |j: usize| glm::lerp(&self.previous[j], &self.positions[j], alpha);
+        at(i) - at(parent)
+    }
+
+    /// Velocity of body `i` relative to body `parent` after the last step
+    pub fn velocity(&self, i: usize, parent: usize) -> glm::Vec3 {
+        self.velocities[i] - self.velocities[parent]
+    }
+
+    /// Mass of body `i`, as given or as found from the orbits of its moons
+    pub fn mass(&self, i: usize) -> f32 {
+        self.masses[i]
+    }
+
+    /// Positions of body `i` relative to body `parent` over the next
+    /// `duration` simulated seconds, at `samples` evenly spaced times, from a
+    /// copy of the simulation left as it is
+    pub fn predict(&self, i: usize, parent: usize, duration: f32, samples: usize) -> Vec<glm::Vec3> {
+        let mut ahead = self.clone();
+        let mut points = vec![ahead.offset(i, parent, 1.0)];
+        for _ in 1..samples {
+            ahead.step(duration / (samples - 1) as f32);
+            points.push(ahead.offset(i, parent, 1.0));
+        }
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::SIMULATION_STEP;
+
+    // Kinetic and potential energy, with the softening of `accelerations`
+    fn energy(nbody: &NBody) -> f32 {
+        let (m, x, v) = (&nbody.masses, &nbody.positions, &nbody.velocities);
+        let kinetic: f32 = (0..m.len()).map(|i| 0.5 * m[i] * glm::length2(&v[i])).sum();
+        let r2 = glm::length2(&(x[1] - x[0])) + NBODY_SOFTENING * NBODY_SOFTENING;
+        kinetic - m[0] * m[1] / r2.sqrt()
+    }
+
+    #[test]
+    fn circular_orbit_conserves_energy() {
+        let (big, small, radius) = (1000.0, 1.0, 10.0);
+        let r2 = radius * radius + NBODY_SOFTENING * NBODY_SOFTENING;
+        let speed = (big * radius * radius / (r2 * r2.sqrt())).sqrt();
+        for integrator in [Integrator::SemiImplicitEuler, Integrator::RungeKutta4] {
+            let positions = vec![glm::zero(), glm::vec3(radius, 0.0, 0.0)];
+            let mut nbody = NBody {
+                integrator,
+                masses: vec![big, small],
+                previous: positions.clone(),
+                positions,
+                // At rest together, so the pair doesn't drift off
+                velocities: vec![glm::vec3(0.0, 0.0, -speed * small / big), glm::vec3(0.0, 0.0, speed)],
+            };
+            let before = energy(&nbody);
+            // A little over one orbit
+            for _ in 0..(std::f32::consts::TAU * radius / speed / SIMULATION_STEP) as usize + 60 {
+                nbody.step(SIMULATION_STEP);
+            }
+            let drift = ((energy(&nbody) - before) / before).abs();
+            assert!(drift < 1e-3, "{:?} energy drifted by {}", integrator, drift);
+            assert!((glm::length(&nbody.offset(1, 0, 1.0)) - radius).abs() < 0.1);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Procedural nebula baked into a cubemap, sampled behind the skybox stars
+
+use nalgebra_glm as glm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::gpu_resources::{self, Kind};
+use crate::{color, shader};
+
+/// Texture unit the cubemap is bound to, unit 0 is for node textures. Set
+/// for `u_nebula` even without a nebula, so it never shares a unit with a
+/// 2D sampler.
+pub const NEBULA_UNIT: u32 = 1;
+
+pub struct Nebula {
+    pub texture: u32,
+}
+
+/// Random colour of a given brightness
+fn random_color(rng: &mut StdRng, brightness: f32) -> glm::Vec3 {
+    let c = glm::vec3(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
+    c / c.max().max(f32::EPSILON) * brightness
+}
+
+impl Nebula {
+    /// Render a cubemap with faces `size` texels across
+    pub unsafe fn bake(seed: u64, size: u32) -> Self {
+        let timer = std::time::SystemTime::now();
+        eprint!("Baking nebula . . . ");
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/fullscreen.vert", None)
+            .attach_file(
+                "./resources/shaders/nebula.frag",
+                Some(vec!["./resources/shaders/noise.glsl"]),
+            )
+            .link_or_error_shader();
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let offset = glm::vec3(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) * 100.0;
+        let band = glm::normalize(&glm::vec3(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        ));
+        let dust = color::linear(&random_color(&mut rng, 0.12));
+        let glow = color::linear(&random_color(&mut rng, 0.3));
+
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+        for face in 0..6 {
+            gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                0,
+                gl::RGBA8 as i32,
+                size as i32,
+                size as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+        }
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as i32);
+        }
+        gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        gpu_resources::track(Kind::Texture, texture, size as u64 * size as u64 * 4 * 6, "nebula");
+
+        // Draw each face, keeping the framebuffer and viewport of the caller
+        let (mut target, mut viewport) = (0, [0; 4]);
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let (mut fbo, mut vao) = (0, 0);
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::BindVertexArray(vao);
+        gl::Viewport(0, 0, size as i32, size as i32);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+
+        shader.activate();
+        gl::Uniform3fv(shader.get_uniform_location("u_offset"), 1, offset.as_ptr());
+        gl::Uniform3fv(shader.get_uniform_location("u_band"), 1, band.as_ptr());
+        gl::Uniform3fv(shader.get_uniform_location("u_color_dust"), 1, dust.as_ptr());
+        gl::Uniform3fv(shader.get_uniform_location("u_color_glow"), 1, glow.as_ptr());
+        for face in 0..6 {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                texture,
+                0,
+            );
+            gl::Uniform1i(shader.get_uniform_location("u_face"), face as i32);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+
+        gl::Enable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+        gl::DeleteFramebuffers(1, &fbo);
+        gl::DeleteVertexArrays(1, &vao);
+        gl::DeleteProgram(shader.program_id);
+        eprintln!("took {:?}", timer.elapsed().unwrap());
+
+        Nebula { texture }
+    }
+
+    /// Bind the cubemap for the skybox
+    pub unsafe fn bind(&self) {
+        gl::ActiveTexture(gl::TEXTURE0 + NEBULA_UNIT);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.texture);
+        gl::ActiveTexture(gl::TEXTURE0);
+    }
+}
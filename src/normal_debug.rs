@@ -0,0 +1,76 @@
+//! Face normals and broken triangles drawn over meshes, for finding seams
+//! and winding mistakes without a frame debugger
+
+use std::cell::{Cell, RefCell};
+
+use crate::error;
+use crate::gl_state::GlState;
+use crate::globals::DEBUG_NORMAL_LENGTH;
+use crate::render_queue::DrawCommand;
+use crate::scene_graph::SceneNodeType;
+use crate::shader::{self, Shader};
+
+thread_local! {
+    static SHADER: RefCell<Option<Shader>> = const { RefCell::new(None) };
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Compile the shaders. Without them, nothing is drawn.
+pub unsafe fn init() {
+    let shader = shader::ShaderBuilder::new()
+        .attach_file("./resources/shaders/normals.vert", None)
+        .attach_file("./resources/shaders/normals.geom", None)
+        .attach_file("./resources/shaders/normals.frag", None)
+        .link();
+    match shader {
+        Ok(shader) => SHADER.with(|s| *s.borrow_mut() = Some(shader)),
+        Err(e) => error::report(&e),
+    }
+}
+
+/// Draw the normals of every node, not only those marked
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+}
+
+pub fn enabled() -> bool {
+    ENABLED.with(|e| e.get())
+}
+
+/// Whether the normals of `cmd` are drawn
+pub fn wanted(cmd: &DrawCommand) -> bool {
+    (cmd.debug_normals || enabled())
+        && cmd.skin == 0
+        && !matches!(cmd.node_type, SceneNodeType::Skybox | SceneNodeType::Geometry2d)
+}
+
+/// Draw the normals of those of `cmds` that want them, with the depth of the
+/// pass drawn with `sh`. Returns the number of draw calls made.
+pub unsafe fn draw<'a>(state: &mut GlState, sh: &Shader, cmds: impl Iterator<Item = &'a DrawCommand>) -> usize {
+    let cmds: Vec<&DrawCommand> = cmds.filter(|cmd| wanted(cmd)).collect();
+    if cmds.is_empty() {
+        return 0;
+    }
+    SHADER.with(|shader| {
+        let shader = shader.borrow();
+        let shader = match shader.as_ref() {
+            Some(shader) => shader,
+            None => return 0,
+        };
+        let mut log_depth = 0.0;
+        let u_log_depth = sh.get_uniform_location("u_log_depth");
+        if u_log_depth != -1 {
+            gl::GetUniformfv(sh.program_id, u_log_depth, &mut log_depth);
+        }
+        state.use_program(shader.program_id);
+        state.uniform_1f(shader.get_uniform_location("u_log_depth"), log_depth);
+        state.uniform_1f(shader.get_uniform_location("u_normal_length"), DEBUG_NORMAL_LENGTH);
+        let u_mvp = shader.get_uniform_location("u_mvp");
+        for cmd in &cmds {
+            state.bind_vertex_array(cmd.vao);
+            gl::UniformMatrix4fv(u_mvp, 1, gl::FALSE, cmd.mvp.as_ptr());
+            gl::DrawElements(gl::TRIANGLES, cmd.index_count, gl::UNSIGNED_INT, std::ptr::null());
+        }
+        cmds.len()
+    })
+}
@@ -0,0 +1,248 @@
+//! Culling of moons and props hidden behind planets, with occlusion queries
+
+use std::collections::HashMap;
+
+use nalgebra_glm as glm;
+
+use crate::globals::{OCCLUSION_CONFIRM, OCCLUSION_MARGIN};
+use crate::procedural_planet::Planet;
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+use crate::shader;
+
+#[derive(Debug, Default)]
+struct Body {
+    queries: Vec<u32>, // Per depth pass
+    issued: Vec<bool>,
+    reference: u32, // Without depth testing, for the pixels the box covers
+    pending: bool,  // Queries are issued, waiting for results
+    hidden: usize,  // Results in a row with the box covered
+    // This frame, relative to the origin
+    center: glm::Vec3,
+    radius: f32,  // Half the width of the box
+    seen: bool,   // Still in the scene this frame
+    tested: bool, // And not around the eye
+}
+
+pub struct OcclusionCulling {
+    shader: shader::Shader,
+    vao: u32,                     // Without buffers, corners come from the vertex id
+    bodies: HashMap<usize, Body>, // By node id
+    pub count: usize,             // Bodies hidden this frame
+}
+
+/// Sphere around everything drawn below `node`, in world space
+fn bounds(node: &SceneNode) -> Option<(glm::Vec3, f32)> {
+    let transformation: glm::Mat4 = node.world_transformation.cast();
+    let mut sphere = (node.bounds > 0.0).then(|| {
+        let scale = (0..3)
+            .map(|i| glm::length(&transformation.column(i).xyz()))
+            .fold(0.0, f32::max);
+        (transformation.column(3).xyz(), node.bounds * scale)
+    });
+    for &child in &node.children {
+        let Some((center, radius)) = bounds(unsafe { &*child }) else {
+            continue;
+        };
+        sphere = Some(match sphere {
+            None => (center, radius),
+            Some((c, r)) => {
+                // Smallest sphere around both
+                let distance = glm::distance(&c, &center);
+                if distance + radius <= r {
+                    (c, r)
+                } else if distance + r <= radius {
+                    (center, radius)
+                } else {
+                    let r_new = (distance + r + radius) / 2.0;
+                    (c + (center - c) * ((r_new - r) / distance), r_new)
+                }
+            }
+        });
+    }
+    sphere
+}
+
+impl OcclusionCulling {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/occlusion.vert", None)
+            .attach_file("./resources/shaders/occlusion.frag", None)
+            .link_or_error_shader();
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        OcclusionCulling {
+            shader,
+            vao,
+            bodies: HashMap::new(),
+            count: 0,
+        }
+    }
+
+    /// Read back the queries of the frames before, and hide the bodies found
+    /// covered. Nothing is hidden unless `enabled`, but bodies are still
+    /// tested, so turning it on takes effect right away.
+    pub unsafe fn begin(
+        &mut self,
+        planets: &[Planet],
+        planet_nodes: &mut [Node],
+        props: &mut [Node],
+        eye: &glm::Vec3,
+        origin: &glm::Vec3,
+        enabled: bool,
+    ) {
+        let planet_bodies = planets
+            .iter()
+            .zip(planet_nodes.iter_mut())
+            .map(|(planet, node)| (Some((planet.position, planet.reach())), node));
+        let prop_bodies = props
+            .iter_mut()
+            .filter(|node| node.node_type != SceneNodeType::Geometry2d)
+            .map(|node| (bounds(node), node));
+
+        self.count = 0;
+        for body in self.bodies.values_mut() {
+            body.seen = false;
+            body.tested = false;
+        }
+        for (sphere, node) in planet_bodies.chain(prop_bodies) {
+            node.occluded = false;
+            let Some((center, radius)) = sphere else {
+                continue;
+            };
+            let body = self.bodies.entry(node.node_id).or_default();
+            if body.pending && available(body.reference) {
+                let reference = result(body.reference);
+                // Outside the view is for frustum culling to decide
+                let covered = reference > 0
+                    && body
+                        .queries
+                        .iter()
+                        .zip(&body.issued)
+                        .any(|(&query, &issued)| issued && result(query) == 0);
+                body.hidden = if covered { body.hidden + 1 } else { 0 };
+                body.issued.iter_mut().for_each(|issued| *issued = false);
+                body.pending = false;
+            }
+            body.seen = true;
+            body.center = center - origin;
+            body.radius = radius * OCCLUSION_MARGIN;
+            // The box is not clipped at the near plane, so must stay in front of the eye
+            body.tested = glm::distance(eye, &center) > body.radius * 3.0f32.sqrt();
+            if !body.tested {
+                body.hidden = 0;
+            }
+            node.occluded = enabled && body.hidden >= OCCLUSION_CONFIRM;
+            self.count += node.occluded as usize;
+        }
+
+        // Bodies gone, like after reloading the scene
+        self.bodies.retain(|_, body| {
+            if !body.seen {
+                delete_queries(body);
+            }
+            body.seen
+        });
+    }
+
+    /// Count the pixels of each box not covered by depth pass `pass`, with
+    /// the same `view` and `perspective` as the pass. Depth is clamped, so
+    /// boxes beyond the pass are tested against everything in it. Leaves the
+    /// occlusion shader active.
+    pub unsafe fn probe(&mut self, pass: usize, view: &glm::Mat4, perspective: &glm::Mat4, log_depth: f32) {
+        if self.bodies.values().all(|body| body.pending || !body.tested) {
+            return;
+        }
+        self.shader.activate();
+        gl::BindVertexArray(self.vao);
+        gl::UniformMatrix4fv(self.shader.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_perspective"),
+            1,
+            gl::FALSE,
+            perspective.as_ptr(),
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_log_depth"), log_depth);
+        gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+        gl::DepthMask(gl::FALSE);
+        gl::DepthFunc(gl::LEQUAL); // Clamped to the far plane, where nothing was drawn
+        gl::Enable(gl::DEPTH_CLAMP);
+        gl::Disable(gl::CULL_FACE);
+
+        for body in self.bodies.values_mut() {
+            if body.pending || !body.tested {
+                continue;
+            }
+            if body.queries.len() <= pass {
+                let mut queries = vec![0; pass + 1 - body.queries.len()];
+                gl::GenQueries(queries.len() as i32, queries.as_mut_ptr());
+                body.queries.extend(queries);
+                body.issued.resize(pass + 1, false);
+            }
+            gl::Uniform3fv(self.shader.get_uniform_location("u_center"), 1, body.center.as_ptr());
+            gl::Uniform1f(self.shader.get_uniform_location("u_radius"), body.radius);
+            gl::BeginQuery(gl::ANY_SAMPLES_PASSED, body.queries[pass]);
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 14);
+            gl::EndQuery(gl::ANY_SAMPLES_PASSED);
+            body.issued[pass] = true;
+        }
+
+        gl::Enable(gl::CULL_FACE);
+        gl::Disable(gl::DEPTH_CLAMP);
+        gl::DepthFunc(gl::LESS);
+        gl::DepthMask(gl::TRUE);
+        gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+    }
+
+    /// Count the pixels of each box without depth testing, after all depth
+    /// passes, with an infinite `perspective`, and wait for the results.
+    /// Leaves the occlusion shader active.
+    pub unsafe fn end(&mut self, view: &glm::Mat4, perspective: &glm::Mat4) {
+        self.shader.activate();
+        gl::BindVertexArray(self.vao);
+        gl::UniformMatrix4fv(self.shader.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_perspective"),
+            1,
+            gl::FALSE,
+            perspective.as_ptr(),
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_log_depth"), 0.0);
+        gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+        gl::Disable(gl::DEPTH_TEST);
+        for body in self.bodies.values_mut() {
+            if body.pending || !body.issued.iter().any(|&issued| issued) {
+                continue;
+            }
+            if body.reference == 0 {
+                gl::GenQueries(1, &mut body.reference);
+            }
+            gl::Uniform3fv(self.shader.get_uniform_location("u_center"), 1, body.center.as_ptr());
+            gl::Uniform1f(self.shader.get_uniform_location("u_radius"), body.radius);
+            gl::BeginQuery(gl::ANY_SAMPLES_PASSED, body.reference);
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 14);
+            gl::EndQuery(gl::ANY_SAMPLES_PASSED);
+            body.pending = true;
+        }
+        gl::Enable(gl::DEPTH_TEST);
+        gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+    }
+}
+
+unsafe fn available(query: u32) -> bool {
+    let mut available = 0;
+    gl::GetQueryObjectuiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+    available != 0
+}
+
+unsafe fn result(query: u32) -> u32 {
+    let mut samples = 0;
+    gl::GetQueryObjectuiv(query, gl::QUERY_RESULT, &mut samples);
+    samples
+}
+
+unsafe fn delete_queries(body: &Body) {
+    gl::DeleteQueries(body.queries.len() as i32, body.queries.as_ptr());
+    if body.reference != 0 {
+        gl::DeleteQueries(1, &body.reference);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::color;
+use crate::gpu_resources::{GpuHandle, Kind};
+
+/// Framebuffer object with colour and depth/stencil renderbuffers, for
+/// rendering without a visible window. Deleted when dropped.
+pub struct Framebuffer {
+    pub fbo: GpuHandle,
+    pub size: (u32, u32),
+    _color: GpuHandle,
+    _depth_stencil: GpuHandle,
+}
+
+impl Framebuffer {
+    pub unsafe fn new(size: (u32, u32)) -> Result<Self, String> {
+        let mut fbo = 0;
+        let mut renderbuffers = [0; 2];
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+        let [color, depth_stencil] = renderbuffers;
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, color);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, color::texture_format(), size.0 as i32, size.1 as i32);
+        gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::RENDERBUFFER,
+            color,
+        );
+        gl::BindRenderbuffer(gl::RENDERBUFFER, depth_stencil);
+        gl::RenderbufferStorage(
+            gl::RENDERBUFFER,
+            gl::DEPTH24_STENCIL8,
+            size.0 as i32,
+            size.1 as i32,
+        );
+        gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_STENCIL_ATTACHMENT,
+            gl::RENDERBUFFER,
+            depth_stencil,
+        );
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+        let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        let texels = size.0 as u64 * size.1 as u64;
+        let framebuffer = Framebuffer {
+            fbo: GpuHandle::new(Kind::Framebuffer, fbo, 0, "offscreen"),
+            size,
+            _color: GpuHandle::new(Kind::Renderbuffer, color, texels * 4, "offscreen"),
+            _depth_stencil: GpuHandle::new(Kind::Renderbuffer, depth_stencil, texels * 4, "offscreen"),
+        };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(format!("Framebuffer incomplete (status {:#x})", status));
+        }
+        Ok(framebuffer)
+    }
+
+    /// Draw to and read from this framebuffer
+    pub unsafe fn bind(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo.id());
+    }
+}
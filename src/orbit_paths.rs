@@ -0,0 +1,165 @@
+//! Orbit paths drawn through the scene, for seeing how the system is laid out
+
+use nalgebra_glm as glm;
+
+use crate::globals::{ORBIT_PATH_ALPHA, ORBIT_PATH_INTERVAL, ORBIT_PATH_SEGMENTS, WORLD_SPEED};
+use crate::minimap::body_color;
+use crate::nbody::{self, NBody};
+use crate::procedural_planet::Planet;
+use crate::{shader, util};
+
+struct Path {
+    planet: usize,
+    parent: usize,
+    first: i32,   // Vertex in the buffer
+    count: i32,
+    closed: bool, // Drawn as a loop
+}
+
+pub struct OrbitPaths {
+    shader: shader::Shader,
+    vao: u32,
+    vbo: u32,
+    capacity: isize, // Bytes allocated for vbo
+    paths: Vec<Path>,
+    scale: f32,      // Of trajectories to the scene units the paths are in
+    simulated: bool, // Sampled from the n-body simulation
+    age: usize,      // Frames since sampled
+}
+
+impl OrbitPaths {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/orbit.vert", None)
+            .attach_file("./resources/shaders/orbit.geom", None)
+            .attach_file("./resources/shaders/orbit.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+
+        OrbitPaths {
+            shader,
+            vao,
+            vbo,
+            capacity: 0,
+            paths: vec![],
+            scale: 1.0,
+            simulated: false,
+            age: 0,
+        }
+    }
+
+    /// Sample the paths of `planets` again when due, from `nbody` while it
+    /// runs, with trajectories currently `scale` times those of the scene
+    pub unsafe fn update(&mut self, planets: &[Planet], nbody: Option<&NBody>, scale: f32) {
+        let stale = self.age >= ORBIT_PATH_INTERVAL
+            || scale != self.scale
+            || nbody.is_some() != self.simulated
+            || self.paths.iter().any(|path| path.planet >= planets.len());
+        self.age += 1;
+        if !stale && !self.paths.is_empty() {
+            return;
+        }
+        self.age = 0;
+        self.scale = scale;
+        self.simulated = nbody.is_some();
+
+        self.paths.clear();
+        let mut vertices: Vec<f32> = vec![];
+        for planet in planets {
+            let (i, parent) = (planet.planet_id, planet.parent_id);
+            let speed = planet.traj_speed * WORLD_SPEED;
+            if i == parent || speed == 0.0 {
+                continue;
+            }
+            let period = std::f32::consts::TAU / speed.abs();
+            let points = match nbody {
+                Some(nbody) => nbody.predict(i, parent, period, ORBIT_PATH_SEGMENTS + 1),
+                None => (0..ORBIT_PATH_SEGMENTS)
+                    .map(|k| {
+                        let time = k as f64 / ORBIT_PATH_SEGMENTS as f64 * period as f64;
+                        nbody::kepler_offset(planet, time) / scale
+                    })
+                    .collect(),
+            };
+            self.paths.push(Path {
+                planet: i,
+                parent,
+                first: (vertices.len() / 3) as i32,
+                count: points.len() as i32,
+                closed: nbody.is_none(),
+            });
+            for p in points {
+                vertices.extend_from_slice(&[p.x, p.y, p.z]);
+            }
+        }
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        let bytes = util::byte_size_of_array(&vertices);
+        if bytes > self.capacity {
+            // Grow, and orphan the old storage
+            self.capacity = bytes.max(2 * self.capacity);
+            gl::BufferData(gl::ARRAY_BUFFER, self.capacity, std::ptr::null(), gl::DYNAMIC_DRAW);
+        }
+        gl::BufferSubData(gl::ARRAY_BUFFER, 0, bytes, util::pointer_to_array(&vertices));
+    }
+
+    /// Draw the paths around where the parents in `planets` are, relative to
+    /// `origin`, in a clipping pass of `perspective` with `log_depth`, `width`
+    /// pixels wide in a `viewport` of pixels across. Hidden behind what is
+    /// drawn, without hiding anything.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn draw(
+        &self,
+        planets: &[Planet],
+        view: &glm::Mat4,
+        perspective: &glm::Mat4,
+        origin: &glm::Vec3,
+        log_depth: f32,
+        viewport: (u32, u32),
+        width: f32,
+    ) {
+        if self.paths.is_empty() {
+            return;
+        }
+        self.shader.activate();
+        gl::Uniform2f(
+            self.shader.get_uniform_location("u_viewport"),
+            viewport.0 as f32,
+            viewport.1 as f32,
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_width"), width);
+        gl::Uniform1f(self.shader.get_uniform_location("u_log_depth"), log_depth);
+        let u_mvp = self.shader.get_uniform_location("u_mvp");
+        let u_color = self.shader.get_uniform_location("u_color");
+
+        // The quads face either way
+        let culling = gl::IsEnabled(gl::CULL_FACE) == gl::TRUE;
+        gl::Disable(gl::CULL_FACE);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::DepthMask(gl::FALSE);
+        gl::BindVertexArray(self.vao);
+        for path in self.paths.iter().filter(|path| path.planet < planets.len()) {
+            let planet = &planets[path.planet];
+            let model = glm::translation(&(planets[path.parent].position - origin))
+                * glm::scaling(&glm::vec3(self.scale, self.scale, self.scale));
+            let mvp = perspective * view * model;
+            gl::UniformMatrix4fv(u_mvp, 1, gl::FALSE, mvp.as_ptr());
+            let color = planet.orbit_color.unwrap_or_else(|| body_color(planet).xyz());
+            gl::Uniform4f(u_color, color.x, color.y, color.z, ORBIT_PATH_ALPHA);
+            let mode = if path.closed { gl::LINE_LOOP } else { gl::LINE_STRIP };
+            gl::DrawArrays(mode, path.first, path.count);
+        }
+        gl::DepthMask(gl::TRUE);
+        if culling {
+            gl::Enable(gl::CULL_FACE);
+        }
+    }
+}
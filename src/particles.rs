@@ -0,0 +1,566 @@
+//! Particle effects, like engine exhaust, impact dust and snow
+
+use nalgebra_glm as glm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::globals::{GPU_PARTICLES_BINDING, MAX_GPU_PARTICLES, MAX_PARTICLES};
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::scene_graph::SceneNode;
+use crate::{capabilities, error, shader, util};
+
+// Per instance center (3), size (1) and colour (4)
+const INSTANCE_SIZE: usize = 8;
+// Of a particle in the storage buffer, as in particles.comp
+const GPU_PARTICLE_BYTES: usize = 6 * 4 * std::mem::size_of::<f32>();
+const WORKGROUP_SIZE: u32 = 64; // local_size_x in particles.comp
+
+/// Emitters available from scene files
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum Preset {
+    Exhaust,
+    Snow,
+    Impact,
+    CometTail,
+}
+
+/// Particles spawned by a scene node. Distances are in the node's units, so
+/// effects scale with it.
+#[derive(Debug, Copy, Clone)]
+pub struct Emitter {
+    pub rate: f32,     // Particles per second
+    pub lifetime: f32, // Seconds
+    pub speed: f32,    // Along the emitter's y axis
+    pub spread: f32,   // Random speed in any direction
+    pub radius: f32,   // Spawned in a disc across the y axis
+    pub gravity: f32,  // Acceleration against the y axis
+    pub size: (f32, f32),
+    pub color: (glm::Vec4, glm::Vec4), // From spawning to dying, alpha fading out
+    pub gpu: bool,     // Simulated in a compute shader where there is one
+    accumulator: f32, // Particles due, for rates below one per frame
+}
+
+impl Emitter {
+    pub fn preset(preset: Preset) -> Self {
+        let emitter = Emitter {
+            rate: 0.0,
+            lifetime: 1.0,
+            speed: 0.0,
+            spread: 0.0,
+            radius: 0.0,
+            gravity: 0.0,
+            size: (1.0, 1.0),
+            color: (glm::vec4(1.0, 1.0, 1.0, 1.0), glm::vec4(1.0, 1.0, 1.0, 0.0)),
+            gpu: false,
+            accumulator: 0.0,
+        };
+        match preset {
+            Preset::Exhaust => Emitter {
+                rate: 200.0,
+                lifetime: 0.6,
+                speed: 12.0,
+                spread: 2.0,
+                radius: 0.3,
+                size: (0.6, 2.0),
+                color: (glm::vec4(1.0, 0.7, 0.3, 0.8), glm::vec4(0.3, 0.3, 0.4, 0.0)),
+                ..emitter
+            },
+            Preset::Snow => Emitter {
+                rate: 150.0,
+                lifetime: 8.0,
+                spread: 0.5,
+                radius: 60.0,
+                gravity: 1.0,
+                size: (0.3, 0.3),
+                color: (glm::vec4(0.9, 0.95, 1.0, 0.6), glm::vec4(0.9, 0.95, 1.0, 0.0)),
+                ..emitter
+            },
+            // Only burst, see `ParticleSystem::burst`
+            Preset::Impact => Emitter {
+                lifetime: 1.2,
+                speed: 3.0,
+                spread: 4.0,
+                radius: 1.0,
+                gravity: 6.0,
+                size: (0.8, 2.5),
+                color: (glm::vec4(0.6, 0.5, 0.4, 0.6), glm::vec4(0.4, 0.35, 0.3, 0.0)),
+                ..emitter
+            },
+            // Pushed out by the sunlight, turned and dimmed by comet.rs
+            Preset::CometTail => Emitter {
+                rate: 120.0,
+                lifetime: 6.0,
+                speed: 2.0,
+                spread: 0.4,
+                radius: 0.8,
+                gravity: -1.5,
+                size: (1.0, 5.0),
+                color: (glm::vec4(0.8, 0.9, 1.0, 0.5), glm::vec4(0.5, 0.7, 1.0, 0.0)),
+                ..emitter
+            },
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Particle {
+    position: glm::Vec3,
+    previous: glm::Vec3, // Position before the last update
+    velocity: glm::Vec3,
+    acceleration: glm::Vec3,
+    age: f32,
+    lifetime: f32,
+    size: (f32, f32),
+    color: (glm::Vec4, glm::Vec4),
+}
+
+/// Slots of the storage buffer an emitter on the GPU spawns in, in turn
+#[derive(Debug)]
+struct Pool {
+    node_id: usize, // Of the emitter
+    offset: usize,  // First slot in the buffer
+    capacity: usize,
+    next: usize,    // Slot of the pool spawned in next
+    spawned: usize, // Slots spawned in, for counting
+    seen: bool,     // Emitter still in the scene this step
+}
+
+/// Particles of emitters simulated on the GPU. Each emitter gets a pool of
+/// slots in one storage buffer, enough for the particles it keeps alive,
+/// where the compute shader spawns new ones in place of the oldest.
+struct GpuParticles {
+    update: shader::Shader, // particles.comp
+    shader: shader::Shader, // particle.vert drawing from the buffer
+    vao: GpuHandle,         // The quad only
+    buffer: GpuHandle,
+    pools: Vec<Pool>, // By offset
+}
+
+impl GpuParticles {
+    /// Compile the shaders and allocate the buffer, if the context has compute
+    /// shaders. Draws the quad in `quad_vbo`.
+    unsafe fn new(quad_vbo: u32) -> Option<Self> {
+        if !capabilities::global().compute_shaders() {
+            return None;
+        }
+        let update = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/particles.comp", None)
+            .link();
+        let shader = shader::ShaderBuilder::new()
+            .with_defines(&[("GPU_PARTICLES", "")])
+            .attach_file("./resources/shaders/particle.vert", None)
+            .attach_file("./resources/shaders/particle.frag", None)
+            .link();
+        let (update, shader) = match (update, shader) {
+            (Ok(update), Ok(shader)) => (update, shader),
+            (Err(e), _) | (_, Err(e)) => {
+                error::report(&e);
+                return None;
+            }
+        };
+
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, quad_vbo);
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+        gl::BindVertexArray(0);
+
+        let bytes = MAX_GPU_PARTICLES * GPU_PARTICLE_BYTES;
+        let mut buffer = 0;
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, buffer);
+        gl::BufferData(gl::SHADER_STORAGE_BUFFER, bytes as isize, std::ptr::null(), gl::DYNAMIC_COPY);
+        // Zero lifetimes, never spawned
+        gl::ClearBufferData(gl::SHADER_STORAGE_BUFFER, gl::R32UI, gl::RED_INTEGER, gl::UNSIGNED_INT, std::ptr::null());
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+
+        Some(GpuParticles {
+            update,
+            shader,
+            vao: GpuHandle::new(Kind::VertexArray, vao, 0, "gpu particles"),
+            buffer: GpuHandle::new(Kind::Buffer, buffer, bytes as u64, "gpu particles"),
+            pools: Vec::new(),
+        })
+    }
+
+    /// Particles alive or recently died
+    fn count(&self) -> usize {
+        self.pools.iter().map(|pool| pool.spawned).sum()
+    }
+
+    /// Kill the particles in `count` slots from `offset`
+    unsafe fn kill(&self, offset: usize, count: usize) {
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.buffer.id());
+        gl::ClearBufferSubData(
+            gl::SHADER_STORAGE_BUFFER,
+            gl::R32UI,
+            (offset * GPU_PARTICLE_BYTES) as isize,
+            (count * GPU_PARTICLE_BYTES) as isize,
+            gl::RED_INTEGER,
+            gl::UNSIGNED_INT,
+            std::ptr::null(),
+        );
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+    }
+
+    /// Index of the pool of emitter `node_id`, allocated with `capacity` slots
+    /// in the first gap large enough if it has none. None when full.
+    fn pool(&mut self, node_id: usize, capacity: usize) -> Option<usize> {
+        if let Some(i) = self.pools.iter().position(|pool| pool.node_id == node_id) {
+            return Some(i);
+        }
+        let mut offset = 0;
+        let mut index = self.pools.len();
+        for (i, pool) in self.pools.iter().enumerate() {
+            if pool.offset - offset >= capacity {
+                index = i;
+                break;
+            }
+            offset = pool.offset + pool.capacity;
+        }
+        if index == self.pools.len() && offset + capacity > MAX_GPU_PARTICLES {
+            return None;
+        }
+        self.pools.insert(index, Pool { node_id, offset, capacity, next: 0, spawned: 0, seen: false });
+        Some(index)
+    }
+
+    /// Age and move the particles of emitter `node_id` by `delta_time`, and
+    /// spawn `count` from it. Returns false if it has no room in the buffer.
+    unsafe fn step(
+        &mut self,
+        node_id: usize,
+        transform: &glm::Mat4,
+        emitter: &Emitter,
+        count: usize,
+        delta_time: f32,
+        seed: u32,
+    ) -> bool {
+        let capacity = (emitter.rate * emitter.lifetime).ceil() as usize + 1;
+        let pool = match self.pool(node_id, capacity) {
+            Some(i) => &mut self.pools[i],
+            None => return false,
+        };
+        pool.seen = true;
+        let count = count.min(pool.capacity);
+
+        let sh = &self.update;
+        sh.activate();
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, GPU_PARTICLES_BINDING, self.buffer.id());
+        gl::Uniform1ui(sh.get_uniform_location("u_offset"), pool.offset as u32);
+        gl::Uniform1ui(sh.get_uniform_location("u_capacity"), pool.capacity as u32);
+        gl::Uniform1ui(sh.get_uniform_location("u_spawn_first"), pool.next as u32);
+        gl::Uniform1ui(sh.get_uniform_location("u_spawn_count"), count as u32);
+        gl::Uniform1ui(sh.get_uniform_location("u_seed"), seed);
+        gl::Uniform1f(sh.get_uniform_location("u_delta_time"), delta_time);
+        gl::UniformMatrix4fv(sh.get_uniform_location("u_transform"), 1, gl::FALSE, transform.as_ptr());
+        gl::Uniform1f(sh.get_uniform_location("u_speed"), emitter.speed);
+        gl::Uniform1f(sh.get_uniform_location("u_spread"), emitter.spread);
+        gl::Uniform1f(sh.get_uniform_location("u_radius"), emitter.radius);
+        gl::Uniform1f(sh.get_uniform_location("u_gravity"), emitter.gravity);
+        gl::Uniform1f(sh.get_uniform_location("u_lifetime"), emitter.lifetime);
+        gl::Uniform2f(sh.get_uniform_location("u_size"), emitter.size.0, emitter.size.1);
+        gl::Uniform4fv(sh.get_uniform_location("u_color_start"), 1, emitter.color.0.as_ptr());
+        gl::Uniform4fv(sh.get_uniform_location("u_color_end"), 1, emitter.color.1.as_ptr());
+        gl::DispatchCompute((pool.capacity as u32).div_ceil(WORKGROUP_SIZE), 1, 1);
+        // Stepped again or drawn from next
+        gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, GPU_PARTICLES_BINDING, 0);
+
+        pool.next = (pool.next + count) % pool.capacity;
+        pool.spawned = (pool.spawned + count).min(pool.capacity);
+        true
+    }
+
+    /// Free the pools of emitters not stepped since the last call
+    unsafe fn end_step(&mut self) {
+        for pool in self.pools.iter().filter(|pool| !pool.seen) {
+            self.kill(pool.offset, pool.capacity);
+        }
+        self.pools.retain(|pool| pool.seen);
+        for pool in self.pools.iter_mut() {
+            pool.seen = false;
+        }
+    }
+
+    /// Kill all particles and free all pools
+    unsafe fn clear(&mut self) {
+        if let Some(last) = self.pools.last() {
+            self.kill(0, last.offset + last.capacity);
+        }
+        self.pools.clear();
+    }
+
+    /// Draw the particles at a distance from `eye` within `range`, both
+    /// relative to `origin`, `alpha` of the way from the step before the last
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn draw(
+        &self,
+        view: &glm::Mat4,
+        perspective: &glm::Mat4,
+        log_depth: f32,
+        range: (f32, f32),
+        origin: &glm::Vec3,
+        eye: &glm::Vec3,
+        alpha: f32,
+    ) {
+        let end = match self.pools.last() {
+            Some(last) => last.offset + last.capacity,
+            None => return,
+        };
+        let sh = &self.shader;
+        sh.activate();
+        gl::BindVertexArray(self.vao.id());
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, GPU_PARTICLES_BINDING, self.buffer.id());
+        gl::UniformMatrix4fv(sh.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(sh.get_uniform_location("u_perspective"), 1, gl::FALSE, perspective.as_ptr());
+        gl::Uniform1f(sh.get_uniform_location("u_log_depth"), log_depth);
+        gl::Uniform3fv(sh.get_uniform_location("u_origin"), 1, origin.as_ptr());
+        gl::Uniform3fv(sh.get_uniform_location("u_eye"), 1, (eye - origin).as_ptr());
+        gl::Uniform1f(sh.get_uniform_location("u_alpha"), alpha);
+        gl::Uniform2f(sh.get_uniform_location("u_range"), range.0, range.1);
+        gl::DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, end as i32);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, GPU_PARTICLES_BINDING, 0);
+    }
+}
+
+/// CPU simulation and GPU side of all particles
+pub struct ParticleSystem {
+    shader: shader::Shader,
+    vao: u32,
+    instance_vbo: u32,
+    capacity: isize, // Bytes allocated for instance_vbo
+    particles: Vec<Particle>,
+    distances: Vec<f32>, // Of uploaded instances to the eye, far to near
+    rng: StdRng,
+    gpu: Option<GpuParticles>, // None without compute shaders
+    // Of the last `prepare`, for drawing those on the GPU
+    eye: glm::Vec3,
+    origin: glm::Vec3,
+    alpha: f32,
+}
+
+impl ParticleSystem {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/particle.vert", None)
+            .attach_file("./resources/shaders/particle.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut vbos) = (0, [0; 2]);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(2, vbos.as_mut_ptr());
+        let [quad_vbo, instance_vbo] = vbos;
+
+        // Corners of a quad, as a triangle strip
+        let quad: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+        gl::BindBuffer(gl::ARRAY_BUFFER, quad_vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            util::byte_size_of_array(&quad),
+            util::pointer_to_array(&quad),
+            gl::STATIC_DRAW,
+        );
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribDivisor(1, 1);
+        gl::EnableVertexAttribArray(2);
+        gl::VertexAttribDivisor(2, 1);
+        gl::BindVertexArray(0);
+
+        ParticleSystem {
+            shader,
+            vao,
+            instance_vbo,
+            capacity: 0,
+            particles: Vec::new(),
+            distances: Vec::new(),
+            rng: StdRng::seed_from_u64(0),
+            gpu: GpuParticles::new(quad_vbo),
+            eye: glm::zero(),
+            origin: glm::zero(),
+            alpha: 1.0,
+        }
+    }
+
+    /// Number of live particles, counting those on the GPU by the slots
+    /// spawned in
+    pub fn count(&self) -> usize {
+        self.particles.len() + self.gpu.as_ref().map_or(0, |gpu| gpu.count())
+    }
+
+    /// Remove all particles, when world space moves under them
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        if let Some(gpu) = self.gpu.as_mut() {
+            unsafe { gpu.clear() };
+        }
+    }
+
+    /// Age and move particles by a fixed step, and spawn new ones from the
+    /// emitters below `root`, as transformed when last drawn
+    pub fn update(&mut self, root: &mut SceneNode, delta_time: f32) {
+        for p in self.particles.iter_mut() {
+            p.previous = p.position;
+            p.velocity += p.acceleration * delta_time;
+            p.position += p.velocity * delta_time;
+            p.age += delta_time;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+        self.emit(root, delta_time);
+        if let Some(gpu) = self.gpu.as_mut() {
+            unsafe { gpu.end_step() };
+        }
+    }
+
+    fn emit(&mut self, node: &mut SceneNode, delta_time: f32) {
+        if let Some(emitter) = node.emitter.as_mut() {
+            emitter.accumulator += emitter.rate * delta_time;
+            let count = emitter.accumulator as usize;
+            emitter.accumulator -= count as f32;
+            let emitter = *emitter;
+            let transform: glm::Mat4 = node.world_transformation.cast();
+            let seed = self.rng.gen();
+            let on_gpu = match self.gpu.as_mut().filter(|_| emitter.gpu) {
+                Some(gpu) => unsafe { gpu.step(node.node_id, &transform, &emitter, count, delta_time, seed) },
+                None => false,
+            };
+            if !on_gpu {
+                self.spawn(&transform, &emitter, count);
+            }
+        }
+        for &child in &node.children {
+            self.emit(unsafe { &mut *child }, delta_time);
+        }
+    }
+
+    /// Spawn `count` particles at once, e.g. from an impact at `position`
+    /// on ground facing `up`
+    pub fn burst(&mut self, position: &glm::Vec3, up: &glm::Vec3, emitter: &Emitter, count: usize) {
+        let rotation = glm::quat_to_mat4(&glm::quat_rotation(&glm::vec3(0.0, 1.0, 0.0), up));
+        let transform = glm::translation(position) * rotation;
+        self.spawn(&transform, emitter, count);
+    }
+
+    fn spawn(&mut self, transform: &glm::Mat4, emitter: &Emitter, count: usize) {
+        let count = count.min(MAX_PARTICLES.saturating_sub(self.particles.len()));
+        let scale = glm::length(&transform.column(0).xyz());
+        let axis = glm::normalize(&transform.column(1).xyz());
+        for _ in 0..count {
+            let angle = self.rng.gen_range(0.0..std::f32::consts::TAU);
+            let r = emitter.radius * self.rng.gen::<f32>().sqrt();
+            let local = glm::vec4(angle.cos() * r, 0.0, angle.sin() * r, 1.0);
+            let random = glm::vec3(
+                self.rng.gen_range(-1.0..1.0),
+                self.rng.gen_range(-1.0..1.0),
+                self.rng.gen_range(-1.0..1.0),
+            );
+            let position = (transform * local).xyz();
+            self.particles.push(Particle {
+                position,
+                previous: position,
+                velocity: (axis * emitter.speed + random * emitter.spread) * scale,
+                acceleration: -axis * emitter.gravity * scale,
+                age: 0.0,
+                // Varied a little, so bursts don't vanish all at once
+                lifetime: emitter.lifetime * self.rng.gen_range(0.7..1.0),
+                size: (emitter.size.0 * scale, emitter.size.1 * scale),
+                color: emitter.color,
+            });
+        }
+    }
+
+    /// Sort particles by distance to `eye` and upload them relative to
+    /// `origin`, `alpha` of the way from the update before the last to the
+    /// last, for drawing this frame
+    pub unsafe fn prepare(&mut self, eye: &glm::Vec3, origin: &glm::Vec3, alpha: f32) {
+        self.eye = *eye;
+        self.origin = *origin;
+        self.alpha = alpha;
+        let mut sorted: Vec<(f32, glm::Vec3, &Particle)> = self
+            .particles
+            .iter()
+            .map(|p| {
+                let position = glm::lerp(&p.previous, &p.position, alpha);
+                (glm::distance(&position, eye), position, p)
+            })
+            .collect();
+        sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        self.distances = sorted.iter().map(|(d, _, _)| *d).collect();
+        if sorted.is_empty() {
+            return;
+        }
+
+        let mut instances = Vec::with_capacity(sorted.len() * INSTANCE_SIZE);
+        for (_, position, p) in sorted {
+            let t = p.age / p.lifetime;
+            let center = position - origin;
+            let color = glm::lerp(&p.color.0, &p.color.1, t);
+            instances.extend_from_slice(&[center.x, center.y, center.z]);
+            instances.push(p.size.0 + (p.size.1 - p.size.0) * t);
+            instances.extend_from_slice(&[color.x, color.y, color.z, color.w]);
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+        let size = util::byte_size_of_array(&instances);
+        if size > self.capacity {
+            // Grow, and orphan the old storage
+            self.capacity = size.max(2 * self.capacity);
+            gl::BufferData(gl::ARRAY_BUFFER, self.capacity, std::ptr::null(), gl::DYNAMIC_DRAW);
+        }
+        gl::BufferSubData(gl::ARRAY_BUFFER, 0, size, util::pointer_to_array(&instances));
+    }
+
+    /// Draw the particles prepared at a distance within `range`, with the
+    /// same `view` and `perspective` as the rest of the depth pass. Leaves
+    /// a particle shader active, so reactivate the scene shader afterwards.
+    pub unsafe fn draw(&self, view: &glm::Mat4, perspective: &glm::Mat4, log_depth: f32, range: (f32, f32)) {
+        // Far to near, so each range is a contiguous run
+        let start = self.distances.partition_point(|&d| d >= range.1);
+        let end = self.distances.partition_point(|&d| d >= range.0);
+        let gpu = self.gpu.as_ref().filter(|gpu| !gpu.pools.is_empty());
+        if start >= end && gpu.is_none() {
+            return;
+        }
+
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE);
+        gl::DepthMask(gl::FALSE);
+        // Blended additively, so in any order
+        if let Some(gpu) = gpu {
+            gpu.draw(view, perspective, log_depth, range, &self.origin, &self.eye, self.alpha);
+        }
+        if start < end {
+            self.draw_instances(view, perspective, log_depth, start, end);
+        }
+        gl::DepthMask(gl::TRUE);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    }
+
+    /// Draw the prepared instances from `start` to `end`
+    unsafe fn draw_instances(&self, view: &glm::Mat4, perspective: &glm::Mat4, log_depth: f32, start: usize, end: usize) {
+        gl::BindVertexArray(self.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+        let stride = (INSTANCE_SIZE * std::mem::size_of::<f32>()) as i32;
+        let first = start * INSTANCE_SIZE;
+        gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, util::offset::<f32>(first as u32));
+        gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, util::offset::<f32>(first as u32 + 4));
+
+        self.shader.activate();
+        gl::UniformMatrix4fv(self.shader.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_perspective"),
+            1,
+            gl::FALSE,
+            perspective.as_ptr(),
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_log_depth"), log_depth);
+        gl::DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, (end - start) as i32);
+    }
+}
@@ -0,0 +1,132 @@
+use nalgebra_glm as glm;
+
+use crate::globals::{
+    GRAVITY_SCALE, IMPACT_SPEED, PHYSICS_FRICTION, PHYSICS_MAX_STEP, PHYSICS_RESTITUTION,
+};
+use crate::procedural_planet::Planet;
+use crate::scene_graph::Node;
+use crate::util::ease;
+
+/// Dynamic prop, kept in the frame of the planet pulling it, so it moves and
+/// turns along with the planet, and stays put when the scene is rescaled or
+/// the origin moves
+#[derive(Debug, Copy, Clone)]
+struct Body {
+    planet_id: usize,
+    position: glm::Vec3, // Relative to the planet's node
+    previous: glm::Vec3, // Position before the last step
+    velocity: glm::Vec3,
+}
+
+/// Radius of planet meshes relative to their node, see `Planet::radius`
+const NODE_RADIUS: f32 = 0.5;
+
+/// Minimal rigid-body gravity for props tagged `dynamic`. Each is pulled
+/// towards the nearest planet with inverse-square gravity, and bounces on and
+/// slides to rest on its terrain, or ocean. Props are points at their origin,
+/// and don't collide with each other.
+#[derive(Default)]
+pub struct Physics {
+    bodies: Vec<Option<Body>>, // Per prop, from its position at the first step
+    pub impacts: Vec<(glm::Vec3, glm::Vec3)>, // Hard landings in the last step, position and up
+}
+
+/// Transform of a planet node, the same as its world transform as planets are
+/// children of the scene root
+fn frame(node: &Node) -> glm::Mat4 {
+    node.local_transformation().cast()
+}
+
+fn transform_point(m: &glm::Mat4, p: &glm::Vec3) -> glm::Vec3 {
+    (m * glm::vec4(p.x, p.y, p.z, 1.0)).xyz()
+}
+
+fn transform_vector(m: &glm::Mat4, v: &glm::Vec3) -> glm::Vec3 {
+    (m * glm::vec4(v.x, v.y, v.z, 0.0)).xyz()
+}
+
+impl Physics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Move dynamic props by a fixed step, placed with `place` for drawing
+    pub fn step(&mut self, props: &mut [Node], planets: &[Planet], planet_nodes: &[Node], delta_time: f32) {
+        self.bodies.resize(props.len(), None);
+        self.impacts.clear();
+        let delta_time = delta_time.min(PHYSICS_MAX_STEP);
+        for (prop, body) in props.iter_mut().zip(self.bodies.iter_mut()) {
+            if !prop.dynamic || planets.is_empty() {
+                continue;
+            }
+            let (world, world_velocity) = match body {
+                Some(b) => {
+                    let m = frame(&planet_nodes[b.planet_id]);
+                    (transform_point(&m, &b.position), transform_vector(&m, &b.velocity))
+                }
+                None => (prop.position(), glm::zero()),
+            };
+            // Closest surface, by distance to the ocean level
+            let planet_id = (0..planets.len())
+                .min_by(|&a, &b| {
+                    let surface = |i: usize| {
+                        glm::length(&(world - planet_nodes[i].position()))
+                            - planet_nodes[i].scale().x * NODE_RADIUS
+                    };
+                    surface(a).total_cmp(&surface(b))
+                })
+                .unwrap();
+            if body.is_none_or(|b| b.planet_id != planet_id) {
+                let inverse = glm::inverse(&frame(&planet_nodes[planet_id]));
+                let position = transform_point(&inverse, &world);
+                *body = Some(Body {
+                    planet_id,
+                    position,
+                    previous: position,
+                    velocity: transform_vector(&inverse, &world_velocity),
+                });
+            }
+            let b = body.as_mut().unwrap();
+            b.previous = b.position;
+            let planet = &planets[planet_id];
+
+            // Falling off with the square of the distance in radii, the same
+            // however the scene is scaled
+            let distance = glm::length(&b.position).max(f32::EPSILON) / NODE_RADIUS;
+            let up = glm::normalize(&b.position);
+            let gravity = planet.gravity * GRAVITY_SCALE * NODE_RADIUS;
+            b.velocity -= up * gravity / (distance * distance) * delta_time;
+            b.position += b.velocity * delta_time;
+
+            // Bounce off the ground, with friction slowing the rest down
+            let up = glm::normalize(&b.position);
+            let height = planet.surface_height(&up) * NODE_RADIUS;
+            if glm::length(&b.position) < height {
+                b.position = up * height;
+                let falling = glm::dot(&b.velocity, &up);
+                if falling < 0.0 {
+                    b.velocity -= up * falling * (1.0 + PHYSICS_RESTITUTION);
+                }
+                if -falling / NODE_RADIUS > IMPACT_SPEED {
+                    let m = frame(&planet_nodes[planet_id]);
+                    let up = glm::normalize(&transform_vector(&m, &up));
+                    self.impacts.push((transform_point(&m, &b.position), up));
+                }
+                let sliding = b.velocity - up * glm::dot(&b.velocity, &up);
+                b.velocity -= sliding * ease(PHYSICS_FRICTION, delta_time);
+            }
+        }
+    }
+
+    /// Place dynamic props `alpha` of the way from the step before the last
+    /// to the last. Call once the planet nodes are placed, before updating
+    /// the scene graph transformations.
+    pub fn place(&self, props: &mut [Node], planet_nodes: &[Node], alpha: f32) {
+        for (prop, body) in props.iter_mut().zip(&self.bodies) {
+            if let (true, Some(b)) = (prop.dynamic, body) {
+                let position = glm::lerp(&b.previous, &b.position, alpha);
+                prop.set_position(transform_point(&frame(&planet_nodes[b.planet_id]), &position));
+            }
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use nalgebra_glm as glm;
+
+use crate::procedural_planet::Planet;
+
+/// Direction of the ray from the camera through a pixel in the window, for a
+/// camera looking along `direction` with vertical field of view `fov`
+pub fn cursor_ray(
+    cursor: (f32, f32),
+    size: (u32, u32),
+    fov: f32,
+    direction: &glm::Vec3,
+    up: &glm::Vec3,
+) -> glm::Vec3 {
+    // Normalized device coordinates, y up
+    let x = 2.0 * cursor.0 / size.0 as f32 - 1.0;
+    let y = 1.0 - 2.0 * cursor.1 / size.1 as f32;
+    let aspect = size.0 as f32 / size.1 as f32;
+    let half_height = (fov / 2.0).tan();
+
+    let forward = glm::normalize(direction);
+    let right = glm::normalize(&glm::cross(&forward, up));
+    let up = glm::cross(&right, &forward);
+    glm::normalize(&(forward + right * x * half_height * aspect + up * y * half_height))
+}
+
+/// Distance along a ray to where it enters a sphere, if it hits
+pub fn ray_sphere(origin: &glm::Vec3, dir: &glm::Vec3, center: &glm::Vec3, radius: f32) -> Option<f32> {
+    let oc = origin - center;
+    let b = glm::dot(&oc, dir);
+    let c = glm::dot(&oc, &oc) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = -b - discriminant.sqrt();
+    // Inside the sphere, e.g. landed on a planet, only rays pointing down
+    // towards the center count as hitting it
+    if t >= 0.0 {
+        Some(t)
+    } else if c <= 0.0 && b < 0.0 {
+        Some(0.0)
+    } else {
+        None
+    }
+}
+
+/// Distance along a ray to where it enters a sphere scaled by `scale` about
+/// its center, like a planet flattened at the poles
+pub fn ray_ellipsoid(
+    origin: &glm::Vec3,
+    dir: &glm::Vec3,
+    center: &glm::Vec3,
+    radius: f32,
+    scale: &glm::Vec3,
+) -> Option<f32> {
+    // Hit the sphere in the space before scaling, where the ray is slower
+    let squashed = dir.component_div(scale);
+    let length = glm::length(&squashed);
+    let origin = center + (origin - center).component_div(scale);
+    ray_sphere(&origin, &(squashed / length), center, radius).map(|t| t / length)
+}
+
+/// Closest planet hit by a ray, using spheres bounding the highest terrain,
+/// flattened like the planet
+pub fn pick_planet(origin: &glm::Vec3, dir: &glm::Vec3, planets: &[Planet]) -> Option<usize> {
+    planets
+        .iter()
+        .filter_map(|planet| {
+            let radius = planet.radius * (1.0 + planet.max_height);
+            ray_ellipsoid(origin, dir, &planet.position, radius, &planet.flattening())
+                .map(|t| (planet.planet_id, t))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(planet_id, _)| planet_id)
+}
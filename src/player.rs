@@ -2,8 +2,6 @@ use nalgebra_glm as glm;
 // Player controller
 // - Mark closest planet and apply UP with an on/off switch
 
-use crate::globals::*;
-
 #[derive(Debug)]
 pub enum PlayerState { 
     FreeFloat, 
@@ -24,6 +22,8 @@ pub struct Player {
     pub state: PlayerState,
     pub height: f32,                // Camera height over movement position (feet)
     pub hspeed: f32,                // Horizontal speed, for simple physics
+    pub velocity: glm::TVec3<f32>,  // Movement, eased towards the keys held
+    pub speed: f32,                 // Movement speed this frame, see Cameras::speed
 
     pub closest_planet_id: usize,
 }
@@ -0,0 +1,324 @@
+//! Generated meshes of common shapes, for assembling props like satellites,
+//! stations and markers without model files
+
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+use crate::mesh::{generate_color_vec, Mesh};
+
+/// Build a mesh from its vertices, computing tangents. Triangles without
+/// area, like those at the poles of grids, are dropped.
+fn assemble(
+    vertices: Vec<glm::Vec3>,
+    normals: Vec<glm::Vec3>,
+    uvs: Vec<glm::Vec2>,
+    indices: Vec<u32>,
+    color: glm::Vec4,
+) -> Mesh {
+    let vertex_count = vertices.len();
+    let indices: Vec<u32> = indices
+        .chunks_exact(3)
+        .filter(|t| {
+            let (a, b, c) = (vertices[t[0] as usize], vertices[t[1] as usize], vertices[t[2] as usize]);
+            let longest = glm::distance2(&a, &b).max(glm::distance2(&b, &c)).max(glm::distance2(&c, &a));
+            glm::length(&glm::cross(&(b - a), &(c - a))) > 1e-6 * longest
+        })
+        .flatten()
+        .cloned()
+        .collect();
+    let mut mesh = Mesh {
+        vertices: vertices.iter().flat_map(|v| [v.x, v.y, v.z]).collect(),
+        normals: normals.iter().flat_map(|n| [n.x, n.y, n.z]).collect(),
+        texture_coordinates: uvs.iter().flat_map(|uv| [uv.x, uv.y]).collect(),
+        colors: generate_color_vec(color, vertex_count),
+        index_count: indices.len() as i32,
+        indices,
+        ..Default::default()
+    };
+    mesh.compute_tangents();
+    mesh
+}
+
+/// Surface sampled on a grid of `columns` by `rows` quads, from a function
+/// of (u, v) in 0 to 1 giving the position and normal. The cross product of
+/// the derivatives along u and v must point out of the surface.
+fn grid(
+    columns: usize,
+    rows: usize,
+    surface: impl Fn(f32, f32) -> (glm::Vec3, glm::Vec3),
+) -> (Vec<glm::Vec3>, Vec<glm::Vec3>, Vec<glm::Vec2>, Vec<u32>) {
+    let (mut vertices, mut normals, mut uvs) = (Vec::new(), Vec::new(), Vec::new());
+    for j in 0..=rows {
+        for i in 0..=columns {
+            let uv = glm::vec2(i as f32 / columns as f32, j as f32 / rows as f32);
+            let (position, normal) = surface(uv.x, uv.y);
+            vertices.push(position);
+            normals.push(normal);
+            uvs.push(uv);
+        }
+    }
+    let mut indices = Vec::with_capacity(columns * rows * 6);
+    for j in 0..rows {
+        for i in 0..columns {
+            let a = (j * (columns + 1) + i) as u32;
+            let (b, c) = (a + 1, a + columns as u32 + 1);
+            indices.extend_from_slice(&[a, b, c + 1, a, c + 1, c]);
+        }
+    }
+    (vertices, normals, uvs, indices)
+}
+
+/// Disc at height `y` closing the end of a cylinder or cone, facing up or
+/// down, appended to the vertices
+fn cap(
+    radius: f32,
+    y: f32,
+    up: bool,
+    segments: usize,
+    (vertices, normals, uvs, indices): &mut (Vec<glm::Vec3>, Vec<glm::Vec3>, Vec<glm::Vec2>, Vec<u32>),
+) {
+    let normal = glm::vec3(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+    let center = vertices.len() as u32;
+    vertices.push(glm::vec3(0.0, y, 0.0));
+    normals.push(normal);
+    uvs.push(glm::vec2(0.5, 0.5));
+    for i in 0..=segments {
+        let angle = TAU * i as f32 / segments as f32;
+        let (sin, cos) = angle.sin_cos();
+        vertices.push(glm::vec3(radius * cos, y, -radius * sin));
+        normals.push(normal);
+        // Mirrored on the bottom, so the texture isn't flipped seen from below
+        uvs.push(glm::vec2(0.5 + 0.5 * cos, 0.5 + 0.5 * if up { -sin } else { sin }));
+    }
+    for i in 0..segments as u32 {
+        let (a, b) = (center + 1 + i, center + 2 + i);
+        if up {
+            indices.extend_from_slice(&[center, a, b]);
+        } else {
+            indices.extend_from_slice(&[center, b, a]);
+        }
+    }
+}
+
+impl Mesh {
+    /// Sphere of `segments` around and `rings` from pole to pole
+    pub fn uv_sphere(radius: f32, segments: usize, rings: usize, color: glm::Vec4) -> Self {
+        let (vertices, normals, uvs, indices) = grid(segments.max(3), rings.max(2), |u, v| {
+            let (longitude, latitude) = (TAU * u, PI * (v - 0.5));
+            let normal = glm::vec3(
+                latitude.cos() * longitude.cos(),
+                latitude.sin(),
+                -latitude.cos() * longitude.sin(),
+            );
+            (normal * radius, normal)
+        });
+        assemble(vertices, normals, uvs, indices, color)
+    }
+
+    /// Sphere from an icosahedron with each triangle split in four
+    /// `subdivisions` times, so triangles are close to the same size
+    /// everywhere. Texture coordinates are mapped like on `uv_sphere`.
+    pub fn icosphere(radius: f32, subdivisions: usize, color: glm::Vec4) -> Self {
+        let t = (1.0 + 5f32.sqrt()) / 2.0;
+        let mut directions: Vec<glm::Vec3> = [
+            (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+            (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+            (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|&(x, y, z)| glm::normalize(&glm::vec3(x, y, z)))
+        .collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            // Edges are shared, so each midpoint is made once
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32, directions: &mut Vec<glm::Vec3>| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    let m = glm::normalize(&(directions[a as usize] + directions[b as usize]));
+                    directions.push(m);
+                    directions.len() as u32 - 1
+                })
+            };
+            triangles = triangles
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let ab = midpoint(a, b, &mut directions);
+                    let bc = midpoint(b, c, &mut directions);
+                    let ca = midpoint(c, a, &mut directions);
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let mut uvs: Vec<glm::Vec2> = directions
+            .iter()
+            .map(|d| {
+                let u = (-d.z).atan2(d.x) / TAU;
+                glm::vec2(u.rem_euclid(1.0), d.y.clamp(-1.0, 1.0).asin() / PI + 0.5)
+            })
+            .collect();
+        // Triangles across the seam at u = 0 get copies of their vertices on
+        // the low side, moved past 1
+        let mut wrapped: HashMap<u32, u32> = HashMap::new();
+        for triangle in triangles.iter_mut() {
+            let u = triangle.map(|i| uvs[i as usize].x);
+            if u.iter().cloned().fold(0.0, f32::max) - u.iter().cloned().fold(1.0, f32::min) < 0.5 {
+                continue;
+            }
+            for i in triangle.iter_mut() {
+                if uvs[*i as usize].x < 0.5 {
+                    *i = *wrapped.entry(*i).or_insert_with(|| {
+                        directions.push(directions[*i as usize]);
+                        uvs.push(uvs[*i as usize] + glm::vec2(1.0, 0.0));
+                        directions.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        let vertices = directions.iter().map(|d| d * radius).collect();
+        let indices = triangles.concat();
+        assemble(vertices, directions, uvs, indices, color)
+    }
+
+    /// Ring around y, `major_radius` to the middle of the tube. `segments`
+    /// around the ring and `sides` around the tube.
+    pub fn torus(
+        major_radius: f32,
+        minor_radius: f32,
+        segments: usize,
+        sides: usize,
+        color: glm::Vec4,
+    ) -> Self {
+        let (vertices, normals, uvs, indices) = grid(segments.max(3), sides.max(3), |u, v| {
+            let (around, tube) = (TAU * u, TAU * v);
+            let direction = glm::vec3(around.cos(), 0.0, -around.sin());
+            let normal = direction * tube.cos() + glm::vec3(0.0, tube.sin(), 0.0);
+            (direction * major_radius + normal * minor_radius, normal)
+        });
+        assemble(vertices, normals, uvs, indices, color)
+    }
+
+    /// Cylinder along y, closed at both ends
+    pub fn cylinder(radius: f32, height: f32, segments: usize, color: glm::Vec4) -> Self {
+        Self::frustum(radius, radius, height, segments, color)
+    }
+
+    /// Cone along y with the tip up, closed at the bottom
+    pub fn cone(radius: f32, height: f32, segments: usize, color: glm::Vec4) -> Self {
+        Self::frustum(radius, 0.0, height, segments, color)
+    }
+
+    /// Cone cut off at `top_radius`, closed at the ends that aren't a point
+    pub fn frustum(
+        bottom_radius: f32,
+        top_radius: f32,
+        height: f32,
+        segments: usize,
+        color: glm::Vec4,
+    ) -> Self {
+        let segments = segments.max(3);
+        // Normals lean up as the side narrows
+        let slope = glm::vec2(height, bottom_radius - top_radius).normalize();
+        let mut parts = grid(segments, 1, |u, v| {
+            let around = TAU * u;
+            let direction = glm::vec3(around.cos(), 0.0, -around.sin());
+            let radius = bottom_radius + (top_radius - bottom_radius) * v;
+            let position = direction * radius + glm::vec3(0.0, height * (v - 0.5), 0.0);
+            (position, direction * slope.x + glm::vec3(0.0, slope.y, 0.0))
+        });
+        if bottom_radius > 0.0 {
+            cap(bottom_radius, -height / 2.0, false, segments, &mut parts);
+        }
+        if top_radius > 0.0 {
+            cap(top_radius, height / 2.0, true, segments, &mut parts);
+        }
+        let (vertices, normals, uvs, indices) = parts;
+        assemble(vertices, normals, uvs, indices, color)
+    }
+
+    /// Flat rectangle in the xz plane facing up, split into `subdivisions`
+    /// quads along each side, with the texture once across it
+    pub fn plane(size: glm::Vec2, subdivisions: usize, color: glm::Vec4) -> Self {
+        let (vertices, normals, uvs, indices) = grid(subdivisions.max(1), subdivisions.max(1), |u, v| {
+            let position = glm::vec3(size.x * (u - 0.5), 0.0, size.y * (0.5 - v));
+            (position, glm::vec3(0.0, 1.0, 0.0))
+        });
+        assemble(vertices, normals, uvs, indices, color)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: glm::Vec4 = glm::Vec4::new(1.0, 1.0, 1.0, 1.0);
+
+    /// Check every vertex has unit normals and every triangle faces the way
+    /// the normals of its corners do, returning the vertex and index counts
+    fn counts(mesh: &Mesh) -> (usize, usize) {
+        let n = mesh.vertices.len() / 3;
+        assert_eq!(mesh.normals.len(), n * 3);
+        assert_eq!(mesh.texture_coordinates.len(), n * 2);
+        assert_eq!(mesh.tangents.len(), n * 4);
+        assert_eq!(mesh.index_count as usize, mesh.indices.len());
+        let at = |values: &[f32], i: u32| glm::make_vec3(&values[i as usize * 3..i as usize * 3 + 3]);
+        for i in 0..n as u32 {
+            let length = glm::length(&at(&mesh.normals, i));
+            assert!((length - 1.0).abs() < 1e-5, "normal {} is {} long", i, length);
+        }
+        for t in mesh.indices.chunks(3) {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|i| at(&mesh.vertices, i));
+            let normal = [t[0], t[1], t[2]].iter().map(|&i| at(&mesh.normals, i)).sum::<glm::Vec3>();
+            assert!(glm::dot(&glm::cross(&(b - a), &(c - a)), &normal) > 0.0, "triangle {:?} wound inwards", t);
+        }
+        (n, mesh.indices.len())
+    }
+
+    #[test]
+    fn uv_sphere() {
+        let mesh = Mesh::uv_sphere(2.0, 16, 8, WHITE);
+        // Triangles at the poles have no area
+        assert_eq!(counts(&mesh), (17 * 9, 6 * 16 * 7));
+        for (v, n) in mesh.vertices.chunks(3).zip(mesh.normals.chunks(3)) {
+            assert!((glm::make_vec3(v) - glm::make_vec3(n) * 2.0).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn icosphere() {
+        for subdivisions in 0..4 {
+            let (vertices, indices) = counts(&Mesh::icosphere(1.0, subdivisions, WHITE));
+            let triangles = 20 * 4usize.pow(subdivisions as u32);
+            assert_eq!(indices, triangles * 3);
+            // Some are copied for the texture seam
+            assert!(vertices >= triangles / 2 + 2);
+        }
+    }
+
+    #[test]
+    fn torus() {
+        assert_eq!(counts(&Mesh::torus(2.0, 0.5, 24, 12, WHITE)), (25 * 13, 6 * 24 * 12));
+    }
+
+    #[test]
+    fn cylinder_and_cone() {
+        // Side and two caps
+        assert_eq!(counts(&Mesh::cylinder(1.0, 2.0, 12, WHITE)), (2 * 13 + 2 * 14, 6 * 12 + 2 * 3 * 12));
+        // Triangles at the tip have no area, and there is no top cap
+        assert_eq!(counts(&Mesh::cone(1.0, 2.0, 12, WHITE)), (2 * 13 + 14, 3 * 12 + 3 * 12));
+    }
+
+    #[test]
+    fn plane() {
+        assert_eq!(counts(&Mesh::plane(glm::vec2(4.0, 2.0), 5, WHITE)), (6 * 6, 6 * 5 * 5));
+        // At least one quad
+        assert_eq!(counts(&Mesh::plane(glm::vec2(1.0, 1.0), 0, WHITE)), (4, 6));
+    }
+}
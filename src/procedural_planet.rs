@@ -1,13 +1,44 @@
+use crate::archetype::Archetype;
 use crate::scene_graph::{self, SceneNodeType};
-use crate::{mesh, shader::Shader};
+use crate::surface_features::{self, Features};
+use crate::shader::{self, Shader};
+use crate::{color, erosion, jobs, mesh, profile_scope, terrain_compute, terrain_tessellation};
 use nalgebra_glm as glm;
+use std::cell::Cell;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::globals::*;
+use crate::memory_budget::MemoryBudget;
+use crate::terrain_uploads::TerrainUploads;
 use crate::util;
 
-pub static PLANET_COUNTER: AtomicU64 = AtomicU64::new(0);
+thread_local! {
+    // Next planet id, the index of the planet in the scene being built
+    static PLANET_COUNTER: Cell<usize> = const { Cell::new(0) };
+}
 pub static IN_FLIGHT: AtomicU64 = AtomicU64::new(0);
+pub static CULLED_PATCHES: AtomicU64 = AtomicU64::new(0); // Reset every frame
+
+/// Number the planets created from now on from 0, at the start of building
+/// each scene, so planet ids index the scene's vectors
+pub fn reset_ids() {
+    PLANET_COUNTER.with(|counter| counter.set(0));
+}
+
+/// Counts a terrain job in `IN_FLIGHT` until dropped, which also happens when
+/// the job panics
+struct InFlight;
+impl InFlight {
+    fn new() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+}
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 /// Procedurally generated planet. Will use a quad-tree form, each side
 /// either drawing a plane or subdividing into nodes covering recursively
@@ -21,7 +52,7 @@ pub static IN_FLIGHT: AtomicU64 = AtomicU64::new(0);
 /// to render, connecting with the scene graph
 /// - Sun: Set low maximum LoD for terrain or completely avoid generating
 /// terrain, set high emission and get ocean as sun surface
-/// ```
+/// ```ignore
 /// let planet0 = Planet::new(600.0)    // radius
 ///     .position(glm::vec3(0.0, 0.0, 0.0))
 ///     .height(1.0)
@@ -66,6 +97,10 @@ pub struct Planet {
     pub trajectory: f32,            // Radius of trajectory
     pub traj_speed: f32,            // Angle speed of trajectory
     pub traj_init_angle: glm::Vec3, // Inital trajectory position
+    pub traj_offset: Option<glm::Vec3>, // Last computed position relative to parent, None until first
+    pub mass: Option<f32>,          // For n-body motion, from the orbits around it if None, see nbody.rs
+    pub velocity: Option<glm::Vec3>, // Relative to the parent when n-body motion starts, circular if None
+    pub orbit_color: Option<glm::Vec3>, // Of the orbit path, that of the body if None, see orbit_paths.rs
     pub rot_speed: f32,             // Angle speed of rotaion
     pub rot_axis: glm::Vec3,        // Axis around which the planet rotates
     pub rot_init_angle: f32,        // Initial rotation
@@ -75,17 +110,39 @@ pub struct Planet {
     pub emission: glm::TVec3<f32>,   // Emission colour and intensity
     pub reflection: glm::TVec3<f32>, // Reflection colour and intensity
     // Terrain
+    pub archetype: Archetype, // Preset the planet was set up from, see archetype.rs
     pub has_terrain: bool,
     pub max_height: f32,
     pub color_scheme: [glm::TVec3<f32>; N_LAYERS],
     pub color_thresholds: [f32; N_LAYERS - 1],
     pub color_blending: f32,
     pub max_lod: usize,
+    pub lod_bias: f32, // Scales distances where more detail is used
+    pub detail_strength: f32, // Bumpiness of the detail normal map, 0 for none
+    pub detail_scale: f32,    // Repeats of the detail normal map across a side
+    pub texture_strength: f32, // Contrast of the rock, grass and snow patterns, 0 for none
+    pub texture_scale: [f32; 3], // Repeats of the rock, grass and snow patterns across the planet
+    pub bands: f32, // Bands of colour by latitude, making it a gas giant without terrain, 0 for none
+    pub oblateness: f32, // Flattening at the poles, 0 for a sphere
+    pub glow: f32,  // Brightness of the bottom colour layer glowing in the dark, as lava, 0 for none
+    pub surface_features: bool, // Craters, volcanoes and canyons, placed by `stamp_features`
+    pub features: Features,
+    pub city_density: f32, // Cities lit at night, relative to CITY_CLUSTERS, 0 for none
+    pub city_lights: u32,  // Texture baked by city_lights::bake, 0 for none
+    pub vegetation: f32,   // Grass and trees scattered near the camera, relative to VEGETATION_PER_CELL, 0 for none
     // Ocean colours
     pub has_ocean: bool, // Set true to include ocean
     pub ocean_lvl: f32,  // offset from radius
     pub ocean_dark_color: glm::TVec3<f32>,
     pub ocean_light_color: glm::TVec3<f32>,
+    // Clouds
+    pub has_clouds: bool,
+    pub cloud_height: f32,   // Above the radius, relative to it
+    pub cloud_coverage: f32, // Share of the sky covered, 0 to 1
+    pub cloud_density: f32,  // Opacity of the thickest clouds
+    pub cloud_size: f32,     // Noise frequency, higher for smaller clouds
+    pub cloud_wind: f32,     // Drift around the planet's y axis, radians per second
+    pub cloud_color: glm::TVec3<f32>,
 
     pub noise_fn: u32,
     pub seed: u32,
@@ -101,7 +158,7 @@ impl Planet {
         Self::with_seed(seed)
     }
     pub fn with_seed(seed: u32) -> Self {
-        let planet_id = PLANET_COUNTER.fetch_add(1, Ordering::Relaxed) as usize;
+        let planet_id = PLANET_COUNTER.with(|counter| counter.replace(counter.get() + 1));
         Planet {
             node: std::usize::MAX,
             radius: 1.0,
@@ -112,10 +169,23 @@ impl Planet {
             emission: glm::vec3(1.0, 1.0, 0.0),
             lightsource: false,
             max_lod: MAX_LOD,
+            lod_bias: 1.0,
+            detail_strength: 0.5,
+            detail_scale: 64.0,
+            texture_strength: 0.3,
+            texture_scale: [48.0, 160.0, 24.0],
+            surface_features: true,
             has_ocean: true,
             ocean_lvl: 0.0,
             ocean_dark_color: glm::vec3(0.01, 0.2, 0.3),
             ocean_light_color: glm::vec3(0.04, 0.3, 0.43),
+            has_clouds: false,
+            cloud_height: 0.03,
+            cloud_coverage: 0.45,
+            cloud_density: 0.85,
+            cloud_size: 4.0,
+            cloud_wind: 0.01,
+            cloud_color: glm::vec3(1.0, 1.0, 1.0),
             noise_fn: 0,
             perlin: noise::Perlin::new().set_seed(seed),
             seed,
@@ -124,8 +194,40 @@ impl Planet {
         }
     }
 
-    /// Update uniforms for planet in shader
-    pub unsafe fn update_uniforms(&self, sh: &Shader) {
+    /// Place craters, volcanoes and canyons from the seed, once the planet
+    /// is set up: airless bodies, without an ocean or clouds, get craters
+    pub fn stamp_features(&mut self) {
+        self.features = if self.surface_features && !self.lightsource && !self.is_gas_giant() {
+            surface_features::generate(self.seed, !self.has_ocean && !self.has_clouds)
+        } else {
+            Features::default()
+        };
+    }
+
+    /// Whether drawn as a gas giant, in bands of colour without any terrain
+    pub fn is_gas_giant(&self) -> bool {
+        self.bands > 0.0
+    }
+
+    /// Scale of the planet's shells, flattened at the poles by `oblateness`
+    pub fn flattening(&self) -> glm::Vec3 {
+        glm::vec3(1.0, 1.0 - self.oblateness.clamp(0.0, 0.9), 1.0)
+    }
+
+    /// Direction on the sphere before flattening that ends up in `direction`,
+    /// and how far out the flattened unit sphere is in `direction`
+    fn unflatten(&self, direction: &glm::Vec3) -> (glm::Vec3, f32) {
+        let squashed = glm::normalize(direction).component_div(&self.flattening());
+        let length = glm::length(&squashed);
+        (squashed / length, 1.0 / length)
+    }
+
+    /// Update uniforms for planet in shader, with the position relative to
+    /// `origin` like the rest of the scene
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn update_uniforms(&self, sh: &Shader, origin: &glm::Vec3) {
         gl::Uniform1ui(
             sh.get_uniform_location(&format!("u_planets[{}].planet_id", self.planet_id)),
             self.planet_id as u32,
@@ -133,7 +235,7 @@ impl Planet {
         gl::Uniform3fv(
             sh.get_uniform_location(&format!("u_planets[{}].position", self.planet_id)),
             1,
-            self.position.as_ptr(),
+            (self.position - origin).as_ptr(),
         ); // u_planets[id].position
         gl::Uniform1f(
             sh.get_uniform_location(&format!("u_planets[{}].radius", self.planet_id)),
@@ -147,12 +249,12 @@ impl Planet {
         gl::Uniform3fv(
             sh.get_uniform_location(&format!("u_planets[{}].emission", self.planet_id)),
             1,
-            self.emission.as_ptr(),
+            color::linear(&self.emission).as_ptr(),
         ); // u_planets[id].emission
         gl::Uniform3fv(
             sh.get_uniform_location(&format!("u_planets[{}].reflection", self.planet_id)),
             1,
-            self.emission.as_ptr(),
+            color::linear(&self.emission).as_ptr(),
         ); // u_planets[id].reflection
            //-Terrain-------------------------------------------------------------/
         gl::Uniform3fv(
@@ -161,7 +263,7 @@ impl Planet {
                 self.planet_id, 0
             )),
             1,
-            self.color_scheme[0].as_ptr(),
+            color::linear(&self.color_scheme[0]).as_ptr(),
         ); // u_planets[id].color_scheme[]
         for i in 0..N_LAYERS - 1 {
             gl::Uniform3fv(
@@ -171,7 +273,7 @@ impl Planet {
                     i + 1
                 )),
                 1,
-                self.color_scheme[i + 1].as_ptr(),
+                color::linear(&self.color_scheme[i + 1]).as_ptr(),
             ); // u_planets[id].color_scheme[1..N_LAYERS]
             gl::Uniform1f(
                 sh.get_uniform_location(&format!(
@@ -185,24 +287,114 @@ impl Planet {
             sh.get_uniform_location(&format!("u_planets[{}].color_blending", self.planet_id)),
             self.color_blending,
         ); // u_planets[id].color_blending
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].detail_strength", self.planet_id)),
+            self.detail_strength,
+        ); // u_planets[id].detail_strength
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].detail_scale", self.planet_id)),
+            self.detail_scale,
+        ); // u_planets[id].detail_scale
+        gl::Uniform1ui(
+            sh.get_uniform_location(&format!("u_planets[{}].max_lod", self.planet_id)),
+            self.max_lod as u32,
+        ); // u_planets[id].max_lod
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].texture_strength", self.planet_id)),
+            self.texture_strength,
+        ); // u_planets[id].texture_strength
+        gl::Uniform3fv(
+            sh.get_uniform_location(&format!("u_planets[{}].texture_scale", self.planet_id)),
+            1,
+            self.texture_scale.as_ptr(),
+        ); // u_planets[id].texture_scale
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].bands", self.planet_id)),
+            self.bands,
+        ); // u_planets[id].bands
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].glow", self.planet_id)),
+            self.glow,
+        ); // u_planets[id].glow
            //-Ocean---------------------------------------------------------------/
         gl::Uniform3fv(
             sh.get_uniform_location(&format!("u_planets[{}].ocean_dark_color", self.planet_id)),
             1,
-            self.ocean_dark_color.as_ptr(),
+            color::linear(&self.ocean_dark_color).as_ptr(),
         ); // u_planets[id].ocean_dark_color
         gl::Uniform3fv(
             sh.get_uniform_location(&format!("u_planets[{}].ocean_dark_color", self.planet_id)),
             1,
-            self.ocean_light_color.as_ptr(),
+            color::linear(&self.ocean_light_color).as_ptr(),
         ); // u_planets[id].ocean_light_color
+           //-Clouds--------------------------------------------------------------/
+        gl::Uniform3fv(
+            sh.get_uniform_location(&format!("u_planets[{}].cloud_color", self.planet_id)),
+            1,
+            color::linear(&self.cloud_color).as_ptr(),
+        ); // u_planets[id].cloud_color
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].cloud_coverage", self.planet_id)),
+            self.cloud_coverage,
+        ); // u_planets[id].cloud_coverage
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].cloud_density", self.planet_id)),
+            self.cloud_density,
+        ); // u_planets[id].cloud_density
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].cloud_size", self.planet_id)),
+            self.cloud_size,
+        ); // u_planets[id].cloud_size
+        gl::Uniform1f(
+            sh.get_uniform_location(&format!("u_planets[{}].cloud_wind", self.planet_id)),
+            self.cloud_wind,
+        ); // u_planets[id].cloud_wind
+        shader::arrays_changed(sh);
     }
+    /// Update the terrain noise in shaders including terrain.glsl, with the
+    /// permutation table bound separately
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn update_terrain_uniforms(&self, sh: &Shader) {
+        let noise = self.noise;
+        // Gas giants are smooth
+        let max_height = if self.is_gas_giant() { 0.0 } else { self.max_height };
+        gl::Uniform1f(sh.get_uniform_location("u_max_height"), max_height);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.size"), noise.size);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.amplitude"), noise.amplitude);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.frequency"), noise.frequency);
+        gl::Uniform1i(sh.get_uniform_location("u_noise.octaves"), noise.octaves as i32);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.gain"), noise.gain);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.gain_frequency"), noise.gain_frequency);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.gain_amplitude"), noise.gain_amplitude);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.gain_offset"), noise.gain_offset);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.lacunarity"), noise.lacunarity);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.lac_frequency"), noise.lac_frequency);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.lac_amplitude"), noise.lac_amplitude);
+        gl::Uniform1f(sh.get_uniform_location("u_noise.lac_offset"), noise.lac_offset);
+        let features = surface_features::pack(self.features.as_slice());
+        gl::Uniform1ui(
+            sh.get_uniform_location("u_features_len"),
+            self.features.as_slice().len() as u32,
+        );
+        if !features.is_empty() {
+            gl::Uniform4fv(sh.get_uniform_location("u_features"), features.len() as i32 / 4, features.as_ptr());
+        }
+    }
+
     /// Set level of detail to be drawn, generate new if needed
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context, and the children of `node` must be live.
     pub unsafe fn lod(
         &mut self,
         node: &mut scene_graph::SceneNode,
         player_position: glm::TVec3<f32>,
+        budget: &mut MemoryBudget,
+        uploads: &mut TerrainUploads,
     ) {
+        profile_scope!("lod");
         self.parts = 0;
         self.position = glm::vec4_to_vec3(
             &(node.current_transformation_matrix * glm::vec4(0.0, 0.0, 0.0, 1.0)),
@@ -227,24 +419,36 @@ impl Planet {
             node.add_child(&planet_root);
         }
         let planet_root = node.get_child(0);
+        planet_root.set_scale(self.flattening());
 
-        for (i, &child) in (&planet_root.children).iter().enumerate() {
-            self.lod_terrain(
-                &mut *child,
-                glm::vec3(1.0, 1.0, 1.0),
-                rotations[i],
-                glm::vec3(0.0, 1.0, 0.0), //positions[i],
-                0,
-                player_position,
-            );
+        if terrain_tessellation::enabled() {
+            // Drawn as a whole by terrain_tessellation instead
+            planet_root.node_type = SceneNodeType::PlanetSkip;
+        } else {
+            planet_root.node_type = SceneNodeType::Empty;
+            for (i, &child) in (&planet_root.children).iter().enumerate() {
+                self.lod_terrain(
+                    &mut *child,
+                    glm::vec3(1.0, 1.0, 1.0),
+                    rotations[i],
+                    glm::vec3(0.0, 1.0, 0.0), //positions[i],
+                    0,
+                    player_position,
+                    budget,
+                    uploads,
+                );
+            }
         }
 
+        self.lod_clouds(node, &rotations);
+
         if !self.has_ocean {
             return;
         }
         // Handle ocean
-        if node.get_n_children() < 2 {
+        if node.children.iter().all(|&c| (*c).name != "ocean") {
             let mut ocean_root = scene_graph::SceneNode::with_type(SceneNodeType::Empty);
+            ocean_root.name = "ocean".into();
             for i in 0..6 {
                 // Generate sides if they don't exist yet
                 let ocean_mesh = mesh::Mesh::cs_plane(
@@ -255,15 +459,58 @@ impl Planet {
                     None,
                     true,
                 );
-                let mut ocean_node = scene_graph::SceneNode::from_vao(ocean_mesh.mkvao());
+                let mut ocean_node = scene_graph::SceneNode::from_vao(
+                    ocean_mesh.mkvao_with(mesh::VertexLayout::LIT.interleaved()),
+                );
                 ocean_node.node_type = SceneNodeType::Ocean;
                 ocean_node.planet_id = self.planet_id;
                 ocean_root.add_child(&ocean_node);
             }
             node.add_child(&ocean_root);
         }
+        if let Some(&ocean_root) = node.children.iter().find(|&&c| (*c).name == "ocean") {
+            (*ocean_root).set_scale(self.flattening());
+        }
+    }
+
+    /// Add the cloud shell when enabled, a sphere of its own drawn
+    /// translucent above the terrain, and follow changes to its height
+    unsafe fn lod_clouds(&self, node: &mut scene_graph::SceneNode, rotations: &[glm::TVec3<f32>; 6]) {
+        if self.has_clouds && node.children.iter().all(|&c| (*c).name != "clouds") {
+            let mut cloud_root = scene_graph::SceneNode::with_type(SceneNodeType::Empty);
+            cloud_root.name = "clouds".into();
+            for rotation in rotations {
+                let cloud_mesh = mesh::Mesh::cs_plane(
+                    glm::vec3(1.0, 1.0, 1.0),
+                    *rotation,
+                    glm::vec3(0.0, 1.0, 0.0),
+                    32,
+                    None,
+                    true,
+                );
+                let mut cloud_node = scene_graph::SceneNode::from_vao(
+                    cloud_mesh.mkvao_with(mesh::VertexLayout::LIT.interleaved()),
+                );
+                cloud_node.node_type = SceneNodeType::Cloud;
+                cloud_node.planet_id = self.planet_id;
+                cloud_root.add_child(&cloud_node);
+            }
+            node.add_child(&cloud_root);
+        }
+        if let Some(&cloud_root) = node.children.iter().find(|&&c| (*c).name == "clouds") {
+            // Skipped rather than removed, so they can be turned on again
+            (*cloud_root).node_type = if self.has_clouds {
+                SceneNodeType::Empty
+            } else {
+                SceneNodeType::PlanetSkip
+            };
+            (*cloud_root).set_scale(self.flattening() * (1.0 + self.cloud_height));
+        }
     }
 
+    /// # Safety
+    /// Needs a current OpenGL context, and the children of `node` must be live.
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn lod_terrain(
         &self,
         node: &mut scene_graph::SceneNode, // Either gets the mesh (leaf) or becomes a parent to four subdivisions
@@ -272,6 +519,8 @@ impl Planet {
         position: glm::TVec3<f32>,         // 2D position. Modify x and z components
         level: usize,
         player_position: glm::TVec3<f32>,
+        budget: &mut MemoryBudget,
+        uploads: &mut TerrainUploads,
     ) -> bool {
         let displacements: [glm::TVec3<f32>; 4] = [
             glm::vec3(1.0, 0.0, 1.0),
@@ -292,12 +541,22 @@ impl Planet {
         // cos of angle between player position and plane center
         let dot = glm::dot(&plane_normal, &glm::normalize(&player_normal));
         let angle = dot.acos();
-        let angle_lim = (std::f32::consts::FRAC_PI_2 * 1.6) / ((level as f32) + 1.0).powf(1.4);
+        let angle_lim =
+            self.lod_bias * (std::f32::consts::FRAC_PI_2 * 1.6) / ((level as f32) + 1.0).powf(1.4);
 
         // Use height to limit LoD when planet is further away
         let player_height = glm::length(&(player_position - self.position));
+
+        // Horizon culling: skip patches entirely on the far side of the
+        // planet, which covers those facing away as well
+        if self.beyond_horizon(angle, level, player_height) {
+            node.node_type = SceneNodeType::PlanetSkip;
+            CULLED_PATCHES.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+
         let height_lim = self.radius * (1.0 + self.max_height)
-            + (self.radius * 2.6) / ((level as f32) + 1.0).powf(1.5);
+            + self.lod_bias * (self.radius * 2.6) / ((level as f32) + 1.0).powf(1.5);
 
         if angle < angle_lim && player_height < height_lim && level < self.max_lod {
             // Generate next level
@@ -305,6 +564,7 @@ impl Planet {
                 for i in 0..4 {
                     node.add_child(&scene_graph::SceneNode::with_type(SceneNodeType::Planet));
                     node.get_child(i).planet_id = self.planet_id;
+                    node.get_child(i).lod_level = level + 1;
                 }
             }
             node.node_type = SceneNodeType::Empty;
@@ -317,6 +577,8 @@ impl Planet {
                     position + (displacements[i] * scale.x) / 2.0,
                     level + 1,
                     player_position,
+                    budget,
+                    uploads,
                 );
             }
             if !ready {
@@ -327,6 +589,7 @@ impl Planet {
         // Use this detail level
         node.node_type = SceneNodeType::Planet;
         if node.index_count != -1 {
+            budget.touch(node.node_id);
             return true;
         }
         //---------------------------------------------------------------------/
@@ -339,42 +602,148 @@ impl Planet {
 
         return match status {
             NotStarted => {
-                // Start thread generating terrain
+                // Queue a job generating terrain, coarse patches first
                 let in_flight = IN_FLIGHT.load(Ordering::Relaxed);
                 if in_flight >= MAX_IN_FLIGHT {
                     return false;
                 }
-                IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+                let in_flight = InFlight::new();
                 let planet = *self;
+                // Only the flat grid when the GPU displaces it, eroded
+                // terrain is displaced here
+                let on_gpu = terrain_compute::enabled() && erosion::iterations() == 0;
                 *arc_vao_status.lock().unwrap() = (Generating, mesh::Mesh::default());
-                std::thread::spawn(move || {
-                    let mut planet_mesh = mesh::Mesh::cs_plane(
-                        scale,
-                        rotation,
-                        position,
-                        (1 + level) * SUBDIVS_PER_LEVEL,
-                        None,
-                        true,
-                    );
-                    planet.displace_vertices(&mut planet_mesh);
-                    *arc_vao_status.lock().unwrap() = (Ready, planet_mesh);
-                    IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
-                });
+                let priority = if level < 3 {
+                    jobs::Priority::High
+                } else {
+                    jobs::Priority::Normal
+                };
+                jobs::spawn_with_completion(
+                    priority,
+                    move || {
+                        let mut planet_mesh = mesh::Mesh::cs_plane(
+                            scale,
+                            rotation,
+                            position,
+                            (1 + level) * SUBDIVS_PER_LEVEL,
+                            None,
+                            true,
+                        );
+                        if on_gpu {
+                            planet_mesh.compute_tangents();
+                        } else {
+                            planet.displace_vertices(&mut planet_mesh);
+                        }
+                        planet_mesh.optimize_vertex_cache();
+                        planet_mesh.optimize_vertex_fetch();
+                        (planet_mesh, in_flight)
+                    },
+                    move |(planet_mesh, _in_flight)| {
+                        let status = if on_gpu { ReadyUndisplaced } else { Ready };
+                        *arc_vao_status.lock().unwrap() = (status, planet_mesh);
+                    },
+                );
                 false
             }
-            Ready => {
-                // Finish creating scene node
-                let vao = arc_vao_status.lock().unwrap().1.mkvao();
-                node.update_vao(vao);
-                true
+            Ready | ReadyUndisplaced => {
+                // Uploaded by `uploads.flush`, those up close first
+                let subdivisions = (1 + level) * SUBDIVS_PER_LEVEL;
+                let spacing = scale.x * 2.0 * self.radius / subdivisions as f32;
+                let surface = planet_center + plane_normal * self.radius;
+                let distance = glm::distance(&surface, &player_position).max(1e-6);
+                uploads.push(node, self, level, scale.x, spacing / distance);
+                false
             }
             Generating => {
-                // Just return while thread is still working
+                // Just return while the job is still working
                 false
             }
         };
     }
 
+    /// Free generated terrain, so it's generated again with new parameters.
+    /// Patches still being generated finish with the old parameters.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn regenerate(&self, node: &mut scene_graph::SceneNode, budget: &mut MemoryBudget) {
+        unsafe fn free(node: &mut scene_graph::SceneNode, budget: &mut MemoryBudget) {
+            node.free_buffers();
+            budget.remove(node.node_id);
+            for &child in &node.children {
+                free(&mut *child, budget);
+            }
+        }
+        // First child is the terrain, leave the ocean be
+        if node.get_n_children() > 0 {
+            free(node.get_child(0), budget);
+        }
+        if let Some(body) = node.body.as_mut() {
+            body.surface = None;
+        }
+    }
+
+    /// Test if a patch is hidden behind the horizon as seen from the player.
+    /// This also culls patches facing away: a normal cone around the patch
+    /// center would have to be widened by the steepest slope of the terrain,
+    /// and as soon as that's over `acos(r_min / r_max)`, the allowance made
+    /// here for mountains, the cone culls no patch this doesn't already.
+    /// * `angle` - Angle between patch center and player, seen from planet center
+    /// * `level` - Patch LoD level, determines angular size of the patch
+    /// * `player_height` - Distance from player to planet center
+    fn beyond_horizon(&self, angle: f32, level: usize, player_height: f32) -> bool {
+        let r_min = self.radius * (1.0 - self.max_height);
+        let r_max = self.radius * (1.0 + self.max_height);
+        if player_height <= r_min {
+            // Below the lowest terrain, nothing to hide behind
+            return false;
+        }
+        // Angle from the cube face center to a corner is acos(1/sqrt(3))
+        let patch_angle = 0.9553 / (1 << level) as f32;
+        // Horizon of the lowest terrain, a sphere nothing sees through,
+        // extended by how far the highest terrain can peek over it. Holds
+        // among the mountains as well, as long as the eye is above it.
+        let horizon = (r_min / player_height).acos() + (r_min / r_max).acos();
+        angle - patch_angle > horizon + HORIZON_MARGIN
+    }
+
+    /// Radius with the mountains and clouds
+    pub fn reach(&self) -> f32 {
+        self.radius * (1.0 + self.max_height.max(self.cloud_height))
+    }
+
+    /// Height of the terrain, or the ocean above it, in radii
+    pub fn surface_height(&self, direction: &glm::Vec3) -> f32 {
+        let (direction, stretch) = self.unflatten(direction);
+        let terrain = 1.0 + self.noise(&direction);
+        if self.has_ocean {
+            stretch * terrain.max(1.0 + self.ocean_lvl)
+        } else {
+            stretch * terrain
+        }
+    }
+
+    /// Share of the surface under the ocean, then in each colour layer above
+    /// it, from the heights at `samples` points spread evenly over the sphere
+    pub fn surface_shares(&self, samples: usize) -> [f32; N_LAYERS + 1] {
+        let mut shares = [0.0; N_LAYERS + 1];
+        let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+        for i in 0..samples {
+            // Fibonacci sphere
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / samples as f32;
+            let r = (1.0 - y * y).sqrt();
+            let angle = golden_angle * i as f32;
+            let h = self.noise(&glm::vec3(r * angle.cos(), y, r * angle.sin()));
+            let layer = if self.has_ocean && h < self.ocean_lvl {
+                0
+            } else {
+                1 + self.color_thresholds.iter().take_while(|&&threshold| h >= threshold).count()
+            };
+            shares[layer] += 1.0 / samples as f32;
+        }
+        shares
+    }
+
     pub fn get_height(&self, pos: &glm::TVec3<f32>) -> f32 {
         let pos = glm::vec3(
             (100.0 * pos.x).round() / 100.0,
@@ -392,11 +761,24 @@ impl Planet {
         //         self.noise_size.into(), self.max_height, 0.0
         //     )
         // )
-        self.radius * (1.0 + self.noise(&glm::normalize(&(pos - &position))))
+        let (direction, stretch) = self.unflatten(&(pos - &position));
+        self.radius * stretch * (1.0 + self.noise(&direction))
+    }
+
+    /// Fractal noise of the planet's generator in `direction`, between about
+    /// -1 and 1, for masks changing `size` times per radius
+    pub fn mask(&self, direction: &glm::Vec3, size: f64) -> f32 {
+        mesh::fractal_noise(self.perlin, direction, size, 1.0, 0.0)
+    }
+
+    /// Seed of the terrain noise, for sampling it on the GPU
+    pub fn noise_seed(&self) -> u32 {
+        self.perlin.seed()
     }
 
     fn displace_vertices(&self, mesh: &mut mesh::Mesh) {
         let mut vertices = util::to_array_of_vec3(mesh.vertices.clone());
+        let mut heights = vec![0.0; vertices.len()];
         for i in 0..vertices.len() {
             // let val = 1.0 + mesh::fractal_noise(
             //     self.noise_fn,
@@ -404,26 +786,43 @@ impl Planet {
             //     self.noise_size.into(),
             //     self.max_height,
             //     0.0);
-            let val = 1.0 + self.noise(&glm::normalize(&vertices[i]));
-            vertices[i] *= val;
+            let direction = glm::normalize(&vertices[i]);
+            let (stamped, tint) = surface_features::stamp(self.features.as_slice(), &direction);
+            heights[i] = self.fractal_noise(&direction) + stamped * self.max_height;
+            // Ground tinted by the features, see planet_shader in scene.frag
+            mesh.colors[4 * i..4 * i + 3].copy_from_slice(tint.as_slice());
         }
-
-        // TODO: Solve the seams, could reuse the noise generator and use polar coordinates
-        let mut normals = util::to_array_of_vec3(mesh.normals.clone());
-        for i in (0..mesh.index_count).step_by(3) {
-            let i = i as usize;
-            let v1 = vertices[mesh.indices[i + 1] as usize] - vertices[mesh.indices[i] as usize];
-            let v2 = vertices[mesh.indices[i + 2] as usize] - vertices[mesh.indices[i] as usize];
-            let norm = glm::normalize(&glm::cross(&v1, &v2));
-            normals[mesh.indices[i] as usize] = norm;
-            normals[mesh.indices[i + 1] as usize] = norm;
-            normals[mesh.indices[i + 2] as usize] = norm;
+        // Patches are square grids of vertices, row by row
+        let res = (vertices.len() as f64).sqrt() as usize;
+        if !self.lightsource && res * res == vertices.len() && res > 1 {
+            let spacing = glm::distance(&glm::normalize(&vertices[0]), &glm::normalize(&vertices[1]));
+            // Raindrops differ between patches, but not between runs
+            let corner = vertices[0];
+            let seed = ((corner.x.to_bits() as u64) << 32 | corner.y.to_bits() as u64) ^ corner.z.to_bits() as u64;
+            let seed = seed ^ self.seed as u64;
+            let sea_level = if self.has_ocean { Some(self.ocean_lvl) } else { None };
+            erosion::erode(&mut heights, res, spacing, self.max_height, sea_level, seed);
         }
-        mesh.normals = util::from_array_of_vec3(normals);
+        for (vertex, height) in vertices.iter_mut().zip(&heights) {
+            *vertex *= 1.0 + height;
+        }
+
         mesh.vertices = util::from_array_of_vec3(vertices);
+        // TODO: Solve the seams, edge vertices only see the faces of their own patch
+        mesh.recompute_normals(None);
+        mesh.compute_tangents();
     }
 
+    /// Terrain height at `pos`, a direction from the center, in radii
     fn noise(&self, pos: &glm::Vec3) -> f32 {
+        if self.is_gas_giant() {
+            return 0.0;
+        }
+        let (stamped, _) = surface_features::stamp(self.features.as_slice(), pos);
+        self.fractal_noise(pos) + stamped * self.max_height
+    }
+
+    fn fractal_noise(&self, pos: &glm::Vec3) -> f32 {
         let params = self.noise;
         match self.noise_fn {
             _ => {
@@ -471,7 +870,8 @@ impl Planet {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct NoiseParams {
     // Initial values
     pub size: f32, // Constant multiplier on frequency
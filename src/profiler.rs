@@ -0,0 +1,297 @@
+//! Time spent in each part of the frame, on the GPU from timer queries and on
+//! the CPU from named scopes
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::globals::{PROFILER_MAX_PENDING, PROFILER_WINDOW};
+
+struct Section {
+    name: &'static str,
+    times: VecDeque<f32>, // Milliseconds in the last frames read, newest last
+}
+
+/// Add the milliseconds of a frame to each section. Sections left out of the
+/// frame count as taking no time.
+fn add_frame(sections: &mut [Section], totals: &[f32]) {
+    for (section, &ms) in sections.iter_mut().zip(totals) {
+        section.times.push_back(ms);
+        if section.times.len() > PROFILER_WINDOW {
+            section.times.pop_front();
+        }
+    }
+}
+
+/// Index of the section called `name`, added if new
+fn section_index(sections: &mut Vec<Section>, name: &'static str) -> usize {
+    match sections.iter().position(|s| s.name == name) {
+        Some(section) => section,
+        None => {
+            sections.push(Section {
+                name,
+                times: VecDeque::new(),
+            });
+            sections.len() - 1
+        }
+    }
+}
+
+fn averages(sections: &[Section]) -> Vec<(&'static str, f32)> {
+    sections
+        .iter()
+        .map(|s| (s.name, s.times.iter().sum::<f32>() / s.times.len().max(1) as f32))
+        .collect()
+}
+
+//-GPU-------------------------------------------------------------------------/
+
+#[derive(Default)]
+struct Frame {
+    number: u64,
+    queries: Vec<(usize, u32)>, // Section and query, in the order issued
+}
+
+#[derive(Default)]
+pub struct GpuProfiler {
+    pub enabled: bool,
+    sections: Vec<Section>, // In the order first seen
+    current: Option<Frame>,
+    pending: VecDeque<Frame>, // Waiting for results, oldest first
+    open: Option<(usize, u32)>,
+    free: Vec<u32>, // Queries with their results read, for reuse
+    frames: u64,
+    csv: Option<BufWriter<File>>,
+}
+
+impl GpuProfiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Also write the time of each part in every frame to `path`, one
+    /// `frame,section,ms` row per part
+    pub fn with_csv(mut self, path: &str) -> io::Result<Self> {
+        let mut csv = BufWriter::new(File::create(path)?);
+        writeln!(csv, "frame,section,ms")?;
+        self.csv = Some(csv);
+        self.enabled = true;
+        Ok(self)
+    }
+
+    /// Read the frames done on the GPU, and start timing a new one unless too
+    /// many are still in flight
+    pub unsafe fn begin_frame(&mut self) {
+        while let Some(frame) = self.pending.front() {
+            let &(_, last) = frame.queries.last().unwrap();
+            let mut available = 0;
+            gl::GetQueryObjectuiv(last, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available == 0 {
+                break;
+            }
+            let frame = self.pending.pop_front().unwrap();
+            self.read(frame);
+        }
+        self.frames += 1;
+        self.current = if self.enabled && self.pending.len() < PROFILER_MAX_PENDING {
+            Some(Frame {
+                number: self.frames,
+                ..Default::default()
+            })
+        } else {
+            None
+        };
+    }
+
+    unsafe fn read(&mut self, frame: Frame) {
+        let mut totals = vec![0.0; self.sections.len()];
+        for &(section, query) in &frame.queries {
+            let mut nanoseconds = 0;
+            gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut nanoseconds);
+            totals[section] += nanoseconds as f32 / 1e6;
+            self.free.push(query);
+        }
+        add_frame(&mut self.sections, &totals);
+        if let Some(csv) = self.csv.as_mut() {
+            for (section, &ms) in self.sections.iter().zip(&totals) {
+                let _ = writeln!(csv, "{},{},{:.4}", frame.number, section.name, ms);
+            }
+        }
+    }
+
+    /// Start timing the part `name`, ending the part before
+    pub unsafe fn begin(&mut self, name: &'static str) {
+        self.end();
+        if self.current.is_none() {
+            return;
+        }
+        let section = section_index(&mut self.sections, name);
+        let query = self.free.pop().unwrap_or_else(|| {
+            let mut query = 0;
+            gl::GenQueries(1, &mut query);
+            query
+        });
+        gl::BeginQuery(gl::TIME_ELAPSED, query);
+        self.open = Some((section, query));
+    }
+
+    /// Stop timing the current part
+    pub unsafe fn end(&mut self) {
+        if let Some((section, query)) = self.open.take() {
+            gl::EndQuery(gl::TIME_ELAPSED);
+            if let Some(frame) = self.current.as_mut() {
+                frame.queries.push((section, query));
+            }
+        }
+    }
+
+    pub unsafe fn end_frame(&mut self) {
+        self.end();
+        if let Some(frame) = self.current.take() {
+            if !frame.queries.is_empty() {
+                self.pending.push_back(frame);
+            }
+        }
+        // Readable while running, and not lost if the process is killed
+        if let Some(csv) = self.csv.as_mut() {
+            let _ = csv.flush();
+        }
+    }
+
+    /// Average milliseconds of each part, in the order first timed
+    pub fn averages(&self) -> Vec<(&'static str, f32)> {
+        averages(&self.sections)
+    }
+}
+
+//-CPU-------------------------------------------------------------------------/
+
+/// Time the rest of the enclosing block as the CPU scope `name`
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::profiler::scope($name);
+    };
+}
+
+/// Scope timed until dropped, see `profile_scope!`
+pub struct Scope {
+    name: &'static str,
+    start: Option<Instant>, // Not timed while the CPU profiler is off
+}
+
+pub fn scope(name: &'static str) -> Scope {
+    Scope {
+        name,
+        start: CPU_ENABLED.load(Ordering::Relaxed).then(Instant::now),
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            cpu().record(self.name, start, start.elapsed());
+        }
+    }
+}
+
+static CPU: OnceLock<Mutex<CpuProfiler>> = OnceLock::new();
+static CPU_ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The CPU profiler, shared by all threads. Not to be held while scopes end.
+pub fn cpu() -> MutexGuard<'static, CpuProfiler> {
+    CPU.get_or_init(|| Mutex::new(CpuProfiler::new()))
+        .lock()
+        .unwrap()
+}
+
+pub struct CpuProfiler {
+    sections: Vec<Section>, // In the order first seen
+    frame: Vec<f32>,        // Milliseconds in each section this frame
+    epoch: Instant,         // Zero of trace timestamps
+    trace: Option<BufWriter<File>>,
+    named_threads: Vec<u64>, // Already given a name in the trace
+}
+
+impl CpuProfiler {
+    fn new() -> Self {
+        CpuProfiler {
+            sections: Vec::new(),
+            frame: Vec::new(),
+            epoch: Instant::now(),
+            trace: None,
+            named_threads: Vec::new(),
+        }
+    }
+
+    /// Time scopes, always on while tracing
+    pub fn set_enabled(&self, enabled: bool) {
+        CPU_ENABLED.store(enabled || self.trace.is_some(), Ordering::Relaxed);
+    }
+
+    /// Write every scope to `path` in the chrome tracing format. The array of
+    /// events is left open, which trace viewers allow, so the file is valid
+    /// whenever the program stops.
+    pub fn trace_to(&mut self, path: &str) -> io::Result<()> {
+        let mut trace = BufWriter::new(File::create(path)?);
+        writeln!(trace, "[")?;
+        self.trace = Some(trace);
+        self.set_enabled(true);
+        Ok(())
+    }
+
+    fn record(&mut self, name: &'static str, start: Instant, duration: Duration) {
+        let section = section_index(&mut self.sections, name);
+        self.frame.resize(self.sections.len(), 0.0);
+        self.frame[section] += duration.as_secs_f32() * 1000.0;
+
+        let Some(trace) = self.trace.as_mut() else {
+            return;
+        };
+        let thread = THREAD.with(|thread| *thread);
+        if !self.named_threads.contains(&thread) {
+            self.named_threads.push(thread);
+            let current = std::thread::current();
+            let event = serde_json::json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": 0,
+                "tid": thread,
+                "args": { "name": current.name().unwrap_or("unnamed") },
+            });
+            let _ = writeln!(trace, "{},", event);
+        }
+        let event = serde_json::json!({
+            "name": name,
+            "ph": "X",
+            "ts": start.saturating_duration_since(self.epoch).as_secs_f64() * 1e6,
+            "dur": duration.as_secs_f64() * 1e6,
+            "pid": 0,
+            "tid": thread,
+        });
+        let _ = writeln!(trace, "{},", event);
+    }
+
+    /// Add the scopes ended since the last frame to the averages
+    pub fn end_frame(&mut self) {
+        self.frame.resize(self.sections.len(), 0.0);
+        add_frame(&mut self.sections, &self.frame);
+        self.frame.iter_mut().for_each(|ms| *ms = 0.0);
+        if let Some(trace) = self.trace.as_mut() {
+            let _ = trace.flush();
+        }
+    }
+
+    /// Average milliseconds of each scope per frame, in the order first timed
+    pub fn averages(&self) -> Vec<(&'static str, f32)> {
+        averages(&self.sections)
+    }
+}
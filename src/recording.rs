@@ -0,0 +1,188 @@
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+/// Frames read back before the oldest is mapped, so reading never stalls on
+/// the frame just drawn
+const PBO_COUNT: usize = 3;
+
+/// Offline recording of a frame sequence: the scene is simulated with a fixed
+/// timestep, and every frame is read back asynchronously through pixel buffer
+/// objects and written as a PNG sequence or piped to ffmpeg, by a writer
+/// thread.
+pub struct Recorder {
+    pub fps: u32,
+    size: (u32, u32),
+    pbos: [u32; PBO_COUNT],
+    frames_read: u64,    // Frames read into a PBO
+    frames_written: u64, // Frames mapped and sent to the writer
+    // Bounded, so rendering waits for the writer instead of piling up frames
+    frames: mpsc::SyncSender<Vec<u8>>,
+    writer: JoinHandle<()>,
+    ffmpeg: Option<Child>,
+}
+
+impl Recorder {
+    /// Record frames of `size` to `path`. Paths ending in a video extension
+    /// are encoded by ffmpeg, which must be on the PATH, anything else is a
+    /// directory for PNG files.
+    pub unsafe fn new(path: &str, fps: u32, size: (u32, u32)) -> Result<Self, String> {
+        let is_video = [".mp4", ".mkv", ".webm", ".mov"]
+            .iter()
+            .any(|ext| path.ends_with(ext));
+        let (frames, receiver) = mpsc::sync_channel::<Vec<u8>>(PBO_COUNT * 2);
+        let (writer, ffmpeg) = if is_video {
+            let (writer, child) = Self::spawn_ffmpeg(path, fps, size, receiver)?;
+            (writer, Some(child))
+        } else {
+            std::fs::create_dir_all(path).map_err(|e| format!("{}: {}", path, e))?;
+            (Self::spawn_png_writer(path, size, receiver)?, None)
+        };
+
+        let mut pbos = [0; PBO_COUNT];
+        gl::GenBuffers(PBO_COUNT as i32, pbos.as_mut_ptr());
+        for &pbo in &pbos {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+            gl::BufferData(
+                gl::PIXEL_PACK_BUFFER,
+                (size.0 * size.1 * 4) as isize,
+                std::ptr::null(),
+                gl::STREAM_READ,
+            );
+        }
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+
+        eprintln!("Recording {}x{} at {} fps to {}", size.0, size.1, fps, path);
+        Ok(Recorder {
+            fps,
+            size,
+            pbos,
+            frames_read: 0,
+            frames_written: 0,
+            frames,
+            writer,
+            ffmpeg,
+        })
+    }
+
+    /// Write numbered PNG files to the directory `path`
+    fn spawn_png_writer(
+        path: &str,
+        size: (u32, u32),
+        receiver: mpsc::Receiver<Vec<u8>>,
+    ) -> Result<JoinHandle<()>, String> {
+        let dir = path.to_string();
+        std::thread::Builder::new()
+            .name("png-writer".into())
+            .spawn(move || {
+                for (i, pixels) in receiver.into_iter().enumerate() {
+                    let path = format!("{}/frame_{:06}.png", dir, i);
+                    let image = image::RgbaImage::from_raw(size.0, size.1, pixels).unwrap();
+                    // OpenGL rows start at the bottom
+                    if let Err(e) = image::imageops::flip_vertical(&image).save(&path) {
+                        eprintln!("Failed to write {}: {}", path, e);
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())
+    }
+
+    /// Pipe raw frames to ffmpeg, encoding the video `path`
+    fn spawn_ffmpeg(
+        path: &str,
+        fps: u32,
+        size: (u32, u32),
+        receiver: mpsc::Receiver<Vec<u8>>,
+    ) -> Result<(JoinHandle<()>, Child), String> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{}x{}", size.0, size.1)])
+            .args(["-r", &fps.to_string(), "-i", "-"])
+            .args(["-vf", "vflip", "-pix_fmt", "yuv420p", path])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start ffmpeg: {}", e))?;
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = std::thread::Builder::new()
+            .name("ffmpeg-writer".into())
+            .spawn(move || {
+                for frame in receiver {
+                    if let Err(e) = stdin.write_all(&frame) {
+                        eprintln!("Failed to write frame to ffmpeg: {}", e);
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        Ok((writer, child))
+    }
+
+    /// Time since start and timestep for a frame, fixed by the frame rate
+    pub fn frame_time(&self, frame: u64) -> (f32, f32) {
+        let delta_time = 1.0 / self.fps as f32;
+        (frame as f32 * delta_time, delta_time)
+    }
+
+    /// Frames captured so far
+    pub fn frames(&self) -> u64 {
+        self.frames_read
+    }
+
+    /// Start reading back the frame just drawn, and write out the oldest
+    /// frame read. Call before `swap_buffers`.
+    pub unsafe fn capture(&mut self) {
+        let pbo = self.pbos[(self.frames_read % PBO_COUNT as u64) as usize];
+        if self.frames_read >= PBO_COUNT as u64 {
+            // Still holds a frame from PBO_COUNT frames ago
+            self.write_oldest();
+        }
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+        gl::ReadPixels(
+            0,
+            0,
+            self.size.0 as i32,
+            self.size.1 as i32,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null_mut(),
+        );
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        self.frames_read += 1;
+    }
+
+    unsafe fn write_oldest(&mut self) {
+        let pbo = self.pbos[(self.frames_written % PBO_COUNT as u64) as usize];
+        let len = (self.size.0 * self.size.1 * 4) as usize;
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+        let ptr = gl::MapBuffer(gl::PIXEL_PACK_BUFFER, gl::READ_ONLY) as *const u8;
+        let pixels = if ptr.is_null() {
+            eprintln!("Failed to map frame {} for recording", self.frames_written);
+            vec![0; len]
+        } else {
+            std::slice::from_raw_parts(ptr, len).to_vec()
+        };
+        gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+
+        // Fails only if the writer stopped, which reports why
+        let _ = self.frames.send(pixels);
+        self.frames_written += 1;
+    }
+
+    /// Write the frames still being read back, and wait for the sink to
+    /// finish writing everything
+    pub unsafe fn finish(mut self) {
+        while self.frames_written < self.frames_read {
+            self.write_oldest();
+        }
+        gl::DeleteBuffers(PBO_COUNT as i32, self.pbos.as_ptr());
+        drop(self.frames); // Ends the writer once it's done
+        let _ = self.writer.join();
+        if let Some(mut ffmpeg) = self.ffmpeg {
+            let _ = ffmpeg.wait();
+        }
+        eprintln!("Recorded {} frames", self.frames_written);
+    }
+}
@@ -0,0 +1,242 @@
+//! Reflections of the surroundings in the oceans of planets
+
+use nalgebra_glm as glm;
+
+use crate::globals::{REFLECTION_FACES, REFLECTION_SIZE};
+use crate::gpu_resources::{self, Kind};
+use crate::procedural_planet::Planet;
+use crate::render_queue::RenderQueue;
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+use crate::shader::Shader;
+use crate::{terrain_tessellation, util};
+
+/// Direction and up of each face, in the order of the cubemap targets from
+/// `TEXTURE_CUBE_MAP_POSITIVE_X`, so the faces come out as sampled
+pub const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+struct Cubemap {
+    texture: u32,
+    rendered: u8, // Bit for each face rendered at least once
+}
+
+impl Cubemap {
+    /// Used once every face is rendered, and not undefined
+    fn complete(&self) -> bool {
+        self.rendered == 0x3F
+    }
+}
+
+pub struct OceanReflections {
+    fbo: u32,
+    cubemaps: Vec<Option<Cubemap>>, // By planet id
+    next: usize,                    // Face to render next, counted over all oceans
+}
+
+impl OceanReflections {
+    pub unsafe fn new() -> Self {
+        let (mut fbo, mut depth) = (0, 0);
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::GenRenderbuffers(1, &mut depth);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+        let size = REFLECTION_SIZE as i32;
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, size, size);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+        let mut target = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::FramebufferRenderbuffer(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_STENCIL_ATTACHMENT,
+            gl::RENDERBUFFER,
+            depth,
+        );
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+        gpu_resources::track(Kind::Framebuffer, fbo, 0, "ocean reflection");
+        let texels = REFLECTION_SIZE as u64 * REFLECTION_SIZE as u64;
+        gpu_resources::track(Kind::Renderbuffer, depth, texels * 4, "ocean reflection");
+
+        OceanReflections {
+            fbo,
+            cubemaps: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Render the next faces due, with everything but the planet uniforms and
+    /// the transformations of the scene set up for drawing. Leaves planet
+    /// uniforms, `u_view`, `u_perspective` and the types of the planet nodes
+    /// to be set again for drawing the views.
+    pub unsafe fn update(
+        &mut self,
+        sh: &Shader,
+        planets: &[Planet],
+        planet_nodes: &mut [Node],
+        scene_root: &SceneNode,
+        skybox: &mut Node,
+        enabled: bool,
+    ) {
+        if !enabled {
+            return;
+        }
+        let oceans: Vec<usize> = planets
+            .iter()
+            .filter(|planet| planet.has_ocean)
+            .map(|planet| planet.planet_id)
+            .collect();
+        if oceans.is_empty() {
+            return;
+        }
+        self.cubemaps.resize_with(planets.len(), || None);
+        for _ in 0..REFLECTION_FACES {
+            let face = self.next % (oceans.len() * FACES.len());
+            self.next = face + 1;
+            let planet_id = oceans[face / FACES.len()];
+            self.render(sh, planets, planet_nodes, scene_root, skybox, planet_id, face % FACES.len());
+        }
+    }
+
+    /// Render face `face` of the cubemap of planet `planet_id`
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn render(
+        &mut self,
+        sh: &Shader,
+        planets: &[Planet],
+        planet_nodes: &mut [Node],
+        scene_root: &SceneNode,
+        skybox: &mut Node,
+        planet_id: usize,
+        face: usize,
+    ) {
+        let cubemap = self.cubemaps[planet_id].get_or_insert_with(|| {
+            let texture = new_cubemap();
+            let bytes = REFLECTION_SIZE as u64 * REFLECTION_SIZE as u64 * 4 * 6;
+            gpu_resources::track(Kind::Texture, texture, bytes, "ocean reflection");
+            Cubemap { texture, rendered: 0 }
+        });
+
+        // Keeping the framebuffer, viewport and clear colour of the caller
+        let (mut target, mut viewport, mut clear_color) = (0, [0; 4], [0.0; 4]);
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::FramebufferTexture2D(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+            cubemap.texture,
+            0,
+        );
+        gl::Viewport(0, 0, REFLECTION_SIZE as i32, REFLECTION_SIZE as i32);
+        gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+
+        // From the center of the planet, drawn relative to it, out past its
+        // mountains and clouds
+        let planet = &planets[planet_id];
+        let center = planet.position;
+        let (direction, up) = FACES[face];
+        let look = glm::look_at(&glm::zero(), &glm::Vec3::from(direction), &glm::Vec3::from(up));
+        let near = planet.reach();
+        let perspective = util::perspective_infinite(1.0, std::f32::consts::FRAC_PI_2, near);
+        let perspective_view = perspective * look;
+        sh.activate();
+        for planet in planets {
+            planet.update_uniforms(sh, &center);
+        }
+        gl::UniformMatrix4fv(sh.get_uniform_location("u_view"), 1, gl::FALSE, look.as_ptr());
+        gl::UniformMatrix4fv(sh.get_uniform_location("u_perspective"), 1, gl::FALSE, perspective.as_ptr());
+        gl::Uniform3fv(sh.get_uniform_location("u_player_position"), 1, glm::Vec3::zeros().as_ptr());
+        gl::Uniform1f(sh.get_uniform_location("u_log_depth"), 0.0);
+
+        gl::DepthFunc(gl::LEQUAL);
+        skybox.set_position(glm::zero());
+        skybox.update_node_transformations(&glm::identity(), &glm::zero());
+        skybox.draw_scene(&perspective_view, sh, (0.1, 10.0));
+        gl::DepthFunc(gl::LESS);
+
+        for (i, node) in planet_nodes.iter_mut().enumerate() {
+            node.node_type = if i == planet_id { SceneNodeType::PlanetSkip } else { SceneNodeType::Empty };
+            node.occluded = false;
+        }
+        let mut queue = RenderQueue::new();
+        queue.origin = center;
+        let clipping = (near, f32::INFINITY);
+        if terrain_tessellation::enabled() {
+            terrain_tessellation::draw(
+                sh,
+                planets
+                    .iter()
+                    .zip(planet_nodes.iter().map(|node| &***node))
+                    .filter(|(planet, _)| planet.planet_id != planet_id),
+                &perspective_view,
+                &center,
+                clipping,
+                REFLECTION_SIZE as f32 / 2.0,
+                &mut queue,
+            );
+            sh.activate();
+        }
+        scene_root.collect_draws(&perspective_view, sh.program_id, clipping, &mut queue);
+        queue.sort();
+        queue.submit(sh);
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target as u32);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        gl::ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+        cubemap.rendered |= 1 << face;
+    }
+
+    /// Cubemaps by planet id for `RenderQueue::reflections`, 0 for planets
+    /// without an ocean or a complete cubemap yet
+    pub fn cubemaps(&self) -> Vec<u32> {
+        self.cubemaps
+            .iter()
+            .map(|cubemap| match cubemap {
+                Some(cubemap) if cubemap.complete() => cubemap.texture,
+                _ => 0,
+            })
+            .collect()
+    }
+
+    /// Delete the cubemaps, when the planets are replaced
+    pub unsafe fn clear(&mut self) {
+        for cubemap in self.cubemaps.drain(..).flatten() {
+            gpu_resources::delete(Kind::Texture, cubemap.texture);
+        }
+        self.next = 0;
+    }
+}
+
+unsafe fn new_cubemap() -> u32 {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+    for face in 0..FACES.len() as u32 {
+        gl::TexImage2D(
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+            0,
+            gl::RGBA8 as i32,
+            REFLECTION_SIZE as i32,
+            REFLECTION_SIZE as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            std::ptr::null(),
+        );
+    }
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+    texture
+}
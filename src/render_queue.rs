@@ -0,0 +1,359 @@
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+
+use crate::city_lights::CITY_LIGHTS_UNIT;
+use crate::gl_state::GlState;
+use crate::globals::{BATCH_MIN_DRAWS, BONES_BINDING, OUTLINE_COLOR, OUTLINE_WIDTH};
+use crate::scene_graph::SceneNodeType;
+use crate::shader::{self, Shader};
+use crate::{capabilities, normal_debug, profile_scope, terrain_batch};
+
+/// Texture unit normal maps of nodes are bound to
+pub const NORMAL_MAP_UNIT: u32 = 2;
+/// Texture unit the reflection cubemap of an ocean is bound to
+pub const REFLECTION_UNIT: u32 = 4;
+
+/// Everything needed to draw a single node, collected while traversing the
+/// scene graph
+#[derive(Copy, Clone, Debug)]
+pub struct DrawCommand {
+    pub program: u32, // Shader program
+    pub node_id: usize,
+    pub vao: u32,
+    pub vbo: u32, // Vertex and index buffer of the vao, copied from for batches, see terrain_batch.rs
+    pub ibo: u32,
+    pub index_count: i32,
+    pub node_type: SceneNodeType,
+    pub planet_id: usize,
+    pub lod_level: usize,
+    pub texture_id: Option<u32>,
+    pub normal_map_id: Option<u32>,
+    pub skin: u32,       // Uniform buffer of bone matrices, 0 if not skinned
+    pub reflection: u32, // Cubemap reflected by oceans, 0 for none, set by `RenderQueue::push`
+    pub city_lights: u32, // Lights of the planet at night, 0 for none, set by `RenderQueue::push`
+    pub tint: glm::Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub fade: f32,       // Share of pixels drawn, dithered, the rest if negative, see lod.rs
+    pub selected: bool,  // Highlighted and outlined
+    pub debug_normals: bool, // Face normals drawn over it, see normal_debug.rs
+    pub mvp: glm::Mat4,
+    pub previous_mvp: glm::Mat4, // Of the node the last frame, set by `RenderQueue::push`
+    pub model: glm::Mat4,
+    pub depth: f32, // Distance to player, for sorting
+}
+
+impl DrawCommand {
+    /// Nodes that are blended with what's behind them
+    pub fn is_transparent(&self) -> bool {
+        matches!(
+            self.node_type,
+            SceneNodeType::Ocean | SceneNodeType::Cloud | SceneNodeType::Geometry2d
+        )
+    }
+
+    /// Key used to group draws with the same state together
+    fn state_key(&self) -> (u32, u32, u32, usize, u32) {
+        (
+            self.program,
+            self.node_type as u32,
+            self.texture_id.unwrap_or(0),
+            self.planet_id, // Patches of a planet are batched together
+            self.vao,
+        )
+    }
+}
+
+/// Two-phase rendering: traversal pushes draw commands, which are sorted to
+/// minimize state changes (opaque) or by depth (transparent) before submission
+#[derive(Default)]
+pub struct RenderQueue {
+    pub opaque: Vec<DrawCommand>,
+    pub transparent: Vec<DrawCommand>,
+    pub overdraw: bool, // Blend everything additively to visualize overdraw
+    pub origin: glm::Vec3, // World position draws are relative to, the camera for camera-relative rendering
+    pub reflections: Vec<u32>, // Cubemaps reflected by oceans by planet id, 0 for none, see reflections.rs
+    pub city_lights: Vec<u32>, // Lights at night by planet id, 0 for none, see city_lights.rs
+    pub view: usize,       // Drawn for, keeping the transformations of each view apart
+    mvps: HashMap<(usize, usize), glm::Mat4>, // By view and node id, this frame
+    previous_mvps: HashMap<(usize, usize), glm::Mat4>, // And the last, for motion blur
+    batchable: HashMap<u32, bool>, // Whether each program has u_batched, looked up once
+    state: GlState,
+    // Statistics, accumulated until reset
+    pub draw_calls: usize,
+    pub triangles: usize,
+    pub state_changes: usize,
+    pub batched: usize, // Terrain patches drawn in batches, see terrain_batch.rs
+}
+
+impl RenderQueue {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Model matrix moved to be relative to `origin`, for uploading.
+    /// Subtracting it from the translation directly, in double precision and
+    /// before combining with the view, keeps precision far away from the
+    /// world origin.
+    pub fn rebase(&self, model: &glm::DMat4) -> glm::Mat4 {
+        let mut model = *model;
+        model[12] -= self.origin.x as f64;
+        model[13] -= self.origin.y as f64;
+        model[14] -= self.origin.z as f64;
+        model.cast()
+    }
+
+    /// Transformation node `node_id` was drawn with in the view the last
+    /// frame, remembering `mvp` for the next. The same as `mvp` where it
+    /// wasn't drawn, so it stands still.
+    pub fn previous_mvp(&mut self, node_id: usize, mvp: &glm::Mat4) -> glm::Mat4 {
+        let key = (self.view, node_id);
+        self.mvps.insert(key, *mvp);
+        self.previous_mvps.get(&key).copied().unwrap_or(*mvp)
+    }
+
+    /// Move on to the next frame, keeping the transformations of this one
+    pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.mvps, &mut self.previous_mvps);
+        self.mvps.clear();
+        self.state.end_frame();
+        terrain_batch::end_frame();
+    }
+
+    pub fn push(&mut self, mut cmd: DrawCommand) {
+        cmd.previous_mvp = self.previous_mvp(cmd.node_id, &cmd.mvp);
+        if cmd.node_type == SceneNodeType::Ocean {
+            cmd.reflection = self.reflections.get(cmd.planet_id).copied().unwrap_or(0);
+        }
+        if cmd.node_type == SceneNodeType::Planet {
+            cmd.city_lights = self.city_lights.get(cmd.planet_id).copied().unwrap_or(0);
+        }
+        if cmd.is_transparent() {
+            self.transparent.push(cmd);
+        } else {
+            self.opaque.push(cmd);
+        }
+    }
+
+    /// Sort opaque draws by state, transparent draws back to front
+    pub fn sort(&mut self) {
+        self.opaque.sort_by_key(|cmd| cmd.state_key());
+        // Stable, so 2D elements (all at depth 0) keep their graph order
+        self.transparent.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+    }
+
+    /// Submit and clear all queued draws. Opaque draws go first without
+    /// blending, then transparent draws back to front with depth writes
+    /// disabled, so they blend with everything behind them but not each other.
+    /// Each draw is made with its own program, leaving `sh` in use.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn submit(&mut self, sh: &Shader) {
+        profile_scope!("submit");
+        // Anything may have been bound since the last submit
+        self.state.invalidate();
+        // Other programs, like variants of `sh`, get the values set for the pass
+        if capabilities::global().at_least(4, 1) {
+            let mut programs: Vec<u32> = self.opaque.iter().chain(&self.transparent).map(|cmd| cmd.program).collect();
+            programs.sort_unstable();
+            programs.dedup();
+            for program in programs.into_iter().filter(|&program| program != sh.program_id) {
+                shader::share_uniforms(sh, &Shader::of(program));
+                self.state.forget_uniforms(program);
+            }
+        }
+
+        if self.overdraw {
+            self.state.set(gl::BLEND, true);
+            self.state.blend_func(gl::ONE, gl::ONE);
+        } else {
+            self.state.set(gl::BLEND, false);
+        }
+        // Selected draws are marked in the stencil buffer
+        let outline = !self.overdraw && self.opaque.iter().any(|cmd| cmd.selected);
+        if outline {
+            self.state.set(gl::STENCIL_TEST, true);
+            gl::StencilFunc(gl::ALWAYS, 1, 0xFF);
+            gl::StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
+        }
+        // Terrain patches in a row with the same state are drawn together
+        let batching = terrain_batch::enabled();
+        let mut i = 0;
+        while i < self.opaque.len() {
+            let cmd = &self.opaque[i];
+            let batchable = *self
+                .batchable
+                .entry(cmd.program)
+                .or_insert_with(|| Shader::of(cmd.program).get_uniform_location("u_batched") != -1);
+            let run = match batching && batchable {
+                true => self.opaque[i..].iter().take_while(|other| terrain_batch::compatible(cmd, other)).count(),
+                false => 0,
+            };
+            if run >= BATCH_MIN_DRAWS {
+                self.state_changes += Self::apply_state(&mut self.state, cmd);
+                if outline {
+                    self.state.stencil_mask(0x00);
+                }
+                let batch = &self.opaque[i..i + run];
+                let unbatched = terrain_batch::draw(&mut self.state, &Shader::of(cmd.program), batch);
+                if unbatched.len() < run {
+                    self.draw_calls += 1;
+                    self.batched += run - unbatched.len();
+                }
+                // Left out when the shared buffers are full
+                for &j in &unbatched {
+                    self.state_changes += Self::apply_state(&mut self.state, &batch[j]);
+                    Self::draw(&batch[j]);
+                    self.draw_calls += 1;
+                }
+                self.triangles += batch.iter().map(|cmd| cmd.index_count as usize / 3).sum::<usize>();
+                i += run;
+                continue;
+            }
+            self.state_changes += Self::apply_state(&mut self.state, cmd);
+            if outline {
+                self.state.stencil_mask(if cmd.selected { 0xFF } else { 0x00 });
+            }
+            Self::draw(cmd);
+            self.draw_calls += 1;
+            self.triangles += cmd.index_count as usize / 3;
+            i += 1;
+        }
+
+        self.state.set(gl::BLEND, true);
+        if !self.overdraw {
+            self.state.blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
+        self.state.depth_mask(false);
+        for cmd in self.transparent.iter() {
+            self.state_changes += Self::apply_state(&mut self.state, cmd);
+            // Clouds are seen from below as well
+            self.state.set(gl::CULL_FACE, cmd.node_type != SceneNodeType::Cloud);
+            Self::draw(cmd);
+            self.draw_calls += 1;
+            self.triangles += cmd.index_count as usize / 3;
+        }
+        self.state.set(gl::CULL_FACE, true);
+        self.state.depth_mask(true);
+        self.state.blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        if outline {
+            self.state.stencil_mask(0x00);
+        }
+        self.draw_calls += normal_debug::draw(&mut self.state, sh, self.opaque.iter().chain(&self.transparent));
+        if outline {
+            self.submit_outline(sh);
+        }
+        // Others expect the first unit active, and `sh` in use
+        self.state.active_texture(0);
+        self.state.use_program(sh.program_id);
+
+        self.opaque.clear();
+        self.transparent.clear();
+    }
+
+    /// Draw the selected nodes again, pushed out along their normals, where
+    /// the stencil isn't marked by the first draw. Leaves a flat coloured
+    /// rim around them, on top of everything else in the pass.
+    unsafe fn submit_outline(&mut self, sh: &Shader) {
+        gl::StencilFunc(gl::NOTEQUAL, 1, 0xFF);
+        self.state.stencil_mask(0x00);
+        self.state.set(gl::DEPTH_TEST, false);
+        // Set for every program the selected nodes are drawn with, not just `sh`
+        let mut programs: Vec<u32> = self.opaque.iter().filter(|cmd| cmd.selected).map(|cmd| cmd.program).collect();
+        programs.push(sh.program_id);
+        programs.sort_unstable();
+        programs.dedup();
+        for &program in &programs {
+            self.set_outline(program, true);
+        }
+
+        for cmd in self.opaque.iter().filter(|cmd| cmd.selected) {
+            self.state_changes += Self::apply_state(&mut self.state, cmd);
+            Self::draw(cmd);
+            self.draw_calls += 1;
+            self.triangles += cmd.index_count as usize / 3;
+        }
+
+        for &program in &programs {
+            self.set_outline(program, false);
+        }
+        self.state.set(gl::DEPTH_TEST, true);
+        self.state.stencil_mask(0xFF);
+        self.state.set(gl::STENCIL_TEST, false);
+    }
+
+    /// Turn drawing the outline in `program` on or off
+    unsafe fn set_outline(&mut self, program: u32, on: bool) {
+        let sh = Shader::of(program);
+        self.state.use_program(program);
+        gl::Uniform1i(sh.get_uniform_location("u_outline"), on as i32);
+        gl::Uniform1f(sh.get_uniform_location("u_outline_width"), if on { OUTLINE_WIDTH } else { 0.0 });
+        if on {
+            gl::Uniform3fv(sh.get_uniform_location("u_outline_color"), 1, OUTLINE_COLOR.as_ptr());
+        }
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.draw_calls = 0;
+        self.triangles = 0;
+        self.state_changes = 0;
+        self.batched = 0;
+        self.state.reset_stats();
+    }
+
+    /// GL calls skipped as they wouldn't have changed anything, since the
+    /// stats were reset
+    pub fn avoided_calls(&self) -> usize {
+        self.state.avoided
+    }
+
+    /// Bind VAO, textures and per-node uniforms, skipping those already set.
+    /// Returns the number of state changes made.
+    unsafe fn apply_state(state: &mut GlState, cmd: &DrawCommand) -> usize {
+        let sh = Shader::of(cmd.program);
+        let changes = [
+            state.use_program(cmd.program),
+            state.bind_vertex_array(cmd.vao),
+            state.uniform_1ui(sh.get_uniform_location("u_node_type"), cmd.node_type as u32),
+            // Applies only for planets, but send anyway
+            state.uniform_1ui(sh.get_uniform_location("u_current_planet_id"), cmd.planet_id as u32),
+            state.uniform_1ui(sh.get_uniform_location("u_lod_level"), cmd.lod_level as u32),
+            state.uniform_1i(sh.get_uniform_location("u_selected"), cmd.selected as i32),
+            // Bind textures, or signal that none exist
+            cmd.texture_id.is_some_and(|id| state.bind_texture(0, gl::TEXTURE_2D, id)),
+            state.uniform_1i(sh.get_uniform_location("u_has_texture"), cmd.texture_id.is_some() as i32),
+            cmd.normal_map_id.is_some_and(|id| state.bind_texture(NORMAL_MAP_UNIT, gl::TEXTURE_2D, id)),
+            state.uniform_1i(sh.get_uniform_location("u_has_normal_map"), cmd.normal_map_id.is_some() as i32),
+            cmd.reflection != 0 && state.bind_texture(REFLECTION_UNIT, gl::TEXTURE_CUBE_MAP, cmd.reflection),
+            state.uniform_1i(sh.get_uniform_location("u_has_reflection"), (cmd.reflection != 0) as i32),
+            cmd.city_lights != 0 && state.bind_texture(CITY_LIGHTS_UNIT, gl::TEXTURE_2D, cmd.city_lights),
+            state.uniform_1i(sh.get_uniform_location("u_has_city_lights"), (cmd.city_lights != 0) as i32),
+            cmd.skin != 0 && state.bind_buffer_base(gl::UNIFORM_BUFFER, BONES_BINDING, cmd.skin),
+            state.uniform_1i(sh.get_uniform_location("u_skinned"), (cmd.skin != 0) as i32),
+            state.uniform_1f(sh.get_uniform_location("u_metallic"), cmd.metallic),
+            state.uniform_1f(sh.get_uniform_location("u_roughness"), cmd.roughness),
+            state.uniform_4f(sh.get_uniform_location("u_tint"), &cmd.tint.into()),
+            state.uniform_1f(sh.get_uniform_location("u_fade"), cmd.fade),
+        ];
+        changes.iter().filter(|&&changed| changed).count()
+    }
+
+    unsafe fn draw(cmd: &DrawCommand) {
+        let sh = Shader::of(cmd.program);
+        let u_mvp = sh.get_uniform_location("u_mvp");
+        gl::UniformMatrix4fv(u_mvp, 1, gl::FALSE, cmd.mvp.as_ptr());
+        let u_previous_mvp = sh.get_uniform_location("u_previous_mvp");
+        gl::UniformMatrix4fv(u_previous_mvp, 1, gl::FALSE, cmd.previous_mvp.as_ptr());
+        let u_model = sh.get_uniform_location("u_model");
+        gl::UniformMatrix4fv(u_model, 1, gl::FALSE, cmd.model.as_ptr());
+
+        gl::DrawElements(
+            gl::TRIANGLES,
+            cmd.index_count,
+            gl::UNSIGNED_INT,
+            std::ptr::null(),
+        );
+    }
+}
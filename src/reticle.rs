@@ -0,0 +1,53 @@
+//! Crosshair in the middle of the view, lit up over a body
+
+use nalgebra_glm as glm;
+
+use crate::body_info;
+use crate::camera::View;
+use crate::globals::{OUTLINE_COLOR, RETICLE_SCALE};
+use crate::picking;
+use crate::procedural_planet::Planet;
+use crate::scene_graph::{Node, SceneNode};
+use crate::text::{self, Align, Font, Text};
+
+const IDLE: glm::Vec4 = glm::Vec4::new(1.0, 1.0, 1.0, 0.6);
+
+pub struct Reticle {
+    pub root: Node,
+    cross: Text,
+    name: Text,
+    pub hovered: Option<usize>, // Planet under the crosshair
+}
+
+impl Reticle {
+    pub fn new(font: &Font) -> Self {
+        let mut root = SceneNode::new();
+        let cross = Text::new(font, "+", glm::zero(), RETICLE_SCALE).align(Align::Center);
+        let name = Text::new(font, "", glm::zero(), RETICLE_SCALE * 0.8).align(Align::Center);
+        root.add_child(&cross.node);
+        root.add_child(&name.node);
+        Reticle {
+            root,
+            cross,
+            name,
+            hovered: None,
+        }
+    }
+
+    /// Cast the ray through the centre of `view` at `planets`, with the
+    /// crosshair at `center` in normalized device coordinates of the window
+    pub fn update(&mut self, view: &View, planets: &[Planet], planet_nodes: &[Node], center: glm::Vec2) {
+        self.hovered = picking::pick_planet(&view.eye, &glm::normalize(&view.direction), planets);
+        // Centred on the line, not standing on it
+        let line = RETICLE_SCALE * text::LINE_HEIGHT;
+        self.cross.set_anchor(center - glm::vec2(0.0, line / 2.0));
+        self.name.set_anchor(center - glm::vec2(0.0, line * 1.5));
+        let color = match self.hovered {
+            Some(_) => glm::vec4(OUTLINE_COLOR[0], OUTLINE_COLOR[1], OUTLINE_COLOR[2], 1.0),
+            None => IDLE,
+        };
+        self.cross.node.tint = color;
+        self.name.node.tint = color;
+        self.name.set_text(&self.hovered.map_or(String::new(), |i| body_info::name(planet_nodes, i)));
+    }
+}
@@ -1,13 +1,17 @@
+use crate::body_info;
+use crate::particles::Preset;
 use crate::procedural_planet as planet;
+use crate::scene_file::{Material, NodeDescription, Shape};
 use crate::scene_graph::{Node, SceneNode, SceneNodeType};
 
-pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
+pub fn create_scene(seed: u32) -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     let mut planets = vec![];
     let mut planet_nodes = vec![];
     let mut lightsources = vec![];
+    planet::reset_ids();
 
     // sun
-    let mut planet = planet::Planet::with_seed(498765401);
+    let mut planet = planet::Planet::with_seed(498765401u32.wrapping_add(seed));
     let planet_sun = planet.planet_id;
     planet.max_height = 0.005; // relative to scale
     planet.noise.size = 500.0;
@@ -25,8 +29,9 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.lightsource = true;
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 65.0;
-    planet_node.position = glm::vec3(00.0, 0.0, 0.0);
+    planet_node.body = Some(body_info::BodyMetadata::named("Sun"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 65.0);
+    planet_node.set_position(glm::vec3(00.0, 0.0, 0.0));
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     lightsources.push(planet.planet_id);
@@ -34,13 +39,16 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet_nodes.push(planet_node);
 
     // Small earth-like planet
-    let mut planet = planet::Planet::with_seed(43932);
+    let mut planet = planet::Planet::with_seed(43932u32.wrapping_add(seed));
     let planet_earth0 = planet.planet_id;
     planet.max_height = 0.03;
     planet.noise.size = 25.0;
     planet.ocean_dark_color = glm::vec3(0.001, 0.03, 0.01);
     planet.ocean_light_color = glm::vec3(0.04, 0.37, 0.33);
     planet.emission = glm::vec3(0.03, 0.32, 0.37);
+    planet.has_clouds = true;
+    planet.city_density = 1.0;
+    planet.vegetation = 1.0;
     planet.color_scheme = [
         glm::vec3(0.4, 0.4, 0.3),
         glm::vec3(0.7, 0.55, 0.0),
@@ -51,21 +59,22 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.color_thresholds = [-0.0005, 0.0008, 0.019, 0.022];
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 23.0;
+    planet_node.body = Some(body_info::BodyMetadata::named("Small earth"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 23.0);
     planet.trajectory = 1120.0;
     planet.traj_speed = 0.012;
     planet.traj_init_angle = glm::vec3(6.24f32, 0.5, 1.0f32);
-    planet_node.position = glm::vec3(
+    planet_node.set_position(glm::vec3(
         planet.traj_init_angle.x.sin() * planet.trajectory,
         planet.traj_init_angle.y,
         planet.traj_init_angle.x.cos() * planet.trajectory,
-    );
+    ));
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Other planet
-    let mut planet = planet::Planet::with_seed(1834327);
+    let mut planet = planet::Planet::with_seed(1834327u32.wrapping_add(seed));
     let planet_earth1 = planet.planet_id;
     planet.max_height = 0.08;
     planet.noise.size = 4.0;
@@ -88,22 +97,23 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.color_thresholds = [-0.0005, 0.001, 0.014, 0.024];
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 16.0;
+    planet_node.body = Some(body_info::BodyMetadata::named("Second earth"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 16.0);
     planet.trajectory = 690.0;
     planet.traj_speed = 0.03;
     planet.traj_init_angle = glm::vec3(0.08f32, 0.3, 1.0);
-    planet_node.position = glm::vec3(
+    planet_node.set_position(glm::vec3(
         planet.traj_init_angle.x.sin() * planet.trajectory,
         planet.traj_init_angle.y,
         planet.traj_init_angle.x.cos() * planet.trajectory,
-    );
+    ));
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Small mars-like planet
-    let mut planet = planet::Planet::with_seed(94333);
+    let mut planet = planet::Planet::with_seed(94333u32.wrapping_add(seed));
     let planet_mars = planet.planet_id;
     planet.parent_id = planet_sun; // default
     planet.max_height = 0.03;
@@ -120,19 +130,20 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.color_thresholds = [-0.0005, 0.001, 0.014, 0.026];
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 15.3;
+    planet_node.body = Some(body_info::BodyMetadata::named("Small mars"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 15.3);
     planet.trajectory = 460.0;
     planet.traj_init_angle = glm::vec3(6.24, 0.1, 1.0);
-    planet_node.position = glm::vec3(
+    planet_node.set_position(glm::vec3(
         planet.traj_init_angle.x.sin() * planet.trajectory,
         planet.traj_init_angle.y,
         planet.traj_init_angle.x.cos() * planet.trajectory,
-    );
+    ));
     planet.rot_speed = 2.0;
     planet.rot_axis = glm::normalize(&glm::vec3(1.0, 4.0, 0.0));
     eprintln!(
         "Mars is {} away from the sun",
-        glm::length(&planet_node.position)
+        glm::length(&planet_node.position())
     );
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
@@ -140,7 +151,7 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet_nodes.push(planet_node);
 
     // Moon of mars-like planet
-    let mut planet = planet::Planet::with_seed(4329713);
+    let mut planet = planet::Planet::with_seed(4329713u32.wrapping_add(seed));
     planet.parent_id = planet_mars;
     planet.max_height = 0.003;
     planet.noise.size = 6.0;
@@ -157,22 +168,25 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.traj_init_angle = glm::vec3(0.02, 0.0, 1.0);
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 4.0;
+    planet_node.body = Some(body_info::BodyMetadata::named("Moon of small mars"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 4.0);
     planet.trajectory = 50.0;
     planet.traj_speed = 0.8;
-    planet_node.position = planet_nodes[2].position
-        + glm::vec3(
-            planet.traj_init_angle.x.sin() * planet.trajectory,
-            planet.traj_init_angle.y,
-            planet.traj_init_angle.x.cos() * planet.trajectory,
-        );
+    planet_node.set_position(
+        planet_nodes[2].position()
+            + glm::vec3(
+                planet.traj_init_angle.x.sin() * planet.trajectory,
+                planet.traj_init_angle.y,
+                planet.traj_init_angle.x.cos() * planet.trajectory,
+            ),
+    );
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Moon of closest earth-like planet
-    let mut planet = planet::Planet::with_seed(35462);
+    let mut planet = planet::Planet::with_seed(35462u32.wrapping_add(seed));
     planet.parent_id = planet_earth0;
     planet.max_height = 0.09;
     planet.noise.size = 5.4;
@@ -189,22 +203,25 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.traj_init_angle = glm::vec3(0.7, 0.0, 1.0);
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 4.4;
+    planet_node.body = Some(body_info::BodyMetadata::named("Moon of small earth"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 4.4);
     planet.trajectory = 48.0;
     planet.traj_speed = 0.8;
-    planet_node.position = planet_nodes[1].position
-        + glm::vec3(
-            planet.traj_init_angle.x.sin() * planet.trajectory,
-            planet.traj_init_angle.y,
-            planet.traj_init_angle.x.cos() * planet.trajectory,
-        );
+    planet_node.set_position(
+        planet_nodes[1].position()
+            + glm::vec3(
+                planet.traj_init_angle.x.sin() * planet.trajectory,
+                planet.traj_init_angle.y,
+                planet.traj_init_angle.x.cos() * planet.trajectory,
+            ),
+    );
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Moon 1 of second earth-like planet
-    let mut planet = planet::Planet::with_seed(87635462);
+    let mut planet = planet::Planet::with_seed(87635462u32.wrapping_add(seed));
     planet.parent_id = planet_earth1;
     planet.max_height = 0.12;
     planet.noise.size = 3.4;
@@ -221,22 +238,25 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.traj_init_angle = glm::vec3(3.13, 0.0, 3.7);
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 4.8;
+    planet_node.body = Some(body_info::BodyMetadata::named("First moon of second earth"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 4.8);
     planet.trajectory = 72.0;
     planet.traj_speed = 0.8;
-    planet_node.position = planet_nodes[0].position
-        + glm::vec3(
-            planet.traj_init_angle.x.sin() * planet.trajectory,
-            planet.traj_init_angle.y,
-            planet.traj_init_angle.x.cos() * planet.trajectory,
-        );
+    planet_node.set_position(
+        planet_nodes[0].position()
+            + glm::vec3(
+                planet.traj_init_angle.x.sin() * planet.trajectory,
+                planet.traj_init_angle.y,
+                planet.traj_init_angle.x.cos() * planet.trajectory,
+            ),
+    );
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Moon 2 of second earth-like planet
-    let mut planet = planet::Planet::with_seed(192743);
+    let mut planet = planet::Planet::with_seed(192743u32.wrapping_add(seed));
     planet.parent_id = planet_earth1;
     planet.max_height = 0.09;
     planet.noise.size = 3.6;
@@ -253,22 +273,25 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.traj_init_angle = glm::vec3(0.46, 0.0, 2.2);
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 3.1;
+    planet_node.body = Some(body_info::BodyMetadata::named("Second moon of second earth"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 3.1);
     planet.trajectory = 48.0;
     planet.traj_speed = 0.8;
-    planet_node.position = planet_nodes[0].position
-        + glm::vec3(
-            planet.traj_init_angle.x.sin() * planet.trajectory,
-            planet.traj_init_angle.y,
-            planet.traj_init_angle.x.cos() * planet.trajectory,
-        );
+    planet_node.set_position(
+        planet_nodes[0].position()
+            + glm::vec3(
+                planet.traj_init_angle.x.sin() * planet.trajectory,
+                planet.traj_init_angle.y,
+                planet.traj_init_angle.x.cos() * planet.trajectory,
+            ),
+    );
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Moon 3 of second earth-like planet
-    let mut planet = planet::Planet::with_seed(12342);
+    let mut planet = planet::Planet::with_seed(12342u32.wrapping_add(seed));
     planet.parent_id = planet_earth1;
     planet.max_height = 0.04;
     planet.noise.size = 2.7;
@@ -285,22 +308,25 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.traj_init_angle = glm::vec3(3.80, 0.0, 2.8);
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 3.9;
+    planet_node.body = Some(body_info::BodyMetadata::named("Third moon of second earth"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 3.9);
     planet.trajectory = 36.0;
     planet.traj_speed = 0.8;
-    planet_node.position = planet_nodes[0].position
-        + glm::vec3(
-            planet.traj_init_angle.x.sin() * planet.trajectory,
-            planet.traj_init_angle.y,
-            planet.traj_init_angle.x.cos() * planet.trajectory,
-        );
+    planet_node.set_position(
+        planet_nodes[0].position()
+            + glm::vec3(
+                planet.traj_init_angle.x.sin() * planet.trajectory,
+                planet.traj_init_angle.y,
+                planet.traj_init_angle.x.cos() * planet.trajectory,
+            ),
+    );
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Blue small planet in outer rim
-    let mut planet = planet::Planet::with_seed(71772);
+    let mut planet = planet::Planet::with_seed(71772u32.wrapping_add(seed));
     planet.parent_id = planet_sun;
     planet.max_height = 0.02;
     planet.noise.size = 8.2;
@@ -316,22 +342,23 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.color_thresholds = [-0.01, 0.001, 0.010, 0.016];
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 13.5;
+    planet_node.body = Some(body_info::BodyMetadata::named("Blue rim planet"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 13.5);
     planet.trajectory = 1690.0;
     planet.traj_speed = 0.1;
     planet.traj_init_angle = glm::vec3(-6.22, 1.1, 3.5);
-    planet_node.position = glm::vec3(
+    planet_node.set_position(glm::vec3(
         planet.traj_init_angle.x.sin() * planet.trajectory,
         planet.traj_init_angle.y,
         planet.traj_init_angle.x.cos() * planet.trajectory,
-    );
+    ));
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Yellow ish planet close to sun
-    let mut planet = planet::Planet::with_seed(98732);
+    let mut planet = planet::Planet::with_seed(98732u32.wrapping_add(seed));
     planet.parent_id = planet_sun;
     planet.max_height = 0.023;
     planet.noise.size = 6.7;
@@ -349,22 +376,23 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.color_thresholds = [-0.01, 0.001, 0.010, 0.016];
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 10.0;
+    planet_node.body = Some(body_info::BodyMetadata::named("Yellow inner planet"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 10.0);
     planet.trajectory = 190.0;
     planet.traj_speed = 0.1;
     planet.traj_init_angle = glm::vec3(6.20, 1.1, 3.4);
-    planet_node.position = glm::vec3(
+    planet_node.set_position(glm::vec3(
         planet.traj_init_angle.x.sin() * planet.trajectory,
         planet.traj_init_angle.y,
         planet.traj_init_angle.x.cos() * planet.trajectory,
-    );
+    ));
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
     planet_nodes.push(planet_node);
 
     // Quite large planet
-    let mut planet = planet::Planet::with_seed(87546432);
+    let mut planet = planet::Planet::with_seed(87546432u32.wrapping_add(seed));
     planet.parent_id = planet_sun;
     planet.max_height = 0.08;
     planet.noise.size = 4.0;
@@ -389,16 +417,17 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
     planet.color_thresholds = [-0.0005, 0.001, 0.014, 0.058];
     let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
     planet_node.planet_id = planet.planet_id;
-    planet_node.scale *= 46.0;
+    planet_node.body = Some(body_info::BodyMetadata::named("Large planet"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 46.0);
     planet.trajectory = 1450.0;
     planet.traj_speed = 0.03;
     planet.traj_init_angle = glm::vec3(0.08f32, 0.3, 2.3);
     planet.rot_speed = 2.4;
-    planet_node.position = glm::vec3(
+    planet_node.set_position(glm::vec3(
         planet.traj_init_angle.x.sin() * planet.trajectory,
         planet.traj_init_angle.y,
         planet.traj_init_angle.x.cos() * planet.trajectory,
-    );
+    ));
     planet.node = planet_node.node_id;
     planet.node = planet_node.node_id;
     planets.push(planet);
@@ -406,3 +435,134 @@ pub fn create_scene() -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>) {
 
     (planets, planet_nodes, lightsources)
 }
+
+/// Small test scene covering the render paths: every node type, emissive
+/// and lit planets, transparent ocean, plain geometry and a 2D GUI quad.
+/// Started with `--kitchen-sink`, meant for comparing screenshots between
+/// changes. The extra nodes are returned separately from the planets, as
+/// descriptions built like those of scene files, so they are saved with it.
+///
+/// There are no instanced batches in the renderer yet, add them here when
+/// they are.
+pub fn create_kitchen_sink_scene(
+    seed: u32,
+) -> (Vec<planet::Planet>, Vec<Node>, Vec<usize>, Vec<NodeDescription>) {
+    let mut planets = vec![];
+    let mut planet_nodes = vec![];
+    let mut lightsources = vec![];
+    let mut props = vec![];
+    planet::reset_ids();
+
+    // Emissive sun at the origin, lighting the others
+    let mut planet = planet::Planet::with_seed(1u32.wrapping_add(seed));
+    let planet_sun = planet.planet_id;
+    planet.max_height = 0.005;
+    planet.noise.size = 500.0;
+    planet.max_lod = 2;
+    planet.has_ocean = false;
+    planet.emission = glm::vec3(1.0, 0.5, 0.3);
+    planet.lightsource = true;
+    let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
+    planet_node.planet_id = planet.planet_id;
+    planet_node.body = Some(body_info::BodyMetadata::named("Sun"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 65.0);
+    planet.node = planet_node.node_id;
+    lightsources.push(planet.planet_id);
+    planets.push(planet);
+    planet_nodes.push(planet_node);
+
+    // Terrain with a transparent ocean, close to the start position
+    let mut planet = planet::Planet::with_seed(2u32.wrapping_add(seed));
+    planet.parent_id = planet_sun;
+    planet.max_height = 0.03;
+    planet.noise.size = 25.0;
+    planet.has_clouds = true;
+    planet.cloud_coverage = 0.3;
+    let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
+    planet_node.planet_id = planet.planet_id;
+    planet_node.body = Some(body_info::BodyMetadata::named("Ocean planet"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 23.0);
+    planet.trajectory = 540.0;
+    planet.traj_speed = 0.0;
+    planet.traj_init_angle = glm::vec3(0.0, 20.0, 1.0);
+    planet_node.set_position(glm::vec3(0.0, planet.traj_init_angle.y, planet.trajectory));
+    planet.node = planet_node.node_id;
+    planets.push(planet);
+    planet_nodes.push(planet_node);
+
+    // Second, dim light source without ocean
+    let mut planet = planet::Planet::with_seed(3u32.wrapping_add(seed));
+    planet.parent_id = planet_sun;
+    planet.max_height = 0.01;
+    planet.has_ocean = false;
+    planet.emission = glm::vec3(0.2, 0.3, 1.0);
+    planet.lightsource = true;
+    let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
+    planet_node.planet_id = planet.planet_id;
+    planet_node.body = Some(body_info::BodyMetadata::named("Dim light"));
+    planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * 8.0);
+    planet.trajectory = 540.0;
+    planet.traj_speed = 0.0;
+    planet.traj_init_angle = glm::vec3(std::f32::consts::PI, -20.0, 1.0);
+    planet_node.set_position(glm::vec3(0.0, planet.traj_init_angle.y, -planet.trajectory));
+    planet.node = planet_node.node_id;
+    lightsources.push(planet.planet_id);
+    planets.push(planet);
+    planet_nodes.push(planet_node);
+
+    // Plain geometry around the start position
+    for i in 0..4 {
+        let angle = i as f32 * std::f32::consts::FRAC_PI_2;
+        let mut children = vec![];
+        if i == 0 {
+            // Lander, firing its engine downwards
+            children.push(NodeDescription {
+                position: [0.0, -2.0, 0.0],
+                rotation: [std::f32::consts::PI, 0.0, 0.0],
+                emitter: Some(Preset::Exhaust),
+                ..Default::default()
+            });
+        }
+        props.push(NodeDescription {
+            shape: Shape::Cube { size: [4.0, 4.0, 4.0] },
+            // Every other one drops
+            dynamic: i % 2 == 0,
+            position: [angle.sin() * 30.0, 600.0, angle.cos() * 30.0],
+            rotation: [0.3, angle, 0.0],
+            material: Material {
+                color: [0.2 + 0.2 * i as f32, 0.5, 0.8 - 0.2 * i as f32, 1.0],
+                ..Default::default()
+            },
+            children,
+            ..Default::default()
+        });
+    }
+
+    // Snowing around the start position
+    props.push(NodeDescription {
+        position: [0.0, 640.0, 0.0],
+        emitter: Some(Preset::Snow),
+        gpu_particles: true, // Where there are compute shaders
+        ..Default::default()
+    });
+
+    // Comet above the start position, its tail blowing away from the sun
+    props.push(NodeDescription {
+        shape: Shape::Comet { radius: 3.0, seed },
+        position: [-60.0, 660.0, 20.0],
+        rotation: [0.4, 0.0, 0.9],
+        material: Material { color: [0.35, 0.33, 0.3, 1.0], ..Default::default() },
+        ..Default::default()
+    });
+
+    // Translucent GUI quad in the lower right corner
+    props.push(NodeDescription {
+        shape: Shape::Quad { size: [0.2, 0.2] },
+        gui: true,
+        position: [0.8, -0.8, 0.0],
+        material: Material { color: [1.0, 0.2, 0.6, 0.5], ..Default::default() },
+        ..Default::default()
+    });
+
+    (planets, planet_nodes, lightsources, props)
+}
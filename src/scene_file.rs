@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::archetype::Archetype;
+use crate::error::{self, Error};
+use crate::gltf;
+use crate::globals::{N_LAYERS, WORLD_SPEED};
+use crate::mesh;
+use crate::particles::{Emitter, Preset};
+use crate::procedural_planet::{self as planet, NoiseParams};
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+use crate::{animation, body_info, comet, lod, nbody, skinning, texture};
+
+/// Planets, their nodes, the ids of the lightsources and the props of a
+/// built scene
+pub type Scene = (Vec<planet::Planet>, Vec<Node>, Vec<usize>, Vec<Node>);
+
+//-----------------------------------------------------------------------------/
+// Scene description, read from RON or JSON
+//-----------------------------------------------------------------------------/
+
+/// Scene read from a file, see `resources/scenes/example.ron`. Planets are
+/// created in order, so a planet can only orbit one listed before it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SceneDescription {
+    pub planets: Vec<PlanetDescription>,
+    pub nodes: Vec<NodeDescription>,
+}
+
+/// Planet parameters. Values left out keep the defaults of `Planet::with_seed`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PlanetDescription {
+    pub name: String,
+    pub seed: u32,
+    pub parent: Option<String>, // Name of the planet orbited
+    pub scale: f32,
+    // Orbit and rotation
+    pub trajectory: Option<f32>,
+    pub traj_speed: Option<f32>,
+    pub traj_init_angle: Option<[f32; 3]>,
+    pub rot_speed: Option<f32>,
+    pub rot_axis: Option<[f32; 3]>,
+    pub rot_init_angle: Option<f32>, // Turned about rot_axis at the start
+    pub mass: Option<f32>,          // For n-body motion, see nbody.rs
+    pub velocity: Option<[f32; 3]>, // Relative to the parent when n-body motion starts
+    pub orbit_color: Option<[f32; 3]>, // Of the orbit path, see orbit_paths.rs
+    // Lighting
+    pub lightsource: bool,
+    pub emission: Option<[f32; 3]>,
+    // Debugging
+    pub debug_normals: bool, // Face normals drawn over the terrain, see normal_debug.rs
+    // Terrain
+    pub archetype: Option<Archetype>, // Preset applied before the values below, see archetype.rs
+    pub max_height: Option<f32>,
+    pub max_lod: Option<usize>,
+    pub color_scheme: Option<[[f32; 3]; N_LAYERS]>,
+    pub color_thresholds: Option<[f32; N_LAYERS - 1]>,
+    pub noise: Option<NoiseParams>,
+    pub detail_strength: Option<f32>,
+    pub detail_scale: Option<f32>,
+    pub texture_strength: Option<f32>,
+    pub texture_scale: Option<[f32; 3]>,
+    pub bands: Option<f32>, // Makes a gas giant, see archetype.rs
+    pub oblateness: Option<f32>,
+    pub glow: Option<f32>,
+    pub surface_features: Option<bool>,
+    pub city_density: Option<f32>,
+    pub vegetation: Option<f32>,
+    // Ocean
+    pub has_ocean: Option<bool>,
+    pub ocean_dark_color: Option<[f32; 3]>,
+    pub ocean_light_color: Option<[f32; 3]>,
+    // Clouds
+    pub has_clouds: Option<bool>,
+    pub cloud_height: Option<f32>,
+    pub cloud_coverage: Option<f32>,
+    pub cloud_density: Option<f32>,
+    pub cloud_size: Option<f32>,
+    pub cloud_wind: Option<f32>,
+    pub cloud_color: Option<[f32; 3]>,
+}
+
+/// Node outside the planets, like props or GUI elements
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NodeDescription {
+    pub name: String,
+    pub shape: Shape,
+    pub gui: bool, // Draw as 2D GUI element, in normalized device coordinates
+    pub dynamic: bool, // Falls towards the nearest planet and rests on it
+    pub debug_normals: bool, // Face normals drawn over it and its children, see normal_debug.rs
+    pub emitter: Option<Preset>, // Particles spawned along the y axis
+    pub gpu_particles: bool, // Emitter simulated in a compute shader, where there is one
+    pub animation: Option<animation::Clip>, // Looped unless told otherwise
+    pub lod: Option<Lod>, // Shapes of less detail far away
+    pub position: [f32; 3],
+    pub rotation: [f32; 3],
+    pub orientation: Option<[f32; 4]>, // Quaternion (x, y, z, w), used instead of rotation
+    pub look_at: Option<[f32; 3]>, // Turns -z to face this point in the parent's space, y up
+    pub scale: [f32; 3],
+    pub material: Material,
+    pub children: Vec<NodeDescription>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Shape {
+    /// Group node without geometry
+    Empty,
+    Cube { size: [f32; 3] },
+    /// Flat rectangle in the xy plane
+    Quad { size: [f32; 2] },
+    /// Wavefront OBJ file, all models in it merged into one mesh
+    Model { path: String },
+    /// glTF 2.0 file, `.gltf` or `.glb`. Skinned models loop the clip called
+    /// `animation`, or their first one.
+    Gltf { path: String, animation: Option<String> },
+    // Generated shapes, see primitives.rs
+    UvSphere { radius: f32, segments: usize, rings: usize },
+    Icosphere { radius: f32, subdivisions: usize },
+    Torus { major_radius: f32, minor_radius: f32, segments: usize, sides: usize },
+    Cylinder { radius: f32, height: f32, segments: usize },
+    Cone { radius: f32, height: f32, segments: usize },
+    /// Flat and facing up, in the xz plane
+    Plane { size: [f32; 2], subdivisions: usize },
+    /// Irregular nucleus shaped by `seed`, with a tail blowing away from
+    /// the sun, see comet.rs
+    Comet { radius: f32, seed: u32 },
+}
+
+/// Shapes drawn instead of the shape of a node beyond their distances, see
+/// lod.rs
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Lod {
+    pub levels: Vec<(f32, Shape)>, // By increasing distance
+    pub fade: f32,                 // Width of the band levels are cross-faded in
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Material {
+    pub color: [f32; 4],
+    pub texture: Option<String>,
+    pub normal_map: Option<String>, // Tangent space, x along u and y along v
+    pub metallic: f32,              // 0 for dielectrics, 1 for metals tinted by their colour
+    pub roughness: f32,             // 0 for a mirror, 1 for a blur of the sky, see ibl.rs
+}
+
+impl Default for NodeDescription {
+    fn default() -> Self {
+        NodeDescription {
+            name: String::new(),
+            shape: Shape::Empty,
+            gui: false,
+            dynamic: false,
+            debug_normals: false,
+            emitter: None,
+            gpu_particles: false,
+            animation: None,
+            lod: None,
+            position: [0.0; 3],
+            rotation: [0.0; 3],
+            orientation: None,
+            look_at: None,
+            scale: [1.0; 3],
+            material: Material::default(),
+            children: vec![],
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            color: [1.0; 4],
+            texture: None,
+            normal_map: None,
+            metallic: 0.0,
+            roughness: 0.5,
+        }
+    }
+}
+
+fn vec3(v: [f32; 3]) -> glm::Vec3 {
+    glm::vec3(v[0], v[1], v[2])
+}
+
+impl SceneDescription {
+    /// Read a scene, as JSON if the extension is `.json` and RON otherwise
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let is_json = Path::new(path).extension().is_some_and(|ext| ext == "json");
+        if is_json {
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))
+        } else {
+            ron::from_str(&text).map_err(|e| format!("{}: {}", path, e))
+        }
+    }
+
+    /// Create planets, lightsources and nodes, the nodes built by
+    /// `build_props`. `seed` is added to every planet seed.
+    pub fn build(&self, seed: u32) -> Result<Scene, String> {
+        let mut planets: Vec<planet::Planet> = vec![];
+        let mut planet_nodes: Vec<Node> = vec![];
+        let mut lightsources = vec![];
+        let mut by_name: HashMap<&str, usize> = HashMap::new();
+        planet::reset_ids();
+
+        for desc in &self.planets {
+            let parent = match &desc.parent {
+                Some(name) => Some(*by_name.get(name.as_str()).ok_or(format!(
+                    "Planet '{}' orbits '{}', which is not listed before it",
+                    desc.name, name
+                ))?),
+                None => None,
+            };
+            let (planet, planet_node) = desc.build(seed, parent, &planets, &planet_nodes);
+            if planet.lightsource {
+                lightsources.push(planet.planet_id);
+            }
+            if !desc.name.is_empty() {
+                by_name.insert(&desc.name, planets.len());
+            }
+            planets.push(planet);
+            planet_nodes.push(planet_node);
+        }
+
+        Ok((planets, planet_nodes, lightsources, self.build_props()))
+    }
+
+    /// Create the nodes outside the planets, like props or GUI elements
+    pub fn build_props(&self) -> Vec<Node> {
+        self.nodes.iter().map(|desc| desc.build()).collect()
+    }
+}
+
+impl PlanetDescription {
+    fn build(
+        &self,
+        seed: u32,
+        parent: Option<usize>,
+        planets: &[planet::Planet],
+        planet_nodes: &[Node],
+    ) -> (planet::Planet, Node) {
+        let mut planet = planet::Planet::with_seed(self.seed.wrapping_add(seed));
+        if let Some(archetype) = self.archetype {
+            archetype.apply(&mut planet);
+        }
+        if let Some(parent) = parent {
+            planet.parent_id = planets[parent].planet_id;
+        }
+        planet.lightsource = self.lightsource;
+        if let Some(v) = self.trajectory { planet.trajectory = v; }
+        if let Some(v) = self.traj_speed { planet.traj_speed = v; }
+        if let Some(v) = self.traj_init_angle { planet.traj_init_angle = vec3(v); }
+        if let Some(v) = self.rot_speed { planet.rot_speed = v; }
+        if let Some(v) = self.rot_axis { planet.rot_axis = glm::normalize(&vec3(v)); }
+        if let Some(v) = self.rot_init_angle { planet.rot_init_angle = v; }
+        planet.mass = self.mass;
+        planet.velocity = self.velocity.map(vec3);
+        planet.orbit_color = self.orbit_color.map(vec3);
+        if let Some(v) = self.emission { planet.emission = vec3(v); }
+        if let Some(v) = self.max_height { planet.max_height = v; }
+        if let Some(v) = self.max_lod { planet.max_lod = v; }
+        if let Some(v) = self.color_scheme { planet.color_scheme = v.map(vec3); }
+        if let Some(v) = self.color_thresholds { planet.color_thresholds = v; }
+        if let Some(v) = self.noise { planet.noise = v; }
+        if let Some(v) = self.detail_strength { planet.detail_strength = v; }
+        if let Some(v) = self.detail_scale { planet.detail_scale = v; }
+        if let Some(v) = self.texture_strength { planet.texture_strength = v; }
+        if let Some(v) = self.texture_scale { planet.texture_scale = v; }
+        if let Some(v) = self.bands { planet.bands = v; }
+        if let Some(v) = self.oblateness { planet.oblateness = v; }
+        if let Some(v) = self.glow { planet.glow = v; }
+        if let Some(v) = self.surface_features { planet.surface_features = v; }
+        if let Some(v) = self.city_density { planet.city_density = v; }
+        if let Some(v) = self.vegetation { planet.vegetation = v; }
+        if let Some(v) = self.has_ocean { planet.has_ocean = v; }
+        if let Some(v) = self.ocean_dark_color { planet.ocean_dark_color = vec3(v); }
+        if let Some(v) = self.ocean_light_color { planet.ocean_light_color = vec3(v); }
+        if let Some(v) = self.has_clouds { planet.has_clouds = v; }
+        if let Some(v) = self.cloud_height { planet.cloud_height = v; }
+        if let Some(v) = self.cloud_coverage { planet.cloud_coverage = v; }
+        if let Some(v) = self.cloud_density { planet.cloud_density = v; }
+        if let Some(v) = self.cloud_size { planet.cloud_size = v; }
+        if let Some(v) = self.cloud_wind { planet.cloud_wind = v; }
+        if let Some(v) = self.cloud_color { planet.cloud_color = vec3(v); }
+
+        let mut planet_node = SceneNode::with_type(SceneNodeType::Empty);
+        planet_node.planet_id = planet.planet_id;
+        planet_node.debug_normals = self.debug_normals;
+        if !self.name.is_empty() {
+            planet_node.body = Some(body_info::BodyMetadata::named(&self.name));
+        }
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+        planet_node.set_scale(glm::vec3(1.0, 1.0, 1.0) * scale);
+        // Start on the trajectory around the parent, as in scene::create_scene
+        let origin = parent.map_or(glm::zero(), |p| planet_nodes[p].position());
+        planet_node.set_position(
+            origin
+                + glm::vec3(
+                    planet.traj_init_angle.x.sin() * planet.trajectory,
+                    planet.traj_init_angle.y,
+                    planet.traj_init_angle.x.cos() * planet.trajectory,
+                ),
+        );
+        planet.node = planet_node.node_id;
+        (planet, planet_node)
+    }
+}
+
+impl Shape {
+    /// Mesh of the shape, with the skeleton of skinned glTF models. Models
+    /// that fail to load are reported, and replaced by a placeholder.
+    fn mesh(&self, color: glm::Vec4) -> Option<(mesh::Mesh, Option<skinning::Skeleton>)> {
+        let mut skeleton = None;
+        let mesh = match self {
+            Shape::Empty => None,
+            Shape::Cube { size } => Some(mesh::Mesh::cube(
+                vec3(*size),
+                glm::vec2(1.0, 1.0), true, false,
+                glm::vec3(1.0, 1.0, 1.0),
+                color,
+            )),
+            Shape::Quad { size } => Some(mesh::Mesh::cube(
+                glm::vec3(size[0], size[1], 0.0),
+                glm::vec2(1.0, 1.0), true, false,
+                glm::vec3(1.0, 1.0, 1.0),
+                color,
+            )),
+            Shape::Model { path } => Some(load_model(path, color).unwrap_or_else(|e| {
+                error::report(&e);
+                placeholder_mesh()
+            })),
+            Shape::Gltf { path, .. } => Some(match gltf::load(path, color) {
+                Ok(model) => {
+                    skeleton = model.skeleton;
+                    model.mesh
+                }
+                Err(e) => {
+                    error::report(&e);
+                    placeholder_mesh()
+                }
+            }),
+            Shape::UvSphere { radius, segments, rings } => {
+                Some(mesh::Mesh::uv_sphere(*radius, *segments, *rings, color))
+            }
+            Shape::Icosphere { radius, subdivisions } => {
+                Some(mesh::Mesh::icosphere(*radius, *subdivisions, color))
+            }
+            Shape::Torus { major_radius, minor_radius, segments, sides } => Some(
+                mesh::Mesh::torus(*major_radius, *minor_radius, *segments, *sides, color),
+            ),
+            Shape::Cylinder { radius, height, segments } => {
+                Some(mesh::Mesh::cylinder(*radius, *height, *segments, color))
+            }
+            Shape::Cone { radius, height, segments } => {
+                Some(mesh::Mesh::cone(*radius, *height, *segments, color))
+            }
+            Shape::Plane { size, subdivisions } => Some(mesh::Mesh::plane(
+                glm::vec2(size[0], size[1]),
+                *subdivisions,
+                color,
+            )),
+            Shape::Comet { radius, seed } => Some(comet::nucleus(*radius, *seed, color)),
+        };
+        mesh.map(|mesh| (mesh, skeleton))
+    }
+}
+
+impl NodeDescription {
+    /// Node with its children. Models and textures that fail to load are
+    /// reported, and replaced by placeholders.
+    fn build(&self) -> Node {
+        let color = glm::vec4(
+            self.material.color[0],
+            self.material.color[1],
+            self.material.color[2],
+            self.material.color[3],
+        );
+        // Joints of a skinned model, added below it after the children
+        let (mesh, skeleton) = match self.shape.mesh(color) {
+            Some((mesh, skeleton)) => (Some(mesh), skeleton),
+            None => (None, None),
+        };
+
+        let mut node = match mesh {
+            Some(mesh) => {
+                let layout = match skeleton {
+                    Some(_) => mesh::VertexLayout::SKINNED,
+                    None => mesh::VertexLayout::NORMAL_MAPPED,
+                };
+                let mut node = SceneNode::from_vao(unsafe { mesh.mkvao_with(layout.interleaved()) });
+                node.bounds = mesh.bounding_radius();
+                node
+            }
+            None => SceneNode::with_type(SceneNodeType::Empty),
+        };
+        if self.gui && node.node_type != SceneNodeType::Empty {
+            node.node_type = SceneNodeType::Geometry2d;
+        }
+        node.name = self.name.clone();
+        node.dynamic = self.dynamic;
+        node.debug_normals = self.debug_normals;
+        node.emitter = self.emitter.map(|preset| {
+            let mut emitter = Emitter::preset(preset);
+            emitter.gpu = self.gpu_particles;
+            emitter
+        });
+        node.animation = self.animation.clone().map(animation::Animation::new);
+        node.lod = self.lod.as_ref().map(|lod| lod::LodGroup {
+            levels: lod
+                .levels
+                .iter()
+                .filter_map(|(distance, shape)| {
+                    let (mesh, _) = shape.mesh(color)?;
+                    let layout = mesh::VertexLayout::NORMAL_MAPPED.interleaved();
+                    let vao = unsafe { mesh.mkvao_with(layout) };
+                    Some(lod::LodLevel::new(vao, *distance))
+                })
+                .collect(),
+            fade: lod.fade,
+        });
+        node.set_position(vec3(self.position));
+        node.set_rotation(vec3(self.rotation));
+        node.set_orientation(self.orientation.map(|[x, y, z, w]| glm::quat(x, y, z, w)));
+        if let Some(target) = self.look_at {
+            node.look_at(&vec3(target), &glm::vec3(0.0, 1.0, 0.0));
+        }
+        node.set_scale(vec3(self.scale));
+        // GUI nodes are drawn over the scene, in display colours, and loaded
+        // at once so they don't pop in
+        node.texture_id = self.material.texture.as_deref().map(|path| {
+            if self.gui {
+                texture::load_texture(path).unwrap_or_else(|e| {
+                    error::report(&e);
+                    texture::placeholder()
+                })
+            } else {
+                texture::load_color_texture_async(path)
+            }
+        });
+        // Flat until loaded, and without the normal map
+        node.normal_map_id = self
+            .material
+            .normal_map
+            .as_deref()
+            .map(|path| texture::load_texture_async(path, [128, 128, 255, 255]));
+        node.metallic = self.material.metallic;
+        node.roughness = self.material.roughness;
+        for child in &self.children {
+            let child = child.build();
+            node.add_child(&child);
+        }
+        if let Shape::Comet { .. } = self.shape {
+            node.add_child(&comet::tail(self.gpu_particles));
+        }
+        if let (Some(skeleton), Shape::Gltf { animation, .. }) = (skeleton, &self.shape) {
+            unsafe { skinning::attach(&mut node, skeleton, animation.as_deref()) };
+        }
+        node
+    }
+}
+
+/// Mesh of an OBJ file, its models merged into one
+pub fn load_model(path: &str, color: glm::Vec4) -> error::Result<mesh::Mesh> {
+    let options = tobj::LoadOptions {
+        triangulate: true,
+        single_index: true,
+        ..Default::default()
+    };
+    let (models, _) = tobj::load_obj(path, &options).map_err(|source| Error::Model {
+        path: path.to_string(),
+        source,
+    })?;
+    let mut merged = mesh::Mesh::default();
+    for model in models {
+        merged.merge(&mesh::Mesh::from(model.mesh, color));
+    }
+    let report = merged.optimize();
+    eprintln!("Optimized '{}': {}", path, report);
+    Ok(merged)
+}
+
+/// Magenta cube, in place of a model that failed to load
+fn placeholder_mesh() -> mesh::Mesh {
+    mesh::Mesh::cube(
+        glm::vec3(1.0, 1.0, 1.0),
+        glm::vec2(1.0, 1.0), true, false,
+        glm::vec3(1.0, 1.0, 1.0),
+        glm::vec4(1.0, 0.0, 1.0, 1.0),
+    )
+}
+
+//-----------------------------------------------------------------------------/
+// Save the current state of a scene
+//-----------------------------------------------------------------------------/
+impl SceneDescription {
+    /// Description of the scene as it is now, with orbits advanced to where
+    /// they are after `elapsed` seconds. `seed` is subtracted from planet
+    /// seeds, as it's added again when loading, and distances are divided by
+    /// `unit_scale`, to undo scaling while anchored to a planet. With `nbody`
+    /// simulating the orbits, each body is saved where it is and as fast as
+    /// it goes relative to its parent.
+    ///
+    /// Scene nodes don't keep their shape or material, so nodes are taken
+    /// from `self`, the description the scene was built from, with their
+    /// transforms updated. Planets are matched to their parents by id.
+    #[allow(clippy::too_many_arguments)]
+    pub fn capture(
+        &self,
+        planets: &[planet::Planet],
+        planet_nodes: &[Node],
+        props: &[Node],
+        nbody: Option<&nbody::NBody>,
+        seed: u32,
+        elapsed: f64,
+        unit_scale: f32,
+    ) -> Self {
+        let names: Vec<String> = (0..planets.len())
+            .map(|i| match self.planets.get(i) {
+                Some(desc) if !desc.name.is_empty() => desc.name.clone(),
+                _ => format!("planet{}", i),
+            })
+            .collect();
+        // Planets are matched to their parents by id
+        let index: HashMap<usize, usize> =
+            planets.iter().enumerate().map(|(i, planet)| (planet.planet_id, i)).collect();
+
+        let planets = planets
+            .iter()
+            .zip(planet_nodes)
+            .enumerate()
+            .map(|(i, (planet, node))| {
+                let parent = (planet.parent_id != planet.planet_id)
+                    .then(|| names[index[&planet.parent_id]].clone());
+                let mut angle = planet.traj_init_angle;
+                angle.x = nbody::orbit_angle(planet, elapsed) % std::f32::consts::TAU;
+                let mut trajectory = planet.trajectory / unit_scale;
+                let mut velocity = planet.velocity;
+                let mut mass = planet.mass;
+                // Simulated orbits start again from where the bodies are now
+                if let Some(nbody) = nbody {
+                    mass = Some(nbody.mass(planet.planet_id));
+                    if parent.is_some() {
+                        let offset = nbody.offset(planet.planet_id, planet.parent_id, 1.0);
+                        angle.x = offset.x.atan2(offset.z).rem_euclid(std::f32::consts::TAU);
+                        angle.y = offset.y;
+                        trajectory = glm::length(&glm::vec2(offset.x, offset.z));
+                        velocity = Some(nbody.velocity(planet.planet_id, planet.parent_id));
+                    }
+                }
+                let turned = (planet.rot_speed as f64 * WORLD_SPEED as f64 * elapsed) % std::f64::consts::TAU;
+                let rot_angle = (turned as f32 + planet.rot_init_angle) % std::f32::consts::TAU;
+                PlanetDescription {
+                    name: names[i].clone(),
+                    seed: planet.seed.wrapping_sub(seed),
+                    parent,
+                    scale: node.scale().x / unit_scale,
+                    trajectory: Some(trajectory),
+                    traj_speed: Some(planet.traj_speed),
+                    traj_init_angle: Some(angle.into()),
+                    rot_speed: Some(planet.rot_speed),
+                    rot_axis: Some(planet.rot_axis.into()),
+                    rot_init_angle: Some(rot_angle),
+                    mass,
+                    velocity: velocity.map(|v| v.into()),
+                    orbit_color: planet.orbit_color.map(|c| c.into()),
+                    lightsource: planet.lightsource,
+                    emission: Some(planet.emission.into()),
+                    debug_normals: node.debug_normals,
+                    archetype: Some(planet.archetype),
+                    max_height: Some(planet.max_height),
+                    max_lod: Some(planet.max_lod),
+                    color_scheme: Some(planet.color_scheme.map(|c| c.into())),
+                    color_thresholds: Some(planet.color_thresholds),
+                    noise: Some(planet.noise),
+                    detail_strength: Some(planet.detail_strength),
+                    detail_scale: Some(planet.detail_scale),
+                    texture_strength: Some(planet.texture_strength),
+                    texture_scale: Some(planet.texture_scale),
+                    bands: Some(planet.bands),
+                    oblateness: Some(planet.oblateness),
+                    glow: Some(planet.glow),
+                    surface_features: Some(planet.surface_features),
+                    city_density: Some(planet.city_density),
+                    vegetation: Some(planet.vegetation),
+                    has_ocean: Some(planet.has_ocean),
+                    ocean_dark_color: Some(planet.ocean_dark_color.into()),
+                    ocean_light_color: Some(planet.ocean_light_color.into()),
+                    has_clouds: Some(planet.has_clouds),
+                    cloud_height: Some(planet.cloud_height),
+                    cloud_coverage: Some(planet.cloud_coverage),
+                    cloud_density: Some(planet.cloud_density),
+                    cloud_size: Some(planet.cloud_size),
+                    cloud_wind: Some(planet.cloud_wind),
+                    cloud_color: Some(planet.cloud_color.into()),
+                }
+            })
+            .collect();
+
+        let nodes = self
+            .nodes
+            .iter()
+            .zip(props)
+            .map(|(desc, node)| {
+                let mut prop = desc.capture(node);
+                // Dynamic props are placed in the frames of the scaled planets
+                if node.dynamic {
+                    prop.position = (node.position() / unit_scale).into();
+                }
+                prop
+            })
+            .collect();
+
+        SceneDescription { planets, nodes }
+    }
+
+    /// Write the scene, as JSON if the extension is `.json` and RON otherwise
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let is_json = Path::new(path).extension().is_some_and(|ext| ext == "json");
+        let text = if is_json {
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())?
+        } else {
+            ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(|e| e.to_string())?
+        };
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        std::fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+impl NodeDescription {
+    /// Copy of the description with the transforms of the node built from it
+    fn capture(&self, node: &SceneNode) -> Self {
+        let children = self
+            .children
+            .iter()
+            .zip(&node.children)
+            .map(|(desc, &child)| desc.capture(unsafe { &*child }))
+            .collect();
+        NodeDescription {
+            position: node.position().into(),
+            rotation: node.rotation().into(),
+            orientation: node.explicit_orientation().map(|q| q.coords.into()),
+            look_at: None, // Kept in the orientation
+            scale: node.scale().into(),
+            children,
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn planet_ids_index_each_built_scene() {
+        let description: SceneDescription = ron::from_str(
+            r#"(planets: [(name: "sun"), (name: "earth", parent: Some("sun")), (name: "moon", parent: Some("earth"))])"#,
+        )
+        .unwrap();
+        for _ in 0..2 {
+            let (planets, _, _, _) = description.build(0).unwrap();
+            let ids: Vec<usize> = planets.iter().map(|planet| planet.planet_id).collect();
+            let parents: Vec<usize> = planets.iter().map(|planet| planet.parent_id).collect();
+            assert_eq!(ids, [0, 1, 2]);
+            assert_eq!(parents, [0, 0, 1]);
+        }
+    }
+}
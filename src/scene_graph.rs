@@ -5,7 +5,8 @@ use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
-use crate::{mesh, util};
+use crate::render_queue::{DrawCommand, RenderQueue};
+use crate::{animation, body_info, lod, mesh, particles, profile_scope, shader, skinning, util};
 
 static NODE_COUNTER: AtomicU64 = AtomicU64::new(0);
 
@@ -33,6 +34,7 @@ pub enum VAOStatus {
     NotStarted,
     Generating,
     Ready,
+    ReadyUndisplaced, // Planet terrain still to be displaced on the GPU
 }
 impl Default for VAOStatus {
     fn default() -> Self {
@@ -62,6 +64,7 @@ pub enum SceneNodeType {
     Geometry2d = 2, // For gui
     Planet = 3,     // Planet terrain shader
     Ocean = 4,      // Planet ocean shader
+    Cloud = 5,      // Planet cloud shell shader
     Empty,          // Empty nodes with other functions
     PlanetSkip,     // Planet, but skip this one, return
 }
@@ -69,22 +72,41 @@ pub enum SceneNodeType {
 pub struct SceneNode {
     pub node_id: usize,
     pub planet_id: usize,           // Used if node belongs to a planet
-    pub position: glm::Vec3,        // Where I am in relation to my parent
-    pub rotation: glm::Vec3,        // How I should be rotated
-    pub scale: glm::Vec3,           // How I should be scaled
+    pub lod_level: usize,           // Level of planet terrain patches
+    position: glm::DVec3,           // Where I am in relation to my parent, kept in double precision
+    rotation: glm::Vec3,            // How I should be rotated
+    orientation: Option<glm::Quat>, // Used instead of rotation when set
+    scale: glm::Vec3,               // How I should be scaled
     pub reference_point: glm::Vec3, // About which point I shall rotate about
+    dirty: bool,                    // Moved since my transformation was computed, see mark_dirty
 
     pub node_type: SceneNodeType,
     pub name: String,
     pub current_transformation_matrix: glm::Mat4, // The fruits of my labor
+    pub world_transformation: glm::DMat4,         // The same, kept in double precision
     pub distance: f32,                            // Distance to player
+    pub selected: bool,                           // Highlighted, with everything below it
+    pub debug_normals: bool,                      // Face normals drawn, below too, see normal_debug.rs
+    pub occluded: bool,                           // Hidden behind a planet, see occlusion.rs
+    pub bounds: f32,                              // Radius of my mesh about my origin, 0 if unknown
+    pub dynamic: bool,                            // Falls towards the nearest planet, see physics.rs
+    pub emitter: Option<particles::Emitter>,      // Spawns particles, see particles.rs
+    pub skin: Option<skinning::Skin>,             // Posed by the joints below, see skinning.rs
+    pub animation: Option<animation::Animation>,  // Clip played, see animation.rs
+    pub body: Option<body_info::BodyMetadata>,    // Shown when a planet is selected, see body_info.rs
 
     pub vao: mesh::VAOobj,                                 // What I should draw
     pub index_count: i32,                                  // How much of it I shall draw
+    pub shader: Option<u32>,                               // Program I'm drawn with, if not the default
+    pub lod: Option<lod::LodGroup>,                        // Less detail far away, see lod.rs
     pub vao_generate: Arc<Mutex<(VAOStatus, mesh::Mesh)>>, // False if not ready
 
     // IDs of maps
     pub texture_id: Option<u32>,
+    pub normal_map_id: Option<u32>, // Tangent space, needs a mesh with tangents
+    pub tint: glm::Vec4,            // Multiplies the colour of geometry
+    pub metallic: f32,              // How much the sky is reflected, see ibl.rs
+    pub roughness: f32,             // How blurred the reflection is
 
     pub children: Vec<*mut SceneNode>, // Those I command
 }
@@ -94,18 +116,37 @@ impl SceneNode {
         ManuallyDrop::new(Pin::new(Box::new(SceneNode {
             node_id: NODE_COUNTER.fetch_add(1, Ordering::Relaxed) as usize,
             planet_id: 0,
+            lod_level: 0,
             position: glm::zero(),
             rotation: glm::zero(),
+            orientation: None,
             scale: glm::vec3(1.0, 1.0, 1.0),
             reference_point: glm::zero(),
+            dirty: true,
             node_type: SceneNodeType::Empty,
             name: String::new(),
             current_transformation_matrix: glm::identity(),
+            world_transformation: glm::identity(),
             distance: 0.0,
+            selected: false,
+            debug_normals: false,
+            occluded: false,
+            bounds: 0.0,
+            dynamic: false,
+            emitter: None,
+            skin: None,
+            animation: None,
+            body: None,
             vao: Default::default(),
             index_count: -1,
+            shader: None,
+            lod: None,
             vao_generate: Arc::new(Mutex::new((VAOStatus::default(), mesh::Mesh::default()))),
             texture_id: None,
+            normal_map_id: None,
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 0.5,
             children: vec![],
         })))
     }
@@ -114,18 +155,37 @@ impl SceneNode {
         ManuallyDrop::new(Pin::new(Box::new(SceneNode {
             node_id: NODE_COUNTER.fetch_add(1, Ordering::Relaxed) as usize,
             planet_id: 0,
+            lod_level: 0,
             position: glm::zero(),
             rotation: glm::zero(),
+            orientation: None,
             scale: glm::vec3(1.0, 1.0, 1.0),
             reference_point: glm::zero(),
+            dirty: true,
             node_type,
             name: String::new(),
             current_transformation_matrix: glm::identity(),
+            world_transformation: glm::identity(),
             distance: 0.0,
+            selected: false,
+            debug_normals: false,
+            occluded: false,
+            bounds: 0.0,
+            dynamic: false,
+            emitter: None,
+            skin: None,
+            animation: None,
+            body: None,
             vao: Default::default(),
             index_count: -1,
+            shader: None,
+            lod: None,
             vao_generate: Arc::new(Mutex::new((VAOStatus::default(), mesh::Mesh::default()))),
             texture_id: None,
+            normal_map_id: None,
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 0.5,
             children: vec![],
         })))
     }
@@ -134,18 +194,37 @@ impl SceneNode {
         ManuallyDrop::new(Pin::new(Box::new(SceneNode {
             node_id: NODE_COUNTER.fetch_add(1, Ordering::Relaxed) as usize,
             planet_id: 0,
+            lod_level: 0,
             position: glm::zero(),
             rotation: glm::zero(),
+            orientation: None,
             scale: glm::vec3(1.0, 1.0, 1.0),
             reference_point: glm::zero(),
+            dirty: true,
             node_type: SceneNodeType::Geometry,
             name: String::new(),
             current_transformation_matrix: glm::identity(),
+            world_transformation: glm::identity(),
             distance: 0.0,
+            selected: false,
+            debug_normals: false,
+            occluded: false,
+            bounds: 0.0,
+            dynamic: false,
+            emitter: None,
+            skin: None,
+            animation: None,
+            body: None,
             vao: vao,
             index_count: vao.n,
+            shader: None,
+            lod: None,
             vao_generate: Arc::new(Mutex::new((VAOStatus::Ready, mesh::Mesh::default()))),
             texture_id: None,
+            normal_map_id: None,
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 0.5,
             children: vec![],
         })))
     }
@@ -155,9 +234,93 @@ impl SceneNode {
         self.index_count = vao.n;
     }
 
+    /// Delete GPU buffers, so the mesh will be regenerated when needed again
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn free_buffers(&mut self) {
+        if self.index_count == -1 {
+            return;
+        }
+        self.vao.delete();
+        self.index_count = -1;
+        *self.vao_generate.lock().unwrap() = (VAOStatus::NotStarted, mesh::Mesh::default());
+    }
+
+    /// Drop a node and everything below it, deleting their GPU buffers.
+    /// Nothing else may hold the nodes below, as they are freed too.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context. Nothing else may use `node` or the nodes below it afterwards.
+    pub unsafe fn destroy(node: Node) {
+        let node = ManuallyDrop::into_inner(node);
+        for &child in &node.children {
+            Self::destroy(ManuallyDrop::new(Pin::new(Box::from_raw(child))));
+        }
+    }
+
     pub fn add_child(&mut self, child: &SceneNode) {
         self.children
-            .push(child as *const SceneNode as *mut SceneNode)
+            .push(child as *const SceneNode as *mut SceneNode);
+        // The child may have been below another parent
+        self.dirty = true;
+    }
+
+    /// Recompute the transformation of this node and those below it on the
+    /// next update. The setters below do this when the value changes, this
+    /// is for `reference_point` or changes above the transformations.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn position(&self) -> glm::Vec3 {
+        self.position.cast()
+    }
+
+    pub fn position_f64(&self) -> glm::DVec3 {
+        self.position
+    }
+
+    pub fn rotation(&self) -> glm::Vec3 {
+        self.rotation
+    }
+
+    pub fn scale(&self) -> glm::Vec3 {
+        self.scale
+    }
+
+    /// The quaternion set in place of the Euler angles, if any
+    pub fn explicit_orientation(&self) -> Option<glm::Quat> {
+        self.orientation
+    }
+
+    // Setters, marking the node dirty when the value changes
+    pub fn set_position(&mut self, position: glm::Vec3) {
+        self.set_position_f64(position.cast());
+    }
+
+    /// Set the position without rounding it, for large orbits and deep
+    /// hierarchies
+    pub fn set_position_f64(&mut self, position: glm::DVec3) {
+        self.dirty |= self.position != position;
+        self.position = position;
+    }
+
+    pub fn set_rotation(&mut self, rotation: glm::Vec3) {
+        self.dirty |= self.rotation != rotation;
+        self.rotation = rotation;
+    }
+
+    pub fn set_scale(&mut self, scale: glm::Vec3) {
+        self.dirty |= self.scale != scale;
+        self.scale = scale;
+    }
+
+    /// Rotate by `orientation` instead of the Euler angles, or by the angles
+    /// again with `None`
+    pub fn set_orientation(&mut self, orientation: Option<glm::Quat>) {
+        self.dirty |= self.orientation != orientation;
+        self.orientation = orientation;
     }
 
     #[allow(dead_code)]
@@ -218,29 +381,150 @@ impl SceneNode {
         );
     }
 
-    /// Update node transformations and accumulate global uniforms
-    pub unsafe fn update_node_transformations(
-        &mut self,
-        transformation_so_far: &glm::Mat4,
-        player_position: &glm::TVec3<f32>,
-    ) {
-        //eprintln!("node_id: {} VAO: {}, index_count: {}", self.node_id, self.vao.vao, self.index_count);
-        // Construct the correct transformation matrix
+    /// Rotation relative to the parent, from the Euler angles unless the
+    /// orientation is set
+    pub fn orientation(&self) -> glm::Quat {
+        self.orientation
+            .unwrap_or_else(|| util::quat_from_euler(&self.rotation))
+    }
+
+    /// Turn so the -z axis points at `target` and the y axis is as close to
+    /// `up` as possible, both in the parent's space
+    pub fn look_at(&mut self, target: &glm::Vec3, up: &glm::Vec3) {
+        let direction = target - self.position();
+        if glm::length2(&direction) > 0.0 {
+            // quat_look_at gives the view rotation, turning the world to the
+            // node, the node turns the other way
+            self.orientation = Some(glm::quat_conjugate(&glm::quat_look_at(&direction, up)));
+            self.dirty = true;
+        }
+    }
+
+    /// Rotate by `angle` radians about `axis`, in the parent's space
+    pub fn rotate_about_axis(&mut self, axis: &glm::Vec3, angle: f32) {
+        let rotation = glm::quat_angle_axis(angle, &glm::normalize(axis));
+        self.orientation = Some(glm::quat_normalize(&(rotation * self.orientation())));
+        self.dirty = true;
+    }
+
+    /// Transformation relative to the parent, computed in double precision
+    pub fn local_transformation(&self) -> glm::DMat4 {
+        let reference_point: glm::DVec3 = self.reference_point.cast();
+        let rotation: glm::DVec3 = self.rotation.cast();
         let mut transform = glm::identity();
         // Translate
         transform = glm::translate(&transform, &self.position);
         // Rotate around reference point
-        transform = glm::translate(&transform, &(self.reference_point));
-        transform = glm::rotate_y(&transform, self.rotation[1]);
-        transform = glm::rotate_z(&transform, self.rotation[2]);
-        transform = glm::rotate_x(&transform, self.rotation[0]);
+        transform = glm::translate(&transform, &reference_point);
+        match self.orientation {
+            Some(orientation) => transform *= glm::quat_to_mat4(&orientation).cast::<f64>(),
+            None => {
+                transform = glm::rotate_y(&transform, rotation[1]);
+                transform = glm::rotate_z(&transform, rotation[2]);
+                transform = glm::rotate_x(&transform, rotation[0]);
+            }
+        }
         // Move back from reference point
-        transform = glm::translate(&transform, &(-self.reference_point));
+        transform = glm::translate(&transform, &(-reference_point));
         // Scale
-        transform = glm::scale(&transform, &self.scale);
+        glm::scale(&transform, &self.scale.cast())
+    }
+
+    /// Transformation relative to the parent, computed in single precision
+    /// throughout, to compare against `local_transformation`
+    fn local_transformation_f32(&self) -> glm::Mat4 {
+        let mut transform = glm::translate(&glm::identity(), &self.position());
+        transform = glm::translate(&transform, &self.reference_point);
+        transform *= glm::quat_to_mat4(&self.orientation());
+        transform = glm::translate(&transform, &(-self.reference_point));
+        glm::scale(&transform, &self.scale)
+    }
+
+    /// Update node transformations and accumulate global uniforms. Only nodes
+    /// marked dirty and those below them are recomputed, `transformation_so_far`
+    /// is assumed the same as last time. Returns the number of nodes skipped.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context, and every node below must be live.
+    pub unsafe fn update_node_transformations(
+        &mut self,
+        transformation_so_far: &glm::Mat4,
+        player_position: &glm::TVec3<f32>,
+    ) -> usize {
+        self.update_transformations(transformation_so_far, player_position, false)
+    }
 
+    unsafe fn update_transformations(
+        &mut self,
+        transformation_so_far: &glm::Mat4,
+        player_position: &glm::TVec3<f32>,
+        parent_moved: bool,
+    ) -> usize {
+        //eprintln!("node_id: {} VAO: {}, index_count: {}", self.node_id, self.vao.vao, self.index_count);
         // Update the node's transformation matrix
-        self.current_transformation_matrix = transformation_so_far * transform;
+        let moved = self.dirty || parent_moved;
+        let mut skipped = 0;
+        if moved {
+            self.current_transformation_matrix = transformation_so_far * self.local_transformation_f32();
+            self.world_transformation = self.current_transformation_matrix.cast();
+            self.dirty = false;
+        } else {
+            skipped += 1;
+        }
+        self.update_distance(player_position);
+        // Recurse
+        for &child in &self.children {
+            skipped += (&mut *child).update_transformations(
+                &self.current_transformation_matrix,
+                player_position,
+                moved,
+            );
+        }
+        skipped
+    }
+
+    /// Update node transformations like `update_node_transformations`, but
+    /// accumulated in double precision and only rounded to single precision
+    /// for each node, so deep hierarchies and large orbits don't add up errors
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context, and every node below must be live.
+    pub unsafe fn update_node_transformations_f64(
+        &mut self,
+        transformation_so_far: &glm::DMat4,
+        player_position: &glm::TVec3<f32>,
+    ) -> usize {
+        self.update_transformations_f64(transformation_so_far, player_position, false)
+    }
+
+    unsafe fn update_transformations_f64(
+        &mut self,
+        transformation_so_far: &glm::DMat4,
+        player_position: &glm::TVec3<f32>,
+        parent_moved: bool,
+    ) -> usize {
+        let moved = self.dirty || parent_moved;
+        let mut skipped = 0;
+        if moved {
+            self.world_transformation = transformation_so_far * self.local_transformation();
+            self.current_transformation_matrix = self.world_transformation.cast();
+            self.dirty = false;
+        } else {
+            skipped += 1;
+        }
+        self.update_distance(player_position);
+        for &child in &self.children {
+            skipped += (&mut *child).update_transformations_f64(
+                &self.world_transformation,
+                player_position,
+                moved,
+            );
+        }
+        skipped
+    }
+
+    /// Distance from the player to the bounds of the node
+    fn update_distance(&mut self, player_position: &glm::TVec3<f32>) {
         let position = glm::vec4_to_vec3(
             &(self.current_transformation_matrix * glm::vec4(0.0, 0.0, 0.0, 1.0)),
         );
@@ -250,90 +534,163 @@ impl SceneNode {
             self.current_transformation_matrix[4 * 2 + 2],
         );
         self.distance = glm::length(&(player_position - position)) - scale.x;
-        // Recurse
-        for &child in &self.children {
-            (&mut *child)
-                .update_node_transformations(&self.current_transformation_matrix, player_position);
-        }
     }
 
-    /// Draw scene from scene graph
+    /// Draw scene from scene graph, through a sorted render queue
     /// * `node` - Current node
     /// * `view_projection_matrix` - Precalculated view and perspective matrix
     /// * `sh` - Active shader
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context, and every node below must be live.
     pub unsafe fn draw_scene(
         &self,
         view_projection_matrix: &glm::Mat4,
         sh: &crate::shader::Shader,
         clipping: (f32, f32),
+    ) {
+        let mut queue = RenderQueue::new();
+        self.collect_draws(view_projection_matrix, sh.program_id, clipping, &mut queue);
+        queue.sort();
+        queue.submit(sh);
+    }
+
+    /// Traverse scene graph and push draw commands for drawable nodes
+    /// * `view_projection_matrix` - Precalculated view and perspective matrix,
+    ///   with the view relative to `queue.origin`
+    /// * `program` - Shader program to draw with, where the node or its type
+    ///   has none of its own
+    /// * `clipping` - Near and far plane of the current pass
+    /// * `queue` - Render queue to collect draws in
+    pub fn collect_draws(
+        &self,
+        view_projection_matrix: &glm::Mat4,
+        program: u32,
+        clipping: (f32, f32),
+        queue: &mut RenderQueue,
+    ) {
+        profile_scope!("culling");
+        self.collect_draws_below(view_projection_matrix, program, clipping, false, false, queue);
+    }
+
+    /// Collect draws, `selected` and with `debug_normals` if a parent is
+    fn collect_draws_below(
+        &self,
+        view_projection_matrix: &glm::Mat4,
+        program: u32,
+        clipping: (f32, f32),
+        selected: bool,
+        debug_normals: bool,
+        queue: &mut RenderQueue,
     ) {
         use SceneNodeType::*;
-        // Check if node is drawable, set model specific uniforms, draw
+        if self.occluded {
+            return;
+        }
+        let selected = selected || self.selected;
+        let debug_normals = debug_normals || self.debug_normals;
+        // Check if node is drawable, compute model specific uniforms
         match self.node_type {
             PlanetSkip => return,
-            Geometry | Geometry2d | Planet | Ocean | Skybox => {
+            Geometry | Geometry2d | Planet | Ocean | Cloud | Skybox => {
                 if self.index_count != -1
-                    && (!matches!(self.node_type, Ocean | Planet)
+                    && (!matches!(self.node_type, Ocean | Planet | Cloud)
                         || (self.distance >= clipping.0 || self.distance < 10.0 * clipping.0))
                 {
-                    gl::BindVertexArray(self.vao.vao);
-
-                    let u_node_type = sh.get_uniform_location("u_node_type");
-                    gl::Uniform1ui(u_node_type, self.node_type as u32);
-                    // Applies only for planets, but send anyway
-                    let u_node_type = sh.get_uniform_location("u_current_planet_id");
-                    gl::Uniform1ui(u_node_type, self.planet_id as u32);
-
-                    let u_mvp = sh.get_uniform_location("u_mvp");
-                    let mvp = match self.node_type {
+                    let model = match self.node_type {
                         SceneNodeType::Geometry2d => self.current_transformation_matrix,
-                        _ => view_projection_matrix * self.current_transformation_matrix,
+                        _ => queue.rebase(&self.world_transformation),
                     };
-                    gl::UniformMatrix4fv(u_mvp, 1, gl::FALSE, mvp.as_ptr());
-
-                    let u_model = sh.get_uniform_location("u_model");
-                    gl::UniformMatrix4fv(
-                        u_model,
-                        1,
-                        gl::FALSE,
-                        self.current_transformation_matrix.as_ptr(),
-                    );
-
-                    // Bind textures, or signal that none exist
-                    let u_has_texture = sh.get_uniform_location("u_has_texture");
-                    if let Some(texture_id) = self.texture_id {
-                        gl::BindTextureUnit(0, texture_id);
-                        gl::Uniform1i(u_has_texture, 1);
-                    } else {
-                        gl::Uniform1i(u_has_texture, 1);
+                    let mvp = match self.node_type {
+                        SceneNodeType::Geometry2d => model,
+                        _ => view_projection_matrix * model,
+                    };
+                    // Level of detail, and the next one when fading into it
+                    let (level, next) = match self.lod.as_ref() {
+                        Some(lod) => lod.select(self.distance),
+                        None => ((0, 1.0), None),
+                    };
+                    for (level, fade) in std::iter::once(level).chain(next) {
+                        let (vao, index_count, skin) =
+                            match self.lod.as_ref().and_then(|lod| lod.level(level)) {
+                                Some(level) => (level.vao, level.index_count, 0), // Unskinned
+                                None => (
+                                    self.vao,
+                                    self.index_count,
+                                    self.skin.as_ref().map_or(0, |skin| skin.buffer()),
+                                ),
+                            };
+                        queue.push(DrawCommand {
+                            program: self
+                                .shader
+                                .or_else(|| shader::program_for(self.node_type))
+                                .unwrap_or(program),
+                            node_id: self.node_id,
+                            vao: vao.vao,
+                            vbo: vao.vbo,
+                            ibo: vao.ibo,
+                            index_count,
+                            node_type: self.node_type,
+                            planet_id: self.planet_id,
+                            lod_level: self.lod_level,
+                            texture_id: self.texture_id,
+                            normal_map_id: self.normal_map_id,
+                            skin,
+                            reflection: 0,
+                            city_lights: 0,
+                            tint: self.tint,
+                            metallic: self.metallic,
+                            roughness: self.roughness,
+                            fade,
+                            selected,
+                            debug_normals,
+                            mvp,
+                            previous_mvp: mvp,
+                            model,
+                            depth: match self.node_type {
+                                Geometry2d => 0.0,
+                                _ => self.distance,
+                            },
+                        });
                     }
-
-                    gl::DrawElements(
-                        gl::TRIANGLES,
-                        self.index_count,
-                        gl::UNSIGNED_INT,
-                        std::ptr::null(),
-                    );
                 }
-                if matches!(self.node_type, Ocean | Planet) {
+                if matches!(self.node_type, Ocean | Planet | Cloud) {
                     return;
-                } // Planet and Ocean mesh can't have children
+                } // Planet, Ocean and Cloud mesh can't have children
             }
             _ => (),
         }
 
         // Recurse
         for &child in &self.children {
-            (&*child).draw_scene(view_projection_matrix, sh, clipping);
+            unsafe { &*child }.collect_draws_below(
+                view_projection_matrix,
+                program,
+                clipping,
+                selected,
+                debug_normals,
+                queue,
+            );
         }
     }
 
+    /// Replace a mesh changing every so often, without stalling on draws of
+    /// the old one, see `VAOobj::update_dynamic`. Works with any layout.
+    pub fn update_buffers_dynamic(&mut self, mesh: &mesh::Mesh) {
+        unsafe { self.vao.update_dynamic(mesh) };
+        self.index_count = mesh.index_count;
+    }
+
+    /// Replace the mesh, uploaded with separate buffers by `Mesh::mkvao`
     pub fn update_buffers(&mut self, mesh: &mesh::Mesh) {
+        debug_assert!(!self.vao.layout.interleaved, "Interleaved buffers are updated as a whole");
         unsafe { self.update_vertex_buffer(mesh) };
         unsafe { self.update_normal_buffer(mesh) };
         unsafe { self.update_texture_buffer(mesh) };
         unsafe { self.update_index_buffer(mesh) };
     }
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn update_vertex_buffer(&self, mesh: &mesh::Mesh) {
         gl::BindVertexArray(self.vao.vao);
         gl::BindBuffer(gl::ARRAY_BUFFER, self.vao.vbo);
@@ -348,6 +705,8 @@ impl SceneNode {
             gl::STATIC_DRAW,
         );
     }
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn update_index_buffer(&mut self, mesh: &mesh::Mesh) {
         gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.vao.ibo);
 
@@ -362,6 +721,8 @@ impl SceneNode {
         );
         self.index_count = mesh.index_count;
     }
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn update_normal_buffer(&self, mesh: &mesh::Mesh) {
         gl::BindVertexArray(self.vao.vao);
         gl::BindBuffer(gl::ARRAY_BUFFER, self.vao.nbo);
@@ -376,6 +737,8 @@ impl SceneNode {
             gl::STATIC_DRAW,
         );
     }
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn update_texture_buffer(&self, mesh: &mesh::Mesh) {
         gl::BindVertexArray(self.vao.vao);
         gl::BindBuffer(gl::ARRAY_BUFFER, self.vao.texbo);
@@ -392,6 +755,13 @@ impl SceneNode {
     }
 }
 
+// Only runs for nodes taken out of their ManuallyDrop, see `SceneNode::destroy`
+impl Drop for SceneNode {
+    fn drop(&mut self) {
+        unsafe { self.vao.delete() };
+    }
+}
+
 // You can also use square brackets to access the children of a SceneNode
 use std::ops::{Index, IndexMut};
 impl Index<usize> for SceneNode {
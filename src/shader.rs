@@ -1,6 +1,12 @@
 use gl;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::{ffi::CString, path::Path, ptr, str};
 
+use crate::capabilities;
+use crate::error::{self, Error, Result};
+use crate::scene_graph::SceneNodeType;
+
 pub struct Shader {
     pub program_id: u32,
 }
@@ -10,12 +16,19 @@ pub struct Shader {
 /// The snippet is not enough to do the assignment, and will need to be modified (outside of
 /// just using the correct path), but it only needs to be called once
 ///
-///     shader::ShaderBuilder::new()
-///        .attach_file("./path/to/shader.file")
-///        .link();
+/// Errors reading or compiling a stage are kept until `link`, which returns
+/// the first one.
+///
+/// ```ignore
+/// shader::ShaderBuilder::new()
+///    .attach_file("./path/to/shader.file")
+///    .link()?;
+/// ```
 pub struct ShaderBuilder {
     program_id: u32,
     shaders: Vec<u32>,
+    error: Option<Error>,
+    defines: String, // Put after the version line of the stages attached
 }
 
 #[allow(dead_code)]
@@ -25,18 +38,245 @@ pub enum ShaderType {
     TessellationControl,
     TessellationEvaluation,
     Geometry,
+    Compute,
+}
+
+thread_local! {
+    /// Programs drawing nodes of a type, by the type, see `program_for`
+    static PROGRAMS: RefCell<HashMap<u32, u32>> = RefCell::new(HashMap::new());
+    /// Uniforms in common by source and target program, see `share_uniforms`
+    static SHARED: RefCell<HashMap<(u32, u32), SharedUniforms>> = RefCell::new(HashMap::new());
+    /// Times the arrays of uniforms were set in each program, see `arrays_changed`
+    static ARRAYS_SET: RefCell<HashMap<u32, u64>> = RefCell::new(HashMap::new());
+}
+
+/// Draw nodes of `node_type` with `program`, or with the program the scene
+/// is drawn with for `None`. Nodes with a `shader` of their own keep it.
+pub fn set_program_for(node_type: SceneNodeType, program: Option<u32>) {
+    PROGRAMS.with(|programs| match program {
+        Some(program) => programs.borrow_mut().insert(node_type as u32, program),
+        None => programs.borrow_mut().remove(&(node_type as u32)),
+    });
+}
+
+/// Program nodes of `node_type` are drawn with, if not the scene's
+pub fn program_for(node_type: SceneNodeType) -> Option<u32> {
+    PROGRAMS.with(|programs| programs.borrow().get(&(node_type as u32)).copied())
+}
+
+/// Give `target` the values set in `source` of the uniforms they share, as
+/// for variants of a program drawn in the same pass. Needs GL 4.1. Only
+/// values changed since the last time are written, and elements of arrays,
+/// like `u_planets[]`, are only looked at after `arrays_changed`.
+///
+/// # Safety
+/// Needs a current OpenGL context on the calling thread.
+pub unsafe fn share_uniforms(source: &Shader, target: &Shader) {
+    let arrays_set = ARRAYS_SET.with(|set| set.borrow().get(&source.program_id).copied().unwrap_or(0));
+    SHARED.with(|shared| {
+        shared
+            .borrow_mut()
+            .entry((source.program_id, target.program_id))
+            .or_insert_with(|| SharedUniforms::new(source, target))
+            .copy(source, target, arrays_set);
+    });
+}
+
+/// Note that elements of arrays of uniforms were set in `source`, for
+/// `share_uniforms` to copy them again
+pub fn arrays_changed(source: &Shader) {
+    ARRAYS_SET.with(|set| *set.borrow_mut().entry(source.program_id).or_insert(0) += 1);
+}
+
+/// Variants of the same stages compiled with different `#define`s, each
+/// built the first time it is asked for and kept by its defines
+///
+/// The scene shader takes `NODE_TYPE`, `HAS_TEXTURE`, `DEBUG_MODE`,
+/// `SHADOWS_ON` and `LOG_DEPTH`, fixing what it otherwise branches on at
+/// runtime.
+pub struct ShaderVariants {
+    stages: Vec<(String, Vec<String>)>, // Paths and includes
+    variants: HashMap<Vec<(String, String)>, Option<Shader>>, // None where it failed to build
+}
+
+impl ShaderVariants {
+    pub fn new(stages: &[(&str, &[&str])]) -> Self {
+        ShaderVariants {
+            stages: stages
+                .iter()
+                .map(|(path, includes)| (path.to_string(), includes.iter().map(|s| s.to_string()).collect()))
+                .collect(),
+            variants: HashMap::new(),
+        }
+    }
+
+    /// The variant with `defines`, in any order, built the first time. None
+    /// if it doesn't build, reported once.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn get(&mut self, defines: &[(&str, &str)]) -> Option<&Shader> {
+        let mut key: Vec<(String, String)> =
+            defines.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        key.sort();
+        let stages = &self.stages;
+        self.variants
+            .entry(key)
+            .or_insert_with(|| {
+                let mut builder = ShaderBuilder::new().with_defines(defines);
+                for (path, includes) in stages {
+                    let includes = includes.iter().map(String::as_str).collect();
+                    builder = builder.attach_file(path, Some(includes));
+                }
+                builder.link().map_err(|e| error::report(&e)).ok()
+            })
+            .as_ref()
+    }
+
+    /// Variants built so far
+    pub fn len(&self) -> usize {
+        self.variants.values().filter(|variant| variant.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl Shader {
+    /// Refer to a linked program, for looking up its uniforms. Deleting it
+    /// is left to its owner.
+    pub fn of(program_id: u32) -> Shader {
+        Shader { program_id }
+    }
+
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     // Make sure the shader is active before calling this
     pub unsafe fn get_uniform_location(&self, name: &str) -> i32 {
         let name_cstr = CString::new(name).expect("CString::new failed");
         gl::GetUniformLocation(self.program_id, name_cstr.as_ptr())
     }
 
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn activate(&self) {
         gl::UseProgram(self.program_id);
     }
+
+    /// Plain magenta, in place of a shader that failed to build. Takes the
+    /// vertex positions and `u_mvp` of the scene shader.
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn error() -> Shader {
+        let header = capabilities::global().glsl_header();
+        let vertex = format!(
+            "{}\nlayout(location = 0) in vec3 position;\nuniform mat4 u_mvp;\n\
+             void main() {{ gl_Position = u_mvp * vec4(position, 1.0); }}\n",
+            header
+        );
+        let fragment = format!(
+            "{}\nout vec4 color;\nvoid main() {{ color = vec4(1.0, 0.0, 1.0, 1.0); }}\n",
+            header
+        );
+        ShaderBuilder::new()
+            .compile_shader("error.vert", &vertex, ShaderType::Vertex)
+            .compile_shader("error.frag", &fragment, ShaderType::Fragment)
+            .link()
+            .expect("Failed to build the error shader")
+    }
+}
+
+/// Uniforms a program has in common with another, for giving a program that
+/// shares stages with another the same values without setting them twice
+///
+/// Values written to the target are remembered and not written again, so
+/// uniforms also set in the target directly must be set for every draw, as
+/// `RenderQueue` does for those of nodes.
+pub struct SharedUniforms {
+    uniforms: Vec<(i32, i32, u32, bool)>, // Location in the source, in the target, type, and if in an array
+    written: Vec<Option<[u32; 16]>>,      // Value last written to the target, by uniform
+    arrays_copied: Option<u64>,           // `arrays_changed` count when arrays were last copied
+}
+
+impl SharedUniforms {
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn new(source: &Shader, target: &Shader) -> Self {
+        let mut count = 0;
+        gl::GetProgramiv(target.program_id, gl::ACTIVE_UNIFORMS, &mut count);
+        let mut uniforms = vec![];
+        for index in 0..count as u32 {
+            let mut name = [0u8; 256];
+            let (mut length, mut size, mut kind) = (0, 0, 0);
+            gl::GetActiveUniform(
+                target.program_id,
+                index,
+                name.len() as i32,
+                &mut length,
+                &mut size,
+                &mut kind,
+                name.as_mut_ptr() as *mut gl::types::GLchar,
+            );
+            let name = String::from_utf8_lossy(&name[..length as usize]).into_owned();
+            // Arrays of basic types are listed once, by their first element
+            let array = name.strip_suffix("[0]").unwrap_or(&name);
+            for i in 0..size {
+                let element = if size > 1 { format!("{}[{}]", array, i) } else { name.clone() };
+                let from = source.get_uniform_location(&element);
+                let to = target.get_uniform_location(&element);
+                if from != -1 && to != -1 {
+                    // Members of arrays of structs are listed one by one
+                    uniforms.push((from, to, kind, element.contains('[')));
+                }
+            }
+        }
+        let written = vec![None; uniforms.len()];
+        SharedUniforms { uniforms, written, arrays_copied: None }
+    }
+
+    /// Give `target` the values currently set in `source` that differ from
+    /// those last written, leaving out arrays unless `arrays_set`, the count
+    /// of `arrays_changed` for `source`, is new
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    pub unsafe fn copy(&mut self, source: &Shader, target: &Shader, arrays_set: u64) {
+        let (from_program, to_program) = (source.program_id, target.program_id);
+        let arrays = self.arrays_copied != Some(arrays_set);
+        self.arrays_copied = Some(arrays_set);
+        for (&(from, to, kind, in_array), written) in self.uniforms.iter().zip(&mut self.written) {
+            if in_array && !arrays {
+                continue;
+            }
+            let mut value = [0u32; 16];
+            let float = value.as_mut_ptr() as *mut f32;
+            let int = value.as_mut_ptr() as *mut i32;
+            match kind {
+                gl::FLOAT | gl::FLOAT_VEC2 | gl::FLOAT_VEC3 | gl::FLOAT_VEC4 | gl::FLOAT_MAT3 | gl::FLOAT_MAT4 => {
+                    gl::GetUniformfv(from_program, from, float)
+                }
+                gl::UNSIGNED_INT => gl::GetUniformuiv(from_program, from, value.as_mut_ptr()),
+                // Integers, booleans and samplers
+                _ => gl::GetUniformiv(from_program, from, int),
+            }
+            if *written == Some(value) {
+                continue;
+            }
+            *written = Some(value);
+            match kind {
+                gl::FLOAT => gl::ProgramUniform1fv(to_program, to, 1, float),
+                gl::FLOAT_VEC2 => gl::ProgramUniform2fv(to_program, to, 1, float),
+                gl::FLOAT_VEC3 => gl::ProgramUniform3fv(to_program, to, 1, float),
+                gl::FLOAT_VEC4 => gl::ProgramUniform4fv(to_program, to, 1, float),
+                gl::FLOAT_MAT3 => gl::ProgramUniformMatrix3fv(to_program, to, 1, gl::FALSE, float),
+                gl::FLOAT_MAT4 => gl::ProgramUniformMatrix4fv(to_program, to, 1, gl::FALSE, float),
+                gl::UNSIGNED_INT => gl::ProgramUniform1uiv(to_program, to, 1, value.as_ptr()),
+                _ => gl::ProgramUniform1iv(to_program, to, 1, int),
+            }
+        }
+    }
 }
 
 impl Into<gl::types::GLenum> for ShaderType {
@@ -47,70 +287,123 @@ impl Into<gl::types::GLenum> for ShaderType {
             ShaderType::TessellationControl => gl::TESS_CONTROL_SHADER,
             ShaderType::TessellationEvaluation => gl::TESS_EVALUATION_SHADER,
             ShaderType::Geometry => gl::GEOMETRY_SHADER,
+            ShaderType::Compute => gl::COMPUTE_SHADER,
         }
     }
 }
 
 impl ShaderType {
-    fn from_ext(ext: &std::ffi::OsStr) -> Result<ShaderType, String> {
-        match ext.to_str().expect("Failed to read extension") {
+    fn from_ext(ext: &std::ffi::OsStr) -> std::result::Result<ShaderType, String> {
+        match ext.to_str().unwrap_or_default() {
             "vert" => Ok(ShaderType::Vertex),
             "frag" => Ok(ShaderType::Fragment),
             "tcs" => Ok(ShaderType::TessellationControl),
             "tes" => Ok(ShaderType::TessellationEvaluation),
             "geom" => Ok(ShaderType::Geometry),
+            "comp" => Ok(ShaderType::Compute),
             e => Err(e.to_string()),
         }
     }
 }
 
 impl ShaderBuilder {
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn new() -> ShaderBuilder {
         ShaderBuilder {
             program_id: gl::CreateProgram(),
             shaders: vec![],
+            error: None,
+            defines: String::new(),
         }
     }
 
+    /// Define `(name, value)` pairs in the stages attached from here on, for
+    /// compiling a variant of them. See `ShaderVariants`.
+    pub fn with_defines(mut self, defines: &[(&str, &str)]) -> ShaderBuilder {
+        for (name, value) in defines {
+            self.defines += &format!("\n#define {} {}", name, value);
+        }
+        self
+    }
+
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn attach_file(
-        self,
+        mut self,
         shader_path: &str,
         include_paths: Option<Vec<&str>>,
     ) -> ShaderBuilder {
+        if self.error.is_some() {
+            return self;
+        }
+        let read = |path: &str| {
+            std::fs::read_to_string(path).map_err(|source| Error::Io {
+                path: path.to_string(),
+                source,
+            })
+        };
         let path = Path::new(shader_path);
-        if let Some(extension) = path.extension() {
-            let shader_type =
-                ShaderType::from_ext(extension).expect("Failed to parse file extension.");
-            let mut shader_src = std::fs::read_to_string(path)
-                .expect(&format!("Failed to read shader source. {}", shader_path));
-
-            if let Some(inc) = include_paths {
-                for path in inc {
-                    let src = std::fs::read_to_string(path).unwrap();
-                    shader_src = shader_src + &src;
+        let shader_type = match path.extension().map(ShaderType::from_ext) {
+            Some(Ok(shader_type)) => shader_type,
+            _ => {
+                self.error = Some(Error::Shader {
+                    path: shader_path.to_string(),
+                    log: "Unknown shader type, from the file extension".to_string(),
+                });
+                return self;
+            }
+        };
+        let mut shader_src = match read(shader_path) {
+            Ok(shader_src) => shader_src,
+            Err(e) => {
+                self.error = Some(e);
+                return self;
+            }
+        };
+        for include in include_paths.unwrap_or_default() {
+            match read(include) {
+                Ok(src) => shader_src += &src,
+                Err(e) => {
+                    self.error = Some(e);
+                    return self;
                 }
             }
-            self.compile_shader(&shader_src, shader_type)
-        } else {
-            panic!(
-                "Failed to read extension of file with path: {}",
-                shader_path
-            );
         }
+        // Target the GLSL version the context supports
+        if shader_src.starts_with("#version") {
+            let end = shader_src.find('\n').unwrap_or(shader_src.len());
+            let header = capabilities::global().glsl_header() + &self.defines;
+            shader_src.replace_range(..end, &header);
+        }
+        self.compile_shader(shader_path, &shader_src, shader_type)
     }
 
+    /// Compile a stage from source, `path` naming it in errors
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     pub unsafe fn compile_shader(
         mut self,
+        path: &str,
         shader_src: &str,
         shader_type: ShaderType,
     ) -> ShaderBuilder {
+        if self.error.is_some() {
+            return self;
+        }
         let shader = gl::CreateShader(shader_type.into());
         let c_str_shader = CString::new(shader_src.as_bytes()).unwrap();
         gl::ShaderSource(shader, 1, &c_str_shader.as_ptr(), ptr::null());
         gl::CompileShader(shader);
 
-        if !self.check_shader_errors(shader) {
-            panic!("Shader failed to compile.");
+        if let Err(log) = self.check_shader_errors(shader) {
+            gl::DeleteShader(shader);
+            self.error = Some(Error::Shader {
+                path: path.to_string(),
+                log,
+            });
+            return self;
         }
 
         self.shaders.push(shader);
@@ -118,64 +411,81 @@ impl ShaderBuilder {
         self
     }
 
-    unsafe fn check_shader_errors(&self, shader_id: u32) -> bool {
+    unsafe fn check_shader_errors(&self, shader_id: u32) -> std::result::Result<(), String> {
         let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
         gl::GetShaderiv(shader_id, gl::COMPILE_STATUS, &mut success);
         if success != i32::from(gl::TRUE) {
+            let mut info_log = vec![0u8; 4096];
+            let mut length = 0;
             gl::GetShaderInfoLog(
                 shader_id,
-                512,
-                ptr::null_mut(),
+                info_log.len() as i32,
+                &mut length,
                 info_log.as_mut_ptr() as *mut gl::types::GLchar,
             );
-            println!(
-                "ERROR::Shader Compilation Failed!\n{}",
-                String::from_utf8_lossy(&info_log)
-            );
-            return false;
+            info_log.truncate(length as usize);
+            return Err(String::from_utf8_lossy(&info_log).into_owned());
         }
-        true
+        Ok(())
     }
 
-    unsafe fn check_linker_errors(&self) -> bool {
+    unsafe fn check_linker_errors(&self) -> std::result::Result<(), String> {
         let mut success = i32::from(gl::FALSE);
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1);
         gl::GetProgramiv(self.program_id, gl::LINK_STATUS, &mut success);
         if success != i32::from(gl::TRUE) {
+            let mut info_log = vec![0u8; 4096];
+            let mut length = 0;
             gl::GetProgramInfoLog(
                 self.program_id,
-                512,
-                ptr::null_mut(),
+                info_log.len() as i32,
+                &mut length,
                 info_log.as_mut_ptr() as *mut gl::types::GLchar,
             );
-            println!(
-                "ERROR::SHADER::PROGRAM::COMPILATION_FAILED\n{}",
-                String::from_utf8_lossy(&info_log)
-            );
-            return false;
+            info_log.truncate(length as usize);
+            return Err(String::from_utf8_lossy(&info_log).into_owned());
         }
-        true
+        Ok(())
     }
 
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
     #[must_use = "The shader program is useless if not stored in a variable."]
-    pub unsafe fn link(self) -> Shader {
+    pub unsafe fn link(self) -> Result<Shader> {
+        if let Some(error) = self.error {
+            for &shader in &self.shaders {
+                gl::DeleteShader(shader);
+            }
+            gl::DeleteProgram(self.program_id);
+            return Err(error);
+        }
         for &shader in &self.shaders {
             gl::AttachShader(self.program_id, shader);
         }
         gl::LinkProgram(self.program_id);
-
-        // todo:: use this to make safer abstraction
-        self.check_linker_errors();
+        let linked = self.check_linker_errors();
 
         for &shader in &self.shaders {
             gl::DeleteShader(shader);
         }
+        if let Err(log) = linked {
+            gl::DeleteProgram(self.program_id);
+            return Err(Error::Link { log });
+        }
 
-        Shader {
+        Ok(Shader {
             program_id: self.program_id,
-        }
+        })
+    }
+
+    /// Link, or report the error and use the error shader in its place
+    ///
+    /// # Safety
+    /// Needs a current OpenGL context on the calling thread.
+    #[must_use = "The shader program is useless if not stored in a variable."]
+    pub unsafe fn link_or_error_shader(self) -> Shader {
+        self.link().unwrap_or_else(|e| {
+            error::report(&e);
+            Shader::error()
+        })
     }
 }
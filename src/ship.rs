@@ -0,0 +1,204 @@
+//! A ship flown with six degrees of freedom, followed by the chase camera
+
+use std::f32::consts::PI;
+
+use nalgebra_glm as glm;
+
+use crate::camera::Movement;
+use crate::error;
+use crate::globals::*;
+use crate::mesh::{Mesh, VertexLayout};
+use crate::particles::{Emitter, Preset};
+use crate::procedural_planet::Planet;
+use crate::scene_file;
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+use crate::util::damp;
+
+/// Name of the engine's emitter node below the hull
+pub const EXHAUST_NAME: &str = "ship exhaust";
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ship {
+    pub position: glm::Vec3,
+    pub velocity: glm::Vec3,
+    pub orientation: glm::Quat, // Turns the nose from -z, like a camera
+    pub spin: glm::Vec3,        // Turning about its own axes, radians per second
+    pub throttle: f32,          // Thrust along the nose, from -1 to 1
+}
+
+impl Ship {
+    /// At rest at `position`, the nose along `direction` with the world's y
+    /// axis up
+    pub fn new(position: glm::Vec3, direction: glm::Vec3) -> Self {
+        let forward = glm::normalize(&direction);
+        let mut right = glm::cross(&forward, &glm::vec3(0.0, 1.0, 0.0));
+        if glm::length2(&right) < 1e-6 {
+            // Looking straight up or down
+            right = glm::vec3(1.0, 0.0, 0.0);
+        }
+        let right = glm::normalize(&right);
+        let up = glm::cross(&right, &forward);
+        let basis = glm::mat3(
+            right.x, up.x, -forward.x,
+            right.y, up.y, -forward.y,
+            right.z, up.z, -forward.z,
+        );
+        Ship {
+            position,
+            velocity: glm::zero(),
+            orientation: glm::mat3_to_quat(&basis),
+            spin: glm::zero(),
+            throttle: 0.0,
+        }
+    }
+
+    pub fn forward(&self) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 0.0, -1.0))
+    }
+
+    pub fn right(&self) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&self.orientation, &glm::vec3(1.0, 0.0, 0.0))
+    }
+
+    pub fn up(&self) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 1.0, 0.0))
+    }
+
+    /// Start turning by mouse movement, in radians, about the ship's own
+    /// axes. Damped at the same rate, it turns that far in all.
+    pub fn look(&mut self, delta_h: f32, delta_v: f32) {
+        self.spin += glm::vec3(-delta_v, -delta_h, 0.0) * SHIP_TURN_DAMPING;
+    }
+
+    /// Turn and thrust by what is held, pushing out of `planet`, the closest
+    /// one, if flown into it
+    pub fn fly(&mut self, movement: &Movement, planet: &Planet, speed: f32, delta_time: f32) {
+        // Rolling right turns the right wing down
+        self.spin.z -= movement.roll * SHIP_ROLL_ACCELERATION * delta_time;
+        let angle = glm::length(&self.spin) * delta_time;
+        if angle > 0.0 {
+            let turn = glm::quat_angle_axis(angle, &glm::normalize(&self.spin));
+            self.orientation = glm::quat_normalize(&(self.orientation * turn));
+        }
+        self.spin = damp(self.spin, glm::zero(), SHIP_TURN_DAMPING, delta_time);
+
+        let wish = self.right() * movement.right
+            + self.up() * movement.up
+            + self.forward() * movement.forward;
+        let thrust = wish / glm::length(&wish).max(1.0) * speed * SHIP_THRUST;
+        self.velocity += thrust * delta_time;
+        self.velocity = damp(self.velocity, glm::zero(), SHIP_DRAG, delta_time);
+        self.position += self.velocity * delta_time;
+        self.throttle = movement.forward.clamp(-1.0, 1.0);
+        self.collide(planet);
+    }
+
+    /// Keep the hull above the terrain of `planet`, losing the speed into it
+    fn collide(&mut self, planet: &Planet) {
+        let offset = self.position - planet.position;
+        let distance = glm::length(&offset);
+        let ground = planet.get_height(&self.position) + SHIP_CLEARANCE;
+        if distance < ground && distance > 0.0 {
+            let outward = offset / distance;
+            self.position = planet.position + outward * ground;
+            self.velocity -= outward * glm::dot(&self.velocity, &outward).min(0.0);
+        }
+    }
+
+    /// Where the ship is after the scene's root moves from `from` to `to`
+    /// and distances are scaled by `scale`, like when anchoring to a planet
+    pub fn rebase(&mut self, from: &glm::Vec3, to: &glm::Vec3, scale: f32) {
+        self.position = to + (self.position - from) * scale;
+        self.velocity *= scale;
+    }
+
+    /// Move `node`, made by `node()`, to the ship, with the engine burning as
+    /// hard as it's pushed forward. Drawn `unit_scale` times larger, like the
+    /// rest of the scene while anchored to a planet.
+    pub fn place(&self, node: &mut SceneNode, unit_scale: f32) {
+        node.set_position(self.position);
+        node.set_orientation(Some(self.orientation));
+        node.set_scale(glm::vec3(1.0, 1.0, 1.0) * unit_scale);
+        let burn = SHIP_IDLE + (1.0 - SHIP_IDLE) * self.throttle.max(0.0);
+        let preset = Emitter::preset(Preset::Exhaust);
+        for &child in &node.children {
+            let child = unsafe { &mut *child };
+            if let (EXHAUST_NAME, Some(emitter)) = (child.name.as_str(), child.emitter.as_mut()) {
+                emitter.rate = preset.rate * burn;
+                emitter.speed = preset.speed * (0.5 + burn);
+            }
+        }
+    }
+}
+
+/// Copy of `mesh` transformed by `transform`, turning normals and tangents
+/// with it
+fn placed(mut mesh: Mesh, transform: &glm::Mat4) -> Mesh {
+    let linear = glm::mat4_to_mat3(transform);
+    let normal_matrix = glm::transpose(&glm::inverse(&linear));
+    for v in mesh.vertices.chunks_exact_mut(3) {
+        let p = transform * glm::vec4(v[0], v[1], v[2], 1.0);
+        v.copy_from_slice(&[p.x, p.y, p.z]);
+    }
+    for n in mesh.normals.chunks_exact_mut(3) {
+        let n2 = glm::normalize(&(normal_matrix * glm::vec3(n[0], n[1], n[2])));
+        n.copy_from_slice(&[n2.x, n2.y, n2.z]);
+    }
+    for t in mesh.tangents.chunks_exact_mut(4) {
+        let t2 = glm::normalize(&(linear * glm::vec3(t[0], t[1], t[2])));
+        t[..3].copy_from_slice(&[t2.x, t2.y, t2.z]);
+    }
+    mesh
+}
+
+/// Hull of about `SHIP_LENGTH`, nose along -z: a fuselage with a cone for a
+/// nose, swept wings and an engine bell at the back
+pub fn hull(color: glm::Vec4) -> Mesh {
+    let length = SHIP_LENGTH;
+    // Primitives stand along y, turned so y points along the nose
+    let along_nose = glm::scaling(&glm::vec3(length, length, length))
+        * glm::rotation(-PI / 2.0, &glm::vec3(1.0, 0.0, 0.0));
+    let at = |z: f32| glm::translation(&glm::vec3(0.0, 0.0, z * length)) * along_nose;
+
+    let mut mesh = placed(Mesh::cylinder(0.1, 0.6, 16, color), &along_nose);
+    mesh.merge(&placed(Mesh::cone(0.1, 0.25, 16, color), &at(-0.425)));
+    // Flaring out backwards
+    mesh.merge(&placed(Mesh::frustum(0.12, 0.08, 0.1, 16, color), &at(0.35)));
+    for side in [-1.0, 1.0] {
+        // A square prism turned into a flat box, swept back from the middle
+        let root = glm::translation(&glm::vec3(side * 0.25 * length, 0.0, 0.1 * length));
+        let sweep = glm::rotation(-side * 0.35, &glm::vec3(0.0, 1.0, 0.0));
+        let size = glm::scaling(&glm::vec3(0.3 * length, 0.03 * length, 0.12 * length));
+        let corners = glm::rotation(PI / 4.0, &glm::vec3(0.0, 1.0, 0.0));
+        mesh.merge(&placed(Mesh::cylinder(1.0, 1.0, 4, color), &(root * sweep * size * corners)));
+    }
+    mesh
+}
+
+/// Node of the ship with its engine, from the OBJ file at `model` or the
+/// built-in hull if empty or failing to load
+pub fn node(model: &str) -> Node {
+    let color = glm::vec4(0.75, 0.77, 0.8, 1.0);
+    let mesh = if model.is_empty() {
+        hull(color)
+    } else {
+        scene_file::load_model(model, color).unwrap_or_else(|e| {
+            error::report(&e);
+            hull(color)
+        })
+    };
+    let mut node = SceneNode::from_vao(unsafe { mesh.mkvao_with(VertexLayout::NORMAL_MAPPED.interleaved()) });
+    node.bounds = mesh.bounding_radius();
+    node.name = "ship".to_string();
+    node.metallic = 0.6;
+    node.roughness = 0.35;
+
+    // Firing backwards, along +z
+    let mut exhaust = SceneNode::with_type(SceneNodeType::Empty);
+    exhaust.name = EXHAUST_NAME.to_string();
+    exhaust.set_position(glm::vec3(0.0, 0.0, 0.4 * SHIP_LENGTH));
+    exhaust.set_rotation(glm::vec3(PI / 2.0, 0.0, 0.0));
+    exhaust.emitter = Some(Emitter::preset(Preset::Exhaust));
+    node.add_child(&exhaust);
+    node
+}
@@ -0,0 +1,366 @@
+use crate::globals::{MAX_SIMULATION_STEPS, SHADER_TIME_WRAP, SIMULATION_STEP, TIME_SCALE_MAX, TIME_WARP_STOPS};
+
+/// Region of interest for simulation updates
+///
+/// Systems far away from the player are updated less often, keeping the CPU
+/// cost bounded as the number of bodies grows. Within `radius` everything is
+/// updated every tick; further away the update interval grows linearly with
+/// distance, up to `max_interval` ticks.
+#[derive(Debug, Copy, Clone)]
+pub struct RegionOfInterest {
+    pub radius: f32,
+    pub max_interval: u64,
+    pub skipped: usize, // Updates skipped since last reset, for statistics
+}
+
+impl RegionOfInterest {
+    pub fn new(radius: f32, max_interval: u64) -> Self {
+        RegionOfInterest {
+            radius,
+            max_interval: max_interval.max(1),
+            skipped: 0,
+        }
+    }
+
+    /// Number of ticks between updates at a given distance
+    pub fn interval(&self, distance: f32) -> u64 {
+        if self.radius <= 0.0 || distance <= self.radius {
+            return 1;
+        }
+        ((distance / self.radius).ceil() as u64).min(self.max_interval)
+    }
+
+    /// Check if an object at `distance` should be updated this tick. `id`
+    /// spreads updates of different objects across ticks.
+    pub fn should_update(&mut self, distance: f32, tick: u64, id: usize) -> bool {
+        let update = (tick + id as u64).is_multiple_of(self.interval(distance));
+        if !update {
+            self.skipped += 1;
+        }
+        update
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.skipped = 0;
+    }
+}
+
+/// Simulation time, apart from wall time, for orbits, ocean waves, particles
+/// and props. Pausing stops it, stepping advances a paused clock one update,
+/// and the time scale speeds it up or slows it down by factors of two, up to
+/// `TIME_SCALE_MAX`. Past that, time warps through `TIME_WARP_STOPS`, from
+/// minutes to a year per second.
+///
+/// The simulation is updated in fixed steps of `SIMULATION_STEP`, however
+/// long frames take, so it runs the same at any frame rate. Time left over
+/// is carried to the next frame, and frames are drawn between the last two
+/// updates, `alpha` of the way to the last. When more are due than
+/// `MAX_SIMULATION_STEPS`, like at high time scales or while warping, the
+/// clock jumps past the updates it can't run, so orbits keep to the time
+/// scale shown while props, particles and n-body gravity fall behind.
+#[derive(Debug, Copy, Clone)]
+pub struct SimulationClock {
+    pub updates: u64, // Counting the start, drawn from until the first update
+    pub scale: f32,
+    pub paused: bool,
+    pub delta: f32, // Simulation seconds drawn since the last frame
+    step: bool,     // Update once while paused
+    accumulator: f64, // Seconds not yet updated, less than a step
+}
+
+impl SimulationClock {
+    pub fn new() -> Self {
+        SimulationClock {
+            updates: 1,
+            scale: 1.0,
+            paused: false,
+            delta: 0.0,
+            step: false,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Advance with a frame of `delta_time` wall clock seconds, returning the
+    /// number of fixed updates to run, at most `MAX_SIMULATION_STEPS`. The
+    /// rest of those due are skipped, still advancing the clock.
+    pub fn tick(&mut self, delta_time: f32) -> usize {
+        let drawn = self.render_seconds();
+        let mut steps = 0;
+        if self.step {
+            steps += 1;
+        } else if !self.paused {
+            self.accumulator += delta_time as f64 * self.scale as f64;
+        }
+        self.step = false;
+        let due = (self.accumulator / SIMULATION_STEP as f64).floor();
+        self.accumulator -= due * SIMULATION_STEP as f64;
+        let run = (due as usize).min(MAX_SIMULATION_STEPS);
+        steps += run;
+        self.updates += steps as u64 + (due as u64 - run as u64);
+        self.delta = (self.render_seconds() - drawn) as f32;
+        steps
+    }
+
+    /// How far between the last two updates to draw
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / SIMULATION_STEP as f64) as f32
+    }
+
+    /// Simulation seconds to draw, `alpha` of the way to the last update,
+    /// wrapped in double precision at `SHADER_TIME_WRAP` for waves and clouds
+    /// in shaders, which would move in steps after a simulated year otherwise
+    pub fn render_time(&self) -> f32 {
+        (self.render_seconds() % SHADER_TIME_WRAP) as f32
+    }
+
+    /// As `render_time`, in double precision for orbits and the date, which
+    /// would move in steps of seconds after a simulated year in single
+    pub fn render_seconds(&self) -> f64 {
+        (self.updates as f64 - 1.0) * SIMULATION_STEP as f64 + self.accumulator
+    }
+
+    /// Simulation seconds drawn since the last frame, as `delta`, but no more
+    /// than the updates run in a frame, for animations and camera paths that
+    /// shouldn't leap ahead with the updates skipped
+    pub fn stepped_delta(&self) -> f32 {
+        self.delta.min(MAX_SIMULATION_STEPS as f32 * SIMULATION_STEP)
+    }
+
+    /// Running faster than the fixed updates keep up with
+    pub fn warping(&self) -> bool {
+        self.scale > TIME_SCALE_MAX
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Update once on the next tick, pausing if running
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step = true;
+    }
+
+    /// Double the time scale, or warp to the next stop past `TIME_SCALE_MAX`
+    pub fn faster(&mut self) {
+        self.scale = if self.scale < TIME_SCALE_MAX {
+            (self.scale * 2.0).min(TIME_SCALE_MAX)
+        } else {
+            let next = TIME_WARP_STOPS.iter().find(|&&stop| stop > self.scale);
+            *next.unwrap_or(&self.scale)
+        };
+    }
+
+    /// Halve the time scale, or warp to the stop before
+    pub fn slower(&mut self) {
+        self.scale = if self.warping() {
+            let before = TIME_WARP_STOPS.iter().rev().find(|&&stop| stop < self.scale);
+            *before.unwrap_or(&TIME_SCALE_MAX)
+        } else {
+            (self.scale / 2.0).max(1.0 / TIME_SCALE_MAX)
+        };
+    }
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const MINUTE: f64 = 60.0;
+const HOUR: f64 = 60.0 * MINUTE;
+const DAY: f64 = 24.0 * HOUR;
+const YEAR: f64 = 365.25 * DAY; // Julian
+// Units for time scales, largest first
+const SPANS: [(f64, &str); 7] = [
+    (YEAR, "year"),
+    (YEAR / 12.0, "month"),
+    (7.0 * DAY, "week"),
+    (DAY, "day"),
+    (HOUR, "hour"),
+    (MINUTE, "minute"),
+    (1.0, "second"),
+];
+
+/// Time scale for the HUD, like "x1/4", "x16" or "1 day/s"
+pub fn format_scale(scale: f32) -> String {
+    if scale > TIME_SCALE_MAX {
+        let seconds = scale as f64;
+        let &(unit, name) = SPANS.iter().find(|(unit, _)| seconds >= *unit).unwrap_or(&SPANS[6]);
+        let count = (seconds / unit).round();
+        format!("{} {}{}/s", count, name, if count == 1.0 { "" } else { "s" })
+    } else if scale < 1.0 {
+        format!("x1/{}", (1.0 / scale).round())
+    } else {
+        format!("x{}", scale)
+    }
+}
+
+/// Simulated time since the start, like "T+1y 73d 05:12:33"
+pub fn format_elapsed(seconds: f64) -> String {
+    format!("T+{}", format_duration(seconds))
+}
+
+/// Length of time, like "1y 73d 05:12:33"
+pub fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.max(0.0);
+    let years = (seconds / YEAR).floor();
+    let rest = seconds - years * YEAR;
+    let days = (rest / DAY).floor();
+    let years = if years > 0.0 { format!("{}y ", years) } else { String::new() };
+    format!("{}{}d {}", years, days, format_clock(rest - days * DAY))
+}
+
+/// Simulated date and time of day, `seconds` after midnight on the `start`
+/// year, month and day, like "2000-03-14 05:12:33"
+pub fn format_date(start: (i32, u32, u32), seconds: f64) -> String {
+    let days = (seconds / DAY).floor();
+    let (year, month, day) = civil_from_days(days_from_civil(start) + days as i64);
+    format!("{:04}-{:02}-{:02} {}", year, month, day, format_clock(seconds - days * DAY))
+}
+
+fn format_clock(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar,
+/// after Howard Hinnant's `days_from_civil`
+fn days_from_civil((year, month, day): (i32, u32, u32)) -> i64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year as i32, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f64 = SIMULATION_STEP as f64;
+
+    #[test]
+    fn render_time_starts_at_zero() {
+        let clock = SimulationClock::new();
+        assert_eq!(clock.render_seconds(), 0.0);
+        assert_eq!(clock.alpha(), 0.0);
+    }
+
+    #[test]
+    fn tick_carries_time_over() {
+        let mut clock = SimulationClock::new();
+        assert_eq!(clock.tick(1.5 * SIMULATION_STEP), 1);
+        assert!((clock.alpha() - 0.5).abs() < 1e-3);
+        assert_eq!(clock.tick(0.6 * SIMULATION_STEP), 1);
+        assert!((clock.alpha() - 0.1).abs() < 1e-3);
+        assert!((clock.render_seconds() - 2.1 * STEP).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tick_skips_updates_past_the_most_a_frame() {
+        let mut clock = SimulationClock::new();
+        let steps = clock.tick(20.5 * SIMULATION_STEP);
+        assert_eq!(steps, MAX_SIMULATION_STEPS);
+        // The clock keeps to the time scale, the updates skipped or not
+        assert!((clock.render_seconds() - 20.5 * STEP).abs() < 1e-6);
+        assert!(clock.stepped_delta() <= MAX_SIMULATION_STEPS as f32 * SIMULATION_STEP);
+    }
+
+    #[test]
+    fn alpha_stays_between_updates() {
+        let mut clock = SimulationClock::new();
+        clock.scale = 3.0;
+        for i in 0..500 {
+            clock.tick(0.001 + (i % 7) as f32 * 0.0037);
+            assert!((0.0..1.0).contains(&clock.alpha()), "alpha {}", clock.alpha());
+        }
+    }
+
+    #[test]
+    fn paused_clock_only_steps() {
+        let mut clock = SimulationClock::new();
+        clock.toggle_pause();
+        assert_eq!(clock.tick(1.0), 0);
+        assert_eq!(clock.render_seconds(), 0.0);
+
+        clock.step();
+        assert_eq!(clock.tick(1.0), 1);
+        assert!(clock.paused);
+        assert!((clock.render_seconds() - STEP).abs() < 1e-9);
+        assert!((clock.delta - SIMULATION_STEP).abs() < 1e-6);
+        assert_eq!(clock.tick(1.0), 0);
+
+        // Stepping a running clock pauses it
+        let mut clock = SimulationClock::new();
+        clock.step();
+        assert!(clock.paused);
+        assert_eq!(clock.tick(0.5 * SIMULATION_STEP), 1);
+    }
+
+    #[test]
+    fn scales_are_formatted() {
+        assert_eq!(format_scale(1.0), "x1");
+        assert_eq!(format_scale(16.0), "x16");
+        assert_eq!(format_scale(0.25), "x1/4");
+        assert_eq!(format_scale(600.0), "10 minutes/s");
+        assert_eq!(format_scale(86400.0), "1 day/s");
+        assert_eq!(format_scale(31557600.0), "1 year/s");
+    }
+
+    #[test]
+    fn durations_are_formatted() {
+        assert_eq!(format_duration(0.0), "0d 00:00:00");
+        assert_eq!(format_duration(-5.0), "0d 00:00:00");
+        assert_eq!(format_duration(DAY + 59.0), "1d 00:00:59");
+        assert_eq!(format_duration(YEAR + 73.0 * DAY + 5.0 * HOUR + 12.0 * MINUTE + 33.0), "1y 73d 05:12:33");
+        assert_eq!(format_elapsed(HOUR), "T+0d 01:00:00");
+    }
+
+    #[test]
+    fn dates_are_formatted() {
+        assert_eq!(format_date((2000, 3, 14), 5.0 * HOUR + 12.0 * MINUTE + 33.0), "2000-03-14 05:12:33");
+        assert_eq!(format_date((1999, 12, 31), DAY + 1.0), "2000-01-01 00:00:01");
+        // Leap days, every fourth year but only every fourth century
+        assert_eq!(format_date((2000, 2, 28), DAY), "2000-02-29 00:00:00");
+        assert_eq!(format_date((2024, 2, 28), DAY), "2024-02-29 00:00:00");
+        assert_eq!(format_date((1900, 2, 28), DAY), "1900-03-01 00:00:00");
+        assert_eq!(format_date((2100, 2, 28), DAY), "2100-03-01 00:00:00");
+        assert_eq!(format_date((2023, 1, 1), 365.0 * DAY), "2024-01-01 00:00:00");
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        assert_eq!(days_from_civil((1970, 1, 1)), 0);
+        assert_eq!(days_from_civil((2000, 3, 1)), 11017);
+        assert_eq!(days_from_civil((1969, 12, 31)), -1);
+        for date in [(1600, 2, 29), (1900, 3, 1), (2000, 2, 29), (2000, 12, 31), (2100, 1, 1), (-1, 12, 31)] {
+            assert_eq!(civil_from_days(days_from_civil(date)), date);
+        }
+        // Days follow each other across month, year and century ends
+        let mut previous = civil_from_days(-200_000);
+        for days in -199_999..200_000 {
+            let date = civil_from_days(days);
+            assert_eq!(days_from_civil(date), days);
+            assert!(date > previous, "{:?} after {:?}", date, previous);
+            previous = date;
+        }
+    }
+}
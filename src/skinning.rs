@@ -0,0 +1,213 @@
+//! Skinned meshes, posed by joints in the scene graph and animated by clips
+
+use nalgebra_glm as glm;
+
+use crate::globals::MAX_JOINTS;
+use crate::gpu_resources::{self, Kind};
+use crate::scene_graph::{SceneNode, SceneNodeType};
+use crate::util;
+
+/// Joint of a skeleton as loaded, in its rest pose
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>, // Joint above, none for the roots
+    pub armature: glm::Mat4,   // Above a root joint, relative to the skinned node
+    pub translation: glm::Vec3,
+    pub rotation: glm::Quat,
+    pub scale: glm::Vec3,
+    pub inverse_bind: glm::Mat4, // From the mesh to the joint, in the bind pose
+}
+
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    pub clips: Vec<AnimationClip>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+/// Keyframes of one property of a joint
+pub struct Channel {
+    pub joint: usize,
+    pub property: Property,
+    pub step: bool, // Hold each key until the next, instead of interpolating
+    pub times: Vec<f32>,
+    pub values: Vec<glm::Vec4>, // Quaternions as (x, y, z, w), vectors in xyz
+}
+
+impl Channel {
+    fn sample(&self, time: f32) -> glm::Vec4 {
+        let next = self.times.partition_point(|&t| t <= time);
+        if next == 0 || next == self.times.len() || self.step {
+            return self.values[next.max(1) - 1];
+        }
+        let (a, b) = (self.values[next - 1], self.values[next]);
+        let t = (time - self.times[next - 1]) / (self.times[next] - self.times[next - 1]);
+        match self.property {
+            Property::Rotation => glm::quat_slerp(&a.into(), &b.into(), t).coords,
+            _ => glm::lerp(&a, &b, t),
+        }
+    }
+}
+
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32, // Seconds, until the last key
+    channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: String, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |a: f32, &b| a.max(b));
+        AnimationClip {
+            name,
+            duration,
+            channels,
+        }
+    }
+
+    /// Move the joints to where they are `time` seconds into the clip
+    unsafe fn pose(&self, joints: &[*mut SceneNode], time: f32) {
+        for channel in &self.channels {
+            let value = channel.sample(time);
+            let joint = &mut *joints[channel.joint];
+            match channel.property {
+                Property::Translation => joint.set_position(value.xyz()),
+                Property::Rotation => joint.set_orientation(Some(value.into())),
+                Property::Scale => joint.set_scale(value.xyz()),
+            }
+        }
+    }
+}
+
+/// Joints and clips of a skinned node, see `attach`
+pub struct Skin {
+    joints: Vec<*mut SceneNode>, // Below the skinned node
+    inverse_bind: Vec<glm::DMat4>,
+    clips: Vec<AnimationClip>,
+    pub playing: Option<usize>, // Clip looping
+    pub time: f32,              // Seconds into it
+    buffer: u32,                // Bone matrices, for drawing
+}
+
+impl Skin {
+    /// Uniform buffer of bone matrices
+    pub fn buffer(&self) -> u32 {
+        self.buffer
+    }
+
+    /// Loop the clip called `name` from the start, or the first clip
+    pub fn play(&mut self, name: Option<&str>) {
+        self.playing = match name {
+            Some(name) => self.clips.iter().position(|clip| clip.name == name),
+            None => (!self.clips.is_empty()).then_some(0),
+        };
+        self.time = 0.0;
+    }
+}
+
+/// Add nodes for the joints of `skeleton` below `node`, skinning it, and
+/// play the clip called `clip`, or the first one
+pub unsafe fn attach(node: &mut SceneNode, skeleton: Skeleton, clip: Option<&str>) {
+    // Leaked like all nodes, freed with the node they are below
+    let joints: Vec<*mut SceneNode> = skeleton
+        .joints
+        .iter()
+        .map(|joint| {
+            let mut joint_node = SceneNode::with_type(SceneNodeType::Empty);
+            joint_node.name = joint.name.clone();
+            joint_node.set_position(joint.translation);
+            joint_node.set_orientation(Some(joint.rotation));
+            joint_node.set_scale(joint.scale);
+            &mut **joint_node as *mut SceneNode
+        })
+        .collect();
+    for (joint, &joint_node) in skeleton.joints.iter().zip(&joints) {
+        match joint.parent {
+            Some(parent) => {
+                let parent = joints[parent];
+                (*parent).add_child(&*joint_node)
+            }
+            None => {
+                let (position, orientation, scale) = util::decompose(&joint.armature);
+                let mut armature = SceneNode::with_type(SceneNodeType::Empty);
+                armature.set_position(position);
+                armature.set_orientation(Some(orientation));
+                armature.set_scale(scale);
+                armature.add_child(&*joint_node);
+                node.add_child(&armature);
+            }
+        }
+    }
+
+    let bytes = MAX_JOINTS * std::mem::size_of::<glm::Mat4>();
+    let mut buffer = 0;
+    gl::GenBuffers(1, &mut buffer);
+    gl::BindBuffer(gl::UNIFORM_BUFFER, buffer);
+    gl::BufferData(gl::UNIFORM_BUFFER, bytes as isize, std::ptr::null(), gl::DYNAMIC_DRAW);
+    gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+    gpu_resources::track(Kind::Buffer, buffer, bytes as u64, "bones");
+
+    let mut skin = Skin {
+        joints,
+        inverse_bind: skeleton.joints.iter().map(|joint| joint.inverse_bind.cast()).collect(),
+        clips: skeleton.clips,
+        playing: None,
+        time: 0.0,
+        buffer,
+    };
+    skin.play(clip);
+    node.skin = Some(skin);
+}
+
+/// Advance the clips playing below `node` by `delta_time` seconds, looping,
+/// and pose their joints
+pub fn animate(node: &mut SceneNode, delta_time: f32) {
+    if let Some(skin) = node.skin.as_mut() {
+        if let Some(playing) = skin.playing {
+            let clip = &skin.clips[playing];
+            skin.time = if clip.duration > 0.0 {
+                (skin.time + delta_time) % clip.duration
+            } else {
+                0.0
+            };
+            unsafe { clip.pose(&skin.joints, skin.time) };
+        }
+    }
+    for &child in &node.children {
+        animate(unsafe { &mut *child }, delta_time);
+    }
+}
+
+/// Upload the bone matrices of the skinned nodes below `node`, once their
+/// transformations are updated
+pub unsafe fn upload(node: &SceneNode) {
+    if let Some(skin) = node.skin.as_ref() {
+        // Bones move vertices within the skinned node, which then places them
+        let to_model = glm::inverse(&node.world_transformation);
+        let bones: Vec<glm::Mat4> = skin
+            .joints
+            .iter()
+            .zip(&skin.inverse_bind)
+            .map(|(&joint, inverse_bind)| (to_model * (*joint).world_transformation * inverse_bind).cast())
+            .collect();
+        gl::BindBuffer(gl::UNIFORM_BUFFER, skin.buffer);
+        gl::BufferSubData(
+            gl::UNIFORM_BUFFER,
+            0,
+            util::byte_size_of_array(&bones),
+            bones.as_ptr() as *const _,
+        );
+        gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+    }
+    for &child in &node.children {
+        upload(&*child);
+    }
+}
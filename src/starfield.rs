@@ -0,0 +1,166 @@
+//! Stars from a catalog, drawn as point sprites over the skybox
+
+use nalgebra_glm as glm;
+
+use crate::globals::{STAR_MAGNITUDE_LIMIT, STAR_REFERENCE_MAGNITUDE};
+use crate::{shader, util};
+
+/// Star parsed from the catalog
+#[derive(Debug, Copy, Clone)]
+pub struct Star {
+    pub direction: glm::Vec3, // Celestial north is up (y)
+    pub magnitude: f32,
+    pub color_index: f32, // B-V, blue stars are negative, red above 1
+}
+
+/// Surface temperature in kelvin from the B-V colour index (Ballesteros)
+fn temperature(color_index: f32) -> f32 {
+    4600.0 * (1.0 / (0.92 * color_index + 1.7) + 1.0 / (0.92 * color_index + 0.62))
+}
+
+/// Approximate colour of a black body at `kelvin`, 0 to 1
+fn tint(kelvin: f32) -> glm::Vec3 {
+    let t = kelvin / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.7 * (t - 60.0).powf(-0.1332) };
+    let g = if t <= 66.0 {
+        99.47 * t.ln() - 161.12
+    } else {
+        288.12 * (t - 60.0).powf(-0.0755)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.52 * (t - 10.0).ln() - 305.04
+    };
+    glm::vec3(r, g, b).map(|c| c.clamp(0.0, 255.0) / 255.0)
+}
+
+/// Parse a catalog, skipping the sun and stars fainter than the limit
+pub fn parse(text: &str) -> Result<Vec<Star>, String> {
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty() && !l.starts_with('#'));
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or("Empty star catalog")?
+        .split(',')
+        .map(|c| c.trim().trim_matches('"'))
+        .collect();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|&c| c == name)
+            .ok_or(format!("Star catalog has no column {}", name))
+    };
+    let (ra, dec, mag, ci) = (column("ra")?, column("dec")?, column("mag")?, column("ci")?);
+
+    let mut stars = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let values: Vec<&str> = line.split(',').map(|v| v.trim().trim_matches('"')).collect();
+        let value = |c: usize| -> Result<f32, String> {
+            values
+                .get(c)
+                .and_then(|v| v.parse().ok())
+                .ok_or(format!("Invalid star on line {}: {}", i + 2, line))
+        };
+        let magnitude = value(mag)?;
+        // The sun is in the HYG database too
+        if !(-5.0..=STAR_MAGNITUDE_LIMIT).contains(&magnitude) {
+            continue;
+        }
+        let ra = value(ra)? * std::f32::consts::PI / 12.0;
+        let dec = value(dec)?.to_radians();
+        stars.push(Star {
+            direction: glm::vec3(dec.cos() * ra.cos(), dec.sin(), -dec.cos() * ra.sin()),
+            magnitude,
+            // Missing for a few stars
+            color_index: value(ci).unwrap_or(0.6),
+        });
+    }
+    Ok(stars)
+}
+
+pub struct Starfield {
+    shader: shader::Shader,
+    vao: u32,
+    count: i32,
+}
+
+impl Starfield {
+    pub unsafe fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let stars = parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self::new(&stars))
+    }
+
+    pub unsafe fn new(stars: &[Star]) -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/star.vert", None)
+            .attach_file("./resources/shaders/star.frag", None)
+            .link_or_error_shader();
+
+        // Per star direction (3), colour (3) and flux (1)
+        let mut vertices: Vec<f32> = Vec::with_capacity(stars.len() * 7);
+        for star in stars {
+            let color = tint(temperature(star.color_index));
+            let flux = 10f32.powf(-0.4 * (star.magnitude - STAR_REFERENCE_MAGNITUDE));
+            vertices.extend_from_slice(&[star.direction.x, star.direction.y, star.direction.z]);
+            vertices.extend_from_slice(&[color.x, color.y, color.z, flux]);
+        }
+
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            util::byte_size_of_array(&vertices),
+            util::pointer_to_array(&vertices),
+            gl::STATIC_DRAW,
+        );
+        let stride = 7 * util::size_of::<f32>();
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, util::offset::<f32>(3));
+
+        Starfield {
+            shader,
+            vao,
+            count: stars.len() as i32,
+        }
+    }
+
+    /// Number of stars drawn
+    pub fn count(&self) -> usize {
+        self.count as usize
+    }
+
+    /// Draw the stars over the skybox, right after it, without depth testing.
+    /// Takes an infinite `perspective`. Leaves the star shader active.
+    pub unsafe fn draw(&self, view: &glm::Mat4, perspective: &glm::Mat4, brightness: f32) {
+        if self.count == 0 {
+            return;
+        }
+        self.shader.activate();
+        gl::UniformMatrix4fv(self.shader.get_uniform_location("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(
+            self.shader.get_uniform_location("u_perspective"),
+            1,
+            gl::FALSE,
+            perspective.as_ptr(),
+        );
+        gl::Uniform1f(self.shader.get_uniform_location("u_brightness"), brightness);
+
+        gl::BindVertexArray(self.vao);
+        gl::Enable(gl::PROGRAM_POINT_SIZE);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::DrawArrays(gl::POINTS, 0, self.count);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        gl::Disable(gl::PROGRAM_POINT_SIZE);
+    }
+}
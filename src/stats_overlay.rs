@@ -0,0 +1,123 @@
+use nalgebra_glm as glm;
+
+use crate::scene_graph::{Node, SceneNode};
+use crate::text::{self, Align, Font, Text};
+
+const TEXT_SCALE: f32 = 0.6;
+const N_LINES: usize = 7;
+const N_GPU_LINES: usize = 8; // Total and the parts of the frame
+const N_CPU_LINES: usize = 7; // Heading and the scopes
+const UPDATE_INTERVAL: f32 = 0.25; // Seconds between text updates
+
+/// Statistics gathered during a frame
+#[derive(Default, Debug, Copy, Clone)]
+pub struct FrameStats {
+    pub frame_time: f32, // Seconds
+    pub draw_calls: usize,
+    pub triangles: usize,
+    pub state_changes: usize,
+    pub avoided_calls: usize, // Redundant GL calls skipped by the render queue
+    pub batched_patches: usize, // Terrain patches drawn together with others
+    pub culled_patches: usize, // Planet patches behind the horizon
+    pub skipped_planets: usize, // Planets too small to be drawn
+    pub impostors: usize, // Planets drawn as billboards
+    pub occluded: usize, // Planets and props hidden behind planets
+    pub skipped_updates: usize, // Simulation updates skipped outside region of interest
+    pub skipped_transforms: usize, // Scene nodes that didn't move
+    pub particles: usize,
+    pub plants: usize, // Grass and trees around the camera
+}
+
+/// Text overlay in the upper right corner with frame statistics and the
+/// times of parts of the frame, toggled by F3
+pub struct StatsOverlay {
+    pub root: Node,
+    lines: Vec<Text>,
+    pub visible: bool,
+    // Accumulated since last text update
+    time: f32,
+    frames: usize,
+}
+
+impl StatsOverlay {
+    pub fn new(font: &Font) -> Self {
+        let mut root = SceneNode::new();
+        let lines = (0..N_LINES + N_GPU_LINES + N_CPU_LINES)
+            .map(|i| {
+                let anchor = glm::vec2(1.0, 1.0 - TEXT_SCALE * text::LINE_HEIGHT * (i + 1) as f32);
+                let line = Text::new(font, "", anchor, TEXT_SCALE).align(Align::Right);
+                root.add_child(&line.node);
+                line
+            })
+            .collect();
+        StatsOverlay {
+            root,
+            lines,
+            visible: false,
+            time: 0.0,
+            frames: 0,
+        }
+    }
+
+    /// Add a frame, and update text a few times per second with averages.
+    /// `gpu_times` and `cpu_times` are milliseconds for parts of the frame,
+    /// from the profilers.
+    pub fn update(
+        &mut self,
+        stats: &FrameStats,
+        gpu_times: &[(&str, f32)],
+        cpu_times: &[(&str, f32)],
+    ) {
+        self.time += stats.frame_time;
+        self.frames += 1;
+        if !self.visible || self.time < UPDATE_INTERVAL {
+            return;
+        }
+        let frame_time = self.time / self.frames as f32;
+        self.time = 0.0;
+        self.frames = 0;
+
+        let mut text = vec![
+            format!("FPS: {:.1}", 1.0 / frame_time),
+            format!("Frame time: {:.2}ms", frame_time * 1000.0),
+            format!(
+                "Draw calls: {} ({} state changes, {} avoided)",
+                stats.draw_calls, stats.state_changes, stats.avoided_calls
+            ),
+            format!("Triangles: {} ({} patches batched)", stats.triangles, stats.batched_patches),
+            format!(
+                "Culled: {} patches, {} planets, {} occluded ({} impostors)",
+                stats.culled_patches, stats.skipped_planets, stats.occluded, stats.impostors
+            ),
+            format!(
+                "Skipped updates: {} orbits, {} transforms",
+                stats.skipped_updates, stats.skipped_transforms
+            ),
+            format!("Particles: {}, plants: {}", stats.particles, stats.plants),
+        ];
+        if !gpu_times.is_empty() {
+            let total: f32 = gpu_times.iter().map(|&(_, ms)| ms).sum();
+            text.push(format!("GPU: {:.2}ms", total));
+            text.extend(
+                gpu_times
+                    .iter()
+                    .take(N_GPU_LINES - 1)
+                    .map(|(name, ms)| format!("{}: {:.2}ms", name, ms)),
+            );
+        }
+        text.resize(N_LINES + N_GPU_LINES, String::new());
+        if !cpu_times.is_empty() {
+            text.push("CPU scopes:".to_string());
+            text.extend(
+                cpu_times
+                    .iter()
+                    .take(N_CPU_LINES - 1)
+                    .map(|(name, ms)| format!("{}: {:.2}ms", name, ms)),
+            );
+        }
+        text.resize(N_LINES + N_GPU_LINES + N_CPU_LINES, String::new());
+        for (line, s) in self.lines.iter_mut().zip(text.iter()) {
+            line.set_text(s);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Craters, volcanoes and canyons stamped onto planet terrain
+
+use nalgebra_glm as glm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::globals::MAX_SURFACE_FEATURES;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum FeatureKind {
+    #[default]
+    Crater = 0,
+    Volcano = 1,
+    Canyon = 2,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Feature {
+    pub kind: FeatureKind,
+    pub center: glm::Vec3, // Direction from the planet center
+    pub axis: glm::Vec3,   // Normal of the great circle canyons run along
+    pub radius: f32,       // Angle covered, across canyons
+    pub length: f32,       // Angle from the center to the ends of canyons
+    pub depth: f32,        // In max heights, negative for volcanoes
+}
+
+/// Features of a planet, kept inline so planets stay `Copy`
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Features {
+    list: [Feature; MAX_SURFACE_FEATURES],
+    len: usize,
+}
+
+impl Features {
+    pub fn as_slice(&self) -> &[Feature] {
+        &self.list[..self.len]
+    }
+
+    /// Add `feature`, if there is room for it
+    fn push(&mut self, feature: Feature) {
+        if self.len < MAX_SURFACE_FEATURES {
+            self.list[self.len] = feature;
+            self.len += 1;
+        }
+    }
+}
+
+/// Place features for the planet of `seed`, many craters if `airless`
+pub fn generate(seed: u32, airless: bool) -> Features {
+    let mut rng = StdRng::seed_from_u64(seed as u64 ^ 0x5eed_f00d);
+    let (craters, volcanoes, canyons) = if airless {
+        (rng.gen_range(18..28), rng.gen_range(0..3), rng.gen_range(0..2))
+    } else {
+        (rng.gen_range(1..6), rng.gen_range(1..5), rng.gen_range(1..4))
+    };
+    let direction = |rng: &mut StdRng| {
+        let v = glm::vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+        if glm::length(&v) < 1e-3 { glm::vec3(0.0, 1.0, 0.0) } else { glm::normalize(&v) }
+    };
+    let mut features = Features::default();
+    for _ in 0..craters {
+        // Many small and a few large
+        let radius = 0.02 + 0.25 * rng.gen::<f32>().powi(4);
+        let weathering = if airless { 1.0 } else { 0.4 };
+        features.push(Feature {
+            kind: FeatureKind::Crater,
+            center: direction(&mut rng),
+            axis: glm::zero(),
+            radius,
+            length: 0.0,
+            depth: radius * rng.gen_range(3.0..6.0) * weathering,
+        });
+    }
+    for _ in 0..volcanoes {
+        features.push(Feature {
+            kind: FeatureKind::Volcano,
+            center: direction(&mut rng),
+            axis: glm::zero(),
+            radius: rng.gen_range(0.04..0.12),
+            length: 0.0,
+            depth: -rng.gen_range(0.5..1.0),
+        });
+    }
+    for _ in 0..canyons {
+        let center = direction(&mut rng);
+        let axis = glm::normalize(&glm::cross(&center, &direction(&mut rng)));
+        features.push(Feature {
+            kind: FeatureKind::Canyon,
+            center,
+            axis,
+            radius: rng.gen_range(0.01..0.03),
+            length: rng.gen_range(0.2..0.6),
+            depth: rng.gen_range(0.4..0.8),
+        });
+    }
+    features
+}
+
+/// Height in max heights, and the tint of the ground, the features give the
+/// surface in `direction`, a unit vector from the planet center. As
+/// `stamped` in terrain.glsl.
+pub fn stamp(features: &[Feature], direction: &glm::Vec3) -> (f32, glm::Vec3) {
+    let mut height = 0.0;
+    let mut tint = glm::vec3(1.0, 1.0, 1.0);
+    for feature in features {
+        let angle = glm::dot(direction, &feature.center).clamp(-1.0, 1.0).acos();
+        match feature.kind {
+            FeatureKind::Crater => {
+                let t = angle / feature.radius;
+                if t > 2.5 {
+                    continue;
+                }
+                let bowl = if t < 1.0 { t * t - 1.0 } else { 0.0 };
+                let rim = (-((t - 1.0) / 0.25).powi(2)).exp() * 0.3;
+                height += (bowl + rim) * feature.depth;
+                // Dark floor, bright ejecta fading out around it
+                let shade = if t < 1.0 {
+                    0.85
+                } else {
+                    1.0 + 0.2 * (1.0 - (t - 1.0) / 1.5)
+                };
+                tint *= shade;
+            }
+            FeatureKind::Volcano => {
+                let t = angle / feature.radius;
+                if t > 1.0 {
+                    continue;
+                }
+                let cone = (1.0 - t).powi(2);
+                let caldera = (1.0 - t / 0.15).max(0.0) * 0.6;
+                height -= (cone - caldera) * feature.depth;
+                tint = glm::lerp(&tint, &glm::vec3(0.45, 0.4, 0.4), cone);
+            }
+            FeatureKind::Canyon => {
+                let tangent = glm::cross(&feature.axis, &feature.center);
+                let across = glm::dot(direction, &feature.axis).clamp(-1.0, 1.0).asin() / feature.radius;
+                let along = glm::dot(direction, &tangent).atan2(glm::dot(direction, &feature.center))
+                    / feature.length;
+                if across.abs() > 1.0 || along.abs() > 1.0 {
+                    continue;
+                }
+                // Flat floor and steep walls, narrowing towards the ends
+                let u = across.abs() / (1.0 - along * along).sqrt().max(1e-3);
+                let cut = 1.0 - smoothstep(0.6, 1.0, u);
+                height -= cut * feature.depth;
+                tint = glm::lerp(&tint, &glm::vec3(0.8, 0.62, 0.5), cut);
+            }
+        }
+    }
+    (height, tint)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Features as three vec4s each, for `u_features` in terrain.glsl: the
+/// center and radius, the axis and length, and the depth and kind
+pub fn pack(features: &[Feature]) -> Vec<f32> {
+    features
+        .iter()
+        .flat_map(|f| {
+            [
+                f.center.x, f.center.y, f.center.z, f.radius,
+                f.axis.x, f.axis.y, f.axis.z, f.length,
+                f.depth, f.kind as u32 as f32, 0.0, 0.0,
+            ]
+        })
+        .collect()
+}
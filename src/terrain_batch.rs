@@ -0,0 +1,343 @@
+//! Terrain patches drawn together with multi-draw indirect, on GL 4.6 and up
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use crate::capabilities;
+use crate::gl_state::GlState;
+use crate::globals::{BATCH_DRAWS_BINDING, BATCH_INDEX_MB, BATCH_VERTEX_MB};
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::mesh::VertexLayout;
+use crate::render_queue::DrawCommand;
+use crate::scene_graph::SceneNodeType;
+use crate::shader::Shader;
+
+/// Layout of terrain patches, see terrain_uploads.rs
+const LAYOUT: VertexLayout = VertexLayout {
+    interleaved: true,
+    ..VertexLayout::NORMAL_MAPPED
+};
+
+/// Values of each draw, as `Draw` in scene.vert and scene.frag (std430)
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Draw {
+    mvp: [f32; 16],
+    previous_mvp: [f32; 16],
+    model: [f32; 16],
+    planet_id: u32,
+    lod_level: u32,
+    fade: f32,
+    _padding: u32,
+}
+
+/// As read by `glMultiDrawElementsIndirect`
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct IndirectCommand {
+    count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    base_instance: u32,
+}
+
+/// Free ranges of a shared buffer, handed out first fit
+struct Arena {
+    free: Vec<(usize, usize)>, // Start and length, by start, never touching
+}
+
+impl Arena {
+    fn new(length: usize) -> Self {
+        Arena {
+            free: vec![(0, length)],
+        }
+    }
+
+    fn alloc(&mut self, length: usize) -> Option<usize> {
+        let i = self.free.iter().position(|&(_, free)| free >= length)?;
+        let (start, free) = self.free[i];
+        if free == length {
+            self.free.remove(i);
+        } else {
+            self.free[i] = (start + length, free - length);
+        }
+        Some(start)
+    }
+
+    fn free(&mut self, start: usize, length: usize) {
+        let i = self.free.partition_point(|&(free, _)| free < start);
+        self.free.insert(i, (start, length));
+        // Merged with the ranges on either side
+        if i + 1 < self.free.len() && start + length == self.free[i + 1].0 {
+            self.free[i].1 += self.free.remove(i + 1).1;
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == start {
+            self.free[i - 1].1 += self.free.remove(i).1;
+        }
+    }
+}
+
+/// Where a patch is in the shared buffers
+struct Slot {
+    vertices: (usize, usize), // First vertex and count
+    indices: (usize, usize),  // First index and count
+    frame: u64,               // Last drawn in
+}
+
+struct TerrainBatch {
+    vao: GpuHandle,
+    vertices: GpuHandle,
+    indices: GpuHandle,
+    draws: GpuHandle,    // Storage buffer of `Draw`
+    indirect: GpuHandle, // Of `IndirectCommand`
+    vertex_arena: Arena,
+    index_arena: Arena,
+    slots: HashMap<u32, Slot>, // By the vertex buffer of the patch
+    frame: u64,
+}
+
+thread_local! {
+    static BATCH: RefCell<Option<TerrainBatch>> = const { RefCell::new(None) };
+    static ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Make the shared buffers, when the context has multi-draw with `gl_DrawID`
+pub unsafe fn init() {
+    if !capabilities::global().multi_draw() {
+        return;
+    }
+    let stride = LAYOUT.stride() * std::mem::size_of::<f32>();
+    let (vertex_bytes, index_bytes) = (BATCH_VERTEX_MB * 1024 * 1024, BATCH_INDEX_MB * 1024 * 1024);
+    let mut buffers = [0; 4];
+    gl::GenBuffers(4, buffers.as_mut_ptr());
+    let mut vao = 0;
+    gl::GenVertexArrays(1, &mut vao);
+    gl::BindVertexArray(vao);
+    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, buffers[1]);
+    gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, index_bytes as isize, std::ptr::null(), gl::STATIC_DRAW);
+    gl::BindBuffer(gl::ARRAY_BUFFER, buffers[0]);
+    gl::BufferData(gl::ARRAY_BUFFER, vertex_bytes as isize, std::ptr::null(), gl::STATIC_DRAW);
+    LAYOUT.point_interleaved();
+    gl::BindVertexArray(0);
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+    let batch = TerrainBatch {
+        vao: GpuHandle::new(Kind::VertexArray, vao, 0, "terrain batch"),
+        vertices: GpuHandle::new(Kind::Buffer, buffers[0], vertex_bytes, "terrain batch"),
+        indices: GpuHandle::new(Kind::Buffer, buffers[1], index_bytes, "terrain batch"),
+        draws: GpuHandle::new(Kind::Buffer, buffers[2], 0, "terrain batch"),
+        indirect: GpuHandle::new(Kind::Buffer, buffers[3], 0, "terrain batch"),
+        vertex_arena: Arena::new(vertex_bytes as usize / stride),
+        index_arena: Arena::new(index_bytes as usize / std::mem::size_of::<u32>()),
+        slots: HashMap::new(),
+        frame: 0,
+    };
+    BATCH.with(|b| *b.borrow_mut() = Some(batch));
+}
+
+/// Draw terrain patches in batches when available, or one by one
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+}
+
+/// Whether terrain patches are drawn in batches
+pub fn enabled() -> bool {
+    ENABLED.with(|e| e.get()) && BATCH.with(|batch| batch.borrow().is_some())
+}
+
+/// Whether `cmd` can be drawn in a batch
+pub fn batchable(cmd: &DrawCommand) -> bool {
+    cmd.node_type == SceneNodeType::Planet
+        && cmd.vbo != 0
+        && !cmd.selected
+        && cmd.skin == 0
+        && cmd.texture_id.is_none()
+        && cmd.normal_map_id.is_none()
+}
+
+/// Whether `a` and `b` can be drawn in the same batch, all their state
+/// but what goes in the storage buffer being the same
+pub fn compatible(a: &DrawCommand, b: &DrawCommand) -> bool {
+    batchable(a)
+        && batchable(b)
+        && a.program == b.program
+        && a.city_lights == b.city_lights
+        && a.tint == b.tint
+        && (a.metallic, a.roughness) == (b.metallic, b.roughness)
+}
+
+/// Draw `cmds`, compatible patches, in one call with the state of the first
+/// already applied. Returns the indices of those left out, when the shared
+/// buffers are full, to be drawn one by one.
+pub unsafe fn draw(state: &mut GlState, sh: &Shader, cmds: &[DrawCommand]) -> Vec<usize> {
+    BATCH.with(|batch| match batch.borrow_mut().as_mut() {
+        Some(batch) => batch.draw(state, sh, cmds),
+        None => (0..cmds.len()).collect(),
+    })
+}
+
+/// Let patches not drawn since be dropped from the shared buffers for others
+pub fn end_frame() {
+    BATCH.with(|batch| {
+        if let Some(batch) = batch.borrow_mut().as_mut() {
+            batch.frame += 1;
+        }
+    });
+}
+
+/// Drop the patch with vertex buffer `vbo`, as it is being deleted
+pub fn forget(vbo: u32) {
+    BATCH.with(|batch| {
+        if let Some(batch) = batch.borrow_mut().as_mut() {
+            batch.evict(vbo);
+        }
+    });
+}
+
+impl TerrainBatch {
+    fn evict(&mut self, vbo: u32) {
+        if let Some(slot) = self.slots.remove(&vbo) {
+            self.vertex_arena.free(slot.vertices.0, slot.vertices.1);
+            self.index_arena.free(slot.indices.0, slot.indices.1);
+        }
+    }
+
+    /// Make room by dropping the patches not drawn this frame
+    fn evict_unused(&mut self) -> bool {
+        let unused: Vec<u32> = self
+            .slots
+            .iter()
+            .filter(|(_, slot)| slot.frame != self.frame)
+            .map(|(&vbo, _)| vbo)
+            .collect();
+        for &vbo in &unused {
+            self.evict(vbo);
+        }
+        !unused.is_empty()
+    }
+
+    /// Copy the patch of `cmd` into the shared buffers, if not already there
+    unsafe fn make_resident(&mut self, cmd: &DrawCommand) -> Option<&mut Slot> {
+        if !self.slots.contains_key(&cmd.vbo) {
+            let stride = (LAYOUT.stride() * std::mem::size_of::<f32>()) as isize;
+            let mut bytes = 0;
+            gl::BindBuffer(gl::COPY_READ_BUFFER, cmd.vbo);
+            gl::GetBufferParameteriv(gl::COPY_READ_BUFFER, gl::BUFFER_SIZE, &mut bytes);
+            let vertex_count = bytes as usize / stride as usize;
+            let index_count = cmd.index_count as usize;
+
+            let alloc = |batch: &mut Self| {
+                let vertices = batch.vertex_arena.alloc(vertex_count)?;
+                match batch.index_arena.alloc(index_count) {
+                    Some(indices) => Some((vertices, indices)),
+                    None => {
+                        batch.vertex_arena.free(vertices, vertex_count);
+                        None
+                    }
+                }
+            };
+            let (vertices, indices) = match alloc(self) {
+                Some(ranges) => ranges,
+                None if self.evict_unused() => alloc(self)?,
+                None => return None,
+            };
+
+            // Patches may just have been displaced by the compute shader
+            if capabilities::global().compute_shaders() {
+                gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+            }
+            let index_size = std::mem::size_of::<u32>() as isize;
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.vertices.id());
+            gl::CopyBufferSubData(
+                gl::COPY_READ_BUFFER,
+                gl::COPY_WRITE_BUFFER,
+                0,
+                vertices as isize * stride,
+                vertex_count as isize * stride,
+            );
+            gl::BindBuffer(gl::COPY_READ_BUFFER, cmd.ibo);
+            gl::BindBuffer(gl::COPY_WRITE_BUFFER, self.indices.id());
+            gl::CopyBufferSubData(
+                gl::COPY_READ_BUFFER,
+                gl::COPY_WRITE_BUFFER,
+                0,
+                indices as isize * index_size,
+                index_count as isize * index_size,
+            );
+            self.slots.insert(
+                cmd.vbo,
+                Slot {
+                    vertices: (vertices, vertex_count),
+                    indices: (indices, index_count),
+                    frame: self.frame,
+                },
+            );
+        }
+        self.slots.get_mut(&cmd.vbo)
+    }
+
+    unsafe fn draw(&mut self, state: &mut GlState, sh: &Shader, cmds: &[DrawCommand]) -> Vec<usize> {
+        let mut draws = Vec::with_capacity(cmds.len());
+        let mut commands = Vec::with_capacity(cmds.len());
+        let mut unbatched = Vec::new();
+        for (i, cmd) in cmds.iter().enumerate() {
+            let frame = self.frame;
+            let slot = match self.make_resident(cmd) {
+                Some(slot) => slot,
+                None => {
+                    unbatched.push(i);
+                    continue;
+                }
+            };
+            slot.frame = frame;
+            commands.push(IndirectCommand {
+                count: slot.indices.1 as u32,
+                instance_count: 1,
+                first_index: slot.indices.0 as u32,
+                base_vertex: slot.vertices.0 as i32,
+                base_instance: 0,
+            });
+            draws.push(Draw {
+                mvp: cmd.mvp.as_slice().try_into().unwrap(),
+                previous_mvp: cmd.previous_mvp.as_slice().try_into().unwrap(),
+                model: cmd.model.as_slice().try_into().unwrap(),
+                planet_id: cmd.planet_id as u32,
+                lod_level: cmd.lod_level as u32,
+                fade: cmd.fade,
+                _padding: 0,
+            });
+        }
+        gl::BindBuffer(gl::COPY_READ_BUFFER, 0);
+        gl::BindBuffer(gl::COPY_WRITE_BUFFER, 0);
+        if commands.is_empty() {
+            return unbatched;
+        }
+
+        // Refilled for every batch, orphaning what earlier batches still read
+        let draw_bytes = (draws.len() * std::mem::size_of::<Draw>()) as isize;
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.draws.id());
+        gl::BufferData(gl::SHADER_STORAGE_BUFFER, draw_bytes, draws.as_ptr() as *const _, gl::STREAM_DRAW);
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        self.draws.resize(draw_bytes as u64);
+        let command_bytes = (commands.len() * std::mem::size_of::<IndirectCommand>()) as isize;
+        gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.indirect.id());
+        gl::BufferData(gl::DRAW_INDIRECT_BUFFER, command_bytes, commands.as_ptr() as *const _, gl::STREAM_DRAW);
+        self.indirect.resize(command_bytes as u64);
+
+        state.bind_vertex_array(self.vao.id());
+        state.bind_buffer_base(gl::SHADER_STORAGE_BUFFER, BATCH_DRAWS_BINDING, self.draws.id());
+        let u_batched = sh.get_uniform_location("u_batched");
+        state.uniform_1i(u_batched, 1);
+        gl::MultiDrawElementsIndirect(
+            gl::TRIANGLES,
+            gl::UNSIGNED_INT,
+            std::ptr::null(),
+            commands.len() as i32,
+            0,
+        );
+        state.uniform_1i(u_batched, 0);
+        gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        unbatched
+    }
+}
@@ -0,0 +1,126 @@
+//! Planet terrain displaced on the GPU with a compute shader, on GL 4.3 and
+//! up, and on the CPU without it
+
+use std::cell::{Cell, RefCell};
+
+use crate::capabilities;
+use crate::error;
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::mesh::VAOobj;
+use crate::procedural_planet::Planet;
+use crate::shader::{self, Shader};
+
+const WORKGROUP_SIZE: u32 = 64; // local_size_x in planets.comp
+
+struct TerrainCompute {
+    shader: Shader,
+    permutation: GpuHandle, // Storage buffer for the noise permutation table
+}
+
+thread_local! {
+    static COMPUTE: RefCell<Option<TerrainCompute>> = const { RefCell::new(None) };
+    static ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// The permutation table `noise::Perlin` uses for `seed`, as in noise 0.7: a
+/// xorshift generator seeded with 1 and the seed bytes shuffles 0 to 255.
+pub fn permutation_table(seed: u32) -> [u32; 256] {
+    let mut state = [1, seed, seed, seed];
+    let mut next = || {
+        let t = state[0] ^ (state[0] << 11);
+        state = [state[1], state[2], state[3], state[3] ^ (state[3] >> 19) ^ (t ^ (t >> 8))];
+        state[3]
+    };
+    let mut table: [u32; 256] = std::array::from_fn(|i| i as u32);
+    for i in (1..table.len()).rev() {
+        // Uniform in 0..=i by widening multiply, rejecting the biased zone
+        let range = i as u32 + 1;
+        let zone = (range << range.leading_zeros()).wrapping_sub(1);
+        let j = loop {
+            let m = next() as u64 * range as u64;
+            if m as u32 <= zone {
+                break (m >> 32) as usize;
+            }
+        };
+        table.swap(i, j);
+    }
+    table
+}
+
+/// Compile the compute shader, when the context has compute shaders
+pub unsafe fn init() {
+    if !capabilities::global().compute_shaders() {
+        return;
+    }
+    let shader = shader::ShaderBuilder::new()
+        .attach_file(
+            "./resources/shaders/planets.comp",
+            Some(vec!["./resources/shaders/terrain.glsl"]),
+        )
+        .link();
+    // Terrain is displaced on the CPU without it
+    let shader = match shader {
+        Ok(shader) => shader,
+        Err(e) => {
+            error::report(&e);
+            return;
+        }
+    };
+    let mut permutation = 0;
+    gl::GenBuffers(1, &mut permutation);
+    gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, permutation);
+    gl::BufferData(gl::SHADER_STORAGE_BUFFER, 256 * 4, std::ptr::null(), gl::DYNAMIC_DRAW);
+    gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+    let permutation = GpuHandle::new(Kind::Buffer, permutation, 256 * 4, "terrain compute");
+    COMPUTE.with(|compute| *compute.borrow_mut() = Some(TerrainCompute { shader, permutation }));
+}
+
+/// Displace new patches on the GPU when available, or on the CPU
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+}
+
+/// Whether new patches are displaced on the GPU
+pub fn enabled() -> bool {
+    ENABLED.with(|e| e.get()) && COMPUTE.with(|compute| compute.borrow().is_some())
+}
+
+/// Displace the terrain patch in `vao`, an interleaved buffer of
+/// `vertex_count` vertices on the cube sphere with normals and tangents.
+/// `spacing` is about the distance between neighbouring vertices, for
+/// finding the normals. Leaves the compute shader active.
+pub unsafe fn displace(planet: &Planet, vao: &VAOobj, vertex_count: usize, spacing: f32) {
+    debug_assert!(vao.layout.interleaved && vao.layout.color && vao.layout.normal && vao.layout.tangent);
+    COMPUTE.with(|compute| {
+        let compute = compute.borrow();
+        let compute = match compute.as_ref() {
+            Some(compute) => compute,
+            None => return,
+        };
+        let sh = &compute.shader;
+        sh.activate();
+
+        let table = permutation_table(planet.noise_seed());
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, compute.permutation.id());
+        gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, 0, 256 * 4, table.as_ptr() as *const _);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, vao.vbo);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 1, compute.permutation.id());
+
+        let layout = vao.layout;
+        let vertex_count = vertex_count as u32;
+        gl::Uniform1ui(sh.get_uniform_location("u_vertex_count"), vertex_count);
+        gl::Uniform1ui(sh.get_uniform_location("u_stride"), layout.stride() as u32);
+        gl::Uniform1ui(sh.get_uniform_location("u_color"), layout.offset(1) as u32);
+        gl::Uniform1ui(sh.get_uniform_location("u_normal"), layout.offset(2) as u32);
+        gl::Uniform1ui(sh.get_uniform_location("u_tangent"), layout.offset(4) as u32);
+        gl::Uniform1f(sh.get_uniform_location("u_spacing"), spacing);
+
+        planet.update_terrain_uniforms(sh);
+
+        gl::DispatchCompute(vertex_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        // Drawn from the buffer next
+        gl::MemoryBarrier(gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, 0);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 1, 0);
+    });
+}
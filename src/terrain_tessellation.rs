@@ -0,0 +1,207 @@
+//! Planet terrain tessellated on the GPU, instead of patches generated for
+//! each level of detail, on GL 4.3 and up
+
+use std::cell::{Cell, RefCell};
+
+use crate::capabilities;
+use crate::city_lights::CITY_LIGHTS_UNIT;
+use crate::error;
+use crate::globals::TESS_PATCHES_PER_SIDE;
+use crate::gpu_resources::{GpuHandle, Kind};
+use crate::mesh::{Mesh, VAOobj, VertexLayout};
+use crate::procedural_planet::Planet;
+use crate::render_queue::RenderQueue;
+use crate::scene_graph::{SceneNode, SceneNodeType};
+use crate::shader::{self, Shader};
+use crate::terrain_compute::permutation_table;
+
+struct TerrainTessellation {
+    shader: Shader,
+    patches: VAOobj,
+    permutation: GpuHandle, // Storage buffer for the noise permutation table
+}
+
+thread_local! {
+    static TESSELLATION: RefCell<Option<TerrainTessellation>> = const { RefCell::new(None) };
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    static EDGE_PIXELS: Cell<f32> = const { Cell::new(8.0) };
+}
+
+/// Point on a side of the cube, turned from (x, 1, z) on the top like the
+/// sides in `Planet::lod`. Exact, so patches meet along the cube edges.
+fn on_side(side: usize, x: f32, z: f32) -> glm::Vec3 {
+    match side {
+        0 => glm::vec3(x, 1.0, z),   // Top
+        1 => glm::vec3(x, -1.0, -z), // Bottom
+        2 => glm::vec3(x, -z, 1.0),  // Front
+        3 => glm::vec3(x, z, -1.0),  // Back
+        4 => glm::vec3(1.0, -x, z),  // Left
+        _ => glm::vec3(-1.0, x, z),  // Right
+    }
+}
+
+/// Patches of four corners covering the cube, `per_side` along each edge of
+/// a side, with texture coordinates across each side like `Mesh::cs_plane`
+fn patch_mesh(per_side: usize) -> Mesh {
+    let res = per_side + 1;
+    let mut mesh = Mesh::default();
+    for side in 0..6 {
+        let first = (side * res * res) as u32;
+        for z in 0..res {
+            for x in 0..res {
+                let (x, z) = (x as f32 / per_side as f32, z as f32 / per_side as f32);
+                let corner = on_side(side, x * 2.0 - 1.0, z * 2.0 - 1.0);
+                mesh.vertices.extend_from_slice(&[corner.x, corner.y, corner.z]);
+                mesh.texture_coordinates.extend_from_slice(&[x, z]);
+            }
+        }
+        for z in 0..per_side {
+            for x in 0..per_side {
+                let a = first + (z * res + x) as u32;
+                let b = a + res as u32;
+                mesh.indices.extend_from_slice(&[a, a + 1, b + 1, b]);
+            }
+        }
+    }
+    mesh.index_count = mesh.indices.len() as i32;
+    mesh
+}
+
+/// Compile the tessellation shader, with the fragment stage of the scene
+/// shader, when the context has tessellation shaders
+pub unsafe fn init() {
+    if !capabilities::global().tessellation() {
+        return;
+    }
+    let shader = shader::ShaderBuilder::new()
+        .attach_file("./resources/shaders/terrain.vert", None)
+        .attach_file("./resources/shaders/terrain.tcs", None)
+        .attach_file(
+            "./resources/shaders/terrain.tes",
+            Some(vec!["./resources/shaders/terrain.glsl"]),
+        )
+        .attach_file(
+            "./resources/shaders/scene.frag",
+            Some(vec!["./resources/shaders/noise.glsl"]),
+        )
+        .link();
+    // Patches are generated on the CPU without it
+    let shader = match shader {
+        Ok(shader) => shader,
+        Err(e) => {
+            error::report(&e);
+            return;
+        }
+    };
+    let patches = patch_mesh(TESS_PATCHES_PER_SIDE).mkvao_with(VertexLayout {
+        uv: true,
+        ..Default::default()
+    });
+    let mut permutation = 0;
+    gl::GenBuffers(1, &mut permutation);
+    gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, permutation);
+    gl::BufferData(gl::SHADER_STORAGE_BUFFER, 256 * 4, std::ptr::null(), gl::DYNAMIC_DRAW);
+    gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+    let permutation = GpuHandle::new(Kind::Buffer, permutation, 256 * 4, "terrain tessellation");
+    TESSELLATION.with(|tessellation| {
+        *tessellation.borrow_mut() = Some(TerrainTessellation { shader, patches, permutation })
+    });
+}
+
+/// Draw terrain tessellated on the GPU when available, or generate patches
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+}
+
+/// Whether terrain is tessellated on the GPU
+pub fn enabled() -> bool {
+    ENABLED.with(|e| e.get()) && TESSELLATION.with(|tessellation| tessellation.borrow().is_some())
+}
+
+/// Length on screen, in pixels, patches are split to triangle edges of
+pub fn set_edge_pixels(pixels: f32) {
+    EDGE_PIXELS.with(|e| e.set(pixels.max(1.0)));
+}
+
+/// Draw the terrain of the planets not skipped and reaching into the
+/// `clipping` planes, with `sh` the scene shader holding the uniforms for
+/// shading. `pixel_scale` is the pixels a unit covers at distance 1. Leaves
+/// the tessellation shader active.
+pub unsafe fn draw<'a>(
+    sh: &Shader,
+    planets: impl Iterator<Item = (&'a Planet, &'a SceneNode)>,
+    view_projection: &glm::Mat4,
+    eye: &glm::Vec3,
+    clipping: (f32, f32),
+    pixel_scale: f32,
+    queue: &mut RenderQueue,
+) {
+    TESSELLATION.with(|tessellation| {
+        let tessellation = tessellation.borrow();
+        let tessellation = match tessellation.as_ref() {
+            Some(tessellation) => tessellation,
+            None => return,
+        };
+        let tsh = &tessellation.shader;
+        shader::share_uniforms(sh, tsh);
+        tsh.activate();
+        gl::Uniform1ui(tsh.get_uniform_location("u_node_type"), SceneNodeType::Planet as u32);
+        for flag in ["u_has_texture", "u_has_normal_map", "u_selected", "u_outline"] {
+            gl::Uniform1i(tsh.get_uniform_location(flag), 0);
+        }
+        gl::Uniform1f(tsh.get_uniform_location("u_pixel_scale"), pixel_scale);
+        gl::Uniform1f(tsh.get_uniform_location("u_edge_pixels"), EDGE_PIXELS.with(|e| e.get()));
+
+        gl::Disable(gl::BLEND);
+        gl::PatchParameteri(gl::PATCH_VERTICES, 4);
+        gl::BindVertexArray(tessellation.patches.vao);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 1, tessellation.permutation.id());
+        for (planet, node) in planets {
+            let eye_distance = glm::distance(&planet.position, eye);
+            let reach = planet.radius * (1.0 + 2.0 * planet.max_height);
+            if node.node_type == SceneNodeType::PlanetSkip
+                || node.occluded
+                || eye_distance - reach > clipping.1
+                || eye_distance + reach < clipping.0
+            {
+                continue;
+            }
+            let model = queue.rebase(&node.world_transformation) * glm::scaling(&planet.flattening());
+            let eye = eye - queue.origin;
+            let eye_model = glm::inverse(&model) * glm::vec4(eye.x, eye.y, eye.z, 1.0);
+            let table = permutation_table(planet.noise_seed());
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, tessellation.permutation.id());
+            gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, 0, 256 * 4, table.as_ptr() as *const _);
+
+            let mvp = view_projection * model;
+            gl::UniformMatrix4fv(tsh.get_uniform_location("u_mvp"), 1, gl::FALSE, mvp.as_ptr());
+            let previous_mvp = queue.previous_mvp(node.node_id, &mvp);
+            gl::UniformMatrix4fv(
+                tsh.get_uniform_location("u_previous_mvp"),
+                1,
+                gl::FALSE,
+                previous_mvp.as_ptr(),
+            );
+            gl::UniformMatrix4fv(tsh.get_uniform_location("u_model"), 1, gl::FALSE, model.as_ptr());
+            gl::Uniform3fv(tsh.get_uniform_location("u_eye"), 1, eye_model.xyz().as_ptr());
+            gl::Uniform1ui(tsh.get_uniform_location("u_current_planet_id"), planet.planet_id as u32);
+            planet.update_terrain_uniforms(tsh);
+            let city_lights = queue.city_lights.get(planet.planet_id).copied().unwrap_or(0);
+            if city_lights != 0 {
+                gl::ActiveTexture(gl::TEXTURE0 + CITY_LIGHTS_UNIT);
+                gl::BindTexture(gl::TEXTURE_2D, city_lights);
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+            gl::Uniform1i(tsh.get_uniform_location("u_has_city_lights"), (city_lights != 0) as i32);
+            gl::DrawElements(
+                gl::PATCHES,
+                tessellation.patches.n,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+            );
+            queue.draw_calls += 1;
+        }
+        gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 1, 0);
+    });
+}
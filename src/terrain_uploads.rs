@@ -0,0 +1,106 @@
+//! Budget for uploading generated terrain patches to the GPU
+
+use crate::globals::SUBDIVS_PER_LEVEL;
+use crate::memory_budget::{self, MemoryBudget, ResourceKind};
+use crate::procedural_planet::Planet;
+use crate::scene_graph::{self, SceneNode};
+use crate::{mesh, profile_scope, terrain_compute};
+
+struct Upload {
+    error: f32,
+    node: *mut SceneNode,
+    planet: Planet, // Displacing it on the GPU, if not done on the CPU
+    level: usize,
+    scale: f32, // Of the patch, 1 for a whole side
+}
+
+pub struct TerrainUploads {
+    waiting: Vec<Upload>,
+    bytes: u64,     // Per frame, 0 for no limit
+    patches: usize, // Per frame, 0 for no limit
+    left: usize,    // Patches left waiting after the last flush
+}
+
+impl TerrainUploads {
+    pub fn new() -> Self {
+        TerrainUploads {
+            waiting: Vec::new(),
+            bytes: 0,
+            patches: 0,
+            left: 0,
+        }
+    }
+
+    /// Upload at most `bytes` and `patches` per frame, 0 for no limit. At
+    /// least one patch is uploaded each frame, however large.
+    pub fn set_budget(&mut self, bytes: u64, patches: usize) {
+        self.bytes = bytes;
+        self.patches = patches;
+    }
+
+    /// Patches ready but not uploaded by the last flush
+    pub fn waiting(&self) -> usize {
+        self.left
+    }
+
+    /// Queue the generated patch of `node`, at `level` and `scale` of a side
+    /// of `planet`, with its screen-space error
+    pub fn push(&mut self, node: &mut SceneNode, planet: &Planet, level: usize, scale: f32, error: f32) {
+        self.waiting.push(Upload {
+            error,
+            node: node as *mut SceneNode,
+            planet: *planet,
+            level,
+            scale,
+        });
+    }
+
+    /// Upload the patches queued this frame with the largest error first,
+    /// within the budget. Must be called in the frame they were queued,
+    /// before any scene nodes are destroyed.
+    pub unsafe fn flush(&mut self, budget: &mut MemoryBudget) {
+        profile_scope!("terrain uploads");
+        self.waiting.sort_by(|a, b| b.error.total_cmp(&a.error));
+        let (mut bytes, mut patches) = (0, 0);
+        for upload in &self.waiting {
+            if patches > 0
+                && ((self.bytes > 0 && bytes >= self.bytes) || (self.patches > 0 && patches >= self.patches))
+            {
+                break;
+            }
+            let node = &mut *upload.node;
+            // The mesh is not needed on the CPU side anymore
+            let (status, mesh) = {
+                let mut generated = node.vao_generate.lock().unwrap();
+                (generated.0, std::mem::take(&mut generated.1))
+            };
+            let vao = mesh.mkvao_with(mesh::VertexLayout::NORMAL_MAPPED.interleaved());
+            if let scene_graph::VAOStatus::ReadyUndisplaced = status {
+                let subdivisions = (1 + upload.level) * SUBDIVS_PER_LEVEL;
+                terrain_compute::displace(
+                    &upload.planet,
+                    &vao,
+                    mesh.vertices.len() / 3,
+                    upload.scale / subdivisions as f32,
+                );
+            }
+            node.update_vao(vao);
+            budget.register(
+                node.node_id,
+                memory_budget::Handle::Node(node as *mut SceneNode),
+                ResourceKind::Terrain,
+                vao.bytes,
+            );
+            bytes += vao.bytes;
+            patches += 1;
+        }
+        self.left = self.waiting.len() - patches;
+        self.waiting.clear();
+    }
+}
+
+impl Default for TerrainUploads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,142 @@
+use nalgebra_glm as glm;
+
+use crate::error::{self, Result};
+use crate::mesh;
+use crate::scene_graph::{Node, SceneNode, SceneNodeType};
+use crate::texture;
+
+/// Height of a GUI text line at scale 1.0, in normalized device coordinates
+pub const LINE_HEIGHT: f32 = 0.05;
+// Characters in a text of width 1.0 at scale 1.0
+const CHARS_PER_UNIT: f32 = 28.0;
+
+/// Bitmap font atlas: a single row of 128 ASCII glyphs of equal width
+#[derive(Copy, Clone, Debug)]
+pub struct Font {
+    pub texture_id: u32,
+    pub char_height_over_width: f32,
+}
+
+impl Font {
+    pub fn load(path: &str, char_height_over_width: f32) -> Result<Self> {
+        Ok(Font {
+            texture_id: texture::load_texture(path)?,
+            char_height_over_width,
+        })
+    }
+
+    /// The default charmap, or checkers in place of glyphs if it's missing
+    pub fn charmap() -> Self {
+        Self::load("resources/textures/charmap.png", 49.0 / 29.0).unwrap_or_else(|e| {
+            error::report(&e);
+            Font {
+                texture_id: texture::placeholder(),
+                char_height_over_width: 49.0 / 29.0,
+            }
+        })
+    }
+
+    /// Textured quads for a string, starting at the origin
+    pub fn mesh(&self, text: &str) -> mesh::Mesh {
+        // An empty buffer can't be uploaded, use a blank instead
+        let text = if text.is_empty() { " " } else { text };
+        mesh::Mesh::text_buffer(text, self.char_height_over_width, Self::width(text))
+    }
+
+    /// Width of a string at scale 1.0
+    pub fn width(text: &str) -> f32 {
+        text.len() as f32 / CHARS_PER_UNIT
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Line of text in a `Geometry2d` scene node, add `node` to a GUI root to
+/// display it. The mesh is only rebuilt when the text changes.
+pub struct Text {
+    pub node: Node,
+    font: Font,
+    text: String,
+    anchor: glm::Vec2, // Point the text is aligned to, bottom of the line
+    align: Align,
+    scale: f32,
+}
+
+impl Text {
+    pub fn new(font: &Font, text: &str, anchor: glm::Vec2, scale: f32) -> Self {
+        let text_mesh = font.mesh(text);
+        let mut node = SceneNode::from_vao(unsafe { text_mesh.mkvao() });
+        node.node_type = SceneNodeType::Geometry2d;
+        node.texture_id = Some(font.texture_id);
+        node.set_scale(glm::vec3(1.0, 1.0, 1.0) * scale);
+        let mut label = Text {
+            node,
+            font: *font,
+            text: text.to_string(),
+            anchor,
+            align: Align::Left,
+            scale,
+        };
+        label.place();
+        label
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self.place();
+        self
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        if text == self.text {
+            return;
+        }
+        self.text = text.to_string();
+        self.node.update_buffers_dynamic(&self.font.mesh(text));
+        self.place();
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+        self.node.set_scale(glm::vec3(1.0, 1.0, 1.0) * scale);
+        self.place();
+    }
+
+    pub fn set_anchor(&mut self, anchor: glm::Vec2) {
+        self.anchor = anchor;
+        self.place();
+    }
+
+    /// Anchor the label at a point in world space, e.g. over a planet. Hides
+    /// the label when the point is behind the camera.
+    pub fn set_world_anchor(&mut self, world: &glm::Vec3, view_projection: &glm::Mat4) {
+        let clip = view_projection * glm::vec4(world.x, world.y, world.z, 1.0);
+        if clip.w <= 0.0 {
+            self.node.node_type = SceneNodeType::Empty;
+            return;
+        }
+        self.node.node_type = SceneNodeType::Geometry2d;
+        self.set_anchor(glm::vec2(clip.x / clip.w, clip.y / clip.w));
+    }
+
+    #[allow(dead_code)]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn place(&mut self) {
+        let width = Font::width(&self.text) * self.scale;
+        let x = match self.align {
+            Align::Left => self.anchor.x,
+            Align::Center => self.anchor.x - width / 2.0,
+            Align::Right => self.anchor.x - width,
+        };
+        self.node.set_position(glm::vec3(x, self.anchor.y, 0.0));
+    }
+}
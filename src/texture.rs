@@ -1,8 +1,29 @@
-use crate::util;
+use crate::color;
+use crate::error::{self, Error, Result};
+use crate::globals::{DETAIL_CRATERS, DETAIL_OCTAVES, TERRAIN_OCTAVES, TEXTURE_PREVIEW_SIZE};
+use crate::gpu_resources::{self, Kind};
+use crate::{jobs, util};
 use image::io::Reader as ImageReader;
+use noise::{NoiseFn, Perlin, Seedable};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::VecDeque;
 
-/// Generate a texture binding for an RGBA8 image
-unsafe fn get_texture_id(img: &image::ImageBuffer<image::Rgba<u8>, std::vec::Vec<u8>>) -> u32 {
+/// Texture unit of the planet detail normal map, see `detail_normal_map`.
+/// Node textures use unit 0 and their normal maps unit 2.
+pub const DETAIL_MAP_UNIT: u32 = 3;
+/// Texture unit of the rock, grass and snow patterns of planet terrain, see
+/// `terrain_detail_map`
+pub const TERRAIN_MAP_UNIT: u32 = 7;
+
+/// Generate a texture binding for an RGBA8 image, stored as `format` and
+/// tracked under `label`
+pub unsafe fn get_texture_id(
+    img: &image::ImageBuffer<image::Rgba<u8>, std::vec::Vec<u8>>,
+    format: u32,
+    label: &'static str,
+) -> u32 {
     let mut tex_id = 0;
     gl::GenTextures(1, &mut tex_id);
 
@@ -20,7 +41,7 @@ unsafe fn get_texture_id(img: &image::ImageBuffer<image::Rgba<u8>, std::vec::Vec
     gl::TexImage2D(
         gl::TEXTURE_2D,
         0,
-        gl::RGBA as i32,
+        format as i32,
         img.dimensions().0 as i32,
         img.dimensions().1 as i32,
         0,
@@ -30,20 +51,359 @@ unsafe fn get_texture_id(img: &image::ImageBuffer<image::Rgba<u8>, std::vec::Vec
     );
 
     gl::GenerateMipmap(gl::TEXTURE_2D);
+    // Mipmaps add a third
+    let (width, height) = img.dimensions();
+    gpu_resources::track(Kind::Texture, tex_id, width as u64 * height as u64 * 4 * 4 / 3, label);
 
     tex_id
 }
 
-pub fn load_texture(path: &str) -> u32 {
+/// Load a texture of data, such as a normal map or a font, used as it is
+pub fn load_texture(path: &str) -> Result<u32> {
+    load(path, gl::RGBA8)
+}
+
+fn load(path: &str, format: u32) -> Result<u32> {
     let timer = std::time::SystemTime::now();
     eprint!("Loading texture '{}' . . . ", path);
-    let img = ImageReader::open(path)
-        .unwrap()
+    let img = decode(path)?;
+    let t_id = unsafe { get_texture_id(&img, format, "texture") };
+    eprintln!("took {:?}", timer.elapsed().unwrap());
+    Ok(t_id)
+}
+
+/// Read the image at `path`, flipped for OpenGL
+fn decode(path: &str) -> Result<image::RgbaImage> {
+    Ok(ImageReader::open(path)
+        .map_err(|source| Error::Io {
+            path: path.to_string(),
+            source,
+        })?
         .decode()
-        .unwrap()
+        .map_err(|source| Error::Image {
+            path: path.to_string(),
+            source,
+        })?
         .flipv()
-        .into_rgba8();
-    let t_id = unsafe { get_texture_id(&img) };
+        .into_rgba8())
+}
+
+//-----------------------------------------------------------------------------/
+// Asynchronous loading
+//-----------------------------------------------------------------------------/
+
+/// A decoded image on its way to the GPU
+struct Upload {
+    texture: u32,
+    generation: u64, // Of the texture in gpu_resources, see `loaded`
+    format: u32,
+    image: image::RgbaImage,
+    pbo: u32,      // Staging buffer, 0 until the first bytes are copied
+    copied: usize, // Bytes of the image in the staging buffer
+}
+
+thread_local! {
+    static UPLOADS: RefCell<VecDeque<Upload>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// Load a texture of data like `load_texture`, returning at once with a
+/// texture of `placeholder` that is filled in over the next frames, see
+/// `stream_uploads`. Stays the placeholder if the image can't be read.
+pub fn load_texture_async(path: &str, placeholder: [u8; 4]) -> u32 {
+    load_async(path, gl::RGBA8, placeholder, false)
+}
+
+/// Load a texture of colours, decoded from sRGB when lighting in linear
+/// space (see color.rs), returning at once with a grey texture that is filled in over the next frames, see
+/// `stream_uploads`. Becomes the checkers of `placeholder` instead if the
+/// image can't be read.
+pub fn load_color_texture_async(path: &str) -> u32 {
+    load_async(path, color::texture_format(), [128, 128, 128, 255], true)
+}
+
+fn load_async(path: &str, format: u32, placeholder: [u8; 4], checkers: bool) -> u32 {
+    let solid = image::ImageBuffer::from_pixel(1, 1, image::Rgba(placeholder));
+    let texture = unsafe { get_texture_id(&solid, format, "texture") };
+    let generation = gpu_resources::generation(Kind::Texture, texture).unwrap_or(0);
+    let path = path.to_string();
+    jobs::spawn_with_completion(
+        jobs::Priority::Low,
+        move || {
+            // Shrunk here as well, as a stand-in until the upload finishes
+            decode(&path).map(|image| {
+                let preview = image::imageops::thumbnail(&image, TEXTURE_PREVIEW_SIZE, TEXTURE_PREVIEW_SIZE);
+                (image, preview)
+            })
+        },
+        move |decoded| unsafe {
+            // Deleted in the meantime, with the scene it was loaded for
+            if !loaded(texture, generation) {
+                return;
+            }
+            match decoded {
+                Ok((image, preview)) => {
+                    fill(texture, format, &preview);
+                    UPLOADS.with(|uploads| {
+                        uploads.borrow_mut().push_back(Upload {
+                            texture,
+                            generation,
+                            format,
+                            image,
+                            pbo: 0,
+                            copied: 0,
+                        })
+                    });
+                }
+                Err(e) => {
+                    error::report(&e);
+                    if checkers {
+                        fill(texture, gl::RGBA8, &checkers_image());
+                    }
+                }
+            }
+        },
+    );
+    texture
+}
+
+/// Whether `texture` is still the one of `generation` it was loaded for.
+/// Names of deleted textures are given out again, so one made since under
+/// the same name doesn't count.
+fn loaded(texture: u32, generation: u64) -> bool {
+    gpu_resources::generation(Kind::Texture, texture) == Some(generation)
+}
+
+/// Replace the storage of `texture` with `img`, with mipmaps
+unsafe fn fill(texture: u32, format: u32, img: &image::RgbaImage) {
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    let (width, height) = img.dimensions();
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        format as i32,
+        width as i32,
+        height as i32,
+        0,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        util::pointer_to_array(img),
+    );
+    gl::GenerateMipmap(gl::TEXTURE_2D);
+    gpu_resources::resize(Kind::Texture, texture, width as u64 * height as u64 * 4 * 4 / 3);
+}
+
+/// Copy up to `budget` bytes of loaded images into pixel buffer objects,
+/// oldest first, 0 for no limit, and hand those copied in full to the GPU.
+/// At least one row is copied each frame. The GL copies the texture out of
+/// the buffer without waiting for it on the CPU.
+pub unsafe fn stream_uploads(budget: u64) {
+    UPLOADS.with(|uploads| {
+        let mut uploads = uploads.borrow_mut();
+        let mut left = if budget == 0 { usize::MAX } else { budget as usize };
+        while let Some(upload) = uploads.front_mut() {
+            if left == 0 {
+                break;
+            }
+            if !loaded(upload.texture, upload.generation) {
+                gl::DeleteBuffers(1, &upload.pbo);
+                uploads.pop_front();
+                continue;
+            }
+            let (width, height) = upload.image.dimensions();
+            let size = upload.image.len();
+            if upload.pbo == 0 {
+                gl::GenBuffers(1, &mut upload.pbo);
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, upload.pbo);
+                gl::BufferData(gl::PIXEL_UNPACK_BUFFER, size as isize, std::ptr::null(), gl::STREAM_DRAW);
+            } else {
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, upload.pbo);
+            }
+            // Whole rows, so a slow frame doesn't split one
+            let row = width as usize * 4;
+            let chunk = (left.max(row) / row * row).min(size - upload.copied);
+            let mapped = gl::MapBufferRange(
+                gl::PIXEL_UNPACK_BUFFER,
+                upload.copied as isize,
+                chunk as isize,
+                gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT,
+            );
+            if mapped.is_null() {
+                // Copied again next frame
+                break;
+            }
+            let source = upload.image.as_ptr().add(upload.copied);
+            std::ptr::copy_nonoverlapping(source, mapped as *mut u8, chunk);
+            if gl::UnmapBuffer(gl::PIXEL_UNPACK_BUFFER) == gl::FALSE {
+                // The contents were lost, like when the display mode changes
+                break;
+            }
+            upload.copied += chunk;
+            left = left.saturating_sub(chunk);
+
+            if upload.copied >= size {
+                // From the bound buffer, at offset 0
+                gl::BindTexture(gl::TEXTURE_2D, upload.texture);
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    upload.format as i32,
+                    width as i32,
+                    height as i32,
+                    0,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    std::ptr::null(),
+                );
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+                gpu_resources::resize(Kind::Texture, upload.texture, size as u64 * 4 / 3);
+                gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+                gl::DeleteBuffers(1, &upload.pbo);
+                uploads.pop_front();
+            }
+        }
+        gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+    });
+}
+
+/// Magenta and black checkers, in place of a texture that failed to load
+pub fn placeholder() -> u32 {
+    unsafe { get_texture_id(&checkers_image(), gl::RGBA8, "placeholder texture") }
+}
+
+fn checkers_image() -> image::RgbaImage {
+    image::ImageBuffer::from_fn(8, 8, |x, y| {
+        if (x + y) % 2 == 0 {
+            image::Rgba([255, 0, 255, 255])
+        } else {
+            image::Rgba([0, 0, 0, 255])
+        }
+    })
+}
+
+/// Generate a tiling normal map of craters and rock grain, `size` texels
+/// across, for detail on planet surfaces closer than the terrain resolves.
+///
+/// Heights are fractal noise sampled on a torus, so the map wraps seamlessly,
+/// with craters of random sizes dug into them. Normals are in tangent space,
+/// x along u and y along v.
+pub fn detail_normal_map(seed: u32, size: u32) -> u32 {
+    let timer = std::time::SystemTime::now();
+    eprint!("Generating detail normal map . . . ");
+    let n = size as usize;
+    let perlin = Perlin::new().set_seed(seed);
+    let mut rng = StdRng::seed_from_u64(seed as u64);
+    // Center, radius and depth, relative to the map size
+    let craters: Vec<(f32, f32, f32, f32)> = (0..DETAIL_CRATERS)
+        .map(|_| {
+            let radius = 0.01 + 0.08 * rng.gen::<f32>().powi(3);
+            (rng.gen(), rng.gen(), radius, radius * rng.gen_range(0.5..1.0))
+        })
+        .collect();
+
+    let tau = std::f64::consts::TAU;
+    let mut heights = vec![0.0f32; n * n];
+    for y in 0..n {
+        for x in 0..n {
+            let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+            let (a, b) = (u as f64 * tau, v as f64 * tau);
+            let mut h = 0.0;
+            let (mut amp, mut freq) = (0.01, 1.5);
+            for _ in 0..DETAIL_OCTAVES {
+                let p = [a.cos() * freq, a.sin() * freq, b.cos() * freq, b.sin() * freq];
+                h += perlin.get(p) as f32 * amp;
+                amp *= 0.5;
+                freq *= 2.0;
+            }
+            for &(cu, cv, radius, depth) in &craters {
+                // Shortest distance, wrapping around the edges
+                let du = (u - cu).abs().min(1.0 - (u - cu).abs());
+                let dv = (v - cv).abs().min(1.0 - (v - cv).abs());
+                let d = (du * du + dv * dv).sqrt() / radius;
+                if d < 1.0 {
+                    h += (d * d - 1.0) * depth; // Bowl
+                }
+                if d < 1.5 {
+                    h += (1.0 - ((d - 1.0) * 4.0).powi(2)).max(0.0) * depth * 0.3; // Rim
+                }
+            }
+            heights[y * n + x] = h;
+        }
+    }
+
+    let img = image::ImageBuffer::from_fn(size, size, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let h = |x: usize, y: usize| heights[(y % n) * n + x % n];
+        // Central differences, in heights per map width
+        let dx = (h(x + 1, y) - h(x + n - 1, y)) * size as f32 / 2.0;
+        let dy = (h(x, y + 1) - h(x, y + n - 1)) * size as f32 / 2.0;
+        let normal = glm::normalize(&glm::vec3(-dx, -dy, 1.0));
+        let c = normal.map(|c| ((c * 0.5 + 0.5) * 255.0).round() as u8);
+        image::Rgba([c.x, c.y, c.z, 255])
+    });
+    let t_id = unsafe { get_texture_id(&img, gl::RGBA8, "detail map") };
+    eprintln!("took {:?}", timer.elapsed().unwrap());
+    t_id
+}
+
+/// Generate tiling patterns for the materials of planet terrain, `size`
+/// texels across, with rock in the red channel, grass in the green and snow
+/// in the blue.
+///
+/// Each is fractal noise sampled on a torus, so the map wraps seamlessly,
+/// stretched to fill the channel and averaging about a half: rock is ridged
+/// into cracks, grass is fine and grainy, and snow is soft with the odd
+/// glint. Planets brighten and darken their colours with them, see
+/// `Planet::texture_strength`.
+pub fn terrain_detail_map(seed: u32, size: u32) -> u32 {
+    let timer = std::time::SystemTime::now();
+    eprint!("Generating terrain detail map . . . ");
+    let n = size as usize;
+    let perlin = Perlin::new().set_seed(seed.wrapping_add(1));
+    let mut rng = StdRng::seed_from_u64(seed as u64 + 1);
+
+    let tau = std::f64::consts::TAU;
+    // Fractal noise at (u, v), from `freq` repeats across, optionally ridged
+    let fractal = |u: f32, v: f32, freq: f64, octaves: usize, ridged: bool| {
+        let (a, b) = (u as f64 * tau, v as f64 * tau);
+        let (mut h, mut amp, mut freq) = (0.0, 1.0, freq);
+        for _ in 0..octaves {
+            let p = [a.cos() * freq, a.sin() * freq, b.cos() * freq, b.sin() * freq];
+            let value = perlin.get(p) as f32;
+            h += if ridged { 1.0 - value.abs() * 2.0 } else { value } * amp;
+            amp *= 0.5;
+            freq *= 2.0;
+        }
+        h
+    };
+    let mut channels = vec![[0.0f32; 3]; n * n];
+    for y in 0..n {
+        for x in 0..n {
+            let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+            let rock = fractal(u, v, 1.5, TERRAIN_OCTAVES, true).powi(3);
+            let grass = fractal(u, v, 6.0, TERRAIN_OCTAVES - 2, false) + rng.gen::<f32>() * 0.5;
+            let glint = if rng.gen::<f32>() < 0.01 { 0.5 } else { 0.0 };
+            let snow = fractal(u, v, 1.0, TERRAIN_OCTAVES - 2, false) * 0.3 + glint;
+            channels[y * n + x] = [rock, grass, snow];
+        }
+    }
+    // Stretched to fill each channel
+    let mut range = [(f32::MAX, f32::MIN); 3];
+    for texel in &channels {
+        for (c, (low, high)) in texel.iter().zip(range.iter_mut()) {
+            *low = low.min(*c);
+            *high = high.max(*c);
+        }
+    }
+
+    let img = image::ImageBuffer::from_fn(size, size, |x, y| {
+        let texel = channels[y as usize * n + x as usize];
+        let c: [u8; 3] = std::array::from_fn(|i| {
+            let (low, high) = range[i];
+            ((texel[i] - low) / (high - low).max(1e-6) * 255.0).round() as u8
+        });
+        image::Rgba([c[0], c[1], c[2], 255])
+    });
+    let t_id = unsafe { get_texture_id(&img, gl::RGBA8, "terrain detail map") };
     eprintln!("took {:?}", timer.elapsed().unwrap());
     t_id
 }
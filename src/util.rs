@@ -1,6 +1,7 @@
 extern crate nalgebra_glm as glm;
 use glm::Scalar;
 use itertools::Itertools;
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::sync::atomic::AtomicU64;
 #[allow(unused_imports)]
@@ -128,6 +129,8 @@ pub fn vec2_f64_to_f632(v: &glm::TVec2<f64>) -> glm::TVec2<f32> {
 // OpenGL debug utils
 //-----------------------------------------------------------------------------/
 
+/// # Safety
+/// Needs a current OpenGL context on the calling thread.
 pub unsafe fn get_gl_string(name: gl::types::GLenum) -> String {
     std::ffi::CStr::from_ptr(gl::GetString(name) as *mut i8)
         .to_string_lossy()
@@ -172,13 +175,30 @@ pub extern "system" fn debug_callback(
 // Run configurations utils
 //-----------------------------------------------------------------------------/
 
+pub const CONFIG_PATH: &str = "resources/settings.conf";
+
 #[derive(Default, Debug)]
 pub struct Config {
+    pub screen_width: u32,
+    pub screen_height: u32,
+    pub fullscreen: bool,
+    pub seed: u64,
     pub fov: f32,
     pub clip_near: f32,
     pub clip_far: f32,
+    pub clip_near_surface: f32,
+    pub clip_far_surface: f32,
+    pub auto_clip: bool,
+    pub log_depth: bool,
+    pub camera_relative: bool,
+    pub double_precision: bool,
     pub movement_speed: f32,
+    pub acceleration: f32,
+    pub damping: f32,
     pub mouse_speed: f32,
+    pub look_smoothing: f32,
+    pub gamepad_dead_zone: f32,
+    pub gamepad_sensitivity: f32,
     pub tilt_speed: f32,
     pub tilt: f32,
     pub init_position: [f32; 3],
@@ -186,82 +206,455 @@ pub struct Config {
     pub init_h_angle: f32,
     pub init_v_angle: f32,
     pub camera_position: CameraPosition,
+    pub ship_model: String,
+    pub auto_land: bool,
+    pub auto_speed: bool,
+    pub auto_speed_rate: f32,
+    pub auto_speed_min: f32,
+    pub auto_speed_max: f32,
+    pub bookmarks: String,
+    pub bookmark_transition: f32,
+    pub focus_fill: f32,
+    pub landing_altitude: f32,
+    pub split_screen: bool,
+    pub split_distance: f32,
+    pub depth_of_field: bool,
+    pub focus_distance: f32,
+    pub aperture: f32,
+    pub auto_focus: bool,
+    pub motion_blur: bool,
+    pub motion_blur_shutter: f32,
     pub polymode: usize,
+    pub debug_mode: DebugMode,
+    pub debug_lines: bool,
+    pub debug_normals: bool,
     pub draw_gui: bool,
+    pub minimap: bool,
+    pub lens_flare: bool,
+    pub light_shafts: bool,
+    pub star_catalog: String,
+    pub star_brightness: f32,
+    pub light_intensity: f32,
+    pub ambient_light: f32,
+    pub nebula: bool,
+    pub normal_maps: bool,
+    pub ocean_reflections: bool,
+    pub srgb: bool,
+    pub color_lut: String,
+    pub image_based_lighting: bool,
+    pub shader_variants: bool,
+    pub compute_terrain: bool,
+    pub batch_terrain: bool,
+    pub tessellated_terrain: bool,
+    pub erosion_iterations: usize,
+    pub vegetation: bool,
+    pub tess_edge_pixels: f32,
     pub render_limit: f32,
+    pub impostor_pixels: f32,
+    pub occlusion_culling: bool,
     pub player_height: f32,
     pub jump_speed: f32,
+    pub nbody: bool,
+    pub nbody_integrator: Integrator,
+    pub orbit_paths: bool,
+    pub orbit_path_width: f32,
+    pub crosshair: bool,
+    pub labels_planets: bool,
+    pub labels_props: bool,
+    pub start_date: Option<(i32, u32, u32)>, // Year, month and day, for the simulated date
+    pub roi_radius: f32,
+    pub roi_max_interval: u64,
+    pub budget_terrain_mb: u64,
+    pub budget_impostor_mb: u64,
+    pub budget_upload_kb: u64,
+    pub budget_upload_patches: usize,
+    pub budget_texture_upload_kb: u64,
     //init_direction: [f32; 3],
 }
 
 impl Config {
-    fn parse_array<T: std::str::FromStr + std::fmt::Debug, const D: usize>(val: &str) -> [T; D] {
+    fn parse_value<T: std::str::FromStr>(key: &str, val: &str) -> Result<T, String> {
+        val.trim()
+            .parse::<T>()
+            .map_err(|_| format!("Invalid value for {}: {}", key, val.trim()))
+    }
+    fn parse_array<T: std::str::FromStr + std::fmt::Debug, const D: usize>(
+        key: &str,
+        val: &str,
+    ) -> Result<[T; D], String> {
         use std::convert::TryInto;
-        let mut s = val.trim().split(",");
-        let mut arr = Vec::new();
-        (0..D).for_each(|_| {
-            arr.push(
-                s.next()
-                    .unwrap()
-                    .trim()
-                    .parse::<T>()
-                    .unwrap_or_else(|_t| panic!("Config::parse_array")),
-            );
-        });
-
-        arr.try_into().unwrap()
+        let arr = val
+            .trim()
+            .split(",")
+            .map(|v| Self::parse_value::<T>(key, v))
+            .collect::<Result<Vec<T>, String>>()?;
+        arr.try_into()
+            .map_err(|_| format!("Expected {} values for {}: {}", D, key, val.trim()))
+    }
+    /// Year, month and day of a `YYYY-MM-DD` date, or None if empty
+    fn parse_date(key: &str, val: &str) -> Result<Option<(i32, u32, u32)>, String> {
+        if val.trim().is_empty() {
+            return Ok(None);
+        }
+        let invalid = || format!("Invalid date for {}: {}, expected YYYY-MM-DD", key, val.trim());
+        let parts: Vec<&str> = val.trim().splitn(3, '-').collect();
+        let [year, month, day] = parts[..] else {
+            return Err(invalid());
+        };
+        let date = (
+            year.parse::<i32>().map_err(|_| invalid())?,
+            month.parse::<u32>().map_err(|_| invalid())?,
+            day.parse::<u32>().map_err(|_| invalid())?,
+        );
+        if !(1..=12).contains(&date.1) || !(1..=31).contains(&date.2) {
+            return Err(invalid());
+        }
+        Ok(Some(date))
+    }
+    /// Read the settings file
+    pub fn load() -> Result<Self, String> {
+        let text = std::fs::read_to_string(CONFIG_PATH).map_err(|e| format!("{}: {}", CONFIG_PATH, e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", CONFIG_PATH, e))
     }
-    pub fn load() -> Self {
-        use std::fs;
+
+    /// Parse settings, one `key=value` per line
+    pub fn parse(text: &str) -> Result<Self, String> {
         let mut conf = Config {
             ..Default::default()
         };
-        fs::read_to_string("resources/settings.conf")
-            .unwrap()
+        conf.set_from(text)?;
+        Ok(conf)
+    }
+
+    /// Set the values given in `text`, leaving the rest as they are
+    fn set_from(&mut self, text: &str) -> Result<(), String> {
+        let conf = self;
+        // Filter empty lines and comments
+        for line in text
             .lines()
-            .filter(|&line| line.trim().len() > 0 && !line.starts_with("#")) // Filter empty lines and comments
-            .for_each(|line| {
-                println!("{}", line);
-                let mut s = line.split("=");
-                let (key, val) = (s.next().unwrap(), s.next().unwrap());
-                match key {
-                    "fov" => conf.fov = val.trim().parse::<f32>().unwrap(),
-                    "clip_near" => conf.clip_near = val.trim().parse::<f32>().unwrap(),
-                    "clip_far" => conf.clip_far = val.trim().parse::<f32>().unwrap(),
-                    "movement_speed" => conf.movement_speed = val.trim().parse::<f32>().unwrap(),
-                    "mouse_speed" => conf.mouse_speed = val.trim().parse::<f32>().unwrap(),
-                    "tilt_speed" => conf.tilt_speed = val.trim().parse::<f32>().unwrap(),
-                    "tilt" => conf.tilt = val.trim().parse::<f32>().unwrap(),
-                    "init_h_angle" => conf.init_h_angle = val.trim().parse::<f32>().unwrap(),
-                    "init_v_angle" => conf.init_v_angle = val.trim().parse::<f32>().unwrap(),
-                    "camera_position" => {
-                        conf.camera_position =
-                            num::FromPrimitive::from_u32(val.trim().parse::<u32>().unwrap())
-                                .unwrap()
-                    }
-                    "init_position" => conf.init_position = Self::parse_array::<f32, 3>(val),
-                    "bg_color" => conf.bg_color = Self::parse_array::<f32, 4>(val),
-                    "polymode" => conf.polymode = val.trim().parse::<usize>().unwrap(),
-                    "draw_gui" => conf.draw_gui = val.trim() != "false",
-                    "render_limit" => conf.render_limit = val.trim().parse::<f32>().unwrap(),
-                    "player_height" => conf.player_height = val.trim().parse::<f32>().unwrap(),
-                    "jump_speed" => conf.jump_speed = val.trim().parse::<f32>().unwrap(),
-                    //"init_direction" => conf.init_direction = Self::parse_array::<f32, 3>(val),
-                    &_ => (),
+            .filter(|&line| !line.trim().is_empty() && !line.starts_with("#"))
+        {
+            let mut s = line.split("=");
+            let (key, val) = match (s.next(), s.next()) {
+                (Some(key), Some(val)) => (key, val),
+                _ => return Err(format!("Expected key=value: {}", line)),
+            };
+            match key {
+                "screen_width" => conf.screen_width = Self::parse_value::<u32>(key, val)?,
+                "screen_height" => conf.screen_height = Self::parse_value::<u32>(key, val)?,
+                "fullscreen" => conf.fullscreen = val.trim() != "false",
+                "seed" => conf.seed = Self::parse_value::<u64>(key, val)?,
+                "fov" => conf.fov = Self::parse_value::<f32>(key, val)?,
+                "clip_near" => conf.clip_near = Self::parse_value::<f32>(key, val)?,
+                "clip_far" => conf.clip_far = Self::parse_value::<f32>(key, val)?,
+                "clip_near_surface" => conf.clip_near_surface = Self::parse_value::<f32>(key, val)?,
+                "clip_far_surface" => conf.clip_far_surface = Self::parse_value::<f32>(key, val)?,
+                "auto_clip" => conf.auto_clip = val.trim() != "false",
+                "log_depth" => conf.log_depth = val.trim() != "false",
+                "camera_relative" => conf.camera_relative = val.trim() != "false",
+                "double_precision" => conf.double_precision = val.trim() != "false",
+                "movement_speed" => conf.movement_speed = Self::parse_value::<f32>(key, val)?,
+                "acceleration" => conf.acceleration = Self::parse_value::<f32>(key, val)?,
+                "damping" => conf.damping = Self::parse_value::<f32>(key, val)?,
+                "mouse_speed" => conf.mouse_speed = Self::parse_value::<f32>(key, val)?,
+                "look_smoothing" => conf.look_smoothing = Self::parse_value::<f32>(key, val)?,
+                "gamepad_dead_zone" => conf.gamepad_dead_zone = Self::parse_value::<f32>(key, val)?,
+                "gamepad_sensitivity" => {
+                    conf.gamepad_sensitivity = Self::parse_value::<f32>(key, val)?
+                }
+                "tilt_speed" => conf.tilt_speed = Self::parse_value::<f32>(key, val)?,
+                "tilt" => conf.tilt = Self::parse_value::<f32>(key, val)?,
+                "init_h_angle" => conf.init_h_angle = Self::parse_value::<f32>(key, val)?,
+                "init_v_angle" => conf.init_v_angle = Self::parse_value::<f32>(key, val)?,
+                "camera_position" => {
+                    conf.camera_position =
+                        num::FromPrimitive::from_u32(Self::parse_value::<u32>(key, val)?)
+                            .ok_or(format!("Invalid value for {}: {}", key, val.trim()))?
+                }
+                "ship_model" => conf.ship_model = val.trim().to_string(),
+                "auto_land" => conf.auto_land = val.trim() != "false",
+                "auto_speed" => conf.auto_speed = val.trim() != "false",
+                "auto_speed_rate" => conf.auto_speed_rate = Self::parse_value::<f32>(key, val)?,
+                "auto_speed_min" => conf.auto_speed_min = Self::parse_value::<f32>(key, val)?,
+                "auto_speed_max" => conf.auto_speed_max = Self::parse_value::<f32>(key, val)?,
+                "bookmarks" => conf.bookmarks = val.trim().to_string(),
+                "bookmark_transition" => conf.bookmark_transition = Self::parse_value::<f32>(key, val)?,
+                "focus_fill" => conf.focus_fill = Self::parse_value::<f32>(key, val)?,
+                "landing_altitude" => conf.landing_altitude = Self::parse_value::<f32>(key, val)?,
+                "split_screen" => conf.split_screen = val.trim() != "false",
+                "split_distance" => conf.split_distance = Self::parse_value::<f32>(key, val)?,
+                "depth_of_field" => conf.depth_of_field = val.trim() != "false",
+                "focus_distance" => conf.focus_distance = Self::parse_value::<f32>(key, val)?,
+                "aperture" => conf.aperture = Self::parse_value::<f32>(key, val)?,
+                "auto_focus" => conf.auto_focus = val.trim() != "false",
+                "motion_blur" => conf.motion_blur = val.trim() != "false",
+                "motion_blur_shutter" => conf.motion_blur_shutter = Self::parse_value::<f32>(key, val)?,
+                "init_position" => conf.init_position = Self::parse_array::<f32, 3>(key, val)?,
+                "bg_color" => conf.bg_color = Self::parse_array::<f32, 4>(key, val)?,
+                "polymode" => conf.polymode = Self::parse_value::<usize>(key, val)?,
+                "debug_lines" => conf.debug_lines = val.trim() != "false",
+                "debug_normals" => conf.debug_normals = val.trim() != "false",
+                "debug_mode" => {
+                    conf.debug_mode =
+                        num::FromPrimitive::from_u32(Self::parse_value::<u32>(key, val)?)
+                            .ok_or(format!("Invalid value for {}: {}", key, val.trim()))?
+                }
+                "draw_gui" => conf.draw_gui = val.trim() != "false",
+                "minimap" => conf.minimap = val.trim() != "false",
+                "lens_flare" => conf.lens_flare = val.trim() != "false",
+                "light_shafts" => conf.light_shafts = val.trim() != "false",
+                "star_catalog" => conf.star_catalog = val.trim().to_string(),
+                "star_brightness" => conf.star_brightness = Self::parse_value::<f32>(key, val)?,
+                "light_intensity" => conf.light_intensity = Self::parse_value::<f32>(key, val)?,
+                "ambient_light" => conf.ambient_light = Self::parse_value::<f32>(key, val)?,
+                "nebula" => conf.nebula = val.trim() != "false",
+                "normal_maps" => conf.normal_maps = val.trim() != "false",
+                "ocean_reflections" => conf.ocean_reflections = val.trim() != "false",
+                "srgb" => conf.srgb = val.trim() != "false",
+                "color_lut" => conf.color_lut = val.trim().to_string(),
+                "image_based_lighting" => conf.image_based_lighting = val.trim() != "false",
+                "shader_variants" => conf.shader_variants = val.trim() != "false",
+                "compute_terrain" => conf.compute_terrain = val.trim() != "false",
+                "batch_terrain" => conf.batch_terrain = val.trim() != "false",
+                "tessellated_terrain" => conf.tessellated_terrain = val.trim() != "false",
+                "erosion_iterations" => conf.erosion_iterations = Self::parse_value::<usize>(key, val)?,
+                "vegetation" => conf.vegetation = val.trim() != "false",
+                "tess_edge_pixels" => conf.tess_edge_pixels = Self::parse_value::<f32>(key, val)?,
+                "render_limit" => conf.render_limit = Self::parse_value::<f32>(key, val)?,
+                "impostor_pixels" => conf.impostor_pixels = Self::parse_value::<f32>(key, val)?,
+                "occlusion_culling" => conf.occlusion_culling = val.trim() != "false",
+                "player_height" => conf.player_height = Self::parse_value::<f32>(key, val)?,
+                "jump_speed" => conf.jump_speed = Self::parse_value::<f32>(key, val)?,
+                "nbody" => conf.nbody = val.trim() != "false",
+                "nbody_integrator" => {
+                    conf.nbody_integrator =
+                        num::FromPrimitive::from_u32(Self::parse_value::<u32>(key, val)?)
+                            .ok_or(format!("Invalid value for {}: {}", key, val.trim()))?
+                }
+                "orbit_paths" => conf.orbit_paths = val.trim() != "false",
+                "orbit_path_width" => conf.orbit_path_width = Self::parse_value::<f32>(key, val)?,
+                "start_date" => conf.start_date = Self::parse_date(key, val)?,
+                "crosshair" => conf.crosshair = val.trim() != "false",
+                "labels_planets" => conf.labels_planets = val.trim() != "false",
+                "labels_props" => conf.labels_props = val.trim() != "false",
+                "roi_radius" => conf.roi_radius = Self::parse_value::<f32>(key, val)?,
+                "budget_terrain_mb" => conf.budget_terrain_mb = Self::parse_value::<u64>(key, val)?,
+                "budget_upload_kb" => conf.budget_upload_kb = Self::parse_value::<u64>(key, val)?,
+                "budget_upload_patches" => {
+                    conf.budget_upload_patches = Self::parse_value::<usize>(key, val)?
+                }
+                "budget_texture_upload_kb" => {
+                    conf.budget_texture_upload_kb = Self::parse_value::<u64>(key, val)?
                 }
-            });
-        conf
+                "budget_impostor_mb" => {
+                    conf.budget_impostor_mb = Self::parse_value::<u64>(key, val)?
+                }
+                "roi_max_interval" => conf.roi_max_interval = Self::parse_value::<u64>(key, val)?,
+                //"init_direction" => conf.init_direction = Self::parse_array::<f32, 3>(key, val)?,
+                &_ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Take the values changed in the settings file, `changed` lines of it
+    /// as from `ConfigWatcher::poll`, keeping the rest as tweaked while
+    /// running. Keys in `STARTUP_KEYS` only apply at startup, and keys
+    /// removed from the file keep their value.
+    pub fn update_from(&mut self, changed: &str) -> Result<(), String> {
+        let lines: Vec<&str> = changed
+            .lines()
+            .filter(|line| !STARTUP_KEYS.contains(&line.split('=').next().unwrap_or("")))
+            .collect();
+        self.set_from(&lines.join("\n"))
+    }
+}
+
+/// Settings for the window, scene seed, initial camera, memory budgets, star
+/// catalog, ship model and bookmarks file, only read at startup
+const STARTUP_KEYS: [&str; 12] = [
+    "screen_width",
+    "screen_height",
+    "fullscreen",
+    "seed",
+    "init_position",
+    "init_h_angle",
+    "init_v_angle",
+    "budget_terrain_mb",
+    "budget_impostor_mb",
+    "star_catalog",
+    "ship_model",
+    "bookmarks",
+];
+
+/// `key=value` lines of settings, by key
+fn settings(text: &str) -> HashMap<&str, &str> {
+    text.lines()
+        .filter(|&line| !line.trim().is_empty() && !line.starts_with("#"))
+        .filter_map(|line| line.split_once('='))
+        .collect()
+}
+
+/// Polls the settings file for changes, to apply them without restarting
+pub struct ConfigWatcher {
+    modified: Option<std::time::SystemTime>,
+    last_poll: std::time::Instant,
+    text: String, // As last read, to tell which settings changed
+}
+
+impl Default for ConfigWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigWatcher {
+    const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+    pub fn new() -> Self {
+        ConfigWatcher {
+            modified: Self::modified(),
+            last_poll: std::time::Instant::now(),
+            text: std::fs::read_to_string(CONFIG_PATH).unwrap_or_default(),
+        }
+    }
+
+    fn modified() -> Option<std::time::SystemTime> {
+        std::fs::metadata(CONFIG_PATH)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Returns the lines of settings changed if the file changed since last
+    /// time, and parsed without errors, for `Config::update_from`. Errors are
+    /// printed and otherwise ignored, as the file is likely being edited.
+    pub fn poll(&mut self) -> Option<String> {
+        if self.last_poll.elapsed() < Self::POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = std::time::Instant::now();
+        let modified = Self::modified();
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let text = std::fs::read_to_string(CONFIG_PATH).ok()?;
+        match Config::parse(&text) {
+            Ok(_) => {
+                eprintln!("Reloaded {}", CONFIG_PATH);
+                let old = settings(&self.text);
+                let changed: Vec<String> = settings(&text)
+                    .into_iter()
+                    .filter(|(key, val)| old.get(key) != Some(val))
+                    .map(|(key, val)| format!("{}={}", key, val))
+                    .collect();
+                self.text = text;
+                Some(changed.join("\n"))
+            }
+            Err(e) => {
+                eprintln!("Failed to reload {}: {}", CONFIG_PATH, e);
+                None
+            }
+        }
+    }
+}
+
+// Projections
+
+/// Right handed perspective projection with the far plane at infinity, and
+/// depth mapped to -1..1 like glm::perspective. Nothing beyond the near plane
+/// is ever clipped, at the cost of depth precision far away.
+pub fn perspective_infinite(aspect: f32, fovy: f32, near: f32) -> glm::Mat4 {
+    let f = 1.0 / (fovy / 2.0).tan();
+    let mut m = glm::Mat4::zeros();
+    m[(0, 0)] = f / aspect;
+    m[(1, 1)] = f;
+    m[(2, 2)] = -1.0;
+    m[(3, 2)] = -1.0;
+    m[(2, 3)] = -2.0 * near;
+    m
+}
+
+// Smoothing
+
+/// Seed of one `part` of world generation, derived from the world `seed` so
+/// the parts don't draw the same random numbers, and the whole world comes
+/// from one number. The world seed 0 gives 0 for every part, which is the
+/// default world.
+pub fn derive_seed(seed: u64, part: &str) -> u64 {
+    if seed == 0 {
+        return 0;
+    }
+    // FNV-1a hash of the name, mixed into the seed with SplitMix64
+    let name = part.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3));
+    let mut z = (seed ^ name).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Fraction of the way to go this frame when easing exponentially at `rate`
+/// per second, the same whatever the frame rate. A rate of 0 gets there at once.
+pub fn ease(rate: f32, delta_time: f32) -> f32 {
+    if rate > 0.0 {
+        1.0 - (-rate * delta_time).exp()
+    } else {
+        1.0
     }
 }
 
+/// Move `current` towards `target` exponentially, closing the gap at `lambda`
+/// per second however long the frame, instead of by a fixed fraction per frame
+pub fn damp<T>(current: T, target: T, lambda: f32, delta_time: f32) -> T
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Sub<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    current + (target - current) * ease(lambda, delta_time)
+}
+
+/// Turn `current` towards `target` like `damp`, along the shortest arc
+pub fn damp_quat(current: &glm::Quat, target: &glm::Quat, lambda: f32, delta_time: f32) -> glm::Quat {
+    glm::quat_slerp(current, target, ease(lambda, delta_time))
+}
+
+// Rotations
+
+/// Quaternion of Euler angles in radians, like `SceneNode::rotation`, turning
+/// about x first, then z, then y
+pub fn quat_from_euler(rotation: &glm::Vec3) -> glm::Quat {
+    glm::quat_angle_axis(rotation.y, &glm::Vec3::y())
+        * glm::quat_angle_axis(rotation.z, &glm::Vec3::z())
+        * glm::quat_angle_axis(rotation.x, &glm::Vec3::x())
+}
+
+/// Translation, rotation and scale making up a transformation without shear
+pub fn decompose(m: &glm::Mat4) -> (glm::Vec3, glm::Quat, glm::Vec3) {
+    let columns = [m.column(0).xyz(), m.column(1).xyz(), m.column(2).xyz()];
+    let mut scale = glm::vec3(
+        glm::length(&columns[0]),
+        glm::length(&columns[1]),
+        glm::length(&columns[2]),
+    );
+    // A mirrored transformation keeps a proper rotation, flipped along x
+    if glm::dot(&glm::cross(&columns[0], &columns[1]), &columns[2]) < 0.0 {
+        scale.x = -scale.x;
+    }
+    let rotation = glm::Mat3::from_columns(&[
+        columns[0] / scale.x,
+        columns[1] / scale.y,
+        columns[2] / scale.z,
+    ]);
+    (m.column(3).xyz(), glm::mat3_to_quat(&rotation), scale)
+}
+
 // Connected vectors
 
 // Calculate right camera vector from horixontal angle
 pub fn vec_right(h_angle: f32) -> glm::Vec3 {
     glm::vec3(
-        (h_angle - 3.14 / 2.0).sin(),
+        (h_angle - std::f32::consts::FRAC_PI_2).sin(),
         0.0,
-        (h_angle - 3.14 / 2.0).cos(),
+        (h_angle - std::f32::consts::FRAC_PI_2).cos(),
     )
 }
 
@@ -324,15 +717,38 @@ pub fn vec_direction(h_angle: f32, v_angle: f32) -> glm::Vec3 {
 //     }
 // }
 
-#[derive(Debug, PartialEq, Copy, Clone, num_derive::FromPrimitive)]
+/// Integration of n-body motion, see nbody.rs
+#[derive(Debug, Default, PartialEq, Copy, Clone, num_derive::FromPrimitive)]
+pub enum Integrator {
+    SemiImplicitEuler = 0, // Velocity first, then position, keeping orbits closed
+    #[default]
+    RungeKutta4 = 1,       // Fourth order, more accurate for close moons
+}
+
+#[derive(Debug, Default, PartialEq, Copy, Clone, num_derive::FromPrimitive)]
 pub enum CameraPosition {
-    ThirdPerson, // Third person camera on helicopter
-    FirstPerson, // Camera on pilot head (not finished)
-                 //Chase
-                 //Free              // Free movement
+    ThirdPerson = 0,   // Walking on a planet, seen from behind
+    FirstPerson = 1,   // Walking on a planet
+    #[default]
+    FreeFly = 2,       // Free movement
+    Orbital = 3,       // Flying around the closest planet
+    OrbitSelected = 4, // Orbiting the selected body, turned by dragging
+    Ship = 5,          // Flying the ship, seen from behind
 }
-impl Default for CameraPosition {
-    fn default() -> Self {
-        Self::FirstPerson
+
+/// Debug render modes, must match DEBUG_* defines in scene.frag
+#[derive(Debug, Default, PartialEq, Copy, Clone, num_derive::FromPrimitive)]
+pub enum DebugMode {
+    #[default]
+    None = 0,      // Normal rendering
+    Wireframe = 1, // Polygon mode lines
+    Normals = 2,   // World space normals as colour
+    UvChecker = 3, // Checkerboard from texture coordinates
+    LodLevel = 4,  // Heatmap of planet patch LoD levels
+    Overdraw = 5,  // Additive blending, bright where many fragments are drawn
+}
+impl DebugMode {
+    pub fn next(self) -> Self {
+        num::FromPrimitive::from_u32((self as u32 + 1) % 6).unwrap()
     }
 }
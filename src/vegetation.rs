@@ -0,0 +1,334 @@
+//! Grass and trees scattered over the ground around the camera
+
+use nalgebra_glm as glm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc;
+
+use crate::globals::{
+    VEGETATION_CELLS, VEGETATION_CELLS_PER_FRAME, VEGETATION_GRASS_SIZE, VEGETATION_MAX_SLOPE, VEGETATION_PER_CELL,
+    VEGETATION_REACH, VEGETATION_TREE_SIZE,
+};
+use crate::procedural_planet::Planet;
+use crate::{jobs, shader, util};
+
+// Per instance position on the unit sphere (3), size (1), colour (3) and kind (1)
+const INSTANCE_SIZE: usize = 8;
+
+const GRASS: f32 = 0.0;
+const TREE: f32 = 1.0;
+
+/// Face of the cube, and column and row on it
+type Cell = (usize, usize, usize);
+
+/// Normal and the two axes across each face of the cube
+const FACES: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+];
+
+/// Direction from the planet center through `(u, v)`, from -1 to 1, on `face`
+fn direction(face: usize, u: f32, v: f32) -> glm::Vec3 {
+    let (n, t, b) = FACES[face];
+    glm::normalize(&(glm::make_vec3(&n) + glm::make_vec3(&t) * u + glm::make_vec3(&b) * v))
+}
+
+/// Cube coordinates of the edges of the cell at `i`
+fn cell_edge(i: usize) -> f32 {
+    2.0 * i as f32 / VEGETATION_CELLS as f32 - 1.0
+}
+
+/// Plants growing in `cell` of `planet`
+fn grow(planet: &Planet, cell: Cell) -> Vec<f32> {
+    let (face, i, j) = cell;
+    let mut rng = StdRng::seed_from_u64(
+        ((planet.seed as u64) << 32) ^ (face * VEGETATION_CELLS * VEGETATION_CELLS + j * VEGETATION_CELLS + i) as u64,
+    );
+    let (u0, v0) = (cell_edge(i), cell_edge(j));
+    let step = 2.0 / VEGETATION_CELLS as f32;
+    // Across the cell, in radii, for the slope
+    let spacing = glm::distance(&direction(face, u0, v0), &direction(face, u0 + step, v0)) * 0.25;
+    let meadow = planet.color_scheme[2];
+
+    let count = (VEGETATION_PER_CELL as f32 * planet.vegetation).round() as usize;
+    let mut instances = Vec::with_capacity(count * INSTANCE_SIZE);
+    for _ in 0..count {
+        let (u, v) = (u0 + rng.gen::<f32>() * step, v0 + rng.gen::<f32>() * step);
+        let d = direction(face, u, v);
+        let h = planet.surface_height(&d) - 1.0;
+        // Dry land below the mountains
+        if (planet.has_ocean && h <= planet.ocean_lvl) || h >= planet.color_thresholds[2] {
+            continue;
+        }
+        let (_, t, b) = FACES[face];
+        let du = planet.surface_height(&(d + glm::make_vec3(&t) * spacing)) - 1.0 - h;
+        let dv = planet.surface_height(&(d + glm::make_vec3(&b) * spacing)) - 1.0 - h;
+        if (du * du + dv * dv).sqrt() / spacing > VEGETATION_MAX_SLOPE {
+            continue;
+        }
+        // Forests where the mask is high, meadows around them, bare ground
+        // where it is low
+        let mask = planet.mask(&d, 40.0);
+        let roll = rng.gen::<f32>();
+        let (kind, size) = if mask > 0.15 && roll < 0.6 && h > planet.color_thresholds[0] {
+            (TREE, VEGETATION_TREE_SIZE)
+        } else if mask > -0.2 + 0.4 * roll {
+            (GRASS, VEGETATION_GRASS_SIZE)
+        } else {
+            continue;
+        };
+        let size = size * rng.gen_range(0.6..1.4);
+        let shade = rng.gen_range(0.7..1.1) * if kind == TREE { 0.6 } else { 1.0 };
+        let color = meadow * shade;
+        // Sunk a little, so they stand on slopes
+        let position = d * (1.0 + h - size * 0.1);
+        instances.extend_from_slice(&[position.x, position.y, position.z, size]);
+        instances.extend_from_slice(&[color.x, color.y, color.z, kind]);
+    }
+    instances
+}
+
+/// Instances streamed around the camera, and the GPU side of them
+pub struct Vegetation {
+    shader: shader::Shader,
+    vao: u32,
+    instance_vbo: u32,
+    capacity: isize, // Bytes allocated for instance_vbo
+    count: usize,    // Instances uploaded
+    dirty: bool,     // Cells changed since the last upload
+    planet: Option<usize>, // Index of the planet grown on
+    cells: HashMap<Cell, Vec<f32>>,
+    pending: HashSet<Cell>,
+    sender: mpsc::Sender<(usize, Cell, Vec<f32>)>,
+    receiver: mpsc::Receiver<(usize, Cell, Vec<f32>)>,
+}
+
+impl Vegetation {
+    pub unsafe fn new() -> Self {
+        let shader = shader::ShaderBuilder::new()
+            .attach_file("./resources/shaders/vegetation.vert", None)
+            .attach_file("./resources/shaders/vegetation.frag", None)
+            .link_or_error_shader();
+
+        let (mut vao, mut vbos) = (0, [0; 2]);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(2, vbos.as_mut_ptr());
+        let [quad_vbo, instance_vbo] = vbos;
+
+        // Corners of a quad standing on the ground, as a triangle strip
+        let quad: [f32; 8] = [-1.0, 0.0, 1.0, 0.0, -1.0, 1.0, 1.0, 1.0];
+        gl::BindBuffer(gl::ARRAY_BUFFER, quad_vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            util::byte_size_of_array(&quad),
+            util::pointer_to_array(&quad),
+            gl::STATIC_DRAW,
+        );
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
+        let stride = (INSTANCE_SIZE * std::mem::size_of::<f32>()) as i32;
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::VertexAttribDivisor(1, 1);
+        gl::EnableVertexAttribArray(2);
+        gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, util::offset::<f32>(4));
+        gl::VertexAttribDivisor(2, 1);
+
+        let (sender, receiver) = mpsc::channel();
+        Vegetation {
+            shader,
+            vao,
+            instance_vbo,
+            capacity: 0,
+            count: 0,
+            dirty: false,
+            planet: None,
+            cells: HashMap::new(),
+            pending: HashSet::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Number of plants uploaded
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Remove all plants, e.g. when planets are regenerated
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.pending.clear();
+        self.planet = None;
+        self.dirty = true;
+    }
+
+    /// Take in grown cells, drop those out of reach and start growing those
+    /// that came into reach of `eye`, on the nearest planet with vegetation
+    pub unsafe fn update(&mut self, planets: &[Planet], eye: &glm::Vec3) {
+        let nearest = planets
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.vegetation > 0.0 && !p.lightsource)
+            .map(|(i, p)| (glm::distance(&p.position, eye) / p.radius - p.surface_height(&(eye - p.position)), i))
+            .filter(|(altitude, _)| *altitude < VEGETATION_REACH)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, i)| i);
+        if nearest != self.planet {
+            self.clear();
+            self.planet = nearest;
+        }
+        for (i, cell, instances) in self.receiver.try_iter() {
+            if Some(i) == self.planet && self.pending.remove(&cell) {
+                self.cells.insert(cell, instances);
+                self.dirty = true;
+            }
+        }
+        if let Some(i) = nearest {
+            let up = glm::normalize(&(eye - planets[i].position));
+            self.stream(i, &planets[i], &up);
+        }
+        if self.dirty {
+            self.upload();
+        }
+    }
+
+    /// Cells of `planet`, at `index` in the planets, within reach of the
+    /// camera above `up`
+    fn stream(&mut self, index: usize, planet: &Planet, up: &glm::Vec3) {
+        let step = 2.0 / VEGETATION_CELLS as f32;
+        // Half the diagonal of the largest cells, near the middle of faces
+        let margin = step * std::f32::consts::FRAC_1_SQRT_2;
+        let reach = VEGETATION_REACH + margin;
+        // Kept a little further out, so cells don't flicker at the edge
+        let keep_reach = reach * 1.25;
+        let cells = VEGETATION_CELLS as isize;
+        let mut wanted = Vec::new();
+        for (face, (n, t, b)) in FACES.iter().enumerate() {
+            let along = glm::dot(up, &glm::make_vec3(n));
+            if along <= 0.0 {
+                continue;
+            }
+            // Nearest cube coordinates to the camera on this face
+            let (u, v) = (
+                (glm::dot(up, &glm::make_vec3(t)) / along).clamp(-1.0, 1.0),
+                (glm::dot(up, &glm::make_vec3(b)) / along).clamp(-1.0, 1.0),
+            );
+            if glm::distance(&direction(face, u, v), up) >= keep_reach {
+                continue;
+            }
+            let cell_at = |x: f32| ((x + 1.0) / step).floor() as isize;
+            // Cells are narrowest towards the corners
+            let span = (2.0 * keep_reach * (1.0 + u * u + v * v) / step).ceil() as isize + 1;
+            let (ci, cj) = (cell_at(u), cell_at(v));
+            for j in (cj - span).max(0)..(cj + span + 1).min(cells) {
+                for i in (ci - span).max(0)..(ci + span + 1).min(cells) {
+                    let (i, j) = (i as usize, j as usize);
+                    let center = direction(face, cell_edge(i) + step * 0.5, cell_edge(j) + step * 0.5);
+                    // As good as the angle this close, and more precise
+                    let distance = glm::distance(&center, up);
+                    if distance < keep_reach {
+                        wanted.push((distance, (face, i, j)));
+                    }
+                }
+            }
+        }
+
+        let keep: HashSet<Cell> = wanted.iter().map(|&(_, cell)| cell).collect();
+        let before = self.cells.len();
+        self.cells.retain(|cell, _| keep.contains(cell));
+        self.pending.retain(|cell| keep.contains(cell));
+        self.dirty |= self.cells.len() != before;
+
+        wanted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut started = 0;
+        for (distance, cell) in wanted {
+            if started >= VEGETATION_CELLS_PER_FRAME || distance >= reach {
+                break;
+            }
+            if self.cells.contains_key(&cell) || self.pending.contains(&cell) {
+                continue;
+            }
+            self.pending.insert(cell);
+            started += 1;
+            let planet = *planet;
+            let sender = self.sender.clone();
+            jobs::spawn(jobs::Priority::Low, move || {
+                // The receiver is gone when the game is shutting down
+                let _ = sender.send((index, cell, grow(&planet, cell)));
+            });
+        }
+    }
+
+    unsafe fn upload(&mut self) {
+        self.dirty = false;
+        let instances: Vec<f32> = self.cells.values().flatten().copied().collect();
+        self.count = instances.len() / INSTANCE_SIZE;
+        if instances.is_empty() {
+            return;
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+        let size = util::byte_size_of_array(&instances);
+        if size > self.capacity {
+            // Grow, and orphan the old storage
+            self.capacity = size.max(2 * self.capacity);
+            gl::BufferData(gl::ARRAY_BUFFER, self.capacity, std::ptr::null(), gl::DYNAMIC_DRAW);
+        }
+        gl::BufferSubData(gl::ARRAY_BUFFER, 0, size, util::pointer_to_array(&instances));
+    }
+
+    /// Draw the plants at a distance within `range` from `eye`, with the same
+    /// `view` and `perspective` as the rest of the depth pass, relative to
+    /// `origin`. Lit by the light at `sun`, if any. Leaves the vegetation
+    /// shader active, so reactivate the scene shader afterwards.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn draw(
+        &self,
+        planets: &[Planet],
+        view: &glm::Mat4,
+        perspective: &glm::Mat4,
+        origin: &glm::Vec3,
+        eye: &glm::Vec3,
+        sun: Option<glm::Vec3>,
+        log_depth: f32,
+        range: (f32, f32),
+    ) {
+        let planet = match self.planet {
+            Some(i) if self.count > 0 => &planets[i],
+            _ => return,
+        };
+        // Nothing out here, not even the tallest trees
+        let reach = VEGETATION_REACH * planet.radius;
+        let altitude = glm::distance(&planet.position, eye) - planet.radius * (1.0 + planet.max_height);
+        if range.0 > 2.0 * reach || range.1 < altitude.max(0.0) {
+            return;
+        }
+
+        gl::BindVertexArray(self.vao);
+        self.shader.activate();
+        let uniform = |name| self.shader.get_uniform_location(name);
+        gl::UniformMatrix4fv(uniform("u_view"), 1, gl::FALSE, view.as_ptr());
+        gl::UniformMatrix4fv(uniform("u_perspective"), 1, gl::FALSE, perspective.as_ptr());
+        gl::Uniform1f(uniform("u_log_depth"), log_depth);
+        let center = planet.position - origin;
+        gl::Uniform3fv(uniform("u_planet_position"), 1, center.as_ptr());
+        gl::Uniform1f(uniform("u_planet_radius"), planet.radius);
+        gl::Uniform3fv(uniform("u_eye"), 1, (eye - origin).as_ptr());
+        let sun = sun.map(|sun| glm::normalize(&(sun - planet.position))).unwrap_or_else(glm::zero);
+        gl::Uniform3fv(uniform("u_sun_direction"), 1, sun.as_ptr());
+        gl::Uniform1f(uniform("u_reach"), reach);
+        gl::Uniform2f(uniform("u_range"), range.0, range.1);
+
+        gl::Disable(gl::CULL_FACE);
+        gl::DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, self.count as i32);
+        gl::Enable(gl::CULL_FACE);
+    }
+}